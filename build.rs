@@ -2,6 +2,8 @@ use std::env;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 fn main() {
   // Put `memory.x` in our output directory and ensure it's on the linker search path.
@@ -15,4 +17,41 @@ fn main() {
   // By default, Cargo will re-run a build script whenever any file in the project changes.
   // By specifying `memory.x` here, we ensure the build script is only re-run when `memory.x` is changed.
   println!("cargo:rerun-if-changed=memory.x");
+
+  // Build information for `AT+VER?`, see `src/version.rs`.
+  let git_hash = Command::new("git")
+    .args(["rev-parse", "--short=8", "HEAD"])
+    .output()
+    .ok()
+    .filter(|output| output.status.success())
+    .and_then(|output| String::from_utf8(output.stdout).ok())
+    .map(|hash| hash.trim().to_string())
+    .unwrap_or_else(|| "unknown".to_string());
+  println!("cargo:rustc-env=BLUE_HIGH_GIT_HASH={}", git_hash);
+  // A new commit or checkout moves HEAD or the branch it points to.
+  println!("cargo:rerun-if-changed=.git/HEAD");
+  println!("cargo:rerun-if-changed=.git/refs");
+
+  // Reproducible builds pin the timestamp.
+  let build_time = env::var("SOURCE_DATE_EPOCH")
+    .ok()
+    .and_then(|epoch| epoch.parse::<u64>().ok())
+    .unwrap_or_else(|| {
+      SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+    });
+  println!("cargo:rustc-env=BLUE_HIGH_BUILD_TIME={}", build_time);
+  println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+  // Cargo passes every enabled feature as `CARGO_FEATURE_<NAME>`.
+  let mut features: Vec<String> = env::vars()
+    .filter_map(|(key, _)| {
+      key
+        .strip_prefix("CARGO_FEATURE_")
+        .map(|name| name.to_lowercase().replace('_', "-"))
+    })
+    .collect();
+  features.sort();
+  println!("cargo:rustc-env=BLUE_HIGH_FEATURES={}", features.join(","));
 }
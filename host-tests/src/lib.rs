@@ -0,0 +1,43 @@
+// 该文件是 BlueHigh 项目的一部分。
+// host-tests/src/lib.rs - 主机测试用的硬件无关模块
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! The hardware-independent firmware modules, built for the host.
+//!
+//! The firmware crate builds only for the MCU, with its HAL, runtime and
+//! defmt logger.  This crate compiles the modules that need none of them
+//! from `../src` unchanged, so `tests/` can exercise them with the std test
+//! harness and without a board:
+//!
+//! ```text
+//! cargo test --manifest-path host-tests/Cargo.toml --target $(rustc --print host-tuple)
+//! ```
+//!
+//! The `--target` overrides the MCU target `.cargo/config.toml` sets for
+//! the firmware.  [`radio`] is built without its MCU parts (the backend,
+//! the interrupt line and blocking transmission, all behind
+//! `target_os = "none"`); its packet status goes through the std critical
+//! section.  The modules keep their `crate::` paths, so each one is
+//! declared here under its firmware name.
+
+#![no_std]
+
+#[path = "../../src/band.rs"]
+pub mod band;
+#[path = "../../src/crc.rs"]
+pub mod crc;
+#[path = "../../src/lora_config.rs"]
+pub mod lora_config;
+#[path = "../../src/radio.rs"]
+pub mod radio;
+#[path = "../../src/sim.rs"]
+pub mod sim;
@@ -0,0 +1,126 @@
+// 该文件是 BlueHigh 项目的一部分。
+// host-tests/tests/crc.rs - CRC 的主机测试
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Host tests of the CRCs, see `src/crc.rs`.
+//!
+//! The check values are `const` assertions in the module itself; here the
+//! tables are compared with the bit-at-a-time definitions over inputs of
+//! every length up to a few hundred bytes, and against values published
+//! for other inputs.
+
+use blue_high_host_tests::crc;
+
+/// `len` bytes from a xorshift32 started at `seed`.
+fn data(seed: u32, len: usize) -> Vec<u8> {
+  let mut x = seed;
+  (0..len)
+    .map(|_| {
+      x ^= x << 13;
+      x ^= x >> 17;
+      x ^= x << 5;
+      x as u8
+    })
+    .collect()
+}
+
+fn ccitt_bitwise(data: &[u8]) -> u16 {
+  let mut crc = 0xFFFFu16;
+  for &byte in data {
+    crc ^= (byte as u16) << 8;
+    for _ in 0..8 {
+      crc = if crc & 0x8000 != 0 {
+        (crc << 1) ^ 0x1021
+      } else {
+        crc << 1
+      };
+    }
+  }
+  crc
+}
+
+fn modbus_bitwise(data: &[u8]) -> u16 {
+  let mut crc = 0xFFFFu16;
+  for &byte in data {
+    crc ^= byte as u16;
+    for _ in 0..8 {
+      crc = if crc & 1 != 0 {
+        (crc >> 1) ^ 0xA001
+      } else {
+        crc >> 1
+      };
+    }
+  }
+  crc
+}
+
+fn crc32_bitwise(data: &[u8]) -> u32 {
+  let mut crc = !0u32;
+  for &byte in data {
+    crc ^= byte as u32;
+    for _ in 0..8 {
+      crc = if crc & 1 != 0 {
+        (crc >> 1) ^ 0xEDB8_8320
+      } else {
+        crc >> 1
+      };
+    }
+  }
+  !crc
+}
+
+/// Each table agrees with the definition bit by bit.
+#[test]
+fn tables_match_definitions() {
+  for len in 0..300 {
+    let data = data(len as u32 + 1, len);
+    assert_eq!(crc::crc16_ccitt(&data), ccitt_bitwise(&data), "{len} bytes");
+    assert_eq!(
+      crc::crc16_modbus(&data),
+      modbus_bitwise(&data),
+      "{len} bytes"
+    );
+    assert_eq!(crc::crc32(&data), crc32_bitwise(&data), "{len} bytes");
+  }
+}
+
+/// Values published for inputs other than the check string: the pangram
+/// for CRC-32 and CRC-16/CCITT-FALSE, and the usual Modbus example, a
+/// read of ten holding registers from unit 1 (`01 03 00 00 00 0A C5 CD`).
+#[test]
+fn published_values() {
+  let pangram = b"The quick brown fox jumps over the lazy dog";
+  assert_eq!(crc::crc32(pangram), 0x414F_A339);
+  assert_eq!(crc::crc16_ccitt(pangram), 0x8FDD);
+  assert_eq!(
+    crc::crc16_modbus(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]).to_le_bytes(),
+    [0xC5, 0xCD]
+  );
+}
+
+/// A CRC-32 taken over any split of the data matches the one over the
+/// whole, as the image check reads flash in pieces.
+#[test]
+fn crc32_in_pieces() {
+  let data = data(0x1234_5678, 1024);
+  let whole = crc::crc32(&data);
+  for split in [0, 1, 3, 255, 256, 512, 1023, 1024] {
+    let (head, tail) = data.split_at(split);
+    assert_eq!(
+      crc::crc32_update(crc::crc32(head), tail),
+      whole,
+      "split at {split}"
+    );
+  }
+  let pieces = data.chunks(100).fold(0, crc::crc32_update);
+  assert_eq!(pieces, whole);
+}
@@ -0,0 +1,210 @@
+// 该文件是 BlueHigh 项目的一部分。
+// host-tests/tests/sim.rs - 仿真射频的主机测试
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Host tests of the simulated air, see `src/sim.rs`.
+//!
+//! The default parameters put a node at 20 dBm on SF11, whose
+//! demodulation floor is -17 dB SNR; over a -120 dBm noise floor a frame
+//! arrives intact up to 154 dB of path loss, with a CRC error up to 157 dB
+//! and not at all beyond.
+
+use core::cell::RefCell;
+use std::sync::{Mutex, MutexGuard};
+
+use blue_high_host_tests::radio::{self, Radio, RadioParams, RxError};
+use blue_high_host_tests::sim::{CAPTURE_DB, Medium};
+
+/// Path loss and noise floor of the simulated air.
+const PATH_LOSS_DB: i16 = 100;
+const NOISE_DBM: i16 = -120;
+
+/// The packet status is one static shared by every radio, and the harness
+/// runs tests in parallel: tests that receive hold this while they do.
+static PACKET_STATUS: Mutex<()> = Mutex::new(());
+
+fn packet_status() -> MutexGuard<'static, ()> {
+  PACKET_STATUS
+    .lock()
+    .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A frame arrives when its airtime is over, with the RSSI and SNR of the
+/// path, and the sender sees TxDone at the same moment.
+#[test]
+fn delivery_after_airtime() {
+  let _status = packet_status();
+  let medium = RefCell::new(Medium::<2>::new(PATH_LOSS_DB, NOISE_DBM, 1));
+  let mut a = Medium::radio(&medium, 0);
+  let mut b = Medium::radio(&medium, 1);
+  let params = RadioParams::default();
+  assert!(a.apply(&params) && b.apply(&params));
+
+  let frame = *b"hello, air";
+  let airtime_us = params.airtime_us(frame.len()) as u64;
+  assert!(a.send(&frame));
+  medium.borrow_mut().advance(airtime_us - 1);
+  assert!(!a.irq() && !b.irq());
+  assert!(b.rx_progress().is_some());
+  medium.borrow_mut().advance(1);
+  assert!(a.irq() && b.irq());
+  assert_eq!(medium.borrow().now_us(), airtime_us);
+
+  let mut buf = [0u8; 32];
+  assert_eq!(b.receive(&mut buf), Ok(Some(frame.len())));
+  assert_eq!(&buf[..frame.len()], &frame);
+  assert!(!b.irq());
+  let rssi_dbm = params.tx_power_dbm() as i16 - PATH_LOSS_DB;
+  assert_eq!(
+    radio::take_packet_status(),
+    Some(radio::PacketStatus {
+      rssi_dbm,
+      snr_db: (rssi_dbm - NOISE_DBM) as i8,
+    })
+  );
+  assert_eq!(b.receive(&mut buf), Ok(None));
+}
+
+/// Near the demodulation floor a frame arrives with a CRC error, which
+/// `capture` still reads; below it the frame is not heard.
+#[test]
+fn range_edge() {
+  let _status = packet_status();
+  let medium = RefCell::new(Medium::<2>::new(PATH_LOSS_DB, NOISE_DBM, 1));
+  let mut a = Medium::radio(&medium, 0);
+  let mut b = Medium::radio(&medium, 1);
+  let params = RadioParams::default();
+  let airtime_us = params.airtime_us(4) as u64;
+  let mut buf = [0u8; 4];
+
+  for (loss_db, heard, crc_ok) in [
+    (154, true, true),
+    (155, true, false),
+    (157, true, false),
+    (158, false, false),
+  ] {
+    medium.borrow_mut().set_path_loss(0, 1, loss_db);
+    assert!(a.apply(&params) && b.apply(&params));
+    assert!(a.send(&[1, 2, 3, 4]));
+    medium.borrow_mut().advance(airtime_us);
+    assert_eq!(b.irq(), heard, "{loss_db} dB");
+    if crc_ok {
+      assert_eq!(b.receive(&mut buf), Ok(Some(4)), "{loss_db} dB");
+    } else if heard {
+      let capture = b.capture(&mut buf).unwrap().unwrap();
+      assert!(!capture.crc_ok, "{loss_db} dB");
+      assert_eq!(buf, [1, 2, 3, 4]);
+    } else {
+      assert_eq!(b.receive(&mut buf), Ok(None));
+    }
+  }
+  medium.borrow_mut().set_path_loss(0, 1, 155);
+  assert!(a.apply(&params) && b.apply(&params));
+  assert!(a.send(&[1, 2, 3, 4]));
+  medium.borrow_mut().advance(airtime_us);
+  assert_eq!(b.receive(&mut buf), Err(RxError));
+}
+
+/// Two overlapping frames of similar strength destroy each other; one
+/// `CAPTURE_DB` stronger than the other gets through.
+#[test]
+fn collisions() {
+  let _status = packet_status();
+  let params = RadioParams::default();
+  let airtime_us = params.airtime_us(4) as u64;
+  let mut buf = [0u8; 4];
+
+  for (weaker_db, survivor) in [(CAPTURE_DB - 1, None), (CAPTURE_DB, Some(1u8))] {
+    let medium = RefCell::new(Medium::<3>::new(PATH_LOSS_DB, NOISE_DBM, 1));
+    medium
+      .borrow_mut()
+      .set_path_loss(1, 2, PATH_LOSS_DB + weaker_db);
+    let mut a = Medium::radio(&medium, 0);
+    let mut b = Medium::radio(&medium, 1);
+    let mut c = Medium::radio(&medium, 2);
+    assert!(a.apply(&params) && b.apply(&params) && c.apply(&params));
+
+    assert!(a.send(&[1; 4]));
+    medium.borrow_mut().advance(airtime_us / 2);
+    assert!(b.send(&[2; 4]));
+    medium.borrow_mut().advance(airtime_us);
+    match survivor {
+      Some(byte) => {
+        assert_eq!(c.receive(&mut buf), Ok(Some(4)));
+        assert_eq!(buf, [byte; 4]);
+      }
+      None => assert!(!c.irq()),
+    }
+  }
+}
+
+/// A node that transmits while a frame is on the air misses it even when
+/// it listens again before the frame ends, and nodes on another spreading
+/// factor or frequency never hear it.
+#[test]
+fn half_duplex_and_channels() {
+  let _status = packet_status();
+  let medium = RefCell::new(Medium::<4>::new(PATH_LOSS_DB, NOISE_DBM, 1));
+  let mut a = Medium::radio(&medium, 0);
+  let mut b = Medium::radio(&medium, 1);
+  let mut c = Medium::radio(&medium, 2);
+  let mut d = Medium::radio(&medium, 3);
+  let params = RadioParams::default();
+  let other_sf = RadioParams { sf: 10, ..params };
+  let other_channel = RadioParams {
+    frequency_hz: params.frequency_hz + 500_000,
+    ..params
+  };
+  assert!(a.apply(&params) && b.apply(&params));
+  assert!(c.apply(&other_sf) && d.apply(&other_channel));
+
+  let long_us = params.airtime_us(64) as u64;
+  let short_us = params.airtime_us(1) as u64;
+  assert!(a.send(&[1; 64]));
+  medium.borrow_mut().advance(1);
+  assert!(b.send(&[2]));
+  medium.borrow_mut().advance(short_us);
+  assert!(b.irq() && b.start_rx());
+  medium.borrow_mut().advance(long_us);
+  assert!(a.irq() && !b.irq());
+  let mut buf = [0u8; 64];
+  assert_eq!(b.receive(&mut buf), Ok(None));
+  assert!(!c.irq() && !d.irq());
+}
+
+/// Losses follow the seed: the same seed drops the same deliveries.
+#[test]
+fn seeded_loss() {
+  let _status = packet_status();
+  let params = RadioParams::default();
+  let airtime_us = params.airtime_us(1) as u64;
+  let deliveries = |seed| {
+    let medium = RefCell::new(Medium::<2>::new(PATH_LOSS_DB, NOISE_DBM, seed));
+    medium.borrow_mut().set_loss(500);
+    let mut a = Medium::radio(&medium, 0);
+    let mut b = Medium::radio(&medium, 1);
+    assert!(a.apply(&params) && b.apply(&params));
+    let mut buf = [0u8; 1];
+    (0..64u8)
+      .map(|n| {
+        assert!(a.start_rx() && a.send(&[n]));
+        medium.borrow_mut().advance(airtime_us);
+        b.receive(&mut buf) == Ok(Some(1))
+      })
+      .fold(0u64, |heard, delivered| (heard << 1) | delivered as u64)
+  };
+
+  let run = deliveries(7);
+  assert_eq!(deliveries(7), run);
+  assert_ne!(run, 0);
+  assert_ne!(run, u64::MAX);
+}
@@ -1,10 +1,33 @@
 MEMORY
 {
   /* NOTE K = KiBi = 1024 bytes */
-  FLASH (rx) : ORIGIN = 0x08000000, LENGTH = 64K
+  /* The first 1K page holds the boot selector (see `src/boot_select.rs`), */
+  /* the last 1K page (0x0800FC00) the persisted settings. */
+  BOOT (rx) : ORIGIN = 0x08000000, LENGTH = 1K
+  FLASH (rx) : ORIGIN = 0x08000400, LENGTH = 62K
   RAM (rwx) : ORIGIN = 0x20000000, LENGTH = 20K
 }
 
+/* The boot selector the core resets into, ahead of the application. */
+EXTERN(BOOT_VECTORS);
+SECTIONS
+{
+  .boot ORIGIN(BOOT) :
+  {
+    KEEP(*(.boot.vectors));
+    *(.boot.text .boot.text.*);
+  } > BOOT
+} INSERT BEFORE .vector_table;
+
+/* The image CRC32 seal, the last word of the image (see `src/version.rs`). */
+SECTIONS
+{
+  .image_crc : ALIGN(4)
+  {
+    KEEP(*(.image_crc));
+  } > FLASH
+} INSERT AFTER .data;
+
 
 
 
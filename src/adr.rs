@@ -0,0 +1,109 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/adr.rs - 自适应数据速率
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Adaptive data rate.
+//!
+//! Frames received from the peer are collected in windows of [`WINDOW`].
+//! For each window the controller looks at the worst SNR and at how many
+//! frames were lost (gaps in the MIC frame counter, so loss is only seen
+//! with `AT+MIC=1`):
+//!
+//! - more than 1 in [`LOSS_DIVISOR`] frames lost: one SF up (more robust);
+//! - no loss and the worst SNR still [`MARGIN_DB`] above what one SF lower
+//!   needs: one SF down (faster).
+//!
+//! Changes are applied to both ends through the remote configuration
+//! handshake.  Only the node with the lower address drives ADR, so the two
+//! ends never propose at once.
+
+use crate::radio::{self, RadioParams};
+
+/// Frames per evaluation window.
+pub const WINDOW: u32 = 16;
+/// Step up when more than `1 / LOSS_DIVISOR` of the frames were lost.
+const LOSS_DIVISOR: u32 = 10;
+/// SNR headroom required after stepping down.
+const MARGIN_DB: i8 = 5;
+const SF_MIN: u8 = 7;
+const SF_MAX: u8 = 12;
+
+pub struct Adr {
+  enabled: bool,
+  received: u32,
+  lost: u32,
+  worst_snr: i8,
+}
+
+impl Adr {
+  pub fn new(enabled: bool) -> Self {
+    Self {
+      enabled,
+      received: 0,
+      lost: 0,
+      worst_snr: i8::MAX,
+    }
+  }
+
+  pub fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  pub fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+    self.reset();
+  }
+
+  /// Start a new window, e.g. after the parameters changed.
+  pub fn reset(&mut self) {
+    self.received = 0;
+    self.lost = 0;
+    self.worst_snr = i8::MAX;
+  }
+
+  /// Account for a frame from the peer, `lost` frames after the previous.
+  pub fn record(&mut self, snr_db: i8, lost: u32) {
+    if !self.enabled {
+      return;
+    }
+    self.received += 1;
+    self.lost = self.lost.saturating_add(lost);
+    self.worst_snr = self.worst_snr.min(snr_db);
+  }
+
+  /// Parameters to switch to once a window is complete, if any.
+  pub fn evaluate(&mut self, current: &RadioParams) -> Option<RadioParams> {
+    if !self.enabled || self.received < WINDOW {
+      return None;
+    }
+    let lost = self.lost;
+    let lossy = lost.saturating_mul(LOSS_DIVISOR) > self.received.saturating_add(lost);
+    let faster = current.sf.saturating_sub(1);
+    let headroom = self.worst_snr.saturating_sub(radio::required_snr_db(faster));
+    defmt::info!(
+      "[adr] window: {} received, {} lost, worst SNR {} dB",
+      self.received,
+      lost,
+      self.worst_snr
+    );
+    self.reset();
+
+    let sf = if lossy && current.sf < SF_MAX {
+      current.sf + 1
+    } else if lost == 0 && headroom >= MARGIN_DB && current.sf > SF_MIN {
+      faster
+    } else {
+      return None;
+    };
+    Some(RadioParams { sf, ..*current })
+  }
+}
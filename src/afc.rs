@@ -0,0 +1,108 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/afc.rs - 频率误差测量与自动频率校正
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Automatic frequency correction.
+//!
+//! The crystals of cheap modules are off by several ppm, a few kHz at
+//! 433 MHz, and the offsets of two modules add up.  After every frame the
+//! modem estimates how far the sender's carrier was from our channel (see
+//! [`Radio::freq_error_hz`](crate::radio::Radio::freq_error_hz)).  The
+//! last estimate is reported by `AT+AFC?` and in the RX metadata header
+//! ([`crate::rxmeta`]).
+//!
+//! With `AT+AFC=1`, the estimates of frames from the peer (any frame
+//! without addressing) are averaged and our carrier follows theirs for TX
+//! and RX alike, so the two ends meet in the middle of the channel filter.
+//! The correction moves once the average is [`STEP_HZ`] away from it, at
+//! most [`MAX_CORRECTION_HZ`] off the configured frequency, and is not
+//! persisted; `AT+AFC=0` returns to the configured frequency.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::radio;
+
+/// Change of the average that moves the correction.
+pub const STEP_HZ: i32 = 200;
+/// Largest correction applied.
+pub const MAX_CORRECTION_HZ: i32 = 10_000;
+/// Weight of a new estimate in the average, as a divisor.
+const SMOOTHING: i32 = 4;
+
+pub struct Afc {
+  enabled: bool,
+  /// Last estimate, relative to the corrected frequency.
+  last_error_hz: Option<i32>,
+  /// Average offset of the peer from the configured frequency.
+  average_hz: i32,
+  correction_hz: i32,
+}
+
+impl Afc {
+  pub fn new(enabled: bool) -> Self {
+    Self {
+      enabled,
+      last_error_hz: None,
+      average_hz: 0,
+      correction_hz: 0,
+    }
+  }
+
+  /// Switch correction on or off.  Returns whether the radio has to be
+  /// applied again for a changed correction.
+  pub fn set_enabled(&mut self, enabled: bool) -> bool {
+    self.enabled = enabled;
+    self.average_hz = self.correction_hz;
+    if enabled || self.correction_hz == 0 {
+      return false;
+    }
+    self.set_correction(0);
+    true
+  }
+
+  /// Account the estimate of a frame.  Returns whether the radio has to be
+  /// applied again for a new correction.
+  pub fn on_frame(&mut self, error_hz: i32, from_peer: bool) -> bool {
+    self.last_error_hz = Some(error_hz);
+    if !self.enabled || !from_peer {
+      return false;
+    }
+    let offset_hz = self.correction_hz + error_hz;
+    self.average_hz += (offset_hz - self.average_hz) / SMOOTHING;
+    let target = self.average_hz.clamp(-MAX_CORRECTION_HZ, MAX_CORRECTION_HZ);
+    if (target - self.correction_hz).abs() < STEP_HZ {
+      return false;
+    }
+    self.set_correction(target);
+    true
+  }
+
+  fn set_correction(&mut self, correction_hz: i32) {
+    defmt::info!("[afc] correction {} Hz", correction_hz);
+    self.correction_hz = correction_hz;
+    radio::set_frequency_offset(correction_hz);
+  }
+
+  /// `+AFC:<0|1>,<last error Hz|->,<correction Hz>`.
+  pub fn report(&self) -> String<40> {
+    let mut line = String::new();
+    let _ = write!(line, "+AFC:{},", self.enabled as u8);
+    let _ = match self.last_error_hz {
+      Some(error) => write!(line, "{}", error),
+      None => write!(line, "-"),
+    };
+    let _ = write!(line, ",{}\r\n", self.correction_hz);
+    line
+  }
+}
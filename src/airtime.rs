@@ -0,0 +1,168 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/airtime.rs - 发射时间统计与占空比预算
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Airtime accounting and duty-cycle budget.
+//!
+//! The time spent transmitting is summed over a rolling minute, in
+//! [`MINUTE_SLOTS`] slots, and a rolling hour, in [`HOUR_SLOTS`] slots.
+//! The sums come from the radio's own airtime total (see
+//! [`radio::tx_time_ms`]), so CW identification and test carriers count
+//! as well as frames.
+//!
+//! `AT+AIRTIME=<‰>` sets a budget in thousandths of the hour, e.g. `10`
+//! for a 1 % and `100` for a 10 % duty-cycle rule; `0` turns it off.  With
+//! a budget, a data frame, beacon or sensor report is only sent when its
+//! time on air, from the airtime calculator
+//! ([`RadioParams::airtime_us`]), fits in what is left of the hour;
+//! until then host data waits in the port queue, the way it waits for a
+//! TDMA slot.  Control traffic (pairing, acknowledgements, radio
+//! negotiation, LoRaWAN) is not held back but counts.
+//!
+//! The budget is persisted.  `AT+AIRTIME?` answers
+//! `+AIRTIME:<minute ms>,<hour ms>,<budget ‰>,<left ms>`, or
+//! `+AIRTIME:<minute ms>,<hour ms>,OFF,-` without a budget; the binary
+//! protocol reports the same sums in its statistics.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::radio::{self, RadioParams};
+
+/// Slots of the rolling minute, 5 s each.
+pub const MINUTE_SLOTS: usize = 12;
+/// Slots of the rolling hour, 1 min each.
+pub const HOUR_SLOTS: usize = 60;
+const HOUR_MS: u32 = 3_600_000;
+
+/// Persisted budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Config {
+  /// Airtime allowed per hour in thousandths, `0` for no budget.
+  pub permille: u16,
+}
+
+impl Config {
+  /// Accept up to the whole hour.
+  pub fn new(permille: u16) -> Option<Self> {
+    (permille <= 1_000).then_some(Self { permille })
+  }
+
+  /// Airtime allowed per hour.
+  pub fn hour_ms(&self) -> Option<u32> {
+    (self.permille > 0).then(|| HOUR_MS / 1_000 * self.permille as u32)
+  }
+}
+
+/// Airtime per slot over a rolling window.
+struct Window<const SLOTS: usize> {
+  slots: [u32; SLOTS],
+  slot_ms: u32,
+  /// Slot number of the last update, counted from boot.
+  current: u32,
+}
+
+impl<const SLOTS: usize> Window<SLOTS> {
+  const fn new(slot_ms: u32) -> Self {
+    Self {
+      slots: [0; SLOTS],
+      slot_ms,
+      current: 0,
+    }
+  }
+
+  /// Move to the slot of `now`, clearing the slots passed over.
+  fn advance(&mut self, now: u32) {
+    let slot = now / self.slot_ms;
+    let passed = slot.wrapping_sub(self.current).min(SLOTS as u32);
+    for step in 1..=passed {
+      self.slots[(self.current.wrapping_add(step) as usize) % SLOTS] = 0;
+    }
+    self.current = slot;
+  }
+
+  fn add(&mut self, ms: u32) {
+    let slot = &mut self.slots[self.current as usize % SLOTS];
+    *slot = slot.saturating_add(ms);
+  }
+
+  fn total(&self) -> u32 {
+    self.slots.iter().sum()
+  }
+}
+
+/// Rolling airtime sums.
+pub struct Meter {
+  minute: Window<MINUTE_SLOTS>,
+  hour: Window<HOUR_SLOTS>,
+  /// Radio airtime total at the last update.
+  tx_time_ms: u32,
+}
+
+impl Meter {
+  pub fn new(now: u32) -> Self {
+    let mut meter = Self {
+      minute: Window::new(60_000 / MINUTE_SLOTS as u32),
+      hour: Window::new(HOUR_MS / HOUR_SLOTS as u32),
+      tx_time_ms: radio::tx_time_ms(),
+    };
+    meter.minute.advance(now);
+    meter.hour.advance(now);
+    meter
+  }
+
+  /// Account the airtime since the last call.
+  pub fn update(&mut self, now: u32) {
+    let tx_time_ms = radio::tx_time_ms();
+    let airtime_ms = tx_time_ms.wrapping_sub(self.tx_time_ms);
+    self.tx_time_ms = tx_time_ms;
+    self.minute.advance(now);
+    self.hour.advance(now);
+    self.minute.add(airtime_ms);
+    self.hour.add(airtime_ms);
+  }
+
+  /// Airtime over the last minute.
+  pub fn minute_ms(&self) -> u32 {
+    self.minute.total()
+  }
+
+  /// Airtime over the last hour.
+  pub fn hour_ms(&self) -> u32 {
+    self.hour.total()
+  }
+
+  /// Airtime left of the budget this hour, `None` without a budget.
+  pub fn left_ms(&self, config: &Config) -> Option<u32> {
+    Some(config.hour_ms()?.saturating_sub(self.hour_ms()))
+  }
+
+  /// Whether a `frame_len`-byte frame fits in the budget.
+  pub fn may_transmit(&self, config: &Config, params: &RadioParams, frame_len: usize) -> bool {
+    self
+      .left_ms(config)
+      .is_none_or(|left| params.airtime_us(frame_len).div_ceil(1_000) <= left)
+  }
+
+  /// `+AIRTIME:<minute ms>,<hour ms>,<budget ‰>,<left ms>` or
+  /// `+AIRTIME:<minute ms>,<hour ms>,OFF,-`.
+  pub fn report(&self, config: &Config) -> String<48> {
+    let mut line = String::new();
+    let _ = write!(line, "+AIRTIME:{},{},", self.minute_ms(), self.hour_ms());
+    let _ = match self.left_ms(config) {
+      Some(left) => write!(line, "{},{}\r\n", config.permille, left),
+      None => write!(line, "OFF,-\r\n"),
+    };
+    line
+  }
+}
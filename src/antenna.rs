@@ -0,0 +1,161 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/antenna.rs - 天线对比检查
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Antenna sanity check against the paired peer.
+//!
+//! The radio is half duplex, so a bridge cannot listen to its own burst
+//! for reflected power; the peer listens instead.  `AT+ANT=<A|B>` runs
+//! [`PROBES`] ping probes (see [`crate::ping`]) and keeps, under the
+//! label, how the peer heard us and how we heard the peer on average.
+//!
+//! * The path loss is the same both ways, so with both ends at the same
+//!   power the two RSSIs agree within a few dB.  When the peer hears us
+//!   [`ASYMMETRY_DB`] or more below how we hear it, our transmit path
+//!   (PA, connector, antenna match) is suspect.  No reply at all is
+//!   reported the same way.
+//! * Measured once per antenna, e.g. `A` with the usual one and `B` with
+//!   a candidate, the two labels are compared as `+ANT:B-A`.
+//!
+//! Warnings go to the control port and the OLED, so the bridge is not left
+//! transmitting at full power into a bad antenna unnoticed.
+
+use crate::ping::Summary;
+use crate::radio::PacketStatus;
+
+/// Probes per measurement.
+pub const PROBES: u16 = 10;
+/// How much weaker the peer may hear us than we hear it.
+pub const ASYMMETRY_DB: i16 = 10;
+
+/// Label of a measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Slot {
+  A,
+  B,
+}
+
+impl Slot {
+  pub fn name(self) -> &'static str {
+    match self {
+      Slot::A => "A",
+      Slot::B => "B",
+    }
+  }
+}
+
+/// Average link quality of one measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Measurement {
+  pub sent: u16,
+  pub received: u16,
+  /// How the peer heard us.
+  pub there: PacketStatus,
+  /// How we heard the peer.
+  pub here: PacketStatus,
+}
+
+impl Measurement {
+  /// Whether the transmit path looks faulty.
+  pub fn suspect(&self) -> bool {
+    self.received == 0 || self.here.rssi_dbm - self.there.rssi_dbm >= ASYMMETRY_DB
+  }
+}
+
+/// Sums of the measurement in progress.
+#[derive(Default)]
+struct Totals {
+  replies: i32,
+  there_rssi: i32,
+  there_snr: i32,
+  here_rssi: i32,
+  here_snr: i32,
+}
+
+/// Stored measurements and the one in progress.
+pub struct Check {
+  slots: [Option<Measurement>; 2],
+  running: Option<(Slot, Totals)>,
+}
+
+impl Check {
+  pub fn new() -> Self {
+    Self {
+      slots: [None; 2],
+      running: None,
+    }
+  }
+
+  /// Attribute the ping test about to start to `slot`.
+  pub fn start(&mut self, slot: Slot) {
+    defmt::info!("[antenna] measuring {}", slot);
+    self.running = Some((slot, Totals::default()));
+  }
+
+  /// Stop attributing ping replies, e.g. for a plain `AT+PING`.
+  pub fn cancel(&mut self) {
+    self.running = None;
+  }
+
+  /// Account a ping reply.
+  pub fn on_reply(&mut self, there: PacketStatus, here: PacketStatus) {
+    if let Some((_, totals)) = self.running.as_mut() {
+      totals.replies += 1;
+      totals.there_rssi += there.rssi_dbm as i32;
+      totals.there_snr += there.snr_db as i32;
+      totals.here_rssi += here.rssi_dbm as i32;
+      totals.here_snr += here.snr_db as i32;
+    }
+  }
+
+  /// Store the measurement when the ping test ends.  Returns its label.
+  pub fn finish(&mut self, summary: &Summary) -> Option<(Slot, Measurement)> {
+    let (slot, totals) = self.running.take()?;
+    let average = |rssi: i32, snr: i32| PacketStatus {
+      rssi_dbm: rssi.checked_div(totals.replies).unwrap_or(0) as i16,
+      snr_db: snr.checked_div(totals.replies).unwrap_or(0) as i8,
+    };
+    let measurement = Measurement {
+      sent: summary.sent,
+      received: summary.received,
+      there: average(totals.there_rssi, totals.there_snr),
+      here: average(totals.here_rssi, totals.here_snr),
+    };
+    if measurement.suspect() {
+      defmt::warn!("[antenna] {} suspect: {}", slot, measurement);
+    } else {
+      defmt::info!("[antenna] {}: {}", slot, measurement);
+    }
+    self.slots[slot as usize] = Some(measurement);
+    Some((slot, measurement))
+  }
+
+  pub fn measurement(&self, slot: Slot) -> Option<Measurement> {
+    self.slots[slot as usize]
+  }
+
+  /// RSSI gained with `B` over `A` at the peer and here, once both are
+  /// measured with replies.
+  pub fn difference(&self) -> Option<(i16, i16)> {
+    let (a, b) = (self.slots[0]?, self.slots[1]?);
+    (a.received > 0 && b.received > 0).then_some((
+      b.there.rssi_dbm - a.there.rssi_dbm,
+      b.here.rssi_dbm - a.here.rssi_dbm,
+    ))
+  }
+}
+
+impl Default for Check {
+  fn default() -> Self {
+    Self::new()
+  }
+}
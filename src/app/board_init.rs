@@ -0,0 +1,230 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/app/board_init.rs - 板级初始化
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Board bring-up: clocks, the time base, the pin map and the peripherals
+//! the main loop takes over.
+//!
+//! [`init`] configures what every build needs and hands the rest out as raw
+//! parts in a [`Board`]; the drivers that depend on settings (data port
+//! baud, log storage) are started by [`super::run`] once those are loaded.
+
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+use core::cell::RefCell;
+
+use defmt::info;
+
+use crate::diagnostics::BlueHighDiagnostics as Diag;
+#[cfg(not(feature = "no-display"))]
+use crate::hal::i2c::{BlockingI2c, DutyCycle, Mode};
+use crate::hal::{adc::Adc, flash, pac, prelude::*, rcc::Rcc, usb::Peripheral};
+#[cfg(not(feature = "no-display"))]
+use crate::i2c_bus;
+#[cfg(feature = "driver-sx1268-rs")]
+use crate::lora::{ControlCell, LoraControl, OptionalPin, SharedControl};
+#[cfg(feature = "driver-sx1268-rs")]
+use crate::sx126x;
+#[cfg(feature = "sx1276")]
+use crate::sx1276;
+use crate::{board, hal, led, radio, spi_bus, timer};
+
+/// What bring-up hands to the main loop.
+pub struct Board {
+  pub flash: flash::Parts,
+  pub rcc: Rcc,
+  /// Supply and temperature measurements.
+  pub adc: Adc<pac::ADC1>,
+  /// Free for the boot delay.
+  pub tim2: pac::TIM2,
+  /// The display and sensor bus.
+  #[cfg(not(feature = "no-display"))]
+  pub i2c: i2c_bus::Shared,
+  /// Handed to [`crate::usb::init`].
+  pub usb: Peripheral,
+  /// Constructed, not yet configured.
+  pub lora: radio::BlueHighRadio,
+  pub dio1: radio::Dio1,
+  pub pair_button: board::Button,
+  /// The UART data port, started only when the settings select it.
+  pub usart1: pac::USART1,
+  pub uart_tx: board::UartTx,
+  pub uart_rx: board::UartRx,
+  pub uart_strap: board::UartStrap,
+  #[cfg(feature = "gps")]
+  pub usart2: pac::USART2,
+  #[cfg(feature = "gps")]
+  pub gps_tx: board::GpsTx,
+  #[cfg(feature = "gps")]
+  pub gps_rx: board::GpsRx,
+  /// The log storage bus, and the chip select of the store on it.
+  #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+  pub spi2: &'static RefCell<spi_bus::Bus2>,
+  #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+  pub spi2_cs: board::Spi2Cs,
+  #[cfg(feature = "vbat")]
+  pub vbat: board::Vbat,
+}
+
+/// Take the peripherals and bring the board up; panics if called twice.
+pub fn init() -> Board {
+  // Get access to the device specific peripherals from the peripheral access crate
+  let dp = pac::Peripherals::take().unwrap();
+  let cp = cortex_m::Peripherals::take().unwrap();
+
+  // Take ownership over the raw flash and rcc devices and convert them into the corresponding
+  // HAL structs
+  let mut flash = dp.FLASH.constrain();
+  let rcc = dp.RCC.constrain();
+
+  // Freeze the configuration of all the clocks in the system and store the frozen frequencies in
+  // `clocks`. Configure 72MHz system clock with USB support
+  use crate::hal::rcc::Config;
+  let mut rcc = rcc.freeze(
+    Config::hse(hal::HSE_HZ.Hz())
+      .sysclk(hal::SYSCLK_HZ.Hz())
+      .pclk1(hal::PCLK1_HZ.Hz()),
+    &mut flash.acr,
+  );
+
+  Diag::clocks_configured(hal::SYSCLK_HZ / 1_000_000, hal::PCLK1_HZ / 1_000_000);
+
+  // 1 kHz SysTick time base for timeouts and log timestamps, cycle counter
+  // for microsecond keying.
+  timer::init(cp.SYST, cp.DCB, cp.DWT, hal::SYSCLK_HZ);
+
+  // Acquire the GPIO ports and hand out the pins as the board wires them.
+  let pins = board::Pins::new(board::Ports {
+    gpioa: dp.GPIOA.split(&mut rcc),
+    gpiob: dp.GPIOB.split(&mut rcc),
+    gpioc: dp.GPIOC.split(&mut rcc),
+  });
+  info!("[main] Board {}", board::NAME);
+
+  // Status LEDs blink the boot pattern until the main loop starts.
+  led::init(
+    pins.status_led,
+    #[cfg(feature = "activity-leds")]
+    pins.tx_led,
+    #[cfg(feature = "activity-leds")]
+    pins.rx_led,
+  );
+  // AFIO is still initialized to enable alternate function remapping for peripherals
+  let _afio = dp.AFIO.constrain(&mut rcc);
+
+  // ADC1 measures the supply against the internal reference for beacons.
+  let adc = Adc::new(dp.ADC1, &mut rcc);
+
+  // ========================================
+  // OLED Display Setup (I2C2 on PB10/PB11)
+  // ========================================
+  #[cfg(not(feature = "no-display"))]
+  let i2c = {
+    Diag::oled_status("I2C2 OLED init (PB10/PB11)");
+    let i2c = BlockingI2c::new(
+      dp.I2C2,
+      (pins.scl, pins.sda),
+      Mode::Fast {
+        frequency: 400_000.Hz(),
+        duty_cycle: DutyCycle::Ratio2to1,
+      },
+      &mut rcc,
+      1000,
+      10,
+      1000,
+      1000,
+    );
+
+    // The sensors share the bus.
+    i2c_bus::init(i2c)
+  };
+  // ========================================
+  // E22-400M30S LoRa SPI Setup with SX1268 Driver
+  // ========================================
+  // The E22-400M30S uses SPI communication with SX1268 chip.  The control
+  // lines (NSS, BUSY, DIO1, NRST, TXEN/RXEN) come from the board pin map.
+  let dio1 = pins.dio1;
+  // Button to ground (active low): tap for the next status page, hold to pair.
+  let pair_button = pins.button;
+
+  // Configure SPI1; the radio is one device on it.
+  let spi1 = spi_bus::init1(dp.SPI1, (pins.sck, pins.miso, pins.mosi), &mut rcc);
+
+  // lora
+  // The driver and the direct command helpers share the control pins.
+  #[cfg(feature = "driver-sx1268-rs")]
+  let lora = {
+    let control = cortex_m::singleton!(
+      : ControlCell<sx126x::BlueHighControl> = ControlCell::new(LoraControl {
+        spi: spi_bus::shared_device(spi1, pins.nss),
+        nrst_pin: pins.nrst,
+        busy_pin: pins.busy,
+        // With DIO2 driving the switch, TXEN/RXEN stay low.
+        tx_pin: if cfg!(feature = "dio2-rf-switch") {
+          OptionalPin::none()
+        } else {
+          OptionalPin::new(pins.txen)
+        },
+        rx_pin: if cfg!(feature = "dio2-rf-switch") {
+          OptionalPin::none()
+        } else {
+          OptionalPin::new(pins.rxen)
+        },
+        switch_guard: radio::SwitchGuard::default(),
+      })
+    )
+    .unwrap();
+    sx126x::Sx126x::new(SharedControl::new(control))
+  };
+  // SX127x modules have no BUSY line and switch the antenna themselves.
+  #[cfg(feature = "sx1276")]
+  let lora = {
+    let _ = (pins.busy, pins.txen, pins.rxen);
+    sx1276::Sx1276::new(spi_bus::shared_device(spi1, pins.nss), pins.nrst)
+  };
+
+  Board {
+    flash,
+    rcc,
+    adc,
+    tim2: dp.TIM2,
+    #[cfg(not(feature = "no-display"))]
+    i2c,
+    usb: Peripheral {
+      usb: dp.USB,
+      pin_dm: pins.usb_dm,
+      pin_dp: pins.usb_dp,
+    },
+    lora,
+    dio1,
+    pair_button,
+    usart1: dp.USART1,
+    uart_tx: pins.uart_tx,
+    uart_rx: pins.uart_rx,
+    uart_strap: pins.uart_strap,
+    #[cfg(feature = "gps")]
+    usart2: dp.USART2,
+    #[cfg(feature = "gps")]
+    gps_tx: pins.gps_tx,
+    #[cfg(feature = "gps")]
+    gps_rx: pins.gps_rx,
+    #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+    spi2: spi_bus::init2(
+      dp.SPI2,
+      (pins.spi2_sck, pins.spi2_miso, pins.spi2_mosi),
+      &mut rcc,
+    ),
+    #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+    spi2_cs: pins.spi2_cs,
+    #[cfg(feature = "vbat")]
+    vbat: pins.vbat,
+  }
+}
@@ -0,0 +1,1158 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/app/commands.rs - 控制端口命令处理
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! The control port: AT command lines and binary host protocol frames,
+//! run against the state of the main loop.
+
+use core::fmt::Write;
+
+use defmt::{info, warn};
+
+use crate::app::{App, radio_task, save_settings, usb_bridge};
+use crate::command::{self, Command};
+use crate::diagnostics::BlueHighDiagnostics as Diag;
+use crate::log_control::{self, Category};
+use crate::per::PerMode;
+use crate::ping::PingTest;
+use crate::radio::{self, AirProfile, BlueHighRadio, Radio};
+use crate::startup::Stage;
+use crate::terminal::Direction;
+use crate::{
+  antenna, bench, bootloader, calibration, csma, device_id, event_log, flash_log, host, link,
+  lorawan, noise, ook, ota, packet, per, random, scan, sd_log, selftest, settings, sniffer, tdma,
+  telemetry, timer, timesync, uart, usb, version,
+};
+
+impl App {
+  /// Host commands from the control port, as AT lines or as binary
+  /// protocol frames.  True when one asks to start pairing.
+  pub(super) fn poll_control(&mut self, lora: &mut BlueHighRadio) -> bool {
+    if !usb::read_control_line(&mut self.cmd_line) {
+      return false;
+    }
+    if self.cmd_line[0] == 0 {
+      self.host_request(lora);
+      false
+    } else {
+      self.at_command(lora)
+    }
+  }
+
+  /// Answer a binary host protocol frame.
+  pub(super) fn host_request(&mut self, lora: &mut BlueHighRadio) {
+    let mut reset = false;
+    let (id, reply) = match host::decode(&mut self.cmd_line[1..]) {
+      None => (0, host::Reply::Error(host::Error::Malformed)),
+      Some(request) => {
+        info!("[main] Host request {}", request);
+        self.ui.wake(timer::now_ms());
+        let radio_idle = self.pairing.is_none() && self.scanner.is_none() && self.lorawan.idle();
+        let payload_limit = self.link.max_payload() - self.security.overhead();
+        let reply = match request.op {
+          host::Op::Hello => host::hello(self.boot.ok(Stage::Radio)),
+          host::Op::GetConfig => host::Reply::Config(host::Config::of(&self.settings)),
+          host::Op::SetConfig(config) => match config.radio() {
+            None => host::Reply::Error(host::Error::Invalid),
+            Some(_) if !radio_idle => host::Reply::Error(host::Error::Busy),
+            Some(params) => {
+              self.settings.node_address = config.node_address;
+              self.settings.peer_address = config.peer_address;
+              self.settings.addressing = config.addressing;
+              self.link.local = config.node_address;
+              self.link.peer = config.peer_address;
+              self.link.addressing = config.addressing;
+              self.repeater.set_local(config.node_address);
+              self.packetizer.set_limit(
+                self.link.header_len(),
+                self.link.max_payload() - self.security.overhead(),
+              );
+              if params != self.settings.radio {
+                self.settings.radio = params;
+                self.adr.reset();
+                lora.apply(&self.settings.radio);
+              }
+              if save_settings(&self.settings, &mut self.flash) == command::REPLY_OK {
+                host::Reply::Done
+              } else {
+                host::Reply::Error(host::Error::Failed)
+              }
+            }
+          },
+          host::Op::Transmit(_) if !radio_idle => host::Reply::Error(host::Error::Busy),
+          host::Op::Transmit(payload) if payload.len() > payload_limit => {
+            host::Reply::Error(host::Error::TooLong)
+          }
+          host::Op::Transmit(payload)
+            if !self.airtime.may_transmit(
+              &self.settings.airtime,
+              &self.settings.radio,
+              self.link.header_len() + payload.len() + self.security.overhead(),
+            ) =>
+          {
+            host::Reply::Error(host::Error::OverBudget)
+          }
+          host::Op::Transmit(payload) => {
+            self
+              .link
+              .encode(link::Kind::Data, payload, &mut self.tx_frame);
+            if self
+              .security
+              .seal(&mut self.tx_frame, self.link.header_len())
+            {
+              self.settings.tx_counter_base = self.security.reservation();
+              save_settings(&self.settings, &mut self.flash);
+            }
+            usb::set_radio_busy(true);
+            let sent = radio::transmit_blocking(lora, &self.dio1, &self.tx_frame);
+            lora.start_rx();
+            usb::set_radio_busy(false);
+            if sent {
+              Diag::usb_bridge_tx(payload.len());
+              self.ui.log_traffic(Direction::Tx, payload);
+              let logging = self.settings.flash_log && self.flash_dump.is_none();
+              let now = timer::now_ms();
+              let network_ms = self.clock.now(now);
+              radio_task::log_frame(
+                logging,
+                &mut self.flash_log,
+                &mut self.ext_flash,
+                network_ms,
+                true,
+                None,
+                &self.tx_frame,
+              );
+              #[cfg(feature = "sd-card")]
+              if let Some(card) = self.sd_card.as_mut() {
+                card.packet(now, network_ms, true, None, &self.tx_frame);
+              }
+              host::Reply::Done
+            } else {
+              Diag::error_occurred(Category::Radio, "LoRa TX failed");
+              host::Reply::Error(host::Error::Failed)
+            }
+          }
+          host::Op::Stats => {
+            let counters = Diag::counters();
+            host::Reply::Stats(host::Stats {
+              uptime_ms: timer::now_ms(),
+              lora_tx: counters.lora_tx,
+              lora_rx: counters.lora_rx,
+              errors: counters.errors,
+              crc_errors: Diag::crc_errors(),
+              host_drops: self.bridge.drops(),
+              airtime_minute_ms: self.airtime.minute_ms(),
+              airtime_hour_ms: self.airtime.hour_ms(),
+              packets_in_use: packet::in_use() as u32,
+              packets_peak: packet::peak() as u32,
+              usb_suspended: self.host.suspended,
+              usb_suspends: usb::stats().suspends,
+            })
+          }
+          host::Op::Reset => {
+            reset = true;
+            host::Reply::Done
+          }
+          host::Op::Telemetry { interval_s } => {
+            if telemetry::Stream::valid_interval(interval_s as u32) {
+              let framing = telemetry::Framing::Binary;
+              self
+                .telemetry_stream
+                .set(interval_s as u32, framing, timer::now_ms());
+              host::Reply::Done
+            } else {
+              host::Reply::Error(host::Error::Invalid)
+            }
+          }
+        };
+        (request.id, reply)
+      }
+    };
+    let mut frame = [0u8; host::FRAME_MAX];
+    if let Some(frame) = host::encode(&host::Response { id, reply }, &mut frame) {
+      usb::write_control(frame);
+    }
+    self.cmd_line.clear();
+
+    if reset {
+      // Give the USB interrupt a moment to deliver the response.
+      let start = timer::now_ms();
+      while timer::elapsed_ms(start) < 50 {}
+      cortex_m::peripheral::SCB::sys_reset();
+    }
+  }
+
+  /// Run an AT command line; true for `AT+PAIR`.
+  pub(super) fn at_command(&mut self, lora: &mut BlueHighRadio) -> bool {
+    let mut start_pairing = false;
+    let mut enter_bootloader = false;
+    let mut restart = false;
+    let reply = match Command::parse(&self.cmd_line) {
+      Some(command) => {
+        info!("[main] Host command {}", command);
+        self.ui.wake(timer::now_ms());
+        match command {
+          Command::Log(enabled) => {
+            Diag::set_usb_log(enabled);
+            command::REPLY_OK
+          }
+          Command::QueryEvents => {
+            for index in 0..event_log::len() {
+              if let Some(line) = event_log::line(index)
+                && !usb::write_control_all(line.as_bytes(), 500)
+              {
+                break;
+              }
+            }
+            command::REPLY_OK
+          }
+          Command::ClearEvents => {
+            event_log::clear();
+            command::REPLY_OK
+          }
+          Command::SetLogLevel(category, level) => {
+            self.settings.log_levels = self.settings.log_levels.with(category, level);
+            log_control::set(self.settings.log_levels);
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryLogLevel => {
+            usb::write_control(self.settings.log_levels.report().as_bytes());
+            command::REPLY_OK
+          }
+          Command::Packetizer(framing) => {
+            self.host.set_transparent(framing, &mut self.packetizer);
+            command::REPLY_OK
+          }
+          Command::SetAddress(address) => {
+            self.settings.node_address = address;
+            self.link.local = address;
+            self.repeater.set_local(address);
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::Relay(enabled) => {
+            self.settings.repeater = enabled;
+            self.repeater.set_enabled(enabled);
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryRelay => {
+            let routing = Diag::routing();
+            let mut line = heapless::String::<40>::new();
+            write!(
+              &mut line,
+              "+RELAY:{},{},{}\r\n",
+              self.repeater.enabled() as u8,
+              routing.relayed,
+              routing.dropped
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetTdma(config) => {
+            if self.link.addressing || config.role == tdma::Role::Off {
+              self.settings.tdma = config;
+              self.tdma.set_config(config);
+              save_settings(&self.settings, &mut self.flash)
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::QueryTdma => {
+            let config = self.tdma.config();
+            let role = match config.role {
+              tdma::Role::Off => "OFF",
+              tdma::Role::Master => "MASTER",
+              tdma::Role::Node => "NODE",
+            };
+            let mut line = heapless::String::<40>::new();
+            write!(
+              &mut line,
+              "+TDMA:{},{},{},{}\r\n",
+              role,
+              config.slots,
+              config.slot,
+              self.tdma.synced(timer::now_ms()) as u8
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetBattery(config) => {
+            self.settings.battery = config;
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryBattery => {
+            let config = self.settings.battery;
+            let mut line = heapless::String::<48>::new();
+            match self.battery.mv() {
+              Some(mv) => write!(&mut line, "+VBAT:{}", mv).ok(),
+              None => line.push_str("+VBAT:-").ok(),
+            };
+            write!(
+              &mut line,
+              ",{},{},{},{}\r\n",
+              config.ratio_milli,
+              config.low_mv,
+              config.full_mv,
+              if self.battery.low() { "LOW" } else { "OK" }
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::QueryDerate => {
+            usb::write_control(self.derate.report().as_bytes());
+            command::REPLY_OK
+          }
+          Command::QueryBoot => {
+            usb::write_control(self.boot.report().as_bytes());
+            command::REPLY_OK
+          }
+          Command::QueryRssi => {
+            // Off the link channel the reading would mean nothing.
+            if self.pairing.is_none() && self.scanner.is_none() && self.lorawan.idle() {
+              usb::write_control(noise::report(lora.rssi_inst()).as_bytes());
+              command::REPLY_OK
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::StreamStats(interval_s, framing) => {
+            self
+              .telemetry_stream
+              .set(interval_s, framing, timer::now_ms());
+            command::REPLY_OK
+          }
+          Command::StreamRssi(interval_ms) => {
+            self.noise_monitor.set(interval_ms, timer::now_ms());
+            command::REPLY_OK
+          }
+          Command::Sniff(enabled) => {
+            info!("[main] Packet capture {}", enabled);
+            self.sniffing = enabled;
+            // Every capture starts a new pcap stream.
+            if enabled {
+              self.bridge.write(&sniffer::file_header());
+            }
+            command::REPLY_OK
+          }
+          Command::Afc(enabled) => {
+            self.settings.afc = enabled;
+            save_settings(&self.settings, &mut self.flash);
+            if self.afc.set_enabled(enabled) {
+              lora.apply(&self.settings.radio);
+            }
+            command::REPLY_OK
+          }
+          Command::QueryAfc => {
+            usb::write_control(self.afc.report().as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetTrim(config) => {
+            self.settings.trim = config;
+            self.trim.restart(timer::now_ms());
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::SetAirtime(config) => {
+            self.settings.airtime = config;
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryAirtime => {
+            usb::write_control(self.airtime.report(&self.settings.airtime).as_bytes());
+            command::REPLY_OK
+          }
+          Command::QueryTrim => {
+            usb::write_control(self.trim.report(&self.settings.trim).as_bytes());
+            command::REPLY_OK
+          }
+          Command::RxMeta(enabled) => {
+            info!("[main] RX metadata header {}", enabled);
+            self.settings.rx_meta = enabled;
+            save_settings(&self.settings, &mut self.flash);
+            command::REPLY_OK
+          }
+          Command::CrcPass(enabled) => {
+            info!(
+              "[main] CRC-failed frames {}",
+              if enabled { "delivered" } else { "dropped" }
+            );
+            self.settings.crc_pass = enabled;
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryCrcPass => {
+            let mut line = heapless::String::<32>::new();
+            write!(
+              &mut line,
+              "+CRCPASS:{},{}\r\n",
+              self.settings.crc_pass as u8,
+              Diag::crc_errors()
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::RxEarly(enabled) => {
+            info!("[main] Early RX notification {}", enabled);
+            self.rx_early.set(enabled);
+            command::REPLY_OK
+          }
+          Command::CalibrationCarrier(power_dbm) => {
+            // A derated reading would not be the step's.
+            if self.pairing.is_none()
+              && self.scanner.is_none()
+              && self.lorawan.idle()
+              && self.derate.reason().is_none()
+            {
+              let params = radio::RadioParams {
+                power_dbm,
+                ..settings.radio
+              };
+              usb::set_radio_busy(true);
+              let keyed = lora.apply(&params) && calibration::carrier(lora, params.frequency_hz);
+              lora.apply(&self.settings.radio);
+              usb::set_radio_busy(false);
+              if keyed {
+                command::REPLY_OK
+              } else {
+                command::REPLY_ERROR
+              }
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::SetCalibration {
+            power_dbm,
+            output_deci_dbm,
+          } => {
+            let frequency_hz = self.settings.radio.frequency_hz;
+            self
+              .settings
+              .calibration
+              .set(frequency_hz, power_dbm, output_deci_dbm);
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::ClearCalibration => {
+            self.settings.calibration.clear();
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryCalibration => {
+            let frequency_hz = self.settings.radio.frequency_hz;
+            let mut line = heapless::String::<64>::new();
+            write!(&mut line, "+CAL:{}", calibration::segment(frequency_hz)).ok();
+            for reading in self.settings.calibration.readings(frequency_hz) {
+              match reading {
+                Some(deci_dbm) => write!(&mut line, ",{}", deci_dbm).ok(),
+                None => line.push_str(",-").ok(),
+              };
+            }
+            line.push_str("\r\n").ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetOutputPower(output_dbm) => {
+            let frequency_hz = self.settings.radio.frequency_hz;
+            match self
+              .settings
+              .calibration
+              .chip_power_dbm(frequency_hz, output_dbm)
+            {
+              Some(power_dbm) if self.pairing.is_none() => {
+                info!(
+                  "[main] {} dBm output: chip power {} dBm",
+                  output_dbm, power_dbm
+                );
+                self.settings.radio.power_dbm = power_dbm;
+                lora.apply(&self.settings.radio);
+                save_settings(&self.settings, &mut self.flash)
+              }
+              _ => command::REPLY_ERROR,
+            }
+          }
+          Command::SetCsma(config) => {
+            self.settings.csma = config;
+            csma::set(config);
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryTxQueue => {
+            usb::write_control(self.tx_queue.report().as_bytes());
+            command::REPLY_OK
+          }
+          Command::Ota(request) => {
+            if self.link.addressing
+              && self.link.peer != link::BROADCAST
+              && self.pairing.is_none()
+              && self.ota_tx.start(request, timer::now_ms(), &self.security)
+            {
+              command::REPLY_OK
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::QueryOta => {
+            let external = self.ext_flash.is_some();
+            usb::write_control(ota::report(&self.ota_tx, &self.ota_rx, external).as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetFlashLog(enabled) => {
+            if self.flash_log.is_some() {
+              self.settings.flash_log = enabled;
+              save_settings(&self.settings, &mut self.flash)
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::QueryFlashLog => {
+            match &self.flash_log {
+              Some(log) => usb::write_control(log.report(self.settings.flash_log).as_bytes()),
+              None => usb::write_control(flash_log::REPORT_NONE),
+            };
+            command::REPLY_OK
+          }
+          Command::ClearFlashLog => match (&mut self.flash_log, self.ext_flash.as_mut()) {
+            (Some(log), Some(storage)) if log.clear(storage).is_ok() => {
+              self.flash_dump = None;
+              command::REPLY_OK
+            }
+            _ => command::REPLY_ERROR,
+          },
+          Command::SetTime(network_ms) => match self.rtc.set(network_ms) {
+            true => command::REPLY_OK,
+            false => command::REPLY_ERROR,
+          },
+          Command::QueryRtc => {
+            usb::write_control(self.rtc.report(timer::now_ms()).as_bytes());
+            command::REPLY_OK
+          }
+          Command::QuerySdCard => {
+            #[cfg(feature = "sd-card")]
+            match &self.sd_card {
+              Some(card) => usb::write_control(card.report().as_bytes()),
+              None => usb::write_control(sd_log::REPORT_NONE),
+            };
+            #[cfg(not(feature = "sd-card"))]
+            usb::write_control(sd_log::REPORT_NONE);
+            command::REPLY_OK
+          }
+          Command::DumpFlashLog => match &self.flash_log {
+            Some(log) => {
+              self.flash_dump = Some(log.dump());
+              command::REPLY_OK
+            }
+            None => command::REPLY_ERROR,
+          },
+          Command::QueryCsma => {
+            usb::write_control(csma::report().as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetSwitchGuard(guard) => {
+            self.settings.switch_guard = guard;
+            #[cfg(feature = "driver-sx1268-rs")]
+            lora.set_switch_guard(guard);
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QuerySwitchGuard => {
+            let guard = self.settings.switch_guard;
+            let mut line = heapless::String::<32>::new();
+            write!(&mut line, "+RFSW:{},{}\r\n", guard.pre_us, guard.post_us).ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::QueryOutputPower => {
+            let params = self.settings.radio;
+            let mut line = heapless::String::<32>::new();
+            match self
+              .settings
+              .calibration
+              .output_deci_dbm(params.frequency_hz, params.tx_power_dbm())
+            {
+              Some(deci_dbm) => write!(&mut line, "+TXOUT:{}", deci_dbm).ok(),
+              None => line.push_str("+TXOUT:-").ok(),
+            };
+            write!(&mut line, ",{}\r\n", params.tx_power_dbm()).ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::QueryTime => {
+            let now = timer::now_ms();
+            let mut line = heapless::String::<48>::new();
+            match (self.clock.source(now), self.clock.now(now)) {
+              (Some(source), Some(network_ms)) => {
+                let source = match source {
+                  timesync::Source::Gps => "GPS",
+                  timesync::Source::Radio => "RADIO",
+                  timesync::Source::Rtc => "RTC",
+                };
+                write!(&mut line, "+TIME:{},", source).ok();
+                timesync::write_iso(&mut line, network_ms).ok();
+                line.push_str("\r\n").ok();
+              }
+              _ => line.push_str("+TIME:NONE\r\n").unwrap(),
+            }
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetLoRaWan(session) => {
+            self.settings.lorawan = session;
+            self.lorawan.set_session(session);
+            self.settings.lorawan_fcnt_base = self.lorawan.reservation();
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::LoRaWanSend(uplink) => {
+            if self.lorawan.session().is_set()
+              && self.lorawan.idle()
+              && self.pairing.is_none()
+              && self.scanner.is_none()
+            {
+              let params = lorawan::Device::uplink_params(&self.settings.radio);
+              if self.lorawan.encode(&uplink, &mut self.tx_frame) {
+                self.settings.lorawan_fcnt_base = self.lorawan.reservation();
+                save_settings(&self.settings, &mut self.flash);
+              }
+              info!(
+                "[main] LoRaWAN uplink port {}, {} bytes",
+                uplink.port,
+                self.tx_frame.len()
+              );
+              usb::set_radio_busy(true);
+              let sent = lora.apply_profile(&params, AirProfile::LoRaWan { downlink: false })
+                && radio::transmit_blocking(lora, &self.dio1, &self.tx_frame);
+              usb::set_radio_busy(false);
+              if sent {
+                self.lorawan.sent(&params, timer::now_ms());
+                command::REPLY_OK
+              } else {
+                Diag::error_occurred(Category::Proto, "LoRaWAN TX failed");
+                lora.apply(&self.settings.radio);
+                command::REPLY_ERROR
+              }
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::SetLoRaWanOtaa(otaa) => {
+            self.settings.lorawan_otaa = otaa;
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::LoRaWanJoin => {
+            if self.settings.lorawan_otaa.is_set()
+              && self.lorawan.idle()
+              && self.pairing.is_none()
+              && self.scanner.is_none()
+            {
+              // Settings that never saw a join start the count at a
+              // random value, so a wiped device does not repeat the
+              // DevNonces it used before.
+              random::harvest(lora);
+              if self.settings.lorawan_dev_nonce == 0 {
+                self.settings.lorawan_dev_nonce = random::random_u32() as u16 & 0x7FFF;
+              }
+              // The next DevNonce is stored before this one goes out.
+              let dev_nonce = self.settings.lorawan_dev_nonce;
+              self.settings.lorawan_dev_nonce = dev_nonce.wrapping_add(1);
+              save_settings(&self.settings, &mut self.flash);
+              self
+                .lorawan
+                .encode_join(&self.settings.lorawan_otaa, dev_nonce, &mut self.tx_frame);
+              info!("[main] LoRaWAN join request, DevNonce {}", dev_nonce);
+              let params = lorawan::Device::uplink_params(&self.settings.radio);
+              usb::set_radio_busy(true);
+              let sent = lora.apply_profile(&params, AirProfile::LoRaWan { downlink: false })
+                && radio::transmit_blocking(lora, &self.dio1, &self.tx_frame);
+              usb::set_radio_busy(false);
+              if sent {
+                self.lorawan.sent(&params, timer::now_ms());
+                command::REPLY_OK
+              } else {
+                Diag::error_occurred(Category::Proto, "LoRaWAN TX failed");
+                self.lorawan.cancel();
+                lora.apply(&self.settings.radio);
+                command::REPLY_ERROR
+              }
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::SetDataPort(source) => {
+            self.settings.data_port.source = source;
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::SetUartBaud(baud) => {
+            self.settings.data_port.baud = baud;
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryDataPort => {
+            let name = |source| match source {
+              uart::Source::Usb => "USB",
+              uart::Source::Uart => "UART",
+              uart::Source::Hid => "HID",
+            };
+            let active = match self.bridge {
+              uart::DataPort::Usb(_) => uart::Source::Usb,
+              uart::DataPort::Uart(_) => uart::Source::Uart,
+              uart::DataPort::Hid(_) => uart::Source::Hid,
+            };
+            let mut line = heapless::String::<40>::new();
+            write!(
+              &mut line,
+              "+PORT:{},{},{}\r\n",
+              name(active),
+              name(self.settings.data_port.source),
+              self.settings.data_port.baud
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetUsbIdentity(identity) => {
+            self.settings.usb_identity = identity;
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryUsbIdentity => {
+            let identity = self.settings.usb_identity;
+            let mut line = heapless::String::<64>::new();
+            write!(
+              &mut line,
+              "+USBID:{:04X},{:04X},{},{}\r\n",
+              identity.vid,
+              identity.pid,
+              identity.manufacturer(),
+              identity.product()
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::Modbus(enabled) => {
+            self.settings.modbus = enabled;
+            self
+              .host
+              .set_modbus(enabled, &mut self.packetizer, self.bridge.baud());
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryLoRaWan => {
+            let mut line = heapless::String::<40>::new();
+            write!(
+              &mut line,
+              "+LW:{:08X},{},",
+              self.lorawan.session().dev_addr,
+              self.lorawan.fcnt_up()
+            )
+            .ok();
+            match self.lorawan.fcnt_down() {
+              Some(fcnt) => write!(&mut line, "{}\r\n", fcnt).ok(),
+              None => line.push_str("-\r\n").ok(),
+            };
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetHopLimit(limit) => {
+            self.settings.hop_limit = limit;
+            self.link.hop_limit = limit;
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryMesh => {
+            let routing = Diag::routing();
+            let mut line = heapless::String::<64>::new();
+            write!(
+              &mut line,
+              "+MESH:{},{},{},{},{}\r\n",
+              self.link.hop_limit,
+              routing.relayed,
+              routing.suppressed,
+              routing.dropped,
+              routing.duplicates
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            self.route_report = Some(0);
+            command::REPLY_OK
+          }
+          Command::SetPeer(address) => {
+            self.settings.peer_address = address;
+            self.link.peer = address;
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::Addressing(enabled) => {
+            self.settings.addressing = enabled;
+            self.link.addressing = enabled;
+            self.packetizer.set_limit(
+              self.link.header_len(),
+              self.link.max_payload() - self.security.overhead(),
+            );
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::SetKey(key) => {
+            self.settings.link_key = key;
+            self.security.set_key(key);
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::Mic(enabled) => {
+            self.settings.security = enabled;
+            self.security.set_enabled(enabled);
+            self.packetizer.set_limit(
+              self.link.header_len(),
+              self.link.max_payload() - self.security.overhead(),
+            );
+            if enabled {
+              self.settings.tx_counter_base = self.security.reservation();
+            }
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryAddress => {
+            let mut line = heapless::String::<48>::new();
+            write!(
+              &mut line,
+              "+ADDR:{:04X},{:04X},{}\r\n",
+              self.link.local, self.link.peer, self.link.addressing as u8
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::QueryId => {
+            let mut line = heapless::String::<40>::new();
+            write!(&mut line, "+ID:{}\r\n", device_id::serial_string().as_str()).ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::SaveProfile(name) => match self.settings.save_profile(&name, &mut self.flash) {
+            Ok(_) => command::REPLY_OK,
+            Err(error) => {
+              warn!("[main] Profile {} not saved: {}", name, error);
+              command::REPLY_ERROR
+            }
+          },
+          Command::LoadProfile(name) => {
+            match settings::find_profile(&name).and_then(|slot| self.settings.load_profile(slot)) {
+              Some(loaded) => {
+                info!("[main] Profile {} loaded, restarting", name);
+                self.settings = loaded;
+                let reply = save_settings(&self.settings, &mut self.flash);
+                restart = reply == command::REPLY_OK;
+                reply
+              }
+              None => command::REPLY_ERROR,
+            }
+          }
+          Command::DeleteProfile(name) => match settings::find_profile(&name) {
+            Some(slot) => match settings::delete_profile(slot, &mut self.flash) {
+              Ok(()) => command::REPLY_OK,
+              Err(_) => {
+                Diag::error_occurred(Category::Boot, "settings save failed");
+                command::REPLY_ERROR
+              }
+            },
+            None => command::REPLY_ERROR,
+          },
+          Command::QueryProfiles => {
+            for (slot, name) in settings::profiles().iter().enumerate() {
+              if let Some(name) = name {
+                let mut line = heapless::String::<32>::new();
+                write!(&mut line, "+PROFILE:{}", name.as_str()).ok();
+                if self.settings.profile == Some(slot as u8) {
+                  line.push_str(",LOADED").ok();
+                }
+                line.push_str("\r\n").ok();
+                usb::write_control(line.as_bytes());
+              }
+            }
+            command::REPLY_OK
+          }
+          Command::ExportConfig => {
+            let mut record = [0u8; settings::RECORD_MAX];
+            match self.settings.export(&mut record) {
+              Some(len) => {
+                let mut line = heapless::String::<{ 8 + 2 * settings::RECORD_MAX }>::new();
+                line.push_str("+CFG:").ok();
+                for byte in &record[..len] {
+                  write!(&mut line, "{:02X}", byte).ok();
+                }
+                line.push_str("\r\n").ok();
+                // The line is longer than the control buffer.
+                if usb::write_control_all(line.as_bytes(), 500) {
+                  command::REPLY_OK
+                } else {
+                  command::REPLY_ERROR
+                }
+              }
+              None => command::REPLY_ERROR,
+            }
+          }
+          Command::ImportConfig(record) => match self.settings.import(&record) {
+            Some(imported) => {
+              info!("[main] Settings imported, restarting");
+              self.settings = imported;
+              let reply = save_settings(&self.settings, &mut self.flash);
+              // Every part of the bridge takes its settings at boot.
+              restart = reply == command::REPLY_OK;
+              reply
+            }
+            None => {
+              warn!("[main] Imported settings record not intact");
+              command::REPLY_ERROR
+            }
+          },
+          Command::QueryVersion => {
+            usb::write_control(version::report(self.boot.ok(Stage::Radio)).as_bytes());
+            usb::write_control(b"+FEAT:");
+            usb::write_control(version::FEATURES.as_bytes());
+            usb::write_control(b"\r\n");
+            usb::write_control(version::image_report(self.image_crc, self.image_check).as_bytes());
+            command::REPLY_OK
+          }
+          Command::Bootloader => {
+            enter_bootloader = true;
+            command::REPLY_OK
+          }
+          Command::Pair => {
+            start_pairing = true;
+            command::REPLY_OK
+          }
+          Command::SetRadio(params) => {
+            // Both ends change together, so there must be a single peer.
+            if self.link.addressing
+              && self.link.peer != link::BROADCAST
+              && self.pairing.is_none()
+              && self.reconfig.start(params, timer::now_ms())
+            {
+              command::REPLY_OK
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::Ping(count) => {
+            if self.link.addressing && self.link.peer != link::BROADCAST && self.pairing.is_none() {
+              self.ping_test = Some(PingTest::new(count, timer::now_ms()));
+              self.antenna_check.cancel();
+              if self.self_test.cancel() {
+                lora.apply(&self.settings.radio);
+              }
+              command::REPLY_OK
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::Antenna(slot) => {
+            if self.link.addressing && self.link.peer != link::BROADCAST && self.pairing.is_none() {
+              self.ping_test = Some(PingTest::new(antenna::PROBES, timer::now_ms()));
+              self.antenna_check.start(slot);
+              if self.self_test.cancel() {
+                lora.apply(&self.settings.radio);
+              }
+              command::REPLY_OK
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::QueryAntenna => {
+            for slot in [antenna::Slot::A, antenna::Slot::B] {
+              if let Some(measurement) = self.antenna_check.measurement(slot) {
+                usb_bridge::report_antenna(slot, &measurement);
+              }
+            }
+            if let Some((there_db, here_db)) = self.antenna_check.difference() {
+              let mut line = heapless::String::<32>::new();
+              write!(&mut line, "+ANT:B-A,{},{}\r\n", there_db, here_db).ok();
+              usb::write_control(line.as_bytes());
+            }
+            command::REPLY_OK
+          }
+          Command::SelfTest => {
+            if self.link.addressing && self.link.peer != link::BROADCAST && self.pairing.is_none() {
+              self.antenna_check.cancel();
+              match selftest::local(lora, &self.dio1, &self.settings.radio) {
+                Ok(noise_dbm) => {
+                  self.ping_test = Some(PingTest::new(selftest::PROBES, timer::now_ms()));
+                  self.self_test.start(noise_dbm);
+                }
+                Err(stage) => {
+                  self.ping_test = None;
+                  self.self_test.cancel();
+                  lora.apply(&self.settings.radio);
+                  Diag::error_occurred(Category::Radio, "self-test failed");
+                  usb::write_control(selftest::Outcome::Fail(stage).report().as_bytes());
+                }
+              }
+              command::REPLY_OK
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::Bench(seconds) => {
+            if self.link.addressing
+              && self.link.peer != link::BROADCAST
+              && self.pairing.is_none()
+              && self.bench_tx.is_none()
+            {
+              self.bench_run = self.bench_run.wrapping_add(1);
+              self.bench_tx = Some(bench::Sender::new(self.bench_run, seconds, timer::now_ms()));
+              command::REPLY_OK
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::Per(PerMode::Transmit { count, interval_ms }) => {
+            if self.link.addressing && self.link.peer != link::BROADCAST && self.pairing.is_none() {
+              self.per_tx = Some(per::Sender::new(count, interval_ms, timer::now_ms()));
+              command::REPLY_OK
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::Per(PerMode::Receive) => {
+            self.per_rx = Some(per::Receiver::new(timer::now_ms()));
+            command::REPLY_OK
+          }
+          Command::Per(PerMode::Stop) => {
+            self.per_tx = None;
+            if let Some(receiver) = self.per_rx.take() {
+              usb_bridge::report_per(&receiver.stats());
+            }
+            command::REPLY_OK
+          }
+          Command::Scan(range) => {
+            if self.pairing.is_none() && self.scanner.is_none() && self.lorawan.idle() {
+              info!("[main] Scanning {} channels", range.channels());
+              self.scanner = Some(scan::Scanner::new(range));
+              usb::write_control(b"+SCAN:freq_hz,rssi_avg_dbm,rssi_max_dbm\r\n");
+              command::REPLY_OK
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::Ook(sequence) => {
+            if self.pairing.is_none() && self.scanner.is_none() && self.lorawan.idle() {
+              usb::set_radio_busy(true);
+              let keyed = ook::transmit(lora, &sequence);
+              lora.apply(&self.settings.radio);
+              usb::set_radio_busy(false);
+              if keyed {
+                command::REPLY_OK
+              } else {
+                command::REPLY_ERROR
+              }
+            } else {
+              command::REPLY_ERROR
+            }
+          }
+          Command::SetCwId(beacon) => {
+            self.settings.cw_id = beacon;
+            self.last_cw_id = timer::now_ms();
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryCwId => {
+            let beacon = self.settings.cw_id;
+            let mut line = heapless::String::<32>::new();
+            let callsign = core::str::from_utf8(beacon.callsign()).unwrap_or("");
+            write!(&mut line, "+CWID:{},{}\r\n", callsign, beacon.interval_s).ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetBeacon(config) => {
+            self.settings.beacon = config;
+            self.beacon.restart(timer::now_ms());
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryBeacon => {
+            let config = self.settings.beacon;
+            let mut line = heapless::String::<24>::new();
+            write!(
+              &mut line,
+              "+BEACON:{},{}\r\n",
+              config.interval_s,
+              config.letters().as_str()
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::SetSensor(config) => {
+            self.settings.sensor = config;
+            self.sensors.restart(timer::now_ms());
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QuerySensor => {
+            let config = self.settings.sensor;
+            let mut line = heapless::String::<80>::new();
+            write!(
+              &mut line,
+              "+SENSOR:{},{},{}",
+              config.interval_s,
+              config.id,
+              self.sensors.names().as_str()
+            )
+            .ok();
+            if let Some(reading) = self.sensors.reading() {
+              reading.write_fields(&mut line).ok();
+            }
+            line.push_str("\r\n").ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::Adr(enabled) => {
+            self.settings.adr = enabled;
+            self.adr.set_enabled(enabled);
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::SetScreen(power) => {
+            self.settings.screen = power;
+            self.ui.set_power(power, timer::now_ms());
+            save_settings(&self.settings, &mut self.flash)
+          }
+          Command::QueryScreen => {
+            let power = self.settings.screen;
+            let mut line = heapless::String::<32>::new();
+            write!(
+              &mut line,
+              "+OLED:{},{},{}\r\n",
+              power.contrast, power.dim_after_s, power.off_after_s
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::QueryRadio => {
+            let params = self.settings.radio;
+            let mut line = heapless::String::<48>::new();
+            write!(
+              &mut line,
+              "+RADIO:{},{},{},{},{}\r\n",
+              params.frequency_hz,
+              params.power_dbm,
+              params.sf,
+              params.bandwidth.khz(),
+              params.cr
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+            command::REPLY_OK
+          }
+          Command::Unknown => command::REPLY_ERROR,
+        }
+      }
+      None => command::REPLY_ERROR,
+    };
+    self.cmd_line.clear();
+    usb::write_control(reply);
+
+    if enter_bootloader || restart {
+      // Give the USB interrupt a moment to deliver the reply.
+      let start = timer::now_ms();
+      while timer::elapsed_ms(start) < 50 {}
+      if enter_bootloader {
+        bootloader::enter();
+      }
+      cortex_m::peripheral::SCB::sys_reset();
+    }
+    start_pairing
+  }
+}
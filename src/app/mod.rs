@@ -0,0 +1,704 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/app/mod.rs - 固件主循环
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! The firmware application: board bring-up, then the main loop that bridges
+//! the host ports to the radio.
+//!
+//! [`run`] is what the `#[entry]` binary calls.  It brings the board up
+//! into an `App`, the state the loop owns, and then steps each subsystem
+//! once per pass.  The pieces it is built from live in submodules:
+//!
+//! - [`board_init`]: clocks, pins and the peripherals the loop owns.
+//! - `commands`: AT commands and host protocol frames from the control
+//!   port.
+//! - [`usb_bridge`]: the data port state, its framing, the control port
+//!   reports and the host streams.
+//! - [`radio_task`]: queueing, sending and logging of frames on the air,
+//!   the steps that use the radio and the receive path.
+//! - `peer`: radio changes, ping, firmware updates and link tests with
+//!   the peer.
+//! - [`ui`]: the pair button, the status pages and what the loop draws on
+//!   the display itself.
+
+pub mod board_init;
+mod commands;
+mod peer;
+pub mod radio_task;
+pub mod ui;
+pub mod usb_bridge;
+
+use defmt::info;
+
+use crate::adr::Adr;
+use crate::diagnostics::BlueHighDiagnostics as Diag;
+use crate::display::Panel;
+use crate::link::Link;
+use crate::log_control::Category;
+use crate::packetizer::Packetizer;
+use crate::pairing::Pairing;
+use crate::ping::PingTest;
+use crate::radio::{BlueHighRadio, Radio};
+use crate::radio_handle::Shared;
+use crate::remote::RemoteConfig;
+use crate::security::Security;
+use crate::settings::Settings;
+use crate::startup::Stage;
+use crate::ui::Ui;
+use crate::*;
+
+#[cfg(feature = "driver-sx1268-rs")]
+use sx1268_rs::config::LoRaHeaderType;
+
+use crate::hal::prelude::*;
+/// Log bytes mirrored to the control port per pass.
+const BUFFER_SIZE: usize = 64;
+
+/// The radio once the board is up.  The loop borrows it for each pass and
+/// interrupt handlers lock it in between, see [`crate::radio_handle`].
+static RADIO: Shared<BlueHighRadio> = Shared::new();
+
+/// Everything the main loop owns once the board is up.  Each pass calls
+/// one step per subsystem; the steps live in the submodules next to the
+/// code they drive.
+struct App {
+  flash: hal::flash::Parts,
+  /// Supply and temperature measurements.
+  adc: hal::adc::Adc<hal::pac::ADC1>,
+  display: display::Display,
+  dio1: radio::Dio1,
+  pair_button: board::Button,
+  /// What came up at boot, for `AT+BOOT?` and the status pages.
+  boot: startup::Status,
+  image_crc: u32,
+  image_check: version::ImageCheck,
+  #[cfg(feature = "spi-flash")]
+  ext_flash: Option<spi_flash::W25q>,
+  #[cfg(not(feature = "spi-flash"))]
+  ext_flash: Option<spi_flash::Absent>,
+  #[cfg(feature = "sd-card")]
+  sd_card: Option<sd_log::Logger>,
+  /// The log store as a read-only USB drive.
+  #[cfg(feature = "spi-flash")]
+  log_disk: Option<msc::FatImage>,
+  flash_log: Option<flash_log::Log>,
+  /// Progress of an `AT+FLOG=DUMP`.
+  flash_dump: Option<flash_log::Cursor>,
+  /// Persisted settings, including the radio parameters.
+  settings: Settings,
+  /// Settings as last logged; changes at runtime are diffed against them.
+  applied: Settings,
+  #[cfg(feature = "gps")]
+  gps_port: gps::Port,
+  /// Without the module nothing feeds it and there is never a fix.
+  gps: gps::Receiver,
+  /// Battery voltage on the board's VBAT pin; never sampled without it.
+  #[cfg(feature = "vbat")]
+  vbat_pin: board::Vbat,
+  battery: battery::Monitor,
+  /// Transmit power derating on heat and supply.
+  derate: derate::Guard,
+  /// Rolling airtime sums for the duty-cycle budget.
+  airtime: airtime::Meter,
+  /// Radio fault detection and recovery.
+  supervisor: supervisor::Supervisor,
+  bridge: uart::DataPort,
+  /// Persisted node addressing.
+  link: Link,
+  /// Duplicate suppression, the nodes heard and, with `AT+RELAY=1`, the
+  /// repeater.
+  seen: relay::Seen,
+  routes: relay::Routes,
+  repeater: relay::Repeater,
+  /// Next route table entry to report after `AT+MESH?`.
+  route_report: Option<usize>,
+  /// Time-slotted access, when configured.
+  tdma: tdma::Scheduler,
+  /// Network time, from GPS or from a GPS bridge.
+  clock: timesync::Clock,
+  /// Frame authentication.
+  security: Security,
+  /// LoRaWAN end device.
+  lorawan: lorawan::Device,
+  packetizer: Packetizer,
+  host: usb_bridge::Host,
+  rx_buf: [u8; packetizer::MAX_PAYLOAD],
+  tx_frame: heapless::Vec<u8, { packetizer::MAX_PAYLOAD }>,
+  tx_queue: txqueue::Queue,
+  /// Long enough for `AT+CFG=` with a full settings record.
+  cmd_line: heapless::Vec<u8, { 8 + 2 * settings::RECORD_MAX }>,
+  log_buf: [u8; BUFFER_SIZE],
+  pairing: Option<Pairing>,
+  pair_frame: heapless::Vec<u8, { pairing::FRAME_MAX }>,
+  buttons: ui::Buttons,
+  ui: Ui,
+  last_packet: Option<radio::PacketStatus>,
+  reconfig: RemoteConfig,
+  adr: Adr,
+  afc: afc::Afc,
+  trim: trim::Trim,
+  /// Control message received last pass, handled by `reconfig`.
+  pending_control: Option<remote::Message>,
+  ping_test: Option<PingTest>,
+  antenna_check: antenna::Check,
+  self_test: selftest::Check,
+  noise_monitor: noise::Monitor,
+  telemetry_stream: telemetry::Stream,
+  /// `AT+SNIFF=1`: frames go to the host as pcap records.
+  sniffing: bool,
+  rx_early: radio_task::RxEarly,
+  bench_tx: Option<bench::Sender>,
+  bench_rx: bench::Receiver,
+  bench_run: u8,
+  per_tx: Option<per::Sender>,
+  per_rx: Option<per::Receiver>,
+  scanner: Option<scan::Scanner>,
+  /// Last CW identification; the first one follows a full interval.
+  last_cw_id: u32,
+  beacon: beacon::Beacon,
+  /// Environment sensors next to the OLED panel.
+  sensors: sensor::Sensors,
+  /// Battery-backed clock on the same bus.
+  rtc: rtc::Rtc,
+  /// Probe received last pass and how it was heard.
+  pending_probe: Option<(ping::Probe, Option<radio::PacketStatus>)>,
+  /// Firmware updates: ours of the peer, the peer's of us.
+  pending_ota: Option<ota::Message>,
+  ota_tx: ota::Sender,
+  ota_rx: ota::Receiver,
+  ota_staged_ms: Option<u32>,
+  loop_counter: u32,
+  load_monitor: load::Monitor,
+}
+
+/// Bring the board up and run the bridge; never returns.
+pub fn run() -> ! {
+  bootloader::check_and_jump();
+
+  rtt_target::rtt_init_defmt!();
+  event_log::boot();
+
+  info!("=== Blue-High Boot ===");
+  info!("Version: {} ({})", version::VERSION, version::GIT_HASH);
+  info!("MCU: {}", hal::MCU);
+  info!("UID: {}", device_id::serial_string().as_str());
+  info!("Node address: 0x{:04X}", device_id::default_node_address());
+
+  Diag::boot_sequence("STM32F103C8T6 init start");
+
+  let board_init::Board {
+    mut flash,
+    mut rcc,
+    adc,
+    tim2,
+    #[cfg(not(feature = "no-display"))]
+    i2c,
+    usb,
+    mut lora,
+    dio1,
+    pair_button,
+    usart1,
+    uart_tx,
+    uart_rx,
+    uart_strap,
+    #[cfg(feature = "gps")]
+    usart2,
+    #[cfg(feature = "gps")]
+    gps_tx,
+    #[cfg(feature = "gps")]
+    gps_rx,
+    #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+    spi2,
+    #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+    spi2_cs,
+    #[cfg(feature = "vbat")]
+    vbat,
+  } = board_init::init();
+
+  // Create delay abstraction using TIM2
+  let mut delay = tim2.delay_us(&mut rcc);
+
+  // Each stage records whether it came up; boot goes on without it.
+  let mut boot = startup::Status::new();
+  let image_crc = version::image_crc();
+  let image_check = version::check_image(image_crc);
+  if !boot.record(Stage::Image, image_check != version::ImageCheck::Mismatch) {
+    // Capped before the radio first comes up; the guard keeps it so.
+    radio::set_power_cap(Some(derate::DERATED_POWER_DBM));
+  }
+  #[cfg(not(feature = "no-display"))]
+  let (mut display, display_ready) = display::init(i2c);
+  // Headless: PB10/PB11 and I2C2 stay unconfigured for other uses.
+  #[cfg(feature = "no-display")]
+  let (mut display, display_ready) = display::init();
+
+  if boot.record(Stage::Display, display_ready) {
+    Diag::oled_status(display::NAME);
+  } else {
+    Diag::oled_status("no answer, running without the display");
+  }
+
+  ui::splash(&mut display);
+  if boot.ok(Stage::Display) {
+    display.present();
+  }
+
+  // ========================================
+  // External SPI Flash (SPI2 on PB12-PB15)
+  // ========================================
+  #[cfg(feature = "spi-flash")]
+  let mut ext_flash = spi_flash::init(spi_bus::device(spi2, spi2_cs));
+  #[cfg(not(feature = "spi-flash"))]
+  let mut ext_flash: Option<spi_flash::Absent> = None;
+  // ========================================
+  // SD Card Logger (SPI2 on PB12-PB15)
+  // ========================================
+  #[cfg(feature = "sd-card")]
+  let sd_card = sd_log::init(spi_bus::device(spi2, spi2_cs));
+  #[cfg(feature = "sd-card")]
+  Diag::set_card_log(sd_card.is_some());
+  // The log store as a read-only USB drive.
+  #[cfg(feature = "spi-flash")]
+  let log_disk = ext_flash
+    .as_ref()
+    .map(|chip| msc::FatImage::new(spi_flash::Storage::capacity(chip)));
+  #[cfg(feature = "spi-flash")]
+  msc::attach(log_disk.map_or(0, |disk| disk.blocks()));
+  #[cfg(feature = "sd-card")]
+  msc::attach(sd_card.as_ref().map_or(0, sd_log::Logger::blocks));
+  let flash_log = ext_flash
+    .as_mut()
+    .and_then(|storage| flash_log::Log::open(storage).ok());
+  if let Some(storage) = ext_flash.as_mut()
+    && settings::restore(storage, &mut flash)
+  {
+    Diag::boot_sequence("Settings restored from external flash");
+  }
+
+  // Persisted settings, including the radio parameters.
+  let mut settings = Settings::load();
+  // Holding the button at power-up loads the next profile.
+  let mut profile_loaded = None;
+  if pair_button.is_low()
+    && let Some(slot) = settings.next_profile()
+    && let Some(loaded) = settings.load_profile(slot)
+  {
+    settings = loaded;
+    save_settings(&settings, &mut flash);
+    profile_loaded = settings::profiles()[slot];
+    info!("[main] Profile {} loaded", profile_loaded);
+  }
+  // ========================================
+  // USB CDC Setup (PA11/PA12)
+  // ========================================
+  // Enumerates with the identity and the data function from the settings,
+  // so it comes up after them.  From here on the USB stack is serviced by
+  // its interrupts.
+  let hid_data = settings.data_port.source == uart::Source::Hid;
+  let usb_bridge = usb::init(usb, &settings.usb_identity, hid_data);
+  Diag::boot_sequence("USB CDC data + control ports ready");
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  lora.set_switch_guard(settings.switch_guard);
+  csma::set(settings.csma);
+  log_control::set(settings.log_levels);
+  if boot.record(Stage::Radio, lora.apply(&settings.radio)) {
+    Diag::boot_sequence("LoRa radio ready");
+  } else {
+    Diag::error_occurred(Category::Radio, "radio init failed");
+  }
+  info!("[main] Radio {} ({})", band::MODULE, band::CHIP);
+
+  // ========================================
+  // UART Data Port (USART1 on PA9/PA10)
+  // ========================================
+  // Bridge data goes over USART1 instead of USB when the settings say so
+  // or the strap pin is grounded; AT commands stay on USB.
+  let uart_strapped = uart_strap.is_low();
+  // ========================================
+  // GPS Receiver (USART2 on PA2/PA3)
+  // ========================================
+  #[cfg(feature = "gps")]
+  let gps_port = gps::init(usart2, gps_tx, gps_rx, &mut rcc);
+  // Transmit power derating on heat and supply.
+  let mut derate = derate::Guard::new(timer::now_ms());
+  if !boot.ok(Stage::Image) {
+    derate.set_image_bad();
+  }
+
+  let bridge = if settings.data_port.source == uart::Source::Uart || uart_strapped {
+    info!(
+      "[main] Data port USART1, {} baud, strapped: {}",
+      settings.data_port.baud, uart_strapped
+    );
+    uart::DataPort::Uart(uart::init(
+      usart1,
+      uart_tx,
+      uart_rx,
+      settings.data_port.baud,
+      &mut rcc,
+    ))
+  } else if hid_data {
+    info!("[main] Data port USB HID");
+    uart::DataPort::Hid(usb_bridge)
+  } else {
+    uart::DataPort::Usb(usb_bridge)
+  };
+
+  // 打印配置信息到调试日志
+  #[cfg(feature = "driver-sx1268-rs")]
+  if let Some(config) = settings.radio.to_config() {
+    info!("╔══════════════════════════════════╗");
+    info!("║     {} LoRa Config      ║", band::MODULE);
+    info!("╠══════════════════════════════════╣");
+    info!("║ Freq : {}Hz", config.get_frequency_hz());
+    info!("║ Power: {} dBm", config.get_power_dbm());
+    info!("║ BW   : {} kHz", config.get_bandwidth_khz());
+    info!("║ SF   : SF{}", config.get_sf());
+    info!(
+      "║ CR   : CR{}/{}",
+      config.get_cr_ratio().0,
+      config.get_cr_ratio().1
+    );
+    info!("║ Preamble: {} symbols", config.get_preamble_length());
+    info!(
+      "║ CRC  : {}",
+      if config.get_crc_enabled() {
+        "on"
+      } else {
+        "off"
+      }
+    );
+    info!(
+      "║ Header: {}",
+      if config.get_header_type() == LoRaHeaderType::Explicit {
+        "explicit"
+      } else {
+        "implicit"
+      }
+    );
+    info!(
+      "║ Sync : 0x{:04X} ({})",
+      config.get_sync_word(),
+      if config.get_sync_word() == 0x3444 {
+        "public"
+      } else {
+        "private"
+      }
+    );
+    info!(
+      "║ PA   : duty={:02X} hp={:02X}",
+      config.get_pa_duty_cycle(),
+      config.get_pa_hp_max()
+    );
+    info!("╚══════════════════════════════════╝");
+  }
+  #[cfg(feature = "sx1276")]
+  info!("[main] LoRa config {}", settings.radio);
+
+  // Send a startup test packet to verify the TX path; TxDone raises DIO1.
+  // A radio that did not come up is not tried again here.
+  let sent = boot.ok(Stage::Radio) && radio::transmit_blocking(&mut lora, &dio1, &[1, 2, 3, 4, 5]);
+  if !boot.record(Stage::RadioTx, sent) {
+    Diag::error_occurred(Category::Radio, "LoRa startup TX failed");
+  }
+
+  // Enter continuous RX mode.
+  let listening = boot.ok(Stage::Radio) && lora.start_rx();
+  if boot.record(Stage::RadioRx, listening) {
+    Diag::boot_sequence("LoRa entered continuous RX mode");
+    // Seed the random numbers from the noise the radio hears.
+    random::harvest(&mut lora);
+  }
+
+  delay.delay_ms(100_u32);
+
+  Diag::boot_sequence("System init complete, entering main loop");
+  led::booted();
+  // Report what is missing on whatever still works.
+  if boot.degraded() {
+    Diag::boot_sequence("Degraded, see AT+BOOT?");
+    led::error();
+    usb::write_control(boot.report().as_bytes());
+  }
+
+  // Persisted node addressing.
+  let link = Link {
+    addressing: settings.addressing,
+    local: settings.node_address,
+    peer: settings.peer_address,
+    seq: 0,
+    hop_limit: settings.hop_limit,
+  };
+  info!("[main] Link {}", link);
+  let repeater = relay::Repeater::new(settings.repeater, link.local);
+  // Time-slotted access, when configured.
+  let tdma = tdma::Scheduler::new(settings.tdma);
+
+  // Frame authentication; reserve a block of transmit counters up front so a
+  // reboot never reuses one.
+  let security = Security::new(
+    settings.security,
+    settings.link_key,
+    settings.tx_counter_base,
+  );
+  if security.enabled() {
+    settings.tx_counter_base = security.reservation();
+    save_settings(&settings, &mut flash);
+  }
+  // LoRaWAN end device, with FCntUp reserved the same way.
+  let lorawan = lorawan::Device::new(settings.lorawan, settings.lorawan_fcnt_base);
+  if settings.lorawan.is_set() {
+    settings.lorawan_fcnt_base = lorawan.reservation();
+    save_settings(&settings, &mut flash);
+  }
+
+  let mut packetizer = Packetizer::new(packetizer::FrameMode::default());
+  packetizer.set_limit(link.header_len(), link.max_payload() - security.overhead());
+  let host = usb_bridge::Host::new(&bridge, settings.modbus, packetizer.mode());
+  packetizer.set_mode(host.framing(bridge.baud()));
+  packet::init();
+  // A press that loaded a profile at power-up does nothing else.
+  let buttons = ui::Buttons::new(profile_loaded.is_some(), timer::now_ms());
+  let mut ui = Ui::new(timer::now_ms());
+  ui.set_power(settings.screen, timer::now_ms());
+  if boot.degraded() {
+    ui.notice("Boot degraded", timer::now_ms(), crate::ui::NOTICE_MS);
+  } else if let Some(name) = profile_loaded {
+    ui.notice(name.as_str(), timer::now_ms(), crate::ui::NOTICE_MS);
+  }
+  let applied = settings.clone();
+  let adr = Adr::new(settings.adr);
+  let afc = afc::Afc::new(settings.afc);
+  #[cfg(not(feature = "no-display"))]
+  let sensors = sensor::Sensors::new(Some(i2c), timer::now_ms());
+  #[cfg(feature = "no-display")]
+  let sensors = sensor::Sensors::new(None, timer::now_ms());
+  #[cfg(not(feature = "no-display"))]
+  let rtc = rtc::Rtc::new(Some(i2c));
+  #[cfg(feature = "no-display")]
+  let rtc = rtc::Rtc::new(None);
+
+  let mut app = App {
+    flash,
+    adc,
+    display,
+    dio1,
+    pair_button,
+    boot,
+    image_crc,
+    image_check,
+    ext_flash,
+    #[cfg(feature = "sd-card")]
+    sd_card,
+    #[cfg(feature = "spi-flash")]
+    log_disk,
+    flash_log,
+    flash_dump: None,
+    settings,
+    applied,
+    #[cfg(feature = "gps")]
+    gps_port,
+    gps: gps::Receiver::new(),
+    #[cfg(feature = "vbat")]
+    vbat_pin: vbat,
+    battery: battery::Monitor::new(),
+    derate,
+    airtime: airtime::Meter::new(timer::now_ms()),
+    supervisor: supervisor::Supervisor::new(timer::now_ms()),
+    bridge,
+    link,
+    seen: relay::Seen::new(),
+    routes: relay::Routes::new(),
+    repeater,
+    route_report: None,
+    tdma,
+    clock: timesync::Clock::new(),
+    security,
+    lorawan,
+    packetizer,
+    host,
+    rx_buf: [0u8; packetizer::MAX_PAYLOAD],
+    tx_frame: heapless::Vec::new(),
+    tx_queue: txqueue::Queue::new(),
+    cmd_line: heapless::Vec::new(),
+    log_buf: [0u8; BUFFER_SIZE],
+    pairing: None,
+    pair_frame: heapless::Vec::new(),
+    buttons,
+    ui,
+    last_packet: None,
+    reconfig: RemoteConfig::new(),
+    adr,
+    afc,
+    trim: trim::Trim::new(timer::now_ms()),
+    pending_control: None,
+    ping_test: None,
+    antenna_check: antenna::Check::new(),
+    self_test: selftest::Check::new(),
+    noise_monitor: noise::Monitor::new(),
+    telemetry_stream: telemetry::Stream::new(),
+    sniffing: false,
+    rx_early: radio_task::RxEarly::new(),
+    bench_tx: None,
+    bench_rx: bench::Receiver::default(),
+    bench_run: 0,
+    per_tx: None,
+    per_rx: None,
+    scanner: None,
+    last_cw_id: timer::now_ms(),
+    beacon: beacon::Beacon::new(timer::now_ms()),
+    sensors,
+    rtc,
+    pending_probe: None,
+    pending_ota: None,
+    ota_tx: ota::Sender::new(),
+    ota_rx: ota::Receiver::new(),
+    ota_staged_ms: None,
+    loop_counter: 0,
+    load_monitor: load::Monitor::new(),
+  };
+
+  RADIO.init(lora);
+
+  // Main loop — USB ↔ LoRa bridge, one step per subsystem and pass.
+  loop {
+    app.begin_pass();
+
+    let start_pairing = app.poll_buttons();
+    // The radio stays with the loop for the steps; in between, while the
+    // loop sleeps, it is back in `RADIO`.
+    RADIO.lend(|lora| {
+      let start_pairing = start_pairing | app.poll_control(lora);
+      // Magic baud rates on the data port select the firmware mode.
+      app
+        .host
+        .poll_mode_request(&app.bridge, app.settings.modbus, &mut app.packetizer);
+
+      // Pairing, a spectrum scan and the LoRaWAN receive windows take the
+      // radio over; so does a suspended USB bus.
+      app.poll_pairing(lora, start_pairing);
+      app.poll_scan(lora);
+      app.poll_lorawan(lora);
+      app.poll_suspend(lora);
+
+      // Scheduled frames and upkeep; whatever needs the air waits while the
+      // radio is taken over.
+      app.poll_broadcasts(lora);
+      app.poll_repeater(lora);
+      app.poll_routes();
+      app.poll_clock();
+      app.poll_supervisor(lora);
+      app.poll_power(lora);
+      app.poll_streams(lora);
+      app.poll_trim(lora);
+      app.poll_beacons();
+
+      app.poll_reconfig(lora);
+      app.poll_ping(lora);
+      app.poll_ota();
+      app.poll_bench();
+      app.poll_per();
+
+      app.poll_host_data();
+      app.poll_transmit(lora);
+      // Modbus frames from the link wait for a quiet port.
+      app.host.poll_modbus(&mut app.bridge);
+      app.poll_receive(lora);
+
+      app.poll_settings();
+      app.poll_storage();
+      app.poll_log();
+      app.poll_status();
+    });
+    app.sleep_if_idle();
+
+    // Diag::heartbeat(app.loop_counter);
+  }
+}
+
+impl App {
+  /// Start a pass: count it and age the airtime sums.
+  fn begin_pass(&mut self) {
+    self.loop_counter = self.loop_counter.wrapping_add(1);
+    self.load_monitor.pass();
+    self.airtime.update(timer::now_ms());
+  }
+
+  /// Traffic and link tests stay off the air while pairing, scanning,
+  /// waiting for a LoRaWAN downlink or with the USB bus suspended.
+  fn radio_free(&self) -> bool {
+    self.pairing.is_none() && self.scanner.is_none() && self.lorawan.idle() && !self.host.suspended
+  }
+
+  /// Network time from the GPS module and the battery-backed clock.
+  fn poll_clock(&mut self) {
+    // NMEA sentences from the GPS module.
+    #[cfg(feature = "gps")]
+    while let Some(byte) = self.gps_port.read_byte() {
+      if self.gps.feed(byte, timer::now_ms()) {
+        defmt::debug!("[main] GPS fix {}", self.gps.fix(timer::now_ms()));
+        if let Some(fix) = self.gps.fix(timer::now_ms()) {
+          self.clock.on_fix(&fix, timer::now_ms());
+        }
+      }
+    }
+
+    // Battery-backed clock: keep network time without GPS, or check it.
+    self.rtc.poll(&mut self.clock, timer::now_ms());
+  }
+
+  /// Log what a runtime change of the settings changed and list it on screen.
+  fn poll_settings(&mut self) {
+    if self.settings != self.applied {
+      let changes = settings::diff(&self.applied, &self.settings);
+      if !changes.is_empty() {
+        self
+          .ui
+          .summary("Changed", &changes, timer::now_ms(), crate::ui::SUMMARY_MS);
+      }
+      self.applied = self.settings.clone();
+      if let Some(storage) = self.ext_flash.as_mut()
+        && settings::back_up(storage).is_err()
+      {
+        Diag::error_occurred(Category::Boot, "settings backup failed");
+      }
+    }
+  }
+
+  /// Nothing left for this pass: sleep until the next interrupt.  USB,
+  /// the UARTs and SysTick wake the core; DIO1 has no interrupt of its
+  /// own and is seen within the millisecond, as is timed work such as a
+  /// queued frame waiting for its slot or airtime budget.
+  fn sleep_if_idle(&mut self) {
+    if !self.dio1.is_high()
+      && self.pending_control.is_none()
+      && self.pending_probe.is_none()
+      && self.pending_ota.is_none()
+      && self.scanner.is_none()
+      && self.flash_dump.is_none()
+    {
+      self.load_monitor.sleep();
+    }
+  }
+}
+
+/// Persist `settings`, mapping the outcome to a command reply.
+fn save_settings(settings: &Settings, flash: &mut hal::flash::Parts) -> &'static [u8] {
+  match settings.save(flash) {
+    Ok(()) => command::REPLY_OK,
+    Err(_) => {
+      Diag::error_occurred(Category::Boot, "settings save failed");
+      command::REPLY_ERROR
+    }
+  }
+}
@@ -0,0 +1,313 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/app/peer.rs - 与对端的链路协商与测试
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! The loop steps shared with the peer: radio changes negotiated over
+//! control frames, ping probes, firmware updates and the throughput and
+//! packet error rate tests.
+
+use core::fmt::Write;
+
+use defmt::info;
+
+use crate::app::{App, radio_task, save_settings, usb_bridge};
+use crate::diagnostics::BlueHighDiagnostics as Diag;
+use crate::log_control::Category;
+use crate::radio::{self, BlueHighRadio, Radio};
+use crate::remote::{self, Change};
+use crate::txqueue::Priority;
+use crate::{link, ota, packetizer, per, ping, spi_flash, timer, usb};
+
+impl App {
+  /// Radio changes negotiated with the peer over control frames.
+  pub(super) fn poll_reconfig(&mut self, lora: &mut BlueHighRadio) {
+    if !self.radio_free() {
+      return;
+    }
+    let now = timer::now_ms();
+    // The lower address drives ADR for the link.
+    if !self.reconfig.busy()
+      && self.link.addressing
+      && self.link.peer != link::BROADCAST
+      && self.link.local < self.link.peer
+      && let Some(params) = self.adr.evaluate(&self.settings.radio)
+    {
+      info!("[main] ADR proposes SF{}", params.sf);
+      self.reconfig.start(params, now);
+    }
+    let reaction = match self.pending_control.take() {
+      Some(message) => self.reconfig.handle(message, self.settings.radio, now),
+      None => self.reconfig.poll(now),
+    };
+    if let Some(message) = reaction.send {
+      let mut body = [0u8; remote::MESSAGE_MAX];
+      let len = message.encode(&mut body);
+      if radio_task::queue_control(
+        &mut self.link,
+        &mut self.security,
+        &mut self.tx_queue,
+        Priority::Control,
+        &body[..len],
+      ) {
+        self.settings.tx_counter_base = self.security.reservation();
+        save_settings(&self.settings, &mut self.flash);
+      }
+    }
+    match reaction.change {
+      Some(Change::Switch(params)) => {
+        lora.apply(&params);
+      }
+      Some(Change::Commit(params)) => {
+        info!("[main] Radio change committed: {}", params);
+        self.adr.reset();
+        self.settings.radio = params;
+        save_settings(&self.settings, &mut self.flash);
+        usb::write_control(b"+RADIO:OK\r\n");
+      }
+      Some(Change::Revert(params)) => {
+        lora.apply(&params);
+        self.adr.reset();
+        Diag::error_occurred(Category::Radio, "radio change reverted");
+        usb::write_control(b"+RADIO:REVERTED\r\n");
+      }
+      Some(Change::Abort) => {
+        Diag::error_occurred(Category::Radio, "radio change not accepted");
+        usb::write_control(b"+RADIO:FAILED\r\n");
+      }
+      None => {}
+    }
+  }
+
+  /// Ping probes: answer the peer's, drive our own test.
+  pub(super) fn poll_ping(&mut self, lora: &mut BlueHighRadio) {
+    if !self.radio_free() {
+      return;
+    }
+    let now = timer::now_ms();
+    let mut reply = None;
+    let event = match self.pending_probe.take() {
+      Some((ping::Probe::Ping { seq, sent_ms }, heard)) => {
+        reply = Some(ping::Probe::Pong {
+          seq,
+          sent_ms,
+          heard: heard.unwrap_or(radio::PacketStatus {
+            rssi_dbm: 0,
+            snr_db: 0,
+          }),
+        });
+        None
+      }
+      Some((pong, heard)) => self
+        .ping_test
+        .as_mut()
+        .and_then(|test| test.handle(pong, heard, now)),
+      None => self.ping_test.as_mut().and_then(|test| test.poll(now)),
+    };
+
+    let mut line = heapless::String::<64>::new();
+    match event {
+      Some(ping::Event::Send(probe)) => reply = Some(probe),
+      Some(ping::Event::Reply {
+        seq,
+        rtt_ms,
+        there,
+        here,
+      }) => {
+        let here = here.unwrap_or(there);
+        self.antenna_check.on_reply(there, here);
+        self.self_test.on_reply(there, here);
+        write!(
+          &mut line,
+          "+PING:{},{}ms,{},{},{},{}\r\n",
+          seq, rtt_ms, there.rssi_dbm, there.snr_db, here.rssi_dbm, here.snr_db
+        )
+        .ok();
+      }
+      Some(ping::Event::Lost { seq }) => {
+        write!(&mut line, "+PING:{},TIMEOUT\r\n", seq).ok();
+      }
+      Some(ping::Event::Done(summary)) => {
+        self.ping_test = None;
+        if let Some((slot, measurement)) = self.antenna_check.finish(&summary) {
+          usb_bridge::report_antenna(slot, &measurement);
+          if measurement.suspect() {
+            Diag::error_occurred(Category::Radio, "antenna check failed");
+            self.ui.notice("Check antenna!", now, crate::ui::NOTICE_MS);
+          }
+        }
+        if let Some(outcome) = self.self_test.finish(&summary) {
+          lora.apply(&self.settings.radio);
+          if !outcome.passed() {
+            Diag::error_occurred(Category::Radio, "self-test failed");
+          }
+          usb::write_control(outcome.report().as_bytes());
+        }
+        write!(
+          &mut line,
+          "+PING:DONE,{}/{},{}%,{}/{}/{}ms\r\n",
+          summary.received,
+          summary.sent,
+          summary.loss_percent(),
+          summary.rtt_min_ms,
+          summary.rtt_avg_ms,
+          summary.rtt_max_ms
+        )
+        .ok();
+      }
+      None => {}
+    }
+    if !line.is_empty() {
+      usb::write_control(line.as_bytes());
+    }
+
+    if let Some(probe) = reply {
+      let mut body = [0u8; ping::PROBE_MAX];
+      let len = probe.encode(&mut body);
+      if radio_task::queue_control(
+        &mut self.link,
+        &mut self.security,
+        &mut self.tx_queue,
+        Priority::Control,
+        &body[..len],
+      ) {
+        self.settings.tx_counter_base = self.security.reservation();
+        save_settings(&self.settings, &mut self.flash);
+      }
+    }
+  }
+
+  /// Firmware updates: answer the peer's requests, drive our own.
+  pub(super) fn poll_ota(&mut self) {
+    if self.radio_free() {
+      let now = timer::now_ms();
+      let mut send = None;
+      let mut event = None;
+      match self.pending_ota.take() {
+        Some(ota::Message::Request { seq, request }) => {
+          let external = self
+            .ext_flash
+            .as_mut()
+            .map(|chip| chip as &mut dyn spi_flash::Storage);
+          let reaction =
+            self
+              .ota_rx
+              .handle(seq, request, &mut self.flash, external, &self.security);
+          if reaction.install && self.ota_staged_ms.is_none() {
+            self
+              .ui
+              .notice("Firmware received", now, crate::ui::NOTICE_MS);
+            self.ota_staged_ms = Some(now);
+          }
+          send = Some(reaction.reply);
+        }
+        Some(ota::Message::Reply { seq, status, next }) => {
+          event = self.ota_tx.handle(seq, status, next);
+        }
+        None => event = self.ota_tx.poll(now),
+      }
+      if let Some(ota::Event::Send(message)) = event {
+        send = Some(message);
+      } else if let Some(line) = event.and_then(|event| event.report()) {
+        usb::write_control(line.as_bytes());
+      }
+      if let Some(message) = send {
+        let mut body = [0u8; ota::MESSAGE_MAX];
+        let len = message.encode(&mut body);
+        if radio_task::queue_control(
+          &mut self.link,
+          &mut self.security,
+          &mut self.tx_queue,
+          Priority::Control,
+          &body[..len],
+        ) {
+          self.settings.tx_counter_base = self.security.reservation();
+          save_settings(&self.settings, &mut self.flash);
+        }
+      }
+    }
+    if let Some(staged) = self.ota_staged_ms
+      && timer::elapsed_ms(staged) >= ota::INSTALL_DELAY_MS
+    {
+      info!("[main] Installing the received firmware");
+      self.ota_rx.install();
+    }
+  }
+
+  /// Throughput benchmark: one frame per pass so USB keeps being serviced.
+  pub(super) fn poll_bench(&mut self) {
+    if !self.radio_free() {
+      return;
+    }
+    if let Some(sender) = self.bench_tx.as_mut() {
+      let mut body = [0u8; packetizer::MAX_PAYLOAD];
+      let size = self.link.max_payload() - self.security.overhead();
+      match sender.next(timer::now_ms(), &mut body[..size]) {
+        Some(len) => {
+          if radio_task::queue_control(
+            &mut self.link,
+            &mut self.security,
+            &mut self.tx_queue,
+            Priority::Data,
+            &body[..len],
+          ) {
+            self.settings.tx_counter_base = self.security.reservation();
+            save_settings(&self.settings, &mut self.flash);
+          }
+        }
+        None => {
+          let mut line = heapless::String::<32>::new();
+          write!(&mut line, "+BENCH:SENT,{}\r\n", sender.sent()).ok();
+          usb::write_control(line.as_bytes());
+          self.bench_tx = None;
+        }
+      }
+    }
+    if let Some(report) = self.bench_rx.poll(timer::now_ms()) {
+      usb_bridge::report_bench(&report);
+    }
+  }
+
+  /// Packet error rate test.
+  pub(super) fn poll_per(&mut self) {
+    if !self.radio_free() {
+      return;
+    }
+    let mut body = [0u8; per::FRAME_LEN];
+    if let Some(sender) = self.per_tx.as_mut()
+      && sender.next(timer::now_ms(), &mut body)
+    {
+      if radio_task::queue_control(
+        &mut self.link,
+        &mut self.security,
+        &mut self.tx_queue,
+        Priority::Data,
+        &body,
+      ) {
+        self.settings.tx_counter_base = self.security.reservation();
+        save_settings(&self.settings, &mut self.flash);
+      }
+      if sender.done() {
+        let mut line = heapless::String::<32>::new();
+        write!(&mut line, "+PER:SENT,{}\r\n", sender.sent()).ok();
+        usb::write_control(line.as_bytes());
+        self.per_tx = None;
+      }
+    }
+    if let Some(stats) = self
+      .per_rx
+      .as_mut()
+      .and_then(|receiver| receiver.poll(timer::now_ms()))
+    {
+      usb_bridge::report_per(&stats);
+    }
+  }
+}
@@ -0,0 +1,1004 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/app/radio_task.rs - 无线帧排队与记录
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! The radio side of the bridge: the loop steps that use the air, from
+//! pairing, scans and the LoRaWAN windows to scheduled broadcasts, the
+//! transmit queue and the receive path with its control frame dispatch,
+//! and the helpers they share for queueing control frames for the peer,
+//! reporting frames as they arrive and logging frames as they go on and
+//! come off the air.
+
+use core::fmt::Write;
+
+use defmt::{error, info, warn};
+
+use crate::app::{App, save_settings, ui::draw_spectrum, usb_bridge};
+use crate::diagnostics::BlueHighDiagnostics as Diag;
+use crate::link::{self, Link};
+use crate::log_control::Category;
+use crate::mode::BridgeMode;
+use crate::pairing::{self, Pairing, Progress};
+use crate::radio::{AirProfile, BlueHighRadio, Radio, RadioParams};
+use crate::remote::{self, RemoteConfig};
+use crate::security::Security;
+use crate::startup::Stage;
+use crate::terminal::Direction;
+use crate::txqueue::{self, Priority};
+use crate::uart::DataPort;
+use crate::ui::{self, Ui};
+use crate::{
+  airtime, beacon, bench, cw, flash_log, forwarder, lorawan, ota, packet, per, ping, radio, random,
+  relay, rxmeta, scan, sniffer, spi_flash, tdma, timer, timesync, usb,
+};
+
+/// How long the spectrum chart stays up after a scan.
+const SPECTRUM_HOLD_MS: u32 = 10_000;
+/// Downlink bytes reported in one `+LWRX` line.
+const LORAWAN_REPORT_MAX: usize = 48;
+
+/// `AT+RXEARLY=1`: each stage of an arriving frame is reported once on
+/// the control port, so the host can get ready before RxDone.
+pub struct RxEarly {
+  enabled: bool,
+  /// How far the frame arriving has been reported.
+  reported: Option<radio::RxProgress>,
+}
+
+impl RxEarly {
+  /// Reports off until `AT+RXEARLY=1`.
+  pub fn new() -> Self {
+    Self {
+      enabled: false,
+      reported: None,
+    }
+  }
+
+  /// Switch the reports on or off; a frame under way is reported afresh.
+  pub fn set(&mut self, enabled: bool) {
+    self.enabled = enabled;
+    self.reported = None;
+  }
+
+  /// Report a stage the radio reached since the last pass.  Only call
+  /// this while the radio listens for link frames.
+  pub fn step(&mut self, lora: &mut impl Radio, dio1: &radio::Dio1) {
+    if !self.enabled {
+      return;
+    }
+    let progress = if dio1.is_high() {
+      None
+    } else {
+      lora.rx_progress()
+    };
+    if let Some(stage) = progress
+      && progress > self.reported
+    {
+      let mut line = heapless::String::<24>::new();
+      write!(&mut line, "+RXEARLY:{}\r\n", stage.name()).ok();
+      usb::write_control(line.as_bytes());
+    }
+    self.reported = progress;
+  }
+}
+
+impl Default for RxEarly {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Send the head of `queue`, control first.  A frame waits for the TDMA
+/// slot and, unless it is a control frame, the airtime budget.  Returns
+/// the frame and whether it went out; the radio listens again either way.
+pub fn transmit_next(
+  lora: &mut impl Radio,
+  dio1: &radio::Dio1,
+  queue: &mut txqueue::Queue,
+  tdma: &tdma::Scheduler,
+  airtime: &airtime::Meter,
+  budget: &airtime::Config,
+  params: &RadioParams,
+) -> Option<(txqueue::Entry, bool)> {
+  let entry = queue.peek()?;
+  if !tdma.may_transmit(params, entry.frame.len(), timer::now_ms())
+    || (entry.priority != Priority::Control
+      && !airtime.may_transmit(budget, params, entry.frame.len()))
+  {
+    return None;
+  }
+  let entry = queue.pop()?;
+  usb::set_radio_busy(true);
+  let sent = radio::transmit_blocking(lora, dio1, &entry.frame);
+  // Re-enter continuous RX, also after a TX error.
+  lora.start_rx();
+  usb::set_radio_busy(false);
+  Some((entry, sent))
+}
+
+/// The bookkeeping once `entry` was sent, or failed to be.
+pub fn report_sent(entry: &txqueue::Entry, sent: bool, ui: &mut Ui) {
+  match (entry.origin, sent) {
+    (txqueue::Origin::Control, _) => {}
+    (txqueue::Origin::Host { offset, len }, true) => {
+      info!("[main] LoRa TX ok");
+      Diag::usb_bridge_tx(len);
+      ui.log_traffic(Direction::Tx, &entry.frame[offset..offset + len]);
+      ui.wake(timer::now_ms());
+    }
+    (txqueue::Origin::Host { .. }, false) => {
+      error!("[main] LoRa TX failed");
+      Diag::error_occurred(Category::Radio, "LoRa TX failed");
+      ui.notice("LoRa TX failed", timer::now_ms(), ui::NOTICE_MS);
+    }
+    (txqueue::Origin::Beacon(len) | txqueue::Origin::Sensor(len), true) => {
+      Diag::usb_bridge_tx(len);
+    }
+    (txqueue::Origin::Beacon(_), false) => {
+      Diag::error_occurred(Category::Proto, "beacon TX failed")
+    }
+    (txqueue::Origin::Sensor(_), false) => {
+      Diag::error_occurred(Category::Proto, "sensor TX failed")
+    }
+  }
+}
+
+/// Append a frame to the flash log, if there is one and `enabled`.
+pub fn log_frame<S: spi_flash::Storage>(
+  enabled: bool,
+  log: &mut Option<flash_log::Log>,
+  storage: &mut Option<S>,
+  network_ms: Option<u64>,
+  tx: bool,
+  quality: Option<radio::PacketStatus>,
+  frame: &[u8],
+) {
+  if enabled
+    && let (Some(log), Some(storage)) = (log.as_mut(), storage.as_mut())
+    && log
+      .append(storage, timer::now_ms(), network_ms, tx, quality, frame)
+      .is_err()
+  {
+    Diag::error_occurred(Category::Boot, "flash log write failed");
+  }
+}
+
+/// Frame, protect and queue a control message to the peer.  Returns `true`
+/// when the counter reservation must be persisted.
+pub fn queue_control(
+  link: &mut Link,
+  security: &mut Security,
+  queue: &mut txqueue::Queue,
+  priority: Priority,
+  body: &[u8],
+) -> bool {
+  let Some(mut frame) = packet::alloc() else {
+    return false;
+  };
+  link.encode(link::Kind::Control, body, &mut frame);
+  let persist = security.seal(&mut frame, link.header_len());
+  queue.push(priority, txqueue::Origin::Control, frame);
+  persist
+}
+
+/// Take the status of the frame just read, note it as the last one heard
+/// and show its RSSI.
+pub fn take_status(
+  last: &mut Option<radio::PacketStatus>,
+  ui: &mut Ui,
+) -> Option<radio::PacketStatus> {
+  let quality = radio::take_packet_status();
+  if let Some(quality) = quality {
+    *last = Some(quality);
+    ui.record_rssi(quality.rssi_dbm);
+  }
+  quality
+}
+
+/// Packet capture: hand the frame that raised the interrupt line to the
+/// host as a pcap record, bad CRC or not.  Nothing else happens to it.
+pub fn sniff(
+  lora: &mut impl Radio,
+  buf: &mut [u8],
+  port: &mut DataPort,
+  ui: &mut Ui,
+  last: &mut Option<radio::PacketStatus>,
+  clock: &timesync::Clock,
+) {
+  let Ok(Some(capture)) = lora.capture(buf) else {
+    return;
+  };
+  let now = timer::now_ms();
+  let quality = take_status(last, ui);
+  Diag::lora_rx(capture.len);
+  if !capture.crc_ok {
+    Diag::crc_error(capture.len);
+  }
+  let meta = rxmeta::Meta {
+    len: capture.len,
+    quality,
+    freq_error_hz: lora.freq_error_hz(),
+    received_ms: now,
+    addressed: false,
+    secured: false,
+    crc_error: !capture.crc_ok,
+  };
+  let header = sniffer::record_header(&meta, clock.now(now));
+  let frame = &buf[..capture.len];
+  if port.write(&header) < header.len() || port.write(frame) < frame.len() {
+    usb_bridge::overflow(port);
+  }
+  ui.log_traffic(Direction::Rx, frame);
+  ui.wake(now);
+}
+
+/// Read the frame that raised the interrupt line, bad CRC or not.  One
+/// that failed its CRC goes to the host behind a metadata header that
+/// flags it if `flag`, and is not handled any further: that returns
+/// `None`.  Otherwise returns what [`Radio::receive`] would.
+pub fn receive_bad_crc(
+  lora: &mut impl Radio,
+  buf: &mut [u8],
+  port: &mut DataPort,
+  ui: &mut Ui,
+  last: &mut Option<radio::PacketStatus>,
+  flag: bool,
+) -> Option<Result<Option<usize>, radio::RxError>> {
+  match lora.capture(buf) {
+    Ok(Some(capture)) if !capture.crc_ok => {
+      Diag::crc_error(capture.len);
+      let quality = take_status(last, ui);
+      let frame = &buf[..capture.len];
+      if flag {
+        let header = rxmeta::Meta {
+          len: capture.len,
+          quality,
+          freq_error_hz: lora.freq_error_hz(),
+          received_ms: timer::now_ms(),
+          addressed: false,
+          secured: false,
+          crc_error: true,
+        }
+        .encode();
+        if port.write(&header) < header.len() || port.write(frame) < frame.len() {
+          usb_bridge::overflow(port);
+        }
+        ui.log_traffic(Direction::Rx, frame);
+        ui.wake(timer::now_ms());
+      }
+      None
+    }
+    captured => Some(captured.map(|captured| captured.map(|capture| capture.len))),
+  }
+}
+
+/// Packet forwarder: hand `frame`, heard with `params`, to the host as
+/// an uplink record.  Nothing else happens to it.
+pub fn forward_uplink(
+  frame: &[u8],
+  params: &RadioParams,
+  port: &mut DataPort,
+  ui: &mut Ui,
+  last: &mut Option<radio::PacketStatus>,
+  clock: &timesync::Clock,
+) {
+  let quality = take_status(last, ui);
+  Diag::lora_rx(frame.len());
+  let now = timer::now_ms();
+  let record = forwarder::uplink(params, quality, now, clock.now(now), frame);
+  if port.write(record.as_bytes()) < record.len() {
+    usb_bridge::overflow(port);
+  }
+  ui.log_traffic(Direction::Rx, frame);
+  ui.wake(timer::now_ms());
+}
+
+impl App {
+  /// Pairing takes over the radio until it completes or times out.
+  pub(super) fn poll_pairing(&mut self, lora: &mut BlueHighRadio, start_pairing: bool) {
+    if start_pairing && self.pairing.is_none() && self.scanner.is_none() && self.lorawan.idle() {
+      info!("[main] Pairing as 0x{:04X}", self.link.local);
+      // Pairing replaces whatever radio change was under way.
+      self.reconfig = RemoteConfig::new();
+      self.pending_control = None;
+      random::harvest(lora);
+      let noise = random::noise(lora);
+      lora.apply(&pairing::channel());
+      self.pairing = Some(Pairing::start(
+        self.link.local,
+        self.settings.radio,
+        &noise,
+        timer::now_ms(),
+      ));
+      self
+        .ui
+        .notice("Pairing...", timer::now_ms(), pairing::TIMEOUT_MS);
+    }
+    if let Some(session) = self.pairing.as_mut() {
+      let mut rx_len = None;
+      if self.dio1.is_high()
+        && let Ok(Some(len)) = lora.receive(&mut self.rx_buf)
+      {
+        rx_len = Some(len);
+      }
+      let rx = rx_len.map(|len| &self.rx_buf[..len]);
+      match session.poll(timer::now_ms(), rx, &mut self.pair_frame) {
+        Progress::Pending => {}
+        Progress::Transmit => {
+          radio::transmit_blocking(lora, &self.dio1, &self.pair_frame);
+          lora.start_rx();
+        }
+        Progress::Complete(paired) => {
+          self.pairing = None;
+          info!("[main] Paired with 0x{:04X}, {}", paired.peer, paired.radio);
+          self.settings.peer_address = paired.peer;
+          self.settings.addressing = true;
+          self.settings.link_key = paired.key;
+          self.settings.security = true;
+          self.settings.radio = paired.radio;
+          self.link.peer = paired.peer;
+          self.link.addressing = true;
+          self.security.set_key(paired.key);
+          self.security.set_enabled(true);
+          self.settings.tx_counter_base = self.security.reservation();
+          self.packetizer.set_limit(
+            self.link.header_len(),
+            self.link.max_payload() - self.security.overhead(),
+          );
+          save_settings(&self.settings, &mut self.flash);
+          lora.apply(&self.settings.radio);
+
+          let mut line = heapless::String::<16>::new();
+          write!(&mut line, "+PAIR:{:04X}\r\n", paired.peer).ok();
+          usb::write_control(line.as_bytes());
+          self.ui.notice("Paired", timer::now_ms(), ui::NOTICE_MS);
+        }
+        Progress::TimedOut => {
+          self.pairing = None;
+          warn!("[main] Pairing timed out");
+          Diag::error_occurred(Category::Proto, "pairing timed out");
+          lora.apply(&self.settings.radio);
+          usb::write_control(b"+PAIR:TIMEOUT\r\n");
+          self
+            .ui
+            .notice("Pairing timed out", timer::now_ms(), ui::NOTICE_MS);
+        }
+      }
+    }
+  }
+
+  /// Spectrum scan: one channel per pass, only while the control port can
+  /// take the line.
+  pub(super) fn poll_scan(&mut self, lora: &mut BlueHighRadio) {
+    if let Some(sweep) = self.scanner.as_mut()
+      && usb::control_space() >= 32
+    {
+      match sweep.next_frequency() {
+        Some(frequency_hz) => {
+          let mut samples = [0i16; scan::SAMPLES as usize];
+          lora.listen_at(frequency_hz);
+          let start = timer::now_ms();
+          while timer::elapsed_ms(start) < scan::SETTLE_MS {}
+          for sample in samples.iter_mut() {
+            *sample = lora.rssi_inst().unwrap_or(i16::MIN);
+            let start = timer::now_ms();
+            while timer::elapsed_ms(start) < 1 {}
+          }
+          if let Some(channel) = sweep.record(&samples) {
+            let mut line = heapless::String::<32>::new();
+            write!(
+              &mut line,
+              "{},{},{}\r\n",
+              channel.frequency_hz, channel.rssi_avg, channel.rssi_max
+            )
+            .ok();
+            usb::write_control(line.as_bytes());
+          }
+        }
+        None => {
+          info!("[main] Scan complete");
+          draw_spectrum(&mut self.display, sweep.maxima());
+          if self.boot.ok(Stage::Display) {
+            self.display.present();
+          }
+          self.ui.hold(timer::now_ms(), SPECTRUM_HOLD_MS);
+          lora.apply(&self.settings.radio);
+          usb::write_control(b"+SCAN:DONE\r\n");
+          self.scanner = None;
+        }
+      }
+    }
+  }
+
+  /// LoRaWAN receive windows take the radio after an uplink.
+  pub(super) fn poll_lorawan(&mut self, lora: &mut BlueHighRadio) {
+    match self.lorawan.poll(timer::now_ms()) {
+      Some(lorawan::Step::Open(params)) => {
+        lora.apply_profile(&params, AirProfile::LoRaWan { downlink: true });
+      }
+      Some(lorawan::Step::Close { join: false }) => {
+        info!("[main] LoRaWAN no downlink");
+        lora.apply(&self.settings.radio);
+        usb::write_control(b"+LWRX:NONE\r\n");
+      }
+      Some(lorawan::Step::Close { join: true }) => {
+        info!("[main] LoRaWAN join not accepted");
+        lora.apply(&self.settings.radio);
+        usb::write_control(b"+LWJOIN:FAIL\r\n");
+      }
+      None => {}
+    }
+    if self.lorawan.listening()
+      && self.lorawan.joining()
+      && self.dio1.is_high()
+      && let Ok(Some(len)) = lora.receive(&mut self.rx_buf)
+    {
+      match self.lorawan.join_accept(&mut self.rx_buf[..len]) {
+        Ok(session) => {
+          info!("[main] LoRaWAN joined as {:08X}", session.dev_addr);
+          self.settings.lorawan = session;
+          self.settings.lorawan_fcnt_base = self.lorawan.reservation();
+          save_settings(&self.settings, &mut self.flash);
+          let mut line = heapless::String::<24>::new();
+          write!(&mut line, "+LWJOIN:{:08X}\r\n", session.dev_addr).ok();
+          usb::write_control(line.as_bytes());
+          lora.apply(&self.settings.radio);
+        }
+        Err(reject) => info!("[main] LoRaWAN join RX dropped: {}", reject),
+      }
+    } else if self.lorawan.listening()
+      && self.dio1.is_high()
+      && let Ok(Some(len)) = lora.receive(&mut self.rx_buf)
+    {
+      match self.lorawan.downlink(&mut self.rx_buf[..len]) {
+        Ok(downlink) => {
+          info!("[main] LoRaWAN downlink {}", downlink);
+          Diag::lora_rx(downlink.payload.len());
+          // Long payloads are cut to what the control port takes at once.
+          let mut line = heapless::String::<128>::new();
+          write!(
+            &mut line,
+            "+LWRX:{},{},",
+            downlink.port.unwrap_or(0),
+            downlink.ack as u8
+          )
+          .ok();
+          for byte in downlink.payload.iter().take(LORAWAN_REPORT_MAX) {
+            write!(&mut line, "{:02X}", byte).ok();
+          }
+          line.push_str("\r\n").ok();
+          usb::write_control(line.as_bytes());
+          lora.apply(&self.settings.radio);
+        }
+        Err(reject) => info!("[main] LoRaWAN RX dropped: {}", reject),
+      }
+    }
+  }
+
+  /// Frames sent on a schedule of their own: TDMA sync, network time and
+  /// CW identification.
+  pub(super) fn poll_broadcasts(&mut self, lora: &mut BlueHighRadio) {
+    // TDMA master: sync frame at the start of every cycle.
+    self.tdma.set_clock(self.clock);
+    if self.radio_free()
+      && self.link.addressing
+      && let Some(sync) = self.tdma.sync_due(&self.settings.radio, timer::now_ms())
+    {
+      self.link.encode_to(
+        link::BROADCAST,
+        link::Kind::Control,
+        &sync.encode(),
+        &mut self.tx_frame,
+      );
+      if self
+        .security
+        .seal(&mut self.tx_frame, self.link.header_len())
+      {
+        self.settings.tx_counter_base = self.security.reservation();
+        save_settings(&self.settings, &mut self.flash);
+      }
+      usb::set_radio_busy(true);
+      if !radio::transmit_blocking(lora, &self.dio1, &self.tx_frame) {
+        Diag::error_occurred(Category::Proto, "TDMA sync TX failed");
+      }
+      lora.start_rx();
+      usb::set_radio_busy(false);
+    }
+
+    // GPS or RTC bridge: network time for the other bridges; a bridge
+    // without time asks for it.
+    let time_len = self.link.header_len() + timesync::MESSAGE_LEN + self.security.overhead();
+    if self.radio_free()
+      && self.link.addressing
+      && self
+        .tdma
+        .may_transmit(&self.settings.radio, time_len, timer::now_ms())
+      && let Some(transfer) = self.clock.transfer_due(timer::now_ms())
+    {
+      self.link.encode_to(
+        link::BROADCAST,
+        link::Kind::Control,
+        &transfer.encode(),
+        &mut self.tx_frame,
+      );
+      if self
+        .security
+        .seal(&mut self.tx_frame, self.link.header_len())
+      {
+        self.settings.tx_counter_base = self.security.reservation();
+        save_settings(&self.settings, &mut self.flash);
+      }
+      usb::set_radio_busy(true);
+      if !radio::transmit_blocking(lora, &self.dio1, &self.tx_frame) {
+        Diag::error_occurred(Category::Proto, "Time TX failed");
+      }
+      lora.start_rx();
+      usb::set_radio_busy(false);
+    }
+
+    // CW identification on the link frequency, between frames.
+    if self.radio_free() && self.settings.cw_id.due(self.last_cw_id) {
+      self.last_cw_id = timer::now_ms();
+      usb::set_radio_busy(true);
+      if !cw::transmit(lora, &self.settings.cw_id, self.settings.radio.frequency_hz) {
+        Diag::error_occurred(Category::Radio, "CW ID failed");
+      }
+      lora.apply(&self.settings.radio);
+      usb::set_radio_busy(false);
+    }
+  }
+
+  /// Repeater: relay the held frame once its backoff expired and CAD finds
+  /// the channel clear.
+  pub(super) fn poll_repeater(&mut self, lora: &mut BlueHighRadio) {
+    if self.radio_free()
+      && self.repeater.held().is_some_and(|len| {
+        self
+          .tdma
+          .may_transmit(&self.settings.radio, len, timer::now_ms())
+      })
+    {
+      let action = self.repeater.poll(timer::now_ms(), || {
+        let active = lora.channel_active();
+        if active == Some(true) {
+          lora.start_rx();
+        }
+        active
+      });
+      match action {
+        relay::Action::Transmit(frame) => {
+          usb::set_radio_busy(true);
+          if radio::transmit_blocking(lora, &self.dio1, frame)
+            && let Some(header) = link::Header::parse(frame)
+          {
+            Diag::frame_relayed(header.src, header.seq);
+          } else {
+            Diag::error_occurred(Category::Proto, "relay TX failed");
+          }
+          lora.start_rx();
+          usb::set_radio_busy(false);
+        }
+        relay::Action::Dropped => Diag::relay_dropped("channel busy"),
+        relay::Action::Idle => {}
+      }
+    }
+  }
+
+  /// Radio supervisor: reset a hung or faulty radio and restore the
+  /// persisted configuration.
+  pub(super) fn poll_supervisor(&mut self, lora: &mut BlueHighRadio) {
+    if self.radio_free()
+      && let Some(cause) = self.supervisor.poll(lora, timer::now_ms())
+    {
+      let recovered = self
+        .supervisor
+        .recover(lora, &self.settings.radio, cause, timer::now_ms());
+      Diag::error_occurred(Category::Radio, "radio reset");
+      self.ui.notice(
+        if recovered {
+          "Radio reset"
+        } else {
+          "Radio failed"
+        },
+        timer::now_ms(),
+        ui::NOTICE_MS,
+      );
+      let mut line = heapless::String::<48>::new();
+      write!(
+        &mut line,
+        "+RADIO:RESET,{},{},{}\r\n",
+        cause.name(),
+        if recovered { "OK" } else { "FAILED" },
+        self.supervisor.recoveries()
+      )
+      .ok();
+      usb::write_control(line.as_bytes());
+    }
+  }
+
+  /// PA protection: high-power airtime, the MCU rail and the battery,
+  /// sampled between transmissions, may cap the TX power.
+  pub(super) fn poll_power(&mut self, lora: &mut BlueHighRadio) {
+    self
+      .derate
+      .on_airtime(self.settings.radio.tx_power_dbm(), timer::now_ms());
+    if self.radio_free() && self.derate.sample_due(timer::now_ms()) {
+      self
+        .derate
+        .on_supply(beacon::vdd_mv(self.adc.read_vref()), timer::now_ms());
+    }
+    #[cfg(feature = "vbat")]
+    if self.radio_free() && self.battery.due(timer::now_ms()) {
+      use crate::hal::prelude::*;
+
+      let vdd_mv = beacon::vdd_mv(self.adc.read_vref());
+      let raw: u16 = self.adc.read(&mut self.vbat_pin).unwrap_or(0);
+      if let Some(low) = self
+        .battery
+        .sample(&self.settings.battery, raw, vdd_mv, timer::now_ms())
+      {
+        self.derate.set_battery_low(low);
+      }
+    }
+    if self.radio_free()
+      && let Some(reason) = self.derate.update()
+    {
+      lora.apply(&self.settings.radio);
+      let notice = match reason {
+        Some(_) => "TX power derated",
+        None => "TX power restored",
+      };
+      self.ui.notice(notice, timer::now_ms(), ui::NOTICE_MS);
+      usb::write_control(self.derate.report().as_bytes());
+    }
+  }
+
+  /// Temperature trim: the environment sensor if it has a reading, the
+  /// MCU's own sensor otherwise.
+  pub(super) fn poll_trim(&mut self, lora: &mut BlueHighRadio) {
+    if self.radio_free() && self.trim.due(timer::now_ms()) {
+      let temperature_cdeg = self
+        .sensors
+        .reading()
+        .and_then(|reading| reading.temperature_cdeg)
+        .unwrap_or_else(|| self.adc.read_temp() * 100);
+      if self.trim.update(
+        &self.settings.trim,
+        self.settings.radio.frequency_hz,
+        temperature_cdeg,
+      ) {
+        lora.apply(&self.settings.radio);
+      }
+    }
+  }
+
+  /// Periodic beacon and sensor telemetry, sent whether or not a host is
+  /// attached.
+  pub(super) fn poll_beacons(&mut self) {
+    if self.radio_free()
+      && self.beacon.due(&self.settings.beacon, timer::now_ms())
+      && let Some(mut frame) = packet::alloc()
+    {
+      let now = timer::now_ms();
+      let telemetry = beacon::Telemetry {
+        node_address: self.link.local,
+        uptime_ms: now,
+        vdd_mv: beacon::vdd_mv(self.adc.read_vref()),
+        fix: self.gps.fix(now),
+        battery_mv: self.battery.mv(),
+        network_ms: self.clock.now(now),
+      };
+      let line = self.beacon.next(&self.settings.beacon, &telemetry, now);
+      info!("[main] Beacon {}", line.as_str());
+      self
+        .link
+        .encode(link::Kind::Data, line.as_bytes(), &mut frame);
+      if self.security.seal(&mut frame, self.link.header_len()) {
+        self.settings.tx_counter_base = self.security.reservation();
+        save_settings(&self.settings, &mut self.flash);
+      }
+      self
+        .tx_queue
+        .push(Priority::Beacon, txqueue::Origin::Beacon(line.len()), frame);
+    }
+
+    // Sensor telemetry, measured in the background and sent like a beacon.
+    self.sensors.poll(&self.settings.sensor, timer::now_ms());
+    if self.radio_free()
+      && let Some(line) = self.sensors.take_report(&self.settings.sensor)
+      && let Some(mut frame) = packet::alloc()
+    {
+      info!("[main] Sensors {}", line.as_str());
+      self
+        .link
+        .encode(link::Kind::Data, line.as_bytes(), &mut frame);
+      if self.security.seal(&mut frame, self.link.header_len()) {
+        self.settings.tx_counter_base = self.security.reservation();
+        save_settings(&self.settings, &mut self.flash);
+      }
+      self
+        .tx_queue
+        .push(Priority::Beacon, txqueue::Origin::Sensor(line.len()), frame);
+    }
+  }
+
+  /// Transmit queue: one frame per pass.
+  pub(super) fn poll_transmit(&mut self, lora: &mut BlueHighRadio) {
+    if self.radio_free()
+      && let Some((entry, sent)) = transmit_next(
+        lora,
+        &self.dio1,
+        &mut self.tx_queue,
+        &self.tdma,
+        &self.airtime,
+        &self.settings.airtime,
+        &self.settings.radio,
+      )
+    {
+      if sent {
+        let logging = self.settings.flash_log && self.flash_dump.is_none();
+        let now = timer::now_ms();
+        let network_ms = self.clock.now(now);
+        let frame = &entry.frame;
+        log_frame(
+          logging,
+          &mut self.flash_log,
+          &mut self.ext_flash,
+          network_ms,
+          true,
+          None,
+          frame,
+        );
+        #[cfg(feature = "sd-card")]
+        if let Some(card) = self.sd_card.as_mut() {
+          card.packet(now, network_ms, true, None, frame);
+        }
+      }
+      report_sent(&entry, sent, &mut self.ui);
+    }
+  }
+
+  /// LoRa → USB: forward received packets to the USB data port.
+  /// DIO1 is high when the chip has raised an RxDone (or error) IRQ.
+  pub(super) fn poll_receive(&mut self, lora: &mut BlueHighRadio) {
+    if !self.radio_free() {
+      return;
+    }
+    // Early RX notification, before RxDone.
+    self.rx_early.step(lora, &self.dio1);
+    if !self.dio1.is_high() {
+      return;
+    }
+    // Packet capture: every frame, bad CRC or not, goes to the host as
+    // a pcap record and nothing else happens to it.
+    if self.sniffing {
+      sniff(
+        lora,
+        &mut self.rx_buf,
+        &mut self.bridge,
+        &mut self.ui,
+        &mut self.last_packet,
+        &self.clock,
+      );
+      return;
+    }
+    // With AT+CRCPASS=1 a frame that failed its CRC goes to the host
+    // flagged in the metadata header, and is not handled any further.
+    // Without the header the host could not tell it from a good frame;
+    // other modes have no room for the flag.
+    let recv = if self.settings.crc_pass {
+      let flag = self.settings.rx_meta
+        && matches!(self.host.mode, BridgeMode::Transparent | BridgeMode::Framed);
+      let Some(recv) = receive_bad_crc(
+        lora,
+        &mut self.rx_buf,
+        &mut self.bridge,
+        &mut self.ui,
+        &mut self.last_packet,
+        flag,
+      ) else {
+        return;
+      };
+      recv
+    } else {
+      lora.receive(&mut self.rx_buf)
+    };
+    // The frequency error estimate holds until the next frame.
+    let freq_error = match recv {
+      Ok(Some(_)) => lora.freq_error_hz(),
+      _ => None,
+    };
+    if let Ok(Some(frame_len)) = recv {
+      let logging = self.settings.flash_log && self.flash_dump.is_none();
+      let frame = &self.rx_buf[..frame_len];
+      let quality = radio::packet_status();
+      let now = timer::now_ms();
+      let network_ms = self.clock.now(now);
+      log_frame(
+        logging,
+        &mut self.flash_log,
+        &mut self.ext_flash,
+        network_ms,
+        false,
+        quality,
+        frame,
+      );
+      #[cfg(feature = "sd-card")]
+      if let Some(card) = self.sd_card.as_mut() {
+        card.packet(now, network_ms, false, quality, frame);
+      }
+    }
+    // Packet forwarder: every frame goes to the host as an uplink record
+    // and nothing else happens to it.
+    if self.host.mode == BridgeMode::Forwarder
+      && let Ok(Some(frame_len)) = recv
+    {
+      forward_uplink(
+        &self.rx_buf[..frame_len],
+        &self.settings.radio,
+        &mut self.bridge,
+        &mut self.ui,
+        &mut self.last_packet,
+        &self.clock,
+      );
+      return;
+    }
+    // Drop later copies of a frame heard both directly and through
+    // relays, note the sender in the route table and hand frames for
+    // other nodes to the repeater.  With link security only frames with
+    // a valid MIC get that far: a forged copy would otherwise be relayed
+    // and shadow the genuine frame in the duplicate cache.
+    if let Ok(Some(frame_len)) = recv
+      && self.link.addressing
+      && let Some(header) = link::Header::parse(&self.rx_buf[..frame_len])
+    {
+      if !self
+        .security
+        .authentic(&self.rx_buf[..frame_len], self.link.header_len())
+      {
+        warn!(
+          "[main] LoRa RX from 0x{:04X} seq {} failed its MIC",
+          header.src, header.seq
+        );
+        Diag::error_occurred(Category::Proto, "LoRa RX authentication failed");
+        return;
+      }
+      let now = timer::now_ms();
+      if !self.seen.first(&header, now) {
+        if self.repeater.heard_again(&header) {
+          Diag::relay_suppressed(header.src, header.seq);
+        } else {
+          Diag::duplicate_dropped(header.src, header.seq);
+        }
+        return;
+      }
+      let quality = radio::packet_status();
+      self
+        .routes
+        .record(&header, quality.map_or(0, |quality| quality.rssi_dbm), now);
+      let snr_db = quality.map_or(0, |quality| quality.snr_db);
+      if self
+        .repeater
+        .offer(&self.rx_buf[..frame_len], &header, snr_db, now)
+        == relay::Offer::Dropped
+      {
+        Diag::relay_dropped("another frame held");
+      }
+    }
+    match recv {
+      Ok(Some(frame_len)) => match self.link.decode(&self.rx_buf[..frame_len]) {
+        Err(reject) => {
+          info!("[main] LoRa RX {} bytes dropped: {}", frame_len, reject);
+        }
+        Ok(received) => {
+          let src = received.src.unwrap_or(0);
+          let payload =
+            match self
+              .security
+              .open(&self.rx_buf[..frame_len], self.link.header_len(), src)
+            {
+              Ok(payload) => payload,
+              Err(reject) => {
+                warn!("[main] LoRa RX from 0x{:04X} rejected: {}", src, reject);
+                Diag::error_occurred(Category::Proto, "LoRa RX authentication failed");
+                return;
+              }
+            };
+          let quality = take_status(&mut self.last_packet, &mut self.ui);
+          if let Some(quality) = quality {
+            info!(
+              "[main] RSSI {} dBm, SNR {} dB",
+              quality.rssi_dbm, quality.snr_db
+            );
+            if src == self.link.peer {
+              self.adr.record(quality.snr_db, self.security.last_gap());
+            }
+          }
+          let from_peer = !self.link.addressing || src == self.link.peer;
+          if let Some(error_hz) = freq_error
+            && self.afc.on_frame(error_hz, from_peer)
+          {
+            lora.apply(&self.settings.radio);
+          }
+          if received.kind == link::Kind::Control {
+            // Only the paired peer may reconfigure this bridge; any node
+            // may be the TDMA master or the time source.
+            if let Some(sync) = tdma::Sync::decode(payload) {
+              let airtime_ms = self.settings.radio.airtime_us(frame_len).div_ceil(1_000);
+              self.tdma.on_sync(&sync, airtime_ms, timer::now_ms());
+            } else if let Some(message) = timesync::Message::decode(payload) {
+              let airtime_ms = self.settings.radio.airtime_us(frame_len).div_ceil(1_000);
+              self.clock.on_message(&message, airtime_ms, timer::now_ms());
+            } else if timesync::is_request(payload) {
+              self.clock.on_request();
+            } else if src != self.link.peer {
+              info!("[main] Control frame from 0x{:04X} ignored", src);
+            } else if let Some(message) = remote::Message::decode(payload) {
+              self.pending_control = Some(message);
+            } else if let Some(probe) = ping::Probe::decode(payload) {
+              self.pending_probe = Some((probe, quality));
+            } else if let Some(message) = ota::Message::decode(payload) {
+              self.pending_ota = Some(message);
+            } else if per::Receiver::is_per_frame(payload) {
+              if let Some(receiver) = self.per_rx.as_mut() {
+                receiver.on_frame(payload, quality.map(|quality| quality.rssi_dbm));
+              }
+            } else if bench::Receiver::is_bench_frame(payload) {
+              let airtime_us = self.settings.radio.airtime_us(frame_len);
+              if let Some(report) = self.bench_rx.on_frame(payload, airtime_us, timer::now_ms()) {
+                usb_bridge::report_bench(&report);
+              }
+            }
+            return;
+          }
+          let len = payload.len();
+          info!("[main] LoRa RX {} bytes, forwarding to USB", len);
+          Diag::lora_rx(len);
+          info!("[main] RX hex: {:02X}", payload);
+          if let Ok(s) = core::str::from_utf8(payload) {
+            info!("[main] RX str: {}", s);
+          } else {
+            info!("[main] RX str: <non-UTF8>");
+          }
+
+          // Queue received bytes for the USB CDC data port; overflow is
+          // dropped and counted by the bridge.
+          let meta = self.settings.rx_meta.then(|| rxmeta::Meta {
+            len,
+            quality,
+            freq_error_hz: freq_error,
+            received_ms: timer::now_ms(),
+            addressed: received.src.is_some(),
+            secured: self.security.enabled(),
+            crc_error: false,
+          });
+          self.host.forward(&mut self.bridge, payload, meta);
+          self.ui.log_traffic(Direction::Rx, payload);
+          self.ui.wake(timer::now_ms());
+        }
+      },
+      Ok(None) => {
+        // DIO1 glitch — IRQ cleared with no data; ignore.
+      }
+      Err(_) => {
+        error!("[main] LoRa RX error");
+        Diag::error_occurred(Category::Radio, "LoRa RX error");
+        if let Some(receiver) = self.per_rx.as_mut() {
+          receiver.on_rx_error();
+        }
+      }
+    }
+    // In continuous RX mode (0xFFFFFF) the chip auto-relistens after each
+    // packet — do NOT call start_lora_rx here; it would reset the buffer
+    // and corrupt subsequent packets.
+  }
+}
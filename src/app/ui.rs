@@ -0,0 +1,190 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/app/ui.rs - 主循环绘制
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! What the main loop shows itself: the boot splash, the spectrum scan,
+//! the pair button and the status pages of [`crate::ui`] once per pass.
+
+use embedded_graphics::{
+  mono_font::{MonoTextStyleBuilder, ascii::FONT_6X10},
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::Rectangle,
+  text::{Baseline, Text},
+};
+
+use crate::app::App;
+use crate::diagnostics::BlueHighDiagnostics as Diag;
+use crate::display::Panel;
+use crate::startup::Stage;
+use crate::ui::{RadioState, Screen, Snapshot, Ui};
+use crate::{display, hal, link, radio, timer, ui, usb};
+
+/// A press held past this and released toggles big digits.
+const HOLD_MS: u32 = 800;
+/// Holding the button this long starts pairing.
+const LONG_PRESS_MS: u32 = 2_000;
+/// Shorter presses are contact bounce.
+const BOUNCE_MS: u32 = 30;
+
+/// Show the MCU and that the panel answered until the status pages start.
+pub fn splash<D>(display: &mut D)
+where
+  D: DrawTarget<Color = BinaryColor>,
+{
+  let text_style = MonoTextStyleBuilder::new()
+    .font(&FONT_6X10)
+    .text_color(BinaryColor::On)
+    .build();
+
+  let _ = display.clear(BinaryColor::Off);
+  let _ = Text::with_baseline(hal::MCU, Point::new(0, 0), text_style, Baseline::Top).draw(display);
+  let _ =
+    Text::with_baseline("OLED Ready!", Point::new(0, 12), text_style, Baseline::Top).draw(display);
+}
+
+/// Draw the per-channel maxima of a scan as a full-screen bar chart.
+pub fn draw_spectrum<D>(display: &mut D, maxima: &[i16])
+where
+  D: DrawTarget<Color = BinaryColor>,
+{
+  let _ = display.clear(BinaryColor::Off);
+  let width = (display::WIDTH / maxima.len().max(1) as u32).max(1);
+  let screen = Rectangle::new(Point::zero(), Size::new(display::WIDTH, display::HEIGHT));
+  ui::draw_bars(display, maxima, width, screen);
+}
+
+/// The pair button: a tap turns the page, a press held past `HOLD_MS`
+/// and released toggles big digits, holding for `LONG_PRESS_MS` starts
+/// pairing.
+pub struct Buttons {
+  /// When the button went down and whether that press was handled.
+  down: Option<(u32, bool)>,
+}
+
+impl Buttons {
+  /// `held` when a press at power-up already did something, such as
+  /// loading a profile; that press does nothing else.
+  pub fn new(held: bool, now: u32) -> Self {
+    Self {
+      down: held.then_some((now, true)),
+    }
+  }
+
+  /// Follow the button once per pass; returns whether to start pairing.
+  pub fn step(&mut self, pressed: bool, ui: &mut Ui, now: u32) -> bool {
+    let mut start_pairing = false;
+    match (pressed, self.down) {
+      // A press that wakes the blank screen does nothing else.
+      (true, None) => self.down = Some((now, ui.wake(now))),
+      (true, Some((since, false))) if timer::elapsed_ms(since) >= LONG_PRESS_MS => {
+        start_pairing = true;
+        self.down = Some((since, true));
+      }
+      (false, Some((since, handled))) => {
+        // A press that started pairing or woke the screen does nothing
+        // on release.
+        let held = now.wrapping_sub(since);
+        if !handled {
+          if held >= HOLD_MS {
+            ui.toggle_big(now);
+          } else if held >= BOUNCE_MS {
+            ui.next_page(now);
+          }
+        }
+        self.down = None;
+      }
+      _ => {}
+    }
+    start_pairing
+  }
+}
+
+/// What the radio is doing, for the status line.
+pub fn radio_state(suspended: bool, scanning: bool) -> RadioState {
+  if suspended {
+    RadioState::Asleep
+  } else if radio::since_transmit_ms() < 500 {
+    RadioState::Transmitting
+  } else if scanning {
+    // Sweeping, not listening for frames.
+    RadioState::Idle
+  } else {
+    RadioState::Receiving
+  }
+}
+
+/// Draw one changed status line and follow screen power changes.  A
+/// panel that did not answer at boot (`panel_ok` false) is left alone.
+pub fn show_status<P, F>(ui: &mut Ui, display: &mut P, panel_ok: bool, now: u32, snapshot: F)
+where
+  P: Panel,
+  F: FnOnce() -> Snapshot,
+{
+  if ui.poll(now, snapshot, display) && panel_ok {
+    display.present();
+  }
+  match ui.screen_change(now).filter(|_| panel_ok) {
+    Some(Screen::On { contrast }) => {
+      display.power(true);
+      display.contrast(contrast);
+    }
+    Some(Screen::Off) => display.power(false),
+    None => {}
+  }
+}
+
+impl App {
+  /// The pair button: a long press starts pairing, a short hold toggles
+  /// big digits and a tap shows the next status page.  True to start
+  /// pairing.
+  pub(super) fn poll_buttons(&mut self) -> bool {
+    self
+      .buttons
+      .step(self.pair_button.is_low(), &mut self.ui, timer::now_ms())
+  }
+
+  /// Status display, one line per pass and only while the radio is idle.
+  pub(super) fn poll_status(&mut self) {
+    if !self.dio1.is_high() {
+      let now = timer::now_ms();
+      let radio_state = radio_state(self.host.suspended, self.scanner.is_some());
+      let snapshot = || {
+        let counters = Diag::counters();
+        Snapshot {
+          radio: self.settings.radio,
+          local: self.link.local,
+          peer: self.link.peer,
+          adr: self.adr.enabled(),
+          tx_frames: counters.lora_tx,
+          rx_frames: counters.lora_rx,
+          last_packet: self.last_packet,
+          errors: counters.errors,
+          log_dropped: Diag::usb_log_dropped(),
+          uptime_ms: now,
+          usb: usb::is_configured(),
+          radio_state,
+          paired: self.link.addressing && self.link.peer != link::BROADCAST,
+          gps: self.gps.fix(now),
+          battery: self.battery.level(&self.settings.battery),
+        }
+      };
+      show_status(
+        &mut self.ui,
+        &mut self.display,
+        self.boot.ok(Stage::Display),
+        now,
+        snapshot,
+      );
+    }
+  }
+}
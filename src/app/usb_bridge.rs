@@ -0,0 +1,487 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/app/usb_bridge.rs - 数据口分帧与控制口报告
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! The host side of the bridge: how the data port is framed in each bridge
+//! mode, the [`Host`] state the loop steps once per pass, the unsolicited
+//! reports written to the control port, and the loop steps that feed the
+//! host streams, the log store and the log mirror.
+
+use core::fmt::Write;
+
+use defmt::{debug, info, warn};
+
+use crate::app::{App, save_settings};
+use crate::diagnostics::BlueHighDiagnostics as Diag;
+use crate::link::{self, Link};
+use crate::log_control::Category;
+use crate::mode::{self, BridgeMode, ModeRequest};
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+use crate::msc;
+use crate::packetizer::Packetizer;
+use crate::radio::{BlueHighRadio, Radio, RadioParams};
+use crate::security::Security;
+use crate::txqueue::{self, Priority};
+use crate::uart::DataPort;
+use crate::ui::Ui;
+use crate::{
+  antenna, bench, bootloader, flash_log, host, kiss, modbus, noise, packetizer, per, radio, rxmeta,
+  telemetry, timer, usb,
+};
+
+/// Packetizer framing of bridge `mode`; `transparent` is the `AT+PKT`
+/// choice and `baud` the data port rate.
+pub fn framing(
+  mode: BridgeMode,
+  transparent: packetizer::FrameMode,
+  baud: u32,
+) -> packetizer::FrameMode {
+  match mode {
+    BridgeMode::Transparent | BridgeMode::Forwarder => transparent,
+    BridgeMode::Framed => packetizer::FrameMode::LengthPrefixed,
+    BridgeMode::Kiss => packetizer::FrameMode::Kiss,
+    BridgeMode::Modbus => packetizer::FrameMode::Modbus(modbus::silent_ms(baud)),
+  }
+}
+
+/// The data port as the loop sees it: the bridge mode, whether the bus
+/// is suspended and what waits for the host.
+pub struct Host {
+  /// How host data is framed and link data handed back.
+  pub mode: BridgeMode,
+  /// Framing used in transparent mode; `AT+PKT` changes it.
+  pub transparent: packetizer::FrameMode,
+  /// The mode the bridge falls back to without Modbus.
+  base: BridgeMode,
+  /// The line coding request handled last.
+  last_request: Option<ModeRequest>,
+  /// The host suspended the USB bus; the radio sleeps until it resumes.
+  pub suspended: bool,
+  /// Modbus frames from the link wait here for a quiet port.
+  modbus_out: modbus::Output,
+  kiss_frame: heapless::Vec<u8, { kiss::FRAME_MAX }>,
+}
+
+impl Host {
+  /// Start in Modbus mode if `modbus`, else in the port's base mode,
+  /// with `transparent` framing for transparent mode.
+  pub fn new(port: &DataPort, modbus: bool, transparent: packetizer::FrameMode) -> Self {
+    let base = match port.framed_only() {
+      true => BridgeMode::Framed,
+      false => BridgeMode::Transparent,
+    };
+    Self {
+      mode: match modbus {
+        true => BridgeMode::Modbus,
+        false => base,
+      },
+      transparent,
+      base,
+      last_request: None,
+      suspended: false,
+      modbus_out: modbus::Output::new(),
+      kiss_frame: heapless::Vec::new(),
+    }
+  }
+
+  /// Packetizer framing of the current mode at `baud`.
+  pub fn framing(&self, baud: u32) -> packetizer::FrameMode {
+    framing(self.mode, self.transparent, baud)
+  }
+
+  /// `AT+PKT`: use `framing` in transparent mode.
+  pub fn set_transparent(&mut self, framing: packetizer::FrameMode, packetizer: &mut Packetizer) {
+    self.transparent = framing;
+    if matches!(self.mode, BridgeMode::Transparent | BridgeMode::Forwarder) {
+      packetizer.set_mode(framing);
+    }
+  }
+
+  /// `AT+MODBUS`: switch Modbus mode on or back to the base mode.
+  pub fn set_modbus(&mut self, enabled: bool, packetizer: &mut Packetizer, baud: u32) {
+    self.mode = match enabled {
+      true => BridgeMode::Modbus,
+      false => self.base,
+    };
+    packetizer.set_mode(self.framing(baud));
+  }
+
+  /// Magic baud rates on the data port select the firmware mode; a
+  /// 1200 baud touch enters the bootloader.  Modbus keeps its mode.
+  pub fn poll_mode_request(&mut self, port: &DataPort, modbus: bool, packetizer: &mut Packetizer) {
+    let request = mode::from_line_coding(usb::host_baud(), usb::host_dtr());
+    if request == self.last_request {
+      return;
+    }
+    self.last_request = request;
+    match request {
+      Some(ModeRequest::Touch) => {
+        Diag::boot_sequence("1200 baud touch");
+        bootloader::enter();
+      }
+      Some(ModeRequest::Bridge(new_mode))
+        if new_mode != self.mode && !modbus && !port.framed_only() =>
+      {
+        info!("[main] Bridge mode {}", new_mode);
+        self.mode = new_mode;
+        packetizer.set_mode(self.framing(port.baud()));
+      }
+      _ => {}
+    }
+  }
+
+  /// USB suspend: stop bridging, drop host data that would be stale on
+  /// resume, put the radio to sleep and dim the screen.  Resume restores
+  /// the radio configuration `params`.
+  pub fn poll_suspend(
+    &mut self,
+    port: &mut DataPort,
+    packetizer: &mut Packetizer,
+    ui: &mut Ui,
+    lora: &mut impl Radio,
+    params: &RadioParams,
+  ) {
+    if port.suspended() == self.suspended {
+      return;
+    }
+    self.suspended = !self.suspended;
+    ui.set_suspended(self.suspended);
+    if self.suspended {
+      info!("[main] USB suspended");
+      packetizer.clear();
+      port.discard();
+      if !lora.sleep() {
+        Diag::error_occurred(Category::Radio, "radio sleep failed");
+      }
+    } else {
+      info!("[main] USB resumed");
+      lora.apply(params);
+    }
+  }
+
+  /// Host → LoRa: coalesce host data into frames and queue a complete
+  /// one.  Only bridges while the data port is open (DTR on USB, always
+  /// on the UART); while the radio is busy elsewhere host data waits in
+  /// the port queue, and so does a complete frame while the queue holds
+  /// enough host data.  Returns whether the sealed frame moved the
+  /// counter past its reservation, so the settings must be saved.
+  pub fn poll_data(
+    &mut self,
+    port: &mut DataPort,
+    packetizer: &mut Packetizer,
+    radio_free: bool,
+    queue: &mut txqueue::Queue,
+    link: &mut Link,
+    security: &mut Security,
+  ) -> bool {
+    if self.mode == BridgeMode::Modbus {
+      // The host may change the USB line rate at any time.
+      packetizer.set_mode(self.framing(port.baud()));
+    }
+    if !port.open() {
+      packetizer.clear();
+      return false;
+    }
+    if !radio_free
+      || !queue.has_room(Priority::Data)
+      || !packetizer.poll(timer::now_ms(), || port.read_byte())
+    {
+      return false;
+    }
+    let Some(mut frame) = packetizer.take() else {
+      return false;
+    };
+    let started = timer::now_cycles();
+    let offset = link.header_len();
+    let count = frame.len() - offset;
+    Diag::usb_bridge_rx(count);
+    Diag::usb_data_received(&frame[offset..]);
+    info!("[main] Sending {} bytes via LoRa", count);
+
+    // The payload is already behind the header room.
+    link.encode_in_place(link::Kind::Data, &mut frame);
+    let persist = security.seal(&mut frame, offset);
+    let origin = txqueue::Origin::Host { offset, len: count };
+    queue.push(Priority::Data, origin, frame);
+    debug!(
+      "[main] Frame queued in {} cycles",
+      timer::now_cycles().wrapping_sub(started)
+    );
+    persist
+  }
+
+  /// Modbus frames from the link go out once the port has been quiet for
+  /// a silent interval.
+  pub fn poll_modbus(&mut self, port: &mut DataPort) {
+    if let Some(frame) = self.modbus_out.poll(timer::now_ms(), port.baud())
+      && port.write(frame) < frame.len()
+    {
+      overflow(port);
+    }
+  }
+
+  /// Hand a payload from the link to the host, framed for the current
+  /// mode and behind the metadata header `meta` where the mode has room
+  /// for it.  Overflow is dropped and counted by the port.
+  pub fn forward(&mut self, port: &mut DataPort, payload: &[u8], meta: Option<rxmeta::Meta>) {
+    let len = payload.len();
+    let complete = match (self.mode, meta) {
+      (BridgeMode::Transparent | BridgeMode::Framed, Some(meta)) => {
+        let header = meta.encode();
+        port.write(&header) == header.len() && port.write(payload) == len
+      }
+      (BridgeMode::Transparent | BridgeMode::Forwarder, _) => port.write(payload) == len,
+      (BridgeMode::Framed, _) => port.write(&[len as u8]) == 1 && port.write(payload) == len,
+      (BridgeMode::Kiss, _) => {
+        kiss::encode(payload, &mut self.kiss_frame);
+        port.write(&self.kiss_frame) == self.kiss_frame.len()
+      }
+      (BridgeMode::Modbus, _) => {
+        if !self.modbus_out.push(payload) {
+          warn!("[main] Modbus frame of {} bytes dropped", len);
+        }
+        true
+      }
+    };
+    if !complete {
+      overflow(port);
+    }
+  }
+}
+
+/// Log that the host did not take everything written to the data port.
+pub fn overflow(port: &DataPort) {
+  warn!(
+    "[main] Host TX overflow, {} bytes dropped so far",
+    port.drops()
+  );
+}
+
+/// Report a finished benchmark run on the control port.
+pub fn report_bench(report: &bench::Report) {
+  use core::fmt::Write;
+
+  info!("[main] Benchmark {}", report);
+  let mut line = heapless::String::<96>::new();
+  write!(
+    &mut line,
+    "+BENCH:{},{}/{},{}%,{}bit/s,{}%\r\n",
+    report.run,
+    report.received,
+    report.sent,
+    report.loss_percent(),
+    report.goodput_bps(),
+    report.airtime_percent()
+  )
+  .ok();
+  usb::write_control(line.as_bytes());
+}
+
+/// Report an antenna measurement on the control port.
+pub fn report_antenna(slot: antenna::Slot, measurement: &antenna::Measurement) {
+  use core::fmt::Write;
+
+  let mut line = heapless::String::<64>::new();
+  write!(
+    &mut line,
+    "+ANT:{},{}/{},{},{},{},{},{}\r\n",
+    slot.name(),
+    measurement.received,
+    measurement.sent,
+    measurement.there.rssi_dbm,
+    measurement.there.snr_db,
+    measurement.here.rssi_dbm,
+    measurement.here.snr_db,
+    if measurement.suspect() { "CHECK" } else { "OK" }
+  )
+  .ok();
+  usb::write_control(line.as_bytes());
+}
+
+/// Report packet error rate statistics on the control port.
+pub fn report_per(stats: &per::Stats) {
+  use core::fmt::Write;
+
+  let rate = stats.per_permille();
+  let mut line = heapless::String::<96>::new();
+  write!(
+    &mut line,
+    "+PER:{},{},{},{},{}.{}%,{}/{}/{}dBm\r\n",
+    stats.received,
+    stats.missing,
+    stats.rx_errors,
+    stats.corrupted,
+    rate / 10,
+    rate % 10,
+    stats.rssi_min,
+    stats.rssi_avg,
+    stats.rssi_max
+  )
+  .ok();
+  usb::write_control(line.as_bytes());
+}
+
+impl App {
+  /// USB suspend puts the radio to sleep; resume wakes it.
+  pub(super) fn poll_suspend(&mut self, lora: &mut BlueHighRadio) {
+    self.host.poll_suspend(
+      &mut self.bridge,
+      &mut self.packetizer,
+      &mut self.ui,
+      lora,
+      &self.settings.radio,
+    );
+  }
+
+  /// Route table after `AT+MESH?`, one node per pass.
+  pub(super) fn poll_routes(&mut self) {
+    if let Some(index) = self.route_report
+      && usb::control_space() >= 40
+    {
+      let mut line = heapless::String::<40>::new();
+      match self.routes.get(index) {
+        Some(route) => {
+          write!(
+            &mut line,
+            "+NODE:{:04X},{},{},{}\r\n",
+            route.address,
+            route.hops,
+            route.rssi_dbm,
+            timer::elapsed_ms(route.last_ms) / 1_000
+          )
+          .ok();
+          self.route_report = Some(index + 1);
+        }
+        None => {
+          let _ = line.push_str("+NODE:END\r\n");
+          self.route_report = None;
+        }
+      }
+      usb::write_control(line.as_bytes());
+    }
+  }
+
+  /// Telemetry stream for monitoring systems, and the channel noise
+  /// stream.
+  pub(super) fn poll_streams(&mut self, lora: &mut BlueHighRadio) {
+    if let Some(framing) = self.telemetry_stream.due(timer::now_ms()) {
+      let counters = Diag::counters();
+      let load = self.load_monitor.sample();
+      let sample = host::Telemetry {
+        uptime_ms: timer::now_ms(),
+        lora_tx: counters.lora_tx,
+        lora_rx: counters.lora_rx,
+        errors: counters.errors,
+        crc_errors: Diag::crc_errors(),
+        rssi_dbm: self.last_packet.map(|packet| packet.rssi_dbm),
+        snr_db: self.last_packet.map(|packet| packet.snr_db),
+        airtime_ms: radio::tx_time_ms(),
+        duty_budget_ms: self.derate.budget_ms(),
+        cpu_load_permille: load.load_permille,
+        loop_max_us: load.loop_max_us,
+        isr_max_us: load.isr_max_us,
+      };
+      match framing {
+        telemetry::Framing::Text => usb::write_control(telemetry::report(&sample).as_bytes()),
+        telemetry::Framing::Binary => {
+          let response = host::Response {
+            id: 0,
+            reply: host::Reply::Telemetry(sample),
+          };
+          let mut frame = [0u8; host::FRAME_MAX];
+          if let Some(frame) = host::encode(&response, &mut frame) {
+            usb::write_control(frame);
+          }
+        }
+      }
+    }
+
+    // Channel noise stream, read while the radio listens.
+    if self.radio_free() && self.noise_monitor.due(timer::now_ms()) {
+      usb::write_control(noise::report(lora.rssi_inst()).as_bytes());
+    }
+  }
+
+  /// Host → LoRa: coalesce host data into frames and queue complete ones.
+  pub(super) fn poll_host_data(&mut self) {
+    let radio_free = self.radio_free();
+    if self.host.poll_data(
+      &mut self.bridge,
+      &mut self.packetizer,
+      radio_free,
+      &mut self.tx_queue,
+      &mut self.link,
+      &mut self.security,
+    ) {
+      self.settings.tx_counter_base = self.security.reservation();
+      save_settings(&self.settings, &mut self.flash);
+    }
+  }
+
+  /// The log store for the host: USB drive reads and `AT+FLOG=DUMP`.
+  pub(super) fn poll_storage(&mut self) {
+    // USB drive: read the block the host waits for.
+    #[cfg(feature = "spi-flash")]
+    if let (Some(disk), Some(storage)) = (&self.log_disk, self.ext_flash.as_mut()) {
+      msc::serve(|lba, block| disk.read(storage, lba, block));
+    }
+    #[cfg(feature = "sd-card")]
+    if let Some(card) = &self.sd_card {
+      msc::serve(|lba, block| card.read_block(lba, block));
+    }
+
+    // Flash log dump: one record per pass.
+    if let Some(cursor) = self.flash_dump.as_mut()
+      && let (Some(log), Some(storage)) = (&self.flash_log, self.ext_flash.as_mut())
+    {
+      let mut frame = [0u8; flash_log::FRAME_MAX];
+      let more = match log.next(storage, cursor, &mut frame) {
+        // A host that stopped reading ends the dump.
+        Ok(Some(record)) => usb::write_control_all(record.line(&frame).as_bytes(), 500),
+        Ok(None) | Err(_) => false,
+      };
+      if !more {
+        usb::write_control(b"+FLOG:END\r\n");
+        self.flash_dump = None;
+      }
+    }
+  }
+
+  /// Pending log lines: to the SD card, and to the control port while log
+  /// mode is enabled.
+  pub(super) fn poll_log(&mut self) {
+    // With an SD card every log line goes to its event log, and to the
+    // control port as far as it fits while log mode is enabled.
+    #[cfg(feature = "sd-card")]
+    if let Some(card) = self.sd_card.as_mut() {
+      let mut events = [0u8; crate::diagnostics::USB_LOG_CAPACITY];
+      let n = Diag::drain_usb_log(&mut events);
+      if n > 0 {
+        card.events(&events[..n], self.clock.now(timer::now_ms()));
+        if usb::is_configured() && Diag::usb_log_enabled() {
+          usb::write_control(&events[..n]);
+        }
+      }
+      let now = timer::now_ms();
+      card.poll(now, self.clock.now(now));
+    }
+
+    // Mirror pending log lines to the control port while log mode is enabled.
+    if usb::is_configured() {
+      let space = usb::control_space().min(self.log_buf.len());
+      let n = Diag::drain_usb_log(&mut self.log_buf[..space]);
+      if n > 0 {
+        usb::write_control(&self.log_buf[..n]);
+      }
+    }
+  }
+}
@@ -0,0 +1,126 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/band.rs - 射频频段与芯片差异
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Frequency band of the fitted E22 module.
+//!
+//! | feature    | module      | chip   | band        |
+//! |------------|-------------|--------|-------------|
+//! | (none)     | E22-400M30S | SX1268 | 410–510 MHz |
+//! | `band-900` | E22-900M30S | SX1262 | 850–930 MHz |
+//!
+//! With the `sx1276` backend the same bands apply to an RA-02 (SX1278) and
+//! an RFM95 (SX1276) respectively; the rest of this module is SX126x only.
+//!
+//! Both chips share the command set; they differ in the frequencies they
+//! accept, the image calibration window and the recommended SetPaConfig
+//! settings.  The SX1268 uses the driver's PA configuration.  On the SX1262
+//! the PA is set from the datasheet table (DS_SX1261-2 §13.1.14) after every
+//! initialisation: the row just above the requested power is chosen and
+//! the remainder is taken off in SetTxParams.
+
+use core::ops::RangeInclusive;
+
+pub use chip::*;
+
+/// SetPaConfig parameters plus the SetTxParams power that goes with them.
+#[cfg(feature = "driver-sx1268-rs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct PaSetting {
+  pub duty_cycle: u8,
+  pub hp_max: u8,
+  /// `0` selects the high-power PA of the SX1262/SX1268.
+  pub device_sel: u8,
+  pub tx_power_dbm: i8,
+}
+
+/// Image calibration window (CalibrateImage `freq1`, `freq2`) around
+/// `frequency_hz`, ±8 MHz in the chip's 4 MHz steps.
+#[cfg(feature = "driver-sx1268-rs")]
+pub fn image_calibration(frequency_hz: u32) -> [u8; 2] {
+  const STEP_HZ: u32 = 4_000_000;
+  let low = (frequency_hz - 2 * STEP_HZ) / STEP_HZ;
+  let high = (frequency_hz + 2 * STEP_HZ).div_ceil(STEP_HZ);
+  [low.min(0xFF) as u8, high.min(0xFF) as u8]
+}
+
+#[cfg(not(feature = "band-900"))]
+mod chip {
+  use super::RangeInclusive;
+  #[cfg(feature = "driver-sx1268-rs")]
+  use super::PaSetting;
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  pub const MODULE: &str = "E22-400M30S";
+  #[cfg(feature = "driver-sx1268-rs")]
+  pub const CHIP: &str = "SX1268";
+  #[cfg(feature = "sx1276")]
+  pub const MODULE: &str = "RA-02";
+  #[cfg(feature = "sx1276")]
+  pub const CHIP: &str = "SX1278";
+  pub const FREQUENCY_HZ: RangeInclusive<u32> = 410_000_000..=510_000_000;
+  pub const DEFAULT_FREQUENCY_HZ: u32 = 433_000_000;
+  pub const PAIRING_FREQUENCY_HZ: u32 = 434_000_000;
+  /// LoRaWAN RX2 channel of the EU433 plan.
+  pub const LORAWAN_RX2_FREQUENCY_HZ: u32 = 434_665_000;
+
+  /// The driver's PA configuration is kept as is.
+  #[cfg(feature = "driver-sx1268-rs")]
+  pub fn pa_setting(_power_dbm: i8) -> Option<PaSetting> {
+    None
+  }
+}
+
+#[cfg(feature = "band-900")]
+mod chip {
+  use super::RangeInclusive;
+  #[cfg(feature = "driver-sx1268-rs")]
+  use super::PaSetting;
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  pub const MODULE: &str = "E22-900M30S";
+  #[cfg(feature = "driver-sx1268-rs")]
+  pub const CHIP: &str = "SX1262";
+  #[cfg(feature = "sx1276")]
+  pub const MODULE: &str = "RFM95";
+  #[cfg(feature = "sx1276")]
+  pub const CHIP: &str = "SX1276";
+  pub const FREQUENCY_HZ: RangeInclusive<u32> = 850_000_000..=930_000_000;
+  pub const DEFAULT_FREQUENCY_HZ: u32 = 868_000_000;
+  pub const PAIRING_FREQUENCY_HZ: u32 = 869_000_000;
+  /// LoRaWAN RX2 channel of the EU868 plan.
+  pub const LORAWAN_RX2_FREQUENCY_HZ: u32 = 869_525_000;
+
+  /// Output power, paDutyCycle and hpMax with SetTxParams at +22 dBm.
+  #[cfg(feature = "driver-sx1268-rs")]
+  const PA_TABLE: [(i8, u8, u8); 4] = [
+    (14, 0x02, 0x02),
+    (17, 0x02, 0x03),
+    (20, 0x03, 0x05),
+    (22, 0x04, 0x07),
+  ];
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  pub fn pa_setting(power_dbm: i8) -> Option<PaSetting> {
+    let (row_dbm, duty_cycle, hp_max) = PA_TABLE
+      .iter()
+      .copied()
+      .find(|&(row_dbm, _, _)| row_dbm >= power_dbm)
+      .unwrap_or(PA_TABLE[PA_TABLE.len() - 1]);
+    Some(PaSetting {
+      duty_cycle,
+      hp_max,
+      device_sel: 0x00,
+      tx_power_dbm: (22 - (row_dbm - power_dbm)).clamp(-9, 22),
+    })
+  }
+}
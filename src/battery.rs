@@ -0,0 +1,149 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/battery.rs - 电池电压监测与低电量保护
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Battery voltage monitoring.
+//!
+//! Built with the `vbat` feature, the bridge samples the battery through a
+//! resistor divider on an ADC pin chosen by the board module (PA1 on
+//! Blue-High v1) every [`SAMPLE_MS`] and scales the reading, measured
+//! against the internal reference, by the divider ratio.
+//! `AT+VBAT=<ratio ‰>,<low mV>,<full mV>` sets the ratio in thousandths
+//! (2000 for two equal resistors) and the range shown by the battery icon
+//! on the OLED status bar; the voltage also goes into beacons (field `B`,
+//! see [`crate::beacon`]).
+//!
+//! An E22 at 30 dBm draws over half an amp, which a weak battery cannot
+//! supply without browning out the MCU.  Below `low` the battery counts as
+//! low and the transmit power is derated (see [`crate::derate`]) until it
+//! is [`HYSTERESIS_MV`] above `low` again.  Readings are smoothed, so the
+//! sag of a single transmission does not trip it.
+
+#![cfg_attr(not(feature = "vbat"), allow(dead_code))]
+
+/// Interval between samples.
+pub const SAMPLE_MS: u32 = 1_000;
+/// Rise above the low threshold needed to leave low-battery mode.
+pub const HYSTERESIS_MV: u16 = 100;
+/// Highest divider ratio, in thousandths.
+pub const MAX_RATIO: u16 = 20_000;
+/// Segments of the battery icon.
+pub const LEVELS: u8 = 3;
+
+/// Full scale of the 12-bit ADC.
+const ADC_MAX: u32 = 4_095;
+
+/// Persisted battery monitoring settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Config {
+  /// Battery voltage over pin voltage, in thousandths.
+  pub ratio_milli: u16,
+  /// Below this the transmit power is derated.
+  pub low_mv: u16,
+  /// Full battery, for the icon.
+  pub full_mv: u16,
+}
+
+impl Default for Config {
+  /// One Li-ion cell behind two equal resistors.
+  fn default() -> Self {
+    Self {
+      ratio_milli: 2_000,
+      low_mv: 3_500,
+      full_mv: 4_200,
+    }
+  }
+}
+
+impl Config {
+  pub fn new(ratio_milli: u16, low_mv: u16, full_mv: u16) -> Option<Self> {
+    ((1_000..=MAX_RATIO).contains(&ratio_milli) && low_mv < full_mv).then_some(Self {
+      ratio_milli,
+      low_mv,
+      full_mv,
+    })
+  }
+}
+
+/// Smoothed battery voltage and the low-battery state.
+pub struct Monitor {
+  mv: Option<u16>,
+  low: bool,
+  last_ms: u32,
+}
+
+impl Monitor {
+  pub fn new() -> Self {
+    Self {
+      mv: None,
+      low: false,
+      last_ms: 0,
+    }
+  }
+
+  /// Whether the next sample is due.
+  pub fn due(&self, now: u32) -> bool {
+    self.mv.is_none() || now.wrapping_sub(self.last_ms) >= SAMPLE_MS
+  }
+
+  /// Take a pin reading `pin_raw` with the supply at `vdd_mv`.  Returns the
+  /// new low-battery state when it changed.
+  pub fn sample(&mut self, config: &Config, pin_raw: u16, vdd_mv: u16, now: u32) -> Option<bool> {
+    self.last_ms = now;
+    let pin_mv = pin_raw as u32 * vdd_mv as u32 / ADC_MAX;
+    let mv = (pin_mv * config.ratio_milli as u32 / 1_000).min(u16::MAX as u32) as u16;
+    let mv = match self.mv {
+      Some(previous) => ((3 * previous as u32 + mv as u32) / 4) as u16,
+      None => mv,
+    };
+    self.mv = Some(mv);
+
+    let low = if self.low {
+      mv < config.low_mv.saturating_add(HYSTERESIS_MV)
+    } else {
+      mv < config.low_mv
+    };
+    if low == self.low {
+      return None;
+    }
+    self.low = low;
+    if low {
+      defmt::warn!("[battery] low at {} mV", mv);
+    } else {
+      defmt::info!("[battery] recovered at {} mV", mv);
+    }
+    Some(low)
+  }
+
+  /// Battery voltage, once sampled.
+  pub fn mv(&self) -> Option<u16> {
+    self.mv
+  }
+
+  pub fn low(&self) -> bool {
+    self.low
+  }
+
+  /// Icon segments to fill, `0` to [`LEVELS`].
+  pub fn level(&self, config: &Config) -> Option<u8> {
+    let mv = self.mv?;
+    let span = (config.full_mv - config.low_mv) as u32;
+    let above = mv.saturating_sub(config.low_mv) as u32;
+    Some((above * LEVELS as u32).div_ceil(span).min(LEVELS as u32) as u8)
+  }
+}
+
+impl Default for Monitor {
+  fn default() -> Self {
+    Self::new()
+  }
+}
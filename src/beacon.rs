@@ -0,0 +1,198 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/beacon.rs - 周期信标发射
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Periodic telemetry beacon.
+//!
+//! `AT+BEACON=<interval s>,<fields>` makes the bridge send a short text
+//! line every `interval` seconds, whether or not a host is attached.  The
+//! line goes out as an ordinary data frame, so a receiving bridge hands it
+//! to its host like typed text:
+//!
+//! ```text
+//! #42 id=1A2B up=3600 vdd=3.297
+//! ```
+//!
+//! `#` counts beacons since boot, which makes lost beacons visible in
+//! propagation tests.  `fields` picks what follows it: `I` the node address,
+//! `U` the uptime in seconds, `V` the supply voltage in volts, measured
+//! against the internal reference, and `P` the GPS position in degrees
+//! with the altitude in metres (`pos=48.11730,11.51667 alt=545`), or
+//! `pos=-` without a fix; see [`crate::gps`].  `B` adds the battery voltage
+//! in volts (`bat=3.912`), or `bat=-` without battery monitoring; see
+//! [`crate::battery`].  `T` adds the network time in UTC
+//! (`utc=2026-10-15T12:34:56.789Z`), or `utc=-` without one; see
+//! [`crate::timesync`] and [`crate::rtc`].
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::{gps, timesync};
+
+/// Shortest interval accepted.
+pub const MIN_INTERVAL_S: u16 = 5;
+/// Longest beacon line.
+pub const LINE_MAX: usize = 112;
+
+/// Beacon fields as persisted.
+pub const FIELD_ID: u8 = 0x01;
+pub const FIELD_UPTIME: u8 = 0x02;
+pub const FIELD_VOLTAGE: u8 = 0x04;
+pub const FIELD_POSITION: u8 = 0x08;
+pub const FIELD_BATTERY: u8 = 0x10;
+pub const FIELD_TIME: u8 = 0x20;
+const FIELDS: [(u8, u8); 6] = [
+  (b'I', FIELD_ID),
+  (b'U', FIELD_UPTIME),
+  (b'V', FIELD_VOLTAGE),
+  (b'P', FIELD_POSITION),
+  (b'B', FIELD_BATTERY),
+  (b'T', FIELD_TIME),
+];
+
+/// Persisted beacon configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Config {
+  /// Seconds between beacons, `0` for off.
+  pub interval_s: u16,
+  /// `FIELD_*` bits.
+  pub fields: u8,
+}
+
+impl Config {
+  /// Accept `0` (off) or intervals of at least [`MIN_INTERVAL_S`], with
+  /// fields given as letters.
+  pub fn parse(interval_s: u16, letters: &[u8]) -> Option<Self> {
+    if interval_s != 0 && interval_s < MIN_INTERVAL_S {
+      return None;
+    }
+    let mut fields = 0;
+    for letter in letters {
+      let (_, bit) = FIELDS
+        .iter()
+        .find(|(name, _)| *name == letter.to_ascii_uppercase())?;
+      fields |= bit;
+    }
+    Some(Self { interval_s, fields })
+  }
+
+  /// Field letters, for `AT+BEACON?`.
+  pub fn letters(&self) -> String<6> {
+    let mut letters = String::new();
+    for (name, bit) in FIELDS {
+      if self.fields & bit != 0 {
+        let _ = letters.push(name as char);
+      }
+    }
+    letters
+  }
+}
+
+/// What the beacon can report.
+pub struct Telemetry {
+  pub node_address: u16,
+  pub uptime_ms: u32,
+  pub vdd_mv: u16,
+  pub fix: Option<gps::Fix>,
+  pub battery_mv: Option<u16>,
+  pub network_ms: Option<u64>,
+}
+
+/// Beacon schedule and counter.
+pub struct Beacon {
+  count: u32,
+  last_ms: u32,
+}
+
+impl Beacon {
+  pub fn new(now: u32) -> Self {
+    Self {
+      count: 0,
+      last_ms: now,
+    }
+  }
+
+  /// Restart the interval, e.g. after the configuration changed.
+  pub fn restart(&mut self, now: u32) {
+    self.last_ms = now;
+  }
+
+  /// Whether the next beacon is due.
+  pub fn due(&self, config: &Config, now: u32) -> bool {
+    config.interval_s != 0
+      && now.wrapping_sub(self.last_ms) >= config.interval_s as u32 * 1_000
+  }
+
+  /// Compose the next beacon line and start the next interval.
+  pub fn next(&mut self, config: &Config, telemetry: &Telemetry, now: u32) -> String<LINE_MAX> {
+    self.count = self.count.wrapping_add(1);
+    self.last_ms = now;
+
+    let mut line = String::new();
+    let _ = write!(line, "#{}", self.count);
+    if config.fields & FIELD_ID != 0 {
+      let _ = write!(line, " id={:04X}", telemetry.node_address);
+    }
+    if config.fields & FIELD_UPTIME != 0 {
+      let _ = write!(line, " up={}", telemetry.uptime_ms / 1_000);
+    }
+    if config.fields & FIELD_VOLTAGE != 0 {
+      let mv = telemetry.vdd_mv;
+      let _ = write!(line, " vdd={}.{:03}", mv / 1_000, mv % 1_000);
+    }
+    if config.fields & FIELD_POSITION != 0 {
+      let _ = line.push_str(" pos=");
+      match telemetry.fix {
+        Some(fix) => {
+          let _ = gps::write_degrees(&mut line, fix.latitude_ude);
+          let _ = line.push(',');
+          let _ = gps::write_degrees(&mut line, fix.longitude_ude);
+          if let Some(altitude_m) = fix.altitude_m {
+            let _ = write!(line, " alt={}", altitude_m);
+          }
+        }
+        None => {
+          let _ = line.push('-');
+        }
+      }
+    }
+    if config.fields & FIELD_BATTERY != 0 {
+      match telemetry.battery_mv {
+        Some(mv) => {
+          let _ = write!(line, " bat={}.{:03}", mv / 1_000, mv % 1_000);
+        }
+        None => {
+          let _ = line.push_str(" bat=-");
+        }
+      }
+    }
+    if config.fields & FIELD_TIME != 0 {
+      let _ = line.push_str(" utc=");
+      let _ = match telemetry.network_ms {
+        Some(network_ms) => timesync::write_iso(&mut line, network_ms),
+        None => line.push('-').map_err(|_| core::fmt::Error),
+      };
+    }
+    let _ = line.push('\n');
+    line
+  }
+}
+
+/// Supply voltage in mV from a reading of the 1.20 V internal reference.
+pub fn vdd_mv(vrefint_raw: u16) -> u16 {
+  const VREFINT_MV: u32 = 1_200;
+  (VREFINT_MV * 4_095)
+    .checked_div(vrefint_raw as u32)
+    .unwrap_or(0)
+    .min(u16::MAX as u32) as u16
+}
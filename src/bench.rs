@@ -0,0 +1,205 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/bench.rs - 吞吐量测试
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Throughput benchmark.
+//!
+//! `AT+BENCH=<seconds>` makes the bridge send full-size, counted control
+//! frames back to back for the given time, followed by a summary:
+//!
+//! ```text
+//! DATA: [0x20][run u8][seq u32 LE][filler ...]
+//! END:  [0x21][run u8][sent u32 LE][duration_ms u32 LE]
+//! ```
+//!
+//! The receiving bridge counts what arrives and, on `END` (or after
+//! [`IDLE_TIMEOUT_MS`] of silence if `END` is lost), reports goodput, loss
+//! and the share of the time the channel was busy with received frames,
+//! computed with [`RadioParams::airtime_us`](crate::radio::RadioParams::airtime_us).
+
+/// Longest benchmark run.
+pub const MAX_SECONDS: u32 = 600;
+/// Silence after which the receiver reports without an `END`.
+pub const IDLE_TIMEOUT_MS: u32 = 5_000;
+/// Times `END` is sent, as it is not acknowledged.
+const END_REPEATS: u8 = 3;
+
+const TYPE_DATA: u8 = 0x20;
+const TYPE_END: u8 = 0x21;
+/// Bytes of the `DATA` header.
+const DATA_HEADER: usize = 6;
+/// Bytes of an `END` frame.
+pub const END_LEN: usize = 10;
+
+/// Sending side of a run.
+pub struct Sender {
+  run: u8,
+  seq: u32,
+  started: u32,
+  duration_ms: u32,
+  ends_sent: u8,
+}
+
+impl Sender {
+  pub fn new(run: u8, seconds: u32, now: u32) -> Self {
+    Self {
+      run,
+      seq: 0,
+      started: now,
+      duration_ms: seconds * 1000,
+      ends_sent: 0,
+    }
+  }
+
+  /// Fill `out` with the next frame and return its length, or `None` when
+  /// the run is over.
+  pub fn next(&mut self, now: u32, out: &mut [u8]) -> Option<usize> {
+    let elapsed = now.wrapping_sub(self.started);
+    if elapsed < self.duration_ms && out.len() >= DATA_HEADER {
+      out[0] = TYPE_DATA;
+      out[1] = self.run;
+      out[2..6].copy_from_slice(&self.seq.to_le_bytes());
+      for (i, byte) in out[DATA_HEADER..].iter_mut().enumerate() {
+        *byte = i as u8;
+      }
+      self.seq += 1;
+      return Some(out.len());
+    }
+    if self.ends_sent >= END_REPEATS {
+      return None;
+    }
+    self.ends_sent += 1;
+    out[0] = TYPE_END;
+    out[1] = self.run;
+    out[2..6].copy_from_slice(&self.seq.to_le_bytes());
+    out[6..10].copy_from_slice(&elapsed.min(self.duration_ms).to_le_bytes());
+    Some(END_LEN)
+  }
+
+  /// Frames sent so far.
+  pub fn sent(&self) -> u32 {
+    self.seq
+  }
+}
+
+/// Result of a run as seen by the receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Report {
+  pub run: u8,
+  pub received: u32,
+  pub sent: u32,
+  pub payload_bytes: u32,
+  pub duration_ms: u32,
+  pub airtime_us: u64,
+}
+
+impl Report {
+  pub fn goodput_bps(&self) -> u32 {
+    (self.payload_bytes as u64 * 8000)
+      .checked_div(self.duration_ms as u64)
+      .unwrap_or(0) as u32
+  }
+
+  pub fn loss_percent(&self) -> u32 {
+    let lost = self.sent.saturating_sub(self.received);
+    (lost * 100).checked_div(self.sent).unwrap_or(0)
+  }
+
+  /// Share of the run the channel carried received frames, in percent.
+  pub fn airtime_percent(&self) -> u32 {
+    (self.airtime_us / 10)
+      .checked_div(self.duration_ms as u64)
+      .unwrap_or(0) as u32
+  }
+}
+
+/// Receiving side; follows whichever run the peer starts.
+#[derive(Default)]
+pub struct Receiver {
+  run: Option<u8>,
+  /// Last run reported, so repeated `END`s are ignored.
+  finished: Option<u8>,
+  received: u32,
+  highest_seq: u32,
+  payload_bytes: u32,
+  airtime_us: u64,
+  first_ms: u32,
+  last_ms: u32,
+}
+
+impl Receiver {
+  /// Whether `data` is a benchmark frame.
+  pub fn is_bench_frame(data: &[u8]) -> bool {
+    matches!(data.first(), Some(&TYPE_DATA | &TYPE_END))
+  }
+
+  /// Account for a benchmark frame that took `airtime_us` on air.
+  pub fn on_frame(&mut self, data: &[u8], airtime_us: u32, now: u32) -> Option<Report> {
+    let [kind, run, rest @ ..] = data else {
+      return None;
+    };
+    if self.finished == Some(*run) {
+      return None;
+    }
+    if self.run != Some(*run) {
+      self.start(*run, now);
+    }
+    let seq = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?);
+    match *kind {
+      TYPE_DATA => {
+        self.received += 1;
+        self.highest_seq = self.highest_seq.max(seq + 1);
+        self.payload_bytes += data.len() as u32;
+        self.airtime_us += airtime_us as u64;
+        self.last_ms = now;
+        None
+      }
+      TYPE_END => {
+        let duration_ms = u32::from_le_bytes(rest.get(4..8)?.try_into().ok()?);
+        Some(self.finish(seq, duration_ms))
+      }
+      _ => None,
+    }
+  }
+
+  /// Report a run whose `END` never arrived.
+  pub fn poll(&mut self, now: u32) -> Option<Report> {
+    if self.run.is_none() || now.wrapping_sub(self.last_ms) < IDLE_TIMEOUT_MS {
+      return None;
+    }
+    let duration_ms = self.last_ms.wrapping_sub(self.first_ms);
+    Some(self.finish(self.highest_seq, duration_ms))
+  }
+
+  fn start(&mut self, run: u8, now: u32) {
+    *self = Self {
+      run: Some(run),
+      finished: self.finished,
+      first_ms: now,
+      last_ms: now,
+      ..Self::default()
+    };
+  }
+
+  fn finish(&mut self, sent: u32, duration_ms: u32) -> Report {
+    let run = self.run.take().unwrap_or(0);
+    self.finished = Some(run);
+    Report {
+      run,
+      received: self.received,
+      sent: sent.max(self.received),
+      payload_bytes: self.payload_bytes,
+      duration_ms,
+      airtime_us: self.airtime_us,
+    }
+  }
+}
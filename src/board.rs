@@ -0,0 +1,104 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/board.rs - 板级引脚定义
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Board pin maps.
+//!
+//! Which pin does what is chosen at build time:
+//!
+//! | feature             | board                   | pin map                    |
+//! |---------------------|-------------------------|----------------------------|
+//! | `board-bluehigh-v1` | Blue-High v1 (default)  | `src/board/bluehigh_v1.rs` |
+//! | `board-custom`      | your own wiring         | `src/board/custom.rs`      |
+//!
+//! A board module names the type of every pin the firmware uses and
+//! configures them in [`Pins::new`]; peripheral setup in
+//! [`crate::app::board_init`] only consumes [`Pins`].  The SPI1
+//! (PA5/PA6/PA7), I2C2 (PB10/PB11), USART1 (PA9/PA10) and USB (PA11/PA12)
+//! pins belong to their peripherals and are the same on every board; the
+//! E22 control lines, the button, the UART strap and the LEDs may go
+//! anywhere.
+
+use crate::hal::gpio::{gpioa, gpiob, gpioc};
+
+#[cfg(all(feature = "board-bluehigh-v1", feature = "board-custom"))]
+compile_error!("features `board-bluehigh-v1` and `board-custom` select different boards");
+#[cfg(not(any(feature = "board-bluehigh-v1", feature = "board-custom")))]
+compile_error!("select a board with `board-bluehigh-v1` or `board-custom`");
+
+#[cfg(feature = "board-bluehigh-v1")]
+pub mod bluehigh_v1;
+#[cfg(feature = "board-bluehigh-v1")]
+pub use bluehigh_v1::*;
+
+#[cfg(feature = "board-custom")]
+pub mod custom;
+#[cfg(feature = "board-custom")]
+pub use custom::*;
+
+/// Every pin the firmware uses, configured for its role.
+pub struct Pins {
+  pub sck: Sck,
+  pub miso: Miso,
+  pub mosi: Mosi,
+  /// E22 chip select.
+  pub nss: Nss,
+  pub busy: Busy,
+  pub nrst: Nrst,
+  /// RxDone / TxDone / timeout IRQ, active high.
+  pub dio1: Dio1,
+  /// RF switch enables.
+  pub txen: TxEn,
+  pub rxen: RxEn,
+  #[cfg(not(feature = "no-display"))]
+  pub scl: Scl,
+  #[cfg(not(feature = "no-display"))]
+  pub sda: Sda,
+  pub usb_dm: UsbDm,
+  pub usb_dp: UsbDp,
+  /// USART1 data port.
+  pub uart_tx: UartTx,
+  pub uart_rx: UartRx,
+  /// Tied to ground, bridges over USART1 instead of USB; pulled up.
+  pub uart_strap: UartStrap,
+  /// USART2 to the GPS module.
+  #[cfg(feature = "gps")]
+  pub gps_tx: GpsTx,
+  #[cfg(feature = "gps")]
+  pub gps_rx: GpsRx,
+  /// SPI2 to the external flash chip or the SD card.
+  #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+  pub spi2_sck: Spi2Sck,
+  #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+  pub spi2_miso: Spi2Miso,
+  #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+  pub spi2_mosi: Spi2Mosi,
+  #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+  pub spi2_cs: Spi2Cs,
+  /// Battery voltage through a divider.
+  #[cfg(feature = "vbat")]
+  pub vbat: Vbat,
+  /// Page / pairing button to ground, active low.
+  pub button: Button,
+  pub status_led: StatusLed,
+  #[cfg(feature = "activity-leds")]
+  pub tx_led: TxLed,
+  #[cfg(feature = "activity-leds")]
+  pub rx_led: RxLed,
+}
+
+/// The GPIO ports the pins are taken from.
+pub struct Ports {
+  pub gpioa: gpioa::Parts,
+  pub gpiob: gpiob::Parts,
+  pub gpioc: gpioc::Parts,
+}
@@ -0,0 +1,124 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/board/bluehigh_v1.rs - Blue-High v1 引脚定义
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Blue-High v1: Blue Pill with an E22-400M30S.
+//!
+//! | signal    | pin  |
+//! |-----------|------|
+//! | NSS       | PA4  |
+//! | BUSY      | PB1  |
+//! | NRST      | PB0  |
+//! | DIO1      | PA3  |
+//! | TXEN      | PB12 |
+//! | RXEN      | PB13 |
+//! | button    | PA0  |
+//! | strap     | PB5  |
+//! | VBAT      | PA1 (`vbat`) |
+//! | LED       | PC13 |
+//! | TX/RX LED | PB8 / PB9 (`activity-leds`) |
+
+#[cfg(not(feature = "no-display"))]
+use crate::hal::gpio::{OpenDrain, PB10, PB11};
+#[cfg(feature = "activity-leds")]
+use crate::hal::gpio::{PB8, PB9};
+#[cfg(feature = "vbat")]
+use crate::hal::gpio::{Analog, PA1};
+use crate::hal::gpio::{
+  Alternate, Floating, Input, Output, PA0, PA3, PA4, PA5, PA6, PA7, PA9, PA10, PA11, PA12, PB0,
+  PB1, PB5, PB12, PB13, PC13, PullUp, PushPull,
+};
+#[cfg(feature = "driver-sx1268-rs")]
+use crate::lora::LoraControl;
+#[cfg(feature = "driver-sx1268-rs")]
+use crate::spi_bus::{Bus1, SharedDevice};
+
+use super::{Pins, Ports};
+
+#[cfg(feature = "gps")]
+compile_error!("`gps` needs USART2 RX on PA3, which is DIO1 on Blue-High v1; use `board-custom`");
+#[cfg(feature = "spi-flash")]
+compile_error!("`spi-flash` needs SPI2 on PB12..PB15, partly TXEN/RXEN here; use `board-custom`");
+#[cfg(feature = "sd-card")]
+compile_error!("`sd-card` needs SPI2 on PB12..PB15, partly TXEN/RXEN here; use `board-custom`");
+
+pub const NAME: &str = "Blue-High v1";
+
+pub type Sck = PA5<Alternate<PushPull>>;
+pub type Miso = PA6;
+pub type Mosi = PA7<Alternate<PushPull>>;
+pub type Nss = PA4<Output<PushPull>>;
+pub type Busy = PB1<Input<Floating>>;
+pub type Nrst = PB0<Output<PushPull>>;
+pub type Dio1 = PA3<Input<PullUp>>;
+pub type TxEn = PB12<Output<PushPull>>;
+pub type RxEn = PB13<Output<PushPull>>;
+#[cfg(not(feature = "no-display"))]
+pub type Scl = PB10<Alternate<OpenDrain>>;
+#[cfg(not(feature = "no-display"))]
+pub type Sda = PB11<Alternate<OpenDrain>>;
+pub type UsbDm = PA11<Input<Floating>>;
+pub type UsbDp = PA12<Input<Floating>>;
+pub type UartTx = PA9<Alternate<PushPull>>;
+pub type UartRx = PA10<Input<PullUp>>;
+pub type UartStrap = PB5<Input<PullUp>>;
+#[cfg(feature = "vbat")]
+pub type Vbat = PA1<Analog>;
+pub type Button = PA0<Input<PullUp>>;
+pub type StatusLed = PC13<Output<PushPull>>;
+#[cfg(feature = "activity-leds")]
+pub type TxLed = PB8<Output<PushPull>>;
+#[cfg(feature = "activity-leds")]
+pub type RxLed = PB9<Output<PushPull>>;
+
+/// SX126x control with this board's NRST, NSS, BUSY, TXEN and RXEN.
+#[cfg(feature = "driver-sx1268-rs")]
+pub type Control = LoraControl<SharedDevice<Bus1, Nss>, Nrst, Busy, TxEn, RxEn>;
+
+impl Pins {
+  pub fn new(ports: Ports) -> Self {
+    let Ports {
+      mut gpioa,
+      mut gpiob,
+      mut gpioc,
+    } = ports;
+    Self {
+      sck: gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl),
+      miso: gpioa.pa6,
+      mosi: gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl),
+      nss: gpioa.pa4.into_push_pull_output(&mut gpioa.crl),
+      busy: gpiob.pb1.into_floating_input(&mut gpiob.crl),
+      nrst: gpiob.pb0.into_push_pull_output(&mut gpiob.crl),
+      dio1: gpioa.pa3.into_pull_up_input(&mut gpioa.crl),
+      // The E22 may switch its RF path internally; then these are unused.
+      txen: gpiob.pb12.into_push_pull_output(&mut gpiob.crh),
+      rxen: gpiob.pb13.into_push_pull_output(&mut gpiob.crh),
+      #[cfg(not(feature = "no-display"))]
+      scl: gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh),
+      #[cfg(not(feature = "no-display"))]
+      sda: gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh),
+      usb_dm: gpioa.pa11.into_floating_input(&mut gpioa.crh),
+      usb_dp: gpioa.pa12.into_floating_input(&mut gpioa.crh),
+      uart_tx: gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh),
+      uart_rx: gpioa.pa10.into_pull_up_input(&mut gpioa.crh),
+      uart_strap: gpiob.pb5.into_pull_up_input(&mut gpiob.crl),
+      #[cfg(feature = "vbat")]
+      vbat: gpioa.pa1.into_analog(&mut gpioa.crl),
+      button: gpioa.pa0.into_pull_up_input(&mut gpioa.crl),
+      status_led: gpioc.pc13.into_push_pull_output(&mut gpioc.crh),
+      #[cfg(feature = "activity-leds")]
+      tx_led: gpiob.pb8.into_push_pull_output(&mut gpiob.crh),
+      #[cfg(feature = "activity-leds")]
+      rx_led: gpiob.pb9.into_push_pull_output(&mut gpiob.crh),
+    }
+  }
+}
@@ -0,0 +1,171 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/board/custom.rs - 自定义引脚定义
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Pin map for your own wiring, built with `board-custom`.
+//!
+//! It starts out as a copy of `src/board/bluehigh_v1.rs`.  To move a signal,
+//! change its type alias and the pin taken in [`Pins::new`]; [`Control`]
+//! follows the aliases, and the compiler points out any place that
+//! disagrees.  Keep SPI1, I2C2 and USB on their fixed pins.
+//!
+//! With `gps` the GPS module takes USART2 (PA2/PA3) and DIO1 moves from
+//! PA3 to PA1.  With `vbat` the battery divider goes to PA1, so `gps` and
+//! `vbat` together need another ADC pin (PA0..PA7, PB0, PB1) here.
+//!
+//! With `spi-flash` the flash chip, or with `sd-card` the SD card, takes
+//! SPI2 (PB12..PB15, chip select on PB12) and TXEN/RXEN move from
+//! PB12/PB13 to PB6/PB7.  SPI2 is shared (see [`crate::spi_bus`]), but
+//! both together need a second chip select, which is not wired up.
+
+#[cfg(not(feature = "no-display"))]
+use crate::hal::gpio::{OpenDrain, PB10, PB11};
+#[cfg(feature = "activity-leds")]
+use crate::hal::gpio::{PB8, PB9};
+use crate::hal::gpio::{
+  Alternate, Floating, Input, Output, PA0, PA3, PA4, PA5, PA6, PA7, PA9, PA10, PA11, PA12, PB0,
+  PB1, PB5, PB12, PB13, PC13, PullUp, PushPull,
+};
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+use crate::hal::gpio::{PB6, PB7, PB14, PB15};
+#[cfg(feature = "driver-sx1268-rs")]
+use crate::lora::LoraControl;
+#[cfg(feature = "driver-sx1268-rs")]
+use crate::spi_bus::{Bus1, SharedDevice};
+
+#[cfg(feature = "gps")]
+use crate::hal::gpio::PA2;
+#[cfg(any(feature = "gps", feature = "vbat"))]
+use crate::hal::gpio::PA1;
+#[cfg(feature = "vbat")]
+use crate::hal::gpio::Analog;
+
+#[cfg(all(feature = "gps", feature = "vbat"))]
+compile_error!("`gps` moves DIO1 to PA1, the VBAT pin; wire VBAT to another ADC pin here");
+#[cfg(all(feature = "spi-flash", feature = "sd-card"))]
+compile_error!("`spi-flash` and `sd-card` both take SPI2 with the chip select on PB12");
+
+use super::{Pins, Ports};
+
+pub const NAME: &str = "custom";
+
+pub type Sck = PA5<Alternate<PushPull>>;
+pub type Miso = PA6;
+pub type Mosi = PA7<Alternate<PushPull>>;
+pub type Nss = PA4<Output<PushPull>>;
+pub type Busy = PB1<Input<Floating>>;
+pub type Nrst = PB0<Output<PushPull>>;
+#[cfg(not(feature = "gps"))]
+pub type Dio1 = PA3<Input<PullUp>>;
+#[cfg(feature = "gps")]
+pub type Dio1 = PA1<Input<PullUp>>;
+#[cfg(not(any(feature = "spi-flash", feature = "sd-card")))]
+pub type TxEn = PB12<Output<PushPull>>;
+#[cfg(not(any(feature = "spi-flash", feature = "sd-card")))]
+pub type RxEn = PB13<Output<PushPull>>;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+pub type TxEn = PB6<Output<PushPull>>;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+pub type RxEn = PB7<Output<PushPull>>;
+#[cfg(not(feature = "no-display"))]
+pub type Scl = PB10<Alternate<OpenDrain>>;
+#[cfg(not(feature = "no-display"))]
+pub type Sda = PB11<Alternate<OpenDrain>>;
+pub type UsbDm = PA11<Input<Floating>>;
+pub type UsbDp = PA12<Input<Floating>>;
+pub type UartTx = PA9<Alternate<PushPull>>;
+pub type UartRx = PA10<Input<PullUp>>;
+pub type UartStrap = PB5<Input<PullUp>>;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+pub type Spi2Sck = PB13<Alternate<PushPull>>;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+pub type Spi2Miso = PB14<Input<Floating>>;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+pub type Spi2Mosi = PB15<Alternate<PushPull>>;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+pub type Spi2Cs = PB12<Output<PushPull>>;
+#[cfg(feature = "gps")]
+pub type GpsTx = PA2<Alternate<PushPull>>;
+#[cfg(feature = "gps")]
+pub type GpsRx = PA3<Input<Floating>>;
+#[cfg(feature = "vbat")]
+pub type Vbat = PA1<Analog>;
+pub type Button = PA0<Input<PullUp>>;
+pub type StatusLed = PC13<Output<PushPull>>;
+#[cfg(feature = "activity-leds")]
+pub type TxLed = PB8<Output<PushPull>>;
+#[cfg(feature = "activity-leds")]
+pub type RxLed = PB9<Output<PushPull>>;
+
+/// SX126x control with this board's NRST, NSS, BUSY, TXEN and RXEN.
+#[cfg(feature = "driver-sx1268-rs")]
+pub type Control = LoraControl<SharedDevice<Bus1, Nss>, Nrst, Busy, TxEn, RxEn>;
+
+impl Pins {
+  pub fn new(ports: Ports) -> Self {
+    let Ports {
+      mut gpioa,
+      mut gpiob,
+      mut gpioc,
+    } = ports;
+    Self {
+      sck: gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl),
+      miso: gpioa.pa6,
+      mosi: gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl),
+      nss: gpioa.pa4.into_push_pull_output(&mut gpioa.crl),
+      busy: gpiob.pb1.into_floating_input(&mut gpiob.crl),
+      nrst: gpiob.pb0.into_push_pull_output(&mut gpiob.crl),
+      #[cfg(not(feature = "gps"))]
+      dio1: gpioa.pa3.into_pull_up_input(&mut gpioa.crl),
+      #[cfg(feature = "gps")]
+      dio1: gpioa.pa1.into_pull_up_input(&mut gpioa.crl),
+      // The E22 may switch its RF path internally; then these are unused.
+      #[cfg(not(any(feature = "spi-flash", feature = "sd-card")))]
+      txen: gpiob.pb12.into_push_pull_output(&mut gpiob.crh),
+      #[cfg(not(any(feature = "spi-flash", feature = "sd-card")))]
+      rxen: gpiob.pb13.into_push_pull_output(&mut gpiob.crh),
+      #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+      txen: gpiob.pb6.into_push_pull_output(&mut gpiob.crl),
+      #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+      rxen: gpiob.pb7.into_push_pull_output(&mut gpiob.crl),
+      #[cfg(not(feature = "no-display"))]
+      scl: gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh),
+      #[cfg(not(feature = "no-display"))]
+      sda: gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh),
+      usb_dm: gpioa.pa11.into_floating_input(&mut gpioa.crh),
+      usb_dp: gpioa.pa12.into_floating_input(&mut gpioa.crh),
+      uart_tx: gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh),
+      uart_rx: gpioa.pa10.into_pull_up_input(&mut gpioa.crh),
+      uart_strap: gpiob.pb5.into_pull_up_input(&mut gpiob.crl),
+      #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+      spi2_sck: gpiob.pb13.into_alternate_push_pull(&mut gpiob.crh),
+      #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+      spi2_miso: gpiob.pb14.into_floating_input(&mut gpiob.crh),
+      #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+      spi2_mosi: gpiob.pb15.into_alternate_push_pull(&mut gpiob.crh),
+      #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+      spi2_cs: gpiob.pb12.into_push_pull_output(&mut gpiob.crh),
+      #[cfg(feature = "gps")]
+      gps_tx: gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl),
+      #[cfg(feature = "gps")]
+      gps_rx: gpioa.pa3.into_floating_input(&mut gpioa.crl),
+      #[cfg(feature = "vbat")]
+      vbat: gpioa.pa1.into_analog(&mut gpioa.crl),
+      button: gpioa.pa0.into_pull_up_input(&mut gpioa.crl),
+      status_led: gpioc.pc13.into_push_pull_output(&mut gpioc.crh),
+      #[cfg(feature = "activity-leds")]
+      tx_led: gpiob.pb8.into_push_pull_output(&mut gpiob.crh),
+      #[cfg(feature = "activity-leds")]
+      rx_led: gpiob.pb9.into_push_pull_output(&mut gpiob.crh),
+    }
+  }
+}
@@ -0,0 +1,394 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/boot_select.rs - 启动选择器
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Boot selector: the first flash page, which an update never rewrites.
+//!
+//! The core resets into the selector rather than the application:
+//!
+//! ```text
+//! 0x0800_0000  boot selector, 1 KiB
+//! 0x0800_0400  application, 62 KiB
+//! 0x0800_FC00  settings page (see crate::settings)
+//! ```
+//!
+//! The selector looks for an image staged by [`crate::ota`], in the
+//! second half of a 128 KiB flash or in the external SPI flash.  When the
+//! staging record names one whose CRC32 still matches, it copies the image
+//! over the application, forgets the record and resets; otherwise it points
+//! VTOR at the application's vector table and jumps to it.  The record is
+//! forgotten only once the copy is complete and the selector itself is
+//! never erased, so a reset or power loss during the copy just starts it
+//! again at the next boot.
+//!
+//! An update carries the whole `.bin`, selector page included, but only
+//! the application is installed: a bridge keeps the selector it was
+//! flashed with, and a new one needs a probe or the ROM bootloader.
+//!
+//! The selector runs before anything is set up: on the 8 MHz HSI, without
+//! `.data` or `.bss`, and while it copies it may reach nothing outside its
+//! page, since the rest of the flash is being replaced.  Its code stays
+//! plain for that: register accesses, loops and no slices, tables or
+//! library calls.
+
+use core::arch::asm;
+
+use crate::hal::FLASH_SIZE_REG;
+use crate::ota::{self, Slot};
+use crate::spi_flash;
+
+/// Flash the selector occupies: the first page.
+pub const BOOT_LEN: usize = 1024;
+/// Start of the flash, where the core finds the selector's vector table.
+const FLASH_START: usize = 0x0800_0000;
+/// Start of the application and its vector table.
+pub const APP_START: usize = FLASH_START + BOOT_LEN;
+/// RAM the application's stack may start at, as `memory.x` lays it out.
+const RAM_START: u32 = 0x2000_0000;
+const RAM_END: u32 = RAM_START + 20 * 1024;
+
+/// Flash controller registers (RM0008 §3.3.3).
+const FLASH_KEYR: usize = 0x4002_2004;
+const FLASH_SR: usize = 0x4002_200C;
+const FLASH_CR: usize = 0x4002_2010;
+const FLASH_AR: usize = 0x4002_2014;
+const FLASH_KEY1: u32 = 0x4567_0123;
+const FLASH_KEY2: u32 = 0xCDEF_89AB;
+const SR_BSY: u32 = 1 << 0;
+const CR_PG: u32 = 1 << 0;
+const CR_PER: u32 = 1 << 1;
+const CR_STRT: u32 = 1 << 6;
+const CR_LOCK: u32 = 1 << 7;
+/// Clocks and resets of GPIOB and SPI2 (RM0008 §7.3).
+const RCC_APB2RSTR: usize = 0x4002_100C;
+const RCC_APB1RSTR: usize = 0x4002_1010;
+const RCC_APB2ENR: usize = 0x4002_1018;
+const RCC_APB1ENR: usize = 0x4002_101C;
+const APB2_IOPB: u32 = 1 << 3;
+const APB1_SPI2: u32 = 1 << 14;
+/// PB12 push-pull output, PB13 and PB15 alternate push-pull, PB14 floating
+/// input, PB8-PB11 as at reset (RM0008 §9.2.2).
+const GPIOB_CRH: usize = 0x4001_0C04;
+const GPIOB_CRH_SPI2: u32 = 0xB4B3_4444;
+const GPIOB_BSRR: usize = 0x4001_0C10;
+const FLASH_CS_HIGH: u32 = 1 << 12;
+const FLASH_CS_LOW: u32 = 1 << (12 + 16);
+/// SPI2 master, mode 0, PCLK1 / 2 = 4 MHz, software chip select
+/// (RM0008 §25.5).
+const SPI2_CR1: usize = 0x4000_3800;
+const SPI2_CR1_MASTER: u32 = (1 << 9) | (1 << 8) | (1 << 6) | (1 << 2);
+const SPI2_SR: usize = 0x4000_3808;
+const SPI2_DR: usize = 0x4000_380C;
+const SPI_SR_RXNE: u32 = 1 << 0;
+const SPI_SR_TXE: u32 = 1 << 1;
+/// External flash commands, see [`crate::spi_flash`].
+const SPI_FLASH_WRITE_ENABLE: u8 = 0x06;
+const SPI_FLASH_READ_STATUS: u8 = 0x05;
+const SPI_FLASH_READ: u8 = 0x03;
+const SPI_FLASH_PAGE_PROGRAM: u8 = 0x02;
+const SPI_FLASH_RELEASE_POWER_DOWN: u8 = 0xAB;
+const SPI_FLASH_BUSY: u8 = 1 << 0;
+/// Vector table offset register, and the reset request of the application
+/// interrupt and reset control register.
+const SCB_VTOR: usize = 0xE000_ED08;
+const SCB_AIRCR: usize = 0xE000_ED0C;
+const AIRCR_SYSRESETREQ: u32 = 0x05FA_0004;
+
+/// The selector's vector table: its stack and entry, and a halt for the
+/// faults that cannot be masked.
+#[repr(C)]
+struct Vectors {
+  stack: u32,
+  reset: unsafe extern "C" fn() -> !,
+  nmi: unsafe extern "C" fn() -> !,
+  hard_fault: unsafe extern "C" fn() -> !,
+}
+
+/// What the core resets into; `memory.x` puts it at the start of flash.
+#[unsafe(link_section = ".boot.vectors")]
+#[unsafe(no_mangle)]
+#[used]
+static BOOT_VECTORS: Vectors = Vectors {
+  stack: RAM_END,
+  reset: select,
+  nmi: halt,
+  hard_fault: halt,
+};
+
+/// Whether `stack`, the first word of an image after the selector page,
+/// is the initial stack pointer of an application built for the selector.
+/// An image from before the selector has code there instead.
+pub fn is_application(stack: u32) -> bool {
+  (RAM_START + 4..=RAM_END).contains(&stack)
+}
+
+/// Install a staged image if there is one, then start the application.
+#[unsafe(link_section = ".boot.text")]
+unsafe extern "C" fn select() -> ! {
+  unsafe {
+    if let Some(size) = internal_image() {
+      install(size, Slot::Internal);
+    }
+    if cfg!(feature = "spi-flash")
+      && let Some(size) = external_image()
+    {
+      install(size, Slot::External);
+    }
+    (SCB_VTOR as *mut u32).write_volatile(APP_START as u32);
+    let vectors = APP_START as *const u32;
+    asm!(
+      "msr msp, {stack}",
+      "bx {reset}",
+      stack = in(reg) vectors.read_volatile(),
+      reset = in(reg) vectors.add(1).read_volatile(),
+      options(noreturn),
+    );
+  }
+}
+
+#[unsafe(link_section = ".boot.text")]
+unsafe extern "C" fn halt() -> ! {
+  #[allow(clippy::empty_loop)]
+  loop {}
+}
+
+/// Whether a record's `size` is an image [`crate::ota`] would stage.
+#[unsafe(link_section = ".boot.text")]
+fn fits(size: u32) -> bool {
+  size > BOOT_LEN as u32 && size <= ota::SLOT_LEN as u32 && size.is_multiple_of(4)
+}
+
+/// CRC-32 as [`crate::crc::crc32`] without its table, which lives outside
+/// the selector: `crc` starts at `!0` and is inverted at the end.
+#[unsafe(link_section = ".boot.text")]
+fn crc32_byte(crc: u32, byte: u8) -> u32 {
+  let mut crc = crc ^ byte as u32;
+  let mut bit = 0;
+  while bit < 8 {
+    crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+    bit += 1;
+  }
+  crc
+}
+
+/// Size of the image in the internal slot, if its record is there and the
+/// image still matches it.
+#[unsafe(link_section = ".boot.text")]
+unsafe fn internal_image() -> Option<usize> {
+  unsafe {
+    if (FLASH_SIZE_REG as *const u16).read_volatile() < 128 {
+      return None;
+    }
+    let record = ota::RECORD_ADDRESS as *const u32;
+    let size = record.add(1).read_volatile();
+    if record.read_volatile() != ota::RECORD_MAGIC || !fits(size) {
+      return None;
+    }
+    let mut crc = !0;
+    let mut offset = 0;
+    while offset < size as usize {
+      crc = crc32_byte(crc, ((ota::SLOT_B + offset) as *const u8).read_volatile());
+      offset += 1;
+    }
+    if !crc != record.add(2).read_volatile() {
+      return None;
+    }
+    Some(size as usize)
+  }
+}
+
+/// Size of the image in the external flash, if its record is there and
+/// the image still matches it.  Leaves SPI2 set up for [`install`] when it
+/// is, and as at reset otherwise.
+#[unsafe(link_section = ".boot.text")]
+unsafe fn external_image() -> Option<usize> {
+  unsafe {
+    let apb2 = RCC_APB2ENR as *mut u32;
+    let apb1 = RCC_APB1ENR as *mut u32;
+    apb2.write_volatile(apb2.read_volatile() | APB2_IOPB);
+    apb1.write_volatile(apb1.read_volatile() | APB1_SPI2);
+    (GPIOB_BSRR as *mut u32).write_volatile(FLASH_CS_HIGH);
+    (GPIOB_CRH as *mut u32).write_volatile(GPIOB_CRH_SPI2);
+    (SPI2_CR1 as *mut u32).write_volatile(SPI2_CR1_MASTER);
+    // The application may have left the chip powered down; it wakes
+    // within 3 µs.
+    spi_command(SPI_FLASH_RELEASE_POWER_DOWN);
+    let mut wait = 0;
+    while wait < 32 {
+      asm!("nop");
+      wait += 1;
+    }
+
+    read_from(spi_flash::OTA.start + ota::EXTERNAL_RECORD);
+    let magic = spi_word();
+    let size = spi_word();
+    let crc = spi_word();
+    deselect();
+    if magic == ota::RECORD_MAGIC && fits(size) {
+      read_from(spi_flash::OTA.start);
+      let mut sum = !0;
+      let mut offset = 0;
+      while offset < size {
+        sum = crc32_byte(sum, spi_transfer(0));
+        offset += 1;
+      }
+      deselect();
+      if !sum == crc {
+        return Some(size as usize);
+      }
+    }
+
+    let apb2_reset = RCC_APB2RSTR as *mut u32;
+    let apb1_reset = RCC_APB1RSTR as *mut u32;
+    apb2_reset.write_volatile(APB2_IOPB);
+    apb1_reset.write_volatile(APB1_SPI2);
+    apb2_reset.write_volatile(0);
+    apb1_reset.write_volatile(0);
+    apb2.write_volatile(apb2.read_volatile() & !APB2_IOPB);
+    apb1.write_volatile(apb1.read_volatile() & !APB1_SPI2);
+    None
+  }
+}
+
+/// Copy the staged image over the application, forget it and reset.
+#[unsafe(link_section = ".boot.text")]
+unsafe fn install(size: usize, slot: Slot) -> ! {
+  let keyr = FLASH_KEYR as *mut u32;
+  let sr = FLASH_SR as *const u32;
+  let cr = FLASH_CR as *mut u32;
+  let ar = FLASH_AR as *mut u32;
+  unsafe {
+    keyr.write_volatile(FLASH_KEY1);
+    keyr.write_volatile(FLASH_KEY2);
+    let mut page = BOOT_LEN;
+    while page < size {
+      cr.write_volatile(CR_PER);
+      ar.write_volatile((FLASH_START + page) as u32);
+      cr.write_volatile(CR_PER | CR_STRT);
+      while sr.read_volatile() & SR_BSY != 0 {}
+      page += ota::PAGE_LEN;
+    }
+    if matches!(slot, Slot::External) {
+      // One read command streams the whole application.
+      read_from(spi_flash::OTA.start + BOOT_LEN as u32);
+    }
+    cr.write_volatile(CR_PG);
+    let mut offset = BOOT_LEN;
+    while offset < size {
+      let half = match slot {
+        Slot::Internal => ((ota::SLOT_B + offset) as *const u16).read_volatile(),
+        Slot::External => spi_transfer(0) as u16 | (spi_transfer(0) as u16) << 8,
+      };
+      ((FLASH_START + offset) as *mut u16).write_volatile(half);
+      while sr.read_volatile() & SR_BSY != 0 {}
+      offset += 2;
+    }
+    // Installed: a reset before this only installs it again.
+    match slot {
+      Slot::Internal => {
+        cr.write_volatile(CR_PER);
+        ar.write_volatile(ota::RECORD_ADDRESS as u32);
+        cr.write_volatile(CR_PER | CR_STRT);
+        while sr.read_volatile() & SR_BSY != 0 {}
+      }
+      Slot::External => {
+        deselect();
+        // Clearing the magic needs no erase.
+        spi_command(SPI_FLASH_WRITE_ENABLE);
+        let address = spi_flash::OTA.start + ota::EXTERNAL_RECORD;
+        (GPIOB_BSRR as *mut u32).write_volatile(FLASH_CS_LOW);
+        spi_transfer(SPI_FLASH_PAGE_PROGRAM);
+        spi_address(address);
+        spi_word_out(0);
+        deselect();
+        (GPIOB_BSRR as *mut u32).write_volatile(FLASH_CS_LOW);
+        spi_transfer(SPI_FLASH_READ_STATUS);
+        while spi_transfer(0) & SPI_FLASH_BUSY != 0 {}
+        deselect();
+      }
+    }
+    cr.write_volatile(CR_LOCK);
+    (SCB_AIRCR as *mut u32).write_volatile(AIRCR_SYSRESETREQ);
+  }
+  // Nothing to do while the reset takes effect.
+  #[allow(clippy::empty_loop)]
+  loop {}
+}
+
+/// Send `command` alone.
+#[unsafe(link_section = ".boot.text")]
+unsafe fn spi_command(command: u8) {
+  unsafe {
+    (GPIOB_BSRR as *mut u32).write_volatile(FLASH_CS_LOW);
+    spi_transfer(command);
+    deselect();
+  }
+}
+
+/// Start reading the external flash at `address`.
+#[unsafe(link_section = ".boot.text")]
+unsafe fn read_from(address: u32) {
+  unsafe {
+    (GPIOB_BSRR as *mut u32).write_volatile(FLASH_CS_LOW);
+    spi_transfer(SPI_FLASH_READ);
+    spi_address(address);
+  }
+}
+
+#[unsafe(link_section = ".boot.text")]
+unsafe fn spi_address(address: u32) {
+  unsafe {
+    spi_transfer((address >> 16) as u8);
+    spi_transfer((address >> 8) as u8);
+    spi_transfer(address as u8);
+  }
+}
+
+/// The next four bytes read, little-endian.
+#[unsafe(link_section = ".boot.text")]
+unsafe fn spi_word() -> u32 {
+  let mut word = 0;
+  let mut shift = 0;
+  while shift < 32 {
+    word |= (unsafe { spi_transfer(0) } as u32) << shift;
+    shift += 8;
+  }
+  word
+}
+
+/// Write `word` little-endian.
+#[unsafe(link_section = ".boot.text")]
+unsafe fn spi_word_out(word: u32) {
+  let mut shift = 0;
+  while shift < 32 {
+    unsafe { spi_transfer((word >> shift) as u8) };
+    shift += 8;
+  }
+}
+
+#[unsafe(link_section = ".boot.text")]
+unsafe fn deselect() {
+  unsafe { (GPIOB_BSRR as *mut u32).write_volatile(FLASH_CS_HIGH) };
+}
+
+/// Clock one byte through SPI2.
+#[unsafe(link_section = ".boot.text")]
+unsafe fn spi_transfer(byte: u8) -> u8 {
+  let sr = SPI2_SR as *const u32;
+  let dr = SPI2_DR as *mut u32;
+  unsafe {
+    while sr.read_volatile() & SPI_SR_TXE == 0 {}
+    dr.write_volatile(byte as u32);
+    while sr.read_volatile() & SPI_SR_RXNE == 0 {}
+    dr.read_volatile() as u8
+  }
+}
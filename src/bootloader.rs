@@ -0,0 +1,56 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/bootloader.rs - 系统引导程序跳转
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Entry into the STM32F103 ROM system bootloader.
+//!
+//! Jumping straight from the running application would leave clocks, USB
+//! and interrupts configured in ways the ROM code does not expect.  Instead a
+//! magic word is left in a RAM section that survives a soft reset, the core
+//! is reset, and [`check_and_jump`] hands over to the ROM before any
+//! peripheral is touched.
+//!
+//! Note that the F103 ROM bootloader only speaks the USART1 protocol
+//! (PA9/PA10, e.g. `stm32flash`); USB is not available while it runs.
+
+use core::mem::MaybeUninit;
+
+use cortex_m::peripheral::SCB;
+
+use crate::hal::SYSTEM_MEMORY;
+
+/// Marker requesting bootloader entry on the next boot.
+const REQUEST_MAGIC: u32 = 0xB007_10AD;
+
+#[unsafe(link_section = ".uninit.BOOTLOADER_REQUEST")]
+static mut REQUEST: MaybeUninit<u32> = MaybeUninit::uninit();
+
+/// Reset into the system bootloader.
+pub fn enter() -> ! {
+  defmt::println!("[boot] entering system bootloader");
+  unsafe { (&raw mut REQUEST).cast::<u32>().write_volatile(REQUEST_MAGIC) };
+  SCB::sys_reset();
+}
+
+/// Jump to the system bootloader if [`enter`] requested it.
+///
+/// Must run first thing in [`crate::app::run`], before clocks or peripherals are set up.
+pub fn check_and_jump() {
+  let request = unsafe { (&raw const REQUEST).cast::<u32>().read_volatile() };
+  if request != REQUEST_MAGIC {
+    return;
+  }
+  unsafe {
+    (&raw mut REQUEST).cast::<u32>().write_volatile(0);
+    cortex_m::asm::bootload(SYSTEM_MEMORY as *const u32);
+  }
+}
@@ -0,0 +1,183 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/calibration.rs - 发射功率校准表
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Transmit power calibration.
+//!
+//! [`RadioParams::power_dbm`](crate::radio::RadioParams) is the chip
+//! output power; what leaves the E22 after its PA depends on the module,
+//! the supply and the frequency, and can only be found with a power meter.
+//! The calibration table holds those measurements: the module band is
+//! split into [`SEGMENTS`] equal frequency segments, and for each segment
+//! the output power measured at each chip power of [`STEPS`], in tenths of
+//! a dBm.  The table is kept with the settings.
+//!
+//! Calibrating from the control port, on the frequency of the current
+//! radio settings:
+//!
+//! 1. `AT+CALTX=<chip dBm>` sends an unmodulated carrier at one of the
+//!    [`STEPS`] for [`CARRIER_MS`], long enough to read the meter;
+//! 2. `AT+CAL=<chip dBm>,<measured 0.1 dBm>` stores the reading for the
+//!    segment, e.g. `AT+CAL=22,297` for 29.7 dBm;
+//! 3. `AT+CAL?` lists the segment, `AT+CAL=CLEAR` forgets every segment.
+//!
+//! With at least two readings in a segment, `AT+TXOUT=<dBm>` sets the chip
+//! power that gives the wanted output there, interpolating linearly
+//! between readings and extrapolating beyond them; `AT+TXOUT?` reports the
+//! expected output of the current settings.
+
+use heapless::Vec;
+
+use crate::radio::Radio;
+use crate::{band, led, timer};
+
+/// Frequency segments of the module band.
+pub const SEGMENTS: usize = 4;
+/// Chip output powers that are measured, in dBm.
+pub const STEPS: [i8; 5] = [10, 14, 17, 20, 22];
+/// Size of [`Table`] when encoded.
+pub const ENCODED_LEN: usize = SEGMENTS * STEPS.len() * 2;
+/// Length of the measurement carrier.
+pub const CARRIER_MS: u32 = 5_000;
+
+/// Marks a step that has not been measured.
+const UNSET: i16 = i16::MIN;
+
+/// Measured output power per segment and step, in tenths of a dBm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Table {
+  measured: [[i16; STEPS.len()]; SEGMENTS],
+}
+
+impl Default for Table {
+  fn default() -> Self {
+    Self {
+      measured: [[UNSET; STEPS.len()]; SEGMENTS],
+    }
+  }
+}
+
+/// Segment of the module band holding `frequency_hz`.
+pub fn segment(frequency_hz: u32) -> usize {
+  let start = *band::FREQUENCY_HZ.start();
+  let span = band::FREQUENCY_HZ.end() - start + 1;
+  let offset = frequency_hz.saturating_sub(start).min(span - 1);
+  (offset as u64 * SEGMENTS as u64 / span as u64) as usize
+}
+
+/// Index of `power_dbm` in [`STEPS`].
+pub fn step(power_dbm: i8) -> Option<usize> {
+  STEPS.iter().position(|&step| step == power_dbm)
+}
+
+impl Table {
+  /// Store the output measured at chip power `power_dbm` on `frequency_hz`.
+  /// `false` when `power_dbm` is not one of the [`STEPS`].
+  pub fn set(&mut self, frequency_hz: u32, power_dbm: i8, output_deci_dbm: i16) -> bool {
+    let Some(step) = step(power_dbm) else {
+      return false;
+    };
+    self.measured[segment(frequency_hz)][step] = output_deci_dbm;
+    true
+  }
+
+  pub fn clear(&mut self) {
+    *self = Self::default();
+  }
+
+  /// Readings of the segment holding `frequency_hz`, by step.
+  pub fn readings(&self, frequency_hz: u32) -> [Option<i16>; STEPS.len()] {
+    self.measured[segment(frequency_hz)].map(|value| (value != UNSET).then_some(value))
+  }
+
+  /// Chip power giving `output_dbm` at the module output on
+  /// `frequency_hz`, within the chip's -9..=22 dBm.  `None` without two
+  /// readings rising with the chip power.
+  pub fn chip_power_dbm(&self, frequency_hz: u32, output_dbm: i8) -> Option<i8> {
+    let points = self.points(frequency_hz, |chip, output| (output, chip));
+    let chip_deci = interpolate(&points, output_dbm as i32 * 10)?;
+    Some((chip_deci + 5).div_euclid(10).clamp(-9, 22) as i8)
+  }
+
+  /// Expected module output at chip power `power_dbm` on `frequency_hz`,
+  /// in tenths of a dBm.
+  pub fn output_deci_dbm(&self, frequency_hz: u32, power_dbm: i8) -> Option<i16> {
+    let points = self.points(frequency_hz, |chip, output| (chip, output));
+    let output = interpolate(&points, power_dbm as i32 * 10)?;
+    i16::try_from(output).ok()
+  }
+
+  /// Readings of a segment as `(chip, output)` pairs in tenths of a dBm,
+  /// arranged by `pair`.
+  fn points(
+    &self,
+    frequency_hz: u32,
+    pair: impl Fn(i32, i32) -> (i32, i32),
+  ) -> Vec<(i32, i32), { STEPS.len() }> {
+    STEPS
+      .iter()
+      .zip(self.readings(frequency_hz))
+      .filter_map(|(&chip, output)| Some(pair(chip as i32 * 10, output? as i32)))
+      .collect()
+  }
+
+  /// Encode as the readings, segment by segment, as i16 LE.
+  pub fn encode(&self) -> [u8; ENCODED_LEN] {
+    let mut bytes = [0u8; ENCODED_LEN];
+    let values = self.measured.iter().flatten();
+    for (chunk, value) in bytes.chunks_exact_mut(2).zip(values) {
+      chunk.copy_from_slice(&value.to_le_bytes());
+    }
+    bytes
+  }
+
+  pub fn decode(bytes: [u8; ENCODED_LEN]) -> Self {
+    let mut table = Self::default();
+    let values = table.measured.iter_mut().flatten();
+    for (value, chunk) in values.zip(bytes.chunks_exact(2)) {
+      *value = i16::from_le_bytes([chunk[0], chunk[1]]);
+    }
+    table
+  }
+}
+
+/// Piecewise linear `y` at `x` through `points`, which must rise in `x`;
+/// the outer pieces are extended beyond the ends.
+fn interpolate(points: &[(i32, i32)], x: i32) -> Option<i32> {
+  if points.len() < 2 || points.windows(2).any(|pair| pair[1].0 <= pair[0].0) {
+    return None;
+  }
+  let piece = points
+    .windows(2)
+    .position(|pair| x <= pair[1].0)
+    .unwrap_or(points.len() - 2);
+  let ((x0, y0), (x1, y1)) = (points[piece], points[piece + 1]);
+  Some(y0 + (x - x0) * (y1 - y0) / (x1 - x0))
+}
+
+/// Send an unmodulated carrier on `frequency_hz` for [`CARRIER_MS`] at the
+/// power the radio was last configured with, blocking until it is done.
+/// The radio is in standby afterwards.
+pub fn carrier(radio: &mut impl Radio, frequency_hz: u32) -> bool {
+  if !radio.tune(frequency_hz) || !radio.carrier(true) {
+    radio.carrier(false);
+    defmt::warn!("[calibration] carrier not available");
+    return false;
+  }
+  defmt::info!("[calibration] carrier at {} Hz for {} ms", frequency_hz, CARRIER_MS);
+  led::set_transmitting(true);
+  let started = timer::now_ms();
+  while timer::elapsed_ms(started) < CARRIER_MS {}
+  let off = radio.carrier(false);
+  led::set_transmitting(false);
+  off
+}
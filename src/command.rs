@@ -0,0 +1,903 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/command.rs - 主机命令解析
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Host command parsing for the USB log/control port.
+//!
+//! Commands are `AT+` lines read from the control CDC interface; the data
+//! interface is never inspected.  Trailing CR/LF is ignored; everything else
+//! is matched verbatim.
+
+use crate::airtime;
+use crate::antenna;
+use crate::battery;
+use crate::beacon;
+use crate::bench;
+use crate::calibration;
+use crate::csma;
+use crate::cw::Beacon;
+use crate::gps;
+use crate::log_control::{Category, Level};
+use crate::lorawan::{self, Uplink};
+use crate::noise;
+use crate::ook::{self, Sequence};
+use crate::ota;
+use crate::packetizer::{FrameMode, MAX_PAYLOAD};
+use crate::per::PerMode;
+use crate::radio::{Bandwidth, RadioParams, SwitchGuard};
+use crate::relay;
+use crate::scan::ScanRange;
+use crate::sensor;
+use crate::telemetry::{self, Framing};
+use crate::settings::{self, ProfileName};
+use crate::tdma;
+use crate::timesync;
+use crate::trim;
+use crate::uart;
+use crate::ui::ScreenPower;
+use crate::usb;
+
+/// Prefix of every host command.
+const PREFIX: &[u8] = b"AT+";
+
+/// Probes sent by a bare `AT+PING`.
+const DEFAULT_PINGS: u16 = 10;
+
+/// Reply sent when a command was accepted.
+pub const REPLY_OK: &[u8] = b"OK\r\n";
+/// Reply sent when a command was not understood.
+pub const REPLY_ERROR: &[u8] = b"ERROR\r\n";
+
+/// A decoded host command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Command {
+  /// `AT+LOG=<0|1>` — mirror diagnostics over the CDC port.
+  Log(bool),
+  /// `AT+LOGLEVEL=<category|ALL>,<level>` — set and persist the log level
+  /// of one or every diagnostic category.
+  SetLogLevel(Option<Category>, Level),
+  /// `AT+LOGLEVEL?` — report the log level of every category.
+  QueryLogLevel,
+  /// `AT+EVENTS?` — dump the post-mortem event ring, oldest first.
+  QueryEvents,
+  /// `AT+EVENTS=CLEAR` — empty the event ring.
+  ClearEvents,
+  /// `AT+PKT=NL`, `AT+PKT=IDLE,<ms>` or `AT+PKT=SIZE,<n>` — how host data
+  /// is split into LoRa frames.
+  Packetizer(FrameMode),
+  /// `AT+BOOTLOADER` — reset into the ROM system bootloader.
+  Bootloader,
+  /// `AT+ID?` — report the chip unique ID.
+  QueryId,
+  /// `AT+VER?` — report the firmware version, build and features.
+  QueryVersion,
+  /// `AT+ADDR=<hex>` — set and persist the local node address.
+  SetAddress(u16),
+  /// `AT+DST=<hex>` — set and persist the destination address.
+  SetPeer(u16),
+  /// `AT+ADDRMODE=<0|1>` — enable the address header.
+  Addressing(bool),
+  /// `AT+ADDR?` — report local address, destination and address mode.
+  QueryAddress,
+  /// `AT+KEY=<32 hex digits>` — set and persist the AES-128 link key.
+  SetKey([u8; 16]),
+  /// `AT+MIC=<0|1>` — authenticate frames with a counter and MIC.
+  Mic(bool),
+  /// `AT+PAIR` — pair with another bridge in pairing mode.
+  Pair,
+  /// `AT+RADIO=<freq Hz>,<power dBm>,<sf>,<bw kHz>,<cr 5..8>` — change the
+  /// radio parameters of this bridge and its peer together.
+  SetRadio(RadioParams),
+  /// `AT+RADIO?` — report the radio parameters.
+  QueryRadio,
+  /// `AT+ADR=<0|1>` — adapt the spreading factor to the link quality.
+  Adr(bool),
+  /// `AT+PING` or `AT+PING=<1..1000>` — probe the peer and report round
+  /// trip time, RSSI/SNR and loss.
+  Ping(u16),
+  /// `AT+ANT=<A|B>` — measure the link with the fitted antenna under a
+  /// label and check the transmit path.
+  Antenna(antenna::Slot),
+  /// `AT+ANT?` — report the antenna measurements.
+  QueryAntenna,
+  /// `AT+SELFTEST` — check the TX→RX chain at minimum power with the peer.
+  SelfTest,
+  /// `AT+BENCH=<seconds>` — send back-to-back benchmark frames to the peer.
+  Bench(u32),
+  /// `AT+PER=TX,<count>,<interval ms>`, `AT+PER=RX` or `AT+PER=OFF` —
+  /// packet error rate test.
+  Per(PerMode),
+  /// `AT+SCAN=<start Hz>,<stop Hz>,<step Hz>` — sweep the band and report
+  /// the RSSI per channel.
+  Scan(ScanRange),
+  /// `AT+OLED=<contrast 0..255>,<dim s>,<off s>` — screen contrast and the
+  /// idle time before dimming and blanking, `0` to never do so.
+  SetScreen(ScreenPower),
+  /// `AT+OLED?` — report the screen settings.
+  QueryScreen,
+  /// `AT+OOK=<freq Hz>,<repeats>,<on µs>,<off µs>,...` — key a carrier
+  /// with a pulse table.
+  Ook(Sequence),
+  /// `AT+CWID=<callsign>,<interval s>` — identify in Morse every
+  /// `interval` seconds, `0` to stop.
+  SetCwId(Beacon),
+  /// `AT+CWID?` — report the CW identification settings.
+  QueryCwId,
+  /// `AT+BEACON=<interval s>,<fields>` — send a telemetry line every
+  /// `interval` seconds, `0` to stop; fields are letters of `IUVPBT`.
+  SetBeacon(beacon::Config),
+  /// `AT+BEACON?` — report the beacon settings.
+  QueryBeacon,
+  /// `AT+SENSOR=<interval s>,<id>` — send sensor telemetry every
+  /// `interval` seconds, `0` to stop, tagged with station `id`.
+  SetSensor(sensor::Config),
+  /// `AT+SENSOR?` — report the sensor settings, sensors found and the
+  /// last reading.
+  QuerySensor,
+  /// `AT+RELAY=<0|1>` — relay frames for other nodes.
+  Relay(bool),
+  /// `AT+RELAY?` — report the repeater state and counters.
+  QueryRelay,
+  /// `AT+TTL=<0..15>` — relays a transmitted frame may take.
+  SetHopLimit(u8),
+  /// `AT+MESH?` — report routing counters and the nodes heard.
+  QueryMesh,
+  /// `AT+TDMA=OFF`, `AT+TDMA=MASTER,<slots>,<slot>` or
+  /// `AT+TDMA=NODE,<slot>` — time-slotted channel access.
+  SetTdma(tdma::Config),
+  /// `AT+TDMA?` — report the TDMA role, slots and sync state.
+  QueryTdma,
+  /// `AT+LWABP=<DevAddr>,<NwkSKey>,<AppSKey>` (hex) — set and persist the
+  /// LoRaWAN ABP session.
+  SetLoRaWan(lorawan::Session),
+  /// `AT+LWSEND=<port>,<0|1 confirmed>,<hex payload>` — send a LoRaWAN
+  /// uplink.
+  LoRaWanSend(Uplink),
+  /// `AT+LW?` — report DevAddr and frame counters.
+  QueryLoRaWan,
+  /// `AT+LWOTAA=<DevEUI>,<AppEUI>,<AppKey>` (hex) — set and persist the
+  /// LoRaWAN over-the-air activation identity.
+  SetLoRaWanOtaa(lorawan::Otaa),
+  /// `AT+LWJOIN` — send a LoRaWAN join request.
+  LoRaWanJoin,
+  /// `AT+PORT=<USB|UART|HID>` — port of the bridge data, from the next
+  /// reset; `HID` only when built with `usb-hid`.
+  SetDataPort(uart::Source),
+  /// `AT+UARTBAUD=<baud>` — USART1 baud rate, from the next reset.
+  SetUartBaud(u32),
+  /// `AT+PORT?` — report the data port in use and the stored settings.
+  QueryDataPort,
+  /// `AT+USBID=<vid hex>,<pid hex>[,<manufacturer>[,<product>]]` or
+  /// `AT+USBID=DEFAULT` — USB identity, from the next reset.
+  SetUsbIdentity(usb::Identity),
+  /// `AT+USBID?` — report the stored USB identity.
+  QueryUsbIdentity,
+  /// `AT+MODBUS=<0|1>` — carry Modbus RTU on the data port.
+  Modbus(bool),
+  /// `AT+TIME?` — report the network time and where it came from.
+  QueryTime,
+  /// `AT+TIME=<YYYY-MM-DDTHH:MM:SS>` (UTC) — set the RTC.
+  SetTime(u64),
+  /// `AT+RTC?` — report the RTC, its offset to network time and its drift.
+  QueryRtc,
+  /// `AT+VBAT=<ratio ‰>,<low mV>,<full mV>` — battery divider ratio and
+  /// thresholds.
+  SetBattery(battery::Config),
+  /// `AT+VBAT?` — report the battery voltage and settings.
+  QueryBattery,
+  /// `AT+DERATE?` — report whether the transmit power is derated.
+  QueryDerate,
+  /// `AT+BOOT?` — report the boot stages that failed.
+  QueryBoot,
+  /// `AT+RSSI?` — read the instantaneous RSSI of the link channel.
+  QueryRssi,
+  /// `AT+RSSI=<interval ms>` — stream the RSSI; `0` stops.
+  StreamRssi(u32),
+  /// `AT+STATS=<interval s>[,BIN]` — stream status lines, or binary
+  /// protocol frames; `0` stops.
+  StreamStats(u32, Framing),
+  /// `AT+RXEARLY=<0|1>` — report incoming frames before RxDone.
+  RxEarly(bool),
+  /// `AT+RXMETA=<0|1>` — precede received payloads with a metadata header.
+  RxMeta(bool),
+  /// `AT+CRCPASS=<0|1>` — also deliver frames that failed their CRC.
+  CrcPass(bool),
+  /// `AT+CRCPASS?` — report the setting and the CRC failures counted.
+  QueryCrcPass,
+  /// `AT+SNIFF=<0|1>` — stream every frame heard to the data port as pcap.
+  Sniff(bool),
+  /// `AT+AFC=<0|1>` — follow the peer's carrier frequency.
+  Afc(bool),
+  /// `AT+AFC?` — report the last frequency error and the correction.
+  QueryAfc,
+  /// `AT+TRIM=<ppb/°C>,<reference °C>` — temperature coefficient of the
+  /// carrier.
+  SetTrim(trim::Config),
+  /// `AT+TRIM?` — report the trim settings, temperature and trim.
+  QueryTrim,
+  /// `AT+CALTX=<chip dBm>` — send a carrier to measure a calibration step.
+  CalibrationCarrier(i8),
+  /// `AT+CAL=<chip dBm>,<measured 0.1 dBm>` — store a calibration reading
+  /// for the current frequency.
+  SetCalibration { power_dbm: i8, output_deci_dbm: i16 },
+  /// `AT+CAL=CLEAR` — forget the calibration table.
+  ClearCalibration,
+  /// `AT+PROFILE=SAVE,<name>` — store the settings as a named profile.
+  SaveProfile(ProfileName),
+  /// `AT+PROFILE=LOAD,<name>` — make a profile the settings and restart.
+  LoadProfile(ProfileName),
+  /// `AT+PROFILE=DEL,<name>` — forget a profile.
+  DeleteProfile(ProfileName),
+  /// `AT+PROFILE?` — list the stored profiles.
+  QueryProfiles,
+  /// `AT+CFG?` — export the persisted settings record as hex.
+  ExportConfig,
+  /// `AT+CFG=<hex>` — import an exported settings record and restart;
+  /// the bytes after the record are `0xFF`.
+  ImportConfig([u8; settings::RECORD_MAX]),
+  /// `AT+CAL?` — report the readings for the current frequency.
+  QueryCalibration,
+  /// `AT+TXOUT=<dBm>` — set the chip power from the calibration table.
+  SetOutputPower(i8),
+  /// `AT+TXOUT?` — report the expected output power.
+  QueryOutputPower,
+  /// `AT+RFSW=<pre µs>,<post µs>` — delays around RF switch transitions.
+  SetSwitchGuard(SwitchGuard),
+  /// `AT+RFSW?` — report the RF switch delays.
+  QuerySwitchGuard,
+  /// `AT+AIRTIME=<‰>` — airtime budget per hour; `0` for none.
+  SetAirtime(airtime::Config),
+  /// `AT+AIRTIME?` — report the airtime and the budget left.
+  QueryAirtime,
+  /// `AT+CSMA=<min ms>,<max ms>` or `AT+CSMA=OFF` — carrier-sense backoff
+  /// before every transmission.
+  SetCsma(csma::Config),
+  /// `AT+CSMA?` — report the backoff window and counters.
+  QueryCsma,
+  /// `AT+TXQ?` — report the frames queued and dropped per priority.
+  QueryTxQueue,
+  /// `AT+OTA=START,<size>,<crc32 hex>`, `AT+OTA=<offset>,<hex>`,
+  /// `AT+OTA=END` or `AT+OTA=ABORT` — update the peer's firmware.
+  Ota(ota::Request),
+  /// `AT+OTA?` — report the firmware update on both sides.
+  QueryOta,
+  /// `AT+FLOG=<0|1>` — stop or start logging frames to the external flash.
+  SetFlashLog(bool),
+  /// `AT+FLOG?` — report the flash log.
+  QueryFlashLog,
+  /// `AT+FLOG=CLEAR` — empty the flash log.
+  ClearFlashLog,
+  /// `AT+FLOG=DUMP` — print the flash log, oldest first.
+  DumpFlashLog,
+  /// `AT+SD?` — report the SD card logger.
+  QuerySdCard,
+  /// Starts with `AT+` but is not a known command.
+  Unknown,
+}
+
+impl Command {
+  /// Parse a control-port read.  Returns `None` when it is not an `AT+` line.
+  pub fn parse(input: &[u8]) -> Option<Command> {
+    let body = input.strip_prefix(PREFIX)?;
+    let body = trim_line_end(body);
+
+    let command = match body {
+      b"LOG=0" => Command::Log(false),
+      b"LOG=1" => Command::Log(true),
+      b"LOGLEVEL?" => Command::QueryLogLevel,
+      b"EVENTS?" => Command::QueryEvents,
+      b"EVENTS=CLEAR" => Command::ClearEvents,
+      b"PKT=NL" => Command::Packetizer(FrameMode::Terminator(b'\n')),
+      b"BOOTLOADER" => Command::Bootloader,
+      b"ID?" => Command::QueryId,
+      b"VER?" => Command::QueryVersion,
+      b"ADDR?" => Command::QueryAddress,
+      b"ADDRMODE=0" => Command::Addressing(false),
+      b"ADDRMODE=1" => Command::Addressing(true),
+      b"MIC=0" => Command::Mic(false),
+      b"MIC=1" => Command::Mic(true),
+      b"PAIR" => Command::Pair,
+      b"RADIO?" => Command::QueryRadio,
+      b"ADR=0" => Command::Adr(false),
+      b"ADR=1" => Command::Adr(true),
+      b"PING" => Command::Ping(DEFAULT_PINGS),
+      b"ANT=A" => Command::Antenna(antenna::Slot::A),
+      b"ANT=B" => Command::Antenna(antenna::Slot::B),
+      b"ANT?" => Command::QueryAntenna,
+      b"SELFTEST" => Command::SelfTest,
+      b"PER=RX" => Command::Per(PerMode::Receive),
+      b"PER=OFF" => Command::Per(PerMode::Stop),
+      b"OLED?" => Command::QueryScreen,
+      b"CWID?" => Command::QueryCwId,
+      b"BEACON?" => Command::QueryBeacon,
+      b"SENSOR?" => Command::QuerySensor,
+      b"RELAY=0" => Command::Relay(false),
+      b"RELAY=1" => Command::Relay(true),
+      b"RELAY?" => Command::QueryRelay,
+      b"MESH?" => Command::QueryMesh,
+      b"TDMA=OFF" => Command::SetTdma(tdma::Config::default()),
+      b"TDMA?" => Command::QueryTdma,
+      b"LW?" => Command::QueryLoRaWan,
+      b"LWJOIN" => Command::LoRaWanJoin,
+      b"PORT=USB" => Command::SetDataPort(uart::Source::Usb),
+      b"PORT=UART" => Command::SetDataPort(uart::Source::Uart),
+      #[cfg(feature = "usb-hid")]
+      b"PORT=HID" => Command::SetDataPort(uart::Source::Hid),
+      b"PORT?" => Command::QueryDataPort,
+      b"USBID=DEFAULT" => Command::SetUsbIdentity(usb::Identity::default()),
+      b"USBID?" => Command::QueryUsbIdentity,
+      b"MODBUS=0" => Command::Modbus(false),
+      b"MODBUS=1" => Command::Modbus(true),
+      b"TIME?" => Command::QueryTime,
+      b"RTC?" => Command::QueryRtc,
+      b"VBAT?" => Command::QueryBattery,
+      b"DERATE?" => Command::QueryDerate,
+      b"BOOT?" => Command::QueryBoot,
+      b"RSSI?" => Command::QueryRssi,
+      b"RXEARLY=0" => Command::RxEarly(false),
+      b"RXEARLY=1" => Command::RxEarly(true),
+      b"RXMETA=0" => Command::RxMeta(false),
+      b"RXMETA=1" => Command::RxMeta(true),
+      b"CRCPASS=0" => Command::CrcPass(false),
+      b"CRCPASS=1" => Command::CrcPass(true),
+      b"CRCPASS?" => Command::QueryCrcPass,
+      b"AFC=0" => Command::Afc(false),
+      b"AFC=1" => Command::Afc(true),
+      b"AFC?" => Command::QueryAfc,
+      b"SNIFF=0" => Command::Sniff(false),
+      b"SNIFF=1" => Command::Sniff(true),
+      b"TRIM?" => Command::QueryTrim,
+      b"CAL=CLEAR" => Command::ClearCalibration,
+      b"CFG?" => Command::ExportConfig,
+      b"PROFILE?" => Command::QueryProfiles,
+      b"CAL?" => Command::QueryCalibration,
+      b"TXOUT?" => Command::QueryOutputPower,
+      b"RFSW?" => Command::QuerySwitchGuard,
+      b"AIRTIME?" => Command::QueryAirtime,
+      b"CSMA=OFF" => Command::SetCsma(csma::Config::OFF),
+      b"CSMA?" => Command::QueryCsma,
+      b"TXQ?" => Command::QueryTxQueue,
+      b"OTA=END" => Command::Ota(ota::Request::Finish {
+        tag: [0; ota::TAG_LEN],
+      }),
+      b"OTA=ABORT" => Command::Ota(ota::Request::Abort),
+      b"OTA?" => Command::QueryOta,
+      b"FLOG=0" => Command::SetFlashLog(false),
+      b"FLOG=1" => Command::SetFlashLog(true),
+      b"FLOG?" => Command::QueryFlashLog,
+      b"FLOG=CLEAR" => Command::ClearFlashLog,
+      b"FLOG=DUMP" => Command::DumpFlashLog,
+      b"SD?" => Command::QuerySdCard,
+      _ => {
+        if let Some(ms) = body.strip_prefix(b"PKT=IDLE,").and_then(parse_u32) {
+          Command::Packetizer(FrameMode::IdleGap(ms))
+        } else if let Some(address) = body.strip_prefix(b"ADDR=").and_then(parse_hex_u16) {
+          Command::SetAddress(address)
+        } else if let Some(address) = body.strip_prefix(b"DST=").and_then(parse_hex_u16) {
+          Command::SetPeer(address)
+        } else if let Some(name) = body.strip_prefix(b"PROFILE=SAVE,").and_then(ProfileName::new) {
+          Command::SaveProfile(name)
+        } else if let Some(name) = body.strip_prefix(b"PROFILE=LOAD,").and_then(ProfileName::new) {
+          Command::LoadProfile(name)
+        } else if let Some(name) = body.strip_prefix(b"PROFILE=DEL,").and_then(ProfileName::new) {
+          Command::DeleteProfile(name)
+        } else if let Some(record) = body.strip_prefix(b"CFG=").and_then(parse_record) {
+          Command::ImportConfig(record)
+        } else if let Some(key) = body.strip_prefix(b"KEY=").and_then(parse_hex_bytes) {
+          Command::SetKey(key)
+        } else if let Some(count) = body.strip_prefix(b"PING=").and_then(parse_u32) {
+          match count {
+            1..=1000 => Command::Ping(count as u16),
+            _ => Command::Unknown,
+          }
+        } else if let Some(limit) = body.strip_prefix(b"TTL=").and_then(parse_u32) {
+          match u8::try_from(limit) {
+            Ok(limit @ 0..=relay::MAX_HOP_LIMIT) => Command::SetHopLimit(limit),
+            _ => Command::Unknown,
+          }
+        } else if let Some(seconds) = body.strip_prefix(b"BENCH=").and_then(parse_u32) {
+          match seconds {
+            1..=bench::MAX_SECONDS => Command::Bench(seconds),
+            _ => Command::Unknown,
+          }
+        } else if let Some(fields) = body.strip_prefix(b"PER=TX,") {
+          parse_per_transmit(fields).map_or(Command::Unknown, Command::Per)
+        } else if let Some(fields) = body.strip_prefix(b"SCAN=") {
+          parse_scan(fields).map_or(Command::Unknown, Command::Scan)
+        } else if let Some(fields) = body.strip_prefix(b"OLED=") {
+          parse_screen(fields).map_or(Command::Unknown, Command::SetScreen)
+        } else if let Some(fields) = body.strip_prefix(b"OOK=") {
+          parse_ook(fields).map_or(Command::Unknown, Command::Ook)
+        } else if let Some(fields) = body.strip_prefix(b"CWID=") {
+          parse_cw_id(fields).map_or(Command::Unknown, Command::SetCwId)
+        } else if let Some(fields) = body.strip_prefix(b"BEACON=") {
+          parse_beacon(fields).map_or(Command::Unknown, Command::SetBeacon)
+        } else if let Some(fields) = body.strip_prefix(b"VBAT=") {
+          parse_battery(fields).map_or(Command::Unknown, Command::SetBattery)
+        } else if let Some(power) = body.strip_prefix(b"CALTX=").and_then(parse_i32) {
+          match i8::try_from(power) {
+            Ok(power) if calibration::step(power).is_some() => {
+              Command::CalibrationCarrier(power)
+            }
+            _ => Command::Unknown,
+          }
+        } else if let Some(fields) = body.strip_prefix(b"CAL=") {
+          parse_calibration(fields).map_or(Command::Unknown, |(power_dbm, output_deci_dbm)| {
+            Command::SetCalibration {
+              power_dbm,
+              output_deci_dbm,
+            }
+          })
+        } else if let Some(power) = body.strip_prefix(b"TXOUT=").and_then(parse_i32) {
+          i8::try_from(power).map_or(Command::Unknown, Command::SetOutputPower)
+        } else if let Some(interval) = body.strip_prefix(b"RSSI=").and_then(parse_u32) {
+          match interval {
+            interval if noise::Monitor::valid_interval(interval) => Command::StreamRssi(interval),
+            _ => Command::Unknown,
+          }
+        } else if let Some(permille) = body.strip_prefix(b"AIRTIME=").and_then(parse_u32) {
+          u16::try_from(permille)
+            .ok()
+            .and_then(airtime::Config::new)
+            .map_or(Command::Unknown, Command::SetAirtime)
+        } else if let Some(fields) = body.strip_prefix(b"STATS=") {
+          parse_stats(fields).map_or(Command::Unknown, |(interval_s, framing)| {
+            Command::StreamStats(interval_s, framing)
+          })
+        } else if let Some(fields) = body.strip_prefix(b"TRIM=") {
+          parse_trim(fields).map_or(Command::Unknown, Command::SetTrim)
+        } else if let Some(fields) = body.strip_prefix(b"CSMA=") {
+          parse_csma(fields).map_or(Command::Unknown, Command::SetCsma)
+        } else if let Some(fields) = body.strip_prefix(b"OTA=START,") {
+          parse_ota_start(fields).map_or(Command::Unknown, Command::Ota)
+        } else if let Some(fields) = body.strip_prefix(b"OTA=") {
+          parse_ota_chunk(fields).map_or(Command::Unknown, Command::Ota)
+        } else if let Some(fields) = body.strip_prefix(b"RFSW=") {
+          parse_switch_guard(fields).map_or(Command::Unknown, Command::SetSwitchGuard)
+        } else if let Some(fields) = body.strip_prefix(b"SENSOR=") {
+          parse_sensor(fields).map_or(Command::Unknown, Command::SetSensor)
+        } else if let Some(fields) = body.strip_prefix(b"TDMA=MASTER,") {
+          parse_tdma_master(fields).map_or(Command::Unknown, Command::SetTdma)
+        } else if let Some(slot) = body.strip_prefix(b"TDMA=NODE,").and_then(parse_u32) {
+          u8::try_from(slot)
+            .ok()
+            .and_then(tdma::Config::node)
+            .map_or(Command::Unknown, Command::SetTdma)
+        } else if let Some(fields) = body.strip_prefix(b"LWABP=") {
+          parse_lorawan_session(fields).map_or(Command::Unknown, Command::SetLoRaWan)
+        } else if let Some(fields) = body.strip_prefix(b"LWOTAA=") {
+          parse_lorawan_otaa(fields).map_or(Command::Unknown, Command::SetLoRaWanOtaa)
+        } else if let Some(fields) = body.strip_prefix(b"LWSEND=") {
+          parse_lorawan_send(fields).map_or(Command::Unknown, Command::LoRaWanSend)
+        } else if let Some(baud) = body.strip_prefix(b"UARTBAUD=").and_then(parse_u32) {
+          match baud {
+            baud if uart::Config::valid_baud(baud) => Command::SetUartBaud(baud),
+            _ => Command::Unknown,
+          }
+        } else if let Some(fields) = body.strip_prefix(b"USBID=") {
+          parse_usb_identity(fields).map_or(Command::Unknown, Command::SetUsbIdentity)
+        } else if let Some(fields) = body.strip_prefix(b"LOGLEVEL=") {
+          parse_log_level(fields).map_or(Command::Unknown, |(category, level)| {
+            Command::SetLogLevel(category, level)
+          })
+        } else if let Some(text) = body.strip_prefix(b"TIME=") {
+          parse_time(text).map_or(Command::Unknown, Command::SetTime)
+        } else if let Some(fields) = body.strip_prefix(b"RADIO=") {
+          parse_radio(fields).map_or(Command::Unknown, Command::SetRadio)
+        } else if let Some(size) = body.strip_prefix(b"PKT=SIZE,").and_then(parse_u32) {
+          match size as usize {
+            size @ 1..=MAX_PAYLOAD => Command::Packetizer(FrameMode::FixedSize(size)),
+            _ => Command::Unknown,
+          }
+        } else {
+          Command::Unknown
+        }
+      }
+    };
+    Some(command)
+  }
+}
+
+/// Parse an unsigned decimal number.
+fn parse_u32(digits: &[u8]) -> Option<u32> {
+  if digits.is_empty() {
+    return None;
+  }
+  let mut value: u32 = 0;
+  for &digit in digits {
+    if !digit.is_ascii_digit() {
+      return None;
+    }
+    value = value.checked_mul(10)?.checked_add((digit - b'0') as u32)?;
+  }
+  Some(value)
+}
+
+/// Strip any trailing CR/LF characters.
+fn trim_line_end(mut line: &[u8]) -> &[u8] {
+  while let [rest @ .., b'\r' | b'\n'] = line {
+    line = rest;
+  }
+  line
+}
+
+/// Parse a decimal number with an optional minus sign.
+fn parse_i32(field: &[u8]) -> Option<i32> {
+  match field {
+    [b'-', digits @ ..] => Some(-i32::try_from(parse_u32(digits)?).ok()?),
+    digits => i32::try_from(parse_u32(digits)?).ok(),
+  }
+}
+
+/// Parse one to four hex digits.
+fn parse_hex_u16(digits: &[u8]) -> Option<u16> {
+  if digits.is_empty() || digits.len() > 4 {
+    return None;
+  }
+  let mut value: u16 = 0;
+  for &digit in digits {
+    let nibble = (digit as char).to_digit(16)?;
+    value = (value << 4) | nibble as u16;
+  }
+  Some(value)
+}
+
+/// Parse exactly `2 * N` hex digits into `N` bytes.
+fn parse_hex_bytes<const N: usize>(digits: &[u8]) -> Option<[u8; N]> {
+  if digits.len() != 2 * N {
+    return None;
+  }
+  let mut bytes = [0u8; N];
+  for (byte, pair) in bytes.iter_mut().zip(digits.chunks_exact(2)) {
+    *byte = parse_hex_u16(pair)? as u8;
+  }
+  Some(bytes)
+}
+
+/// Parse a settings record of up to [`settings::RECORD_MAX`] bytes in hex,
+/// padded with `0xFF`.
+fn parse_record(digits: &[u8]) -> Option<[u8; settings::RECORD_MAX]> {
+  if digits.is_empty() || digits.len() % 2 != 0 || digits.len() > 2 * settings::RECORD_MAX {
+    return None;
+  }
+  let mut record = [0xFFu8; settings::RECORD_MAX];
+  for (byte, pair) in record.iter_mut().zip(digits.chunks_exact(2)) {
+    *byte = parse_hex_u16(pair)? as u8;
+  }
+  Some(record)
+}
+
+/// Parse `<freq Hz>,<power dBm>,<sf>,<bw kHz>,<cr>`.
+fn parse_radio(fields: &[u8]) -> Option<RadioParams> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let frequency_hz = parse_u32(fields.next()?)?;
+  let power_dbm = match fields.next()? {
+    [b'-', digits @ ..] => -i8::try_from(parse_u32(digits)?).ok()?,
+    digits => i8::try_from(parse_u32(digits)?).ok()?,
+  };
+  let sf = u8::try_from(parse_u32(fields.next()?)?).ok()?;
+  let bandwidth = Bandwidth::from_khz(parse_u32(fields.next()?)?)?;
+  let cr = u8::try_from(parse_u32(fields.next()?)?).ok()?;
+  if fields.next().is_some() {
+    return None;
+  }
+  let params = RadioParams {
+    frequency_hz,
+    power_dbm,
+    sf,
+    bandwidth,
+    cr,
+  };
+  params.is_valid().then_some(params)
+}
+
+/// Parse `<count>,<interval ms>`.
+fn parse_per_transmit(fields: &[u8]) -> Option<PerMode> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let count = parse_u32(fields.next()?)?;
+  let interval_ms = parse_u32(fields.next()?)?;
+  if fields.next().is_some() || count == 0 {
+    return None;
+  }
+  Some(PerMode::Transmit { count, interval_ms })
+}
+
+/// Parse `<start Hz>,<stop Hz>,<step Hz>`.
+fn parse_scan(fields: &[u8]) -> Option<ScanRange> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let start_hz = parse_u32(fields.next()?)?;
+  let stop_hz = parse_u32(fields.next()?)?;
+  let step_hz = parse_u32(fields.next()?)?;
+  if fields.next().is_some() {
+    return None;
+  }
+  ScanRange::new(start_hz, stop_hz, step_hz)
+}
+
+/// Parse `<contrast>,<dim s>,<off s>`.
+fn parse_screen(fields: &[u8]) -> Option<ScreenPower> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let contrast = u8::try_from(parse_u32(fields.next()?)?).ok()?;
+  let dim_after_s = u16::try_from(parse_u32(fields.next()?)?).ok()?;
+  let off_after_s = u16::try_from(parse_u32(fields.next()?)?).ok()?;
+  if fields.next().is_some() {
+    return None;
+  }
+  Some(ScreenPower {
+    contrast,
+    dim_after_s,
+    off_after_s,
+  })
+}
+
+/// Parse `<freq Hz>,<repeats>,<µs>,<µs>,...`.
+fn parse_ook(fields: &[u8]) -> Option<Sequence> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let frequency_hz = parse_u32(fields.next()?)?;
+  let repeats = u8::try_from(parse_u32(fields.next()?)?).ok()?;
+  let mut durations = [0u16; ook::MAX_PULSES];
+  let mut len = 0;
+  for field in fields {
+    *durations.get_mut(len)? = u16::try_from(parse_u32(field)?).ok()?;
+    len += 1;
+  }
+  Sequence::new(frequency_hz, repeats, &durations[..len])
+}
+
+/// Parse `<callsign>,<interval s>`.
+fn parse_cw_id(fields: &[u8]) -> Option<Beacon> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let callsign = fields.next()?;
+  let interval_s = u16::try_from(parse_u32(fields.next()?)?).ok()?;
+  if fields.next().is_some() {
+    return None;
+  }
+  Beacon::new(callsign, interval_s)
+}
+
+/// Parse `<vid hex>,<pid hex>[,<manufacturer>[,<product>]]`; strings left
+/// out keep their defaults.
+fn parse_usb_identity(fields: &[u8]) -> Option<usb::Identity> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let vid = parse_hex_u16(fields.next()?)?;
+  let pid = parse_hex_u16(fields.next()?)?;
+  let manufacturer = fields.next().unwrap_or(b"");
+  let product = fields.next().unwrap_or(b"");
+  if fields.next().is_some() {
+    return None;
+  }
+  usb::Identity::new(vid, pid, manufacturer, product)
+}
+
+/// Parse `<interval s>,<field letters>`.
+fn parse_beacon(fields: &[u8]) -> Option<beacon::Config> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let interval_s = u16::try_from(parse_u32(fields.next()?)?).ok()?;
+  let letters = fields.next()?;
+  if fields.next().is_some() {
+    return None;
+  }
+  beacon::Config::parse(interval_s, letters)
+}
+
+/// Parse `<interval s>,<id>`.
+fn parse_sensor(fields: &[u8]) -> Option<sensor::Config> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let interval_s = u16::try_from(parse_u32(fields.next()?)?).ok()?;
+  let id = u8::try_from(parse_u32(fields.next()?)?).ok()?;
+  if fields.next().is_some() {
+    return None;
+  }
+  sensor::Config::new(interval_s, id)
+}
+
+/// Parse `<category|ALL>,<level>`.
+fn parse_log_level(fields: &[u8]) -> Option<(Option<Category>, Level)> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let category = match fields.next()? {
+    b"ALL" => None,
+    name => Some(Category::parse(name)?),
+  };
+  let level = Level::parse(fields.next()?)?;
+  if fields.next().is_some() {
+    return None;
+  }
+  Some((category, level))
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SS`, optionally followed by `Z`, into network
+/// time.
+fn parse_time(text: &[u8]) -> Option<u64> {
+  let text = text.strip_suffix(b"Z").unwrap_or(text);
+  let [y0, y1, y2, y3, b'-', m0, m1, b'-', d0, d1, b'T', h0, h1, b':', n0, n1, b':', s0, s1] =
+    *text
+  else {
+    return None;
+  };
+  let year = u16::try_from(parse_u32(&[y0, y1, y2, y3])?).ok()?;
+  let date = gps::Date {
+    year,
+    month: parse_u32(&[m0, m1])? as u8,
+    day: parse_u32(&[d0, d1])? as u8,
+  };
+  let time = gps::Time {
+    hours: parse_u32(&[h0, h1])? as u8,
+    minutes: parse_u32(&[n0, n1])? as u8,
+    seconds: parse_u32(&[s0, s1])? as u8,
+  };
+  if !(2_000..2_200).contains(&date.year)
+    || !(1..=12).contains(&date.month)
+    || !(1..=31).contains(&date.day)
+    || time.hours > 23
+    || time.minutes > 59
+    || time.seconds > 59
+  {
+    return None;
+  }
+  Some(timesync::epoch_ms(&date, &time))
+}
+
+/// Parse `<ratio>,<low mV>,<full mV>`.
+fn parse_battery(fields: &[u8]) -> Option<battery::Config> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let mut next = || u16::try_from(parse_u32(fields.next()?)?).ok();
+  let (ratio_milli, low_mv, full_mv) = (next()?, next()?, next()?);
+  if fields.next().is_some() {
+    return None;
+  }
+  battery::Config::new(ratio_milli, low_mv, full_mv)
+}
+
+/// Parse `<interval s>` or `<interval s>,BIN`.
+fn parse_stats(fields: &[u8]) -> Option<(u32, Framing)> {
+  let (interval, framing) = match fields.strip_suffix(b",BIN") {
+    Some(interval) => (interval, Framing::Binary),
+    None => (fields, Framing::Text),
+  };
+  let interval_s = parse_u32(interval)?;
+  telemetry::Stream::valid_interval(interval_s).then_some((interval_s, framing))
+}
+
+/// Parse `<pre µs>,<post µs>`.
+fn parse_switch_guard(fields: &[u8]) -> Option<SwitchGuard> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let mut next = || u16::try_from(parse_u32(fields.next()?)?).ok();
+  let (pre_us, post_us) = (next()?, next()?);
+  if fields.next().is_some() {
+    return None;
+  }
+  SwitchGuard::new(pre_us, post_us)
+}
+
+/// Parse `<min ms>,<max ms>`.
+fn parse_csma(fields: &[u8]) -> Option<csma::Config> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let mut next = || u16::try_from(parse_u32(fields.next()?)?).ok();
+  let (min_ms, max_ms) = (next()?, next()?);
+  if fields.next().is_some() {
+    return None;
+  }
+  csma::Config::new(min_ms, max_ms)
+}
+
+/// Parse `<size>,<crc32 hex>`.
+fn parse_ota_start(fields: &[u8]) -> Option<ota::Request> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let size = parse_u32(fields.next()?)?;
+  let crc = u32::from_be_bytes(parse_hex_bytes(fields.next()?)?);
+  if fields.next().is_some() {
+    return None;
+  }
+  Some(ota::Request::Start { size, crc })
+}
+
+/// Parse `<offset>,<hex>`.
+fn parse_ota_chunk(fields: &[u8]) -> Option<ota::Request> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let offset = parse_u32(fields.next()?)?;
+  let digits = fields.next()?;
+  if fields.next().is_some() || digits.is_empty() || digits.len() % 2 != 0 {
+    return None;
+  }
+  let mut data = [0u8; ota::CHUNK_MAX];
+  let len = digits.len() / 2;
+  for (byte, pair) in data.get_mut(..len)?.iter_mut().zip(digits.chunks_exact(2)) {
+    *byte = parse_hex_u16(pair)? as u8;
+  }
+  let chunk = ota::Chunk::new(&data[..len])?;
+  Some(ota::Request::Data { offset, chunk })
+}
+
+/// Parse `<ppb/°C>,<reference °C>`.
+fn parse_trim(fields: &[u8]) -> Option<trim::Config> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let ppb_per_c = i16::try_from(parse_i32(fields.next()?)?).ok()?;
+  let reference_c = i8::try_from(parse_i32(fields.next()?)?).ok()?;
+  if fields.next().is_some() {
+    return None;
+  }
+  trim::Config::new(ppb_per_c, reference_c)
+}
+
+/// Parse `<chip dBm>,<measured 0.1 dBm>`.
+fn parse_calibration(fields: &[u8]) -> Option<(i8, i16)> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let power_dbm = i8::try_from(parse_i32(fields.next()?)?).ok()?;
+  let output_deci_dbm = i16::try_from(parse_i32(fields.next()?)?).ok()?;
+  if fields.next().is_some() || calibration::step(power_dbm).is_none() {
+    return None;
+  }
+  // Leave room for the unset marker.
+  (output_deci_dbm > i16::MIN).then_some((power_dbm, output_deci_dbm))
+}
+
+/// Parse `<slots>,<slot>`.
+fn parse_tdma_master(fields: &[u8]) -> Option<tdma::Config> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let slots = u8::try_from(parse_u32(fields.next()?)?).ok()?;
+  let slot = u8::try_from(parse_u32(fields.next()?)?).ok()?;
+  if fields.next().is_some() {
+    return None;
+  }
+  tdma::Config::master(slots, slot)
+}
+
+/// Parse `<DevAddr>,<NwkSKey>,<AppSKey>`.
+fn parse_lorawan_session(fields: &[u8]) -> Option<lorawan::Session> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let dev_addr = u32::from_be_bytes(parse_hex_bytes(fields.next()?)?);
+  let nwk_skey = parse_hex_bytes(fields.next()?)?;
+  let app_skey = parse_hex_bytes(fields.next()?)?;
+  if fields.next().is_some() || dev_addr == 0 {
+    return None;
+  }
+  Some(lorawan::Session {
+    dev_addr,
+    nwk_skey,
+    app_skey,
+    ..lorawan::Session::default()
+  })
+}
+
+/// Parse `<DevEUI>,<AppEUI>,<AppKey>`.
+fn parse_lorawan_otaa(fields: &[u8]) -> Option<lorawan::Otaa> {
+  let mut fields = fields.split(|&byte| byte == b',');
+  let otaa = lorawan::Otaa {
+    dev_eui: parse_hex_bytes(fields.next()?)?,
+    app_eui: parse_hex_bytes(fields.next()?)?,
+    app_key: parse_hex_bytes(fields.next()?)?,
+  };
+  if fields.next().is_some() || !otaa.is_set() {
+    return None;
+  }
+  Some(otaa)
+}
+
+/// Parse `<port>,<0|1>,<hex payload>`.
+fn parse_lorawan_send(fields: &[u8]) -> Option<Uplink> {
+  let mut fields = fields.splitn(3, |&byte| byte == b',');
+  let port = u8::try_from(parse_u32(fields.next()?)?).ok()?;
+  let confirmed = match fields.next()? {
+    b"0" => false,
+    b"1" => true,
+    _ => return None,
+  };
+  let digits = fields.next()?;
+  if digits.len() % 2 != 0 || digits.len() > 2 * lorawan::MAX_APP_PAYLOAD {
+    return None;
+  }
+  let mut data = [0u8; lorawan::MAX_APP_PAYLOAD];
+  for (byte, pair) in data.iter_mut().zip(digits.chunks_exact(2)) {
+    *byte = parse_hex_u16(pair)? as u8;
+  }
+  Uplink::new(port, confirmed, &data[..digits.len() / 2])
+}
@@ -0,0 +1,128 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/crc.rs - CRC16 与 CRC32 校验
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Table-driven CRCs shared by the protocol layers.
+//!
+//! * [`crc16_ccitt`] — CRC-16/CCITT-FALSE (polynomial 0x1021, initial
+//!   0xFFFF): the settings records and the binary host protocol frames.
+//! * [`crc16_modbus`] — CRC-16/MODBUS (0x8005 reflected, initial 0xFFFF):
+//!   the Modbus RTU gateway.
+//! * [`crc32`] — CRC-32/ISO-HDLC as in zlib and Ethernet: the firmware
+//!   image check at boot.
+//!
+//! The tables are built at compile time.  The functions are `const`, so
+//! the standard check values (the CRC of `"123456789"`) below are verified
+//! by every build, on the host, before an image exists.  `host-tests/`
+//! also runs the functions on the host against the bitwise definitions.
+
+const CCITT_TABLE: [u16; 256] = ccitt_table();
+const MODBUS_TABLE: [u16; 256] = reflected_table16(0xA001);
+const CRC32_TABLE: [u32; 256] = reflected_table32(0xEDB8_8320);
+
+const CHECK: &[u8] = b"123456789";
+const _: () = assert!(crc16_ccitt(CHECK) == 0x29B1, "CRC: CRC-16/CCITT-FALSE check value");
+const _: () = assert!(crc16_modbus(CHECK) == 0x4B37, "CRC: CRC-16/MODBUS check value");
+const _: () = assert!(crc32(CHECK) == 0xCBF4_3926, "CRC: CRC-32 check value");
+const _: () = assert!(crc16_ccitt(&[]) == 0xFFFF, "CRC: CRC-16 of nothing");
+const _: () = assert!(crc32(&[]) == 0, "CRC: CRC-32 of nothing");
+const _: () = assert!(
+  crc32_update(crc32(b"1234"), b"56789") == crc32(CHECK),
+  "CRC: CRC-32 in pieces"
+);
+
+/// CRC-16/CCITT-FALSE of `data`.
+pub const fn crc16_ccitt(data: &[u8]) -> u16 {
+  let mut crc = 0xFFFF;
+  let mut i = 0;
+  while i < data.len() {
+    crc = (crc << 8) ^ CCITT_TABLE[((crc >> 8) as u8 ^ data[i]) as usize];
+    i += 1;
+  }
+  crc
+}
+
+/// CRC-16/MODBUS of `data`; sent low byte first.
+pub const fn crc16_modbus(data: &[u8]) -> u16 {
+  let mut crc = 0xFFFF;
+  let mut i = 0;
+  while i < data.len() {
+    crc = (crc >> 8) ^ MODBUS_TABLE[(crc as u8 ^ data[i]) as usize];
+    i += 1;
+  }
+  crc
+}
+
+/// CRC-32 of `data`.
+pub const fn crc32(data: &[u8]) -> u32 {
+  crc32_update(0, data)
+}
+
+/// CRC-32 of data read in pieces: `crc` is the CRC of the pieces before
+/// `data`, `0` for the first.
+pub const fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+  let mut crc = !crc;
+  let mut i = 0;
+  while i < data.len() {
+    crc = (crc >> 8) ^ CRC32_TABLE[(crc as u8 ^ data[i]) as usize];
+    i += 1;
+  }
+  !crc
+}
+
+const fn ccitt_table() -> [u16; 256] {
+  let mut table = [0; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut crc = (i as u16) << 8;
+    let mut bit = 0;
+    while bit < 8 {
+      crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+      bit += 1;
+    }
+    table[i] = crc;
+    i += 1;
+  }
+  table
+}
+
+const fn reflected_table16(poly: u16) -> [u16; 256] {
+  let mut table = [0; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut crc = i as u16;
+    let mut bit = 0;
+    while bit < 8 {
+      crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+      bit += 1;
+    }
+    table[i] = crc;
+    i += 1;
+  }
+  table
+}
+
+const fn reflected_table32(poly: u32) -> [u32; 256] {
+  let mut table = [0; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut crc = i as u32;
+    let mut bit = 0;
+    while bit < 8 {
+      crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+      bit += 1;
+    }
+    table[i] = crc;
+    i += 1;
+  }
+  table
+}
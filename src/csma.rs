@@ -0,0 +1,131 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/csma.rs - 载波侦听随机退避
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Carrier-sense backoff for shared channels.
+//!
+//! Several bridge pairs on one channel without the TDMA schedule (see
+//! [`crate::tdma`]) collide whenever two hosts send at once.
+//! `AT+CSMA=<min ms>,<max ms>` makes every transmission wait a random time
+//! within that window first and then check the channel with channel
+//! activity detection; while a frame is on the air it waits again, up to
+//! [`MAX_ATTEMPTS`] times, and then sends anyway, as plain ALOHA would.
+//! Bridges that drew different waits no longer start together, and the
+//! later one hears the earlier.
+//!
+//! The wait runs in [`radio::transmit_blocking`], so it covers every frame;
+//! CW identification and test carriers are not frames and go straight out.
+//! Where the radio cannot run CAD the channel counts as clear.
+//!
+//! The window is persisted; `AT+CSMA=OFF` turns the backoff off.
+//! `AT+CSMA?` answers `+CSMA:<min ms>,<max ms>,<busy>,<forced>`, or
+//! `+CSMA:OFF,<busy>,<forced>`, with the CAD checks that found the channel
+//! busy and the frames sent on a busy channel since boot.
+//!
+//! [`radio::transmit_blocking`]: crate::radio::transmit_blocking
+
+use core::cell::Cell;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::interrupt::{self, Mutex};
+use heapless::String;
+
+use crate::radio::Radio;
+use crate::random;
+use crate::timer;
+
+/// CAD checks before a frame goes out on a busy channel.
+pub const MAX_ATTEMPTS: u8 = 5;
+/// Longest backoff window accepted.
+pub const MAX_WINDOW_MS: u16 = 2_000;
+
+/// Persisted backoff window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Config {
+  pub min_ms: u16,
+  /// `0` for off.
+  pub max_ms: u16,
+}
+
+impl Config {
+  pub const OFF: Self = Self {
+    min_ms: 0,
+    max_ms: 0,
+  };
+
+  /// Accept `min_ms <= max_ms <= MAX_WINDOW_MS`.
+  pub fn new(min_ms: u16, max_ms: u16) -> Option<Self> {
+    (min_ms <= max_ms && max_ms <= MAX_WINDOW_MS).then_some(Self { min_ms, max_ms })
+  }
+
+  pub fn enabled(&self) -> bool {
+    self.max_ms > 0
+  }
+}
+
+static CONFIG: Mutex<Cell<Config>> = Mutex::new(Cell::new(Config::OFF));
+/// CAD checks that found the channel busy.
+static BUSY: AtomicU32 = AtomicU32::new(0);
+/// Frames sent after [`MAX_ATTEMPTS`] busy checks.
+static FORCED: AtomicU32 = AtomicU32::new(0);
+
+/// Use `config` from the next transmission on.
+pub fn set(config: Config) {
+  defmt::info!("[csma] backoff {}", config);
+  interrupt::free(|cs| CONFIG.borrow(cs).set(config));
+}
+
+fn config() -> Config {
+  interrupt::free(|cs| CONFIG.borrow(cs).get())
+}
+
+/// Wait out the backoff before a transmission.  The radio is left in
+/// standby after a clear CAD, or listening when the backoff is off.
+pub fn wait(radio: &mut impl Radio) {
+  let config = config();
+  if !config.enabled() {
+    return;
+  }
+  for _ in 0..MAX_ATTEMPTS {
+    let spread = (config.max_ms - config.min_ms) as u32 + 1;
+    let wait_ms = config.min_ms as u32 + random::random_u32() % spread;
+    let start = timer::now_ms();
+    while timer::elapsed_ms(start) < wait_ms {}
+    if radio.channel_active() != Some(true) {
+      return;
+    }
+    BUSY.fetch_add(1, Ordering::Relaxed);
+    // Let the frame on the air be received meanwhile.
+    radio.start_rx();
+  }
+  FORCED.fetch_add(1, Ordering::Relaxed);
+  defmt::warn!("[csma] channel still busy, sending anyway");
+}
+
+/// `+CSMA:<min ms>,<max ms>,<busy>,<forced>` or `+CSMA:OFF,<busy>,<forced>`.
+pub fn report() -> String<48> {
+  let config = config();
+  let mut line = String::new();
+  let _ = if config.enabled() {
+    write!(line, "+CSMA:{},{},", config.min_ms, config.max_ms)
+  } else {
+    write!(line, "+CSMA:OFF,")
+  };
+  let _ = write!(
+    line,
+    "{},{}\r\n",
+    BUSY.load(Ordering::Relaxed),
+    FORCED.load(Ordering::Relaxed)
+  );
+  line
+}
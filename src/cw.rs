@@ -0,0 +1,174 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/cw.rs - 摩尔斯电码呼号信标
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! CW station identification.
+//!
+//! Amateur use of the 70 cm band requires the station to identify itself at
+//! regular intervals, which LoRa frames do not do in a form anyone can
+//! copy.  With a callsign and interval set (`AT+CWID=<callsign>,<s>`), the
+//! bridge keys the callsign in Morse on the link frequency every `interval`
+//! seconds, using the unmodulated carrier of [`Radio::carrier`].
+//!
+//! Keying runs at [`WPM`] words per minute (PARIS timing) and blocks the
+//! main loop while it lasts, a few seconds for a typical callsign.  The
+//! beacon waits for the radio to be free of pairing and scans, and the
+//! LoRa configuration is restored afterwards.
+
+use crate::radio::Radio;
+use crate::{led, timer};
+
+/// Longest callsign, suffixes such as `/P` included.
+pub const CALLSIGN_MAX: usize = 10;
+/// Keying speed.
+pub const WPM: u32 = 20;
+/// Length of a dot; dashes and gaps are multiples of it.
+const DOT_MS: u32 = 1_200 / WPM;
+/// Shortest interval accepted, so the beacon cannot hog the channel.
+pub const MIN_INTERVAL_S: u16 = 60;
+
+/// Persisted beacon configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Beacon {
+  callsign: [u8; CALLSIGN_MAX],
+  len: u8,
+  /// Seconds between identifications, `0` for off.
+  pub interval_s: u16,
+}
+
+impl Default for Beacon {
+  fn default() -> Self {
+    Self {
+      callsign: [0; CALLSIGN_MAX],
+      len: 0,
+      interval_s: 0,
+    }
+  }
+}
+
+impl Beacon {
+  /// Accept callsigns of letters, digits and `/` that Morse can send,
+  /// folding lower case to upper case, and intervals of `0` or at least
+  /// [`MIN_INTERVAL_S`].
+  pub fn new(callsign: &[u8], interval_s: u16) -> Option<Self> {
+    if callsign.len() > CALLSIGN_MAX
+      || (interval_s != 0 && (callsign.is_empty() || interval_s < MIN_INTERVAL_S))
+    {
+      return None;
+    }
+    let mut beacon = Self {
+      len: callsign.len() as u8,
+      interval_s,
+      ..Self::default()
+    };
+    for (slot, &byte) in beacon.callsign.iter_mut().zip(callsign) {
+      *slot = byte.to_ascii_uppercase();
+      morse(*slot)?;
+    }
+    Some(beacon)
+  }
+
+  pub fn callsign(&self) -> &[u8] {
+    &self.callsign[..self.len as usize]
+  }
+
+  pub fn enabled(&self) -> bool {
+    self.interval_s != 0 && self.len != 0
+  }
+
+  /// Whether an identification is due, `last_ms` being the previous one.
+  pub fn due(&self, last_ms: u32) -> bool {
+    self.enabled() && timer::elapsed_ms(last_ms) >= self.interval_s as u32 * 1_000
+  }
+}
+
+/// Dots and dashes of a character, or `None` if Morse has no code for it.
+fn morse(byte: u8) -> Option<&'static [u8]> {
+  let code: &[u8] = match byte {
+    b'A' => b".-",
+    b'B' => b"-...",
+    b'C' => b"-.-.",
+    b'D' => b"-..",
+    b'E' => b".",
+    b'F' => b"..-.",
+    b'G' => b"--.",
+    b'H' => b"....",
+    b'I' => b"..",
+    b'J' => b".---",
+    b'K' => b"-.-",
+    b'L' => b".-..",
+    b'M' => b"--",
+    b'N' => b"-.",
+    b'O' => b"---",
+    b'P' => b".--.",
+    b'Q' => b"--.-",
+    b'R' => b".-.",
+    b'S' => b"...",
+    b'T' => b"-",
+    b'U' => b"..-",
+    b'V' => b"...-",
+    b'W' => b".--",
+    b'X' => b"-..-",
+    b'Y' => b"-.--",
+    b'Z' => b"--..",
+    b'0' => b"-----",
+    b'1' => b".----",
+    b'2' => b"..---",
+    b'3' => b"...--",
+    b'4' => b"....-",
+    b'5' => b".....",
+    b'6' => b"-....",
+    b'7' => b"--...",
+    b'8' => b"---..",
+    b'9' => b"----.",
+    b'/' => b"-..-.",
+    _ => return None,
+  };
+  Some(code)
+}
+
+/// Key the callsign of `beacon` at `frequency_hz`, blocking until it is
+/// done.  The carrier is off and the radio in standby afterwards.
+pub fn transmit(radio: &mut impl Radio, beacon: &Beacon, frequency_hz: u32) -> bool {
+  if !radio.tune(frequency_hz) {
+    return false;
+  }
+  defmt::info!("[cw] ID {=[u8]:a} at {} Hz", beacon.callsign(), frequency_hz);
+  led::set_transmitting(true);
+  let mut keyed = true;
+  'callsign: for (index, &byte) in beacon.callsign().iter().enumerate() {
+    // Three dots between characters, one between elements.
+    if index > 0 {
+      wait_ms(2 * DOT_MS);
+    }
+    for &element in morse(byte).unwrap_or_default() {
+      if !radio.carrier(true) {
+        keyed = false;
+        break 'callsign;
+      }
+      wait_ms(if element == b'-' { 3 * DOT_MS } else { DOT_MS });
+      radio.carrier(false);
+      wait_ms(DOT_MS);
+    }
+  }
+  let off = radio.carrier(false);
+  led::set_transmitting(false);
+  if !keyed {
+    defmt::warn!("[cw] carrier not available");
+  }
+  keyed && off
+}
+
+fn wait_ms(ms: u32) {
+  let start = timer::now_ms();
+  while timer::elapsed_ms(start) < ms {}
+}
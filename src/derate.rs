@@ -0,0 +1,199 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/derate.rs - 功放保护与发射功率降额
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Power amplifier protection.
+//!
+//! At 1 W an E22-x00M30S draws over half an amp and turns most of it into
+//! heat.  Sustained, that browns out a weak supply or overheats the module,
+//! so the chip output power is capped at [`DERATED_POWER_DBM`] while any of
+//! these holds:
+//!
+//! * **Duty cycle.**  Airtime at [`HIGH_POWER_DBM`] or more fills a bucket
+//!   that drains at [`MAX_DUTY_PERCENT`] of the time passing, so that duty
+//!   cycle can be kept up forever.  A full bucket, [`BURST_MS`] of airtime
+//!   beyond it, caps the power until the bucket is half empty again;
+//!   derated airtime does not count.
+//! * **Supply.**  The MCU rail, measured against the internal reference,
+//!   below [`MIN_VDD_MV`] means the regulator is dropping out under load.
+//!   The cap is lifted [`SUPPLY_HYSTERESIS_MV`] above it.
+//! * **Battery.**  The battery is low (see [`crate::battery`]).
+//! * **Image.**  The firmware image failed its CRC32 check at boot (see
+//!   [`crate::version`]); a partly flashed image must not drive the PA at
+//!   full power.  This one is never lifted.
+//!
+//! The supply is sampled every [`SAMPLE_MS`] between transmissions.  Every
+//! change is logged, shown as an OLED notice and reported on the control
+//! port as `+DERATE:<dBm>,<reason>` or `+DERATE:OFF`.  The configured
+//! power is kept and comes back once the condition is gone.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+/// Chip output power counted as high, about 28 dBm out of the module.
+pub const HIGH_POWER_DBM: i8 = 20;
+/// Chip output power while derated.
+pub const DERATED_POWER_DBM: i8 = 14;
+/// High-power duty cycle that can be kept up.
+pub const MAX_DUTY_PERCENT: u32 = 20;
+/// High-power airtime beyond the duty cycle before derating.
+pub const BURST_MS: u32 = 12_000;
+/// MCU rail below which the supply counts as sagging.
+pub const MIN_VDD_MV: u16 = 3_100;
+pub const SUPPLY_HYSTERESIS_MV: u16 = 100;
+/// Interval between supply samples.
+pub const SAMPLE_MS: u32 = 1_000;
+
+/// Why the power is capped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Reason {
+  DutyCycle,
+  Supply,
+  Battery,
+  Image,
+}
+
+impl Reason {
+  pub fn name(self) -> &'static str {
+    match self {
+      Reason::DutyCycle => "DUTY",
+      Reason::Supply => "SUPPLY",
+      Reason::Battery => "BATTERY",
+      Reason::Image => "IMAGE",
+    }
+  }
+}
+
+/// Duty-cycle bucket and supply state.
+pub struct Guard {
+  /// High-power airtime not yet drained.
+  heat_ms: u32,
+  hot: bool,
+  sagging: bool,
+  battery_low: bool,
+  image_bad: bool,
+  /// Radio airtime total at the last update.
+  tx_time_ms: u32,
+  last_ms: u32,
+  last_sample_ms: Option<u32>,
+  reason: Option<Reason>,
+}
+
+impl Guard {
+  pub fn new(now: u32) -> Self {
+    Self {
+      heat_ms: 0,
+      hot: false,
+      sagging: false,
+      battery_low: false,
+      image_bad: false,
+      tx_time_ms: crate::radio::tx_time_ms(),
+      last_ms: now,
+      last_sample_ms: None,
+      reason: None,
+    }
+  }
+
+  /// Account the airtime since the last call, sent at `power_dbm`.
+  pub fn on_airtime(&mut self, power_dbm: i8, now: u32) {
+    let tx_time_ms = crate::radio::tx_time_ms();
+    let airtime_ms = tx_time_ms.wrapping_sub(self.tx_time_ms);
+    self.tx_time_ms = tx_time_ms;
+    let drained_ms = now.wrapping_sub(self.last_ms) * MAX_DUTY_PERCENT / 100;
+    if drained_ms > 0 {
+      self.last_ms = now;
+    }
+    self.heat_ms = self.heat_ms.saturating_sub(drained_ms);
+    if power_dbm >= HIGH_POWER_DBM {
+      self.heat_ms = self.heat_ms.saturating_add(airtime_ms);
+    }
+    if self.heat_ms >= BURST_MS {
+      self.hot = true;
+    } else if self.heat_ms < BURST_MS / 2 {
+      self.hot = false;
+    }
+  }
+
+  /// Whether the next supply sample is due.
+  pub fn sample_due(&self, now: u32) -> bool {
+    self
+      .last_sample_ms
+      .is_none_or(|last| now.wrapping_sub(last) >= SAMPLE_MS)
+  }
+
+  /// Take a sample of the MCU rail.
+  pub fn on_supply(&mut self, vdd_mv: u16, now: u32) {
+    self.last_sample_ms = Some(now);
+    self.sagging = if self.sagging {
+      vdd_mv < MIN_VDD_MV + SUPPLY_HYSTERESIS_MV
+    } else {
+      vdd_mv < MIN_VDD_MV
+    };
+  }
+
+  pub fn set_battery_low(&mut self, low: bool) {
+    self.battery_low = low;
+  }
+
+  /// Keep the power capped for good: the image failed its check.
+  pub fn set_image_bad(&mut self) {
+    self.image_bad = true;
+  }
+
+  /// Apply the cap the current state calls for.  Returns the new reason
+  /// when it changed; the caller re-applies the radio settings.
+  pub fn update(&mut self) -> Option<Option<Reason>> {
+    let reason = if self.image_bad {
+      Some(Reason::Image)
+    } else if self.battery_low {
+      Some(Reason::Battery)
+    } else if self.sagging {
+      Some(Reason::Supply)
+    } else if self.hot {
+      Some(Reason::DutyCycle)
+    } else {
+      None
+    };
+    if reason == self.reason {
+      return None;
+    }
+    match reason {
+      Some(reason) => {
+        defmt::warn!("[derate] TX power capped at {} dBm: {}", DERATED_POWER_DBM, reason)
+      }
+      None => defmt::info!("[derate] TX power restored"),
+    }
+    self.reason = reason;
+    crate::radio::set_power_cap(reason.map(|_| DERATED_POWER_DBM));
+    Some(reason)
+  }
+
+  /// High-power airtime left before the duty cycle caps the power.
+  pub fn budget_ms(&self) -> u32 {
+    BURST_MS.saturating_sub(self.heat_ms)
+  }
+
+  pub fn reason(&self) -> Option<Reason> {
+    self.reason
+  }
+
+  /// `+DERATE:<dBm>,<reason>` or `+DERATE:OFF`.
+  pub fn report(&self) -> String<32> {
+    let mut line = String::new();
+    let _ = match self.reason {
+      Some(reason) => write!(line, "+DERATE:{},{}\r\n", DERATED_POWER_DBM, reason.name()),
+      None => write!(line, "+DERATE:OFF\r\n"),
+    };
+    line
+  }
+}
@@ -0,0 +1,60 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/device_id.rs - 芯片唯一 ID
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Identity derived from the STM32 96-bit unique device ID.
+//!
+//! The UID is used for the USB serial number (so several bridges on one host
+//! enumerate distinctly) and to seed the default node address.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+/// Address of the 96-bit unique device ID.
+const UID_BASE: *const u32 = crate::hal::UID_BASE as *const u32;
+
+/// Length of the UID rendered as upper-case hex.
+pub const SERIAL_LEN: usize = 24;
+
+/// Read the three UID words.
+pub fn uid() -> [u32; 3] {
+  // The UID is factory-programmed, always readable and never changes.
+  unsafe {
+    [
+      UID_BASE.read_volatile(),
+      UID_BASE.add(1).read_volatile(),
+      UID_BASE.add(2).read_volatile(),
+    ]
+  }
+}
+
+/// The UID as 24 upper-case hex digits, most significant word first.
+pub fn serial_string() -> String<SERIAL_LEN> {
+  let [w0, w1, w2] = uid();
+  let mut serial = String::new();
+  let _ = write!(serial, "{:08X}{:08X}{:08X}", w2, w1, w0);
+  serial
+}
+
+/// Node address derived from the UID.
+///
+/// The 96 bits are folded to 16; the reserved values `0x0000` and `0xFFFF`
+/// are never returned.
+pub fn default_node_address() -> u16 {
+  let folded = uid().iter().fold(0u32, |acc, word| acc ^ word);
+  match ((folded >> 16) ^ folded) as u16 {
+    0x0000 => 0x0001,
+    0xFFFF => 0xFFFE,
+    address => address,
+  }
+}
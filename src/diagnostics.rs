@@ -16,44 +16,148 @@
 //! All output is emitted through `defmt` and is only visible when a
 //! probe-rs / RTT session is active.  The functions are thin wrappers so
 //! that call-sites stay readable.
+//!
+//! Key events (boot, TX/RX, errors) are additionally mirrored as text lines
+//! to the USB log/control port when the host enables log mode with
+//! `AT+LOG=1`, and to the SD card when one is logging (see
+//! [`crate::sd_log`]).  The bridge data port never carries log output.
+//!
+//! Each message is only emitted when its category logs at its level; see
+//! [`crate::log_control`].  Counters count regardless, and boot stages,
+//! errors and frames also go to the post-mortem ring of
+//! [`crate::event_log`].
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use cortex_m::interrupt::Mutex;
+use heapless::{Deque, String};
+
+use crate::event_log;
+use crate::log_control::{self, Category, Level};
+
+// defmt timestamps come from the SysTick millisecond counter.
+defmt::timestamp!("{=u32:ms}", crate::timer::now_ms());
+
+/// Longest mirrored log line, including the trailing CR/LF.
+const USB_LOG_LINE_MAX: usize = 64;
+/// Bytes of pending log text kept for the USB port.
+pub const USB_LOG_CAPACITY: usize = 256;
+
+static USB_LOG_ENABLED: AtomicBool = AtomicBool::new(false);
+static CARD_LOG_ENABLED: AtomicBool = AtomicBool::new(false);
+static USB_LOG_DROPPED: AtomicU32 = AtomicU32::new(0);
+static USB_LOG_QUEUE: Mutex<RefCell<Deque<u8, USB_LOG_CAPACITY>>> =
+  Mutex::new(RefCell::new(Deque::new()));
+
+static LORA_TX_FRAMES: AtomicU32 = AtomicU32::new(0);
+static LORA_RX_FRAMES: AtomicU32 = AtomicU32::new(0);
+static ERRORS: AtomicU32 = AtomicU32::new(0);
+static RELAYED: AtomicU32 = AtomicU32::new(0);
+static RELAY_SUPPRESSED: AtomicU32 = AtomicU32::new(0);
+static RELAY_DROPPED: AtomicU32 = AtomicU32::new(0);
+static DUPLICATES: AtomicU32 = AtomicU32::new(0);
+static CRC_ERRORS: AtomicU32 = AtomicU32::new(0);
 
-// Simple incrementing defmt timestamp (replace with a hardware timer for
-// accurate timing).
-defmt::timestamp!("{=u32:us}", {
-  static mut TIMESTAMP: u32 = 0;
-  unsafe {
-    TIMESTAMP = TIMESTAMP.wrapping_add(1);
-    TIMESTAMP
+/// Event totals since boot, for the status display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Counters {
+  pub lora_tx: u32,
+  pub lora_rx: u32,
+  pub errors: u32,
+}
+
+/// Routing totals since boot, for `AT+MESH?`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Routing {
+  /// Frames relayed for other nodes.
+  pub relayed: u32,
+  /// Held frames dropped because another node relayed them first.
+  pub suppressed: u32,
+  /// Frames that could not be relayed (busy channel, another frame held).
+  pub dropped: u32,
+  /// Second copies of frames already heard.
+  pub duplicates: u32,
+}
+
+/// Format a line and queue it if log mode is enabled or a card logs.
+fn mirror(args: fmt::Arguments) {
+  if !USB_LOG_ENABLED.load(Ordering::Relaxed) && !CARD_LOG_ENABLED.load(Ordering::Relaxed) {
+    return;
   }
-});
+
+  let mut line = String::<USB_LOG_LINE_MAX>::new();
+  // A line that does not fit is truncated rather than dropped.
+  let _ = line.write_fmt(args);
+  while line.len() > USB_LOG_LINE_MAX - 2 {
+    line.pop();
+  }
+  let _ = line.push_str("\r\n");
+  let text = line.as_bytes();
+
+  // Only whole lines are queued so the host never sees a torn line.
+  let queued = cortex_m::interrupt::free(|cs| {
+    let mut queue = USB_LOG_QUEUE.borrow(cs).borrow_mut();
+    if USB_LOG_CAPACITY - queue.len() < text.len() {
+      return false;
+    }
+    for &byte in text {
+      let _ = queue.push_back(byte);
+    }
+    true
+  });
+
+  if !queued {
+    USB_LOG_DROPPED.fetch_add(1, Ordering::Relaxed);
+  }
+}
 
 pub struct BlueHighDiagnostics;
 
 #[allow(dead_code)]
 impl BlueHighDiagnostics {
   /// Emit a boot-sequence step message.
-  pub fn boot_sequence(stage: &str) {
+  pub fn boot_sequence(stage: &'static str) {
+    event_log::stage(stage);
+    if !log_control::enabled(Category::Boot, Level::Info) {
+      return;
+    }
     defmt::println!("[boot] {}", stage);
+    mirror(format_args!("[boot] {}", stage));
   }
 
   /// Emit a clock-configuration summary.
   pub fn clocks_configured(sys_mhz: u32, apb1_mhz: u32) {
+    if !log_control::enabled(Category::Boot, Level::Info) {
+      return;
+    }
     defmt::println!("[clk] sys={}MHz apb1={}MHz", sys_mhz, apb1_mhz);
   }
 
   /// Emit an OLED status message.
   pub fn oled_status(message: &str) {
+    if !log_control::enabled(Category::Ui, Level::Info) {
+      return;
+    }
     defmt::println!("[oled] {}", message);
   }
 
   /// Emit a USB-RX byte count (USB → LoRa direction).
   pub fn usb_bridge_rx(byte_count: usize) {
+    if !log_control::enabled(Category::Usb, Level::Info) {
+      return;
+    }
     defmt::println!("[usb-rx] {} bytes", byte_count);
+    mirror(format_args!("[usb-rx] {} bytes", byte_count));
   }
 
   /// Dump received USB data as hex + printable ASCII.
   pub fn usb_data_received(data: &[u8]) {
     const CHUNK: usize = 16;
+    if !log_control::enabled(Category::Usb, Level::Debug) {
+      return;
+    }
     let len = data.len();
     defmt::println!("[usb-rx] {} bytes --", len);
 
@@ -87,33 +191,185 @@ impl BlueHighDiagnostics {
 
   /// Emit a LoRa-TX byte count (LoRa → USB direction).
   pub fn usb_bridge_tx(byte_count: usize) {
+    LORA_TX_FRAMES.fetch_add(1, Ordering::Relaxed);
+    event_log::tx(byte_count);
+    if !log_control::enabled(Category::Radio, Level::Info) {
+      return;
+    }
     defmt::println!("[lora-tx] {} bytes", byte_count);
+    mirror(format_args!("[lora-tx] {} bytes", byte_count));
+  }
+
+  /// Emit a LoRa-RX byte count (LoRa → USB direction).
+  pub fn lora_rx(byte_count: usize) {
+    LORA_RX_FRAMES.fetch_add(1, Ordering::Relaxed);
+    event_log::rx(byte_count);
+    crate::led::received();
+    if !log_control::enabled(Category::Radio, Level::Info) {
+      return;
+    }
+    defmt::println!("[lora-rx] {} bytes", byte_count);
+    mirror(format_args!("[lora-rx] {} bytes", byte_count));
   }
 
   /// Log an SX1268 reset event.
   pub fn e22_reset() {
+    if !log_control::enabled(Category::Radio, Level::Info) {
+      return;
+    }
     defmt::println!("[e22] reset");
   }
 
   /// Log an SPI transfer byte count.
   pub fn e22_spi_transfer(bytes: usize) {
+    if !log_control::enabled(Category::Radio, Level::Debug) {
+      return;
+    }
     defmt::println!("[spi] {} bytes", bytes);
   }
 
   /// Log an NSS (chip-select) state change.
   pub fn spi_chip_select(active: bool) {
+    if !log_control::enabled(Category::Radio, Level::Debug) {
+      return;
+    }
     defmt::println!("[nss] {}", if active { "assert" } else { "deassert" });
   }
 
-  /// Log an error with caller-supplied context string.
-  pub fn error_occurred(context: &str) {
+  /// Log an error in `category` with caller-supplied context string.
+  pub fn error_occurred(category: Category, context: &'static str) {
+    ERRORS.fetch_add(1, Ordering::Relaxed);
+    event_log::error(category, context);
+    crate::led::error();
+    if !log_control::enabled(category, Level::Error) {
+      return;
+    }
     defmt::println!("[error] {}", context);
+    mirror(format_args!("[error] {}", context));
+  }
+
+  /// Frames bridged and errors logged since boot.
+  pub fn counters() -> Counters {
+    Counters {
+      lora_tx: LORA_TX_FRAMES.load(Ordering::Relaxed),
+      lora_rx: LORA_RX_FRAMES.load(Ordering::Relaxed),
+      errors: ERRORS.load(Ordering::Relaxed),
+    }
+  }
+
+  /// Log a frame that failed its CRC.  Only counted where such frames are
+  /// read at all: in packet capture and with `AT+CRCPASS=1`.
+  pub fn crc_error(len: usize) {
+    CRC_ERRORS.fetch_add(1, Ordering::Relaxed);
+    event_log::crc_error(len);
+    if !log_control::enabled(Category::Radio, Level::Warn) {
+      return;
+    }
+    defmt::warn!("[lora-rx] {} bytes with bad CRC", len);
+    mirror(format_args!("[lora-rx] {} bytes with bad CRC", len));
+  }
+
+  /// Frames with a bad CRC since boot.
+  pub fn crc_errors() -> u32 {
+    CRC_ERRORS.load(Ordering::Relaxed)
+  }
+
+  /// Log a frame relayed for another node.
+  pub fn frame_relayed(src: u16, seq: u8) {
+    RELAYED.fetch_add(1, Ordering::Relaxed);
+    if !log_control::enabled(Category::Proto, Level::Info) {
+      return;
+    }
+    defmt::println!("[relay] relayed {} from 0x{:04X}", seq, src);
+    mirror(format_args!("[relay] relayed {} from 0x{:04X}", seq, src));
+  }
+
+  /// Log a held frame dropped because a neighbour relayed it.
+  pub fn relay_suppressed(src: u16, seq: u8) {
+    RELAY_SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+    if !log_control::enabled(Category::Proto, Level::Debug) {
+      return;
+    }
+    defmt::println!("[relay] {} from 0x{:04X} already relayed", seq, src);
+  }
+
+  /// Log a frame that could not be relayed.
+  pub fn relay_dropped(reason: &str) {
+    RELAY_DROPPED.fetch_add(1, Ordering::Relaxed);
+    if !log_control::enabled(Category::Proto, Level::Warn) {
+      return;
+    }
+    defmt::println!("[relay] dropped: {}", reason);
+    mirror(format_args!("[relay] dropped: {}", reason));
+  }
+
+  /// Log a second copy of a frame.
+  pub fn duplicate_dropped(src: u16, seq: u8) {
+    DUPLICATES.fetch_add(1, Ordering::Relaxed);
+    if !log_control::enabled(Category::Proto, Level::Debug) {
+      return;
+    }
+    defmt::println!("[relay] duplicate {} from 0x{:04X}", seq, src);
+  }
+
+  /// Routing totals since boot.
+  pub fn routing() -> Routing {
+    Routing {
+      relayed: RELAYED.load(Ordering::Relaxed),
+      suppressed: RELAY_SUPPRESSED.load(Ordering::Relaxed),
+      dropped: RELAY_DROPPED.load(Ordering::Relaxed),
+      duplicates: DUPLICATES.load(Ordering::Relaxed),
+    }
   }
 
   /// Emit a periodic heartbeat log (every 1000 iterations).
   pub fn heartbeat(loop_count: u32) {
-    if loop_count.is_multiple_of(1000) {
+    if loop_count.is_multiple_of(1000) && log_control::enabled(Category::Boot, Level::Debug) {
       defmt::println!("[heartbeat] count={}", loop_count);
     }
   }
+
+  /// Enable or disable mirroring of log lines to the USB port.
+  pub fn set_usb_log(enabled: bool) {
+    USB_LOG_ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled && !CARD_LOG_ENABLED.load(Ordering::Relaxed) {
+      cortex_m::interrupt::free(|cs| USB_LOG_QUEUE.borrow(cs).borrow_mut().clear());
+    }
+    if log_control::enabled(Category::Usb, Level::Info) {
+      defmt::println!("[log] usb mirror {}", if enabled { "on" } else { "off" });
+    }
+  }
+
+  /// Queue log lines for the SD card as well; the main loop drains them.
+  pub fn set_card_log(enabled: bool) {
+    CARD_LOG_ENABLED.store(enabled, Ordering::Relaxed);
+  }
+
+  /// Whether log lines are being mirrored to the USB port.
+  pub fn usb_log_enabled() -> bool {
+    USB_LOG_ENABLED.load(Ordering::Relaxed)
+  }
+
+  /// Number of log lines dropped because the USB queue was full.
+  pub fn usb_log_dropped() -> u32 {
+    USB_LOG_DROPPED.load(Ordering::Relaxed)
+  }
+
+  /// Move pending log text into `out`, returning the number of bytes.
+  pub fn drain_usb_log(out: &mut [u8]) -> usize {
+    cortex_m::interrupt::free(|cs| {
+      let mut queue = USB_LOG_QUEUE.borrow(cs).borrow_mut();
+      let mut n = 0;
+      while n < out.len() {
+        match queue.pop_front() {
+          Some(byte) => {
+            out[n] = byte;
+            n += 1;
+          }
+          None => break,
+        }
+      }
+      n
+    })
+  }
 }
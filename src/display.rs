@@ -0,0 +1,183 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/display.rs - OLED 屏幕驱动抽象
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! OLED panel selection.
+//!
+//! The panel on I2C2 is chosen at build time:
+//!
+//! | feature          | panel                              |
+//! |------------------|------------------------------------|
+//! | (none)           | SSD1306 128x64 (0.96")             |
+//! | `ssd1306-128x32` | SSD1306 128x32 (0.91")             |
+//! | `sh1106`         | SH1106 128x64 (1.3")               |
+//! | `no-display`     | none; I2C2 (PB10/PB11) is left free |
+//!
+//! Every driver is wrapped in [`Panel`], which adds the few operations the
+//! firmware needs on top of drawing, and the UI lays itself out from
+//! [`WIDTH`] and [`HEIGHT`].  Without a display the UI draws into a panel
+//! that discards everything.
+//!
+//! `init` returns the panel together with whether it answered; a panel
+//! that did not is still returned so the UI can draw, but is never sent
+//! anything (see [`crate::startup`]).
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+
+#[cfg(all(feature = "sh1106", feature = "ssd1306-128x32"))]
+compile_error!("features `sh1106` and `ssd1306-128x32` select different panels");
+#[cfg(all(feature = "no-display", any(feature = "sh1106", feature = "ssd1306-128x32")))]
+compile_error!("feature `no-display` excludes the panel features");
+
+pub const WIDTH: u32 = 128;
+#[cfg(feature = "ssd1306-128x32")]
+pub const HEIGHT: u32 = 32;
+#[cfg(not(feature = "ssd1306-128x32"))]
+pub const HEIGHT: u32 = 64;
+
+/// The bus the panel is attached to, shared with the sensors.
+#[cfg(not(feature = "no-display"))]
+pub type I2c = crate::i2c_bus::Shared;
+
+/// Panel operations besides drawing into the frame buffer.
+pub trait Panel: DrawTarget<Color = BinaryColor> {
+  /// Send the changed part of the frame buffer to the panel.
+  fn present(&mut self);
+  /// Switch the panel on or off; the frame buffer is kept.
+  fn power(&mut self, on: bool);
+  fn contrast(&mut self, contrast: u8);
+}
+
+pub use driver::{Display, NAME, init};
+
+#[cfg(not(any(feature = "sh1106", feature = "no-display")))]
+mod driver {
+  use ssd1306::{I2CDisplayInterface, Ssd1306, mode::BufferedGraphicsMode, prelude::*};
+
+  use super::{I2c, Panel};
+
+  #[cfg(feature = "ssd1306-128x32")]
+  type Size = DisplaySize128x32;
+  #[cfg(feature = "ssd1306-128x32")]
+  const SIZE: Size = DisplaySize128x32;
+  #[cfg(feature = "ssd1306-128x32")]
+  pub const NAME: &str = "SSD1306 128x32";
+
+  #[cfg(not(feature = "ssd1306-128x32"))]
+  type Size = DisplaySize128x64;
+  #[cfg(not(feature = "ssd1306-128x32"))]
+  const SIZE: Size = DisplaySize128x64;
+  #[cfg(not(feature = "ssd1306-128x32"))]
+  pub const NAME: &str = "SSD1306 128x64";
+
+  pub type Display = Ssd1306<I2CInterface<I2c>, Size, BufferedGraphicsMode<Size>>;
+
+  pub fn init(i2c: I2c) -> (Display, bool) {
+    let mut display = Ssd1306::new(I2CDisplayInterface::new(i2c), SIZE, DisplayRotation::Rotate0)
+      .into_buffered_graphics_mode();
+    let ready = display.init().is_ok();
+    (display, ready)
+  }
+
+  impl Panel for Display {
+    fn present(&mut self) {
+      let _ = self.flush();
+    }
+
+    fn power(&mut self, on: bool) {
+      let _ = self.set_display_on(on);
+    }
+
+    fn contrast(&mut self, contrast: u8) {
+      let _ = self.set_brightness(Brightness::custom(2, contrast));
+    }
+  }
+}
+
+#[cfg(feature = "sh1106")]
+mod driver {
+  use sh1106::{Builder, displaysize::DisplaySize, interface::I2cInterface, mode::GraphicsMode};
+
+  use super::{I2c, Panel};
+
+  pub const NAME: &str = "SH1106 128x64";
+
+  pub type Display = GraphicsMode<I2cInterface<I2c>>;
+
+  pub fn init(i2c: I2c) -> (Display, bool) {
+    let mut display: Display = Builder::new()
+      .with_size(DisplaySize::Display128x64)
+      .connect_i2c(i2c)
+      .into();
+    let ready = display.init().is_ok();
+    (display, ready)
+  }
+
+  impl Panel for Display {
+    fn present(&mut self) {
+      let _ = self.flush();
+    }
+
+    fn power(&mut self, on: bool) {
+      let _ = self.display_on(on);
+    }
+
+    fn contrast(&mut self, contrast: u8) {
+      let _ = self.set_contrast(contrast);
+    }
+  }
+}
+
+#[cfg(feature = "no-display")]
+mod driver {
+  use core::convert::Infallible;
+
+  use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+  use super::{HEIGHT, Panel, WIDTH};
+
+  pub const NAME: &str = "none";
+
+  /// Stand-in panel that discards everything drawn.
+  pub struct Display;
+
+  pub fn init() -> (Display, bool) {
+    (Display, true)
+  }
+
+  impl OriginDimensions for Display {
+    fn size(&self) -> Size {
+      Size::new(WIDTH, HEIGHT)
+    }
+  }
+
+  impl DrawTarget for Display {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, _pixels: I) -> Result<(), Self::Error>
+    where
+      I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+      Ok(())
+    }
+  }
+
+  impl Panel for Display {
+    fn present(&mut self) {}
+
+    fn power(&mut self, _on: bool) {}
+
+    fn contrast(&mut self, _contrast: u8) {}
+  }
+}
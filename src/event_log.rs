@@ -0,0 +1,313 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/event_log.rs - 复位后保留的事件环形缓冲
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Post-mortem event ring.
+//!
+//! The last [`CAPACITY`] key events (resets, boot stages, errors, frames
+//! sent and received, faults) are kept in RAM as compact records of a
+//! code, a detail byte, a length and a word, each behind its time in
+//! milliseconds since boot.  The ring lives in a section the runtime does
+//! not zero (like the bootloader request, see [`crate::bootloader`]), so a
+//! soft reset, a watchdog reset or a fault leaves it intact and the events
+//! that led up to it can be read after the restart.  A magic word tells a
+//! ring that survived from the garbage RAM holds after power-up.
+//!
+//! Every boot adds a `BOOT` event with the reset flags of the RCC, which
+//! it then clears.  A hard fault, which includes a panic, adds a `FAULT`
+//! event with the faulting address and resets the MCU instead of hanging.
+//! Error and stage events point at their text in flash; after a firmware
+//! update those texts are gone and show as `-`.
+//!
+//! Events are recorded whatever the log levels (see
+//! [`crate::log_control`]).  `AT+EVENTS?` answers one line per event,
+//! oldest first:
+//!
+//! ```text
+//! +EVENT:<time ms>,BOOT,<reset flags, e.g. PIN|SOFT>
+//! +EVENT:<time ms>,STAGE,<text>
+//! +EVENT:<time ms>,ERROR,<category>,<text>
+//! +EVENT:<time ms>,<TX|RX|CRC>,<bytes>
+//! +EVENT:<time ms>,FAULT,<address hex>
+//! ```
+//!
+//! `AT+EVENTS=CLEAR` empties the ring.
+
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+
+use cortex_m::interrupt;
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::{ExceptionFrame, exception};
+use heapless::String;
+
+use crate::hal::RCC_CSR;
+use crate::log_control::Category;
+use crate::{crc, timer, version};
+
+/// Events kept.
+pub const CAPACITY: usize = 64;
+/// Longest `+EVENT:` line.
+pub const LINE_MAX: usize = 80;
+
+/// Marks a ring that survived a reset.
+const MAGIC: u32 = 0xE7E7_0001;
+/// Reset flags in the top byte of RCC_CSR, and the bit clearing them.
+const CSR_FLAGS_SHIFT: u32 = 24;
+const CSR_RMVF: u32 = 1 << 24;
+const RESET_FLAGS: [(u8, &str); 7] = [
+  (0x02, "BOR"),
+  (0x04, "PIN"),
+  (0x08, "POR"),
+  (0x10, "SOFT"),
+  (0x20, "IWDG"),
+  (0x40, "WWDG"),
+  (0x80, "LPWR"),
+];
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+enum Code {
+  Boot = 1,
+  Stage,
+  Error,
+  Tx,
+  Rx,
+  Crc,
+  Fault,
+}
+
+impl Code {
+  const ALL: [Code; 7] = [
+    Code::Boot,
+    Code::Stage,
+    Code::Error,
+    Code::Tx,
+    Code::Rx,
+    Code::Crc,
+    Code::Fault,
+  ];
+
+  fn from_u8(code: u8) -> Option<Self> {
+    Self::ALL.into_iter().find(|known| *known as u8 == code)
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      Code::Boot => "BOOT",
+      Code::Stage => "STAGE",
+      Code::Error => "ERROR",
+      Code::Tx => "TX",
+      Code::Rx => "RX",
+      Code::Crc => "CRC",
+      Code::Fault => "FAULT",
+    }
+  }
+}
+
+/// One event.  `word` is the address of the text for stages and errors,
+/// `len` its length.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Entry {
+  time_ms: u32,
+  word: u32,
+  len: u16,
+  code: u8,
+  detail: u8,
+}
+
+#[repr(C)]
+struct Ring {
+  magic: u32,
+  /// Which firmware wrote the texts the entries point at.
+  build: u32,
+  /// Index of the oldest entry and number of entries.
+  first: u32,
+  len: u32,
+  entries: [Entry; CAPACITY],
+}
+
+#[unsafe(link_section = ".uninit.EVENT_LOG")]
+static mut RING: MaybeUninit<Ring> = MaybeUninit::uninit();
+
+/// Identifies this build, whose texts the entries may point at.
+fn build() -> u32 {
+  crc::crc32(version::BUILD_TIME.as_bytes()) ^ crc::crc32(version::GIT_HASH.as_bytes())
+}
+
+/// Run `f` on the ring with interrupts disabled.
+fn with_ring<R>(f: impl FnOnce(&mut Ring) -> R) -> R {
+  interrupt::free(|_| {
+    // SAFETY: interrupts are off and the ring is only reached through
+    // here; [`boot`] made it valid before anything was recorded.
+    let ring = unsafe { &mut *(&raw mut RING).cast::<Ring>() };
+    f(ring)
+  })
+}
+
+/// Take over the ring left by the last run, or start one after power-up,
+/// and record the reset.  Must run once, early in [`crate::app::run`].
+pub fn boot() {
+  let csr = RCC_CSR as *mut u32;
+  // SAFETY: RCC_CSR is a device register; setting RMVF only clears the
+  // reset flags, and nothing else writes it while this runs.
+  let flags = unsafe {
+    let value = csr.read_volatile();
+    csr.write_volatile(value | CSR_RMVF);
+    (value >> CSR_FLAGS_SHIFT) as u8
+  };
+
+  let build = build();
+  with_ring(|ring| {
+    // The header may be garbage, so it is read volatile before trusting it.
+    let magic = unsafe { (&raw const ring.magic).read_volatile() };
+    let first = unsafe { (&raw const ring.first).read_volatile() };
+    let len = unsafe { (&raw const ring.len).read_volatile() };
+    if magic != MAGIC || first as usize >= CAPACITY || len as usize > CAPACITY {
+      ring.magic = MAGIC;
+      ring.first = 0;
+      ring.len = 0;
+    } else if ring.build != build {
+      for entry in &mut ring.entries {
+        if matches!(Code::from_u8(entry.code), Some(Code::Stage | Code::Error)) {
+          entry.len = 0;
+          entry.word = 0;
+        }
+      }
+    }
+    ring.build = build;
+  });
+  defmt::info!("[event] reset flags {=u8:02X}", flags);
+  record(Code::Boot, flags, 0, 0);
+}
+
+fn record(code: Code, detail: u8, len: u16, word: u32) {
+  let entry = Entry {
+    time_ms: timer::now_ms(),
+    word,
+    len,
+    code: code as u8,
+    detail,
+  };
+  with_ring(|ring| {
+    let at = (ring.first + ring.len) as usize % CAPACITY;
+    ring.entries[at] = entry;
+    if ring.len as usize == CAPACITY {
+      ring.first = (ring.first + 1) % CAPACITY as u32;
+    } else {
+      ring.len += 1;
+    }
+  });
+}
+
+fn record_text(code: Code, detail: u8, text: &'static str) {
+  let len = text.len().min(u16::MAX as usize) as u16;
+  record(code, detail, len, text.as_ptr() as u32);
+}
+
+/// A boot stage was reached.
+pub fn stage(text: &'static str) {
+  record_text(Code::Stage, 0, text);
+}
+
+/// An error in `category`.
+pub fn error(category: Category, text: &'static str) {
+  record_text(Code::Error, category as u8, text);
+}
+
+/// A frame of `len` bytes was sent.
+pub fn tx(len: usize) {
+  record(Code::Tx, 0, len as u16, 0);
+}
+
+/// A frame of `len` bytes was received.
+pub fn rx(len: usize) {
+  record(Code::Rx, 0, len as u16, 0);
+}
+
+/// A frame of `len` bytes failed its CRC.
+pub fn crc_error(len: usize) {
+  record(Code::Crc, 0, len as u16, 0);
+}
+
+/// Empty the ring.
+pub fn clear() {
+  with_ring(|ring| {
+    ring.first = 0;
+    ring.len = 0;
+  });
+}
+
+/// Number of events kept.
+pub fn len() -> usize {
+  with_ring(|ring| ring.len as usize)
+}
+
+/// The `+EVENT:` line of the `index`th oldest event.
+pub fn line(index: usize) -> Option<String<LINE_MAX>> {
+  let entry = with_ring(|ring| {
+    (index < ring.len as usize).then(|| ring.entries[(ring.first as usize + index) % CAPACITY])
+  })?;
+  let mut line = String::new();
+  let _ = write!(line, "+EVENT:{},", entry.time_ms);
+  let Some(code) = Code::from_u8(entry.code) else {
+    let _ = write!(line, "?{}\r\n", entry.code);
+    return Some(line);
+  };
+  let _ = line.push_str(code.name());
+  let _ = match code {
+    Code::Boot => {
+      let mut separator = ',';
+      for (bit, name) in RESET_FLAGS {
+        if entry.detail & bit != 0 {
+          let _ = line.push(separator);
+          let _ = line.push_str(name);
+          separator = '|';
+        }
+      }
+      Ok(())
+    }
+    Code::Stage => write!(line, ",{}", text(&entry)),
+    Code::Error => {
+      let category = Category::from_index(entry.detail).map_or("?", Category::name);
+      write!(line, ",{},{}", category, text(&entry))
+    }
+    Code::Tx | Code::Rx | Code::Crc => write!(line, ",{}", entry.len),
+    Code::Fault => write!(line, ",{:08X}", entry.word),
+  };
+  let _ = line.push_str("\r\n");
+  Some(line)
+}
+
+/// The text an entry points at, if it lies in this image.
+fn text(entry: &Entry) -> &'static str {
+  let image = version::image().as_ptr_range();
+  let (start, end) = (
+    entry.word as usize,
+    entry.word as usize + entry.len as usize,
+  );
+  if entry.len == 0 || start < image.start as usize || end > image.end as usize {
+    return "-";
+  }
+  // SAFETY: the range lies in the program image in flash.
+  let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, entry.len as usize) };
+  core::str::from_utf8(bytes).unwrap_or("-")
+}
+
+/// Record a fault, a panic included, and reset.
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+  record(Code::Fault, 0, 0, frame.pc());
+  SCB::sys_reset();
+}
@@ -0,0 +1,328 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/flash_log.rs - 外部闪存中的收发记录
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Packet log in the external SPI flash.
+//!
+//! With an external flash fitted (see [`crate::spi_flash`]) and
+//! `AT+FLOG=1`, every frame the bridge receives, and every frame it sends
+//! from its transmit queue or for the host protocol, is kept in the
+//! [`log`](spi_flash::log) partition, which survives a restart.  The
+//! partition is a ring of sectors, each headed by `[b'L'][b'G'][seq u16]`
+//! and holding whole records:
+//!
+//! ```text
+//! [len u8][flags u8][time u32][rssi dBm i16][snr dB i8][frame ...]
+//! ```
+//!
+//! Flag bit 0 marks a transmitted frame, whose RSSI and SNR are zero.
+//! `time` is network time in seconds when bit 1 is set (see
+//! [`crate::timesync`]), else milliseconds since boot.  A
+//! length of `0xFF` is erased flash and ends a sector; frames longer than
+//! [`FRAME_MAX`] are cut.  When the ring is full, the oldest sector is
+//! erased for the next.  At boot the sector with the highest sequence
+//! number is the one being filled.  All integers are little-endian.
+//!
+//! Erasing a sector holds the main loop for up to half a second, about once
+//! per 4 KiB logged.
+//!
+//! * `AT+FLOG=<0|1>` stops or starts logging; persisted;
+//! * `AT+FLOG?` answers `+FLOG:<0|1>,<log KiB>,<used>/<sectors>`, or
+//!   `+FLOG:NONE` without a flash;
+//! * `AT+FLOG=CLEAR` empties the log;
+//! * `AT+FLOG=DUMP` answers `OK`, then one
+//!   `+FLOG:<time>,<RX|TX>,<rssi>,<snr>,<frame hex>` per record, oldest
+//!   first, and `+FLOG:END`; `time` is in ISO 8601 or milliseconds since
+//!   boot.  Nothing is logged while a dump runs.
+
+#![cfg_attr(not(feature = "spi-flash"), allow(dead_code))]
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::radio::PacketStatus;
+use crate::spi_flash::{self, Error, Partition, SECTOR_LEN, Storage};
+use crate::timesync;
+
+/// Longest frame kept.
+pub const FRAME_MAX: usize = 254;
+/// Bytes ahead of the frame in a record.
+const RECORD_HEADER_LEN: u32 = 9;
+const SECTOR_HEADER_LEN: u32 = 4;
+const SECTOR_MAGIC: [u8; 2] = *b"LG";
+const FLAG_TX: u8 = 1 << 0;
+const FLAG_UTC: u8 = 1 << 1;
+/// Length byte of erased flash.
+const ERASED: u8 = 0xFF;
+
+/// Longest dump line: the fields and a full frame in hex.
+pub const LINE_MAX: usize = 56 + 2 * FRAME_MAX;
+
+/// A logged frame; the frame itself is read into a caller's buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Record {
+  /// Milliseconds since boot, or network time in seconds with `utc`.
+  pub time: u32,
+  pub utc: bool,
+  pub tx: bool,
+  /// RSSI and SNR of a received frame.
+  pub quality: PacketStatus,
+  pub len: usize,
+}
+
+impl Record {
+  /// `+FLOG:<time>,<RX|TX>,<rssi>,<snr>,<frame hex>`.
+  pub fn line(&self, frame: &[u8]) -> String<LINE_MAX> {
+    let mut line = String::new();
+    let _ = line.push_str("+FLOG:");
+    let _ = match self.utc {
+      true => timesync::write_iso(&mut line, self.time as u64 * 1_000),
+      false => write!(line, "{}", self.time),
+    };
+    let _ = write!(
+      line,
+      ",{},{},{},",
+      if self.tx { "TX" } else { "RX" },
+      self.quality.rssi_dbm,
+      self.quality.snr_db
+    );
+    for byte in &frame[..self.len] {
+      let _ = write!(line, "{:02X}", byte);
+    }
+    let _ = line.push_str("\r\n");
+    line
+  }
+}
+
+/// Where a dump has got to.
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+  sector: u32,
+  offset: u32,
+  /// Sectors left, the current one included.
+  left: u32,
+}
+
+/// The ring of sectors.
+pub struct Log {
+  area: Partition,
+  /// Sector being filled and the offset of its next record.
+  sector: u32,
+  offset: u32,
+  /// Sequence number of `sector`.
+  seq: u16,
+  /// Sectors holding records.
+  used: u32,
+}
+
+impl Log {
+  /// Find where the log in a chip of `storage` left off.
+  pub fn open(storage: &mut dyn Storage) -> Result<Self, Error> {
+    let mut log = Self {
+      area: spi_flash::log(storage.capacity()),
+      sector: 0,
+      offset: 0,
+      seq: 0,
+      used: 0,
+    };
+    let mut newest: Option<(u32, u16)> = None;
+    for sector in 0..log.area.sectors() {
+      let Some(seq) = log.sector_seq(storage, sector)? else {
+        continue;
+      };
+      log.used += 1;
+      // Sequence numbers wrap; the newest is ahead of all others.
+      if newest.is_none_or(|(_, best)| seq.wrapping_sub(best) < 0x8000) {
+        newest = Some((sector, seq));
+      }
+    }
+    if let Some((sector, seq)) = newest {
+      log.sector = sector;
+      log.seq = seq;
+      log.offset = SECTOR_HEADER_LEN;
+      while let Some(len) = log.record_len(storage, sector, log.offset)? {
+        log.offset += RECORD_HEADER_LEN + len as u32;
+      }
+    }
+    defmt::info!(
+      "[flash_log] {} of {} sectors used",
+      log.used,
+      log.area.sectors()
+    );
+    Ok(log)
+  }
+
+  /// Append a frame, sent or received with `quality` at `time_ms` since
+  /// boot or, if known, `network_ms`.
+  pub fn append(
+    &mut self,
+    storage: &mut dyn Storage,
+    time_ms: u32,
+    network_ms: Option<u64>,
+    tx: bool,
+    quality: Option<PacketStatus>,
+    frame: &[u8],
+  ) -> Result<(), Error> {
+    let frame = &frame[..frame.len().min(FRAME_MAX)];
+    let len = RECORD_HEADER_LEN + frame.len() as u32;
+    if self.used == 0 || self.offset + len > SECTOR_LEN {
+      self.next_sector(storage)?;
+    }
+    let quality = quality.unwrap_or(PacketStatus {
+      rssi_dbm: 0,
+      snr_db: 0,
+    });
+    let mut header = [0u8; RECORD_HEADER_LEN as usize];
+    header[0] = frame.len() as u8;
+    let (time, utc) = match network_ms {
+      Some(network_ms) => ((network_ms / 1_000) as u32, FLAG_UTC),
+      None => (time_ms, 0),
+    };
+    header[1] = (if tx { FLAG_TX } else { 0 }) | utc;
+    header[2..6].copy_from_slice(&time.to_le_bytes());
+    header[6..8].copy_from_slice(&quality.rssi_dbm.to_le_bytes());
+    header[8] = quality.snr_db as u8;
+    let at = self.sector * SECTOR_LEN + self.offset;
+    self.offset += len;
+    self.area.program(storage, at, &header)?;
+    self.area.program(storage, at + RECORD_HEADER_LEN, frame)
+  }
+
+  /// Erase every sector holding records.
+  pub fn clear(&mut self, storage: &mut dyn Storage) -> Result<(), Error> {
+    for sector in 0..self.area.sectors() {
+      if self.sector_seq(storage, sector)?.is_some() {
+        self.area.erase_sector(storage, sector * SECTOR_LEN)?;
+      }
+    }
+    self.sector = 0;
+    self.offset = 0;
+    self.used = 0;
+    defmt::info!("[flash_log] cleared");
+    Ok(())
+  }
+
+  /// A cursor at the oldest record.
+  pub fn dump(&self) -> Cursor {
+    let sectors = self.area.sectors();
+    Cursor {
+      sector: (self.sector + sectors + 1 - self.used.max(1)) % sectors,
+      offset: SECTOR_HEADER_LEN,
+      left: self.used,
+    }
+  }
+
+  /// The record at `cursor`, its frame read into `frame`, and advance;
+  /// `None` after the newest.
+  pub fn next(
+    &self,
+    storage: &mut dyn Storage,
+    cursor: &mut Cursor,
+    frame: &mut [u8; FRAME_MAX],
+  ) -> Result<Option<Record>, Error> {
+    while cursor.left > 0 {
+      if self
+        .record_len(storage, cursor.sector, cursor.offset)?
+        .is_none()
+      {
+        cursor.sector = (cursor.sector + 1) % self.area.sectors();
+        cursor.offset = SECTOR_HEADER_LEN;
+        cursor.left -= 1;
+        continue;
+      }
+      let at = cursor.sector * SECTOR_LEN + cursor.offset;
+      let mut header = [0u8; RECORD_HEADER_LEN as usize];
+      self.area.read(storage, at, &mut header)?;
+      let len = header[0] as usize;
+      self
+        .area
+        .read(storage, at + RECORD_HEADER_LEN, &mut frame[..len])?;
+      cursor.offset += RECORD_HEADER_LEN + len as u32;
+      return Ok(Some(Record {
+        time: u32::from_le_bytes([header[2], header[3], header[4], header[5]]),
+        utc: header[1] & FLAG_UTC != 0,
+        tx: header[1] & FLAG_TX != 0,
+        quality: PacketStatus {
+          rssi_dbm: i16::from_le_bytes([header[6], header[7]]),
+          snr_db: header[8] as i8,
+        },
+        len,
+      }));
+    }
+    Ok(None)
+  }
+
+  /// `+FLOG:<0|1>,<log KiB>,<used>/<sectors>`.
+  pub fn report(&self, enabled: bool) -> String<40> {
+    let mut line = String::new();
+    let _ = write!(
+      line,
+      "+FLOG:{},{},{}/{}\r\n",
+      enabled as u8,
+      self.area.len / 1024,
+      self.used,
+      self.area.sectors()
+    );
+    line
+  }
+
+  /// Start the sector after the current one, erasing the oldest when the
+  /// ring is full.
+  fn next_sector(&mut self, storage: &mut dyn Storage) -> Result<(), Error> {
+    let sectors = self.area.sectors();
+    if self.used > 0 {
+      self.sector = (self.sector + 1) % sectors;
+    }
+    self.seq = self.seq.wrapping_add(1);
+    // Out of step until the header is written: a failure starts over.
+    self.offset = SECTOR_LEN;
+    self.area.erase_sector(storage, self.sector * SECTOR_LEN)?;
+    let [low, high] = self.seq.to_le_bytes();
+    self.area.program(
+      storage,
+      self.sector * SECTOR_LEN,
+      &[SECTOR_MAGIC[0], SECTOR_MAGIC[1], low, high],
+    )?;
+    self.used = (self.used + 1).min(sectors);
+    self.offset = SECTOR_HEADER_LEN;
+    Ok(())
+  }
+
+  /// Sequence number of a sector holding records.
+  fn sector_seq(&self, storage: &mut dyn Storage, sector: u32) -> Result<Option<u16>, Error> {
+    let mut header = [0u8; SECTOR_HEADER_LEN as usize];
+    self.area.read(storage, sector * SECTOR_LEN, &mut header)?;
+    Ok((header[..2] == SECTOR_MAGIC).then(|| u16::from_le_bytes([header[2], header[3]])))
+  }
+
+  /// Frame length of the record at `offset` of `sector`, if there is one.
+  fn record_len(
+    &self,
+    storage: &mut dyn Storage,
+    sector: u32,
+    offset: u32,
+  ) -> Result<Option<u8>, Error> {
+    if offset + RECORD_HEADER_LEN > SECTOR_LEN {
+      return Ok(None);
+    }
+    let mut len = [ERASED];
+    self
+      .area
+      .read(storage, sector * SECTOR_LEN + offset, &mut len)?;
+    let fits = offset + RECORD_HEADER_LEN + len[0] as u32 <= SECTOR_LEN;
+    Ok((len[0] != ERASED && fits).then_some(len[0]))
+  }
+}
+
+/// `AT+FLOG?` without an external flash.
+pub const REPORT_NONE: &[u8] = b"+FLOG:NONE\r\n";
@@ -0,0 +1,100 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/forwarder.rs - 数据包转发器上行记录
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Packet-forwarder uplink records.
+//!
+//! With the data port opened at
+//! [`BAUD_FORWARDER`](crate::mode::BAUD_FORWARDER) every frame the radio
+//! receives goes to the host as one line of JSON, laid out like the `rxpk`
+//! objects of the Semtech UDP packet forwarder, so a small host script can
+//! relay them to a network server:
+//!
+//! ```text
+//! {"rxpk":[{"tmst":1234000,"chan":0,"rfch":0,"freq":433.125000,"stat":1,
+//! "modu":"LORA","datr":"SF9BW125","codr":"4/5","rssi":-87,"lsnr":7,
+//! "size":5,"data":"AQIDBAU="}]}
+//! ```
+//!
+//! (one line on the wire).  Frames are forwarded whole, link header
+//! included, whatever their address, and are not bridged otherwise.
+//! `tmst` counts microseconds since boot at millisecond resolution and
+//! wraps like the forwarder's.  While the bridge has network time
+//! ([`crate::timesync`]) a `time` field, ISO 8601 UTC in milliseconds,
+//! follows it, so captures from several bridges line up.  Only
+//! frames with a good CRC are reported.  Host data keeps being bridged as
+//! in transparent mode; downlinks (`txpk`) are not supported.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::packetizer::MAX_PAYLOAD;
+use crate::radio::{PacketStatus, RadioParams};
+use crate::timesync;
+
+/// Longest record: the fixed fields plus a full payload in base64.
+pub const RECORD_MAX: usize = 240 + MAX_PAYLOAD.div_ceil(3) * 4;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Uplink record of a frame received at `now_ms`, network time
+/// `network_ms`, with `params`, newline terminated.
+pub fn uplink(
+  params: &RadioParams,
+  quality: Option<PacketStatus>,
+  now_ms: u32,
+  network_ms: Option<u64>,
+  frame: &[u8],
+) -> String<RECORD_MAX> {
+  let mut record = String::new();
+  let quality = quality.unwrap_or(PacketStatus {
+    rssi_dbm: 0,
+    snr_db: 0,
+  });
+  let _ = write!(record, "{{\"rxpk\":[{{\"tmst\":{},", now_ms.wrapping_mul(1_000));
+  if let Some(network_ms) = network_ms {
+    let _ = record.push_str("\"time\":\"");
+    let _ = timesync::write_iso(&mut record, network_ms);
+    let _ = record.push_str("\",");
+  }
+  let _ = write!(
+    record,
+    "\"chan\":0,\"rfch\":0,\"freq\":{}.{:06},\"stat\":1,\
+     \"modu\":\"LORA\",\"datr\":\"SF{}BW{}\",\"codr\":\"4/{}\",\"rssi\":{},\"lsnr\":{},\
+     \"size\":{},\"data\":\"",
+    params.frequency_hz / 1_000_000,
+    params.frequency_hz % 1_000_000,
+    params.sf,
+    params.bandwidth.khz(),
+    params.cr,
+    quality.rssi_dbm,
+    quality.snr_db,
+    frame.len(),
+  );
+  for chunk in frame.chunks(3) {
+    let bits = chunk
+      .iter()
+      .enumerate()
+      .fold(0u32, |bits, (index, &byte)| bits | ((byte as u32) << (16 - 8 * index)));
+    for index in 0..4 {
+      let symbol = if index <= chunk.len() {
+        BASE64[((bits >> (18 - 6 * index)) & 0x3F) as usize]
+      } else {
+        b'='
+      };
+      let _ = record.push(symbol as char);
+    }
+  }
+  let _ = record.push_str("\"}]}\n");
+  record
+}
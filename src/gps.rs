@@ -0,0 +1,347 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/gps.rs - NMEA GPS 接收机
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! NMEA GPS receiver on USART2.
+//!
+//! Built with the `gps` feature, a GPS module's TX goes to USART2 RX (PA3)
+//! at [`BAUD`], 8N1.  The bridge reads its RMC and GGA sentences from any
+//! talker (`GP`, `GN`, `GL`, ...) and keeps the latest fix: position and
+//! UTC time from RMC, altitude and satellites in use from GGA.  Sentences
+//! with a bad checksum are ignored, and a fix older than [`FIX_MAX_AGE_MS`]
+//! or marked void counts as none.
+//!
+//! The fix goes into telemetry beacons (field `P`, see [`crate::beacon`])
+//! and onto the GPS status page, which makes the bridge a simple LoRa
+//! tracker.  PA3 is DIO1 on Blue-High v1, so GPS needs a board that wires
+//! DIO1 elsewhere; `board-custom` moves it to PA1 when `gps` is enabled.
+//!
+//! Without the feature nothing feeds the parser and there is never a fix.
+
+#![cfg_attr(not(feature = "gps"), allow(dead_code))]
+
+use heapless::Vec;
+
+/// Baud rate of the GPS module; the common factory default.
+pub const BAUD: u32 = 9600;
+/// Age after which the last fix is considered lost.
+pub const FIX_MAX_AGE_MS: u32 = 5_000;
+/// Longest NMEA sentence, `$` to checksum, plus some slack.
+const SENTENCE_MAX: usize = 96;
+
+/// UTC time of day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Time {
+  pub hours: u8,
+  pub minutes: u8,
+  pub seconds: u8,
+}
+
+/// UTC date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Date {
+  pub year: u16,
+  pub month: u8,
+  pub day: u8,
+}
+
+/// Latest position solution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Fix {
+  /// Micro-degrees, north positive.
+  pub latitude_ude: i32,
+  /// Micro-degrees, east positive.
+  pub longitude_ude: i32,
+  /// Metres above mean sea level, from GGA; `None` until one was heard.
+  pub altitude_m: Option<i32>,
+  pub satellites: u8,
+  pub time: Time,
+  pub date: Date,
+}
+
+/// Assembles NMEA sentences and tracks the fix.
+pub struct Receiver {
+  sentence: Vec<u8, SENTENCE_MAX>,
+  /// Inside a sentence, after `$`.
+  collecting: bool,
+  fix: Option<Fix>,
+  /// When `fix` was last confirmed by an RMC sentence.
+  fix_ms: u32,
+  altitude_m: Option<i32>,
+  satellites: u8,
+}
+
+impl Receiver {
+  pub fn new() -> Self {
+    Self {
+      sentence: Vec::new(),
+      collecting: false,
+      fix: None,
+      fix_ms: 0,
+      altitude_m: None,
+      satellites: 0,
+    }
+  }
+
+  /// Feed one byte from the module, received at `now`.  Returns `true`
+  /// when it completed an RMC sentence with a valid fix.
+  pub fn feed(&mut self, byte: u8, now: u32) -> bool {
+    match byte {
+      b'$' => {
+        self.sentence.clear();
+        self.collecting = true;
+        false
+      }
+      b'\r' | b'\n' if self.collecting => {
+        self.collecting = false;
+        match self.sentence() {
+          Some(sentence) => self.on_sentence(sentence, now),
+          None => false,
+        }
+      }
+      _ if self.collecting => {
+        if self.sentence.push(byte).is_err() {
+          self.collecting = false;
+        }
+        false
+      }
+      _ => false,
+    }
+  }
+
+  /// The current fix, if it is recent.
+  pub fn fix(&self, now: u32) -> Option<Fix> {
+    self.fix.filter(|_| now.wrapping_sub(self.fix_ms) < FIX_MAX_AGE_MS)
+  }
+
+  /// The collected sentence without its checksum, if the checksum holds.
+  fn sentence(&self) -> Option<Vec<u8, SENTENCE_MAX>> {
+    let star = self.sentence.iter().rposition(|&byte| byte == b'*')?;
+    let (body, checksum) = self.sentence.split_at(star);
+    let expected = u8::from_str_radix(core::str::from_utf8(&checksum[1..]).ok()?, 16).ok()?;
+    let sum = body.iter().fold(0, |sum, byte| sum ^ byte);
+    (sum == expected).then(|| Vec::from_slice(body).unwrap_or_default())
+  }
+
+  fn on_sentence(&mut self, sentence: Vec<u8, SENTENCE_MAX>, now: u32) -> bool {
+    let mut fields = sentence.split(|&byte| byte == b',');
+    let Some(kind) = fields.next().and_then(|name| name.get(2..)) else {
+      return false;
+    };
+    match kind {
+      b"RMC" => self.on_rmc(fields, now),
+      b"GGA" => {
+        self.on_gga(fields);
+        false
+      }
+      _ => false,
+    }
+  }
+
+  /// `time,status,lat,N|S,lon,E|W,speed,course,date,...`
+  fn on_rmc<'a>(&mut self, mut fields: impl Iterator<Item = &'a [u8]>, now: u32) -> bool {
+    let mut next = || fields.next().unwrap_or_default();
+    let time = parse_time(next());
+    let valid = next() == b"A";
+    let latitude = parse_coordinate(next(), next(), 2);
+    let longitude = parse_coordinate(next(), next(), 3);
+    let (_speed, _course) = (next(), next());
+    let date = parse_date(next());
+    match (valid, time, latitude, longitude, date) {
+      (true, Some(time), Some(latitude_ude), Some(longitude_ude), Some(date)) => {
+        self.fix = Some(Fix {
+          latitude_ude,
+          longitude_ude,
+          altitude_m: self.altitude_m,
+          satellites: self.satellites,
+          time,
+          date,
+        });
+        self.fix_ms = now;
+        true
+      }
+      _ => {
+        self.fix = None;
+        false
+      }
+    }
+  }
+
+  /// `time,lat,N|S,lon,E|W,quality,satellites,hdop,altitude,M,...`
+  fn on_gga<'a>(&mut self, mut fields: impl Iterator<Item = &'a [u8]>) {
+    let mut next = || fields.next().unwrap_or_default();
+    let (_time, _lat, _ns, _lon, _ew) = (next(), next(), next(), next(), next());
+    let quality = parse_decimal(next(), 0);
+    self.satellites = parse_decimal(next(), 0).unwrap_or(0).clamp(0, u8::MAX as i32) as u8;
+    let _hdop = next();
+    self.altitude_m = parse_decimal(next(), 0).filter(|_| quality.is_some_and(|q| q > 0));
+  }
+}
+
+impl Default for Receiver {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Parse a decimal number, returning it scaled by `10^decimals` and
+/// truncated; `-12.345` with 2 decimals is `-1234`.
+fn parse_decimal(text: &[u8], decimals: u32) -> Option<i32> {
+  let (negative, text) = match text.split_first() {
+    Some((b'-', rest)) => (true, rest),
+    _ => (false, text),
+  };
+  let mut parts = text.splitn(2, |&byte| byte == b'.');
+  let whole = parts.next().filter(|whole| !whole.is_empty())?;
+  let fraction = parts.next().unwrap_or_default();
+  let mut value: i32 = 0;
+  for &digit in whole {
+    value = value.checked_mul(10)?.checked_add(digit_value(digit)?)?;
+  }
+  for index in 0..decimals as usize {
+    let digit = match fraction.get(index) {
+      Some(&digit) => digit_value(digit)?,
+      None => 0,
+    };
+    value = value.checked_mul(10)?.checked_add(digit)?;
+  }
+  Some(if negative { -value } else { value })
+}
+
+fn digit_value(byte: u8) -> Option<i32> {
+  byte.is_ascii_digit().then(|| (byte - b'0') as i32)
+}
+
+/// `hhmmss[.ss]`
+fn parse_time(text: &[u8]) -> Option<Time> {
+  let [h1, h0, m1, m0, s1, s0, ..] = *text else {
+    return None;
+  };
+  let pair = |tens, ones| Some((digit_value(tens)? * 10 + digit_value(ones)?) as u8);
+  Some(Time {
+    hours: pair(h1, h0).filter(|&hours| hours < 24)?,
+    minutes: pair(m1, m0).filter(|&minutes| minutes < 60)?,
+    // 60 is a leap second.
+    seconds: pair(s1, s0).filter(|&seconds| seconds <= 60)?,
+  })
+}
+
+/// `ddmmyy`, years 2000 to 2099.
+fn parse_date(text: &[u8]) -> Option<Date> {
+  let [d1, d0, m1, m0, y1, y0] = *text else {
+    return None;
+  };
+  let pair = |tens, ones| Some((digit_value(tens)? * 10 + digit_value(ones)?) as u8);
+  Some(Date {
+    year: 2000 + pair(y1, y0)? as u16,
+    month: pair(m1, m0).filter(|month| (1..=12).contains(month))?,
+    day: pair(d1, d0).filter(|day| (1..=31).contains(day))?,
+  })
+}
+
+/// NMEA `d..dmm.mmmm` with `degree_digits` degree digits and its
+/// hemisphere letter, to micro-degrees.
+fn parse_coordinate(text: &[u8], hemisphere: &[u8], degree_digits: usize) -> Option<i32> {
+  let degrees = parse_decimal(text.get(..degree_digits)?, 0)?;
+  // Minutes to 1e-5, so 1e-5 / 60 degrees = 1/6 micro-degree per unit.
+  let minutes_e5 = parse_decimal(text.get(degree_digits..)?, 5)?;
+  if minutes_e5 >= 60 * 100_000 {
+    return None;
+  }
+  let micro = degrees * 1_000_000 + minutes_e5 * 10 / 6;
+  match hemisphere {
+    b"N" | b"E" => Some(micro),
+    b"S" | b"W" => Some(-micro),
+    _ => None,
+  }
+}
+
+/// Format micro-degrees as a signed decimal with five places.
+pub fn write_degrees(out: &mut impl core::fmt::Write, ude: i32) -> core::fmt::Result {
+  let sign = if ude < 0 { "-" } else { "" };
+  let ude = ude.unsigned_abs();
+  write!(out, "{}{}.{:05}", sign, ude / 1_000_000, ude % 1_000_000 / 10)
+}
+
+#[cfg(feature = "gps")]
+pub use port::{Port, init};
+
+/// USART2, receive only, serviced from its interrupt.
+#[cfg(feature = "gps")]
+mod port {
+  use core::cell::RefCell;
+
+  use cortex_m::interrupt::Mutex;
+  use cortex_m::peripheral::NVIC;
+  use heapless::spsc::{Consumer, Producer, Queue};
+
+  use super::BAUD;
+  use crate::board::{GpsRx, GpsTx};
+  use crate::hal::pac::{Interrupt, USART2, interrupt};
+  use crate::hal::prelude::*;
+  use crate::hal::rcc::Rcc;
+  use crate::hal::serial::{self, Serial};
+
+  /// Bytes buffered from the module; a second of RMC and GGA fits.
+  const RX_CAPACITY: usize = 256;
+
+  struct Isr {
+    rx: serial::Rx<USART2>,
+    queue: Producer<'static, u8, RX_CAPACITY>,
+  }
+
+  static GPS: Mutex<RefCell<Option<Isr>>> = Mutex::new(RefCell::new(None));
+
+  /// Application end of the receive queue.
+  pub struct Port {
+    rx: Consumer<'static, u8, RX_CAPACITY>,
+  }
+
+  /// Set up USART2 for the GPS module.  TX is configured but unused.
+  ///
+  /// Must be called exactly once.
+  pub fn init(usart: USART2, tx: GpsTx, rx: GpsRx, rcc: &mut Rcc) -> Port {
+    let config = serial::Config::default().baudrate(BAUD.bps());
+    let (_tx, mut rx) = Serial::new(usart, (tx, rx), config, rcc).split();
+    rx.listen();
+    let queue: &'static mut Queue<u8, RX_CAPACITY> =
+      cortex_m::singleton!(: Queue<u8, RX_CAPACITY> = Queue::new()).unwrap();
+    let (producer, consumer) = queue.split();
+    cortex_m::interrupt::free(|cs| {
+      GPS.borrow(cs).replace(Some(Isr {
+        rx,
+        queue: producer,
+      }));
+    });
+    unsafe { NVIC::unmask(Interrupt::USART2) };
+    Port { rx: consumer }
+  }
+
+  impl Port {
+    pub fn read_byte(&mut self) -> Option<u8> {
+      self.rx.dequeue()
+    }
+  }
+
+  #[interrupt]
+  fn USART2() {
+    let _isr = crate::load::Isr::start();
+    cortex_m::interrupt::free(|cs| {
+      if let Some(gps) = GPS.borrow(cs).borrow_mut().as_mut() {
+        // Bytes that do not fit are lost; the next sentence resyncs.
+        while let Ok(byte) = gps.rx.read() {
+          let _ = gps.queue.enqueue(byte);
+        }
+      }
+    });
+  }
+}
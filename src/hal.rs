@@ -0,0 +1,52 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/hal.rs - 芯片硬件抽象层选择
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! MCU family selection.
+//!
+//! The rest of the firmware reaches the HAL only through this module, as
+//! `crate::hal::gpio`, `crate::hal::spi` and so on, and takes everything
+//! that differs between chips (ROM addresses, clock tree) from the constants
+//! here.
+//!
+//! | feature   | MCU                               |
+//! |-----------|-----------------------------------|
+//! | `stm32f1` | STM32F103C8 "Blue Pill" (default) |
+//!
+//! Another family gets its own feature, its HAL re-export and a `chip`
+//! module next to the F1 one, together with whatever USB bus, settings
+//! storage and pin map it needs.
+
+#[cfg(not(feature = "stm32f1"))]
+compile_error!("select an MCU with `stm32f1`");
+
+#[cfg(feature = "stm32f1")]
+pub use stm32f1xx_hal::*;
+
+#[cfg(feature = "stm32f1")]
+mod chip {
+  pub const MCU: &str = "STM32F103C8T6";
+  /// 96-bit unique device ID (RM0008 §30.2).
+  pub const UID_BASE: u32 = 0x1FFF_F7E8;
+  /// Flash size in KiB, 16 bits (RM0008 §30.1).
+  pub const FLASH_SIZE_REG: u32 = 0x1FFF_F7E0;
+  /// Vector table of the ROM system bootloader.
+  pub const SYSTEM_MEMORY: u32 = 0x1FFF_F000;
+  /// RCC control/status register with the reset flags (RM0008 §7.3.10).
+  pub const RCC_CSR: u32 = 0x4002_1024;
+  /// 8 MHz crystal, PLL to 72 MHz; USB runs from 72 MHz / 1.5.
+  pub const HSE_HZ: u32 = 8_000_000;
+  pub const SYSCLK_HZ: u32 = 72_000_000;
+  pub const PCLK1_HZ: u32 = 36_000_000;
+}
+
+pub use chip::*;
@@ -0,0 +1,94 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/hid.rs - USB HID 数据口
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Bridge data over a USB HID interface.
+//!
+//! Built with `usb-hid`, the bridge data can go through a vendor-defined
+//! HID interface instead of the CDC data port: `AT+PORT=HID`, the default
+//! of such a build, and `AT+PORT=USB` to go back, both from the next reset.
+//! Every OS binds HID without a driver, and hosts whose policy blocks CDC
+//! devices usually still allow it; host programs open the interface with
+//! hidapi or the like.
+//!
+//! Both directions use 64-byte reports without a report ID, each carrying
+//! a piece of a byte stream:
+//!
+//! ```text
+//! [count u8][count bytes][zero padding]
+//! ```
+//!
+//! The stream is the framed protocol, `[len][payload]` per LoRa frame (see
+//! [`crate::mode`]), which is the bridge mode on this port; there is no line
+//! coding to pick another.  A frame may span reports and a report may end
+//! one frame and start the next.
+//!
+//! The HID function takes the place of the CDC data port, so the USB packet
+//! memory still fits the control port, which stays CDC for AT commands, and
+//! the mass storage function.  HID has no line state: the port counts as
+//! open while the device is configured, and radio data the host does not
+//! collect is dropped once the queue is full, as on the CDC port.
+
+use usb_device::bus::UsbBusAllocator;
+use usbd_hid::hid_class::HIDClass;
+
+use crate::hal::usb::UsbBusType;
+
+/// Length of every report, in both directions.
+pub const REPORT_LEN: usize = 64;
+/// Stream bytes one report carries at most.
+pub const CHUNK_MAX: usize = REPORT_LEN - 1;
+/// Interval the host polls the interrupt endpoints at.
+const POLL_MS: u8 = 1;
+
+/// One vendor-defined collection with a 64-byte input and a 64-byte output
+/// report.
+pub const REPORT_DESCRIPTOR: &[u8] = &[
+  0x06, 0x00, 0xFF, // Usage Page (vendor 0xFF00)
+  0x09, 0x01, // Usage (1)
+  0xA1, 0x01, // Collection (Application)
+  0x15, 0x00, //   Logical Minimum (0)
+  0x26, 0xFF, 0x00, //   Logical Maximum (255)
+  0x75, 0x08, //   Report Size (8)
+  0x95, 0x40, //   Report Count (64)
+  0x09, 0x02, //   Usage (2)
+  0x81, 0x02, //   Input (Data, Variable, Absolute)
+  0x95, 0x40, //   Report Count (64)
+  0x09, 0x03, //   Usage (3)
+  0x91, 0x02, //   Output (Data, Variable, Absolute)
+  0xC0, // End Collection
+];
+
+/// The HID function as the USB stack holds it.
+pub type Function = HIDClass<'static, UsbBusType>;
+
+/// Allocate the interface and its IN and OUT interrupt endpoints.
+pub fn new(bus: &'static UsbBusAllocator<UsbBusType>) -> Function {
+  HIDClass::new(bus, REPORT_DESCRIPTOR, POLL_MS)
+}
+
+/// The report carrying `chunk`, of which the first [`CHUNK_MAX`] bytes fit.
+pub fn pack(chunk: &[u8]) -> [u8; REPORT_LEN] {
+  let count = chunk.len().min(CHUNK_MAX);
+  let mut report = [0u8; REPORT_LEN];
+  report[0] = count as u8;
+  report[1..1 + count].copy_from_slice(&chunk[..count]);
+  report
+}
+
+/// The stream bytes `report` carries; a count past its end is cut short.
+pub fn unpack(report: &[u8]) -> &[u8] {
+  match report {
+    [count, rest @ ..] => &rest[..(*count as usize).min(rest.len())],
+    [] => &[],
+  }
+}
@@ -0,0 +1,280 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/host.rs - 二进制主机协议
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Binary host protocol.
+//!
+//! A typed request/response interface for host applications, next to the
+//! AT commands on the control port.  A message is a [postcard] encoding
+//! followed by its CRC16-CCITT (see [`crate::crc`]), low byte first, COBS
+//! framed and sent between two zero bytes:
+//!
+//! ```text
+//! 0x00 [COBS(postcard(Request) crc16)] 0x00
+//! ```
+//!
+//! An AT line never contains a zero byte, so the two share the port: a
+//! zero starts a frame (and discards a partly received line), the next
+//! zero ends it.  Every request is answered with a [`Response`] frame of
+//! the same shape carrying the request's `id`; a frame that does not
+//! decode or fails its CRC is answered with id `0` and
+//! [`Error::Malformed`].  Log lines
+//! (`AT+LOG=1`) still go out between frames.
+//!
+//! The protocol is versioned by [`PROTOCOL_VERSION`], reported by
+//! [`Op::Hello`].  Within a version, variants and fields are only ever
+//! appended, so a host built against an older version keeps working.
+//!
+//! [`Op::SetConfig`] changes this bridge only, like `AT+ADDR` and the
+//! settings restored by pairing; `AT+RADIO=` is the way to move both ends
+//! of a link.  [`Op::Transmit`] sends one data frame to the peer
+//! outside the TDMA schedule.  [`Op::Telemetry`] starts the telemetry
+//! stream (see [`crate::telemetry`]): [`Reply::Telemetry`] frames with id
+//! `0`, pushed without a request.
+
+use serde::{Deserialize, Serialize};
+
+use crate::band;
+use crate::crc::crc16_ccitt;
+use crate::radio::{Bandwidth, RadioParams};
+use crate::settings::Settings;
+use crate::version;
+
+/// Version 2 added the CRC.
+pub const PROTOCOL_VERSION: u16 = 2;
+/// Longest encoded response, delimiters included.
+pub const FRAME_MAX: usize = 128;
+
+/// A request from the host.
+#[derive(Debug, Deserialize, defmt::Format)]
+pub struct Request<'a> {
+  /// Echoed in the response.
+  pub id: u16,
+  #[serde(borrow)]
+  pub op: Op<'a>,
+}
+
+#[derive(Debug, Deserialize, defmt::Format)]
+pub enum Op<'a> {
+  /// Identify the firmware; answered with [`Reply::Hello`].
+  Hello,
+  /// Answered with [`Reply::Config`].
+  GetConfig,
+  /// Apply and persist a configuration.
+  SetConfig(Config),
+  /// Send a payload to the peer.
+  Transmit(&'a [u8]),
+  /// Answered with [`Reply::Stats`].
+  Stats,
+  /// Restart the bridge after answering.
+  Reset,
+  /// Push [`Reply::Telemetry`] every `interval_s` seconds; `0` stops.
+  Telemetry { interval_s: u16 },
+}
+
+/// The answer to a [`Request`].
+#[derive(Debug, Serialize)]
+pub struct Response<'a> {
+  pub id: u16,
+  pub reply: Reply<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub enum Reply<'a> {
+  Hello {
+    protocol: u16,
+    version: &'a str,
+    git_hash: &'a str,
+    /// Radio chip, `-` when it did not answer at boot.
+    chip: &'a str,
+  },
+  Config(Config),
+  Stats(Stats),
+  /// The request was carried out.
+  Done,
+  Error(Error),
+  /// Pushed by the telemetry stream.
+  Telemetry(Telemetry),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+pub enum Error {
+  /// The frame did not decode as a request.
+  Malformed,
+  /// A field is out of range.
+  Invalid,
+  /// The radio is pairing, scanning or on a LoRaWAN exchange.
+  Busy,
+  /// The payload does not fit a frame.
+  TooLong,
+  /// The radio or the settings flash failed.
+  Failed,
+  /// The payload does not fit in what is left of the airtime budget.
+  OverBudget,
+}
+
+/// Link settings exchanged by [`Op::GetConfig`] and [`Op::SetConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+pub struct Config {
+  pub frequency_hz: u32,
+  pub power_dbm: i8,
+  pub sf: u8,
+  pub bandwidth_khz: u16,
+  pub cr: u8,
+  pub node_address: u16,
+  pub peer_address: u16,
+  pub addressing: bool,
+}
+
+impl Config {
+  pub fn of(settings: &Settings) -> Self {
+    let radio = settings.radio;
+    Self {
+      frequency_hz: radio.frequency_hz,
+      power_dbm: radio.power_dbm,
+      sf: radio.sf,
+      bandwidth_khz: radio.bandwidth.khz() as u16,
+      cr: radio.cr,
+      node_address: settings.node_address,
+      peer_address: settings.peer_address,
+      addressing: settings.addressing,
+    }
+  }
+
+  /// Radio parameters, if the module supports them.
+  pub fn radio(&self) -> Option<RadioParams> {
+    let params = RadioParams {
+      frequency_hz: self.frequency_hz,
+      power_dbm: self.power_dbm,
+      sf: self.sf,
+      bandwidth: Bandwidth::from_khz(self.bandwidth_khz as u32)?,
+      cr: self.cr,
+    };
+    params.is_valid().then_some(params)
+  }
+}
+
+/// Totals since boot for [`Op::Stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+pub struct Stats {
+  pub uptime_ms: u32,
+  pub lora_tx: u32,
+  pub lora_rx: u32,
+  pub errors: u32,
+  pub crc_errors: u32,
+  /// Received bytes the host port had no room for.
+  pub host_drops: u32,
+  /// Time spent transmitting over the last minute and hour.
+  pub airtime_minute_ms: u32,
+  pub airtime_hour_ms: u32,
+  /// Packet buffers in use now and at most since boot.
+  pub packets_in_use: u32,
+  pub packets_peak: u32,
+  /// Whether the host has the USB bus suspended, and how often it did.
+  pub usb_suspended: bool,
+  pub usb_suspends: u32,
+}
+
+/// Status pushed by the telemetry stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+pub struct Telemetry {
+  pub uptime_ms: u32,
+  pub lora_tx: u32,
+  pub lora_rx: u32,
+  pub errors: u32,
+  pub crc_errors: u32,
+  /// Quality of the last packet received.
+  pub rssi_dbm: Option<i16>,
+  pub snr_db: Option<i8>,
+  /// Time spent transmitting since boot.
+  pub airtime_ms: u32,
+  /// High-power airtime left before the duty-cycle derating.
+  pub duty_budget_ms: u32,
+  /// CPU load since the last sample, in ‰ (see [`crate::load`]).
+  pub cpu_load_permille: u16,
+  /// Longest main-loop pass and interrupt handler since the last sample.
+  pub loop_max_us: u32,
+  pub isr_max_us: u32,
+}
+
+/// [`Reply::Hello`]; `radio_ok` when the chip answered at boot.
+pub fn hello(radio_ok: bool) -> Reply<'static> {
+  Reply::Hello {
+    protocol: PROTOCOL_VERSION,
+    version: version::VERSION,
+    git_hash: version::GIT_HASH,
+    chip: if radio_ok { band::CHIP } else { "-" },
+  }
+}
+
+/// Decode the COBS frame between the delimiters, in place.
+pub fn decode(frame: &mut [u8]) -> Option<Request<'_>> {
+  let len = cobs_decode(frame)?;
+  let (body, crc) = frame[..len].split_last_chunk::<2>()?;
+  if crc16_ccitt(body).to_le_bytes() != *crc {
+    return None;
+  }
+  postcard::from_bytes(body).ok()
+}
+
+/// Encode `response` into `out` with both delimiters.
+pub fn encode<'a>(response: &Response, out: &'a mut [u8; FRAME_MAX]) -> Option<&'a [u8]> {
+  // Room for the CRC, the COBS overhead byte and both delimiters.
+  let mut raw = [0u8; FRAME_MAX - 3];
+  let len = postcard::to_slice(response, &mut raw[..FRAME_MAX - 5]).ok()?.len();
+  let crc = crc16_ccitt(&raw[..len]);
+  raw[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+  out[0] = 0;
+  let len = cobs_encode(&raw[..len + 2], &mut out[1..FRAME_MAX - 1])?;
+  out[1 + len] = 0;
+  Some(&out[..len + 2])
+}
+
+/// COBS encode `data` into `out`; the encoded length.
+fn cobs_encode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+  let (mut code_at, mut len, mut code) = (0, 1, 1u8);
+  for &byte in data {
+    if byte != 0 {
+      *out.get_mut(len)? = byte;
+      len += 1;
+      code += 1;
+    }
+    if byte == 0 || code == 0xFF {
+      *out.get_mut(code_at)? = code;
+      code_at = len;
+      len += 1;
+      code = 1;
+    }
+  }
+  *out.get_mut(code_at)? = code;
+  Some(len)
+}
+
+/// COBS decode `buf` in place; the decoded length.
+fn cobs_decode(buf: &mut [u8]) -> Option<usize> {
+  let (mut read, mut write) = (0, 0);
+  while read < buf.len() {
+    let code = buf[read] as usize;
+    let end = read + code;
+    if code == 0 || end > buf.len() || buf[read + 1..end].contains(&0) {
+      return None;
+    }
+    buf.copy_within(read + 1..end, write);
+    write += code - 1;
+    read = end;
+    if code < 0xFF && read < buf.len() {
+      buf[write] = 0;
+      write += 1;
+    }
+  }
+  Some(write)
+}
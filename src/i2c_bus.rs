@@ -0,0 +1,64 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/i2c_bus.rs - 共享 I2C2 总线
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! I2C2 shared by the OLED panel and the sensors.
+//!
+//! The bus lives in a `RefCell` and every user holds a copy of [`Shared`],
+//! which borrows it for one transaction at a time.  Only the main loop
+//! touches the bus, so a borrow never overlaps another one.  The SH1106
+//! driver still speaks embedded-hal 0.2, so [`Shared`] implements that
+//! `Write` as well when it is built.
+
+#![cfg_attr(feature = "no-display", allow(dead_code))]
+
+use core::cell::RefCell;
+
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+
+use crate::hal::{i2c::BlockingI2c, pac::I2C2};
+
+type Bus = BlockingI2c<I2C2>;
+
+/// Handle on the shared bus.
+#[derive(Clone, Copy)]
+pub struct Shared(&'static RefCell<Bus>);
+
+/// Take over the bus.
+///
+/// Must be called exactly once.
+pub fn init(i2c: Bus) -> Shared {
+  Shared(cortex_m::singleton!(: RefCell<Bus> = RefCell::new(i2c)).unwrap())
+}
+
+impl ErrorType for Shared {
+  type Error = <Bus as ErrorType>::Error;
+}
+
+impl I2c for Shared {
+  fn transaction(
+    &mut self,
+    address: u8,
+    operations: &mut [Operation<'_>],
+  ) -> Result<(), Self::Error> {
+    self.0.borrow_mut().transaction(address, operations)
+  }
+}
+
+#[cfg(feature = "sh1106")]
+impl embedded_hal_02::blocking::i2c::Write for Shared {
+  type Error = <Bus as embedded_hal_02::blocking::i2c::Write>::Error;
+
+  fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+    embedded_hal_02::blocking::i2c::Write::write(&mut *self.0.borrow_mut(), address, bytes)
+  }
+}
@@ -0,0 +1,145 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/kiss.rs - KISS TNC 协议
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! KISS TNC framing.
+//!
+//! With the data port opened at [`BAUD_KISS`](crate::mode::BAUD_KISS) the
+//! bridge behaves as a single-port KISS TNC, so packet-radio host software
+//! (Direwolf, APRS clients, the Linux `kissattach` driver) can use it
+//! unchanged.  Frames in both directions look like
+//!
+//! ```text
+//! [FEND][command][data, FEND/FESC escaped][FEND]
+//! ```
+//!
+//! where the high nibble of the command byte is the port and the low
+//! nibble the command.  A data frame (command 0) for port 0 becomes one
+//! LoRa frame and every received LoRa frame goes to the host as a data
+//! frame for port 0.  The AX.25 content is passed through untouched.
+//!
+//! The timing commands (TXDELAY, persistence, slot time, TX tail, full
+//! duplex) are accepted and ignored: LoRa keying and channel access are
+//! handled by the bridge itself.  Frames for other ports are dropped.
+
+use heapless::Vec;
+
+use crate::packetizer::MAX_PAYLOAD;
+
+/// Frame delimiter.
+pub const FEND: u8 = 0xC0;
+/// Escape byte.
+pub const FESC: u8 = 0xDB;
+/// Escaped [`FEND`].
+pub const TFEND: u8 = 0xDC;
+/// Escaped [`FESC`].
+pub const TFESC: u8 = 0xDD;
+/// Command byte of a data frame for port 0.
+const DATA_FRAME: u8 = 0x00;
+
+/// Longest KISS frame for a full LoRa payload: every byte escaped, plus
+/// both delimiters and the command byte.
+pub const FRAME_MAX: usize = 2 * MAX_PAYLOAD + 3;
+
+/// What a byte from the host means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Event {
+  /// A data byte of the current frame, unescaped.
+  Byte(u8),
+  /// The current data frame is complete.
+  End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+  /// Outside a frame, waiting for `FEND`.
+  Idle,
+  /// After `FEND`, waiting for the command byte.
+  Command,
+  Data,
+  /// After `FESC` in a data frame.
+  Escaped,
+  /// Inside a frame that is not data for port 0.
+  Skip,
+}
+
+/// Decoder of the host byte stream.
+pub struct Decoder {
+  state: State,
+}
+
+impl Decoder {
+  pub fn new() -> Self {
+    Self { state: State::Idle }
+  }
+
+  /// Forget a partially decoded frame.
+  pub fn reset(&mut self) {
+    self.state = State::Idle;
+  }
+
+  pub fn feed(&mut self, byte: u8) -> Option<Event> {
+    match (self.state, byte) {
+      (state, FEND) => {
+        // Back-to-back delimiters are allowed; they open no frame.
+        self.state = State::Command;
+        matches!(state, State::Data | State::Escaped).then_some(Event::End)
+      }
+      (State::Idle | State::Skip, _) => None,
+      (State::Command, DATA_FRAME) => {
+        self.state = State::Data;
+        None
+      }
+      (State::Command, command) => {
+        defmt::debug!("[kiss] ignoring command 0x{:02X}", command);
+        self.state = State::Skip;
+        None
+      }
+      (State::Data, FESC) => {
+        self.state = State::Escaped;
+        None
+      }
+      (State::Data, byte) => Some(Event::Byte(byte)),
+      (State::Escaped, byte) => {
+        self.state = State::Data;
+        // Anything else after FESC is a protocol error; keep the byte.
+        Some(Event::Byte(match byte {
+          TFEND => FEND,
+          TFESC => FESC,
+          byte => byte,
+        }))
+      }
+    }
+  }
+}
+
+impl Default for Decoder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Wrap a received payload as a data frame for port 0.
+pub fn encode(payload: &[u8], out: &mut Vec<u8, FRAME_MAX>) {
+  out.clear();
+  let _ = out.push(FEND);
+  let _ = out.push(DATA_FRAME);
+  // `FRAME_MAX` leaves room for any payload.
+  for &byte in payload {
+    let _ = match byte {
+      FEND => out.extend_from_slice(&[FESC, TFEND]),
+      FESC => out.extend_from_slice(&[FESC, TFESC]),
+      byte => out.extend_from_slice(&[byte]),
+    };
+  }
+  let _ = out.push(FEND);
+}
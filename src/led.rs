@@ -0,0 +1,164 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/led.rs - 状态指示灯
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Status LEDs.
+//!
+//! The onboard status LED (active low, PC13 on a Blue Pill) shows what the
+//! bridge is doing:
+//!
+//! | state        | pattern                                  |
+//! |--------------|------------------------------------------|
+//! | boot         | fast blink, 100 ms on / 100 ms off       |
+//! | idle         | 30 ms heartbeat flash every 2 s          |
+//! | transmitting | steady on                                |
+//! | received     | one 50 ms flash per frame                |
+//! | error        | fast blink for [`ERROR_MS`] after it     |
+//!
+//! With the `activity-leds` feature, external TX and RX LEDs (PB8 and PB9 on
+//! Blue-High v1), active high, additionally show transmit and receive activity on their
+//! own.
+//!
+//! The pins live in a static and are driven from the SysTick interrupt via
+//! [`tick`], so patterns keep running while the main loop blocks on a
+//! transmission.  The rest of the firmware only reports events.
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use cortex_m::interrupt::{self, Mutex};
+
+use crate::board::StatusLed;
+#[cfg(feature = "activity-leds")]
+use crate::board::{RxLed, TxLed};
+
+/// Length of the flash for a received frame.
+const RX_FLASH_MS: u32 = 50;
+/// How long the error pattern runs after an error.
+pub const ERROR_MS: u32 = 1_200;
+const BLINK_HALF_PERIOD_MS: u32 = 100;
+const HEARTBEAT_PERIOD_MS: u32 = 2_000;
+const HEARTBEAT_ON_MS: u32 = 30;
+
+struct Leds {
+  status: StatusLed,
+  #[cfg(feature = "activity-leds")]
+  tx: TxLed,
+  #[cfg(feature = "activity-leds")]
+  rx: RxLed,
+}
+
+static LEDS: Mutex<RefCell<Option<Leds>>> = Mutex::new(RefCell::new(None));
+
+static BOOTED: AtomicBool = AtomicBool::new(false);
+static TRANSMITTING: AtomicBool = AtomicBool::new(false);
+static RX_FLASH: AtomicBool = AtomicBool::new(false);
+static ERROR: AtomicBool = AtomicBool::new(false);
+/// End of the current RX flash and error pattern; `0` when not running.
+static RX_UNTIL: AtomicU32 = AtomicU32::new(0);
+static ERROR_UNTIL: AtomicU32 = AtomicU32::new(0);
+
+/// Hand the LED pins to the SysTick handler; the boot pattern starts.
+pub fn init(
+  status: StatusLed,
+  #[cfg(feature = "activity-leds")] tx: TxLed,
+  #[cfg(feature = "activity-leds")] rx: RxLed,
+) {
+  interrupt::free(|cs| {
+    LEDS.borrow(cs).replace(Some(Leds {
+      status,
+      #[cfg(feature = "activity-leds")]
+      tx,
+      #[cfg(feature = "activity-leds")]
+      rx,
+    }));
+  });
+}
+
+/// Boot finished; switch from the boot pattern to the idle heartbeat.
+pub fn booted() {
+  BOOTED.store(true, Ordering::Relaxed);
+}
+
+/// A transmission started or ended.
+pub fn set_transmitting(active: bool) {
+  TRANSMITTING.store(active, Ordering::Relaxed);
+}
+
+/// A frame was received.
+pub fn received() {
+  RX_FLASH.store(true, Ordering::Relaxed);
+}
+
+/// Something went wrong.
+pub fn error() {
+  ERROR.store(true, Ordering::Relaxed);
+}
+
+/// Advance the patterns; called from the SysTick handler every millisecond.
+pub fn tick(now: u32) {
+  let rx = pattern_running(&RX_FLASH, &RX_UNTIL, RX_FLASH_MS, now);
+  let error = pattern_running(&ERROR, &ERROR_UNTIL, ERROR_MS, now);
+  let transmitting = TRANSMITTING.load(Ordering::Relaxed);
+
+  let status = if transmitting {
+    true
+  } else if error || !BOOTED.load(Ordering::Relaxed) {
+    (now / BLINK_HALF_PERIOD_MS).is_multiple_of(2)
+  } else {
+    rx || now % HEARTBEAT_PERIOD_MS < HEARTBEAT_ON_MS
+  };
+
+  interrupt::free(|cs| {
+    let mut leds = LEDS.borrow(cs).borrow_mut();
+    let Some(leds) = leds.as_mut() else {
+      return;
+    };
+    // The pin sinks the onboard LED.
+    if status {
+      leds.status.set_low();
+    } else {
+      leds.status.set_high();
+    }
+    #[cfg(feature = "activity-leds")]
+    {
+      if transmitting {
+        leds.tx.set_high();
+      } else {
+        leds.tx.set_low();
+      }
+      if rx {
+        leds.rx.set_high();
+      } else {
+        leds.rx.set_low();
+      }
+    }
+  });
+}
+
+/// Start a pattern `duration_ms` long when `trigger` is set and report
+/// whether it is still running.
+fn pattern_running(trigger: &AtomicBool, until: &AtomicU32, duration_ms: u32, now: u32) -> bool {
+  if trigger.swap(false, Ordering::Relaxed) {
+    // `0` means idle, so never store it as an end time.
+    until.store(now.wrapping_add(duration_ms).max(1), Ordering::Relaxed);
+  }
+  let end = until.load(Ordering::Relaxed);
+  if end == 0 {
+    return false;
+  }
+  if now.wrapping_sub(end) as i32 >= 0 {
+    until.store(0, Ordering::Relaxed);
+    return false;
+  }
+  true
+}
@@ -0,0 +1,107 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/lib.rs - 固件库入口
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Blue-High firmware library.
+//!
+//! Every subsystem of the bridge lives here, and [`app`] ties them into the
+//! firmware; `src/main.rs` only calls [`app::run`] behind `#[entry]`.  The
+//! on-target tests link the same modules, see `tests/on_target.rs`.
+
+#![no_std]
+
+pub mod adr;
+pub mod afc;
+pub mod airtime;
+pub mod antenna;
+pub mod app;
+pub mod band;
+pub mod battery;
+pub mod beacon;
+pub mod bench;
+pub mod board;
+pub mod boot_select;
+pub mod bootloader;
+pub mod calibration;
+pub mod command;
+pub mod crc;
+pub mod csma;
+pub mod cw;
+pub mod derate;
+pub mod device_id;
+pub mod diagnostics;
+pub mod display;
+pub mod event_log;
+pub mod flash_log;
+pub mod forwarder;
+pub mod gps;
+pub mod hal;
+#[cfg(feature = "usb-hid")]
+pub mod hid;
+pub mod host;
+pub mod i2c_bus;
+pub mod kiss;
+pub mod led;
+pub mod link;
+pub mod load;
+pub mod log_control;
+#[cfg(feature = "driver-sx1268-rs")]
+pub mod lora;
+pub mod lora_config;
+pub mod lorawan;
+pub mod modbus;
+pub mod mode;
+pub mod ms_os;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+pub mod msc;
+pub mod noise;
+pub mod ook;
+pub mod ota;
+pub mod packet;
+pub mod packetizer;
+pub mod pairing;
+pub mod per;
+pub mod ping;
+pub mod radio;
+pub mod radio_handle;
+pub mod random;
+pub mod relay;
+pub mod remote;
+pub mod rtc;
+pub mod rxmeta;
+pub mod scan;
+pub mod sd_log;
+pub mod security;
+pub mod selftest;
+pub mod sensor;
+pub mod settings;
+pub mod sim;
+pub mod sniffer;
+pub mod spi_bus;
+pub mod spi_flash;
+pub mod startup;
+pub mod supervisor;
+#[cfg(feature = "driver-sx1268-rs")]
+pub mod sx126x;
+#[cfg(feature = "sx1276")]
+pub mod sx1276;
+pub mod tdma;
+pub mod telemetry;
+pub mod terminal;
+pub mod timer;
+pub mod timesync;
+pub mod trim;
+pub mod txqueue;
+pub mod uart;
+pub mod ui;
+pub mod usb;
+pub mod version;
@@ -0,0 +1,208 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/link.rs - 链路层帧格式
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Link-layer framing on top of raw LoRa packets.
+//!
+//! With addressing enabled every frame starts with a 7-byte header:
+//!
+//! ```text
+//! [dst u16 LE][src u16 LE][kind u8][seq u8][hops u8][payload ...]
+//! ```
+//!
+//! Frames addressed to another node are dropped on receive; [`BROADCAST`]
+//! reaches every node.  `kind` separates host data from bridge-to-bridge
+//! control frames, which are never forwarded to the host.  `seq` counts the
+//! sender's frames so copies arriving over a repeater can be recognised.
+//! `hops` holds the hop limit set by the sender in its high nibble and how
+//! often the frame was relayed in its low nibble; it is the only byte a
+//! repeater changes.  With addressing disabled frames are raw data payloads,
+//! as before, so a bridge interoperates with plain LoRa senders.
+
+use heapless::Vec;
+
+use crate::packetizer::MAX_PAYLOAD;
+
+/// Destination address that every node accepts.
+pub const BROADCAST: u16 = 0xFFFF;
+/// Bytes taken by the address header.
+pub const HEADER_LEN: usize = 7;
+/// Offset of the hop count in the address header.
+pub const HOPS_OFFSET: usize = HEADER_LEN - 1;
+
+/// What a frame carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Kind {
+  /// Host data for the USB data port.
+  Data,
+  /// Message between the two bridges; needs addressing.
+  Control,
+}
+
+impl Kind {
+  fn code(self) -> u8 {
+    match self {
+      Kind::Data => 0,
+      Kind::Control => 1,
+    }
+  }
+}
+
+/// Local link configuration.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct Link {
+  pub addressing: bool,
+  pub local: u16,
+  pub peer: u16,
+  /// Sequence number of the next transmitted frame.
+  pub seq: u8,
+  /// Relays a transmitted frame may take.
+  pub hop_limit: u8,
+}
+
+/// The address header of a frame, whoever it is addressed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Header {
+  pub dst: u16,
+  pub src: u16,
+  pub seq: u8,
+  pub hops: u8,
+  pub hop_limit: u8,
+}
+
+impl Header {
+  /// The `hops` header byte.
+  pub fn hop_byte(hops: u8, hop_limit: u8) -> u8 {
+    (hop_limit << 4) | (hops & 0x0F)
+  }
+
+  /// Read the header of an addressed frame.
+  pub fn parse(frame: &[u8]) -> Option<Self> {
+    let header = frame.get(..HEADER_LEN)?;
+    Some(Self {
+      dst: u16::from_le_bytes([header[0], header[1]]),
+      src: u16::from_le_bytes([header[2], header[3]]),
+      seq: header[5],
+      hops: header[HOPS_OFFSET] & 0x0F,
+      hop_limit: header[HOPS_OFFSET] >> 4,
+    })
+  }
+}
+
+/// A received frame that passed the address filter.
+pub struct Received<'a> {
+  /// Sender address, or `None` when addressing is disabled.
+  pub src: Option<u16>,
+  pub kind: Kind,
+  pub payload: &'a [u8],
+}
+
+/// Why a received frame was not delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Reject {
+  /// Shorter than the address header.
+  Truncated,
+  /// Addressed to a different node.
+  NotForUs(u16),
+  /// Unknown frame kind.
+  UnknownKind(u8),
+}
+
+impl Link {
+  /// Bytes of the address header on this link.
+  pub fn header_len(&self) -> usize {
+    if self.addressing { HEADER_LEN } else { 0 }
+  }
+
+  /// Largest payload that fits in one frame after the header.
+  pub fn max_payload(&self) -> usize {
+    MAX_PAYLOAD - self.header_len()
+  }
+
+  /// Build the frame carrying `payload` to the peer.
+  ///
+  /// Without addressing there is no header and `kind` is not sent.
+  pub fn encode(&mut self, kind: Kind, payload: &[u8], out: &mut Vec<u8, MAX_PAYLOAD>) {
+    self.encode_to(self.peer, kind, payload, out);
+  }
+
+  /// Build a frame for `dst` instead of the peer.
+  pub fn encode_to(
+    &mut self,
+    dst: u16,
+    kind: Kind,
+    payload: &[u8],
+    out: &mut Vec<u8, MAX_PAYLOAD>,
+  ) {
+    out.clear();
+    if self.addressing {
+      let _ = out.extend_from_slice(&self.next_header(dst, kind));
+    }
+    let room = MAX_PAYLOAD - out.len();
+    let _ = out.extend_from_slice(&payload[..payload.len().min(room)]);
+  }
+
+  /// Write the header for the peer into the first
+  /// [`header_len`](Self::header_len) bytes of `frame`, in front of a
+  /// payload already in place.
+  pub fn encode_in_place(&mut self, kind: Kind, frame: &mut [u8]) {
+    if self.addressing {
+      frame[..HEADER_LEN].copy_from_slice(&self.next_header(self.peer, kind));
+    }
+  }
+
+  /// The address header of the next frame.
+  fn next_header(&mut self, dst: u16, kind: Kind) -> [u8; HEADER_LEN] {
+    let [dst_lo, dst_hi] = dst.to_le_bytes();
+    let [src_lo, src_hi] = self.local.to_le_bytes();
+    let header = [
+      dst_lo,
+      dst_hi,
+      src_lo,
+      src_hi,
+      kind.code(),
+      self.seq,
+      Header::hop_byte(0, self.hop_limit),
+    ];
+    self.seq = self.seq.wrapping_add(1);
+    header
+  }
+
+  /// Strip the header of a received frame and apply the address filter.
+  pub fn decode<'a>(&self, frame: &'a [u8]) -> Result<Received<'a>, Reject> {
+    if !self.addressing {
+      return Ok(Received {
+        src: None,
+        kind: Kind::Data,
+        payload: frame,
+      });
+    }
+    if frame.len() < HEADER_LEN {
+      return Err(Reject::Truncated);
+    }
+    let dst = u16::from_le_bytes([frame[0], frame[1]]);
+    let src = u16::from_le_bytes([frame[2], frame[3]]);
+    if dst != self.local && dst != BROADCAST {
+      return Err(Reject::NotForUs(dst));
+    }
+    let kind = match frame[4] {
+      0 => Kind::Data,
+      1 => Kind::Control,
+      other => return Err(Reject::UnknownKind(other)),
+    };
+    Ok(Received {
+      src: Some(src),
+      kind,
+      payload: &frame[HEADER_LEN..],
+    })
+  }
+}
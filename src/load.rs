@@ -0,0 +1,141 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/load.rs - CPU 负载与循环延迟测量
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! CPU load and latency.
+//!
+//! The DWT cycle counter (see [`crate::timer`]) times every interrupt
+//! handler and every pass of the main loop.  With nothing to do the main
+//! loop sleeps in [`Monitor::sleep`] until the next interrupt; the
+//! millisecond SysTick wakes it at the latest.  The quickest pass since
+//! boot, less its interrupts and its sleep, is what a pass costs with
+//! nothing to do, and whatever a pass takes beyond that is work.  The load
+//! is the share of the time since the last sample spent in interrupt
+//! handlers and in that work.  The time is taken from SysTick, which keeps
+//! counting while the core sleeps; the cycle counter may not.
+//!
+//! The longest pass is the longest the bridge went without looking at an
+//! event handled in the loop, such as RxDone, so it bounds how late the
+//! loop notices one.  The longest handler bounds how long it delays any
+//! other interrupt.  All three cover the time since the last sample and
+//! go out in the telemetry stream (see [`crate::telemetry`]).
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::timer;
+
+/// Cycles spent in interrupt handlers since the main loop last looked.
+static ISR_CYCLES: AtomicU32 = AtomicU32::new(0);
+/// Longest handler since the last sample, in cycles.
+static ISR_MAX: AtomicU32 = AtomicU32::new(0);
+
+/// Times an interrupt handler from its creation to its drop.
+pub struct Isr(u32);
+
+impl Isr {
+  /// Call first thing in a handler and keep until it returns.
+  pub fn start() -> Self {
+    Self(timer::now_cycles())
+  }
+}
+
+impl Drop for Isr {
+  fn drop(&mut self) {
+    let cycles = timer::now_cycles().wrapping_sub(self.0);
+    ISR_CYCLES.fetch_add(cycles, Ordering::Relaxed);
+    ISR_MAX.fetch_max(cycles, Ordering::Relaxed);
+  }
+}
+
+/// Load and worst cases over a sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Sample {
+  /// Busy share of the time, in ‰.
+  pub load_permille: u16,
+  pub loop_max_us: u32,
+  pub isr_max_us: u32,
+}
+
+/// Main-loop side of the measurement.
+pub struct Monitor {
+  pass_start: u32,
+  /// Cycles the current pass spent in [`Monitor::sleep`].
+  asleep: u32,
+  /// Quickest pass since boot without its interrupts, in cycles.
+  idle_pass: u32,
+  /// Start of the current sample, in milliseconds.
+  sample_start_ms: u32,
+  busy: u64,
+  loop_max: u32,
+}
+
+impl Monitor {
+  pub fn new() -> Self {
+    Self {
+      pass_start: timer::now_cycles(),
+      asleep: 0,
+      idle_pass: u32::MAX,
+      sample_start_ms: timer::now_ms(),
+      busy: 0,
+      loop_max: 0,
+    }
+  }
+
+  /// End a pass of the main loop and start the next.
+  pub fn pass(&mut self) {
+    let now = timer::now_cycles();
+    let cycles = now.wrapping_sub(self.pass_start);
+    self.pass_start = now;
+    let isr = ISR_CYCLES.swap(0, Ordering::Relaxed);
+    // The interrupt that ended a sleep counts in both; it is short.
+    let work = cycles.saturating_sub(isr).saturating_sub(self.asleep);
+    self.asleep = 0;
+    self.idle_pass = self.idle_pass.min(work);
+    self.busy += (isr.min(cycles) + work - self.idle_pass) as u64;
+    self.loop_max = self.loop_max.max(cycles);
+  }
+
+  /// Sleep until the next interrupt.  Only call this with nothing to do
+  /// that an interrupt or the next tick would not bring.
+  pub fn sleep(&mut self) {
+    let start = timer::now_cycles();
+    cortex_m::asm::wfi();
+    self.asleep = self
+      .asleep
+      .wrapping_add(timer::now_cycles().wrapping_sub(start));
+  }
+
+  /// Load and worst cases since the last sample, then start over.
+  pub fn sample(&mut self) -> Sample {
+    let elapsed_ms = timer::elapsed_ms(self.sample_start_ms);
+    let total = elapsed_ms as u64 * 1_000 * timer::cycles_per_us() as u64;
+    let load_permille = (self.busy * 1_000)
+      .checked_div(total)
+      .unwrap_or(0)
+      .min(1_000) as u16;
+    let sample = Sample {
+      load_permille,
+      loop_max_us: timer::cycles_to_us(self.loop_max),
+      isr_max_us: timer::cycles_to_us(ISR_MAX.swap(0, Ordering::Relaxed)),
+    };
+    self.sample_start_ms = timer::now_ms();
+    self.busy = 0;
+    self.loop_max = 0;
+    sample
+  }
+}
+
+impl Default for Monitor {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,188 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/log_control.rs - 分类日志级别控制
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Log levels per category.
+//!
+//! Every diagnostic in [`crate::diagnostics`] belongs to a category and has
+//! a level.  It is printed over defmt, and mirrored to the control port
+//! and the SD card, only when its level is enabled for its category.  The
+//! levels are `OFF`, `ERROR`, `WARN`, `INFO` and `DEBUG`, each enabling
+//! the ones before it; the categories are:
+//!
+//! * `BOOT`: boot sequence, clocks and settings storage;
+//! * `USB`: host data and the log port;
+//! * `RADIO`: frames sent and received, radio errors and SPI tracing;
+//! * `UI`: the display;
+//! * `PROTO`: link protocols: relaying, pairing, TDMA, time and LoRaWAN.
+//!
+//! Every category starts at `INFO`.  `DEBUG` adds hex dumps of host data,
+//! SPI transfers, relay suppression and duplicates, and the heartbeat,
+//! which are too much for every day but help in the field.
+//!
+//! `AT+LOGLEVEL=<category|ALL>,<level>` sets levels, persisted, and
+//! `AT+LOGLEVEL?` answers `+LOGLEVEL:BOOT=INFO,USB=INFO,...`.  The settings
+//! keep the levels in a `u16`, three bits per category.
+
+use core::cell::Cell;
+
+use cortex_m::interrupt::{self, Mutex};
+use heapless::String;
+
+/// Longest `AT+LOGLEVEL?` answer.
+pub const LINE_MAX: usize = 72;
+
+/// How much is logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum Level {
+  Off,
+  Error,
+  Warn,
+  Info,
+  Debug,
+}
+
+const LEVELS: [(Level, &str); 5] = [
+  (Level::Off, "OFF"),
+  (Level::Error, "ERROR"),
+  (Level::Warn, "WARN"),
+  (Level::Info, "INFO"),
+  (Level::Debug, "DEBUG"),
+];
+
+impl Level {
+  pub fn parse(name: &[u8]) -> Option<Self> {
+    LEVELS
+      .iter()
+      .find(|(_, known)| known.as_bytes() == name)
+      .map(|&(level, _)| level)
+  }
+
+  pub fn name(self) -> &'static str {
+    LEVELS[self as usize].1
+  }
+
+  fn from_bits(bits: u16) -> Option<Self> {
+    LEVELS.get(bits as usize).map(|&(level, _)| level)
+  }
+}
+
+/// What a diagnostic is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Category {
+  Boot,
+  Usb,
+  Radio,
+  Ui,
+  Proto,
+}
+
+const CATEGORIES: [(Category, &str); 5] = [
+  (Category::Boot, "BOOT"),
+  (Category::Usb, "USB"),
+  (Category::Radio, "RADIO"),
+  (Category::Ui, "UI"),
+  (Category::Proto, "PROTO"),
+];
+
+impl Category {
+  pub fn parse(name: &[u8]) -> Option<Self> {
+    CATEGORIES
+      .iter()
+      .find(|(_, known)| known.as_bytes() == name)
+      .map(|&(category, _)| category)
+  }
+
+  pub fn name(self) -> &'static str {
+    CATEGORIES[self as usize].1
+  }
+
+  /// The category numbered `index`, as in `category as u8`.
+  pub fn from_index(index: u8) -> Option<Self> {
+    CATEGORIES.get(index as usize).map(|&(category, _)| category)
+  }
+}
+
+/// Level of every category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Config {
+  levels: [Level; CATEGORIES.len()],
+}
+
+impl Config {
+  pub const DEFAULT: Self = Self {
+    levels: [Level::Info; CATEGORIES.len()],
+  };
+
+  pub fn level(&self, category: Category) -> Level {
+    self.levels[category as usize]
+  }
+
+  /// The same with `category`, or every category for `None`, at `level`.
+  pub fn with(mut self, category: Option<Category>, level: Level) -> Self {
+    match category {
+      Some(category) => self.levels[category as usize] = level,
+      None => self.levels = [level; CATEGORIES.len()],
+    }
+    self
+  }
+
+  pub fn encode(&self) -> u16 {
+    let mut bits = 0;
+    for (i, &level) in self.levels.iter().enumerate() {
+      bits |= (level as u16) << (3 * i);
+    }
+    bits
+  }
+
+  pub fn decode(bits: u16) -> Option<Self> {
+    let mut config = Self::DEFAULT;
+    for (i, level) in config.levels.iter_mut().enumerate() {
+      *level = Level::from_bits((bits >> (3 * i)) & 0x7)?;
+    }
+    Some(config)
+  }
+
+  /// `+LOGLEVEL:BOOT=<level>,USB=<level>,...`.
+  pub fn report(&self) -> String<LINE_MAX> {
+    let mut line = String::new();
+    let _ = line.push_str("+LOGLEVEL:");
+    for (i, &(category, name)) in CATEGORIES.iter().enumerate() {
+      if i > 0 {
+        let _ = line.push(',');
+      }
+      let _ = line.push_str(name);
+      let _ = line.push('=');
+      let _ = line.push_str(self.level(category).name());
+    }
+    let _ = line.push_str("\r\n");
+    line
+  }
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self::DEFAULT
+  }
+}
+
+static CONFIG: Mutex<Cell<Config>> = Mutex::new(Cell::new(Config::DEFAULT));
+
+/// Use `config` from now on.
+pub fn set(config: Config) {
+  interrupt::free(|cs| CONFIG.borrow(cs).set(config));
+}
+
+/// Whether a diagnostic of `category` at `level` is to be logged.
+pub fn enabled(category: Category, level: Level) -> bool {
+  level != Level::Off && interrupt::free(|cs| CONFIG.borrow(cs).get()).level(category) >= level
+}
@@ -11,93 +11,143 @@
 //
 // Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
 
-use core::ops::DerefMut;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
-use stm32f1xx_hal::gpio::{Input, Output, Pin};
-use stm32f1xx_hal::spi::{Instance, Spi};
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::{Operation, SpiDevice};
 use sx1268_rs::{Status, control::Control};
 
+use crate::radio::SwitchGuard;
+use crate::timer;
+
 #[derive(Debug)]
 pub enum ControlError<SE> {
   SpiError(SE),
+  /// BUSY stayed high for [`BUSY_TIMEOUT_US`].
+  BusyTimeout,
+  /// A control line could not be driven or read.
+  PinError,
+}
+
+/// Longest the chip may hold BUSY before it counts as hung.  A full
+/// calibration, the longest command, takes about 3.5 ms.
+pub const BUSY_TIMEOUT_US: u32 = 20_000;
+
+/// Set when a BUSY wait times out, until the backend recovers the chip.
+static BUSY_TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// Whether a BUSY wait timed out since the last call.
+pub fn take_busy_timeout() -> bool {
+  BUSY_TIMED_OUT.swap(false, Ordering::Relaxed)
+}
+
+/// Status byte of the last read transaction.
+static LAST_STATUS: AtomicU8 = AtomicU8::new(0);
+
+/// Status the chip clocked out on the last command, register or buffer
+/// read; [`Control::read_register`] and [`Control::read_buffer`] have no
+/// way to return it.
+pub fn last_status() -> Status {
+  Status::from(LAST_STATUS.load(Ordering::Relaxed))
+}
+
+/// Raw byte of [`last_status`], as the chip clocked it out.
+pub fn last_status_byte() -> u8 {
+  LAST_STATUS.load(Ordering::Relaxed)
+}
+
+/// Keep `byte` as the last status and decode it.
+fn record_status(byte: u8) -> Status {
+  LAST_STATUS.store(byte, Ordering::Relaxed);
+  Status::from(byte)
+}
+
+/// Wait for the chip to accept a command, giving up after
+/// [`BUSY_TIMEOUT_US`] rather than clocking SPI into a busy chip.
+fn wait_ready<B: InputPin, SE>(busy: &mut B) -> Result<(), sx1268_rs::Error<ControlError<SE>>> {
+  let deadline = timer::after_us(timer::now_cycles(), BUSY_TIMEOUT_US);
+  while busy.is_high().map_err(pin_error)? {
+    if timer::cycles_reached(deadline) {
+      BUSY_TIMED_OUT.store(true, Ordering::Relaxed);
+      defmt::error!("[lora] BUSY stuck high");
+      return Err(sx1268_rs::Error::ControlError(ControlError::BusyTimeout));
+    }
+  }
+  Ok(())
 }
 
 fn spi_error<SE>(error: SE) -> sx1268_rs::Error<ControlError<SE>> {
   sx1268_rs::Error::ControlError(ControlError::SpiError(error))
 }
 
-/// Wrapper type to implement Control trait for Spi
-pub struct LoraControl<
-  W,
-  S,
-  const NRST_P: char,
-  const NRST_N: u8,
-  NrstMode,
-  const CS_P: char,
-  const CS_N: u8,
-  CsMode,
-  const BUSY_P: char,
-  const BUSY_N: u8,
-  BusyMode,
-  const TX_P: char,
-  const TX_N: u8,
-  TxMode,
-  const RX_P: char,
-  const RX_N: u8,
-  RxMode,
-> where
-  S: Instance,
-{
-  pub spi: Spi<S, W>,
-  pub nrst_pin: Pin<NRST_P, NRST_N, Output<NrstMode>>,
-  pub cs_pin: Pin<CS_P, CS_N, Output<CsMode>>,
-  pub busy_pin: Pin<BUSY_P, BUSY_N, Input<BusyMode>>,
-  pub tx_pin: Pin<TX_P, TX_N, Output<TxMode>>,
-  pub rx_pin: Pin<RX_P, RX_N, Output<RxMode>>,
+fn pin_error<PE, SE>(_: PE) -> sx1268_rs::Error<ControlError<SE>> {
+  sx1268_rs::Error::ControlError(ControlError::PinError)
+}
+
+/// SX126x control lines behind the driver's [`Control`] trait: the SPI
+/// device (NSS is its chip select), NRST, BUSY and the RF switch enables.
+///
+/// Generic over the embedded-hal traits, so any board can name its own
+/// wiring; see [`crate::board::Control`] for the selected board.  BUSY is
+/// waited for before each transaction, so the bus is only taken once the
+/// chip accepts the command.
+pub struct LoraControl<SPI, NRST, BUSY, TX, RX> {
+  pub spi: SPI,
+  pub nrst_pin: NRST,
+  pub busy_pin: BUSY,
+  /// RF switch enables; unwired when DIO2 drives the switch.
+  pub tx_pin: OptionalPin<TX>,
+  pub rx_pin: OptionalPin<RX>,
+  /// Delays around TXEN/RXEN transitions.
+  pub switch_guard: SwitchGuard,
+}
+
+/// An output that may not be wired, e.g. TXEN/RXEN on modules whose RF
+/// switch follows DIO2.  Driving a missing pin does nothing.
+pub struct OptionalPin<P>(Option<P>);
+
+impl<P> OptionalPin<P> {
+  pub fn new(pin: P) -> Self {
+    Self(Some(pin))
+  }
+
+  pub fn none() -> Self {
+    Self(None)
+  }
+
+  pub fn is_wired(&self) -> bool {
+    self.0.is_some()
+  }
+}
+
+impl<P: OutputPin> OptionalPin<P> {
+  pub fn set_high(&mut self) -> Result<(), P::Error> {
+    self.0.as_mut().map_or(Ok(()), OutputPin::set_high)
+  }
+
+  pub fn set_low(&mut self) -> Result<(), P::Error> {
+    self.0.as_mut().map_or(Ok(()), OutputPin::set_low)
+  }
 }
 
-impl<
-  S,
-  const NRST_P: char,
-  const NRST_N: u8,
-  NrstMode,
-  const CS_P: char,
-  const CS_N: u8,
-  CsMode,
-  const BUSY_P: char,
-  const BUSY_N: u8,
-  BusyMode,
-  const TX_P: char,
-  const TX_N: u8,
-  TxMode,
-  const RX_P: char,
-  const RX_N: u8,
-  RxMode,
-> Control
-  for LoraControl<
-    u8,
-    S,
-    NRST_P,
-    NRST_N,
-    NrstMode,
-    CS_P,
-    CS_N,
-    CsMode,
-    BUSY_P,
-    BUSY_N,
-    BusyMode,
-    TX_P,
-    TX_N,
-    TxMode,
-    RX_P,
-    RX_N,
-    RxMode,
-  >
+/// Busy-wait `us` microseconds.
+fn guard_delay(us: u16) {
+  if us > 0 {
+    timer::wait_until_cycles(timer::after_us(timer::now_cycles(), us as u32));
+  }
+}
+
+impl<SPI, NRST, BUSY, TX, RX> Control for LoraControl<SPI, NRST, BUSY, TX, RX>
 where
-  S: Instance,
+  SPI: SpiDevice,
+  NRST: OutputPin,
+  BUSY: InputPin,
+  TX: OutputPin,
+  RX: OutputPin,
 {
   type Status = Status;
-  type Error = sx1268_rs::Error<ControlError<stm32f1xx_hal::spi::Error>>;
+  type Error = sx1268_rs::Error<ControlError<SPI::Error>>;
 
   // -----------------------------------------------------------------------
   // Low-level SPI helpers
@@ -105,18 +155,20 @@ where
 
   /// Write a command with parameters.
   fn write_command(&mut self, opcode: u8, params: &[u8]) -> Result<(), Self::Error> {
-    while self.busy_pin.is_high() {}
-    self.cs_pin.set_low();
-    self.spi.deref_mut().write(&[opcode]).map_err(spi_error)?;
-    self.spi.deref_mut().write(params).map_err(spi_error)?;
+    wait_ready(&mut self.busy_pin)?;
+    self
+      .spi
+      .transaction(&mut [Operation::Write(&[opcode]), Operation::Write(params)])
+      .map_err(spi_error)?;
     defmt::debug!("SPI write cmd=0x{:02X} params={:02X}", opcode, params);
-    self.cs_pin.set_high();
     Ok(())
   }
 
   /// Read a command response.
-  /// The SX1268 protocol sends a status byte after the opcode + NOP, then
-  /// returns the response data.
+  /// The SX1268 clocks out its status while receiving the NOP after the
+  /// opcode, then returns the response data.  `params` must be that single
+  /// NOP, as for every read command in the datasheet: the status is taken
+  /// from the byte clocked out during the last parameter.
   fn read_command(
     &mut self,
     opcode: u8,
@@ -125,17 +177,16 @@ where
   ) -> Result<Status, Self::Error> {
     // SX1268 read command frame (full-duplex view):
     //   MOSI: [opcode]  [params = NOP × m]  [NOP × response.len()]
-    //   MISO: [Status]  [ignored bytes   ]  [response data       ]
+    //   MISO: [RFU   ]  [..., Status     ]  [response data       ]
     //
-    // stm32f1xx-hal's `write()` reads and discards one MISO byte at the
-    // end of every call (to clear the OVR flag).  If the opcode and params
-    // were sent in two separate `write()` calls, the first would discard
-    // MISO[0] = Status and the second would discard MISO[1] = data[0],
-    // causing a one-byte shift in the received data.
+    // A bus `write()` discards MISO; stm32f1xx-hal's also reads one byte at
+    // the end of every call (to clear the OVR flag).  If the opcode and params
+    // were sent in two separate `write()` calls, the second would discard
+    // the status and the reads after it would shift by one byte.
     //
     // Fix: use `transfer_in_place` with a single combined frame so that
-    // only one MISO byte is ever discarded.  The response bytes sit at
-    // frame[1 + params.len()..total].
+    // every MISO byte is kept.  The status sits at frame[params.len()], the
+    // last NOP, and the response bytes at frame[1 + params.len()..total].
     //
     // All callers pass params.len() == 1 and response.len() <= 3, so the
     // maximum frame size is 1 + 1 + 16 = 18 bytes — no heap allocation needed.
@@ -145,19 +196,16 @@ where
     frame[0] = opcode;
     frame[1..1 + params.len()].copy_from_slice(params);
     // frame[1+params.len()..total] 已是 0x00（NOP）
-    while self.busy_pin.is_high() {}
-    self.cs_pin.set_low();
+    wait_ready(&mut self.busy_pin)?;
     self
       .spi
-      .deref_mut()
       .transfer_in_place(&mut frame[..total])
       .map_err(spi_error)?;
-    self.cs_pin.set_high();
-    // MISO[0] = Status（opcode 期间），MISO[1..1+params.len()] = 数据（丢弃）
+    // MISO[params.len()] = Status（最后一个 NOP 期间），其余参数期间的数据丢弃
     // MISO[1+params.len()..total] = response 数据
     let data_start = 1 + params.len();
     response.copy_from_slice(&frame[data_start..total]);
-    let status = Status::from(frame[0]);
+    let status = record_status(frame[params.len()]);
     defmt::trace!(
       "SPI read cmd=0x{:02X} status={} resp={:?}",
       opcode,
@@ -170,11 +218,11 @@ where
   /// Write to registers starting at the given address.
   fn write_register(&mut self, address: u16, data: &[u8]) -> Result<(), Self::Error> {
     let header = [0x0D, (address >> 8) as u8, address as u8];
-    while self.busy_pin.is_high() {}
-    self.cs_pin.set_low();
-    self.spi.deref_mut().write(&header).map_err(spi_error)?;
-    self.spi.deref_mut().write(data).map_err(spi_error)?;
-    self.cs_pin.set_high();
+    wait_ready(&mut self.busy_pin)?;
+    self
+      .spi
+      .transaction(&mut [Operation::Write(&header), Operation::Write(data)])
+      .map_err(spi_error)?;
     defmt::trace!("WriteRegister addr=0x{:04X} data={:?}", address, data);
     Ok(())
   }
@@ -182,27 +230,37 @@ where
   /// Read from registers starting at the given address.
   fn read_register(&mut self, address: u16, data: &mut [u8]) -> Result<(), Self::Error> {
     // SX1268 read register frame:
-    //   MOSI: [0x1D, addr_hi, addr_lo, NOP]  [NOP × data.len()]
-    //   MISO: [x,    x,       x,       STATUS(discarded)]  [data0, data1, ...]
-    // The trailing NOP in the header causes STATUS to be clocked out and
-    // discarded by write(). data bytes follow directly after.
-    let header = [0x1D, (address >> 8) as u8, address as u8, 0x00];
-    while self.busy_pin.is_high() {}
-    self.cs_pin.set_low();
-    self.spi.deref_mut().write(&header).map_err(spi_error)?;
-    self.spi.deref_mut().read(data).map_err(spi_error)?;
-    self.cs_pin.set_high();
+    //   MOSI: [0x1D, addr_hi, addr_lo, NOP]     [NOP × data.len()]
+    //   MISO: [x,    x,       x,       STATUS]  [data0, data1, ...]
+    // The header goes out full duplex so the STATUS clocked out during the
+    // trailing NOP is kept; data bytes follow directly after.
+    let mut header = [0x1D, (address >> 8) as u8, address as u8, 0x00];
+    wait_ready(&mut self.busy_pin)?;
+    self
+      .spi
+      .transaction(&mut [
+        Operation::TransferInPlace(&mut header),
+        Operation::Read(data),
+      ])
+      .map_err(spi_error)?;
+    let status = record_status(header[3]);
+    defmt::trace!(
+      "ReadRegister addr=0x{:04X} status={} data={:?}",
+      address,
+      status,
+      data
+    );
     Ok(())
   }
 
   /// Write data to the TX buffer at the given offset.
   fn write_buffer(&mut self, offset: u8, data: &[u8]) -> Result<(), Self::Error> {
     let header = [sx1268_rs::codes::WRITE_BUFFER, offset];
-    while self.busy_pin.is_high() {}
-    self.cs_pin.set_low();
-    self.spi.deref_mut().write(&header).map_err(spi_error)?;
-    self.spi.deref_mut().write(data).map_err(spi_error)?;
-    self.cs_pin.set_high();
+    wait_ready(&mut self.busy_pin)?;
+    self
+      .spi
+      .transaction(&mut [Operation::Write(&header), Operation::Write(data)])
+      .map_err(spi_error)?;
     defmt::trace!("WriteBuffer offset={} len={}", offset, data.len());
     Ok(())
   }
@@ -210,63 +268,191 @@ where
   /// Read data from the RX buffer at the given offset.
   fn read_buffer(&mut self, offset: u8, data: &mut [u8]) -> Result<(), Self::Error> {
     // SX1268 read buffer frame:
-    //   MOSI: [READ_BUFFER, offset, NOP]  [NOP × data.len()]
-    //   MISO: [x,           x,     STATUS(discarded)]  [payload0, payload1, ...]
-    // The trailing NOP in the header causes STATUS to be clocked out and
-    // discarded by write(). Payload bytes follow directly after.
-    let header = [sx1268_rs::codes::READ_BUFFER, offset, 0x00];
-    while self.busy_pin.is_high() {}
-    self.cs_pin.set_low();
-    self.spi.deref_mut().write(&header).map_err(spi_error)?;
-    self.spi.deref_mut().read(data).map_err(spi_error)?;
-    self.cs_pin.set_high();
-    defmt::trace!("ReadBuffer offset={} len={}", offset, data.len());
+    //   MOSI: [READ_BUFFER, offset, NOP]     [NOP × data.len()]
+    //   MISO: [x,           x,      STATUS]  [payload0, payload1, ...]
+    // As for registers, the header goes out full duplex to keep STATUS;
+    // payload bytes follow directly after.
+    let mut header = [sx1268_rs::codes::READ_BUFFER, offset, 0x00];
+    wait_ready(&mut self.busy_pin)?;
+    self
+      .spi
+      .transaction(&mut [
+        Operation::TransferInPlace(&mut header),
+        Operation::Read(data),
+      ])
+      .map_err(spi_error)?;
+    let status = record_status(header[2]);
+    defmt::trace!(
+      "ReadBuffer offset={} len={} status={}",
+      offset,
+      data.len(),
+      status
+    );
     Ok(())
   }
 
   /// Get the device status.
   fn get_status(&mut self) -> Result<Status, Self::Error> {
-    let mut status_byte = [0u8; 1];
-    while self.busy_pin.is_high() {}
-    self.cs_pin.set_low();
-    self.spi.deref_mut().write(&[sx1268_rs::codes::GET_STATUS]).map_err(spi_error)?;
-    self
-      .spi
-      .deref_mut()
-      .read(&mut status_byte)
-      .map_err(spi_error)?;
-    self.cs_pin.set_high();
-    let status = Status::from(status_byte[0]);
+    // MOSI: [GET_STATUS, NOP], MISO: [RFU, STATUS].
+    let mut frame = [sx1268_rs::codes::GET_STATUS, 0x00];
+    wait_ready(&mut self.busy_pin)?;
+    self.spi.transfer_in_place(&mut frame).map_err(spi_error)?;
+    let status = record_status(frame[1]);
     defmt::debug!("GetStatus status={}", status);
     Ok(status)
   }
 
   fn reset(&mut self) -> Result<(), Self::Error> {
-    self.nrst_pin.set_low();
+    self.nrst_pin.set_low().map_err(pin_error)?;
     cortex_m::asm::delay(10_000); // 10ms delay
-    self.nrst_pin.set_high();
+    self.nrst_pin.set_high().map_err(pin_error)?;
     cortex_m::asm::delay(10_000); // 10ms delay
     Ok(())
   }
 
   fn wakeup(&mut self) -> Result<(), Self::Error> {
-    // To wake up from sleep, just toggle CS
-    self.cs_pin.set_low();
-    cortex_m::asm::delay(10); // Short delay
-    self.cs_pin.set_high();
+    // To wake up from sleep, just toggle NSS: an empty transaction.
+    self.spi.transaction(&mut []).map_err(spi_error)?;
     cortex_m::asm::delay(10); // Short delay
     Ok(())
   }
 
   fn switch_rx(&mut self, _: u32) -> Result<(), Self::Error> {
-    self.tx_pin.set_low();
-    self.rx_pin.set_high();
+    if !self.rx_pin.is_wired() {
+      return Ok(());
+    }
+    // Break before make, then let the switch settle.
+    self.tx_pin.set_low().map_err(pin_error)?;
+    guard_delay(self.switch_guard.pre_us);
+    self.rx_pin.set_high().map_err(pin_error)?;
+    guard_delay(self.switch_guard.post_us);
     Ok(())
   }
 
   fn switch_tx(&mut self, _: u32) -> Result<(), Self::Error> {
-    self.rx_pin.set_low();
-    self.tx_pin.set_high();
+    if !self.tx_pin.is_wired() {
+      return Ok(());
+    }
+    self.rx_pin.set_low().map_err(pin_error)?;
+    guard_delay(self.switch_guard.pre_us);
+    self.tx_pin.set_high().map_err(pin_error)?;
+    guard_delay(self.switch_guard.post_us);
     Ok(())
   }
 }
+
+/// Where a [`SharedControl`] keeps its control.
+///
+/// A flag marks the control as taken for the length of one access, so a
+/// second access while it is taken panics, as a `RefCell` would, instead
+/// of aliasing it.  Unlike a `RefCell` the cell is `Sync`: the radio
+/// holding the copies may move to an interrupt handler (see
+/// [`crate::radio_handle`]).
+pub struct ControlCell<C> {
+  taken: AtomicBool,
+  control: UnsafeCell<C>,
+}
+
+// SAFETY: `SharedControl::with` is the only way to the control and hands
+// it out only after setting `taken`, which it checks atomically, so no two
+// contexts ever hold it at once and sharing the cell only moves `C`.
+unsafe impl<C: Send> Sync for ControlCell<C> {}
+
+impl<C> ControlCell<C> {
+  pub const fn new(control: C) -> Self {
+    Self {
+      taken: AtomicBool::new(false),
+      control: UnsafeCell::new(control),
+    }
+  }
+}
+
+/// A [`Control`] shared between the driver and direct command helpers.
+///
+/// The driver owns one copy; others issue commands the driver does not
+/// expose (RSSI sweeps, register access).  Each access takes the control
+/// for a single SPI transaction only.
+pub struct SharedControl<C: 'static>(&'static ControlCell<C>);
+
+impl<C> Clone for SharedControl<C> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<C> Copy for SharedControl<C> {}
+
+impl<C> SharedControl<C> {
+  pub fn new(control: &'static ControlCell<C>) -> Self {
+    Self(control)
+  }
+
+  /// Run `f` with exclusive access to the control; panics if it is
+  /// already taken.
+  pub fn with<R>(&self, f: impl FnOnce(&mut C) -> R) -> R {
+    let cell = self.0;
+    assert!(
+      !cell.taken.swap(true, Ordering::Acquire),
+      "radio control already taken"
+    );
+    // SAFETY: `taken` was clear and is now set, so this is the only
+    // reference to the control until it is cleared below.
+    let result = f(unsafe { &mut *cell.control.get() });
+    cell.taken.store(false, Ordering::Release);
+    result
+  }
+}
+
+impl<C: Control> Control for SharedControl<C> {
+  type Status = C::Status;
+  type Error = C::Error;
+
+  fn write_command(&mut self, opcode: u8, params: &[u8]) -> Result<(), Self::Error> {
+    self.with(|control| control.write_command(opcode, params))
+  }
+
+  fn read_command(
+    &mut self,
+    opcode: u8,
+    params: &[u8],
+    response: &mut [u8],
+  ) -> Result<Self::Status, Self::Error> {
+    self.with(|control| control.read_command(opcode, params, response))
+  }
+
+  fn write_register(&mut self, address: u16, data: &[u8]) -> Result<(), Self::Error> {
+    self.with(|control| control.write_register(address, data))
+  }
+
+  fn read_register(&mut self, address: u16, data: &mut [u8]) -> Result<(), Self::Error> {
+    self.with(|control| control.read_register(address, data))
+  }
+
+  fn write_buffer(&mut self, offset: u8, data: &[u8]) -> Result<(), Self::Error> {
+    self.with(|control| control.write_buffer(offset, data))
+  }
+
+  fn read_buffer(&mut self, offset: u8, data: &mut [u8]) -> Result<(), Self::Error> {
+    self.with(|control| control.read_buffer(offset, data))
+  }
+
+  fn get_status(&mut self) -> Result<Self::Status, Self::Error> {
+    self.with(|control| control.get_status())
+  }
+
+  fn reset(&mut self) -> Result<(), Self::Error> {
+    self.with(|control| control.reset())
+  }
+
+  fn wakeup(&mut self) -> Result<(), Self::Error> {
+    self.with(|control| control.wakeup())
+  }
+
+  fn switch_rx(&mut self, timeout: u32) -> Result<(), Self::Error> {
+    self.with(|control| control.switch_rx(timeout))
+  }
+
+  fn switch_tx(&mut self, timeout: u32) -> Result<(), Self::Error> {
+    self.with(|control| control.switch_tx(timeout))
+  }
+}
@@ -0,0 +1,180 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/lora_config.rs - 编译期校验的 LoRa 配置构建器
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Compile-time checked LoRa configurations.
+//!
+//! The configurations written into the source — the link defaults and the
+//! pairing channel — are built with [`LoRaConfigBuilder`] in `const`
+//! items, so a hand edit the radio cannot carry out fails the build:
+//!
+//! ```text
+//! pub const LINK: LoRaConfig = LoRaConfigBuilder::new(band::DEFAULT_FREQUENCY_HZ)
+//!   .sf(13)
+//!   .build();
+//! // error[E0080]: evaluation of constant value failed
+//! //   LoRa config: SF outside 7..=12
+//! ```
+//!
+//! The checks:
+//!
+//! * the frequency lies in the band of the fitted module ([`band`]);
+//! * the power lies in the range of the PA the backend configures,
+//!   [`MIN_POWER_DBM`]`..=`[`MAX_POWER_DBM`];
+//! * SF 7–12: SF5 and SF6 need the implicit header the link does not use.
+//!   Every one of them pairs with every supported bandwidth, since the
+//!   link switches on low data rate optimisation wherever the symbol time
+//!   calls for it;
+//! * coding rate 4/5–4/8;
+//! * preamble of [`MIN_PREAMBLE_SYMBOLS`] symbols or more, the SX127x
+//!   minimum.
+//!
+//! Settings that arrive at runtime are checked by
+//! [`RadioParams::is_valid`] instead.
+
+use crate::band;
+use crate::radio::{Bandwidth, RadioParams};
+
+/// Output power range of the PA: the SX126x high-power PA, or PA_BOOST on
+/// the SX127x.
+#[cfg(feature = "driver-sx1268-rs")]
+pub const MIN_POWER_DBM: i8 = -9;
+#[cfg(feature = "driver-sx1268-rs")]
+pub const MAX_POWER_DBM: i8 = 22;
+#[cfg(feature = "sx1276")]
+pub const MIN_POWER_DBM: i8 = 2;
+#[cfg(feature = "sx1276")]
+pub const MAX_POWER_DBM: i8 = 20;
+pub const MIN_PREAMBLE_SYMBOLS: u16 = 6;
+
+/// The bridge link: the defaults of a fresh bridge.
+pub const LINK: LoRaConfig = LoRaConfigBuilder::new(band::DEFAULT_FREQUENCY_HZ)
+  .power_dbm(20)
+  .sf(11)
+  .bandwidth(Bandwidth::Khz500)
+  .cr(5)
+  .preamble_symbols(8)
+  .build();
+
+/// The pairing channel: low power, robust modulation.
+pub const PAIRING: LoRaConfig = LoRaConfigBuilder::new(band::PAIRING_FREQUENCY_HZ)
+  .power_dbm(if MIN_POWER_DBM > 0 { MIN_POWER_DBM } else { 0 })
+  .sf(9)
+  .bandwidth(Bandwidth::Khz125)
+  .cr(5)
+  .preamble_symbols(LINK.preamble_symbols)
+  .build();
+
+// The LoRaWAN RX2 window reuses the uplink settings on another channel.
+const _: () = assert!(
+  in_band(band::LORAWAN_RX2_FREQUENCY_HZ),
+  "LoRa config: LoRaWAN RX2 frequency outside the band"
+);
+
+/// A checked configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct LoRaConfig {
+  pub params: RadioParams,
+  pub preamble_symbols: u16,
+}
+
+/// What a configuration got wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ConfigError {
+  Frequency,
+  Power,
+  SpreadingFactor,
+  CodingRate,
+  Preamble,
+}
+
+pub struct LoRaConfigBuilder {
+  config: LoRaConfig,
+}
+
+impl LoRaConfigBuilder {
+  /// Start from `frequency_hz` with the modulation of [`LINK`].
+  pub const fn new(frequency_hz: u32) -> Self {
+    Self {
+      config: LoRaConfig {
+        params: RadioParams {
+          frequency_hz,
+          power_dbm: 20,
+          sf: 11,
+          bandwidth: Bandwidth::Khz500,
+          cr: 5,
+        },
+        preamble_symbols: 8,
+      },
+    }
+  }
+
+  pub const fn power_dbm(mut self, power_dbm: i8) -> Self {
+    self.config.params.power_dbm = power_dbm;
+    self
+  }
+
+  pub const fn sf(mut self, sf: u8) -> Self {
+    self.config.params.sf = sf;
+    self
+  }
+
+  pub const fn bandwidth(mut self, bandwidth: Bandwidth) -> Self {
+    self.config.params.bandwidth = bandwidth;
+    self
+  }
+
+  /// Coding rate denominator, 5 for 4/5.
+  pub const fn cr(mut self, cr: u8) -> Self {
+    self.config.params.cr = cr;
+    self
+  }
+
+  pub const fn preamble_symbols(mut self, symbols: u16) -> Self {
+    self.config.preamble_symbols = symbols;
+    self
+  }
+
+  pub const fn check(&self) -> Result<(), ConfigError> {
+    let params = &self.config.params;
+    if !in_band(params.frequency_hz) {
+      Err(ConfigError::Frequency)
+    } else if params.power_dbm < MIN_POWER_DBM || params.power_dbm > MAX_POWER_DBM {
+      Err(ConfigError::Power)
+    } else if params.sf < 7 || params.sf > 12 {
+      Err(ConfigError::SpreadingFactor)
+    } else if params.cr < 5 || params.cr > 8 {
+      Err(ConfigError::CodingRate)
+    } else if self.config.preamble_symbols < MIN_PREAMBLE_SYMBOLS {
+      Err(ConfigError::Preamble)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// The configuration; in a `const` item an invalid one fails the build.
+  pub const fn build(self) -> LoRaConfig {
+    match self.check() {
+      Ok(()) => self.config,
+      Err(ConfigError::Frequency) => panic!("LoRa config: frequency outside the band"),
+      Err(ConfigError::Power) => panic!("LoRa config: power outside the PA's range"),
+      Err(ConfigError::SpreadingFactor) => panic!("LoRa config: SF outside 7..=12"),
+      Err(ConfigError::CodingRate) => panic!("LoRa config: coding rate outside 4/5..=4/8"),
+      Err(ConfigError::Preamble) => panic!("LoRa config: preamble under 6 symbols"),
+    }
+  }
+}
+
+/// Whether the fitted module tunes to `frequency_hz`.
+pub const fn in_band(frequency_hz: u32) -> bool {
+  *band::FREQUENCY_HZ.start() <= frequency_hz && frequency_hz <= *band::FREQUENCY_HZ.end()
+}
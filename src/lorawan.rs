@@ -0,0 +1,595 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/lorawan.rs - LoRaWAN A 类终端（ABP）
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! LoRaWAN 1.0.x Class A end device.
+//!
+//! Next to point-to-point bridging the radio can talk to a LoRaWAN
+//! network.  A session comes either from activation by personalisation,
+//! `AT+LWABP=<DevAddr>,<NwkSKey>,<AppSKey>`, or from over-the-air
+//! activation: `AT+LWOTAA=<DevEUI>,<AppEUI>,<AppKey>` stores the device
+//! identity and `AT+LWJOIN` sends a join request.  A join accept in one of
+//! the join windows ([`JOIN_ACCEPT_DELAY1_MS`], one second later RX2)
+//! yields the session keys, DevAddr and the RX settings of the network,
+//! all persisted, and is reported as `+LWJOIN:<DevAddr>`; otherwise
+//! `+LWJOIN:FAIL`.  DevNonce counts up, from a random value in fresh
+//! settings (see [`crate::random`]), and is persisted before every join
+//! request, so a reboot never repeats one.  A CFList is ignored.
+//!
+//! `AT+LWSEND=<port>,<0|1>,<hex>` sends one (un)confirmed uplink.  Then the
+//! two Class A receive windows open:
+//!
+//! * RX1, RxDelay (default [`RECEIVE_DELAY1_MS`]) after the uplink ended,
+//!   on the uplink channel and the data rate lowered by RX1DROffset;
+//! * RX2, one second later, on the regional RX2 channel
+//!   ([`band::LORAWAN_RX2_FREQUENCY_HZ`]) at the RX2 data rate, SF12 /
+//!   125 kHz by default.
+//!
+//! RX1 stays open until RX2 opens and RX2 for [`RX2_WINDOW_MS`].  The
+//! first valid downlink ends the exchange and goes to the host as
+//! `+LWRX:<port>,<hex>`; `+LWRX:NONE` reports that neither window brought
+//! one.  The bridge forwards no traffic meanwhile.
+//!
+//! Frames follow LoRaWAN 1.0.x: MHDR, FHDR (DevAddr, FCtrl, FCnt), FPort and
+//! FRMPayload encrypted with AES-128 in the standard's counter mode, under
+//! AppSKey (NwkSKey on port 0), then the first four bytes of an AES-CMAC
+//! under NwkSKey.  FCntUp is reserved in flash in blocks of
+//! [`COUNTER_BLOCK`] like the link counter, so a reboot never reuses one;
+//! the network sees a gap.  FCntDown is checked in RAM only.
+//!
+//! Uplinks use the bridge's radio parameters (`AT+RADIO`) at coding rate
+//! 4/5, which must name a channel and data rate the network listens on.
+//! Only the ACK bit of downlinks is acted on; MAC commands are not
+//! interpreted.
+
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::{Aes128, Block};
+use cmac::{Cmac, Mac};
+use heapless::Vec;
+
+use crate::band;
+use crate::packetizer::MAX_PAYLOAD;
+use crate::radio::{Bandwidth, RadioParams};
+
+/// Largest application payload of an uplink.
+pub const MAX_APP_PAYLOAD: usize = 222;
+/// FCntUp values reserved per flash write.
+pub const COUNTER_BLOCK: u32 = 256;
+pub const RECEIVE_DELAY1_MS: u32 = 1_000;
+pub const RECEIVE_DELAY2_MS: u32 = 2_000;
+pub const JOIN_ACCEPT_DELAY1_MS: u32 = 5_000;
+/// How long RX2 listens, enough for a short downlink at SF12 that starts
+/// as the window opens.
+pub const RX2_WINDOW_MS: u32 = 3_000;
+/// Windows open this much early to cover the radio reconfiguration.
+const WINDOW_LEAD_MS: u32 = 50;
+
+const MHDR_JOIN_REQUEST: u8 = 0x00;
+const MHDR_JOIN_ACCEPT: u8 = 0x20;
+const MHDR_UNCONFIRMED_UP: u8 = 0x40;
+const MHDR_CONFIRMED_UP: u8 = 0x80;
+const MHDR_UNCONFIRMED_DOWN: u8 = 0x60;
+const MHDR_CONFIRMED_DOWN: u8 = 0xA0;
+const FCTRL_ACK: u8 = 0x20;
+const FCTRL_FPENDING: u8 = 0x10;
+const FCTRL_FOPTS_LEN: u8 = 0x0F;
+/// MHDR, DevAddr, FCtrl, FCnt.
+const FHDR_LEN: usize = 8;
+const MIC_LEN: usize = 4;
+const DIR_UP: u8 = 0;
+const DIR_DOWN: u8 = 1;
+
+/// Join accept without and with CFList.
+const JOIN_ACCEPT_LEN: usize = 17;
+const JOIN_ACCEPT_CFLIST_LEN: usize = 33;
+
+/// Persisted session, from ABP or a join.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Session {
+  pub dev_addr: u32,
+  pub nwk_skey: [u8; 16],
+  pub app_skey: [u8; 16],
+  /// DLSettings of the join accept: RX1DROffset in bits 6..4, the RX2
+  /// data rate in bits 3..0.  `0` for ABP.
+  pub dl_settings: u8,
+  /// RX1 delay in seconds, `0` meaning 1.
+  pub rx_delay: u8,
+}
+
+impl Session {
+  /// Bytes of [`encode`](Self::encode); the RX settings are stored
+  /// apart, see [`encode_rx`](Self::encode_rx).
+  pub const ENCODED_LEN: usize = 36;
+
+  /// Whether a session was stored; DevAddr 0 means none.
+  pub fn is_set(&self) -> bool {
+    self.dev_addr != 0
+  }
+
+  pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+    let mut bytes = [0u8; Self::ENCODED_LEN];
+    bytes[..4].copy_from_slice(&self.dev_addr.to_le_bytes());
+    bytes[4..20].copy_from_slice(&self.nwk_skey);
+    bytes[20..].copy_from_slice(&self.app_skey);
+    bytes
+  }
+
+  pub fn decode(bytes: [u8; Self::ENCODED_LEN]) -> Self {
+    let mut session = Self {
+      dev_addr: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+      ..Self::default()
+    };
+    session.nwk_skey.copy_from_slice(&bytes[4..20]);
+    session.app_skey.copy_from_slice(&bytes[20..]);
+    session
+  }
+
+  pub fn encode_rx(&self) -> [u8; 2] {
+    [self.dl_settings, self.rx_delay]
+  }
+
+  pub fn decode_rx(&mut self, bytes: [u8; 2]) {
+    [self.dl_settings, self.rx_delay] = bytes;
+  }
+
+  fn rx1_delay_ms(&self) -> u32 {
+    self.rx_delay.clamp(1, 15) as u32 * 1_000
+  }
+
+  /// Spreading factor of RX1 for an uplink at `sf`.
+  fn rx1_sf(&self, sf: u8) -> u8 {
+    (sf + (self.dl_settings >> 4 & 0x07)).min(12)
+  }
+
+  /// Spreading factor of RX2; data rates 0..=5 are SF12..SF7 at 125 kHz.
+  fn rx2_sf(&self) -> u8 {
+    12 - (self.dl_settings & 0x0F).min(5)
+  }
+}
+
+/// Persisted over-the-air activation identity.  EUIs are kept in the
+/// order they are written, most significant byte first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Otaa {
+  pub dev_eui: [u8; 8],
+  pub app_eui: [u8; 8],
+  pub app_key: [u8; 16],
+}
+
+impl Otaa {
+  /// Bytes of [`encode`](Self::encode).
+  pub const ENCODED_LEN: usize = 32;
+
+  /// Whether an identity was stored; DevEUI 0 means none.
+  pub fn is_set(&self) -> bool {
+    self.dev_eui != [0; 8]
+  }
+
+  pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+    let mut bytes = [0u8; Self::ENCODED_LEN];
+    bytes[..8].copy_from_slice(&self.dev_eui);
+    bytes[8..16].copy_from_slice(&self.app_eui);
+    bytes[16..].copy_from_slice(&self.app_key);
+    bytes
+  }
+
+  pub fn decode(bytes: [u8; Self::ENCODED_LEN]) -> Self {
+    let mut otaa = Self::default();
+    otaa.dev_eui.copy_from_slice(&bytes[..8]);
+    otaa.app_eui.copy_from_slice(&bytes[8..16]);
+    otaa.app_key.copy_from_slice(&bytes[16..]);
+    otaa
+  }
+}
+
+/// An uplink requested by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Uplink {
+  pub port: u8,
+  pub confirmed: bool,
+  len: u8,
+  data: [u8; MAX_APP_PAYLOAD],
+}
+
+impl Uplink {
+  /// Accept application ports 1..=223 and payloads up to
+  /// [`MAX_APP_PAYLOAD`].
+  pub fn new(port: u8, confirmed: bool, data: &[u8]) -> Option<Self> {
+    if !(1..=223).contains(&port) || data.len() > MAX_APP_PAYLOAD {
+      return None;
+    }
+    let mut uplink = Self {
+      port,
+      confirmed,
+      len: data.len() as u8,
+      data: [0; MAX_APP_PAYLOAD],
+    };
+    uplink.data[..data.len()].copy_from_slice(data);
+    Some(uplink)
+  }
+
+  pub fn data(&self) -> &[u8] {
+    &self.data[..self.len as usize]
+  }
+}
+
+/// A verified and decrypted downlink.
+#[derive(Debug, PartialEq, Eq, defmt::Format)]
+pub struct Downlink<'a> {
+  /// `None` for a frame without FPort (MAC commands in FOpts only).
+  pub port: Option<u8>,
+  pub payload: &'a [u8],
+  /// The network acknowledged the last confirmed uplink.
+  pub ack: bool,
+  /// The network has more data queued.
+  pub pending: bool,
+}
+
+/// Why a received frame is not a downlink for this device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Reject {
+  Truncated,
+  NotDownlink,
+  OtherDevice(u32),
+  BadMic,
+  Replay { counter: u32, last: u32 },
+}
+
+/// What the receive windows need from the radio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Step {
+  /// Listen for downlinks with these parameters.
+  Open(RadioParams),
+  /// Both windows passed without a downlink, or without a join accept
+  /// when `join`.
+  Close { join: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Window {
+  Idle,
+  /// Uplink sent at `tx_end`, RX1 not yet open.
+  Waiting { tx_end: u32 },
+  Rx1 { tx_end: u32 },
+  Rx2 { tx_end: u32 },
+}
+
+/// A join request waiting for its accept.
+#[derive(Debug, Clone, Copy)]
+struct Join {
+  app_key: [u8; 16],
+  dev_nonce: u16,
+}
+
+pub struct Device {
+  session: Session,
+  fcnt_up: u32,
+  /// First FCntUp not covered by the persisted reservation.
+  reserved_until: u32,
+  /// Last accepted FCntDown.
+  fcnt_down: Option<u32>,
+  /// A confirmed downlink is to be acknowledged in the next uplink.
+  ack_pending: bool,
+  /// Parameters of the last uplink, the base of both windows.
+  uplink: RadioParams,
+  window: Window,
+  join: Option<Join>,
+}
+
+impl Device {
+  /// Start from the persisted FCntUp base.  The caller must persist
+  /// [`reservation`](Self::reservation) before transmitting.
+  pub fn new(session: Session, fcnt_base: u32) -> Self {
+    Self {
+      session,
+      fcnt_up: fcnt_base,
+      reserved_until: fcnt_base.saturating_add(COUNTER_BLOCK),
+      fcnt_down: None,
+      ack_pending: false,
+      uplink: RadioParams::default(),
+      window: Window::Idle,
+      join: None,
+    }
+  }
+
+  pub fn session(&self) -> &Session {
+    &self.session
+  }
+
+  /// Switch to a new session; frame counters start over.
+  pub fn set_session(&mut self, session: Session) {
+    *self = Self::new(session, 0);
+  }
+
+  pub fn fcnt_up(&self) -> u32 {
+    self.fcnt_up
+  }
+
+  pub fn fcnt_down(&self) -> Option<u32> {
+    self.fcnt_down
+  }
+
+  /// FCntUp base to persist so that a reboot never reuses a counter.
+  pub fn reservation(&self) -> u32 {
+    self.reserved_until
+  }
+
+  /// Whether no uplink exchange is under way.
+  pub fn idle(&self) -> bool {
+    self.window == Window::Idle
+  }
+
+  /// Whether the exchange under way is a join.
+  pub fn joining(&self) -> bool {
+    self.join.is_some()
+  }
+
+  /// Whether a receive window is open.
+  pub fn listening(&self) -> bool {
+    matches!(self.window, Window::Rx1 { .. } | Window::Rx2 { .. })
+  }
+
+  /// Radio parameters of an uplink: the bridge's channel and data rate at
+  /// the coding rate LoRaWAN uses.
+  pub fn uplink_params(params: &RadioParams) -> RadioParams {
+    RadioParams { cr: 5, ..*params }
+  }
+
+  /// Build the frame for `uplink` into `out`.
+  ///
+  /// Returns `true` when the counter block is exhausted and
+  /// [`reservation`](Self::reservation) moved on and must be persisted.
+  pub fn encode(&mut self, uplink: &Uplink, out: &mut Vec<u8, MAX_PAYLOAD>) -> bool {
+    self.join = None;
+    let fcnt = self.fcnt_up;
+    self.fcnt_up = self.fcnt_up.wrapping_add(1);
+
+    out.clear();
+    let mhdr = if uplink.confirmed { MHDR_CONFIRMED_UP } else { MHDR_UNCONFIRMED_UP };
+    let fctrl = if self.ack_pending { FCTRL_ACK } else { 0 };
+    self.ack_pending = false;
+    let _ = out.push(mhdr);
+    let _ = out.extend_from_slice(&self.session.dev_addr.to_le_bytes());
+    let _ = out.push(fctrl);
+    let _ = out.extend_from_slice(&(fcnt as u16).to_le_bytes());
+    let _ = out.push(uplink.port);
+    let start = out.len();
+    let _ = out.extend_from_slice(uplink.data());
+    let key = self.session.app_skey;
+    self.crypt(&key, DIR_UP, fcnt, &mut out[start..]);
+    let mic = self.mic(DIR_UP, fcnt, out);
+    let _ = out.extend_from_slice(&mic);
+
+    if self.fcnt_up >= self.reserved_until {
+      self.reserved_until = self.reserved_until.saturating_add(COUNTER_BLOCK);
+      return true;
+    }
+    false
+  }
+
+  /// Build a join request for `otaa` with `dev_nonce` into `out`.  The
+  /// caller persists the next DevNonce first.
+  pub fn encode_join(&mut self, otaa: &Otaa, dev_nonce: u16, out: &mut Vec<u8, MAX_PAYLOAD>) {
+    out.clear();
+    let _ = out.push(MHDR_JOIN_REQUEST);
+    let _ = out.extend_from_slice(&reversed(otaa.app_eui));
+    let _ = out.extend_from_slice(&reversed(otaa.dev_eui));
+    let _ = out.extend_from_slice(&dev_nonce.to_le_bytes());
+    let mic = cmac(&otaa.app_key, out);
+    let _ = out.extend_from_slice(&mic);
+    self.join = Some(Join {
+      app_key: otaa.app_key,
+      dev_nonce,
+    });
+  }
+
+  /// The uplink or join request went out with `params`; the receive
+  /// windows follow.
+  pub fn sent(&mut self, params: &RadioParams, now: u32) {
+    self.uplink = *params;
+    self.window = Window::Waiting { tx_end: now };
+  }
+
+  /// Forget a join request that never went out.
+  pub fn cancel(&mut self) {
+    self.join = None;
+    self.window = Window::Idle;
+  }
+
+  /// Advance the receive windows.
+  pub fn poll(&mut self, now: u32) -> Option<Step> {
+    let delay1_ms = match self.join {
+      Some(_) => JOIN_ACCEPT_DELAY1_MS,
+      None => self.session.rx1_delay_ms(),
+    };
+    let delay2_ms = delay1_ms + RECEIVE_DELAY2_MS - RECEIVE_DELAY1_MS;
+    // Join windows use the defaults; the session's settings are not known
+    // yet.
+    let settings = match self.join {
+      Some(_) => Session::default(),
+      None => self.session,
+    };
+    let due = |tx_end: u32, delay_ms: u32| now.wrapping_sub(tx_end) >= delay_ms;
+    match self.window {
+      Window::Waiting { tx_end } if due(tx_end, delay1_ms - WINDOW_LEAD_MS) => {
+        self.window = Window::Rx1 { tx_end };
+        Some(Step::Open(RadioParams {
+          sf: settings.rx1_sf(self.uplink.sf),
+          ..self.uplink
+        }))
+      }
+      Window::Rx1 { tx_end } if due(tx_end, delay2_ms - WINDOW_LEAD_MS) => {
+        self.window = Window::Rx2 { tx_end };
+        Some(Step::Open(RadioParams {
+          frequency_hz: band::LORAWAN_RX2_FREQUENCY_HZ,
+          sf: settings.rx2_sf(),
+          bandwidth: Bandwidth::Khz125,
+          ..self.uplink
+        }))
+      }
+      Window::Rx2 { tx_end } if due(tx_end, delay2_ms + RX2_WINDOW_MS) => {
+        self.window = Window::Idle;
+        Some(Step::Close {
+          join: self.join.take().is_some(),
+        })
+      }
+      _ => None,
+    }
+  }
+
+  /// Check a frame heard in a join window.  A valid join accept replaces
+  /// the session, restarts the frame counters and closes the windows; the
+  /// caller persists the new session and
+  /// [`reservation`](Self::reservation).
+  pub fn join_accept(&mut self, frame: &mut [u8]) -> Result<Session, Reject> {
+    let Some(join) = self.join else {
+      return Err(Reject::NotDownlink);
+    };
+    if frame.len() != JOIN_ACCEPT_LEN && frame.len() != JOIN_ACCEPT_CFLIST_LEN {
+      return Err(Reject::Truncated);
+    }
+    if frame[0] != MHDR_JOIN_ACCEPT {
+      return Err(Reject::NotDownlink);
+    }
+    // The network encrypts with AES decryption, so encryption undoes it.
+    let cipher = Aes128::new_from_slice(&join.app_key).unwrap();
+    for chunk in frame[1..].chunks_exact_mut(16) {
+      cipher.encrypt_block(Block::from_mut_slice(chunk));
+    }
+    let (message, mic) = frame.split_at(frame.len() - MIC_LEN);
+    if cmac(&join.app_key, message) != *mic {
+      return Err(Reject::BadMic);
+    }
+
+    let [_, n0, n1, n2, i0, i1, i2, a0, a1, a2, a3, dl_settings, rx_delay, ..] = *message else {
+      return Err(Reject::Truncated);
+    };
+    let [d0, d1] = join.dev_nonce.to_le_bytes();
+    let derive = |tag: u8| {
+      let mut block = Block::from([tag, n0, n1, n2, i0, i1, i2, d0, d1, 0, 0, 0, 0, 0, 0, 0]);
+      cipher.encrypt_block(&mut block);
+      <[u8; 16]>::from(block)
+    };
+    let session = Session {
+      dev_addr: u32::from_le_bytes([a0, a1, a2, a3]),
+      nwk_skey: derive(0x01),
+      app_skey: derive(0x02),
+      dl_settings,
+      rx_delay,
+    };
+    *self = Self::new(session, 0);
+    Ok(session)
+  }
+
+  /// Verify and decrypt a frame heard in a receive window.  A valid
+  /// downlink closes the windows.
+  pub fn downlink<'a>(&mut self, frame: &'a mut [u8]) -> Result<Downlink<'a>, Reject> {
+    if frame.len() < FHDR_LEN + MIC_LEN {
+      return Err(Reject::Truncated);
+    }
+    let mhdr = frame[0];
+    if mhdr != MHDR_UNCONFIRMED_DOWN && mhdr != MHDR_CONFIRMED_DOWN {
+      return Err(Reject::NotDownlink);
+    }
+    let dev_addr = u32::from_le_bytes([frame[1], frame[2], frame[3], frame[4]]);
+    if dev_addr != self.session.dev_addr {
+      return Err(Reject::OtherDevice(dev_addr));
+    }
+    let fctrl = frame[5];
+    // Extend the 16-bit FCnt with the upper half of the last one.
+    let fcnt_low = u16::from_le_bytes([frame[6], frame[7]]) as u32;
+    let last = self.fcnt_down.unwrap_or(0);
+    let mut fcnt = (last & 0xFFFF_0000) | fcnt_low;
+    if self.fcnt_down.is_some() && fcnt <= last {
+      fcnt = fcnt.wrapping_add(0x1_0000);
+    }
+
+    let (message, mic) = frame.split_at_mut(frame.len() - MIC_LEN);
+    if self.mic(DIR_DOWN, fcnt, message) != *mic {
+      return Err(Reject::BadMic);
+    }
+    if let Some(last) = self.fcnt_down
+      && fcnt <= last
+    {
+      return Err(Reject::Replay { counter: fcnt, last });
+    }
+
+    let fopts_end = FHDR_LEN + (fctrl & FCTRL_FOPTS_LEN) as usize;
+    if message.len() < fopts_end {
+      return Err(Reject::Truncated);
+    }
+    self.fcnt_down = Some(fcnt);
+    self.ack_pending = mhdr == MHDR_CONFIRMED_DOWN;
+    self.window = Window::Idle;
+
+    let (port, payload) = match message[fopts_end..].split_first_mut() {
+      Some((&mut port, payload)) => {
+        let key = if port == 0 { self.session.nwk_skey } else { self.session.app_skey };
+        self.crypt(&key, DIR_DOWN, fcnt, payload);
+        (Some(port), &*payload)
+      }
+      None => (None, &[][..]),
+    };
+    Ok(Downlink {
+      port,
+      payload,
+      ack: fctrl & FCTRL_ACK != 0,
+      pending: fctrl & FCTRL_FPENDING != 0,
+    })
+  }
+
+  /// Encrypt or decrypt FRMPayload: XOR with AES of the `A_i` blocks.
+  fn crypt(&self, key: &[u8; 16], dir: u8, fcnt: u32, data: &mut [u8]) {
+    let cipher = Aes128::new_from_slice(key).unwrap();
+    for (index, chunk) in data.chunks_mut(16).enumerate() {
+      let mut block = Block::from(self.block(0x01, dir, fcnt, index as u8 + 1));
+      cipher.encrypt_block(&mut block);
+      for (byte, key) in chunk.iter_mut().zip(block) {
+        *byte ^= key;
+      }
+    }
+  }
+
+  /// First four bytes of AES-CMAC over `B_0 | message` under NwkSKey.
+  fn mic(&self, dir: u8, fcnt: u32, message: &[u8]) -> [u8; MIC_LEN] {
+    let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(&self.session.nwk_skey).unwrap();
+    mac.update(&self.block(0x49, dir, fcnt, message.len() as u8));
+    mac.update(message);
+    let tag = mac.finalize().into_bytes();
+    [tag[0], tag[1], tag[2], tag[3]]
+  }
+
+  /// The `A_i` / `B_0` block layout shared by encryption and MIC.
+  fn block(&self, tag: u8, dir: u8, fcnt: u32, last: u8) -> [u8; 16] {
+    let mut block = [0u8; 16];
+    block[0] = tag;
+    block[5] = dir;
+    block[6..10].copy_from_slice(&self.session.dev_addr.to_le_bytes());
+    block[10..14].copy_from_slice(&fcnt.to_le_bytes());
+    block[15] = last;
+    block
+  }
+}
+
+/// First four bytes of AES-CMAC over `message` under `key`.
+fn cmac(key: &[u8; 16], message: &[u8]) -> [u8; MIC_LEN] {
+  let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(key).unwrap();
+  mac.update(message);
+  let tag = mac.finalize().into_bytes();
+  [tag[0], tag[1], tag[2], tag[3]]
+}
+
+/// An EUI in over-the-air (little-endian) order.
+fn reversed(mut eui: [u8; 8]) -> [u8; 8] {
+  eui.reverse();
+  eui
+}
@@ -0,0 +1,119 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/modbus.rs - Modbus RTU 网关
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Modbus RTU gateway.
+//!
+//! With `AT+MODBUS=1` the data port carries Modbus RTU, so a Modbus master
+//! on one bridge reaches slaves wired to another one:
+//!
+//! * Frames from the port end at the RTU silent interval, 3.5 character
+//!   times (1.75 ms above 19200 baud), rounded up to the millisecond timer
+//!   and one tick more.  A frame whose CRC16 does not check out is dropped
+//!   there instead of being sent.
+//! * Every frame goes over the link unchanged, one frame per LoRa frame,
+//!   with the link's own CRC and, if enabled, MIC and addressing.  The
+//!   link does not retransmit; the master's response timeout and retries
+//!   cover lost frames, so it should allow for the LoRa round trip.
+//! * Received frames are checked again and written to the port whole,
+//!   never closer than a silent interval after the previous frame has left
+//!   the wire, so the far side sees correct inter-frame timing.  A frame
+//!   arriving meanwhile waits in [`Output`]; one more is dropped.
+//!
+//! Timing uses the UART baud rate, or the rate the host set on the USB
+//! data port.
+
+use heapless::Vec;
+
+use crate::crc::crc16_modbus;
+use crate::packetizer::MAX_PAYLOAD;
+
+/// Bits per RTU character: start, eight data bits, parity or a second
+/// stop bit, stop.
+const CHAR_BITS: u32 = 11;
+/// Shortest frame: address, function code and CRC.
+const MIN_FRAME: usize = 4;
+
+/// Whether `frame` is long enough and ends in its CRC, low byte first.
+pub fn valid(frame: &[u8]) -> bool {
+  match frame.split_last_chunk::<2>() {
+    Some((body, crc)) => frame.len() >= MIN_FRAME && crc16_modbus(body).to_le_bytes() == *crc,
+    None => false,
+  }
+}
+
+/// Silent interval at `baud`, in whole timer milliseconds.
+pub fn silent_ms(baud: u32) -> u32 {
+  let interval_us = if baud > 19_200 {
+    1_750
+  } else {
+    (7 * CHAR_BITS * 1_000_000).div_ceil(2 * baud.max(1))
+  };
+  // A millisecond timestamp may be up to one tick late.
+  interval_us.div_ceil(1_000) + 1
+}
+
+/// Time `len` characters take on the wire at `baud`.
+fn wire_ms(len: usize, baud: u32) -> u32 {
+  (len as u32 * CHAR_BITS * 1_000).div_ceil(baud.max(1))
+}
+
+/// Paces frames received over the link onto the port.
+pub struct Output {
+  frame: Vec<u8, MAX_PAYLOAD>,
+  pending: bool,
+  /// When the last frame was written and how long the port stays busy
+  /// after it.
+  written_ms: u32,
+  busy_ms: u32,
+}
+
+impl Output {
+  pub fn new() -> Self {
+    Self {
+      frame: Vec::new(),
+      pending: false,
+      written_ms: 0,
+      busy_ms: 0,
+    }
+  }
+
+  /// Hold a received frame for the port.  Returns `false` when it is not
+  /// valid RTU or another frame is still waiting, and it was dropped.
+  pub fn push(&mut self, frame: &[u8]) -> bool {
+    if self.pending || !valid(frame) {
+      return false;
+    }
+    self.frame.clear();
+    let _ = self.frame.extend_from_slice(frame);
+    self.pending = true;
+    true
+  }
+
+  /// The held frame once the port has been silent long enough; the caller
+  /// writes it to the port right away.
+  pub fn poll(&mut self, now_ms: u32, baud: u32) -> Option<&[u8]> {
+    if !self.pending || now_ms.wrapping_sub(self.written_ms) < self.busy_ms {
+      return None;
+    }
+    self.pending = false;
+    self.written_ms = now_ms;
+    self.busy_ms = wire_ms(self.frame.len(), baud) + silent_ms(baud);
+    Some(&self.frame)
+  }
+}
+
+impl Default for Output {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,77 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/mode.rs - 工作模式选择
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Firmware mode selection through the CDC line coding ("magic baud rates").
+//!
+//! The baud rate a host program sets on the data port has no meaning for a
+//! USB CDC device, so a few well-known rates are used as mode switches:
+//!
+//! | baud   | effect                                                    |
+//! |--------|-----------------------------------------------------------|
+//! | 1200   | "touch": closing the port enters the system bootloader    |
+//! | 9600   | transparent bridge (frames split by the packetizer)       |
+//! | 38400  | KISS TNC for packet-radio software (see [`crate::kiss`])   |
+//! | 57600  | packet-forwarder JSON records (see [`crate::forwarder`])   |
+//! | 115200 | framed protocol: `[len][payload]` in both directions      |
+//!
+//! Any other rate leaves the current mode unchanged.  Modbus RTU mode is
+//! chosen with `AT+MODBUS=1` instead (see [`crate::modbus`]), since Modbus
+//! tools pick the baud rate themselves; while it is on, the rates above
+//! only select the RTU timing.
+
+/// Baud rate that requests the system bootloader when the port is closed.
+pub const BAUD_TOUCH: u32 = 1200;
+/// Baud rate selecting [`BridgeMode::Transparent`].
+pub const BAUD_TRANSPARENT: u32 = 9600;
+/// Baud rate selecting [`BridgeMode::Framed`].
+pub const BAUD_FRAMED: u32 = 115_200;
+/// Baud rate selecting [`BridgeMode::Kiss`].
+pub const BAUD_KISS: u32 = 38_400;
+/// Baud rate selecting [`BridgeMode::Forwarder`].
+pub const BAUD_FORWARDER: u32 = 57_600;
+
+/// How bridge data is exchanged with the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum BridgeMode {
+  /// Raw byte stream; the packetizer decides where frames end.
+  Transparent,
+  /// Every LoRa frame is exchanged as a length byte followed by the payload.
+  Framed,
+  /// Every LoRa frame is exchanged as a KISS data frame.
+  Kiss,
+  /// Every received frame goes to the host as a JSON uplink record; host
+  /// data is handled as in `Transparent`.
+  Forwarder,
+  /// Every LoRa frame is a Modbus RTU frame, with RTU timing on the port.
+  Modbus,
+}
+
+/// What the host asked for through the line coding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ModeRequest {
+  Bridge(BridgeMode),
+  /// 1200-baud touch: the host opened the port at 1200 baud and dropped DTR.
+  Touch,
+}
+
+/// Decode the data-port line state into a mode request.
+pub fn from_line_coding(baud: u32, dtr: bool) -> Option<ModeRequest> {
+  match baud {
+    BAUD_TOUCH if !dtr => Some(ModeRequest::Touch),
+    BAUD_TRANSPARENT => Some(ModeRequest::Bridge(BridgeMode::Transparent)),
+    BAUD_FRAMED => Some(ModeRequest::Bridge(BridgeMode::Framed)),
+    BAUD_KISS => Some(ModeRequest::Bridge(BridgeMode::Kiss)),
+    BAUD_FORWARDER => Some(ModeRequest::Bridge(BridgeMode::Forwarder)),
+    _ => None,
+  }
+}
@@ -0,0 +1,185 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/ms_os.rs - Microsoft OS 2.0 描述符
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Microsoft OS 2.0 descriptors.
+//!
+//! Windows 8.1 and later look for a platform capability in the BOS
+//! descriptor of a USB 2.1 device and, when it names a vendor request,
+//! fetch a descriptor set with it.  The set can give a function of the
+//! composite device a compatible ID such as `WINUSB` and a device interface
+//! GUID, so Windows binds the driver without an INF file.
+//!
+//! [`FUNCTIONS`] lists the functions that get one.  The two CDC ports and
+//! the mass storage function already bind the in-box `usbser` and
+//! `USBSTOR` drivers by their class, so the list stays empty until the
+//! vendor-specific control interface lands.  Until then the set holds only
+//! its header and the device enumerates as USB 2.0, so Windows never asks
+//! for it and binds by class as before.
+
+use heapless::Vec;
+use usb_device::class_prelude::{BosWriter, ControlIn, UsbBus, UsbClass};
+use usb_device::control::{Recipient, RequestType};
+
+/// A function of the composite device bound by its descriptor set.
+pub struct Function {
+  /// First interface of the function.
+  pub first_interface: u8,
+  /// Compatible ID, at most 8 ASCII characters, e.g. `WINUSB`.
+  pub compatible_id: &'static str,
+  /// Device interface GUID in braces, for WinUSB functions.
+  pub interface_guid: Option<&'static str>,
+}
+
+/// Functions Windows binds through the descriptor set.
+pub const FUNCTIONS: &[Function] = &[];
+
+/// Vendor request Windows fetches the descriptor set with.
+pub const VENDOR_CODE: u8 = 0x01;
+/// `wIndex` of that request asking for the descriptor set.
+const DESCRIPTOR_INDEX: u16 = 0x07;
+
+/// Room for the descriptor set; one WinUSB function with its GUID takes
+/// 178 bytes.
+pub const SET_CAPACITY: usize = 256;
+
+pub type DescriptorSet = Vec<u8, SET_CAPACITY>;
+
+const WINDOWS_8_1: u32 = 0x0603_0000;
+/// BOS device capability type of a platform capability.
+const PLATFORM_CAPABILITY: u8 = 0x05;
+/// MS OS 2.0 platform capability UUID D8DD60DF-4589-4CC7-9CD2-659D9E648A9F,
+/// in wire order.
+const PLATFORM_UUID: [u8; 16] = [
+  0xDF, 0x60, 0xDD, 0xD8, 0x89, 0x45, 0xC7, 0x4C, 0x9C, 0xD2, 0x65, 0x9D, 0x9E, 0x64, 0x8A, 0x9F,
+];
+
+const SET_HEADER: u16 = 0x00;
+const CONFIGURATION_SUBSET: u16 = 0x01;
+const FUNCTION_SUBSET: u16 = 0x02;
+const COMPATIBLE_ID: u16 = 0x03;
+const REGISTRY_PROPERTY: u16 = 0x04;
+const REG_MULTI_SZ: u16 = 7;
+const GUIDS_PROPERTY: &str = "DeviceInterfaceGUIDs";
+
+/// Build the descriptor set for `functions`; `None` when it does not fit
+/// [`SET_CAPACITY`].
+pub fn descriptor_set(functions: &[Function]) -> Option<DescriptorSet> {
+  let mut set = DescriptorSet::new();
+  push_header(&mut set, 10, SET_HEADER)?;
+  set.extend_from_slice(&WINDOWS_8_1.to_le_bytes()).ok()?;
+  // wTotalLength, patched below like the subset lengths.
+  push_u16(&mut set, 0)?;
+  if !functions.is_empty() {
+    let configuration = set.len();
+    push_header(&mut set, 8, CONFIGURATION_SUBSET)?;
+    // Configuration index 0, reserved, wTotalLength.
+    set.extend_from_slice(&[0, 0, 0, 0]).ok()?;
+    for function in functions {
+      let subset = set.len();
+      push_header(&mut set, 8, FUNCTION_SUBSET)?;
+      set
+        .extend_from_slice(&[function.first_interface, 0, 0, 0])
+        .ok()?;
+      // Compatible ID and an empty sub-compatible ID, zero padded.
+      push_header(&mut set, 20, COMPATIBLE_ID)?;
+      let mut ids = [0u8; 16];
+      let id = function.compatible_id.as_bytes();
+      let len = id.len().min(8);
+      ids[..len].copy_from_slice(&id[..len]);
+      set.extend_from_slice(&ids).ok()?;
+      if let Some(guid) = function.interface_guid {
+        push_guid_property(&mut set, guid)?;
+      }
+      patch_len(&mut set, subset, subset + 6);
+    }
+    patch_len(&mut set, configuration, configuration + 6);
+  }
+  patch_len(&mut set, 0, 8);
+  Some(set)
+}
+
+/// `DeviceInterfaceGUIDs`, a `REG_MULTI_SZ` holding `guid`.
+fn push_guid_property(set: &mut DescriptorSet, guid: &str) -> Option<()> {
+  let name_len = (GUIDS_PROPERTY.len() + 1) * 2;
+  // The list ends with a second NUL.
+  let data_len = (guid.len() + 2) * 2;
+  push_header(set, (10 + name_len + data_len) as u16, REGISTRY_PROPERTY)?;
+  push_u16(set, REG_MULTI_SZ)?;
+  push_u16(set, name_len as u16)?;
+  push_utf16(set, GUIDS_PROPERTY, 1)?;
+  push_u16(set, data_len as u16)?;
+  push_utf16(set, guid, 2)
+}
+
+fn push_header(set: &mut DescriptorSet, len: u16, descriptor_type: u16) -> Option<()> {
+  push_u16(set, len)?;
+  push_u16(set, descriptor_type)
+}
+
+fn push_u16(set: &mut DescriptorSet, value: u16) -> Option<()> {
+  set.extend_from_slice(&value.to_le_bytes()).ok()
+}
+
+/// ASCII `text` as UTF-16LE followed by `nuls` NUL characters.
+fn push_utf16(set: &mut DescriptorSet, text: &str, nuls: usize) -> Option<()> {
+  for byte in text.bytes().chain(core::iter::repeat_n(0, nuls)) {
+    set.extend_from_slice(&[byte, 0]).ok()?;
+  }
+  Some(())
+}
+
+/// Store the length from `start` to the end of `set` at `at`.
+fn patch_len(set: &mut DescriptorSet, start: usize, at: usize) {
+  let len = (set.len() - start) as u16;
+  set[at..at + 2].copy_from_slice(&len.to_le_bytes());
+}
+
+/// USB class without interfaces: the BOS capability and the vendor request
+/// serving the descriptor set.
+pub struct MsOs20 {
+  set: &'static [u8],
+}
+
+impl MsOs20 {
+  pub fn new(set: &'static [u8]) -> Self {
+    Self { set }
+  }
+
+  /// Platform capability data after `bDevCapabilityType`.
+  fn capability(&self) -> [u8; 25] {
+    let mut data = [0u8; 25];
+    data[1..17].copy_from_slice(&PLATFORM_UUID);
+    data[17..21].copy_from_slice(&WINDOWS_8_1.to_le_bytes());
+    data[21..23].copy_from_slice(&(self.set.len() as u16).to_le_bytes());
+    data[23] = VENDOR_CODE;
+    // data[24]: no alternate enumeration.
+    data
+  }
+}
+
+impl<B: UsbBus> UsbClass<B> for MsOs20 {
+  fn get_bos_descriptors(&self, writer: &mut BosWriter) -> usb_device::Result<()> {
+    writer.capability(PLATFORM_CAPABILITY, &self.capability())
+  }
+
+  fn control_in(&mut self, xfer: ControlIn<B>) {
+    let request = xfer.request();
+    if request.request_type == RequestType::Vendor
+      && request.recipient == Recipient::Device
+      && request.request == VENDOR_CODE
+      && request.index == DESCRIPTOR_INDEX
+    {
+      let _ = xfer.accept_with_static(self.set);
+    }
+  }
+}
@@ -0,0 +1,416 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/msc.rs - 记录存储的 USB 大容量存储视图
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Read-only USB mass storage view of the log store.
+//!
+//! Built with `spi-flash` or `sd-card`, the USB device gains a third
+//! function beside the two CDC ports: a SCSI disk over bulk-only transport,
+//! so the logs can be copied off with a file manager.  The host may read
+//! but never write; the disk reports itself write-protected and fails
+//! `WRITE`.
+//!
+//! * With an SD card (see [`crate::sd_log`]) the disk is the card itself,
+//!   and `PACKETS.CSV` and `EVENTS.LOG` show up as they were last flushed.
+//! * With an external flash (see [`crate::spi_flash`]) the disk is a FAT12
+//!   volume [`FatImage`] makes up on the fly, holding each partition as a
+//!   file: `LOG.BIN` (records as in [`crate::flash_log`]), `SETTINGS.BIN`
+//!   and `STAGED.BIN`.
+//!
+//! Without either, or before the store is found, the disk reports no
+//! medium.
+//!
+//! The USB interrupt cannot reach the store, which belongs to the main
+//! loop, so blocks are handed over one at a time: the interrupt asks for a
+//! block with [`REQUEST`], the main loop reads it into [`BLOCK`] in
+//! [`serve`] and marks it ready, and the interrupt sends it.  A read thus
+//! waits for the next pass of the main loop.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use cortex_m::peripheral::NVIC;
+use usbd_storage::subclass::Command;
+use usbd_storage::subclass::scsi::{Scsi, ScsiCommand};
+use usbd_storage::transport::TransportError;
+use usbd_storage::transport::bbb::{BulkOnly, BulkOnlyError};
+
+use crate::hal::pac::Interrupt;
+use crate::hal::usb::UsbBusType;
+
+pub const BLOCK_LEN: usize = 512;
+/// Bulk packet size of the function; the two CDC ports leave room for no
+/// more in the USB packet memory.
+pub const PACKET_SIZE: u16 = 32;
+
+/// The SCSI function as the USB stack holds it.
+pub type Function = Scsi<BulkOnly<'static, UsbBusType, &'static mut [u8]>>;
+
+/// Blocks on the medium; zero while there is none.
+static BLOCKS: AtomicU32 = AtomicU32::new(0);
+/// Block the interrupt waits for, plus one; zero for none.
+static REQUEST: AtomicU32 = AtomicU32::new(0);
+/// Block held in [`BLOCK`], plus one; zero while it is being filled.
+static READY: AtomicU32 = AtomicU32::new(0);
+/// Whether reading the ready block failed.
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+/// A block owned by the main loop while [`READY`] is zero and by the
+/// interrupt otherwise.
+struct Block(UnsafeCell<[u8; BLOCK_LEN]>);
+
+unsafe impl Sync for Block {}
+
+static BLOCK: Block = Block(UnsafeCell::new([0; BLOCK_LEN]));
+
+/// Standard INQUIRY data: direct access, removable, SPC-2.
+const INQUIRY: [u8; 36] = *b"\x00\x80\x04\x02\x1F\x00\x00\x00\
+  WARELESS\
+  Blue-High Logs  \
+  0001";
+
+// SCSI sense keys and additional sense codes.
+const SENSE_NOT_READY: u8 = 0x02;
+const SENSE_MEDIUM_ERROR: u8 = 0x03;
+const SENSE_ILLEGAL_REQUEST: u8 = 0x05;
+const SENSE_DATA_PROTECT: u8 = 0x07;
+const ASC_READ_ERROR: u8 = 0x11;
+const ASC_INVALID_COMMAND: u8 = 0x20;
+const ASC_WRITE_PROTECTED: u8 = 0x27;
+const ASC_NO_MEDIUM: u8 = 0x3A;
+
+/// Make the store visible as a disk of `blocks` blocks, or none.
+pub fn attach(blocks: u32) {
+  BLOCKS.store(blocks, Ordering::Relaxed);
+  defmt::info!("[msc] {} KiB visible over USB", blocks / 2);
+}
+
+/// Read the block the host waits for, if any, with `read`, and wake the
+/// USB interrupt to send it.
+pub fn serve(read: impl FnOnce(u32, &mut [u8; BLOCK_LEN]) -> bool) {
+  let request = REQUEST.load(Ordering::Acquire);
+  if request == 0 || READY.load(Ordering::Acquire) == request {
+    return;
+  }
+  // READY is zero: the interrupt keeps off the block.
+  let block = unsafe { &mut *BLOCK.0.get() };
+  FAILED.store(!read(request - 1, block), Ordering::Relaxed);
+  READY.store(request, Ordering::Release);
+  NVIC::pend(Interrupt::USB_LP_CAN_RX0);
+}
+
+/// Interrupt side of the function: the command in progress.
+#[derive(Default)]
+pub struct State {
+  /// Bytes of the current READ sent.
+  sent: usize,
+  sense: (u8, u8),
+}
+
+impl State {
+  /// Answer the command the transport has in progress; called on every
+  /// poll until it passes or fails.
+  pub fn process(&mut self, mut command: Command<ScsiCommand, Function>) {
+    if let Err(error) = self.answer(&mut command) {
+      // Nothing fits the endpoint yet: the next poll tries again.
+      if !matches!(error, TransportError::Usb(usb_device::UsbError::WouldBlock)) {
+        defmt::warn!("[msc] {}", error);
+      }
+    }
+  }
+
+  fn answer(
+    &mut self,
+    command: &mut Command<ScsiCommand, Function>,
+  ) -> Result<(), TransportError<BulkOnlyError>> {
+    let blocks = BLOCKS.load(Ordering::Relaxed);
+    match command.kind {
+      ScsiCommand::Inquiry { .. } => {
+        command.try_write_data_all(&INQUIRY)?;
+        command.pass();
+      }
+      ScsiCommand::RequestSense { .. } => {
+        let (key, code) = core::mem::take(&mut self.sense);
+        let mut sense = [0u8; 18];
+        sense[0] = 0x70;
+        sense[2] = key;
+        sense[7] = 10;
+        sense[12] = code;
+        command.try_write_data_all(&sense)?;
+        command.pass();
+      }
+      _ if blocks == 0 => self.fail(command, SENSE_NOT_READY, ASC_NO_MEDIUM),
+      ScsiCommand::TestUnitReady { .. } => command.pass(),
+      ScsiCommand::ReadCapacity10 { .. } => {
+        let mut capacity = [0u8; 8];
+        capacity[..4].copy_from_slice(&(blocks - 1).to_be_bytes());
+        capacity[4..].copy_from_slice(&(BLOCK_LEN as u32).to_be_bytes());
+        command.try_write_data_all(&capacity)?;
+        command.pass();
+      }
+      ScsiCommand::ReadFormatCapacities { .. } => {
+        let mut list = [0u8; 12];
+        list[3] = 8;
+        list[4..8].copy_from_slice(&blocks.to_be_bytes());
+        // Formatted media.
+        list[8] = 0x02;
+        list[9..].copy_from_slice(&(BLOCK_LEN as u32).to_be_bytes()[1..]);
+        command.try_write_data_all(&list)?;
+        command.pass();
+      }
+      ScsiCommand::ModeSense6 { .. } => {
+        // No pages; the device-specific byte marks the medium read-only.
+        command.try_write_data_all(&[3, 0, 0x80, 0])?;
+        command.pass();
+      }
+      ScsiCommand::ModeSense10 { .. } => {
+        command.try_write_data_all(&[0, 6, 0, 0x80, 0, 0, 0, 0])?;
+        command.pass();
+      }
+      ScsiCommand::Read { lba, len } => {
+        let total = len as usize * BLOCK_LEN;
+        if self.sent == total {
+          self.sent = 0;
+          command.pass();
+          return Ok(());
+        }
+        let block = lba as u32 + (self.sent / BLOCK_LEN) as u32;
+        if block >= blocks {
+          self.sent = 0;
+          self.fail(command, SENSE_ILLEGAL_REQUEST, ASC_INVALID_COMMAND);
+          return Ok(());
+        }
+        if READY.load(Ordering::Acquire) != block + 1 {
+          // Ask the main loop, which wakes us once the block is read.
+          if REQUEST.load(Ordering::Relaxed) != block + 1 {
+            READY.store(0, Ordering::Release);
+            REQUEST.store(block + 1, Ordering::Release);
+          }
+          return Ok(());
+        }
+        if FAILED.load(Ordering::Relaxed) {
+          self.sent = 0;
+          self.fail(command, SENSE_MEDIUM_ERROR, ASC_READ_ERROR);
+          return Ok(());
+        }
+        // READY holds this block: the main loop keeps off it.
+        let data = unsafe { &*BLOCK.0.get() };
+        self.sent += command.write_data(&data[self.sent % BLOCK_LEN..])?;
+      }
+      ScsiCommand::Write { .. } => self.fail(command, SENSE_DATA_PROTECT, ASC_WRITE_PROTECTED),
+      _ => self.fail(command, SENSE_ILLEGAL_REQUEST, ASC_INVALID_COMMAND),
+    }
+    Ok(())
+  }
+
+  /// Fail the command, to be explained by the next REQUEST SENSE.
+  fn fail(&mut self, command: &mut Command<ScsiCommand, Function>, key: u8, code: u8) {
+    self.sense = (key, code);
+    command.fail();
+  }
+}
+
+#[cfg(feature = "spi-flash")]
+pub use fat::FatImage;
+
+/// A FAT12 volume made up over the partitions of the external flash.
+#[cfg(feature = "spi-flash")]
+mod fat {
+  use super::BLOCK_LEN;
+  use crate::spi_flash::{self, Partition, Storage};
+
+  /// Most clusters FAT12 tells apart from FAT16 safely.
+  const CLUSTERS_MAX: u32 = 4_000;
+  /// Root directory entries; one block.
+  const ROOT_ENTRIES: u32 = (BLOCK_LEN / 32) as u32;
+  const LABEL: &[u8; 11] = b"BLUE-HIGH  ";
+  /// 2026-01-01 in FAT date format, for want of a calendar.
+  const DATE: u16 = (46 << 9) | (1 << 5) | 1;
+  const ATTR_READ_ONLY: u8 = 0x01;
+  const ATTR_VOLUME_ID: u8 = 0x08;
+
+  /// The files: 8.3 names, space padded, and their partitions.
+  fn files(capacity: u32) -> [(&'static [u8; 11], Partition); 3] {
+    [
+      (b"LOG     BIN", spi_flash::log(capacity)),
+      (b"SETTINGSBIN", spi_flash::SETTINGS),
+      (b"STAGED  BIN", spi_flash::OTA),
+    ]
+  }
+
+  /// Layout: boot block, FAT, root directory, then the files one after
+  /// the other, each in whole clusters.
+  #[derive(Debug, Clone, Copy)]
+  pub struct FatImage {
+    capacity: u32,
+    blocks_per_cluster: u32,
+    fat_blocks: u32,
+    clusters: u32,
+  }
+
+  impl FatImage {
+    /// The volume over a chip of `capacity` bytes.
+    pub fn new(capacity: u32) -> Self {
+      let mut blocks_per_cluster = 1;
+      let clusters = loop {
+        let cluster_len = blocks_per_cluster * BLOCK_LEN as u32;
+        let clusters = files(capacity)
+          .iter()
+          .map(|(_, partition)| partition.len.div_ceil(cluster_len))
+          .sum::<u32>();
+        if clusters <= CLUSTERS_MAX {
+          break clusters;
+        }
+        blocks_per_cluster *= 2;
+      };
+      // 12 bits per cluster, two reserved entries ahead.
+      let fat_blocks = ((clusters + 2) * 3 / 2).div_ceil(BLOCK_LEN as u32);
+      Self {
+        capacity,
+        blocks_per_cluster,
+        fat_blocks,
+        clusters,
+      }
+    }
+
+    /// Blocks in the volume.
+    pub fn blocks(&self) -> u32 {
+      self.data_start() + self.clusters * self.blocks_per_cluster
+    }
+
+    fn data_start(&self) -> u32 {
+      1 + self.fat_blocks + 1
+    }
+
+    fn cluster_len(&self) -> u32 {
+      self.blocks_per_cluster * BLOCK_LEN as u32
+    }
+
+    /// First cluster and cluster count of each file.
+    fn chains(&self) -> [(u32, u32); 3] {
+      let mut next = 2;
+      files(self.capacity).map(|(_, partition)| {
+        let count = partition.len.div_ceil(self.cluster_len());
+        let chain = (next, count);
+        next += count;
+        chain
+      })
+    }
+
+    /// Make up block `lba`, reading file contents from `storage`.
+    pub fn read(&self, storage: &mut dyn Storage, lba: u32, block: &mut [u8; BLOCK_LEN]) -> bool {
+      block.fill(0);
+      match lba {
+        0 => self.boot_block(block),
+        _ if lba <= self.fat_blocks => self.fat_block(lba - 1, block),
+        _ if lba < self.data_start() => self.root_block(block),
+        _ => return self.data_block(storage, lba - self.data_start(), block),
+      }
+      true
+    }
+
+    fn boot_block(&self, block: &mut [u8; BLOCK_LEN]) {
+      let blocks = self.blocks();
+      block[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+      block[3..11].copy_from_slice(b"BLUEHIGH");
+      block[11..13].copy_from_slice(&(BLOCK_LEN as u16).to_le_bytes());
+      block[13] = self.blocks_per_cluster as u8;
+      // One reserved block, one FAT.
+      block[14..16].copy_from_slice(&1u16.to_le_bytes());
+      block[16] = 1;
+      block[17..19].copy_from_slice(&(ROOT_ENTRIES as u16).to_le_bytes());
+      if blocks < 0x1_0000 {
+        block[19..21].copy_from_slice(&(blocks as u16).to_le_bytes());
+      } else {
+        block[32..36].copy_from_slice(&blocks.to_le_bytes());
+      }
+      // Fixed disk.
+      block[21] = 0xF8;
+      block[22..24].copy_from_slice(&(self.fat_blocks as u16).to_le_bytes());
+      block[24..26].copy_from_slice(&32u16.to_le_bytes());
+      block[26..28].copy_from_slice(&64u16.to_le_bytes());
+      block[36] = 0x80;
+      block[38] = 0x29;
+      block[39..43].copy_from_slice(&crate::device_id::uid()[0].to_le_bytes());
+      block[43..54].copy_from_slice(LABEL);
+      block[54..62].copy_from_slice(b"FAT12   ");
+      block[510..512].copy_from_slice(&[0x55, 0xAA]);
+    }
+
+    /// Block `index` of the FAT: each file one unbroken chain.
+    fn fat_block(&self, index: u32, block: &mut [u8; BLOCK_LEN]) {
+      let chains = self.chains();
+      let entry = |cluster: u32| -> u16 {
+        match cluster {
+          0 => 0xFF8,
+          1 => 0xFFF,
+          _ => match chains
+            .iter()
+            .find(|&&(first, count)| (first..first + count).contains(&cluster))
+          {
+            Some(&(first, count)) if cluster + 1 == first + count => 0xFFF,
+            Some(_) => cluster as u16 + 1,
+            None => 0,
+          },
+        }
+      };
+      for (i, byte) in block.iter_mut().enumerate() {
+        // Two entries pack into three bytes.
+        let at = index * BLOCK_LEN as u32 + i as u32;
+        let (even, odd) = (entry(at / 3 * 2), entry(at / 3 * 2 + 1));
+        *byte = match at % 3 {
+          0 => even as u8,
+          1 => (even >> 8) as u8 | (odd << 4) as u8,
+          _ => (odd >> 4) as u8,
+        };
+      }
+    }
+
+    fn root_block(&self, block: &mut [u8; BLOCK_LEN]) {
+      block[0..11].copy_from_slice(LABEL);
+      block[11] = ATTR_VOLUME_ID;
+      let entries = files(self.capacity).into_iter().zip(self.chains());
+      for (n, ((name, partition), (first, _))) in entries.enumerate() {
+        let entry = &mut block[(n + 1) * 32..(n + 2) * 32];
+        entry[0..11].copy_from_slice(name);
+        entry[11] = ATTR_READ_ONLY;
+        for at in [16, 18, 24] {
+          entry[at..at + 2].copy_from_slice(&DATE.to_le_bytes());
+        }
+        entry[26..28].copy_from_slice(&(first as u16).to_le_bytes());
+        entry[28..32].copy_from_slice(&partition.len.to_le_bytes());
+      }
+    }
+
+    /// Block `index` of the data area.
+    fn data_block(
+      &self,
+      storage: &mut dyn Storage,
+      index: u32,
+      block: &mut [u8; BLOCK_LEN],
+    ) -> bool {
+      let cluster = 2 + index / self.blocks_per_cluster;
+      let files = files(self.capacity);
+      let Some(((_, partition), (first, _))) = files
+        .into_iter()
+        .zip(self.chains())
+        .find(|&(_, (first, count))| (first..first + count).contains(&cluster))
+      else {
+        return true;
+      };
+      let offset =
+        (cluster - first) * self.cluster_len() + index % self.blocks_per_cluster * BLOCK_LEN as u32;
+      let len = partition.len.saturating_sub(offset).min(BLOCK_LEN as u32) as usize;
+      partition.read(storage, offset, &mut block[..len]).is_ok()
+    }
+  }
+}
@@ -0,0 +1,88 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/noise.rs - 信道噪声监测
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Channel noise monitor.
+//!
+//! `AT+RSSI?` reads the instantaneous RSSI of the link channel once (see
+//! [`Radio::rssi_inst`](crate::radio::Radio::rssi_inst)) and answers
+//! `+RSSI:<dBm>`, or `+RSSI:-` when the radio does not answer.
+//! `AT+RSSI=<interval ms>` streams the same line every `interval`
+//! milliseconds, at least [`MIN_INTERVAL_MS`]; `AT+RSSI=0` stops it.
+//!
+//! Readings are taken between transmissions while the radio listens, so a
+//! terminal shows the noise floor, and any traffic on the channel, without
+//! extra tooling.  Unlike `AT+SCAN` the radio stays on the link channel.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+/// Shortest streaming interval accepted.
+pub const MIN_INTERVAL_MS: u32 = 50;
+/// Longest streaming interval accepted.
+pub const MAX_INTERVAL_MS: u32 = 60_000;
+
+/// Streaming state.
+pub struct Monitor {
+  /// Milliseconds between readings, `0` for off.
+  interval_ms: u32,
+  last_ms: u32,
+}
+
+impl Monitor {
+  pub fn new() -> Self {
+    Self {
+      interval_ms: 0,
+      last_ms: 0,
+    }
+  }
+
+  /// Accept `0` (off) or an interval within
+  /// [`MIN_INTERVAL_MS`]`..=`[`MAX_INTERVAL_MS`].
+  pub fn valid_interval(interval_ms: u32) -> bool {
+    interval_ms == 0 || (MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&interval_ms)
+  }
+
+  /// Stream every `interval_ms`, starting now; `0` stops.
+  pub fn set(&mut self, interval_ms: u32, now: u32) {
+    defmt::info!("[noise] streaming every {} ms", interval_ms);
+    self.interval_ms = interval_ms;
+    // The first reading is due straight away.
+    self.last_ms = now.wrapping_sub(interval_ms);
+  }
+
+  /// Whether a streamed reading is due.
+  pub fn due(&mut self, now: u32) -> bool {
+    if self.interval_ms == 0 || now.wrapping_sub(self.last_ms) < self.interval_ms {
+      return false;
+    }
+    self.last_ms = now;
+    true
+  }
+}
+
+impl Default for Monitor {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// `+RSSI:<dBm>`, or `+RSSI:-` without a reading.
+pub fn report(rssi_dbm: Option<i16>) -> String<16> {
+  let mut line = String::new();
+  let _ = match rssi_dbm {
+    Some(rssi) => write!(line, "+RSSI:{}\r\n", rssi),
+    None => write!(line, "+RSSI:-\r\n"),
+  };
+  line
+}
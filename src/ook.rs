@@ -0,0 +1,112 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/ook.rs - OOK 通断键控发射
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! On-off keyed transmission from pulse tables.
+//!
+//! `AT+OOK=<freq Hz>,<repeats>,<on µs>,<off µs>,...` keys an unmodulated
+//! carrier with the given durations, starting with the carrier on, and
+//! sends the whole table `repeats` times.  This is enough to replay the
+//! fixed-code remotes common on 433 MHz or to exercise OOK receivers.
+//!
+//! Keying is timed against the cycle counter from absolute deadlines, so
+//! errors do not add up over a table; each edge still lags by the SPI
+//! command (tens of µs) and by any USB interrupt that lands on it.  The
+//! bridge does nothing else while keying and restores the LoRa
+//! configuration afterwards.
+
+use crate::radio::Radio;
+use crate::{band, led, timer};
+
+/// Most durations in one table.
+pub const MAX_PULSES: usize = 64;
+/// Most repetitions of a table.
+pub const MAX_REPEATS: u8 = 50;
+/// Longest keying run accepted, all repetitions included.
+const MAX_DURATION_US: u32 = 10_000_000;
+
+/// A validated keying sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Sequence {
+  pub frequency_hz: u32,
+  pub repeats: u8,
+  len: u8,
+  /// Alternating on and off durations in µs.
+  pulses: [u16; MAX_PULSES],
+}
+
+impl Sequence {
+  /// Accept an even, non-empty table on the module's band that keys for
+  /// at most ten seconds.
+  pub fn new(frequency_hz: u32, repeats: u8, durations: &[u16]) -> Option<Self> {
+    if !band::FREQUENCY_HZ.contains(&frequency_hz)
+      || !(1..=MAX_REPEATS).contains(&repeats)
+      || durations.is_empty()
+      || durations.len() > MAX_PULSES
+      || !durations.len().is_multiple_of(2)
+    {
+      return None;
+    }
+    let mut pulses = [0u16; MAX_PULSES];
+    pulses[..durations.len()].copy_from_slice(durations);
+    let sequence = Self {
+      frequency_hz,
+      repeats,
+      len: durations.len() as u8,
+      pulses,
+    };
+    (sequence.duration_us() <= MAX_DURATION_US).then_some(sequence)
+  }
+
+  pub fn pulses(&self) -> &[u16] {
+    &self.pulses[..self.len as usize]
+  }
+
+  /// Keying time of all repetitions.
+  pub fn duration_us(&self) -> u32 {
+    let table: u32 = self.pulses().iter().map(|&pulse| pulse as u32).sum();
+    table * self.repeats as u32
+  }
+}
+
+/// Key `sequence` on `radio`, blocking until it is done.  The carrier is
+/// off and the radio in standby afterwards.
+pub fn transmit(radio: &mut impl Radio, sequence: &Sequence) -> bool {
+  if !radio.tune(sequence.frequency_hz) {
+    return false;
+  }
+  defmt::info!(
+    "[ook] {} pulses x{} at {} Hz",
+    sequence.len,
+    sequence.repeats,
+    sequence.frequency_hz
+  );
+  led::set_transmitting(true);
+  let mut keyed = true;
+  let mut deadline = timer::now_cycles();
+  'sequence: for _ in 0..sequence.repeats {
+    for (index, &pulse_us) in sequence.pulses().iter().enumerate() {
+      if !radio.carrier(index % 2 == 0) {
+        keyed = false;
+        break 'sequence;
+      }
+      deadline = timer::after_us(deadline, pulse_us as u32);
+      timer::wait_until_cycles(deadline);
+    }
+  }
+  let off = radio.carrier(false);
+  led::set_transmitting(false);
+  if !keyed {
+    defmt::warn!("[ook] carrier not available");
+  }
+  keyed && off
+}
@@ -0,0 +1,770 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/ota.rs - 经 LoRa 为对端网桥升级固件
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Firmware updates of the peer bridge over LoRa.
+//!
+//! A remote bridge is updated from the local one: the host feeds the image
+//! to the local bridge in chunks, which carries each to the peer and waits
+//! for its answer.  The peer stages the image in the second half of a
+//! 128 KiB flash and installs it on the next boot:
+//!
+//! ```text
+//! 0x0800_0000  boot selector and application, 63 KiB
+//! 0x0800_FC00  settings page (see crate::settings)
+//! 0x0801_0000  staged image, 63 KiB
+//! 0x0801_FC00  staging record: [magic u32][size u32][crc32 u32]
+//! ```
+//!
+//! The STM32F103C8 is sold with 64 KiB.  A bridge whose flash size register
+//! reads less than 128 KiB stages the image in the [`OTA`](spi_flash::OTA)
+//! partition of an external SPI flash instead (see [`crate::spi_flash`]),
+//! with the same record at [`EXTERNAL_RECORD`] after it; with neither, it
+//! refuses the update.  Any bridge can send one.
+//!
+//! Both bridges refuse an update unless link security is on (see
+//! [`crate::security`]): its MIC keeps others from speaking for the peer,
+//! and its key authenticates the image.  The sending bridge runs an
+//! AES-128-CMAC over the image size and the chunks as it sends them in
+//! order, and `FINISH` carries the tag; the peer runs the same CMAC over
+//! what it staged and writes no staging record unless the tags match.
+//!
+//! The messages travel in [`Kind::Control`](crate::link::Kind::Control)
+//! frames to the paired peer:
+//!
+//! ```text
+//! START:  [0x70][seq u8][size u32][crc32 u32]
+//! DATA:   [0x71][seq u8][offset u32][crc16 u16][data ...]
+//! FINISH: [0x72][seq u8][CMAC 16 bytes]
+//! ABORT:  [0x73][seq u8]
+//! REPLY:  [0x74][seq u8][status u8][next offset u32]
+//! ```
+//!
+//! One request is outstanding at a time and is sent again every
+//! [`RETRY_MS`] until the peer replies, at most [`TRIES`] times.  `DATA`
+//! carries a CRC16 of its chunk (see [`crate::crc`]); a chunk that fails it
+//! is dropped and sent again.  The reply names the offset the peer expects
+//! next, so a repeated or out-of-order chunk is answered without being
+//! written.  `FINISH` checks the CRC32 of the whole image against the one
+//! `START` announced, and the CMAC; only a matching image is staged, and
+//! the peer then restarts.  The size must be a multiple of four, as the sealed `.bin` of
+//! `tools/seal_image.py` is, and the image must be built for the boot
+//! selector.
+//!
+//! At boot, the selector in the first flash page (see
+//! [`crate::boot_select`]) finds the record, checks the CRC32 of the staged
+//! image, copies it over the application and only then forgets the record.
+//! The copy takes about two seconds; losing power meanwhile starts it
+//! again at the next boot.
+//!
+//! On the control port of the sending bridge:
+//!
+//! * `AT+OTA=START,<size>,<crc32 hex>` announces the image;
+//! * `AT+OTA=<offset>,<hex>` sends up to [`CHUNK_MAX`] bytes at `offset`;
+//! * `AT+OTA=END` asks the peer to check and install it;
+//! * `AT+OTA=ABORT` drops a transfer;
+//! * `AT+OTA?` reports both sides and where this bridge would stage an
+//!   image.
+//!
+//! Each answers `OK` once queued, `ERROR` while a request is outstanding
+//! or link security is off, and later `+OTA:ACK,<next offset>`,
+//! `+OTA:DONE` after `END`, or `+OTA:<REFUSED|BAD|FAILED|TIMEOUT>`.
+
+use core::fmt::Write;
+
+use cmac::Mac;
+use heapless::String;
+
+use crate::boot_select;
+use crate::crc::{crc16_ccitt, crc32, crc32_update};
+use crate::hal::FLASH_SIZE_REG;
+use crate::hal::flash::{self, FlashSize, SectorSize};
+use crate::security::{ImageMac, Security};
+use crate::spi_flash::{self, Storage};
+
+/// Attempts at delivering a request.
+pub const TRIES: u8 = 5;
+/// Interval between retransmissions; long enough for a full chunk and its
+/// reply at SF12.
+pub const RETRY_MS: u32 = 4_000;
+/// Pause between staging an image and restarting, for the reply to leave.
+pub const INSTALL_DELAY_MS: u32 = 2_000;
+/// Largest chunk in one `DATA` message.
+pub const CHUNK_MAX: usize = 128;
+/// Bytes of the image CMAC in `FINISH`.
+pub const TAG_LEN: usize = 16;
+
+const TYPE_START: u8 = 0x70;
+const TYPE_DATA: u8 = 0x71;
+const TYPE_FINISH: u8 = 0x72;
+const TYPE_ABORT: u8 = 0x73;
+const TYPE_REPLY: u8 = 0x74;
+
+/// Largest encoded message.
+pub const MESSAGE_MAX: usize = 8 + CHUNK_MAX;
+
+pub(crate) const PAGE_LEN: usize = 1024;
+/// Bytes a slot holds: the running image is kept clear of the settings page.
+pub const SLOT_LEN: usize = 63 * PAGE_LEN;
+/// Start of the running image.
+const SLOT_A: usize = 0x0800_0000;
+/// Offsets of the staged image and its record from the start of flash.
+const SLOT_B_OFFSET: u32 = 64 * 1024;
+const RECORD_OFFSET: u32 = SLOT_B_OFFSET + SLOT_LEN as u32;
+pub(crate) const SLOT_B: usize = SLOT_A + SLOT_B_OFFSET as usize;
+pub(crate) const RECORD_ADDRESS: usize = SLOT_A + RECORD_OFFSET as usize;
+pub(crate) const RECORD_MAGIC: u32 = 0x4248_4F54; // "BHOT"
+/// Offset of the staging record in the external [`OTA`](spi_flash::OTA)
+/// partition, right after the largest image.
+pub const EXTERNAL_RECORD: u32 = SLOT_LEN as u32;
+
+/// Up to [`CHUNK_MAX`] bytes of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Chunk {
+  len: u8,
+  bytes: [u8; CHUNK_MAX],
+}
+
+impl Chunk {
+  pub fn new(data: &[u8]) -> Option<Self> {
+    let mut bytes = [0u8; CHUNK_MAX];
+    bytes.get_mut(..data.len())?.copy_from_slice(data);
+    Some(Self {
+      len: data.len() as u8,
+      bytes,
+    })
+  }
+
+  pub fn data(&self) -> &[u8] {
+    &self.bytes[..self.len as usize]
+  }
+}
+
+/// What the sending bridge asks of its peer.  The `tag` of `Finish`, the
+/// image CMAC, is filled in by [`Sender::start`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Request {
+  Start { size: u32, crc: u32 },
+  Data { offset: u32, chunk: Chunk },
+  Finish { tag: [u8; TAG_LEN] },
+  Abort,
+}
+
+/// How the peer took a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Status {
+  Ok,
+  /// No second slot, no transfer running, or the request does not fit it.
+  Refused,
+  /// The image is incomplete or fails its CRC32 or CMAC.
+  Bad,
+  /// The flash could not be written.
+  Failed,
+}
+
+impl Status {
+  fn code(self) -> u8 {
+    match self {
+      Status::Ok => 0,
+      Status::Refused => 1,
+      Status::Bad => 2,
+      Status::Failed => 3,
+    }
+  }
+
+  fn from_code(code: u8) -> Option<Self> {
+    match code {
+      0 => Some(Status::Ok),
+      1 => Some(Status::Refused),
+      2 => Some(Status::Bad),
+      3 => Some(Status::Failed),
+      _ => None,
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Status::Ok => "OK",
+      Status::Refused => "REFUSED",
+      Status::Bad => "BAD",
+      Status::Failed => "FAILED",
+    }
+  }
+}
+
+/// An update message between bridges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Message {
+  Request { seq: u8, request: Request },
+  Reply { seq: u8, status: Status, next: u32 },
+}
+
+impl Message {
+  /// Encode into `out`, returning the length used.
+  pub fn encode(&self, out: &mut [u8; MESSAGE_MAX]) -> usize {
+    match *self {
+      Message::Request { seq, request } => {
+        out[1] = seq;
+        match request {
+          Request::Start { size, crc } => {
+            out[0] = TYPE_START;
+            out[2..6].copy_from_slice(&size.to_le_bytes());
+            out[6..10].copy_from_slice(&crc.to_le_bytes());
+            10
+          }
+          Request::Data { offset, chunk } => {
+            let data = chunk.data();
+            out[0] = TYPE_DATA;
+            out[2..6].copy_from_slice(&offset.to_le_bytes());
+            out[6..8].copy_from_slice(&crc16_ccitt(data).to_le_bytes());
+            out[8..8 + data.len()].copy_from_slice(data);
+            8 + data.len()
+          }
+          Request::Finish { tag } => {
+            out[0] = TYPE_FINISH;
+            out[2..2 + TAG_LEN].copy_from_slice(&tag);
+            2 + TAG_LEN
+          }
+          Request::Abort => {
+            out[0] = TYPE_ABORT;
+            2
+          }
+        }
+      }
+      Message::Reply { seq, status, next } => {
+        out[0] = TYPE_REPLY;
+        out[1] = seq;
+        out[2] = status.code();
+        out[3..7].copy_from_slice(&next.to_le_bytes());
+        7
+      }
+    }
+  }
+
+  pub fn decode(data: &[u8]) -> Option<Self> {
+    let [kind, seq, rest @ ..] = data else {
+      return None;
+    };
+    let seq = *seq;
+    let word = |at: usize| Some(u32::from_le_bytes(*rest.get(at..at + 4)?.first_chunk()?));
+    let request = match *kind {
+      TYPE_START => Request::Start {
+        size: word(0)?,
+        crc: word(4)?,
+      },
+      TYPE_DATA => {
+        let data = rest.get(6..)?;
+        if crc16_ccitt(data).to_le_bytes() != *rest.get(4..6)? {
+          defmt::warn!("[ota] chunk CRC mismatch");
+          return None;
+        }
+        Request::Data {
+          offset: word(0)?,
+          chunk: Chunk::new(data)?,
+        }
+      }
+      TYPE_FINISH => Request::Finish {
+        tag: *rest.first_chunk()?,
+      },
+      TYPE_ABORT => Request::Abort,
+      TYPE_REPLY => {
+        return Some(Message::Reply {
+          seq,
+          status: Status::from_code(*rest.first()?)?,
+          next: word(1)?,
+        });
+      }
+      _ => return None,
+    };
+    Some(Message::Request { seq, request })
+  }
+}
+
+/// What the sending side reports to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Event {
+  /// Send this message to the peer.
+  Send(Message),
+  /// The peer answered the outstanding request.
+  Answered {
+    request: Request,
+    status: Status,
+    next: u32,
+  },
+  /// The peer did not answer.
+  Timeout,
+}
+
+impl Event {
+  /// The `+OTA:` line for the host, if any.
+  pub fn report(&self) -> Option<String<32>> {
+    let mut line = String::new();
+    let _ = match *self {
+      Event::Send(_) => return None,
+      Event::Answered {
+        request: Request::Finish { .. },
+        status: Status::Ok,
+        ..
+      } => write!(line, "+OTA:DONE\r\n"),
+      Event::Answered {
+        status: Status::Ok,
+        next,
+        ..
+      } => write!(line, "+OTA:ACK,{}\r\n", next),
+      Event::Answered { status, .. } => write!(line, "+OTA:{}\r\n", status.name()),
+      Event::Timeout => write!(line, "+OTA:TIMEOUT\r\n"),
+    };
+    Some(line)
+  }
+}
+
+#[derive(Clone, Copy)]
+struct Pending {
+  seq: u8,
+  request: Request,
+  tries: u8,
+  next_tx: u32,
+}
+
+/// The sending side: one request to the peer at a time.
+pub struct Sender {
+  pending: Option<Pending>,
+  next_seq: u8,
+  /// CMAC over the image sent so far, and how many of its bytes it covers.
+  image: Option<(ImageMac, u32)>,
+}
+
+impl Default for Sender {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Sender {
+  pub const fn new() -> Self {
+    Self {
+      pending: None,
+      next_seq: 0,
+      image: None,
+    }
+  }
+
+  /// Whether a request is waiting for its reply.
+  pub fn busy(&self) -> bool {
+    self.pending.is_some()
+  }
+
+  /// Send `request` to the peer.  Returns `false` while one is
+  /// outstanding, and for an update with `security` off.
+  ///
+  /// Chunks enter the image CMAC in order: one that does not continue the
+  /// image is still sent, but the peer will then find the tag wrong.
+  pub fn start(&mut self, request: Request, now: u32, security: &Security) -> bool {
+    if self.busy() {
+      return false;
+    }
+    let request = match request {
+      Request::Start { size, .. } => {
+        let Some(mut mac) = security.image_mac() else {
+          return false;
+        };
+        mac.update(&size.to_le_bytes());
+        self.image = Some((mac, 0));
+        request
+      }
+      Request::Data { offset, chunk } => {
+        if let Some((mac, covered)) = self.image.as_mut()
+          && offset == *covered
+        {
+          mac.update(chunk.data());
+          *covered += chunk.data().len() as u32;
+        }
+        request
+      }
+      Request::Finish { .. } => {
+        let Some((mac, _)) = &self.image else {
+          return false;
+        };
+        let mut tag = [0u8; TAG_LEN];
+        tag.copy_from_slice(&mac.clone().finalize().into_bytes());
+        Request::Finish { tag }
+      }
+      Request::Abort => {
+        self.image = None;
+        request
+      }
+    };
+    self.pending = Some(Pending {
+      seq: self.next_seq,
+      request,
+      tries: 0,
+      next_tx: now,
+    });
+    self.next_seq = self.next_seq.wrapping_add(1);
+    true
+  }
+
+  /// Drive retransmissions.
+  pub fn poll(&mut self, now: u32) -> Option<Event> {
+    let pending = self.pending.as_mut()?;
+    if !due(now, pending.next_tx) {
+      return None;
+    }
+    if pending.tries >= TRIES {
+      defmt::warn!("[ota] peer did not answer {}", pending.request);
+      self.pending = None;
+      return Some(Event::Timeout);
+    }
+    pending.tries += 1;
+    pending.next_tx = now.wrapping_add(RETRY_MS);
+    Some(Event::Send(Message::Request {
+      seq: pending.seq,
+      request: pending.request,
+    }))
+  }
+
+  /// Handle the peer's reply.
+  pub fn handle(&mut self, seq: u8, status: Status, next: u32) -> Option<Event> {
+    let pending = self.pending.filter(|pending| pending.seq == seq)?;
+    self.pending = None;
+    Some(Event::Answered {
+      request: pending.request,
+      status,
+      next,
+    })
+  }
+}
+
+/// Where an image is staged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Slot {
+  /// The upper half of a 128 KiB flash.
+  Internal,
+  /// The OTA partition of the external flash (see [`crate::spi_flash`]).
+  External,
+}
+
+impl Slot {
+  /// Where this bridge stages an image, if anywhere.
+  pub fn available(external: bool) -> Option<Self> {
+    if dual_slot() {
+      Some(Slot::Internal)
+    } else if external {
+      Some(Slot::External)
+    } else {
+      None
+    }
+  }
+
+  /// Smallest erasable unit.
+  fn erase_len(self) -> u32 {
+    match self {
+      Slot::Internal => PAGE_LEN as u32,
+      Slot::External => spi_flash::SECTOR_LEN,
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Slot::Internal => "INTERNAL",
+      Slot::External => "EXTERNAL",
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+enum State {
+  Idle,
+  Receiving {
+    slot: Slot,
+    size: u32,
+    crc: u32,
+    written: u32,
+    /// Bytes of the slot erased so far.
+    erased: u32,
+  },
+  /// Staged; restarting to install.
+  Staged {
+    size: u32,
+  },
+}
+
+/// What the receiving side asks of the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Reaction {
+  pub reply: Message,
+  /// An image was staged: call [`Receiver::install`] after
+  /// [`INSTALL_DELAY_MS`].
+  pub install: bool,
+}
+
+/// The receiving side: writes the image into the staging slot.
+pub struct Receiver {
+  state: State,
+}
+
+impl Default for Receiver {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Receiver {
+  pub const fn new() -> Self {
+    Self { state: State::Idle }
+  }
+
+  /// Handle a request from the peer.  `external` is the external flash,
+  /// if fitted.  With `security` off every request is refused.
+  pub fn handle(
+    &mut self,
+    seq: u8,
+    request: Request,
+    flash: &mut flash::Parts,
+    external: Option<&mut dyn Storage>,
+    security: &Security,
+  ) -> Reaction {
+    let result = match security.image_mac() {
+      Some(mac) => self.carry_out(request, flash, external, mac),
+      None => Err(Status::Refused),
+    };
+    let (status, next, install) = match result {
+      Ok((next, install)) => (Status::Ok, next, install),
+      Err(status) => {
+        defmt::warn!("[ota] {} answered {}", request, status);
+        (status, 0, false)
+      }
+    };
+    Reaction {
+      reply: Message::Reply { seq, status, next },
+      install,
+    }
+  }
+
+  fn carry_out(
+    &mut self,
+    request: Request,
+    flash: &mut flash::Parts,
+    mut external: Option<&mut dyn Storage>,
+    mut mac: ImageMac,
+  ) -> Result<(u32, bool), Status> {
+    let mut writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz128K);
+    match (self.state, request) {
+      // Our reply was lost; the restart is already on its way.
+      (State::Staged { size }, Request::Finish { .. }) => Ok((size, true)),
+      (State::Staged { .. }, _) => Err(Status::Refused),
+      (_, Request::Abort) => {
+        self.state = State::Idle;
+        Ok((0, false))
+      }
+      (_, Request::Start { size, crc }) => {
+        let slot = Slot::available(external.is_some()).ok_or(Status::Refused)?;
+        if size as usize <= boot_select::BOOT_LEN || size as usize > SLOT_LEN || size % 4 != 0 {
+          return Err(Status::Refused);
+        }
+        // A staged image from before must not be installed half-replaced.
+        match (slot, external.as_deref_mut()) {
+          (Slot::Internal, _) => writer
+            .erase(RECORD_OFFSET, PAGE_LEN)
+            .map_err(|_| Status::Failed)?,
+          (Slot::External, Some(storage)) => spi_flash::OTA
+            .erase_sector(storage, EXTERNAL_RECORD)
+            .map_err(|_| Status::Failed)?,
+          (Slot::External, None) => return Err(Status::Failed),
+        }
+        defmt::info!(
+          "[ota] receiving {} bytes into {}, CRC32 {=u32:X}",
+          size,
+          slot,
+          crc
+        );
+        self.state = State::Receiving {
+          slot,
+          size,
+          crc,
+          written: 0,
+          erased: 0,
+        };
+        Ok((0, false))
+      }
+      (State::Idle, _) => Err(Status::Refused),
+      (
+        State::Receiving {
+          slot,
+          size,
+          crc,
+          written,
+          erased,
+        },
+        Request::Data { offset, chunk },
+      ) => {
+        let data = chunk.data();
+        if offset != written {
+          // A repeat, or a chunk after a lost one: name the one we need.
+          return Ok((written, false));
+        }
+        let end = offset + data.len() as u32;
+        if end > size || data.len() % 2 != 0 {
+          return Err(Status::Refused);
+        }
+        let mut erased = erased;
+        while erased < end {
+          match (slot, external.as_deref_mut()) {
+            (Slot::Internal, _) => writer
+              .erase(SLOT_B_OFFSET + erased, PAGE_LEN)
+              .map_err(|_| Status::Failed)?,
+            (Slot::External, Some(storage)) => spi_flash::OTA
+              .erase_sector(storage, erased)
+              .map_err(|_| Status::Failed)?,
+            (Slot::External, None) => return Err(Status::Failed),
+          }
+          erased += slot.erase_len();
+        }
+        match (slot, external.as_deref_mut()) {
+          (Slot::Internal, _) => writer
+            .write(SLOT_B_OFFSET + offset, data)
+            .map_err(|_| Status::Failed)?,
+          (Slot::External, Some(storage)) => spi_flash::OTA
+            .program(storage, offset, data)
+            .map_err(|_| Status::Failed)?,
+          (Slot::External, None) => return Err(Status::Failed),
+        }
+        self.state = State::Receiving {
+          slot,
+          size,
+          crc,
+          written: end,
+          erased,
+        };
+        Ok((end, false))
+      }
+      (
+        State::Receiving {
+          slot,
+          size,
+          crc,
+          written,
+          ..
+        },
+        Request::Finish { tag },
+      ) => {
+        self.state = State::Idle;
+        if written != size {
+          return Err(Status::Bad);
+        }
+        mac.update(&size.to_le_bytes());
+        // The application's initial stack pointer, after the selector.
+        let mut stack = [0u8; 4];
+        let image_crc = match (slot, external.as_deref_mut()) {
+          (Slot::Internal, _) => {
+            let image = slot_b(size as usize);
+            stack.copy_from_slice(&image[boot_select::BOOT_LEN..][..4]);
+            mac.update(image);
+            crc32(image)
+          }
+          (Slot::External, Some(storage)) => {
+            spi_flash::OTA
+              .read(storage, boot_select::BOOT_LEN as u32, &mut stack)
+              .map_err(|_| Status::Failed)?;
+            external_crc(storage, size, &mut mac)?
+          }
+          (Slot::External, None) => return Err(Status::Failed),
+        };
+        if mac.verify_slice(&tag).is_err() {
+          defmt::warn!("[ota] image CMAC mismatch");
+          return Err(Status::Bad);
+        }
+        if image_crc != crc || !boot_select::is_application(u32::from_le_bytes(stack)) {
+          return Err(Status::Bad);
+        }
+        let mut record = [0u8; 12];
+        record[0..4].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+        record[4..8].copy_from_slice(&size.to_le_bytes());
+        record[8..12].copy_from_slice(&crc.to_le_bytes());
+        match (slot, external) {
+          (Slot::Internal, _) => writer
+            .write(RECORD_OFFSET, &record)
+            .map_err(|_| Status::Failed)?,
+          (Slot::External, Some(storage)) => spi_flash::OTA
+            .program(storage, EXTERNAL_RECORD, &record)
+            .map_err(|_| Status::Failed)?,
+          (Slot::External, None) => return Err(Status::Failed),
+        }
+        defmt::info!("[ota] image staged, restarting to install");
+        self.state = State::Staged { size };
+        Ok((size, true))
+      }
+    }
+  }
+
+  /// Install the staged image: restart into the boot selector, which
+  /// copies it.
+  pub fn install(&self) -> ! {
+    cortex_m::peripheral::SCB::sys_reset()
+  }
+
+  /// `<bytes received>/<size>`, or `IDLE`.
+  fn progress(&self, line: &mut String<48>) {
+    let _ = match self.state {
+      State::Idle => write!(line, "IDLE"),
+      State::Receiving { size, written, .. } => write!(line, "{}/{}", written, size),
+      State::Staged { size } => write!(line, "{}/{}", size, size),
+    };
+  }
+}
+
+/// `+OTA:<IDLE|WAIT>,<received>/<size>|IDLE,<INTERNAL|EXTERNAL|NONE>`: the
+/// sending side, the receiving side and where this bridge stages an image.
+pub fn report(sender: &Sender, receiver: &Receiver, external: bool) -> String<48> {
+  let mut line = String::new();
+  let _ = write!(
+    line,
+    "+OTA:{},",
+    if sender.busy() { "WAIT" } else { "IDLE" }
+  );
+  receiver.progress(&mut line);
+  let slot = Slot::available(external).map_or("NONE", Slot::name);
+  let _ = write!(line, ",{}\r\n", slot);
+  line
+}
+
+/// Whether the flash holds a second slot.
+pub fn dual_slot() -> bool {
+  let kib = unsafe { (FLASH_SIZE_REG as *const u16).read_volatile() };
+  kib >= 128
+}
+
+fn slot_b(size: usize) -> &'static [u8] {
+  unsafe { core::slice::from_raw_parts(SLOT_B as *const u8, size) }
+}
+
+/// CRC32 of the image in the external flash, also fed to `mac`.
+fn external_crc(storage: &mut dyn Storage, size: u32, mac: &mut ImageMac) -> Result<u32, Status> {
+  let mut crc = 0;
+  let mut buf = [0u8; 256];
+  let mut offset = 0;
+  while offset < size {
+    let len = buf.len().min((size - offset) as usize);
+    spi_flash::OTA
+      .read(storage, offset, &mut buf[..len])
+      .map_err(|_| Status::Failed)?;
+    crc = crc32_update(crc, &buf[..len]);
+    mac.update(&buf[..len]);
+    offset += len as u32;
+  }
+  Ok(crc)
+}
+
+/// Whether `deadline` has been reached at `now`, allowing for wrap-around.
+fn due(now: u32, deadline: u32) -> bool {
+  now.wrapping_sub(deadline) as i32 >= 0
+}
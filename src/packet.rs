@@ -0,0 +1,99 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/packet.rs - 数据包缓冲区内存池
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Packet buffers from a fixed pool.
+//!
+//! An outgoing frame is collected or encoded straight into a [`Packet`]
+//! from a `heapless::pool` of [`BLOCKS`] frame-sized blocks, and the
+//! packet itself moves through the transmit queue ([`crate::txqueue`]) to
+//! the radio; the frame is never copied between buffers on the way.
+//! Dropping a packet returns its block.
+//!
+//! The pool counts the packets in use and the most ever in use at once;
+//! both are in the binary protocol's statistics and the `AT+TXQ?` report.
+//! Packets in use while the queue is empty point to a leak.
+
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use heapless::pool::boxed::{Box, BoxBlock};
+use heapless::{Vec, box_pool};
+
+use crate::packetizer::MAX_PAYLOAD;
+use crate::txqueue;
+
+/// Blocks in the pool: a full transmit queue, the frame the packetizer
+/// collects and one frame being encoded.
+pub const BLOCKS: usize = txqueue::DEPTH + 2;
+
+/// A frame as sent on the air.
+pub type Frame = Vec<u8, MAX_PAYLOAD>;
+
+box_pool!(PacketPool: Frame);
+
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Give the pool its blocks.  Call once at boot.
+pub fn init() {
+  let blocks: &'static mut [BoxBlock<Frame>; BLOCKS] =
+    cortex_m::singleton!(: [BoxBlock<Frame>; BLOCKS] = [const { BoxBlock::new() }; BLOCKS])
+      .unwrap();
+  for block in blocks {
+    PacketPool.manage(block);
+  }
+}
+
+/// An empty frame buffer, or `None` when every block is in use.
+pub fn alloc() -> Option<Packet> {
+  let Ok(frame) = PacketPool.alloc(Vec::new()) else {
+    defmt::warn!("[packet] pool exhausted");
+    return None;
+  };
+  let in_use = IN_USE.fetch_add(1, Ordering::Relaxed) + 1;
+  PEAK.fetch_max(in_use, Ordering::Relaxed);
+  Some(Packet(frame))
+}
+
+/// Packets in use now.
+pub fn in_use() -> usize {
+  IN_USE.load(Ordering::Relaxed)
+}
+
+/// Most packets in use at once since boot.
+pub fn peak() -> usize {
+  PEAK.load(Ordering::Relaxed)
+}
+
+/// A frame buffer from the pool.
+pub struct Packet(Box<PacketPool>);
+
+impl Deref for Packet {
+  type Target = Frame;
+
+  fn deref(&self) -> &Frame {
+    &self.0
+  }
+}
+
+impl DerefMut for Packet {
+  fn deref_mut(&mut self) -> &mut Frame {
+    &mut self.0
+  }
+}
+
+impl Drop for Packet {
+  fn drop(&mut self) {
+    IN_USE.fetch_sub(1, Ordering::Relaxed);
+  }
+}
@@ -0,0 +1,253 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/packetizer.rs - USB 数据分帧
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Coalesces the host byte stream into LoRa payloads.
+//!
+//! A host `write()` arrives as a series of 64-byte CDC packets.  Instead of
+//! transmitting each packet as its own LoRa frame, bytes are collected here
+//! until the configured [`FrameMode`] decides the frame is complete.  A frame
+//! is always closed once it reaches the size limit (at most [`MAX_PAYLOAD`],
+//! less when the link layer adds a header).
+//!
+//! Bytes go from the port queue straight into a packet buffer from the pool
+//! ([`crate::packet`]), behind room for the link header.  The completed
+//! packet is taken as a whole, gets its header written in place and goes
+//! through the transmit queue to the radio FIFO without being copied again.
+
+use crate::kiss;
+use crate::modbus;
+use crate::packet::{self, Packet};
+
+/// Largest LoRa payload the SX1268 can transmit.
+pub const MAX_PAYLOAD: usize = 255;
+
+/// How the end of a frame is detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum FrameMode {
+  /// Close the frame after this byte (included in the payload).
+  Terminator(u8),
+  /// Close the frame when no byte arrived for this many milliseconds.
+  IdleGap(u32),
+  /// Close the frame once it holds this many bytes (1..=255).
+  FixedSize(usize),
+  /// Each frame is preceded by its length byte (framed protocol mode).  The
+  /// length byte is not part of the payload; a zero length is ignored.
+  LengthPrefixed,
+  /// KISS TNC frames; see [`kiss`].  Frames longer than the limit are
+  /// dropped whole.
+  Kiss,
+  /// Modbus RTU frames, ended by a silent interval of this many
+  /// milliseconds; see [`modbus`].  Frames with a bad CRC are dropped.
+  Modbus(u32),
+}
+
+impl FrameMode {
+  /// Whether the byte stream carries its own framing, so a partial frame
+  /// means nothing to another mode.
+  fn is_protocol(self) -> bool {
+    matches!(self, FrameMode::LengthPrefixed | FrameMode::Kiss)
+  }
+}
+
+impl Default for FrameMode {
+  fn default() -> Self {
+    FrameMode::IdleGap(20)
+  }
+}
+
+pub struct Packetizer {
+  mode: FrameMode,
+  /// The frame being collected, behind `headroom` bytes; allocated with
+  /// its first byte.
+  frame: Option<Packet>,
+  /// Bytes left in front of the payload for the link header.
+  headroom: usize,
+  /// Largest payload handed out.
+  limit: usize,
+  /// Announced length of the current frame in `LengthPrefixed` mode.
+  expected: Option<usize>,
+  kiss: kiss::Decoder,
+  /// The current KISS frame exceeded the limit.
+  oversize: bool,
+  last_byte_ms: u32,
+  ready: bool,
+}
+
+impl Packetizer {
+  pub fn new(mode: FrameMode) -> Self {
+    Self {
+      mode,
+      frame: None,
+      headroom: 0,
+      limit: MAX_PAYLOAD,
+      expected: None,
+      kiss: kiss::Decoder::new(),
+      oversize: false,
+      last_byte_ms: 0,
+      ready: false,
+    }
+  }
+
+  pub fn mode(&self) -> FrameMode {
+    self.mode
+  }
+
+  /// Switch framing mode.  A partially collected frame is kept and judged
+  /// by the new mode, except when entering or leaving `LengthPrefixed` or
+  /// `Kiss`, where the byte stream changes meaning and the partial frame is
+  /// dropped.
+  pub fn set_mode(&mut self, mode: FrameMode) {
+    if mode != self.mode && (mode.is_protocol() || self.mode.is_protocol()) {
+      self.clear();
+      self.kiss.reset();
+    }
+    self.mode = mode;
+  }
+
+  /// Leave `headroom` bytes for the link header in front of the payload
+  /// and cap the payload at `limit`.  A partial frame moves behind the new
+  /// header room.
+  pub fn set_limit(&mut self, headroom: usize, limit: usize) {
+    self.limit = limit.clamp(1, MAX_PAYLOAD - headroom);
+    if headroom == self.headroom {
+      return;
+    }
+    if let Some(frame) = self.frame.as_mut() {
+      let len = (frame.len() - self.headroom).min(MAX_PAYLOAD - headroom);
+      if headroom > self.headroom {
+        let _ = frame.resize(headroom + len, 0);
+        frame.copy_within(self.headroom..self.headroom + len, headroom);
+      } else {
+        frame.copy_within(self.headroom..self.headroom + len, headroom);
+        frame.truncate(headroom + len);
+      }
+    }
+    self.headroom = headroom;
+  }
+
+  /// Pull bytes from `next_byte` until a frame is complete or the source is
+  /// empty.  Returns `true` when [`frame`](Self::frame) is ready to send.
+  pub fn poll(&mut self, now_ms: u32, mut next_byte: impl FnMut() -> Option<u8>) -> bool {
+    while !self.ready {
+      // Bytes stay in the port queue while the pool is empty.
+      if self.frame.is_none() {
+        let Some(mut frame) = packet::alloc() else {
+          break;
+        };
+        let _ = frame.resize(self.headroom, 0);
+        self.frame = Some(frame);
+      }
+      let Some(byte) = next_byte() else {
+        break;
+      };
+      if self.mode == FrameMode::LengthPrefixed && self.expected.is_none() {
+        if byte > 0 {
+          self.expected = Some(byte as usize);
+        }
+        continue;
+      }
+      if self.mode == FrameMode::Kiss {
+        self.feed_kiss(byte);
+        continue;
+      }
+      // `ready` is false, so there is always room for one more byte.
+      self.push(byte);
+      self.last_byte_ms = now_ms;
+
+      let len = self.frame().len();
+      self.ready = len >= self.limit
+        || match self.mode {
+          FrameMode::Terminator(end) => byte == end,
+          FrameMode::IdleGap(_) | FrameMode::Modbus(_) => false,
+          FrameMode::FixedSize(size) => len >= size,
+          FrameMode::LengthPrefixed => Some(len) == self.expected,
+          FrameMode::Kiss => false,
+        };
+    }
+
+    if let FrameMode::IdleGap(gap_ms) | FrameMode::Modbus(gap_ms) = self.mode
+      && !self.ready
+      && !self.frame().is_empty()
+      && now_ms.wrapping_sub(self.last_byte_ms) >= gap_ms
+    {
+      self.ready = true;
+    }
+    if let FrameMode::Modbus(_) = self.mode
+      && self.ready
+      && !modbus::valid(self.frame())
+    {
+      let len = self.frame().len();
+      defmt::warn!("[packetizer] Modbus frame of {} bytes with bad CRC dropped", len);
+      self.clear();
+    }
+
+    self.ready
+  }
+
+  fn feed_kiss(&mut self, byte: u8) {
+    match self.kiss.feed(byte) {
+      Some(kiss::Event::Byte(byte)) => {
+        if self.frame().len() < self.limit {
+          self.push(byte);
+        } else {
+          self.oversize = true;
+        }
+      }
+      Some(kiss::Event::End) if self.oversize => {
+        defmt::warn!("[packetizer] KISS frame over {} bytes dropped", self.limit);
+        self.truncate();
+        self.oversize = false;
+      }
+      Some(kiss::Event::End) => self.ready = !self.frame().is_empty(),
+      None => {}
+    }
+  }
+
+  /// The payload of the completed frame.  Only meaningful after `poll`
+  /// returned `true`.
+  pub fn frame(&self) -> &[u8] {
+    self.frame.as_ref().map_or(&[], |frame| &frame[self.headroom..])
+  }
+
+  /// Hand out the completed frame, payload behind the header room, and
+  /// start collecting the next one.
+  pub fn take(&mut self) -> Option<Packet> {
+    let frame = self.frame.take();
+    self.clear();
+    frame
+  }
+
+  fn push(&mut self, byte: u8) {
+    if let Some(frame) = self.frame.as_mut() {
+      let _ = frame.push(byte);
+    }
+  }
+
+  fn truncate(&mut self) {
+    if let Some(frame) = self.frame.as_mut() {
+      frame.truncate(self.headroom);
+    }
+  }
+
+  /// Discard the current frame and start collecting the next one.
+  pub fn clear(&mut self) {
+    self.truncate();
+    self.expected = None;
+    // A sent KISS frame may share its closing FEND with the next one.
+    if !self.ready {
+      self.kiss.reset();
+    }
+    self.oversize = false;
+    self.ready = false;
+  }
+}
@@ -0,0 +1,356 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/pairing.rs - 设备配对流程
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Pairing of two bridges over a well-known channel.
+//!
+//! Pairing is started on both devices (button or `AT+PAIR`) within
+//! [`TIMEOUT_MS`] of each other.  Both switch to [`channel`] and exchange
+//! frames of the form
+//!
+//! ```text
+//! ["BHP"][type u8][src u16 LE][dst u16 LE][nonce 8][public key 32]
+//!   [radio params 8][proof 4]?
+//! ```
+//!
+//! 1. Each side broadcasts `HELLO` with a fresh nonce, the public half of
+//!    a fresh X25519 key pair and its current radio parameters until it
+//!    hears another device.
+//! 2. Once it knows the peer's public key it derives the session key and
+//!    sends `ACK` to the peer instead.  `ACK` carries the same body plus a
+//!    proof: the truncated CMAC of the frame under the session key.
+//! 3. A valid `ACK` completes pairing.  The device stays on the pairing
+//!    channel for [`LINGER_MS`] answering the peer's `ACK`s so that the peer
+//!    completes too.
+//!
+//! The session key comes from the X25519 shared secret `Z` in two CMAC
+//! steps, as in NIST SP 800-56C: `KDK = CMAC(PAIRING_KEY, Z)`, then
+//! `CMAC(KDK, nonce_lo || public_lo || nonce_hi || public_hi)`, ordered by
+//! node address.  The radio parameters offered by the lower address win.
+//!
+//! A device that only listens to the exchange learns the nonces and public
+//! keys but not `Z`, so it cannot derive the key.  The exchange is not
+//! authenticated though: a device that takes part in it while pairing is
+//! under way can pose as the peer to each side.  The short range of
+//! [`channel`] keeps that window small.
+//!
+//! The secret half of the key pair is drawn from radio noise read when
+//! pairing starts (see [`random::noise`]) rather than from the random
+//! pool, whose 64-bit state would bound it.  Each X25519 operation takes a
+//! few tens of milliseconds on the MCU; pairing runs two.
+
+use aes::Aes128;
+use cmac::{Cmac, Mac};
+use heapless::Vec;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::device_id;
+use crate::link::BROADCAST;
+use crate::lora_config;
+use crate::radio::{self, RadioParams};
+use crate::random;
+
+/// Pairing gives up after this long without completing.
+pub const TIMEOUT_MS: u32 = 30_000;
+/// Time spent answering the peer after completion.
+pub const LINGER_MS: u32 = 3_000;
+/// Base interval between retransmissions.
+const RETRY_MS: u32 = 500;
+
+/// Radio noise words the key pair is drawn from.
+pub const NOISE_WORDS: usize = 8;
+
+/// Fixed key used only to derive nonces and key pairs and as the salt of
+/// the session key derivation.
+const PAIRING_KEY: [u8; 16] = *b"BlueHighPairing1";
+const MAGIC: &[u8; 3] = b"BHP";
+const TYPE_HELLO: u8 = 1;
+const TYPE_ACK: u8 = 2;
+
+const NONCE_LEN: usize = 8;
+const PUBLIC_LEN: usize = 32;
+const PROOF_LEN: usize = 4;
+/// Magic, type, src and dst.
+const HEADER_LEN: usize = 8;
+const BODY_LEN: usize = NONCE_LEN + PUBLIC_LEN + radio::ENCODED_LEN;
+/// Largest pairing frame.
+pub const FRAME_MAX: usize = HEADER_LEN + BODY_LEN + PROOF_LEN;
+
+/// Radio parameters of the pairing channel: low power, robust modulation.
+pub fn channel() -> RadioParams {
+  lora_config::PAIRING.params
+}
+
+/// Outcome of a successful pairing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Paired {
+  pub peer: u16,
+  pub key: [u8; 16],
+  pub radio: RadioParams,
+}
+
+/// What the caller should do after [`Pairing::poll`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Progress {
+  /// Nothing to do yet.
+  Pending,
+  /// Transmit the frame written to `out`.
+  Transmit,
+  /// Pairing finished; leave the pairing channel.
+  Complete(Paired),
+  /// No peer completed pairing in time.
+  TimedOut,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+  /// Broadcasting `HELLO`.
+  Discover,
+  /// Peer known, sending `ACK` until the peer's `ACK` arrives.
+  Confirm(Paired),
+  /// Paired; answering the peer until the deadline.
+  Linger(Paired, u32),
+}
+
+/// A decoded pairing frame.
+struct Frame {
+  kind: u8,
+  src: u16,
+  dst: u16,
+  nonce: [u8; NONCE_LEN],
+  public: PublicKey,
+  offer: RadioParams,
+}
+
+impl Frame {
+  fn decode(frame: &[u8]) -> Option<Self> {
+    if frame.len() < HEADER_LEN + BODY_LEN || &frame[..3] != MAGIC {
+      return None;
+    }
+    let kind = frame[3];
+    if kind != TYPE_HELLO && kind != TYPE_ACK {
+      return None;
+    }
+    let body = &frame[HEADER_LEN..];
+    let public: [u8; PUBLIC_LEN] = body[NONCE_LEN..NONCE_LEN + PUBLIC_LEN].try_into().ok()?;
+    Some(Self {
+      kind,
+      src: u16::from_le_bytes([frame[4], frame[5]]),
+      dst: u16::from_le_bytes([frame[6], frame[7]]),
+      nonce: body[..NONCE_LEN].try_into().ok()?,
+      public: PublicKey::from(public),
+      offer: RadioParams::decode(&body[NONCE_LEN + PUBLIC_LEN..])?,
+    })
+  }
+}
+
+pub struct Pairing {
+  state: State,
+  local: u16,
+  nonce: [u8; NONCE_LEN],
+  secret: StaticSecret,
+  public: PublicKey,
+  offer: RadioParams,
+  started: u32,
+  next_tx: u32,
+  /// Retransmit interval, jittered per device so two bridges started
+  /// together do not keep colliding.
+  retry_ms: u32,
+}
+
+impl Pairing {
+  /// Start pairing as `local`, offering `offer` as the link parameters.
+  /// `noise` is fresh radio noise for the key pair, see
+  /// [`random::noise`].
+  pub fn start(local: u16, offer: RadioParams, noise: &[u32; NOISE_WORDS], now: u32) -> Self {
+    let nonce = make_nonce(now);
+    let secret = make_secret(noise, now);
+    Self {
+      state: State::Discover,
+      local,
+      nonce,
+      public: PublicKey::from(&secret),
+      secret,
+      offer,
+      started: now,
+      next_tx: now,
+      retry_ms: RETRY_MS + (nonce[0] as u32) * 2,
+    }
+  }
+
+  /// Advance the procedure.
+  ///
+  /// `rx` is a frame received on the pairing channel, if any.  When
+  /// [`Progress::Transmit`] is returned, `out` holds the frame to send.
+  pub fn poll(&mut self, now: u32, rx: Option<&[u8]>, out: &mut Vec<u8, FRAME_MAX>) -> Progress {
+    let reply = rx.is_some_and(|frame| self.receive(frame, now));
+
+    match self.state {
+      State::Linger(paired, until) => {
+        if reply {
+          self.encode(out);
+          Progress::Transmit
+        } else if now.wrapping_sub(until) as i32 >= 0 {
+          Progress::Complete(paired)
+        } else {
+          Progress::Pending
+        }
+      }
+      _ if now.wrapping_sub(self.started) >= TIMEOUT_MS => Progress::TimedOut,
+      _ => {
+        if !reply && (now.wrapping_sub(self.next_tx) as i32) < 0 {
+          return Progress::Pending;
+        }
+        self.next_tx = now.wrapping_add(self.retry_ms);
+        self.encode(out);
+        Progress::Transmit
+      }
+    }
+  }
+
+  /// Handle a received frame.  Returns whether it calls for an immediate
+  /// reply.
+  fn receive(&mut self, data: &[u8], now: u32) -> bool {
+    let Some(frame) = Frame::decode(data) else {
+      return false;
+    };
+    if frame.src == self.local || (frame.dst != BROADCAST && frame.dst != self.local) {
+      return false;
+    }
+
+    let paired = match self.state {
+      State::Discover => {
+        let Some(paired) = self.derive(&frame) else {
+          defmt::warn!("[pair] weak public key from 0x{:04X}", frame.src);
+          return false;
+        };
+        defmt::info!("[pair] found peer 0x{:04X}", paired.peer);
+        self.state = State::Confirm(paired);
+        paired
+      }
+      State::Confirm(paired) | State::Linger(paired, _) if frame.src == paired.peer => paired,
+      // Somebody else pairing nearby.
+      _ => return false,
+    };
+
+    if let State::Confirm(_) = self.state
+      && frame.kind == TYPE_ACK
+      && proof(&paired.key, &data[..HEADER_LEN + BODY_LEN]) == data[HEADER_LEN + BODY_LEN..]
+    {
+      defmt::info!("[pair] confirmed by 0x{:04X}", paired.peer);
+      self.state = State::Linger(paired, now.wrapping_add(LINGER_MS));
+    }
+    true
+  }
+
+  /// Session parameters agreed with the sender of `frame`; `None` when
+  /// its public key is one of the low-order points that force the shared
+  /// secret.
+  fn derive(&self, frame: &Frame) -> Option<Paired> {
+    let shared = self.secret.diffie_hellman(&frame.public);
+    if !shared.was_contributory() {
+      return None;
+    }
+    let ours = (&self.nonce, &self.public);
+    let theirs = (&frame.nonce, &frame.public);
+    let (first, second, radio) = if self.local < frame.src {
+      (ours, theirs, self.offer)
+    } else {
+      (theirs, ours, frame.offer)
+    };
+
+    let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(&PAIRING_KEY).unwrap();
+    mac.update(shared.as_bytes());
+    let kdk = mac.finalize().into_bytes();
+    let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(&kdk).unwrap();
+    for (nonce, public) in [first, second] {
+      mac.update(nonce);
+      mac.update(public.as_bytes());
+    }
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&mac.finalize().into_bytes());
+    Some(Paired {
+      peer: frame.src,
+      key,
+      radio,
+    })
+  }
+
+  /// Build the frame for the current state.
+  fn encode(&self, out: &mut Vec<u8, FRAME_MAX>) {
+    let (kind, dst, key) = match self.state {
+      State::Discover => (TYPE_HELLO, BROADCAST, None),
+      State::Confirm(paired) | State::Linger(paired, _) => {
+        (TYPE_ACK, paired.peer, Some(paired.key))
+      }
+    };
+    out.clear();
+    let _ = out.extend_from_slice(MAGIC);
+    let _ = out.push(kind);
+    let _ = out.extend_from_slice(&self.local.to_le_bytes());
+    let _ = out.extend_from_slice(&dst.to_le_bytes());
+    let _ = out.extend_from_slice(&self.nonce);
+    let _ = out.extend_from_slice(self.public.as_bytes());
+    let _ = out.extend_from_slice(&self.offer.encode());
+    if let Some(key) = key {
+      let tag = proof(&key, out);
+      let _ = out.extend_from_slice(&tag);
+    }
+  }
+}
+
+/// Truncated CMAC proving knowledge of the session key.
+fn proof(key: &[u8; 16], data: &[u8]) -> [u8; PROOF_LEN] {
+  let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(key).unwrap();
+  mac.update(data);
+  let tag = mac.finalize().into_bytes();
+  [tag[0], tag[1], tag[2], tag[3]]
+}
+
+/// Per-session nonce.
+///
+/// Mixes the chip UID and the time pairing was started with radio noise
+/// (see [`crate::random`]), so it stays unpredictable even when both are
+/// known.
+fn make_nonce(now: u32) -> [u8; NONCE_LEN] {
+  let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(&PAIRING_KEY).unwrap();
+  for word in device_id::uid() {
+    mac.update(&word.to_le_bytes());
+  }
+  mac.update(&now.to_le_bytes());
+  for _ in 0..NONCE_LEN / 4 {
+    mac.update(&random::random_u32().to_le_bytes());
+  }
+  let tag = mac.finalize().into_bytes();
+  let mut nonce = [0u8; NONCE_LEN];
+  nonce.copy_from_slice(&tag[..NONCE_LEN]);
+  nonce
+}
+
+/// Secret half of the session's X25519 key pair: `noise` whitened by CMAC
+/// together with the chip UID and the time pairing was started.
+fn make_secret(noise: &[u32; NOISE_WORDS], now: u32) -> StaticSecret {
+  let mut secret = [0u8; 32];
+  for (block, half) in secret.chunks_exact_mut(16).enumerate() {
+    let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(&PAIRING_KEY).unwrap();
+    mac.update(&[block as u8]);
+    for word in device_id::uid() {
+      mac.update(&word.to_le_bytes());
+    }
+    mac.update(&now.to_le_bytes());
+    for word in noise {
+      mac.update(&word.to_le_bytes());
+    }
+    half.copy_from_slice(&mac.finalize().into_bytes());
+  }
+  StaticSecret::from(secret)
+}
@@ -0,0 +1,205 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/per.rs - 误包率测试
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Packet error rate test.
+//!
+//! One bridge runs `AT+PER=TX,<count>,<interval ms>` and sends numbered
+//! control frames with a known filler:
+//!
+//! ```text
+//! [0x30][seq u32 LE][filler 0x00, 0x01, ... 0x0F]
+//! ```
+//!
+//! The other runs `AT+PER=RX` and every [`REPORT_INTERVAL_MS`] prints how
+//! many frames arrived, how many sequence numbers were skipped, frames whose
+//! filler was wrong, receive errors (CRC failures) and RSSI statistics.
+//! `AT+PER=OFF` stops either side.
+
+/// How the test is started from the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum PerMode {
+  Transmit { count: u32, interval_ms: u32 },
+  Receive,
+  Stop,
+}
+
+/// Interval between receiver reports.
+pub const REPORT_INTERVAL_MS: u32 = 5_000;
+
+const TYPE_PER: u8 = 0x30;
+const FILLER_LEN: usize = 16;
+/// Bytes of a test frame.
+pub const FRAME_LEN: usize = 5 + FILLER_LEN;
+
+/// Sending side.
+pub struct Sender {
+  seq: u32,
+  count: u32,
+  interval_ms: u32,
+  next_tx: u32,
+}
+
+impl Sender {
+  pub fn new(count: u32, interval_ms: u32, now: u32) -> Self {
+    Self {
+      seq: 0,
+      count,
+      interval_ms,
+      next_tx: now,
+    }
+  }
+
+  /// Whether every frame has been sent.
+  pub fn done(&self) -> bool {
+    self.seq >= self.count
+  }
+
+  /// Fill `out` with the next frame if it is due.
+  pub fn next(&mut self, now: u32, out: &mut [u8; FRAME_LEN]) -> bool {
+    if self.done() || (now.wrapping_sub(self.next_tx) as i32) < 0 {
+      return false;
+    }
+    out[0] = TYPE_PER;
+    out[1..5].copy_from_slice(&self.seq.to_le_bytes());
+    for (i, byte) in out[5..].iter_mut().enumerate() {
+      *byte = i as u8;
+    }
+    self.seq += 1;
+    self.next_tx = now.wrapping_add(self.interval_ms);
+    true
+  }
+
+  pub fn sent(&self) -> u32 {
+    self.seq
+  }
+}
+
+/// Receiver statistics since the test started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Stats {
+  pub received: u32,
+  pub missing: u32,
+  pub corrupted: u32,
+  pub rx_errors: u32,
+  pub rssi_min: i16,
+  pub rssi_avg: i16,
+  pub rssi_max: i16,
+}
+
+impl Stats {
+  /// Packet error rate in tenths of a percent.
+  pub fn per_permille(&self) -> u32 {
+    let bad = self.missing + self.corrupted;
+    (bad * 1000)
+      .checked_div(self.received + self.missing)
+      .unwrap_or(0)
+  }
+}
+
+/// Receiving side.
+pub struct Receiver {
+  next_seq: Option<u32>,
+  received: u32,
+  missing: u32,
+  corrupted: u32,
+  rx_errors: u32,
+  rssi_count: u32,
+  rssi_sum: i32,
+  rssi_min: i16,
+  rssi_max: i16,
+  next_report: u32,
+}
+
+impl Receiver {
+  pub fn new(now: u32) -> Self {
+    Self {
+      next_seq: None,
+      received: 0,
+      missing: 0,
+      corrupted: 0,
+      rx_errors: 0,
+      rssi_count: 0,
+      rssi_sum: 0,
+      rssi_min: i16::MAX,
+      rssi_max: i16::MIN,
+      next_report: now.wrapping_add(REPORT_INTERVAL_MS),
+    }
+  }
+
+  /// Whether `data` is a PER test frame.
+  pub fn is_per_frame(data: &[u8]) -> bool {
+    data.first() == Some(&TYPE_PER)
+  }
+
+  pub fn on_frame(&mut self, data: &[u8], rssi_dbm: Option<i16>) {
+    let intact = data.len() == FRAME_LEN
+      && data[5..].iter().enumerate().all(|(i, &byte)| byte == i as u8);
+    if !intact {
+      self.corrupted += 1;
+      return;
+    }
+    let seq = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+    match self.next_seq {
+      Some(expected) if seq > expected => self.missing += seq - expected,
+      // Sender restarted; start counting afresh.
+      Some(expected) if seq < expected => {
+        let next_report = self.next_report;
+        *self = Self::new(0);
+        self.next_report = next_report;
+      }
+      _ => {}
+    }
+    self.next_seq = Some(seq + 1);
+    self.received += 1;
+    if let Some(rssi) = rssi_dbm {
+      self.rssi_count += 1;
+      self.rssi_sum += rssi as i32;
+      self.rssi_min = self.rssi_min.min(rssi);
+      self.rssi_max = self.rssi_max.max(rssi);
+    }
+  }
+
+  /// Account for a frame the radio failed to receive (CRC or header error).
+  pub fn on_rx_error(&mut self) {
+    self.rx_errors += 1;
+  }
+
+  /// Statistics, once every [`REPORT_INTERVAL_MS`].
+  pub fn poll(&mut self, now: u32) -> Option<Stats> {
+    if (now.wrapping_sub(self.next_report) as i32) < 0 {
+      return None;
+    }
+    self.next_report = now.wrapping_add(REPORT_INTERVAL_MS);
+    Some(self.stats())
+  }
+
+  pub fn stats(&self) -> Stats {
+    let (rssi_min, rssi_avg, rssi_max) = match self.rssi_count {
+      0 => (0, 0, 0),
+      count => (
+        self.rssi_min,
+        (self.rssi_sum / count as i32) as i16,
+        self.rssi_max,
+      ),
+    };
+    Stats {
+      received: self.received,
+      missing: self.missing,
+      corrupted: self.corrupted,
+      rx_errors: self.rx_errors,
+      rssi_min,
+      rssi_avg,
+      rssi_max,
+    }
+  }
+}
@@ -0,0 +1,232 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/ping.rs - 链路 Ping 测试
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Ping/echo link test between two bridges.
+//!
+//! Probes travel in control frames to the paired peer:
+//!
+//! ```text
+//! PING: [0x10][seq u8][sent_ms u32 LE]
+//! PONG: [0x11][seq u8][sent_ms u32 LE][rssi i16 LE][snr i8]
+//! ```
+//!
+//! The peer answers every `PING` with a `PONG` echoing the timestamp and
+//! reporting how it heard the `PING`; the initiator adds how it heard the
+//! `PONG`.  One probe is outstanding at a time.  The round-trip time
+//! includes both transmissions, so it grows with the spreading factor.
+
+use crate::radio::PacketStatus;
+
+/// Time to wait for a `PONG` before counting the probe as lost.
+pub const PROBE_TIMEOUT_MS: u32 = 3_000;
+/// Pause between a probe completing and the next one.
+const PROBE_INTERVAL_MS: u32 = 500;
+
+const TYPE_PING: u8 = 0x10;
+const TYPE_PONG: u8 = 0x11;
+
+/// Largest encoded probe.
+pub const PROBE_MAX: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Probe {
+  Ping { seq: u8, sent_ms: u32 },
+  Pong {
+    seq: u8,
+    sent_ms: u32,
+    /// How the peer heard the `PING`.
+    heard: PacketStatus,
+  },
+}
+
+impl Probe {
+  /// Encode into `out`, returning the length used.
+  pub fn encode(&self, out: &mut [u8; PROBE_MAX]) -> usize {
+    match *self {
+      Probe::Ping { seq, sent_ms } => {
+        out[0] = TYPE_PING;
+        out[1] = seq;
+        out[2..6].copy_from_slice(&sent_ms.to_le_bytes());
+        6
+      }
+      Probe::Pong {
+        seq,
+        sent_ms,
+        heard,
+      } => {
+        out[0] = TYPE_PONG;
+        out[1] = seq;
+        out[2..6].copy_from_slice(&sent_ms.to_le_bytes());
+        out[6..8].copy_from_slice(&heard.rssi_dbm.to_le_bytes());
+        out[8] = heard.snr_db as u8;
+        PROBE_MAX
+      }
+    }
+  }
+
+  pub fn decode(data: &[u8]) -> Option<Self> {
+    let sent_ms = u32::from_le_bytes(data.get(2..6)?.try_into().ok()?);
+    match (data[0], data.len()) {
+      (TYPE_PING, 6) => Some(Probe::Ping {
+        seq: data[1],
+        sent_ms,
+      }),
+      (TYPE_PONG, PROBE_MAX) => Some(Probe::Pong {
+        seq: data[1],
+        sent_ms,
+        heard: PacketStatus {
+          rssi_dbm: i16::from_le_bytes([data[6], data[7]]),
+          snr_db: data[8] as i8,
+        },
+      }),
+      _ => None,
+    }
+  }
+}
+
+/// Totals of a finished test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Summary {
+  pub sent: u16,
+  pub received: u16,
+  pub rtt_min_ms: u32,
+  pub rtt_avg_ms: u32,
+  pub rtt_max_ms: u32,
+}
+
+impl Summary {
+  /// Lost probes in percent.
+  pub fn loss_percent(&self) -> u32 {
+    if self.sent == 0 {
+      return 0;
+    }
+    (self.sent - self.received) as u32 * 100 / self.sent as u32
+  }
+}
+
+/// Progress of a running test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Event {
+  /// Transmit this probe to the peer.
+  Send(Probe),
+  /// A probe came back.
+  Reply {
+    seq: u8,
+    rtt_ms: u32,
+    /// How the peer heard our `PING`.
+    there: PacketStatus,
+    /// How we heard its `PONG`.
+    here: Option<PacketStatus>,
+  },
+  /// No `PONG` within [`PROBE_TIMEOUT_MS`].
+  Lost { seq: u8 },
+  /// All probes done.
+  Done(Summary),
+}
+
+/// A running `AT+PING` test.
+pub struct PingTest {
+  remaining: u16,
+  seq: u8,
+  /// Sequence and send time of the outstanding probe.
+  awaiting: Option<(u8, u32)>,
+  next_tx: u32,
+  sent: u16,
+  received: u16,
+  rtt_total: u32,
+  rtt_min: u32,
+  rtt_max: u32,
+}
+
+impl PingTest {
+  pub fn new(count: u16, now: u32) -> Self {
+    Self {
+      remaining: count,
+      seq: 0,
+      awaiting: None,
+      next_tx: now,
+      sent: 0,
+      received: 0,
+      rtt_total: 0,
+      rtt_min: u32::MAX,
+      rtt_max: 0,
+    }
+  }
+
+  /// Send the next probe, time out the outstanding one or finish.
+  pub fn poll(&mut self, now: u32) -> Option<Event> {
+    if let Some((seq, sent_ms)) = self.awaiting {
+      if now.wrapping_sub(sent_ms) < PROBE_TIMEOUT_MS {
+        return None;
+      }
+      self.awaiting = None;
+      self.next_tx = now.wrapping_add(PROBE_INTERVAL_MS);
+      return Some(Event::Lost { seq });
+    }
+    if self.remaining == 0 {
+      return Some(Event::Done(self.summary()));
+    }
+    if (now.wrapping_sub(self.next_tx) as i32) < 0 {
+      return None;
+    }
+    self.seq = self.seq.wrapping_add(1);
+    self.remaining -= 1;
+    self.sent += 1;
+    self.awaiting = Some((self.seq, now));
+    Some(Event::Send(Probe::Ping {
+      seq: self.seq,
+      sent_ms: now,
+    }))
+  }
+
+  /// Match a `PONG` against the outstanding probe.
+  pub fn handle(&mut self, probe: Probe, here: Option<PacketStatus>, now: u32) -> Option<Event> {
+    let Probe::Pong {
+      seq,
+      sent_ms,
+      heard,
+    } = probe
+    else {
+      return None;
+    };
+    if self.awaiting.map(|(awaited, _)| awaited) != Some(seq) {
+      // Late reply to a probe already counted as lost.
+      return None;
+    }
+    self.awaiting = None;
+    self.next_tx = now.wrapping_add(PROBE_INTERVAL_MS);
+
+    let rtt_ms = now.wrapping_sub(sent_ms);
+    self.received += 1;
+    self.rtt_total = self.rtt_total.saturating_add(rtt_ms);
+    self.rtt_min = self.rtt_min.min(rtt_ms);
+    self.rtt_max = self.rtt_max.max(rtt_ms);
+    Some(Event::Reply {
+      seq,
+      rtt_ms,
+      there: heard,
+      here,
+    })
+  }
+
+  fn summary(&self) -> Summary {
+    let received = self.received as u32;
+    Summary {
+      sent: self.sent,
+      received: self.received,
+      rtt_min_ms: if received == 0 { 0 } else { self.rtt_min },
+      rtt_avg_ms: self.rtt_total.checked_div(received).unwrap_or(0),
+      rtt_max_ms: self.rtt_max,
+    }
+  }
+}
@@ -0,0 +1,558 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/radio.rs - 射频参数与收发辅助
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Runtime radio parameters, the radio backend interface and blocking TX
+//! helpers.
+//!
+//! [`RadioParams`] holds the handful of LoRa parameters that may change at
+//! runtime (persisted settings, pairing, remote configuration).  Everything
+//! else about the link is fixed by the backend.  The bridge talks to the
+//! radio only through [`Radio`]; the backend is chosen at build time:
+//!
+//! | feature                      | backend  | modules                  |
+//! |------------------------------|----------|--------------------------|
+//! | `driver-sx1268-rs` (default) | `sx126x` | E22-400M30S, E22-900M30S |
+//! | `sx1276`                     | `sx1276` | RFM95, RA-02             |
+//!
+//! Exactly one backend feature is enabled; `sx1276` builds drop the default
+//! features.
+//!
+//! The `driver-native` (an SX126x driver of our own) and
+//! `driver-sx126x-rs` (the `sx126x` crate) backends are not written yet and
+//! have no features; each is a backend of its own to add to the table.
+//!
+//! [`crate::sim`] simulates radios sharing the air for testing protocol
+//! code without modules.
+//!
+//! The backend, the interrupt line and [`transmit_blocking`] exist on the
+//! MCU only (`target_os = "none"`).  The rest is plain `core` code, which
+//! `host-tests/` also builds for the host together with the simulator.
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicI8, AtomicI32, AtomicU8, Ordering};
+
+use critical_section::Mutex;
+#[cfg(feature = "driver-sx1268-rs")]
+use sx1268_rs::{
+  Sx1268Config,
+  config::{
+    CalibrationParams, FallbackMode, LoRaBandwidth, LoRaCodingRate, LoRaHeaderType,
+    LoRaModulationParams, LoRaPacketParams, LoRaSpreadingFactor, PaConfig, RampTime, RegulatorMode,
+    TcxoVoltage,
+  },
+};
+
+use crate::band;
+use crate::lora_config;
+
+#[cfg(all(feature = "driver-sx1268-rs", feature = "sx1276"))]
+compile_error!("features `driver-sx1268-rs` and `sx1276` select different radio backends");
+#[cfg(not(any(feature = "driver-sx1268-rs", feature = "sx1276")))]
+compile_error!("select a radio backend with `driver-sx1268-rs` or `sx1276`");
+
+/// The radio backend as used by the firmware.
+#[cfg(all(target_os = "none", feature = "driver-sx1268-rs"))]
+pub type BlueHighRadio = crate::sx126x::Sx126x;
+#[cfg(all(target_os = "none", feature = "sx1276"))]
+pub type BlueHighRadio = crate::sx1276::Sx1276;
+
+/// Interrupt line for RxDone / TxDone, active high: DIO1 of an SX126x,
+/// DIO0 of an SX1276.
+#[cfg(target_os = "none")]
+pub type Dio1 = crate::board::Dio1;
+
+/// A received frame could not be read (CRC error, bus error).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct RxError;
+
+/// A frame read whatever its CRC, for [`Radio::capture`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Capture {
+  pub len: usize,
+  pub crc_ok: bool,
+}
+
+/// What the bridge needs from a LoRa radio.
+///
+/// The interrupt line ([`Dio1`]) goes high when a transmission completes or
+/// a frame has arrived; every call that changes the mode clears it.
+pub trait Radio {
+  /// Reconfigure the radio for `params` and listen continuously.
+  fn apply(&mut self, params: &RadioParams) -> bool {
+    self.apply_profile(params, AirProfile::Link)
+  }
+  /// Reconfigure the radio for `params` under `profile` and listen
+  /// continuously.
+  fn apply_profile(&mut self, params: &RadioParams, profile: AirProfile) -> bool;
+  /// Listen continuously with the current configuration.
+  fn start_rx(&mut self) -> bool;
+  /// Start transmitting `frame`; TxDone raises the interrupt line.
+  fn send(&mut self, frame: &[u8]) -> bool;
+  /// Read the frame that raised the interrupt line into `buf`; `Ok(None)`
+  /// if nothing was received.  Also records the [`PacketStatus`].
+  fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, RxError>;
+  /// Like [`Radio::receive`], but a frame that failed its CRC is read too.
+  fn capture(&mut self, buf: &mut [u8]) -> Result<Option<Capture>, RxError>;
+  /// Retune to `frequency_hz` and listen, keeping every other setting.
+  /// Used for RSSI sweeps; [`Radio::apply`] restores the configuration.
+  fn listen_at(&mut self, frequency_hz: u32) -> bool;
+  /// Instantaneous RSSI on the current channel in dBm.
+  fn rssi_inst(&mut self) -> Option<i16>;
+  /// Carrier frequency offset the modem estimated for the last frame
+  /// received, in Hz; positive when the sender is above our carrier.
+  fn freq_error_hz(&mut self) -> Option<i32>;
+  /// 32 random bits sampled from the noise the radio hears; it must be
+  /// listening.  See [`crate::random`].
+  fn random_word(&mut self) -> Option<u32>;
+  /// How far the frame being received has got before RxDone; `None` when
+  /// nothing is arriving.  Latched stages are cleared by reading.
+  fn rx_progress(&mut self) -> Option<RxProgress>;
+  /// Go to standby on `frequency_hz`, ready for [`Radio::carrier`].
+  fn tune(&mut self, frequency_hz: u32) -> bool;
+  /// Switch an unmodulated carrier on or off (standby).  `false` when the
+  /// backend cannot send one.
+  fn carrier(&mut self, on: bool) -> bool;
+  /// Run channel activity detection: `Some(true)` when a LoRa preamble is
+  /// on the air.  Leaves the radio in standby.
+  fn channel_active(&mut self) -> Option<bool>;
+  /// Whether the chip stopped accepting commands since the last call.
+  fn take_hang(&mut self) -> bool;
+  /// Fault flags the chip reports, cleared by reading; `None` when it does
+  /// not answer.
+  fn device_errors(&mut self) -> Option<u16>;
+  /// Reset the chip through NRST; [`Radio::apply`] must follow.
+  fn hard_reset(&mut self);
+  /// Put the chip in its lowest-power state that keeps no frame coming in;
+  /// [`Radio::apply`] wakes it.
+  fn sleep(&mut self) -> bool;
+}
+
+/// Over-the-air conventions besides [`RadioParams`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum AirProfile {
+  /// The bridge link: private sync word, LDRO always on.
+  Link,
+  /// LoRaWAN: public sync word, LDRO only where the symbol time calls for
+  /// it, and IQ inverted to hear downlinks.
+  LoRaWan { downlink: bool },
+}
+
+impl AirProfile {
+  /// Whether low data rate optimisation is used with `params`.
+  pub fn ldro(self, params: &RadioParams) -> bool {
+    match self {
+      AirProfile::Link => true,
+      // Symbols of 16 ms and more.
+      AirProfile::LoRaWan { .. } => (1u32 << params.sf) / params.bandwidth.khz() >= 16,
+    }
+  }
+
+  pub fn invert_iq(self) -> bool {
+    self == AirProfile::LoRaWan { downlink: true }
+  }
+}
+
+/// Radio commands that failed since [`take_faults`] was last called.
+static FAULTS: AtomicU8 = AtomicU8::new(0);
+
+/// Ceiling on the chip output power; lowered by [`crate::derate`].
+static POWER_CAP_DBM: AtomicI8 = AtomicI8::new(i8::MAX);
+
+/// Carrier offset from the configured frequency; set by [`crate::afc`].
+static FREQUENCY_OFFSET_HZ: AtomicI32 = AtomicI32::new(0);
+/// Temperature trim on top of the offset; set by [`crate::trim`].
+static FREQUENCY_TRIM_HZ: AtomicI32 = AtomicI32::new(0);
+
+/// Link quality of the last received packet, sampled by the control layer.
+static PACKET_STATUS: Mutex<Cell<Option<PacketStatus>>> = Mutex::new(Cell::new(None));
+
+/// Stage of an incoming frame, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum RxProgress {
+  /// A preamble was detected.
+  Preamble,
+  /// A valid explicit header was received.
+  Header,
+}
+
+impl RxProgress {
+  pub fn name(self) -> &'static str {
+    match self {
+      RxProgress::Preamble => "PREAMBLE",
+      RxProgress::Header => "HEADER",
+    }
+  }
+}
+
+/// RSSI and SNR of a received LoRa packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct PacketStatus {
+  pub rssi_dbm: i16,
+  pub snr_db: i8,
+}
+
+/// Cap the chip output power at `cap`, or lift the cap.  Takes effect with
+/// the next [`Radio::apply`].
+pub fn set_power_cap(cap: Option<i8>) {
+  POWER_CAP_DBM.store(cap.unwrap_or(i8::MAX), Ordering::Relaxed);
+}
+
+/// Offset the carrier from the configured frequency.  Takes effect with
+/// the next [`Radio::apply`].
+pub fn set_frequency_offset(offset_hz: i32) {
+  FREQUENCY_OFFSET_HZ.store(offset_hz, Ordering::Relaxed);
+}
+
+/// Trim the carrier against crystal drift.  Takes effect with the next
+/// [`Radio::apply`].
+pub fn set_frequency_trim(trim_hz: i32) {
+  FREQUENCY_TRIM_HZ.store(trim_hz, Ordering::Relaxed);
+}
+
+/// Count a radio command that failed, for [`crate::supervisor`].
+pub fn record_fault() {
+  FAULTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Failed radio commands since the last call.
+pub fn take_faults() -> u8 {
+  FAULTS.swap(0, Ordering::Relaxed)
+}
+
+/// Store the link quality of the packet just received.
+pub fn set_packet_status(status: PacketStatus) {
+  critical_section::with(|cs| PACKET_STATUS.borrow(cs).set(Some(status)));
+}
+
+/// Store the raw `RssiPkt` / `SnrPkt` bytes of the SX126x GetPacketStatus.
+#[cfg(feature = "driver-sx1268-rs")]
+pub fn record_packet_status(rssi_raw: u8, snr_raw: u8) {
+  set_packet_status(PacketStatus {
+    rssi_dbm: -(rssi_raw as i16) / 2,
+    snr_db: (snr_raw as i8) / 4,
+  });
+}
+
+/// Link quality of the packet returned by the last `recv_lora`.
+pub fn take_packet_status() -> Option<PacketStatus> {
+  critical_section::with(|cs| PACKET_STATUS.borrow(cs).take())
+}
+
+/// Like [`take_packet_status`], but leaves the value for the next caller.
+pub fn packet_status() -> Option<PacketStatus> {
+  critical_section::with(|cs| PACKET_STATUS.borrow(cs).get())
+}
+
+/// Lowest SNR at which LoRa still demodulates with spreading factor `sf`.
+pub fn required_snr_db(sf: u8) -> i8 {
+  // -7.5 dB at SF7, 2.5 dB lower per step; rounded up.
+  -7 - (sf.saturating_sub(7) as i8 * 5) / 2
+}
+
+/// Dead time and settling time around TXEN/RXEN transitions of an E22.
+///
+/// Switching from one path to the other first drops the old enable, waits
+/// `pre_us` with both paths off so the switch never connects the PA to the
+/// LNA, then raises the new enable and waits `post_us` for the switch to
+/// settle before the chip starts to transmit or receive.  Only the SX126x
+/// backend drives the switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct SwitchGuard {
+  pub pre_us: u16,
+  pub post_us: u16,
+}
+
+impl SwitchGuard {
+  /// Longest guard accepted, either side.
+  pub const MAX_US: u16 = 1_000;
+
+  pub fn new(pre_us: u16, post_us: u16) -> Option<Self> {
+    (pre_us <= Self::MAX_US && post_us <= Self::MAX_US).then_some(Self { pre_us, post_us })
+  }
+}
+
+impl Default for SwitchGuard {
+  /// GaAs/CMOS SPDT switches as fitted to E22 modules settle within a
+  /// microsecond or two; these leave a margin without delaying TX much.
+  fn default() -> Self {
+    Self {
+      pre_us: 2,
+      post_us: 10,
+    }
+  }
+}
+
+/// Size of [`RadioParams`] when encoded.
+pub const ENCODED_LEN: usize = 8;
+
+/// LoRa bandwidths supported by the bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Bandwidth {
+  Khz125,
+  Khz250,
+  Khz500,
+}
+
+impl Bandwidth {
+  pub fn khz(self) -> u32 {
+    match self {
+      Bandwidth::Khz125 => 125,
+      Bandwidth::Khz250 => 250,
+      Bandwidth::Khz500 => 500,
+    }
+  }
+
+  pub fn from_khz(khz: u32) -> Option<Self> {
+    match khz {
+      125 => Some(Bandwidth::Khz125),
+      250 => Some(Bandwidth::Khz250),
+      500 => Some(Bandwidth::Khz500),
+      _ => None,
+    }
+  }
+
+  fn code(self) -> u8 {
+    match self {
+      Bandwidth::Khz125 => 0,
+      Bandwidth::Khz250 => 1,
+      Bandwidth::Khz500 => 2,
+    }
+  }
+
+  fn from_code(code: u8) -> Option<Self> {
+    match code {
+      0 => Some(Bandwidth::Khz125),
+      1 => Some(Bandwidth::Khz250),
+      2 => Some(Bandwidth::Khz500),
+      _ => None,
+    }
+  }
+}
+
+/// Runtime-adjustable LoRa parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct RadioParams {
+  pub frequency_hz: u32,
+  /// Radio chip output power (-9..=22 dBm, before the E22 PA; the SX1276
+  /// backend clamps it to 2..=20).
+  pub power_dbm: i8,
+  /// Spreading factor 7..=12.
+  pub sf: u8,
+  pub bandwidth: Bandwidth,
+  /// Coding rate denominator 5..=8 (CR 4/5 .. 4/8).
+  pub cr: u8,
+}
+
+impl Default for RadioParams {
+  fn default() -> Self {
+    lora_config::LINK.params
+  }
+}
+
+impl RadioParams {
+  /// Whether every field is within what the fitted E22 module supports.
+  pub fn is_valid(&self) -> bool {
+    band::FREQUENCY_HZ.contains(&self.frequency_hz)
+      && (-9..=22).contains(&self.power_dbm)
+      && (7..=12).contains(&self.sf)
+      && (5..=8).contains(&self.cr)
+  }
+
+  /// Chip output power to configure: `power_dbm` under the current cap.
+  pub fn tx_power_dbm(&self) -> i8 {
+    self.power_dbm.min(POWER_CAP_DBM.load(Ordering::Relaxed))
+  }
+
+  /// Carrier frequency to configure: `frequency_hz` with the current
+  /// offset and trim.
+  pub fn tuned_frequency_hz(&self) -> u32 {
+    let offset = FREQUENCY_OFFSET_HZ.load(Ordering::Relaxed);
+    let trim = FREQUENCY_TRIM_HZ.load(Ordering::Relaxed);
+    self.frequency_hz.saturating_add_signed(offset.saturating_add(trim))
+  }
+
+  /// Full SX126x driver configuration for these parameters.
+  #[cfg(feature = "driver-sx1268-rs")]
+  pub fn to_config(&self) -> Option<Sx1268Config> {
+    self.to_profile_config(AirProfile::Link)
+  }
+
+  /// SX126x driver configuration for these parameters under `profile`.
+  #[cfg(feature = "driver-sx1268-rs")]
+  pub fn to_profile_config(&self, profile: AirProfile) -> Option<Sx1268Config> {
+    if !self.is_valid() {
+      return None;
+    }
+    let bandwidth = match self.bandwidth {
+      Bandwidth::Khz125 => LoRaBandwidth::Bw125,
+      Bandwidth::Khz250 => LoRaBandwidth::Bw250,
+      Bandwidth::Khz500 => LoRaBandwidth::Bw500,
+    };
+    let spreading_factor = match self.sf {
+      7 => LoRaSpreadingFactor::Sf7,
+      8 => LoRaSpreadingFactor::Sf8,
+      9 => LoRaSpreadingFactor::Sf9,
+      10 => LoRaSpreadingFactor::Sf10,
+      11 => LoRaSpreadingFactor::Sf11,
+      _ => LoRaSpreadingFactor::Sf12,
+    };
+    let coding_rate = match self.cr {
+      5 => LoRaCodingRate::Cr4_5,
+      6 => LoRaCodingRate::Cr4_6,
+      7 => LoRaCodingRate::Cr4_7,
+      _ => LoRaCodingRate::Cr4_8,
+    };
+
+    let config = Sx1268Config::default()
+      .with_package_lora()
+      .with_frequency_hz(self.tuned_frequency_hz())
+      .ok()?
+      .with_pa_config(PaConfig::best_22dbm())
+      .with_tx_power(self.tx_power_dbm())
+      .with_ramp_time(RampTime::Ramp40Us)
+      .with_lora_modulation(
+        LoRaModulationParams::default()
+          .with_bandwidth(bandwidth)
+          .with_spreading_factor(spreading_factor)
+          .with_coding_rate(coding_rate)
+          // On the link kept on for every combination, as in the original
+          // fixed configuration; both ends of a link must agree on it.
+          .with_low_data_rate_optimize(profile.ldro(self)),
+      )
+      .with_lora_packet(
+        LoRaPacketParams::default()
+          .with_preamble_length(lora_config::LINK.preamble_symbols)
+          .with_header_type(LoRaHeaderType::Explicit)
+          .with_payload_length(255)
+          .with_crc_on(true)
+          .with_invert_iq(profile.invert_iq()),
+      )
+      .with_regulator_mode(RegulatorMode::DcDcLdo)
+      .with_lora_sync_word(match profile {
+        AirProfile::Link => 0x1424,
+        AirProfile::LoRaWan { .. } => 0x3444,
+      })
+      .with_tx_base_address(0x00)
+      .with_rx_base_address(0x00)
+      .with_dio2_as_rf_switch(cfg!(feature = "dio2-rf-switch"))
+      .with_fallback_mode(FallbackMode::StbyRc)
+      .with_tcxo_config(TcxoVoltage::Ctrl3v3, 320)
+      .with_calibration(CalibrationParams::ALL);
+    Some(config)
+  }
+
+  /// Encode as `[freq u32 LE][power i8][sf][bw code][cr]`.
+  pub fn encode(&self) -> [u8; ENCODED_LEN] {
+    let f = self.frequency_hz.to_le_bytes();
+    [
+      f[0],
+      f[1],
+      f[2],
+      f[3],
+      self.power_dbm as u8,
+      self.sf,
+      self.bandwidth.code(),
+      self.cr,
+    ]
+  }
+
+  pub fn decode(bytes: &[u8]) -> Option<Self> {
+    let bytes: &[u8; ENCODED_LEN] = bytes.get(..ENCODED_LEN)?.try_into().ok()?;
+    let params = Self {
+      frequency_hz: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+      power_dbm: bytes[4] as i8,
+      sf: bytes[5],
+      bandwidth: Bandwidth::from_code(bytes[6])?,
+      cr: bytes[7],
+    };
+    params.is_valid().then_some(params)
+  }
+
+  /// Time on air of a `payload_len`-byte packet in microseconds.
+  ///
+  /// Semtech AN1200.13 formula for the fixed parts of the configuration:
+  /// the link preamble, explicit header, CRC on, LDRO on.
+  pub fn airtime_us(&self, payload_len: usize) -> u32 {
+    let sf = self.sf as i32;
+    // Symbol time 2^SF / BW; exact for the supported bandwidths.
+    let symbol_us = (1u32 << self.sf) * 1000 / self.bandwidth.khz();
+    let preamble_symbols = lora_config::LINK.preamble_symbols as u32;
+    let preamble_us = (preamble_symbols * 4 + 17) * symbol_us / 4;
+    let numerator = 8 * payload_len as i32 - 4 * sf + 28 + 16;
+    let denominator = 4 * (sf - 2);
+    let blocks = ((numerator + denominator - 1) / denominator).max(0) as u32;
+    let payload_symbols = 8 + blocks * self.cr as u32;
+    preamble_us + payload_symbols * symbol_us
+  }
+}
+
+#[cfg(target_os = "none")]
+pub use blocking::{since_transmit_ms, transmit_blocking, tx_time_ms};
+
+/// Blocking transmission over the interrupt line.
+#[cfg(target_os = "none")]
+mod blocking {
+  use core::sync::atomic::{AtomicU32, Ordering};
+
+  use super::{Dio1, Radio, record_fault};
+
+  /// Busy-wait iterations before giving up on TxDone.
+  const TX_DONE_SPINS: u32 = 20_000_000;
+
+  /// When the last transmission ended, in [`crate::timer`] milliseconds.
+  static LAST_TX_MS: AtomicU32 = AtomicU32::new(0);
+
+  /// Milliseconds spent transmitting since boot, wrapping.
+  static TX_TIME_MS: AtomicU32 = AtomicU32::new(0);
+
+  /// Transmit `frame` and busy-wait for TxDone on DIO1, after the
+  /// carrier-sense backoff ([`crate::csma`]).
+  ///
+  /// The radio is left in standby; callers re-enter RX when done.  `false`
+  /// when the frame was refused or TxDone never came; the frame is then
+  /// not known to have gone out.
+  pub fn transmit_blocking(lora: &mut impl Radio, dio1: &Dio1, frame: &[u8]) -> bool {
+    crate::csma::wait(lora);
+    let started_ms = crate::timer::now_ms();
+    if !lora.send(frame) {
+      return false;
+    }
+    crate::led::set_transmitting(true);
+    let mut spins = 0u32;
+    let mut done = true;
+    while !dio1.is_high() {
+      spins = spins.wrapping_add(1);
+      if spins > TX_DONE_SPINS {
+        defmt::warn!("[radio] TxDone wait timed out");
+        record_fault();
+        done = false;
+        break;
+      }
+    }
+    crate::led::set_transmitting(false);
+    let ended_ms = crate::timer::now_ms();
+    LAST_TX_MS.store(ended_ms, Ordering::Relaxed);
+    TX_TIME_MS.fetch_add(ended_ms.wrapping_sub(started_ms), Ordering::Relaxed);
+    done
+  }
+
+  /// Milliseconds spent in [`transmit_blocking`] since boot, wrapping.
+  pub fn tx_time_ms() -> u32 {
+    TX_TIME_MS.load(Ordering::Relaxed)
+  }
+
+  /// Milliseconds since the last transmission ended.
+  pub fn since_transmit_ms() -> u32 {
+    crate::timer::elapsed_ms(LAST_TX_MS.load(Ordering::Relaxed))
+  }
+}
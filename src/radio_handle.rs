@@ -0,0 +1,102 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/radio_handle.rs - 中断共享的射频句柄
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Radio shared between the main loop and interrupt handlers.
+//!
+//! [`app::run`](crate::app::run) moves the radio into a
+//! `static Shared<BlueHighRadio>` once the board is up and lends it to
+//! each main loop pass, so an interrupt handler can lock it while the
+//! loop sleeps.  Every user goes through the handle:
+//!
+//! - [`Shared::lock`] runs a closure with interrupts disabled.  It is the
+//!   only way an interrupt handler reaches the radio, and it must stay
+//!   short: only the calls marked ISR-safe below.  Even those keep
+//!   interrupts off for up to
+//!   [`BUSY_TIMEOUT_US`](crate::lora::BUSY_TIMEOUT_US) (20 ms) per SPI
+//!   transaction when the chip stays busy.
+//! - [`Shared::lend`] takes the radio out of the handle and runs a closure
+//!   with interrupts enabled, for the long calls.  Meanwhile a handler's
+//!   `lock` returns `None`: it finds the radio gone and must leave its
+//!   work (a flag, a queued frame) for the main loop instead of waiting.
+//! - Neither nests: a `lock` or `lend` inside another returns `None`
+//!   instead of panicking on the `RefCell`.
+//!
+//! SPI1 and the SX126x control are reached only through the radio, so
+//! holding the radio also holds them.  Both may move between contexts
+//! with it: SPI1 is a critical-section device (see [`crate::spi_bus`])
+//! and the control sits in a [`ControlCell`](crate::lora::ControlCell).
+//!
+//! Audit of [`Radio`](crate::radio::Radio) for interrupt context; every SPI
+//! transaction waits at most [`BUSY_TIMEOUT_US`](crate::lora::BUSY_TIMEOUT_US)
+//! for BUSY, and a full 255-byte buffer read takes about 2 ms at 1 MHz:
+//!
+//! | call                                           | context                 |
+//! |------------------------------------------------|-------------------------|
+//! | `receive`, `capture`, `rx_progress`            | ISR-safe                |
+//! | `start_rx`, `send`, `tune`, `listen_at`        | ISR-safe                |
+//! | `carrier`, `rssi_inst`, `freq_error_hz`        | ISR-safe                |
+//! | `random_word`, `take_hang`, `device_errors`    | ISR-safe                |
+//! | `sleep`                                        | ISR-safe                |
+//! | `apply`, `apply_profile`                       | `lend`: calibration     |
+//! | `hard_reset`                                   | `lend`: ~20 ms of reset |
+//! | `channel_active`                               | `lend`: up to 100 ms    |
+//! | [`transmit_blocking`], [`crate::csma::wait`]   | `lend`: whole airtime   |
+//!
+//! [`transmit_blocking`]: crate::radio::transmit_blocking
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::{self, Mutex};
+
+/// Radio behind a critical section, see the module docs.
+pub struct Shared<R>(Mutex<RefCell<Option<R>>>);
+
+impl<R> Shared<R> {
+  /// An empty handle; [`Shared::lock`] returns `None` until [`Shared::init`].
+  pub const fn new() -> Self {
+    Self(Mutex::new(RefCell::new(None)))
+  }
+
+  /// Move `radio` into the handle, dropping any radio held before.
+  pub fn init(&self, radio: R) {
+    interrupt::free(|cs| self.0.borrow(cs).replace(Some(radio)));
+  }
+
+  /// Run `f` on the radio with interrupts disabled.  `None` when the
+  /// handle is empty, lent out or already locked; an interrupt handler
+  /// that gets `None` defers its work to the main loop.
+  pub fn lock<T>(&self, f: impl FnOnce(&mut R) -> T) -> Option<T> {
+    interrupt::free(|cs| {
+      let mut radio = self.0.borrow(cs).try_borrow_mut().ok()?;
+      radio.as_mut().map(f)
+    })
+  }
+
+  /// Take the radio out, run `f` on it with interrupts enabled and put it
+  /// back.  `None` under the same conditions as [`Shared::lock`].  The
+  /// radio stays out for all of `f`: each SPI transaction in it may wait
+  /// [`BUSY_TIMEOUT_US`](crate::lora::BUSY_TIMEOUT_US) for BUSY, and a
+  /// blocking transmission lasts its whole airtime.
+  pub fn lend<T>(&self, f: impl FnOnce(&mut R) -> T) -> Option<T> {
+    let mut radio = interrupt::free(|cs| self.0.borrow(cs).try_borrow_mut().ok()?.take())?;
+    let result = f(&mut radio);
+    interrupt::free(|cs| self.0.borrow(cs).replace(Some(radio)));
+    Some(result)
+  }
+}
+
+impl<R> Default for Shared<R> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
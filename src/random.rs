@@ -0,0 +1,109 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/random.rs - 基于射频噪声的随机数
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Random numbers from radio noise.
+//!
+//! The STM32F103 has no RNG, but a listening radio samples thermal noise
+//! (see [`Radio::random_word`]).  [`harvest`] stirs [`HARVEST_WORDS`] such
+//! words into a pool, together with the cycle counter, and [`random_u32`]
+//! draws from it with SplitMix64, so callers get fresh numbers without
+//! touching the radio.  The pool is stirred at boot and again before
+//! anything that sends a nonce (pairing, a LoRaWAN join).
+//!
+//! Used for the relay backoff jitter, pairing nonces and the first
+//! DevNonce.  Until the first harvest the pool only holds the chip UID, so
+//! numbers differ between devices but not between boots.
+//!
+//! The pool keeps 64 bits of state, too few for a key.  [`noise`] reads
+//! words straight from the radio instead, for the pairing key exchange.
+
+use core::cell::Cell;
+
+use cortex_m::interrupt::{self, Mutex};
+
+use crate::device_id;
+use crate::radio::Radio;
+use crate::timer;
+
+/// Radio words stirred in per harvest.
+pub const HARVEST_WORDS: usize = 8;
+
+/// SplitMix64 increment.
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+static POOL: Mutex<Cell<u64>> = Mutex::new(Cell::new(0));
+
+/// Stir noise from the radio into the pool.  The radio must be listening.
+/// Returns how many words it delivered.
+pub fn harvest(radio: &mut impl Radio) -> usize {
+  let mut words = 0;
+  for _ in 0..HARVEST_WORDS {
+    if let Some(word) = radio.random_word() {
+      stir(word);
+      words += 1;
+    }
+  }
+  stir(timer::now_cycles());
+  if words == 0 {
+    defmt::warn!("[random] no noise from the radio");
+  }
+  words
+}
+
+/// `N` words of radio noise, for key material.  The radio must be
+/// listening; words it does not deliver come from the pool.
+pub fn noise<const N: usize>(radio: &mut impl Radio) -> [u32; N] {
+  let mut missing = 0;
+  let words = core::array::from_fn(|_| {
+    radio.random_word().unwrap_or_else(|| {
+      missing += 1;
+      random_u32()
+    })
+  });
+  if missing > 0 {
+    defmt::warn!("[random] {} noise words from the pool", missing);
+  }
+  words
+}
+
+/// Mix `entropy` into the pool.
+pub fn stir(entropy: u32) {
+  interrupt::free(|cs| {
+    let pool = POOL.borrow(cs);
+    let state = pool.get() ^ (((entropy as u64) << 32) | entropy.rotate_left(16) as u64);
+    pool.set(mix(state.wrapping_add(GOLDEN_GAMMA)));
+  });
+}
+
+/// Next random number.
+pub fn random_u32() -> u32 {
+  let output = interrupt::free(|cs| {
+    let pool = POOL.borrow(cs);
+    if pool.get() == 0 {
+      // Never stirred: start from the chip UID.
+      let [a, b, c] = device_id::uid();
+      pool.set(mix((((a as u64) << 32) | b as u64) ^ c as u64));
+    }
+    let state = pool.get().wrapping_add(GOLDEN_GAMMA);
+    pool.set(state);
+    mix(state)
+  });
+  (output >> 32) as u32
+}
+
+/// SplitMix64 output function.
+fn mix(mut z: u64) -> u64 {
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+  z ^ (z >> 31)
+}
@@ -0,0 +1,289 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/relay.rs - 存储转发中继与受控泛洪路由
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Store-and-forward repeater and managed-flood routing.
+//!
+//! With `AT+RELAY=1` a bridge retransmits addressed frames meant for other
+//! nodes, broadcasts included; with every bridge relaying, more than two of
+//! them form a small flooded network.  A frame is relayed at most once per
+//! node and only while its hop count is below the hop limit (TTL) its
+//! sender set with `AT+TTL`; the relay raises the count and leaves
+//! everything else, MIC included, untouched.  Unicast frames stop at their
+//! destination, which never relays them.
+//!
+//! The flood is managed so it does not grow with the number of nodes:
+//!
+//! * Before relaying a node waits a backoff that is shorter the weaker it
+//!   heard the frame, so the nodes furthest from the sender, which extend
+//!   coverage most, go first.  A random part breaks ties.
+//! * A node that hears another node relay the frame it is holding drops
+//!   its own copy; that neighbourhood is already covered.
+//! * Channel activity detection runs before each transmission; while the
+//!   channel is busy the node backs off again, up to [`MAX_ATTEMPTS`]
+//!   times.
+//!
+//! Every bridge, relaying or not, remembers the `(src, seq)` of recent
+//! frames in [`Seen`] and drops later copies, and keeps a [`Routes`] table
+//! of the nodes it has heard, with their distance in hops.  Relaying needs
+//! the address header.  With link security a frame is only remembered or
+//! relayed once its MIC checked out, so every node on the flood shares the
+//! link key.
+
+use heapless::{Deque, Vec};
+
+use crate::link::{HOPS_OFFSET, Header};
+use crate::packetizer::MAX_PAYLOAD;
+use crate::random;
+
+/// Hop limit of transmitted frames unless changed with `AT+TTL`.
+pub const DEFAULT_HOP_LIMIT: u8 = 3;
+/// Largest hop limit the header can carry.
+pub const MAX_HOP_LIMIT: u8 = 15;
+/// CAD attempts before a relayed frame is dropped.
+pub const MAX_ATTEMPTS: u8 = 5;
+const BACKOFF_SLOT_MS: u32 = 20;
+/// Random slots added to every backoff.
+const BACKOFF_RANDOM_SLOTS: u32 = 4;
+/// SNR range mapped onto the backoff: at or below the floor a node relays
+/// first, each dB above it adds a slot.
+const BACKOFF_SNR_FLOOR_DB: i8 = -20;
+const BACKOFF_SNR_CEILING_DB: i8 = 10;
+/// Frames remembered for duplicate suppression.
+const SEEN_SLOTS: usize = 16;
+/// How long a frame counts as seen; sequence numbers repeat after 256
+/// frames and restart at boot.
+const SEEN_MS: u32 = 30_000;
+/// Nodes kept in the route table.
+pub const ROUTE_SLOTS: usize = 16;
+
+/// Recently heard frames.
+pub struct Seen {
+  frames: Deque<(u16, u8, u32), SEEN_SLOTS>,
+}
+
+impl Seen {
+  pub fn new() -> Self {
+    Self {
+      frames: Deque::new(),
+    }
+  }
+
+  /// Record the frame and report whether it is the first copy heard.
+  pub fn first(&mut self, header: &Header, now: u32) -> bool {
+    let duplicate = self.frames.iter().any(|&(src, seq, at)| {
+      src == header.src && seq == header.seq && now.wrapping_sub(at) < SEEN_MS
+    });
+    if !duplicate {
+      if self.frames.is_full() {
+        self.frames.pop_front();
+      }
+      let _ = self.frames.push_back((header.src, header.seq, now));
+    }
+    !duplicate
+  }
+}
+
+impl Default for Seen {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// A node heard on the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Route {
+  pub address: u16,
+  /// Relays between that node and this one on the last frame heard.
+  pub hops: u8,
+  /// RSSI of the last frame, as received from the last relay.
+  pub rssi_dbm: i16,
+  pub last_ms: u32,
+}
+
+/// Nodes heard recently, the least recently heard replaced first.
+pub struct Routes {
+  routes: Vec<Route, ROUTE_SLOTS>,
+}
+
+impl Routes {
+  pub fn new() -> Self {
+    Self { routes: Vec::new() }
+  }
+
+  /// Update the entry of the sender of a first-heard frame.
+  pub fn record(&mut self, header: &Header, rssi_dbm: i16, now: u32) {
+    let route = Route {
+      address: header.src,
+      hops: header.hops,
+      rssi_dbm,
+      last_ms: now,
+    };
+    if let Some(entry) = self.routes.iter_mut().find(|entry| entry.address == header.src) {
+      *entry = route;
+    } else if let Err(route) = self.routes.push(route)
+      && let Some(oldest) = self
+        .routes
+        .iter_mut()
+        .max_by_key(|entry| now.wrapping_sub(entry.last_ms))
+    {
+      *oldest = route;
+    }
+  }
+
+  pub fn get(&self, index: usize) -> Option<&Route> {
+    self.routes.get(index)
+  }
+}
+
+impl Default for Routes {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// What became of a frame offered to the repeater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Offer {
+  /// Not to be relayed by this node.
+  Ignored,
+  /// Held until its backoff expires.
+  Held,
+  /// Dropped because another frame is still held.
+  Dropped,
+}
+
+/// What the repeater wants to do on this pass.
+pub enum Action<'a> {
+  Idle,
+  Transmit(&'a [u8]),
+  /// The held frame was dropped because the channel stayed busy.
+  Dropped,
+}
+
+pub struct Repeater {
+  enabled: bool,
+  local: u16,
+  frame: Vec<u8, MAX_PAYLOAD>,
+  /// Identity of the held frame, when it may go out and how many CADs it
+  /// has had.
+  pending: Option<Pending>,
+}
+
+#[derive(Clone, Copy)]
+struct Pending {
+  src: u16,
+  seq: u8,
+  due: u32,
+  attempts: u8,
+}
+
+impl Repeater {
+  pub fn new(enabled: bool, local: u16) -> Self {
+    Self {
+      enabled,
+      local,
+      frame: Vec::new(),
+      pending: None,
+    }
+  }
+
+  pub fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  pub fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+    if !enabled {
+      self.pending = None;
+    }
+  }
+
+  pub fn set_local(&mut self, local: u16) {
+    self.local = local;
+  }
+
+  /// Offer a first-heard addressed frame, received with `snr_db`.
+  pub fn offer(&mut self, frame: &[u8], header: &Header, snr_db: i8, now: u32) -> Offer {
+    if !self.enabled
+      || header.dst == self.local
+      || header.src == self.local
+      || header.hops >= header.hop_limit
+    {
+      return Offer::Ignored;
+    }
+    if self.pending.is_some() {
+      return Offer::Dropped;
+    }
+    self.frame.clear();
+    let _ = self.frame.extend_from_slice(frame);
+    self.frame[HOPS_OFFSET] = Header::hop_byte(header.hops + 1, header.hop_limit);
+    let snr = snr_db.clamp(BACKOFF_SNR_FLOOR_DB, BACKOFF_SNR_CEILING_DB);
+    let snr_slots = (snr - BACKOFF_SNR_FLOOR_DB) as u32;
+    self.pending = Some(Pending {
+      src: header.src,
+      seq: header.seq,
+      due: now.wrapping_add(snr_slots * BACKOFF_SLOT_MS + self.random_backoff_ms()),
+      attempts: 0,
+    });
+    defmt::info!("[relay] holding frame {} from 0x{:04X}", header.seq, header.src);
+    Offer::Held
+  }
+
+  /// Length of the held frame, if any.
+  pub fn held(&self) -> Option<usize> {
+    self.pending.map(|_| self.frame.len())
+  }
+
+  /// A copy of a frame already seen was heard.  Returns `true` when it was
+  /// the held frame, which another node has now relayed, so it is dropped.
+  pub fn heard_again(&mut self, header: &Header) -> bool {
+    match self.pending {
+      Some(pending) if pending.src == header.src && pending.seq == header.seq => {
+        self.pending = None;
+        true
+      }
+      _ => false,
+    }
+  }
+
+  /// Send the held frame once its backoff expired and the channel is
+  /// clear.  `channel_active` runs a CAD; when it fails the channel is
+  /// taken as clear.
+  pub fn poll(&mut self, now: u32, channel_active: impl FnOnce() -> Option<bool>) -> Action<'_> {
+    let Some(pending) = self.pending else {
+      return Action::Idle;
+    };
+    if (now.wrapping_sub(pending.due) as i32) < 0 {
+      return Action::Idle;
+    }
+    if channel_active() == Some(true) {
+      if pending.attempts + 1 >= MAX_ATTEMPTS {
+        self.pending = None;
+        return Action::Dropped;
+      }
+      self.pending = Some(Pending {
+        due: now.wrapping_add(self.random_backoff_ms()),
+        attempts: pending.attempts + 1,
+        ..pending
+      });
+      return Action::Idle;
+    }
+    self.pending = None;
+    Action::Transmit(&self.frame)
+  }
+
+  /// Random wait of one to [`BACKOFF_RANDOM_SLOTS`] slots.
+  fn random_backoff_ms(&self) -> u32 {
+    (random::random_u32() % BACKOFF_RANDOM_SLOTS + 1) * BACKOFF_SLOT_MS
+  }
+}
@@ -0,0 +1,304 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/remote.rs - 对端远程射频配置
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Changing the radio parameters of both ends of a link.
+//!
+//! The messages travel in [`Kind::Control`](crate::link::Kind::Control)
+//! frames to the paired peer:
+//!
+//! ```text
+//! [type u8][seq u8][radio params 8]?
+//! ```
+//!
+//! The handshake, with A the initiator and B its peer:
+//!
+//! 1. A sends `PROPOSE(seq, params)` on the old parameters, retrying until
+//!    B answers or [`PROPOSE_TRIES`] is exhausted (nothing changes).
+//! 2. B answers `ACCEPT(seq)` on the old parameters, then switches.
+//! 3. A switches on `ACCEPT` and sends `CONFIRM(seq)` on the new parameters
+//!    until B answers `CONFIRMED(seq)`.  Both then persist the change.
+//! 4. Either side that has switched but does not complete step 3 within
+//!    [`VERIFY_MS`] switches back, so a lost message never strands a node
+//!    on parameters its peer is not using.
+//!
+//! A node that is itself proposing ignores incoming proposals.
+
+use crate::radio::{self, RadioParams};
+
+/// Attempts at delivering `PROPOSE`.
+pub const PROPOSE_TRIES: u8 = 5;
+/// Interval between retransmissions; long enough for one exchange at SF12.
+pub const RETRY_MS: u32 = 2_000;
+/// Time allowed to confirm the new parameters before reverting.
+pub const VERIFY_MS: u32 = 10_000;
+
+const TYPE_PROPOSE: u8 = 1;
+const TYPE_ACCEPT: u8 = 2;
+const TYPE_CONFIRM: u8 = 3;
+const TYPE_CONFIRMED: u8 = 4;
+
+/// Largest encoded message.
+pub const MESSAGE_MAX: usize = 2 + radio::ENCODED_LEN;
+
+/// A control message between bridges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Message {
+  Propose { seq: u8, params: RadioParams },
+  Accept { seq: u8 },
+  Confirm { seq: u8 },
+  Confirmed { seq: u8 },
+}
+
+impl Message {
+  /// Encode into `out`, returning the length used.
+  pub fn encode(&self, out: &mut [u8; MESSAGE_MAX]) -> usize {
+    let (kind, seq) = match *self {
+      Message::Propose { seq, params } => {
+        out[2..].copy_from_slice(&params.encode());
+        (TYPE_PROPOSE, seq)
+      }
+      Message::Accept { seq } => (TYPE_ACCEPT, seq),
+      Message::Confirm { seq } => (TYPE_CONFIRM, seq),
+      Message::Confirmed { seq } => (TYPE_CONFIRMED, seq),
+    };
+    out[0] = kind;
+    out[1] = seq;
+    match self {
+      Message::Propose { .. } => MESSAGE_MAX,
+      _ => 2,
+    }
+  }
+
+  pub fn decode(data: &[u8]) -> Option<Self> {
+    let [kind, seq, rest @ ..] = data else {
+      return None;
+    };
+    let seq = *seq;
+    match *kind {
+      TYPE_PROPOSE => Some(Message::Propose {
+        seq,
+        params: RadioParams::decode(rest)?,
+      }),
+      TYPE_ACCEPT => Some(Message::Accept { seq }),
+      TYPE_CONFIRM => Some(Message::Confirm { seq }),
+      TYPE_CONFIRMED => Some(Message::Confirmed { seq }),
+      _ => None,
+    }
+  }
+}
+
+/// A radio change requested by the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Change {
+  /// Retune to these parameters, tentatively.
+  Switch(RadioParams),
+  /// The tentative parameters are confirmed; persist them.
+  Commit(RadioParams),
+  /// Confirmation failed; retune back to these parameters.
+  Revert(RadioParams),
+  /// The peer never accepted; nothing changed.
+  Abort,
+}
+
+/// What the caller must do, in order: send `send` on the current
+/// parameters, then apply `change`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Reaction {
+  pub send: Option<Message>,
+  pub change: Option<Change>,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+  Idle,
+  /// Initiator waiting for `ACCEPT`.
+  Proposing {
+    seq: u8,
+    params: RadioParams,
+    tries: u8,
+    next_tx: u32,
+  },
+  /// Initiator on the new parameters waiting for `CONFIRMED`.
+  Verifying {
+    seq: u8,
+    params: RadioParams,
+    old: RadioParams,
+    deadline: u32,
+    next_tx: u32,
+  },
+  /// Responder on the new parameters waiting for `CONFIRM`.
+  Switched {
+    seq: u8,
+    params: RadioParams,
+    old: RadioParams,
+    deadline: u32,
+  },
+}
+
+pub struct RemoteConfig {
+  state: State,
+  next_seq: u8,
+  /// Sequence of the last change this node committed as responder, so a
+  /// repeated `CONFIRM` is answered again.
+  committed: Option<u8>,
+}
+
+impl Default for RemoteConfig {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl RemoteConfig {
+  pub const fn new() -> Self {
+    Self {
+      state: State::Idle,
+      next_seq: 0,
+      committed: None,
+    }
+  }
+
+  /// Whether a handshake is in progress.
+  pub fn busy(&self) -> bool {
+    !matches!(self.state, State::Idle)
+  }
+
+  /// Begin changing both ends to `params`.  Returns `false` if a handshake
+  /// is already running.
+  pub fn start(&mut self, params: RadioParams, now: u32) -> bool {
+    if self.busy() {
+      return false;
+    }
+    let seq = self.next_seq;
+    self.next_seq = self.next_seq.wrapping_add(1);
+    self.state = State::Proposing {
+      seq,
+      params,
+      tries: 0,
+      next_tx: now,
+    };
+    true
+  }
+
+  /// Drive timers and retransmissions.
+  pub fn poll(&mut self, now: u32) -> Reaction {
+    let mut reaction = Reaction::default();
+    match self.state {
+      State::Idle => {}
+      State::Proposing {
+        seq,
+        params,
+        tries,
+        next_tx,
+      } if due(now, next_tx) => {
+        if tries >= PROPOSE_TRIES {
+          defmt::warn!("[remote] peer did not accept change {}", seq);
+          self.state = State::Idle;
+          reaction.change = Some(Change::Abort);
+        } else {
+          self.state = State::Proposing {
+            seq,
+            params,
+            tries: tries + 1,
+            next_tx: now.wrapping_add(RETRY_MS),
+          };
+          reaction.send = Some(Message::Propose { seq, params });
+        }
+      }
+      State::Proposing { .. } => {}
+      State::Verifying { old, deadline, .. } | State::Switched { old, deadline, .. }
+        if due(now, deadline) =>
+      {
+        defmt::warn!("[remote] change not confirmed, reverting");
+        self.state = State::Idle;
+        reaction.change = Some(Change::Revert(old));
+      }
+      State::Verifying {
+        seq,
+        params,
+        old,
+        deadline,
+        next_tx,
+      } if due(now, next_tx) => {
+        self.state = State::Verifying {
+          seq,
+          params,
+          old,
+          deadline,
+          next_tx: now.wrapping_add(RETRY_MS),
+        };
+        reaction.send = Some(Message::Confirm { seq });
+      }
+      State::Verifying { .. } | State::Switched { .. } => {}
+    }
+    reaction
+  }
+
+  /// Handle a control message from the peer.  `current` is the radio
+  /// configuration in use, restored if the change is not confirmed.
+  pub fn handle(&mut self, message: Message, current: RadioParams, now: u32) -> Reaction {
+    let mut reaction = Reaction::default();
+    match (self.state, message) {
+      (State::Idle, Message::Propose { seq, params }) => {
+        defmt::info!("[remote] peer proposes {}", params);
+        self.state = State::Switched {
+          seq,
+          params,
+          old: current,
+          deadline: now.wrapping_add(VERIFY_MS),
+        };
+        reaction.send = Some(Message::Accept { seq });
+        reaction.change = Some(Change::Switch(params));
+      }
+      (State::Proposing { seq, params, .. }, Message::Accept { seq: accepted })
+        if accepted == seq =>
+      {
+        self.state = State::Verifying {
+          seq,
+          params,
+          old: current,
+          deadline: now.wrapping_add(VERIFY_MS),
+          next_tx: now,
+        };
+        reaction.change = Some(Change::Switch(params));
+      }
+      (State::Verifying { seq, params, .. }, Message::Confirmed { seq: confirmed })
+        if confirmed == seq =>
+      {
+        self.state = State::Idle;
+        reaction.change = Some(Change::Commit(params));
+      }
+      (State::Switched { seq, params, .. }, Message::Confirm { seq: confirm })
+        if confirm == seq =>
+      {
+        self.state = State::Idle;
+        self.committed = Some(seq);
+        reaction.send = Some(Message::Confirmed { seq });
+        reaction.change = Some(Change::Commit(params));
+      }
+      (State::Idle, Message::Confirm { seq }) if self.committed == Some(seq) => {
+        // Our CONFIRMED was lost; say it again.
+        reaction.send = Some(Message::Confirmed { seq });
+      }
+      (_, message) => {
+        defmt::info!("[remote] ignoring {}", message);
+      }
+    }
+    reaction
+  }
+}
+
+/// Whether `deadline` has been reached at `now`, allowing for wrap-around.
+fn due(now: u32, deadline: u32) -> bool {
+  now.wrapping_sub(deadline) as i32 >= 0
+}
@@ -0,0 +1,299 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/rtc.rs - DS3231 实时时钟
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Battery-backed wall clock.
+//!
+//! A DS3231 (0x68) may sit on I2C2 next to the OLED panel and the sensors;
+//! it is looked for at boot.  It keeps UTC on its coin cell across power
+//! cuts, so a bridge without GPS or time frames still has network time
+//! (see [`crate::timesync`]): sniffer and packet-forwarder records, SD
+//! card logs and beacons with the `T` field then carry real timestamps.
+//!
+//! The DS3231 counts whole seconds.  To read it to the millisecond the
+//! bridge reads it every [`WATCH_MS`] until it ticks, once every
+//! [`CHECK_INTERVAL_MS`].  Without GPS or radio time each tick sets the
+//! network clock.  With either, the tick is compared with it instead: the
+//! offset is reported, and the drift in ppm from how the offset changed
+//! since the first comparison, once [`DRIFT_MIN_MS`] have passed.  An RTC
+//! off by more than [`STEP_MS`] is set from network time on the next whole
+//! second, as writing the seconds register restarts its countdown.
+//!
+//! `AT+TIME=<YYYY-MM-DDTHH:MM:SS>` sets the RTC from the host; GPS or radio
+//! time overrides it.  `AT+RTC?` answers `+RTC:<time>,<offset ms>,<drift
+//! ppm>`, offset and drift `-` until compared, `+RTC:UNSET` while the RTC
+//! has not been set since its oscillator stopped, or `+RTC:NONE`.
+//!
+//! Headless builds (`no-display`) leave I2C2 unconfigured and have no RTC.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::i2c_bus;
+use crate::timesync::{self, Clock, Source};
+
+/// Interval between reads while waiting for a tick.
+pub const WATCH_MS: u32 = 10;
+/// Interval between ticks taken; the network clock must not run out.
+pub const CHECK_INTERVAL_MS: u32 = 30_000;
+/// Largest offset to network time left alone.
+pub const STEP_MS: i64 = 500;
+/// Shortest span to measure the drift over; a tick is only good to
+/// [`WATCH_MS`].
+pub const DRIFT_MIN_MS: u64 = 600_000;
+/// Longest `AT+RTC?` answer.
+pub const LINE_MAX: usize = 64;
+
+const _: () = assert!(CHECK_INTERVAL_MS < timesync::HOLDOVER_MS);
+
+/// The RTC, if found, and what is known of its error.
+pub struct Rtc {
+  bus: Option<i2c_bus::Shared>,
+  /// The RTC kept time since it was last set.
+  valid: bool,
+  /// When the RTC was last read and what it read, while waiting for a tick.
+  watch: Option<(u32, u64)>,
+  /// Local time and RTC time of the last tick.
+  tick: Option<(u32, u64)>,
+  /// Network time and offset of the first comparison since the RTC was set.
+  reference: Option<(u64, i64)>,
+  offset_ms: Option<i64>,
+  drift_ppm: Option<i32>,
+  /// The RTC waits to be set from network time.
+  stepping: bool,
+}
+
+impl Rtc {
+  /// Look for a DS3231 on `bus`, if there is one.
+  pub fn new(bus: Option<i2c_bus::Shared>) -> Self {
+    let mut rtc = Self {
+      bus: None,
+      valid: false,
+      watch: None,
+      tick: None,
+      reference: None,
+      offset_ms: None,
+      drift_ppm: None,
+      stepping: false,
+    };
+    if let Some(mut bus) = bus
+      && let Some(valid) = ds3231::probe(&mut bus)
+    {
+      defmt::info!("[rtc] DS3231 found, time valid: {}", valid);
+      rtc.bus = Some(bus);
+      rtc.valid = valid;
+    }
+    rtc
+  }
+
+  /// Whether there is an RTC.
+  pub fn present(&self) -> bool {
+    self.bus.is_some()
+  }
+
+  /// Set the RTC to `network_ms`, to the second.
+  pub fn set(&mut self, network_ms: u64) -> bool {
+    let Some(mut bus) = self.bus else {
+      return false;
+    };
+    if !ds3231::write(&mut bus, network_ms) {
+      return false;
+    }
+    self.valid = true;
+    self.watch = None;
+    self.tick = None;
+    self.reference = None;
+    self.offset_ms = None;
+    self.drift_ppm = None;
+    self.stepping = false;
+    true
+  }
+
+  /// Take a tick when one is due and set or check `clock` with it.
+  pub fn poll(&mut self, clock: &mut Clock, now: u32) {
+    let Some(mut bus) = self.bus else {
+      return;
+    };
+    if self.stepping {
+      // Write on a whole second of network time.
+      match clock.now(now) {
+        _ if Self::free_running(clock, now) => self.stepping = false,
+        Some(network_ms) if network_ms % 1_000 < WATCH_MS as u64 => {
+          defmt::info!("[rtc] set from network time");
+          self.set(network_ms);
+        }
+        _ => {}
+      }
+      return;
+    }
+    if self
+      .tick
+      .is_some_and(|(local_ms, _)| now.wrapping_sub(local_ms) < CHECK_INTERVAL_MS)
+    {
+      return;
+    }
+    match self.watch {
+      Some((read_ms, _)) if now.wrapping_sub(read_ms) < WATCH_MS => {}
+      watch => {
+        let Some(rtc_ms) = ds3231::read(&mut bus) else {
+          return;
+        };
+        self.watch = Some((now, rtc_ms));
+        if watch.is_some_and(|(_, last_ms)| last_ms != rtc_ms) {
+          self.watch = None;
+          self.tick = Some((now, rtc_ms));
+          self.on_tick(clock, rtc_ms, now);
+        }
+      }
+    }
+  }
+
+  /// Whether `clock` has nothing better than the RTC.
+  fn free_running(clock: &Clock, now: u32) -> bool {
+    matches!(clock.source(now), Some(Source::Rtc) | None)
+  }
+
+  fn on_tick(&mut self, clock: &mut Clock, rtc_ms: u64, now: u32) {
+    let network_ms = match clock.now(now) {
+      Some(network_ms) if !Self::free_running(clock, now) => network_ms,
+      _ => {
+        if self.valid {
+          clock.on_rtc(rtc_ms, now);
+        }
+        return;
+      }
+    };
+    let offset_ms = rtc_ms as i64 - network_ms as i64;
+    self.offset_ms = Some(offset_ms);
+    if !self.valid || offset_ms.abs() > STEP_MS {
+      defmt::warn!("[rtc] off by {} ms", offset_ms);
+      self.stepping = true;
+      return;
+    }
+    match self.reference {
+      None => self.reference = Some((network_ms, offset_ms)),
+      Some((since_ms, since_offset_ms)) => {
+        let span_ms = network_ms.saturating_sub(since_ms);
+        if span_ms >= DRIFT_MIN_MS {
+          let ppm = (offset_ms - since_offset_ms) * 1_000_000 / span_ms as i64;
+          self.drift_ppm = Some(ppm as i32);
+        }
+      }
+    }
+  }
+
+  /// `AT+RTC?` answer.
+  pub fn report(&self, now: u32) -> String<LINE_MAX> {
+    let mut line = String::new();
+    let Some((local_ms, rtc_ms)) = self.tick.filter(|_| self.valid) else {
+      let _ = line.push_str(if self.present() {
+        "+RTC:UNSET\r\n"
+      } else {
+        "+RTC:NONE\r\n"
+      });
+      return line;
+    };
+    let _ = line.push_str("+RTC:");
+    let _ = timesync::write_iso(&mut line, rtc_ms + now.wrapping_sub(local_ms) as u64);
+    let _ = match self.offset_ms {
+      Some(offset_ms) => write!(line, ",{}", offset_ms),
+      None => line.push_str(",-").map_err(|_| core::fmt::Error),
+    };
+    let _ = match self.drift_ppm {
+      Some(ppm) => write!(line, ",{}\r\n", ppm),
+      None => line.push_str(",-\r\n").map_err(|_| core::fmt::Error),
+    };
+    line
+  }
+}
+
+/// Maxim DS3231.
+mod ds3231 {
+  use embedded_hal::i2c::I2c;
+
+  use crate::{gps, timesync};
+
+  const ADDRESS: u8 = 0x68;
+  const REG_SECONDS: u8 = 0x00;
+  const REG_STATUS: u8 = 0x0F;
+  /// The oscillator stopped; the time is not to be trusted.
+  const STATUS_OSF: u8 = 0x80;
+  const HOURS_12H: u8 = 0x40;
+  const HOURS_PM: u8 = 0x20;
+  const MONTH_CENTURY: u8 = 0x80;
+
+  /// Whether the RTC keeps valid time, if a DS3231 answers.
+  pub fn probe(bus: &mut impl I2c) -> Option<bool> {
+    let mut status = [0u8];
+    bus.write_read(ADDRESS, &[REG_STATUS], &mut status).ok()?;
+    Some(status[0] & STATUS_OSF == 0)
+  }
+
+  /// The time, in network milliseconds on a whole second.
+  pub fn read(bus: &mut impl I2c) -> Option<u64> {
+    let mut registers = [0u8; 7];
+    bus
+      .write_read(ADDRESS, &[REG_SECONDS], &mut registers)
+      .ok()?;
+    let [seconds, minutes, hours, _, day, month, year] = registers;
+    let hours = match hours & HOURS_12H {
+      0 => bcd(hours & 0x3F),
+      _ => bcd(hours & 0x1F) % 12 + if hours & HOURS_PM != 0 { 12 } else { 0 },
+    };
+    let century = if month & MONTH_CENTURY != 0 { 100 } else { 0 };
+    let date = gps::Date {
+      year: 2_000 + century + bcd(year) as u16,
+      month: bcd(month & 0x1F),
+      day: bcd(day),
+    };
+    let time = gps::Time {
+      hours,
+      minutes: bcd(minutes),
+      seconds: bcd(seconds),
+    };
+    if !(1..=12).contains(&date.month) || date.day == 0 || time.seconds > 59 {
+      return None;
+    }
+    Some(timesync::epoch_ms(&date, &time))
+  }
+
+  /// Set the time, in 24-hour mode, and clear the oscillator-stop flag.
+  pub fn write(bus: &mut impl I2c, network_ms: u64) -> bool {
+    let (date, time) = timesync::civil(network_ms);
+    let century = if date.year >= 2_100 { MONTH_CENTURY } else { 0 };
+    let registers = [
+      REG_SECONDS,
+      to_bcd(time.seconds),
+      to_bcd(time.minutes),
+      to_bcd(time.hours),
+      timesync::weekday(network_ms),
+      to_bcd(date.day),
+      to_bcd(date.month) | century,
+      to_bcd((date.year % 100) as u8),
+    ];
+    let mut status = [0u8];
+    bus.write(ADDRESS, &registers).is_ok()
+      && bus.write_read(ADDRESS, &[REG_STATUS], &mut status).is_ok()
+      && bus
+        .write(ADDRESS, &[REG_STATUS, status[0] & !STATUS_OSF])
+        .is_ok()
+  }
+
+  fn bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+  }
+
+  fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+  }
+}
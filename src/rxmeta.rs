@@ -0,0 +1,97 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/rxmeta.rs - 接收帧元数据头
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Per-frame metadata on the host RX path.
+//!
+//! With `AT+RXMETA=1` every payload the transparent and framed modes hand
+//! to the host is preceded by a fixed [`HEADER_LEN`]-byte header instead of
+//! going out raw (framed mode drops its length byte; the header has it).
+//! All fields are little endian:
+//!
+//! | offset | size | field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 2    | magic `BH`                                    |
+//! | 2      | 2    | payload length                                |
+//! | 4      | 2    | RSSI, dBm (signed)                            |
+//! | 6      | 1    | SNR, dB (signed)                              |
+//! | 7      | 1    | flags, `FLAG_*`                               |
+//! | 8      | 4    | frequency error, Hz (signed)                  |
+//! | 12     | 4    | receive time, milliseconds since boot         |
+//!
+//! Fields the radio could not supply are zero with their flag clear.  The
+//! setting is persisted; `AT+RXMETA=0`, the default, restores raw output.
+//! KISS, forwarder and Modbus output keep their own formats.
+
+use crate::radio::PacketStatus;
+
+pub const HEADER_LEN: usize = 16;
+pub const MAGIC: [u8; 2] = *b"BH";
+
+/// RSSI and SNR are valid.
+pub const FLAG_QUALITY: u8 = 0x01;
+/// The frequency error is valid.
+pub const FLAG_FREQ_ERROR: u8 = 0x02;
+/// The frame carried the address header.
+pub const FLAG_ADDRESSED: u8 = 0x04;
+/// The frame carried a counter and MIC that checked out.
+pub const FLAG_SECURED: u8 = 0x08;
+/// The frame failed its CRC; only captures (see [`crate::sniffer`]) and
+/// `AT+CRCPASS=1` deliver such frames.
+pub const FLAG_CRC_ERROR: u8 = 0x10;
+
+/// What is known about a received payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Meta {
+  pub len: usize,
+  pub quality: Option<PacketStatus>,
+  pub freq_error_hz: Option<i32>,
+  pub received_ms: u32,
+  pub addressed: bool,
+  pub secured: bool,
+  pub crc_error: bool,
+}
+
+impl Meta {
+  pub fn encode(&self) -> [u8; HEADER_LEN] {
+    let mut flags = 0;
+    if self.quality.is_some() {
+      flags |= FLAG_QUALITY;
+    }
+    if self.freq_error_hz.is_some() {
+      flags |= FLAG_FREQ_ERROR;
+    }
+    if self.addressed {
+      flags |= FLAG_ADDRESSED;
+    }
+    if self.secured {
+      flags |= FLAG_SECURED;
+    }
+    if self.crc_error {
+      flags |= FLAG_CRC_ERROR;
+    }
+    let quality = self.quality.unwrap_or(PacketStatus {
+      rssi_dbm: 0,
+      snr_db: 0,
+    });
+
+    let mut header = [0u8; HEADER_LEN];
+    header[0..2].copy_from_slice(&MAGIC);
+    header[2..4].copy_from_slice(&(self.len as u16).to_le_bytes());
+    header[4..6].copy_from_slice(&quality.rssi_dbm.to_le_bytes());
+    header[6] = quality.snr_db as u8;
+    header[7] = flags;
+    header[8..12].copy_from_slice(&self.freq_error_hz.unwrap_or(0).to_le_bytes());
+    header[12..16].copy_from_slice(&self.received_ms.to_le_bytes());
+    header
+  }
+}
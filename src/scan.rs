@@ -0,0 +1,111 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/scan.rs - 频谱扫描
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Spectrum scan with GetRssiInst.
+//!
+//! `AT+SCAN=<start Hz>,<stop Hz>,<step Hz>` steps the receiver across the
+//! range, one channel per main-loop pass, and takes [`SAMPLES`] instantaneous
+//! RSSI readings on each.  Results stream to the control port as CSV
+//! (`freq_hz,rssi_avg_dbm,rssi_max_dbm`) and the per-channel maxima are
+//! kept for a bar chart on the OLED.  The bridge does not forward traffic
+//! while scanning; the link configuration is restored afterwards.
+
+use heapless::Vec;
+
+/// Most channels in one scan (one OLED column each).
+pub const MAX_CHANNELS: usize = 128;
+/// RSSI readings per channel.
+pub const SAMPLES: u32 = 8;
+/// Time for the receiver to settle after retuning.
+pub const SETTLE_MS: u32 = 2;
+
+/// A validated scan range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct ScanRange {
+  pub start_hz: u32,
+  pub stop_hz: u32,
+  pub step_hz: u32,
+}
+
+impl ScanRange {
+  /// Accept ranges inside the module's band with at most
+  /// [`MAX_CHANNELS`] channels.
+  pub fn new(start_hz: u32, stop_hz: u32, step_hz: u32) -> Option<Self> {
+    let band = crate::band::FREQUENCY_HZ;
+    if !band.contains(&start_hz) || !band.contains(&stop_hz) {
+      return None;
+    }
+    if stop_hz < start_hz || step_hz == 0 {
+      return None;
+    }
+    let range = Self {
+      start_hz,
+      stop_hz,
+      step_hz,
+    };
+    (range.channels() <= MAX_CHANNELS).then_some(range)
+  }
+
+  pub fn channels(&self) -> usize {
+    ((self.stop_hz - self.start_hz) / self.step_hz) as usize + 1
+  }
+}
+
+/// Measurement of one channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Channel {
+  pub frequency_hz: u32,
+  pub rssi_avg: i16,
+  pub rssi_max: i16,
+}
+
+pub struct Scanner {
+  range: ScanRange,
+  /// Maximum RSSI per scanned channel.
+  maxima: Vec<i16, MAX_CHANNELS>,
+}
+
+impl Scanner {
+  pub fn new(range: ScanRange) -> Self {
+    Self {
+      range,
+      maxima: Vec::new(),
+    }
+  }
+
+  /// Frequency of the next channel, or `None` when the scan is complete.
+  pub fn next_frequency(&self) -> Option<u32> {
+    let index = self.maxima.len();
+    (index < self.range.channels())
+      .then(|| self.range.start_hz + index as u32 * self.range.step_hz)
+  }
+
+  /// Reduce the samples of the current channel and move on.
+  pub fn record(&mut self, samples: &[i16]) -> Option<Channel> {
+    let frequency_hz = self.next_frequency()?;
+    let rssi_max = samples.iter().copied().max().unwrap_or(i16::MIN);
+    let sum: i32 = samples.iter().map(|&rssi| rssi as i32).sum();
+    let rssi_avg = sum.checked_div(samples.len() as i32).unwrap_or(0) as i16;
+    let _ = self.maxima.push(rssi_max);
+    Some(Channel {
+      frequency_hz,
+      rssi_avg,
+      rssi_max,
+    })
+  }
+
+  /// Per-channel maxima of the completed part of the scan.
+  pub fn maxima(&self) -> &[i16] {
+    &self.maxima
+  }
+}
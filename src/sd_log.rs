@@ -0,0 +1,331 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/sd_log.rs - SD 卡收发与事件记录
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Packet and event logger on an SD card.
+//!
+//! Built with the `sd-card` feature, an SD card in SPI mode sits on SPI2:
+//! SCK PB13, MISO PB14, MOSI PB15 and chip select PB12, clocked at
+//! [`CLOCK_KHZ`] or below, which every card accepts from power-up and which
+//! is plenty for a log.  The first FAT16/FAT32 partition gets two files in
+//! its root directory, appended to across restarts:
+//!
+//! * [`PACKETS_FILE`], one line per frame received, and per frame sent from
+//!   the transmit queue or for the host protocol:
+//!   `<time ms>,<utc>,<RX|TX>,<rssi>,<snr>,<frame hex>`;
+//! * [`EVENTS_FILE`], the key events also mirrored to the control port by
+//!   `AT+LOG=1` (boot, errors, ...; see [`crate::diagnostics`]), each
+//!   behind its time in milliseconds and the UTC time, and a `--- boot`
+//!   line per start.
+//!
+//! With a card inserted at power-up the bridge logs on its own, so it
+//! works as a field logger with no host attached.  Both files are flushed
+//! every [`FLUSH_MS`]; a power cut loses at most that much.  A card that
+//! fails a write is given up on until the next restart.
+//!
+//! The UTC time is network time in ISO 8601 (see [`crate::timesync`]),
+//! left out while there is none; with an RTC (see [`crate::rtc`]) there
+//! always is.  It also dates the files, which otherwise carry 2026-01-01.
+//!
+//! The card also shows up as a read-only USB drive; see [`crate::msc`].
+//!
+//! `AT+SD?` answers `+SD:<frames>,<event bytes>,<OK|FAILED>` or `+SD:NONE`.
+
+#![cfg_attr(not(feature = "sd-card"), allow(dead_code))]
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::radio::PacketStatus;
+use crate::timesync;
+
+/// Highest SPI clock.
+pub const CLOCK_KHZ: u32 = 400;
+pub const PACKETS_FILE: &str = "PACKETS.CSV";
+pub const EVENTS_FILE: &str = "EVENTS.LOG";
+/// Interval between flushes of both files.
+pub const FLUSH_MS: u32 = 5_000;
+/// First line of a new packets file.
+const PACKETS_HEADER: &[u8] = b"time_ms,utc,dir,rssi,snr,frame\n";
+/// Longest packet line: the fields and a full frame in hex.
+pub const LINE_MAX: usize = 64 + 2 * crate::packetizer::MAX_PAYLOAD;
+
+/// `AT+SD?` without a card.
+pub const REPORT_NONE: &[u8] = b"+SD:NONE\r\n";
+
+/// `<time ms>,<utc>,<RX|TX>,<rssi>,<snr>,<frame hex>`, for a frame sent
+/// or received with `quality`.
+pub fn packet_line(
+  time_ms: u32,
+  network_ms: Option<u64>,
+  tx: bool,
+  quality: Option<PacketStatus>,
+  frame: &[u8],
+) -> String<LINE_MAX> {
+  let mut line = String::new();
+  let _ = write!(line, "{},", time_ms);
+  if let Some(network_ms) = network_ms {
+    let _ = timesync::write_iso(&mut line, network_ms);
+  }
+  let _ = write!(line, ",{},", if tx { "TX" } else { "RX" });
+  let _ = match quality {
+    Some(quality) => write!(line, "{},{},", quality.rssi_dbm, quality.snr_db),
+    None => line.push_str(",,").map_err(|_| core::fmt::Error),
+  };
+  for byte in frame {
+    let _ = write!(line, "{:02X}", byte);
+  }
+  let _ = line.push('\n');
+  line
+}
+
+#[cfg(feature = "sd-card")]
+pub use card::{Logger, init};
+
+/// The card on SPI2.
+#[cfg(feature = "sd-card")]
+mod card {
+  use core::fmt::Write;
+  use core::sync::atomic::{AtomicU32, Ordering};
+
+  use embedded_sdmmc::{
+    Block, BlockCount, BlockDevice, BlockIdx, Error, Mode, RawFile, SdCard, SdCardError,
+    TimeSource, Timestamp, VolumeIdx, VolumeManager,
+  };
+  use heapless::String;
+
+  use super::{EVENTS_FILE, FLUSH_MS, PACKETS_FILE, PACKETS_HEADER, packet_line};
+  use crate::board::Spi2Cs;
+  use crate::radio::PacketStatus;
+  use crate::spi_bus::{Bus2, Device, Wait};
+  use crate::{timer, timesync, version};
+
+  /// Network time in seconds as of the last [`Logger::poll`], `0` for
+  /// none.
+  static NETWORK_S: AtomicU32 = AtomicU32::new(0);
+  /// File dates without network time: 2026-01-01.
+  const FALLBACK_S: u32 = 820_540_800;
+
+  /// File dates from network time.
+  struct Clock;
+
+  impl TimeSource for Clock {
+    fn get_timestamp(&self) -> Timestamp {
+      let network_s = match NETWORK_S.load(Ordering::Relaxed) {
+        0 => FALLBACK_S,
+        network_s => network_s,
+      };
+      let (date, time) = timesync::civil(network_s as u64 * 1_000);
+      Timestamp {
+        year_since_1970: (date.year - 1_970) as u8,
+        zero_indexed_month: date.month - 1,
+        zero_indexed_day: date.day - 1,
+        hours: time.hours,
+        minutes: time.minutes,
+        seconds: time.seconds,
+      }
+    }
+  }
+
+  type Card = SdCard<Device<Bus2, Spi2Cs>, Wait>;
+
+  /// The card, shared by the file system and the raw reads of the USB
+  /// mass storage view.
+  #[derive(Clone, Copy)]
+  struct Shared(&'static Card);
+
+  impl BlockDevice for Shared {
+    type Error = SdCardError;
+
+    fn read(&self, blocks: &mut [Block], start: BlockIdx) -> Result<(), SdCardError> {
+      self.0.read(blocks, start)
+    }
+
+    fn write(&self, blocks: &[Block], start: BlockIdx) -> Result<(), SdCardError> {
+      self.0.write(blocks, start)
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, SdCardError> {
+      self.0.num_blocks()
+    }
+  }
+
+  /// One volume, its root directory and the two files.
+  type Volumes = VolumeManager<Shared, Clock, 1, 2, 1>;
+
+  pub struct Logger {
+    card: Shared,
+    /// Blocks on the card.
+    blocks: u32,
+    volumes: Volumes,
+    packets: RawFile,
+    events: RawFile,
+    flushed_ms: u32,
+    frames: u32,
+    event_bytes: u32,
+    failed: bool,
+  }
+
+  /// Mount the card on `spi` and open both files; `None` without a card
+  /// or a FAT partition.
+  pub fn init(spi: Device<Bus2, Spi2Cs>) -> Option<Logger> {
+    let card: &'static Card = cortex_m::singleton!(: Card = SdCard::new(spi, Wait)).unwrap();
+    let card = Shared(card);
+    let blocks = match card.num_blocks() {
+      Ok(BlockCount(blocks)) => blocks,
+      Err(error) => {
+        defmt::warn!("[sd_log] no card: {}", error);
+        return None;
+      }
+    };
+    defmt::info!("[sd_log] card of {} MiB", blocks / 2048);
+    let volumes = Volumes::new_with_limits(card, Clock, 0);
+    let (packets, events) = match open(&volumes) {
+      Ok(files) => files,
+      Err(error) => {
+        defmt::warn!("[sd_log] cannot open the log files: {}", error);
+        return None;
+      }
+    };
+    let mut logger = Logger {
+      card,
+      blocks,
+      volumes,
+      packets,
+      events,
+      flushed_ms: timer::now_ms(),
+      frames: 0,
+      event_bytes: 0,
+      failed: false,
+    };
+    let mut line = String::<48>::new();
+    let _ = writeln!(
+      line,
+      "--- boot {} ({})",
+      version::VERSION,
+      version::GIT_HASH
+    );
+    logger.write(events, line.as_bytes());
+    defmt::info!("[sd_log] logging to {} and {}", PACKETS_FILE, EVENTS_FILE);
+    Some(logger)
+  }
+
+  /// Open both files in the root directory of the first partition.
+  fn open(volumes: &Volumes) -> Result<(RawFile, RawFile), Error<SdCardError>> {
+    let volume = volumes.open_raw_volume(VolumeIdx(0))?;
+    let root = volumes.open_root_dir(volume)?;
+    let mode = Mode::ReadWriteCreateOrAppend;
+    let packets = volumes.open_file_in_dir(root, PACKETS_FILE, mode)?;
+    let events = volumes.open_file_in_dir(root, EVENTS_FILE, mode)?;
+    volumes.close_dir(root)?;
+    if volumes.file_length(packets)? == 0 {
+      volumes.write(packets, PACKETS_HEADER)?;
+    }
+    Ok((packets, events))
+  }
+
+  impl Logger {
+    /// Log a frame, sent or received with `quality`.
+    pub fn packet(
+      &mut self,
+      time_ms: u32,
+      network_ms: Option<u64>,
+      tx: bool,
+      quality: Option<PacketStatus>,
+      frame: &[u8],
+    ) {
+      let line = packet_line(time_ms, network_ms, tx, quality, frame);
+      if self.write(self.packets, line.as_bytes()) {
+        self.frames += 1;
+      }
+    }
+
+    /// Log event text, one or more lines, each behind the time.
+    pub fn events(&mut self, text: &[u8], network_ms: Option<u64>) {
+      for line in text.split_inclusive(|&byte| byte == b'\n') {
+        let mut time = String::<40>::new();
+        let _ = write!(time, "{} ", timer::now_ms());
+        if let Some(network_ms) = network_ms {
+          let _ = timesync::write_iso(&mut time, network_ms);
+          let _ = time.push(' ');
+        }
+        // The CR of the control port's line ends stays out of the file.
+        let line = line.strip_suffix(b"\r\n").unwrap_or(line);
+        if self.write(self.events, time.as_bytes())
+          && self.write(self.events, line)
+          && self.write(self.events, b"\n")
+        {
+          self.event_bytes += (time.len() + line.len() + 1) as u32;
+        }
+      }
+    }
+
+    /// Blocks on the card.
+    pub fn blocks(&self) -> u32 {
+      self.blocks
+    }
+
+    /// Read block `lba` of the card as it stands, for the USB mass storage
+    /// view.
+    pub fn read_block(&self, lba: u32, buf: &mut [u8; Block::LEN]) -> bool {
+      let mut block = [Block::new()];
+      let read = self.card.read(&mut block, BlockIdx(lba)).is_ok();
+      buf.copy_from_slice(&block[0].contents);
+      read
+    }
+
+    /// Flush both files every [`FLUSH_MS`], dated `network_ms`.
+    pub fn poll(&mut self, now: u32, network_ms: Option<u64>) {
+      let network_s = network_ms.map_or(0, |network_ms| (network_ms / 1_000) as u32);
+      NETWORK_S.store(network_s, Ordering::Relaxed);
+      if self.failed || now.wrapping_sub(self.flushed_ms) < FLUSH_MS {
+        return;
+      }
+      self.flushed_ms = now;
+      if self.volumes.flush_file(self.packets).is_err()
+        || self.volumes.flush_file(self.events).is_err()
+      {
+        self.fail();
+      }
+    }
+
+    /// `+SD:<frames>,<event bytes>,<OK|FAILED>`.
+    pub fn report(&self) -> String<48> {
+      let mut line = String::new();
+      let _ = write!(
+        line,
+        "+SD:{},{},{}\r\n",
+        self.frames,
+        self.event_bytes,
+        if self.failed { "FAILED" } else { "OK" }
+      );
+      line
+    }
+
+    fn write(&mut self, file: RawFile, data: &[u8]) -> bool {
+      if self.failed {
+        return false;
+      }
+      let written = self.volumes.write(file, data).is_ok();
+      if !written {
+        self.fail();
+      }
+      written
+    }
+
+    fn fail(&mut self) {
+      defmt::error!("[sd_log] card write failed, logging stopped");
+      self.failed = true;
+    }
+  }
+}
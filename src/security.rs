@@ -0,0 +1,251 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/security.rs - 帧认证与防重放
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Frame authentication (MIC) and replay protection.
+//!
+//! When enabled, the link frame is extended to
+//!
+//! ```text
+//! [address header?][counter u32 LE][payload ...][MIC 4 bytes]
+//! ```
+//!
+//! The MIC is the first four bytes of AES-128-CMAC over everything before
+//! it, so header, counter and payload are all authenticated.  The one
+//! exception is the count of hops taken, the low nibble of the last
+//! address header byte, which repeaters raise; it enters the MIC as zero.
+//! The hop limit in the high nibble is authenticated.  The payload itself
+//! is not encrypted.
+//!
+//! Every transmitted frame uses a fresh counter.  To survive reboots
+//! without writing flash on every frame, counters are reserved in blocks of
+//! [`COUNTER_BLOCK`]: the persisted value is the first counter of the next
+//! block, and a reboot skips whatever was left of the current one.
+//!
+//! Receivers remember the last counter per sender and reject anything not
+//! strictly newer.  These receive windows live in RAM only.
+//!
+//! Firmware images sent to the peer (see [`crate::ota`]) carry a full
+//! AES-128-CMAC of their own, under a key derived from the link key.
+
+use aes::Aes128;
+use cmac::{Cmac, Mac};
+use heapless::Vec;
+
+use crate::packetizer::MAX_PAYLOAD;
+
+/// Counter values reserved per flash write.
+pub const COUNTER_BLOCK: u32 = 1024;
+/// Bytes of the frame counter.
+const COUNTER_LEN: usize = 4;
+/// Bytes of the truncated CMAC.
+const MIC_LEN: usize = 4;
+/// Senders whose counters are tracked for replay protection.
+const REPLAY_SLOTS: usize = 8;
+/// Input to the derivation of the firmware image key from the link key.
+const IMAGE_KEY_LABEL: &[u8] = b"BlueHigh OTA image";
+
+/// CMAC over a firmware image, see [`Security::image_mac`].
+pub type ImageMac = Cmac<Aes128>;
+
+/// Why a received frame failed verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Reject {
+  /// Too short to hold counter and MIC.
+  Truncated,
+  /// MIC mismatch: wrong key or altered frame.
+  BadMic,
+  /// Counter not newer than the last one seen from this sender.
+  Replay { counter: u32, last: u32 },
+}
+
+pub struct Security {
+  enabled: bool,
+  key: [u8; 16],
+  next_counter: u32,
+  /// First counter not covered by the persisted reservation.
+  reserved_until: u32,
+  /// Last accepted counter per sender address.
+  replay: Vec<(u16, u32), REPLAY_SLOTS>,
+  /// Frames skipped before the last accepted one.
+  last_gap: u32,
+}
+
+impl Security {
+  /// Start from the persisted counter base.  The caller must persist
+  /// [`reservation`](Self::reservation) before transmitting.
+  pub fn new(enabled: bool, key: [u8; 16], counter_base: u32) -> Self {
+    Self {
+      enabled,
+      key,
+      next_counter: counter_base,
+      reserved_until: counter_base.saturating_add(COUNTER_BLOCK),
+      replay: Vec::new(),
+      last_gap: 0,
+    }
+  }
+
+  pub fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  pub fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  pub fn set_key(&mut self, key: [u8; 16]) {
+    self.key = key;
+    // Counters seen under the old key mean nothing under the new one.
+    self.replay.clear();
+  }
+
+  /// A CMAC for authenticating a firmware image, keyed with
+  /// `CMAC(link key, IMAGE_KEY_LABEL)`.  `None` while disabled: without a
+  /// shared key an image cannot be authenticated.
+  pub fn image_mac(&self) -> Option<ImageMac> {
+    if !self.enabled {
+      return None;
+    }
+    let mut kdf = <Cmac<Aes128> as Mac>::new_from_slice(&self.key).unwrap();
+    kdf.update(IMAGE_KEY_LABEL);
+    let key = kdf.finalize().into_bytes();
+    Some(<ImageMac as Mac>::new_from_slice(&key).unwrap())
+  }
+
+  /// Bytes added to every frame.
+  pub fn overhead(&self) -> usize {
+    if self.enabled { COUNTER_LEN + MIC_LEN } else { 0 }
+  }
+
+  /// Counters skipped by the sender before the last opened frame, i.e. how
+  /// many of its frames were lost.  Always 0 while disabled.
+  pub fn last_gap(&self) -> u32 {
+    self.last_gap
+  }
+
+  /// Counter base to persist so that a reboot never reuses a counter.
+  pub fn reservation(&self) -> u32 {
+    self.reserved_until
+  }
+
+  /// Protect `frame`, whose first `header_len` bytes are the link header.
+  ///
+  /// Returns `true` when the counter block is exhausted and
+  /// [`reservation`](Self::reservation) moved on and must be persisted.
+  pub fn seal(&mut self, frame: &mut Vec<u8, MAX_PAYLOAD>, header_len: usize) -> bool {
+    if !self.enabled {
+      return false;
+    }
+
+    let counter = self.next_counter;
+    self.next_counter = self.next_counter.wrapping_add(1);
+
+    // Insert the counter between header and payload.
+    let payload_len = frame.len() - header_len;
+    let _ = frame.resize(frame.len() + COUNTER_LEN, 0);
+    frame.copy_within(header_len..header_len + payload_len, header_len + COUNTER_LEN);
+    frame[header_len..header_len + COUNTER_LEN].copy_from_slice(&counter.to_le_bytes());
+
+    let mic = self.mic(frame, header_len);
+    let _ = frame.extend_from_slice(&mic);
+
+    if self.next_counter >= self.reserved_until {
+      self.reserved_until = self.reserved_until.saturating_add(COUNTER_BLOCK);
+      return true;
+    }
+    false
+  }
+
+  /// Whether the MIC of `frame` is valid, without touching the replay
+  /// windows.  Always `true` while disabled.  Used to keep forged frames
+  /// out of the duplicate cache and the repeater before [`open`]
+  /// consumes the counter.
+  ///
+  /// [`open`]: Self::open
+  pub fn authentic(&self, frame: &[u8], header_len: usize) -> bool {
+    if !self.enabled {
+      return true;
+    }
+    if frame.len() < header_len + COUNTER_LEN + MIC_LEN {
+      return false;
+    }
+    let (authenticated, mic) = frame.split_at(frame.len() - MIC_LEN);
+    self.mic(authenticated, header_len) == mic
+  }
+
+  /// Verify a received frame and return its payload.
+  ///
+  /// `frame` is the whole frame, `header_len` the size of its link header
+  /// and `src` the sender address (0 when addressing is off).
+  pub fn open<'a>(
+    &mut self,
+    frame: &'a [u8],
+    header_len: usize,
+    src: u16,
+  ) -> Result<&'a [u8], Reject> {
+    self.last_gap = 0;
+    if !self.enabled {
+      return Ok(&frame[header_len..]);
+    }
+    if frame.len() < header_len + COUNTER_LEN + MIC_LEN {
+      return Err(Reject::Truncated);
+    }
+
+    let (authenticated, mic) = frame.split_at(frame.len() - MIC_LEN);
+    if self.mic(authenticated, header_len) != mic {
+      return Err(Reject::BadMic);
+    }
+
+    let counter_bytes = &authenticated[header_len..header_len + COUNTER_LEN];
+    let counter = u32::from_le_bytes(counter_bytes.try_into().unwrap());
+    self.accept_counter(src, counter)?;
+
+    Ok(&authenticated[header_len + COUNTER_LEN..])
+  }
+
+  fn accept_counter(&mut self, src: u16, counter: u32) -> Result<(), Reject> {
+    if let Some(slot) = self.replay.iter_mut().find(|(addr, _)| *addr == src) {
+      if counter <= slot.1 {
+        return Err(Reject::Replay {
+          counter,
+          last: slot.1,
+        });
+      }
+      self.last_gap = counter - slot.1 - 1;
+      slot.1 = counter;
+      return Ok(());
+    }
+
+    if self.replay.is_full() {
+      // Forget the oldest sender.
+      self.replay.remove(0);
+    }
+    let _ = self.replay.push((src, counter));
+    Ok(())
+  }
+
+  fn mic(&self, data: &[u8], header_len: usize) -> [u8; MIC_LEN] {
+    let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(&self.key).unwrap();
+    if header_len == 0 {
+      mac.update(data);
+    } else {
+      // The hops byte is the last header byte; only its low nibble, the
+      // hops taken, changes on the way.
+      let hops = header_len - 1;
+      mac.update(&data[..hops]);
+      mac.update(&[data[hops] & 0xF0]);
+      mac.update(&data[hops + 1..]);
+    }
+    let tag = mac.finalize().into_bytes();
+    [tag[0], tag[1], tag[2], tag[3]]
+  }
+}
@@ -0,0 +1,198 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/selftest.rs - 射频收发链路自检
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Radio self-test.
+//!
+//! `AT+SELFTEST` checks the whole TX→RX chain, RF switch included, at the
+//! lowest power, so it can run on the bench without an attenuator.  The
+//! radio is half duplex and cannot hear its own burst, so the test has two
+//! parts:
+//!
+//! 1. Local ([`local`]): a short frame at [`POWER_DBM`] must raise TxDone,
+//!    then the radio must re-enter RX and report the noise floor.
+//! 2. Peer: [`PROBES`] ping probes (see [`crate::ping`]) at the same power;
+//!    the paired peer echoes each, and one answer proves both directions.
+//!
+//! The result goes to the control port:
+//!
+//! ```text
+//! +SELFTEST:PASS,<noise dBm>,<peer RSSI>,<peer SNR>,<RSSI>,<SNR>
+//! +SELFTEST:FAIL,<TX|RX|PEER>
+//! ```
+//!
+//! where the peer RSSI/SNR are how the peer heard us, the others how we
+//! heard the peer.  The configured radio settings come back afterwards.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::ping::Summary;
+use crate::radio::{self, Dio1, PacketStatus, Radio, RadioParams};
+use crate::timer;
+
+/// Transmit power of the test.
+pub const POWER_DBM: i8 = -9;
+/// Probes to the peer; one answer passes.
+pub const PROBES: u16 = 3;
+/// Frame of the local part; the peer drops it as too short.
+const FRAME: [u8; 4] = *b"TEST";
+/// Time for the RSSI to settle after entering RX.
+const RSSI_SETTLE_US: u32 = 1_000;
+
+/// Part of the chain that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Stage {
+  /// No TxDone, or the radio refused the parameters.
+  Tx,
+  /// The radio did not re-enter RX or reports no RSSI.
+  Rx,
+  /// The peer did not answer.
+  Peer,
+}
+
+impl Stage {
+  pub fn name(self) -> &'static str {
+    match self {
+      Stage::Tx => "TX",
+      Stage::Rx => "RX",
+      Stage::Peer => "PEER",
+    }
+  }
+}
+
+/// `params` at the test power.
+pub fn params(params: &RadioParams) -> RadioParams {
+  RadioParams {
+    power_dbm: POWER_DBM,
+    ..*params
+  }
+}
+
+/// Run the local part with `params` at the test power.  Returns the noise
+/// floor in dBm; the radio is left listening at the test power for the
+/// peer part.
+pub fn local(lora: &mut impl Radio, dio1: &Dio1, params: &RadioParams) -> Result<i16, Stage> {
+  if !lora.apply(&self::params(params)) {
+    return Err(Stage::Tx);
+  }
+  // TxDone keeps DIO1 high until RX clears it; a timed out wait leaves it
+  // low.
+  if !radio::transmit_blocking(lora, dio1, &FRAME) || !dio1.is_high() {
+    return Err(Stage::Tx);
+  }
+  if !lora.start_rx() {
+    return Err(Stage::Rx);
+  }
+  timer::wait_until_cycles(timer::after_us(timer::now_cycles(), RSSI_SETTLE_US));
+  lora.rssi_inst().ok_or(Stage::Rx)
+}
+
+/// The test in its peer part.
+struct Running {
+  noise_dbm: i16,
+  /// How the peer heard us and how we heard it, first answer.
+  reply: Option<(PacketStatus, PacketStatus)>,
+}
+
+/// Self-test state across the ping probes.
+pub struct Check {
+  running: Option<Running>,
+}
+
+impl Check {
+  pub fn new() -> Self {
+    Self { running: None }
+  }
+
+  /// Attribute the ping test about to start; the local part measured
+  /// `noise_dbm`.
+  pub fn start(&mut self, noise_dbm: i16) {
+    defmt::info!("[selftest] local part passed, noise {} dBm", noise_dbm);
+    self.running = Some(Running {
+      noise_dbm,
+      reply: None,
+    });
+  }
+
+  /// Abandon the test, e.g. for a plain `AT+PING`.  Returns whether one
+  /// was running, in which case the radio settings must be restored.
+  pub fn cancel(&mut self) -> bool {
+    self.running.take().is_some()
+  }
+
+  /// Account a ping reply.
+  pub fn on_reply(&mut self, there: PacketStatus, here: PacketStatus) {
+    if let Some(running) = self.running.as_mut() {
+      running.reply.get_or_insert((there, here));
+    }
+  }
+
+  /// The outcome when the ping test ends; the radio settings must then be
+  /// restored.
+  pub fn finish(&mut self, summary: &Summary) -> Option<Outcome> {
+    let running = self.running.take()?;
+    defmt::info!("[selftest] {}/{} answers", summary.received, summary.sent);
+    Some(match running.reply {
+      Some((there, here)) => Outcome::Pass {
+        noise_dbm: running.noise_dbm,
+        there,
+        here,
+      },
+      None => Outcome::Fail(Stage::Peer),
+    })
+  }
+}
+
+impl Default for Check {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Result of a test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Outcome {
+  Pass {
+    noise_dbm: i16,
+    /// How the peer heard us.
+    there: PacketStatus,
+    /// How we heard the peer.
+    here: PacketStatus,
+  },
+  Fail(Stage),
+}
+
+impl Outcome {
+  pub fn passed(&self) -> bool {
+    matches!(self, Outcome::Pass { .. })
+  }
+
+  /// The `+SELFTEST:` line.
+  pub fn report(&self) -> String<64> {
+    let mut line = String::new();
+    let _ = match self {
+      Outcome::Pass {
+        noise_dbm,
+        there,
+        here,
+      } => write!(
+        line,
+        "+SELFTEST:PASS,{},{},{},{},{}\r\n",
+        noise_dbm, there.rssi_dbm, there.snr_db, here.rssi_dbm, here.snr_db
+      ),
+      Outcome::Fail(stage) => write!(line, "+SELFTEST:FAIL,{}\r\n", stage.name()),
+    };
+    line
+  }
+}
@@ -0,0 +1,375 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/sensor.rs - I2C 温湿度与气压传感器遥测
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Environment sensor telemetry.
+//!
+//! A BME280 (0x76 or 0x77) and an AHT20 (0x38) may sit on I2C2 next to the
+//! OLED panel; both are looked for at boot.  `AT+SENSOR=<interval s>,<id>`
+//! makes the bridge measure every `interval` seconds and send the result
+//! as a data frame, like a beacon (see [`crate::beacon`]):
+//!
+//! ```text
+//! @3 t=21.37 rh=48.2 p=1008.42
+//! ```
+//!
+//! `@` is followed by the station `id`, then the temperature in °C, the
+//! relative humidity in percent and the pressure in hPa, each only when a
+//! sensor provides it.  Temperature and pressure come from the BME280,
+//! humidity preferably from the AHT20; the AHT20 alone gives temperature
+//! and humidity.  Both sensors measure on request (BME280 forced mode) and
+//! sleep in between; the result is read [`MEASURE_MS`] later, so the main
+//! loop never waits on the bus.
+//!
+//! Headless builds (`no-display`) leave I2C2 unconfigured and have no
+//! sensors.
+
+use core::fmt::Write;
+
+use embedded_hal::i2c::I2c;
+use heapless::String;
+
+use crate::i2c_bus;
+
+/// Shortest interval accepted.
+pub const MIN_INTERVAL_S: u16 = 5;
+/// Longest telemetry line.
+pub const LINE_MAX: usize = 48;
+/// Time from starting a measurement to reading it; the AHT20 needs 80 ms.
+pub const MEASURE_MS: u32 = 100;
+
+/// Persisted sensor telemetry configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Config {
+  /// Seconds between reports, `0` for off.
+  pub interval_s: u16,
+  /// Station ID at the start of every report.
+  pub id: u8,
+}
+
+impl Config {
+  /// Accept `0` (off) or intervals of at least [`MIN_INTERVAL_S`].
+  pub fn new(interval_s: u16, id: u8) -> Option<Self> {
+    (interval_s == 0 || interval_s >= MIN_INTERVAL_S).then_some(Self { interval_s, id })
+  }
+}
+
+/// One measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Reading {
+  /// Hundredths of a degree Celsius.
+  pub temperature_cdeg: Option<i32>,
+  /// Tenths of a percent relative humidity.
+  pub humidity_dpct: Option<u16>,
+  pub pressure_pa: Option<u32>,
+}
+
+impl Reading {
+  /// ` t=21.37 rh=48.2 p=1008.42`, the fields present.
+  pub fn write_fields(&self, out: &mut impl Write) -> core::fmt::Result {
+    if let Some(cdeg) = self.temperature_cdeg {
+      let sign = if cdeg < 0 { "-" } else { "" };
+      let cdeg = cdeg.unsigned_abs();
+      write!(out, " t={}{}.{:02}", sign, cdeg / 100, cdeg % 100)?;
+    }
+    if let Some(dpct) = self.humidity_dpct {
+      write!(out, " rh={}.{}", dpct / 10, dpct % 10)?;
+    }
+    if let Some(pa) = self.pressure_pa {
+      write!(out, " p={}.{:02}", pa / 100, pa % 100)?;
+    }
+    Ok(())
+  }
+}
+
+/// The sensors found, their schedule and the last reading.
+pub struct Sensors {
+  bus: Option<i2c_bus::Shared>,
+  bme280: Option<Bme280>,
+  aht20: bool,
+  /// When the running measurement was started.
+  measuring: Option<u32>,
+  last_ms: u32,
+  reading: Option<Reading>,
+  /// A reading waits to be sent.
+  pending: bool,
+}
+
+impl Sensors {
+  /// Look for sensors on `bus`, if there is one.
+  pub fn new(bus: Option<i2c_bus::Shared>, now: u32) -> Self {
+    let mut sensors = Self {
+      bus,
+      bme280: None,
+      aht20: false,
+      measuring: None,
+      last_ms: now,
+      reading: None,
+      pending: false,
+    };
+    if let Some(mut bus) = bus {
+      sensors.bme280 = Bme280::probe(&mut bus);
+      sensors.aht20 = aht20::probe(&mut bus);
+      defmt::info!("[sensor] found {}", sensors.names().as_str());
+    }
+    sensors
+  }
+
+  /// Names of the sensors found, `NONE` without any.
+  pub fn names(&self) -> String<16> {
+    let mut names = String::new();
+    let _ = match (self.bme280.is_some(), self.aht20) {
+      (true, true) => names.push_str("BME280+AHT20"),
+      (true, false) => names.push_str("BME280"),
+      (false, true) => names.push_str("AHT20"),
+      (false, false) => names.push_str("NONE"),
+    };
+    names
+  }
+
+  /// Restart the interval, e.g. after the configuration changed.
+  pub fn restart(&mut self, now: u32) {
+    self.last_ms = now;
+    self.measuring = None;
+    self.pending = false;
+  }
+
+  /// The last reading, if any.
+  pub fn reading(&self) -> Option<Reading> {
+    self.reading
+  }
+
+  /// Start a measurement when one is due and read it once it is done.
+  pub fn poll(&mut self, config: &Config, now: u32) {
+    let Some(mut bus) = self.bus else {
+      return;
+    };
+    if config.interval_s == 0 || (self.bme280.is_none() && !self.aht20) {
+      return;
+    }
+    match self.measuring {
+      None if now.wrapping_sub(self.last_ms) >= config.interval_s as u32 * 1_000 => {
+        self.last_ms = now;
+        if let Some(bme280) = &self.bme280 {
+          bme280.start(&mut bus);
+        }
+        if self.aht20 {
+          aht20::start(&mut bus);
+        }
+        self.measuring = Some(now);
+      }
+      Some(started) if now.wrapping_sub(started) >= MEASURE_MS => {
+        self.measuring = None;
+        let bme280 = self.bme280.as_ref().and_then(|bme280| bme280.read(&mut bus));
+        let aht20 = if self.aht20 { aht20::read(&mut bus) } else { None };
+        let reading = Reading {
+          temperature_cdeg: bme280.map(|(cdeg, ..)| cdeg).or(aht20.map(|(cdeg, _)| cdeg)),
+          humidity_dpct: aht20
+            .map(|(_, dpct)| dpct)
+            .or(bme280.and_then(|(_, dpct, _)| dpct)),
+          pressure_pa: bme280.map(|(.., pa)| pa),
+        };
+        if reading == Reading::default() {
+          defmt::warn!("[sensor] no sensor answered");
+          return;
+        }
+        self.reading = Some(reading);
+        self.pending = true;
+      }
+      _ => {}
+    }
+  }
+
+  /// The report of a reading not sent yet.
+  pub fn take_report(&mut self, config: &Config) -> Option<String<LINE_MAX>> {
+    let reading = self.reading.filter(|_| self.pending)?;
+    self.pending = false;
+    let mut line = String::new();
+    let _ = write!(line, "@{}", config.id);
+    let _ = reading.write_fields(&mut line);
+    let _ = line.push('\n');
+    Some(line)
+  }
+}
+
+/// Bosch BME280 with its factory calibration.
+struct Bme280 {
+  address: u8,
+  t1: u16,
+  t2: i16,
+  t3: i16,
+  p1: u16,
+  p: [i16; 8],
+  h1: u8,
+  h2: i16,
+  h3: u8,
+  h4: i16,
+  h5: i16,
+  h6: i8,
+}
+
+impl Bme280 {
+  const ADDRESSES: [u8; 2] = [0x76, 0x77];
+  const CHIP_ID: u8 = 0x60;
+  const REG_CHIP_ID: u8 = 0xD0;
+  const REG_CALIB_TP: u8 = 0x88;
+  const REG_CALIB_H1: u8 = 0xA1;
+  const REG_CALIB_H: u8 = 0xE1;
+  const REG_CTRL_HUM: u8 = 0xF2;
+  const REG_CTRL_MEAS: u8 = 0xF4;
+  const REG_DATA: u8 = 0xF7;
+  /// Temperature and pressure oversampling x1 (0b001 each), forced mode
+  /// (0b01).
+  const CTRL_MEAS_FORCED: u8 = 0x25;
+  /// Humidity oversampling x1.
+  const CTRL_HUM: u8 = 0b001;
+
+  fn probe(bus: &mut impl I2c) -> Option<Self> {
+    let address = Self::ADDRESSES.into_iter().find(|&address| {
+      let mut id = [0u8];
+      bus.write_read(address, &[Self::REG_CHIP_ID], &mut id).is_ok() && id[0] == Self::CHIP_ID
+    })?;
+    let mut tp = [0u8; 24];
+    let mut h1 = [0u8];
+    let mut h = [0u8; 7];
+    bus.write_read(address, &[Self::REG_CALIB_TP], &mut tp).ok()?;
+    bus.write_read(address, &[Self::REG_CALIB_H1], &mut h1).ok()?;
+    bus.write_read(address, &[Self::REG_CALIB_H], &mut h).ok()?;
+    let word = |index: usize| u16::from_le_bytes([tp[index], tp[index + 1]]);
+    Some(Self {
+      address,
+      t1: word(0),
+      t2: word(2) as i16,
+      t3: word(4) as i16,
+      p1: word(6),
+      p: core::array::from_fn(|index| word(8 + 2 * index) as i16),
+      h1: h1[0],
+      h2: i16::from_le_bytes([h[0], h[1]]),
+      h3: h[2],
+      h4: ((h[3] as i8 as i16) << 4) | (h[4] & 0x0F) as i16,
+      h5: ((h[5] as i8 as i16) << 4) | (h[4] >> 4) as i16,
+      h6: h[6] as i8,
+    })
+  }
+
+  fn start(&self, bus: &mut impl I2c) {
+    // Humidity settings only take effect with the next ctrl_meas write.
+    let _ = bus.write(self.address, &[Self::REG_CTRL_HUM, Self::CTRL_HUM]);
+    let _ = bus.write(self.address, &[Self::REG_CTRL_MEAS, Self::CTRL_MEAS_FORCED]);
+  }
+
+  /// Temperature (0.01 °C), humidity (0.1 %) and pressure (Pa), with the
+  /// integer compensation of the datasheet.
+  fn read(&self, bus: &mut impl I2c) -> Option<(i32, Option<u16>, u32)> {
+    let mut data = [0u8; 8];
+    bus.write_read(self.address, &[Self::REG_DATA], &mut data).ok()?;
+    let raw20 = |bytes: &[u8]| {
+      ((bytes[0] as i32) << 12) | ((bytes[1] as i32) << 4) | (bytes[2] as i32 >> 4)
+    };
+    let (adc_p, adc_t) = (raw20(&data[0..3]), raw20(&data[3..6]));
+    let adc_h = i32::from_be_bytes([0, 0, data[6], data[7]]);
+    // 0x80000 is what a skipped measurement reads.
+    if adc_t == 0x80000 || adc_p == 0x80000 {
+      return None;
+    }
+
+    let (t1, t2, t3) = (self.t1 as i32, self.t2 as i32, self.t3 as i32);
+    let var1 = (((adc_t >> 3) - (t1 << 1)) * t2) >> 11;
+    let var2 = (((((adc_t >> 4) - t1) * ((adc_t >> 4) - t1)) >> 12) * t3) >> 14;
+    let t_fine = var1 + var2;
+    let temperature_cdeg = (t_fine * 5 + 128) >> 8;
+
+    let [p2, p3, p4, p5, p6, p7, p8, p9] = self.p.map(|p| p as i64);
+    let mut var1 = t_fine as i64 - 128_000;
+    let mut var2 = var1 * var1 * p6 + ((var1 * p5) << 17) + (p4 << 35);
+    var1 = ((var1 * var1 * p3) >> 8) + ((var1 * p2) << 12);
+    var1 = (((1i64 << 47) + var1) * self.p1 as i64) >> 33;
+    if var1 == 0 {
+      return None;
+    }
+    let mut p = 1_048_576 - adc_p as i64;
+    p = (((p << 31) - var2) * 3_125) / var1;
+    var1 = (p9 * (p >> 13) * (p >> 13)) >> 25;
+    var2 = (p8 * p) >> 19;
+    // Q24.8 Pa.
+    let pressure_pa = ((((p + var1 + var2) >> 8) + (p7 << 4)) >> 8) as u32;
+
+    let humidity_dpct = (adc_h != 0x8000).then(|| {
+      let v = t_fine - 76_800;
+      let (h1, h2, h3) = (self.h1 as i32, self.h2 as i32, self.h3 as i32);
+      let (h4, h5, h6) = (self.h4 as i32, self.h5 as i32, self.h6 as i32);
+      let mut v = ((((adc_h << 14) - (h4 << 20) - (h5 * v)) + 16_384) >> 15)
+        * (((((((v * h6) >> 10) * (((v * h3) >> 11) + 32_768)) >> 10) + 2_097_152) * h2
+          + 8_192)
+          >> 14);
+      v -= ((((v >> 15) * (v >> 15)) >> 7) * h1) >> 4;
+      // Q22.10 percent.
+      let q10 = (v.clamp(0, 419_430_400) >> 12) as u32;
+      ((q10 * 10 + 512) / 1_024) as u16
+    });
+
+    Some((temperature_cdeg, humidity_dpct, pressure_pa))
+  }
+}
+
+/// Aosong AHT20.
+mod aht20 {
+  use embedded_hal::i2c::I2c;
+
+  const ADDRESS: u8 = 0x38;
+  const STATUS_BUSY: u8 = 0x80;
+  const STATUS_CALIBRATED: u8 = 0x08;
+  const CMD_INIT: [u8; 3] = [0xBE, 0x08, 0x00];
+  const CMD_MEASURE: [u8; 3] = [0xAC, 0x33, 0x00];
+
+  /// Whether an AHT20 answers; loads its calibration if needed.  It needs
+  /// 10 ms after that, well before the first measurement.
+  pub fn probe(bus: &mut impl I2c) -> bool {
+    let mut status = [0u8];
+    if bus.read(ADDRESS, &mut status).is_err() {
+      return false;
+    }
+    if status[0] & STATUS_CALIBRATED == 0 {
+      let _ = bus.write(ADDRESS, &CMD_INIT);
+    }
+    true
+  }
+
+  pub fn start(bus: &mut impl I2c) {
+    let _ = bus.write(ADDRESS, &CMD_MEASURE);
+  }
+
+  /// Temperature (0.01 °C) and humidity (0.1 %).
+  pub fn read(bus: &mut impl I2c) -> Option<(i32, u16)> {
+    let mut data = [0u8; 7];
+    bus.read(ADDRESS, &mut data).ok()?;
+    if data[0] & STATUS_BUSY != 0 || crc8(&data[..6]) != data[6] {
+      return None;
+    }
+    let humidity = ((data[1] as u32) << 12) | ((data[2] as u32) << 4) | (data[3] as u32 >> 4);
+    let temperature = (((data[3] & 0x0F) as u32) << 16) | ((data[4] as u32) << 8) | data[5] as u32;
+    // Both are fractions of 2^20: 100 % and 200 °C from -50 °C.
+    let humidity_dpct = ((humidity as u64 * 1_000) >> 20) as u16;
+    let temperature_cdeg = ((temperature as u64 * 20_000) >> 20) as i32 - 5_000;
+    Some((temperature_cdeg, humidity_dpct))
+  }
+
+  /// CRC-8, polynomial 0x31, initial 0xFF.
+  fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0xFF, |crc, &byte| {
+      (0..8).fold(crc ^ byte, |crc, _| match crc & 0x80 {
+        0 => crc << 1,
+        _ => (crc << 1) ^ 0x31,
+      })
+    })
+  }
+}
@@ -0,0 +1,811 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/settings.rs - 持久化设置
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Runtime settings persisted in the last flash page.
+//!
+//! `memory.x` keeps the final 1 KiB page out of the program image.  The page
+//! holds one record:
+//!
+//! ```text
+//! [magic u32][version u8][len u8][payload ...][checksum u16]
+//! ```
+//!
+//! All integers are little-endian.  The checksum is a CRC16-CCITT (see
+//! [`crate::crc`]); version 1 records, written before, carry a Fletcher-16
+//! and still load.  A record with a wrong magic, version or checksum is
+//! ignored and defaults are used instead.  New fields are only
+//! ever appended; fields missing from an older record take their defaults.
+//! Flash is only written when a setting changes.
+//!
+//! The rest of the page holds [`PROFILES`] named profiles, each a record
+//! like the one above behind its name:
+//!
+//! ```text
+//! [name len u8][name, PROFILE_NAME_MAX bytes][record ...]
+//! ```
+//!
+//! `AT+PROFILE=SAVE,<name>` stores the current settings as a profile,
+//! `AT+PROFILE=LOAD,<name>` makes a profile the current settings and
+//! restarts, `AT+PROFILE=DEL,<name>` forgets one and `AT+PROFILE?` lists
+//! them.  Holding the button while the bridge powers up loads the profile
+//! after the one loaded last, so a bridge in the field can be switched
+//! between e.g. `long_range` and `fast` without a host.  Profiles leave the
+//! USB identity out; loading one keeps the bridge's own.
+//!
+//! `AT+CFG?` exports the same record as hex and `AT+CFG=<hex>` imports one,
+//! to clone a working configuration onto other bridges.  The checksum
+//! guards the copy; the frame counter and the LoRaWAN DevNonce stay this
+//! bridge's own, since reusing another's would repeat values on the air.
+//!
+//! With an external flash (see [`crate::spi_flash`]) the page is copied to
+//! its [`SETTINGS`](spi_flash::SETTINGS) partition whenever the settings
+//! change, counter reservations included, and copied back at boot if the
+//! page holds no intact record.
+
+use crate::airtime;
+use crate::battery;
+use crate::beacon;
+use crate::calibration;
+use crate::crc::crc16_ccitt;
+use crate::csma;
+use crate::cw::{self, Beacon};
+use crate::device_id;
+use crate::hal::flash::{self, FlashSize, SectorSize};
+use crate::log_control;
+use crate::lorawan;
+use crate::radio::{self, RadioParams, SwitchGuard};
+use crate::sensor;
+use crate::spi_flash::{self, Storage};
+use crate::tdma;
+use crate::trim;
+use crate::uart;
+use crate::ui::ScreenPower;
+use crate::usb;
+
+/// Offset of the settings page from the start of flash.
+const PAGE_OFFSET: u32 = 63 * 1024;
+/// Memory-mapped address of the settings page.
+const PAGE_ADDRESS: u32 = flash::FLASH_START + PAGE_OFFSET;
+/// Bytes reserved for the settings record.
+pub const RECORD_MAX: usize = 256;
+/// Size of the settings page.
+const PAGE_LEN: usize = 1024;
+/// Named profiles kept after the current settings.
+pub const PROFILES: usize = 3;
+/// Longest profile name.
+pub const PROFILE_NAME_MAX: usize = 15;
+/// Bytes of the page per profile.
+const SLOT_LEN: usize = (PAGE_LEN - RECORD_MAX) / PROFILES;
+/// Name length and name ahead of a profile's record.
+const PROFILE_HEADER_LEN: usize = 1 + PROFILE_NAME_MAX;
+
+const MAGIC: u32 = 0x4248_5354; // "BHST"
+const VERSION: u8 = 2;
+/// Records sealed with Fletcher-16 instead of a CRC.
+const VERSION_FLETCHER: u8 = 1;
+/// Size of magic + version + len.
+const HEADER_LEN: usize = 6;
+
+/// Persisted settings.
+#[derive(Debug, Clone, PartialEq, Eq, defmt::Format)]
+pub struct Settings {
+  /// Local node address used by the addressing layer.
+  pub node_address: u16,
+  /// Destination of transmitted frames.
+  pub peer_address: u16,
+  /// Whether frames carry the address header.
+  pub addressing: bool,
+  /// AES-128 key of the frame MIC.
+  pub link_key: [u8; 16],
+  /// Whether frames carry a counter and MIC.
+  pub security: bool,
+  /// First transmit frame counter not yet handed out.
+  pub tx_counter_base: u32,
+  /// LoRa parameters of the link.
+  pub radio: RadioParams,
+  /// Whether adaptive data rate adjusts the spreading factor.
+  pub adr: bool,
+  /// OLED contrast and inactivity timeouts.
+  pub screen: ScreenPower,
+  /// CW identification callsign and interval.
+  pub cw_id: Beacon,
+  /// Periodic telemetry beacon.
+  pub beacon: beacon::Config,
+  /// Whether the bridge relays frames for other nodes.
+  pub repeater: bool,
+  /// Relays a transmitted frame may take.
+  pub hop_limit: u8,
+  /// Time-slotted channel access.
+  pub tdma: tdma::Config,
+  /// LoRaWAN session, from ABP or a join.
+  pub lorawan: lorawan::Session,
+  /// First LoRaWAN FCntUp not yet used; reserved ahead like
+  /// `tx_counter_base`.
+  pub lorawan_fcnt_base: u32,
+  /// LoRaWAN over-the-air activation identity.
+  pub lorawan_otaa: lorawan::Otaa,
+  /// Next LoRaWAN DevNonce.
+  pub lorawan_dev_nonce: u16,
+  /// Which port carries the bridge data, and the UART baud rate.
+  pub data_port: uart::Config,
+  /// Whether the data port carries Modbus RTU.
+  pub modbus: bool,
+  /// Sensor telemetry interval and station ID.
+  pub sensor: sensor::Config,
+  /// Battery divider ratio and thresholds.
+  pub battery: battery::Config,
+  /// Measured transmit power of the module.
+  pub calibration: calibration::Table,
+  /// Delays around TXEN/RXEN transitions.
+  pub switch_guard: SwitchGuard,
+  /// Whether received payloads go to the host with a metadata header.
+  pub rx_meta: bool,
+  /// Whether the carrier follows the peer's.
+  pub afc: bool,
+  /// Temperature coefficient of the carrier.
+  pub trim: trim::Config,
+  /// Whether frames that failed their CRC still go to the host.
+  pub crc_pass: bool,
+  /// Profile slot these settings were last loaded from.
+  pub profile: Option<u8>,
+  /// Duty-cycle budget.
+  pub airtime: airtime::Config,
+  /// Carrier-sense backoff window.
+  pub csma: csma::Config,
+  /// Whether frames are logged to the external flash.
+  pub flash_log: bool,
+  /// Log level of every diagnostic category.
+  pub log_levels: log_control::Config,
+  /// VID, PID and strings the device enumerates with, from the next reset.
+  pub usb_identity: usb::Identity,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      node_address: device_id::default_node_address(),
+      peer_address: crate::link::BROADCAST,
+      addressing: false,
+      link_key: [0; 16],
+      security: false,
+      tx_counter_base: 0,
+      radio: RadioParams::default(),
+      adr: false,
+      screen: ScreenPower::default(),
+      cw_id: Beacon::default(),
+      beacon: beacon::Config::default(),
+      repeater: false,
+      hop_limit: crate::relay::DEFAULT_HOP_LIMIT,
+      tdma: tdma::Config::default(),
+      lorawan: lorawan::Session::default(),
+      lorawan_fcnt_base: 0,
+      lorawan_otaa: lorawan::Otaa::default(),
+      lorawan_dev_nonce: 0,
+      data_port: uart::Config::default(),
+      modbus: false,
+      sensor: sensor::Config::default(),
+      battery: battery::Config::default(),
+      calibration: calibration::Table::default(),
+      switch_guard: SwitchGuard::default(),
+      rx_meta: false,
+      afc: false,
+      trim: trim::Config::default(),
+      crc_pass: false,
+      profile: None,
+      airtime: airtime::Config::default(),
+      csma: csma::Config::OFF,
+      flash_log: false,
+      log_levels: log_control::Config::DEFAULT,
+      usb_identity: usb::Identity::default(),
+    }
+  }
+}
+
+impl Settings {
+  /// Load the persisted settings, or defaults if none are stored.
+  pub fn load() -> Self {
+    match Self::decode(&stored_page()[..RECORD_MAX]) {
+      Some(settings) => settings,
+      None => {
+        defmt::println!("[settings] none stored, using defaults");
+        Self::default()
+      }
+    }
+  }
+
+  /// Write the settings to flash.  Fails with
+  /// [`flash::Error::LengthTooLong`], leaving the page untouched, when the
+  /// record would not fit in [`RECORD_MAX`] bytes.
+  pub fn save(&self, flash: &mut flash::Parts) -> flash::Result<()> {
+    let mut record = [0xFFu8; RECORD_MAX];
+    let len = self.encode(&mut record).ok_or(flash::Error::LengthTooLong)?;
+    rewrite_page(flash, |page| {
+      page[..RECORD_MAX].copy_from_slice(&record);
+    })?;
+    defmt::println!("[settings] saved {} bytes", len);
+    Ok(())
+  }
+
+  /// Store the settings as profile `name`, replacing the profile of that
+  /// name or taking a free slot.  Returns the slot.
+  pub fn save_profile(
+    &self,
+    name: &ProfileName,
+    flash: &mut flash::Parts,
+  ) -> Result<usize, ProfileError> {
+    let names = profiles();
+    let slot = find_profile(name)
+      .or_else(|| names.iter().position(Option::is_none))
+      .ok_or(ProfileError::Full)?;
+    // The USB identity belongs to the bridge, not the profile, and would
+    // not leave room for the record in a slot.
+    let settings = Self {
+      usb_identity: usb::Identity::default(),
+      ..self.clone()
+    };
+    let mut record = [0xFFu8; RECORD_MAX];
+    let len = settings.encode(&mut record).ok_or(ProfileError::TooLong)?;
+    if PROFILE_HEADER_LEN + len > SLOT_LEN {
+      return Err(ProfileError::TooLong);
+    }
+    rewrite_page(flash, |page| {
+      let profile = &mut page[slot_range(slot)];
+      profile.fill(0xFF);
+      profile[0] = name.len;
+      profile[1..PROFILE_HEADER_LEN].copy_from_slice(&name.name);
+      profile[PROFILE_HEADER_LEN..PROFILE_HEADER_LEN + len].copy_from_slice(&record[..len]);
+    })
+    .map_err(|_| ProfileError::Flash)?;
+    defmt::println!("[settings] saved profile {} in slot {}", name, slot);
+    Ok(slot)
+  }
+
+  /// The settings of profile `slot`, keeping the counters and the USB
+  /// identity of `self`.
+  pub fn load_profile(&self, slot: usize) -> Option<Self> {
+    let profile = &stored_page()[slot_range(slot)];
+    let mut settings = self.import(&profile[PROFILE_HEADER_LEN..])?;
+    settings.profile = Some(slot as u8);
+    settings.usb_identity = self.usb_identity;
+    Some(settings)
+  }
+
+  /// Slot of the profile to load at a boot with the button held: the one
+  /// after the profile loaded last.
+  pub fn next_profile(&self) -> Option<usize> {
+    let names = profiles();
+    let start = self.profile.map_or(0, |slot| slot as usize + 1);
+    (start..start + PROFILES)
+      .map(|slot| slot % PROFILES)
+      .find(|&slot| names[slot].is_some())
+  }
+
+  /// The persisted record, for `AT+CFG?`.  Returns its length, or `None`
+  /// when the record would not fit.
+  pub fn export(&self, out: &mut [u8; RECORD_MAX]) -> Option<usize> {
+    self.encode(out)
+  }
+
+  /// Settings from an exported `record`, keeping the counters of `self`.
+  /// `None` when the record is not intact.
+  pub fn import(&self, record: &[u8]) -> Option<Self> {
+    let mut settings = Self::decode(record)?;
+    settings.tx_counter_base = self.tx_counter_base;
+    settings.lorawan_dev_nonce = self.lorawan_dev_nonce;
+    Some(settings)
+  }
+
+  fn encode(&self, out: &mut [u8]) -> Option<usize> {
+    let mut payload = Writer::new(out.get_mut(HEADER_LEN..)?);
+    payload.u16(self.node_address)?;
+    payload.u16(self.peer_address)?;
+    payload.u8(self.addressing as u8)?;
+    payload.bytes(&self.link_key)?;
+    payload.u8(self.security as u8)?;
+    payload.u32(self.tx_counter_base)?;
+    payload.bytes(&self.radio.encode())?;
+    payload.u8(self.adr as u8)?;
+    payload.u8(self.screen.contrast)?;
+    payload.u16(self.screen.dim_after_s)?;
+    payload.u16(self.screen.off_after_s)?;
+    payload.u16(self.cw_id.interval_s)?;
+    payload.u8(self.cw_id.callsign().len() as u8)?;
+    let mut callsign = [0u8; cw::CALLSIGN_MAX];
+    callsign[..self.cw_id.callsign().len()].copy_from_slice(self.cw_id.callsign());
+    payload.bytes(&callsign)?;
+    payload.u16(self.beacon.interval_s)?;
+    payload.u8(self.beacon.fields)?;
+    payload.u8(self.repeater as u8)?;
+    payload.u8(self.hop_limit)?;
+    payload.bytes(&self.tdma.encode())?;
+    payload.bytes(&self.lorawan.encode())?;
+    payload.u32(self.lorawan_fcnt_base)?;
+    payload.bytes(&self.lorawan.encode_rx())?;
+    payload.bytes(&self.lorawan_otaa.encode())?;
+    payload.u16(self.lorawan_dev_nonce)?;
+    payload.u8(self.data_port.source as u8)?;
+    payload.u32(self.data_port.baud)?;
+    payload.u8(self.modbus as u8)?;
+    payload.u16(self.sensor.interval_s)?;
+    payload.u8(self.sensor.id)?;
+    payload.u16(self.battery.ratio_milli)?;
+    payload.u16(self.battery.low_mv)?;
+    payload.u16(self.battery.full_mv)?;
+    payload.bytes(&self.calibration.encode())?;
+    payload.u16(self.switch_guard.pre_us)?;
+    payload.u16(self.switch_guard.post_us)?;
+    payload.u8(self.rx_meta as u8)?;
+    payload.u8(self.afc as u8)?;
+    payload.u16(self.trim.ppb_per_c as u16)?;
+    payload.u8(self.trim.reference_c as u8)?;
+    payload.u8(self.crc_pass as u8)?;
+    payload.u8(self.profile.unwrap_or(u8::MAX))?;
+    payload.u16(self.airtime.permille)?;
+    payload.u16(self.csma.min_ms)?;
+    payload.u16(self.csma.max_ms)?;
+    payload.u8(self.flash_log as u8)?;
+    payload.u16(self.log_levels.encode())?;
+    payload.u16(self.usb_identity.vid)?;
+    payload.u16(self.usb_identity.pid)?;
+    payload.u8(self.usb_identity.manufacturer_raw().len() as u8)?;
+    payload.bytes(self.usb_identity.manufacturer_raw())?;
+    payload.u8(self.usb_identity.product_raw().len() as u8)?;
+    payload.bytes(self.usb_identity.product_raw())?;
+    let payload_len = payload.len;
+
+    out[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    out[4] = VERSION;
+    out[5] = u8::try_from(payload_len).ok()?;
+    let end = HEADER_LEN + payload_len;
+    let sum = crc16_ccitt(&out[..end]);
+    out.get_mut(end..end + 2)?.copy_from_slice(&sum.to_le_bytes());
+    Some(end + 2)
+  }
+
+  fn decode(record: &[u8]) -> Option<Self> {
+    if record.len() < HEADER_LEN || record[0..4] != MAGIC.to_le_bytes() {
+      return None;
+    }
+    let checksum = match record[4] {
+      VERSION => crc16_ccitt,
+      VERSION_FLETCHER => fletcher16,
+      _ => return None,
+    };
+    let end = HEADER_LEN + record[5] as usize;
+    if end + 2 > record.len() {
+      return None;
+    }
+    let stored_sum = u16::from_le_bytes([record[end], record[end + 1]]);
+    if checksum(&record[..end]) != stored_sum {
+      defmt::warn!("[settings] checksum mismatch");
+      return None;
+    }
+
+    let defaults = Self::default();
+    let mut payload = Reader::new(&record[HEADER_LEN..end]);
+    let mut settings = Self {
+      node_address: payload.u16().unwrap_or(defaults.node_address),
+      peer_address: payload.u16().unwrap_or(defaults.peer_address),
+      addressing: payload.bool().unwrap_or(defaults.addressing),
+      link_key: payload.bytes().unwrap_or(defaults.link_key),
+      security: payload.bool().unwrap_or(defaults.security),
+      tx_counter_base: payload.u32().unwrap_or(defaults.tx_counter_base),
+      radio: payload
+        .bytes::<{ radio::ENCODED_LEN }>()
+        .and_then(|bytes| RadioParams::decode(&bytes))
+        .unwrap_or(defaults.radio),
+      adr: payload.bool().unwrap_or(defaults.adr),
+      screen: payload.screen().unwrap_or(defaults.screen),
+      cw_id: payload.cw_id().unwrap_or(defaults.cw_id),
+      beacon: payload.beacon().unwrap_or(defaults.beacon),
+      repeater: payload.bool().unwrap_or(defaults.repeater),
+      // The hop byte has four bits for it, as `AT+TTL` allows.
+      hop_limit: payload
+        .u8()
+        .filter(|&limit| limit <= crate::relay::MAX_HOP_LIMIT)
+        .unwrap_or(defaults.hop_limit),
+      tdma: payload
+        .bytes()
+        .and_then(tdma::Config::decode)
+        .unwrap_or(defaults.tdma),
+      lorawan: payload
+        .bytes()
+        .map(lorawan::Session::decode)
+        .unwrap_or(defaults.lorawan),
+      lorawan_fcnt_base: payload.u32().unwrap_or(defaults.lorawan_fcnt_base),
+      lorawan_otaa: defaults.lorawan_otaa,
+      lorawan_dev_nonce: defaults.lorawan_dev_nonce,
+      data_port: defaults.data_port,
+      modbus: defaults.modbus,
+      sensor: defaults.sensor,
+      battery: defaults.battery,
+      calibration: defaults.calibration,
+      switch_guard: defaults.switch_guard,
+      rx_meta: defaults.rx_meta,
+      afc: defaults.afc,
+      trim: defaults.trim,
+      crc_pass: defaults.crc_pass,
+      profile: defaults.profile,
+      airtime: defaults.airtime,
+      csma: defaults.csma,
+      flash_log: defaults.flash_log,
+      log_levels: defaults.log_levels,
+      usb_identity: defaults.usb_identity,
+    };
+    if let Some(rx) = payload.bytes() {
+      settings.lorawan.decode_rx(rx);
+    }
+    settings.lorawan_otaa = payload
+      .bytes()
+      .map(lorawan::Otaa::decode)
+      .unwrap_or(defaults.lorawan_otaa);
+    settings.lorawan_dev_nonce = payload.u16().unwrap_or(defaults.lorawan_dev_nonce);
+    settings.data_port = payload.data_port().unwrap_or(defaults.data_port);
+    settings.modbus = payload.bool().unwrap_or(defaults.modbus);
+    settings.sensor = payload.sensor().unwrap_or(defaults.sensor);
+    settings.battery = payload.battery().unwrap_or(defaults.battery);
+    settings.calibration = payload
+      .bytes()
+      .map(calibration::Table::decode)
+      .unwrap_or(defaults.calibration);
+    settings.switch_guard = payload.switch_guard().unwrap_or(defaults.switch_guard);
+    settings.rx_meta = payload.bool().unwrap_or(defaults.rx_meta);
+    settings.afc = payload.bool().unwrap_or(defaults.afc);
+    settings.trim = payload.trim().unwrap_or(defaults.trim);
+    settings.crc_pass = payload.bool().unwrap_or(defaults.crc_pass);
+    settings.profile = payload
+      .u8()
+      .map(|slot| (usize::from(slot) < PROFILES).then_some(slot))
+      .unwrap_or(defaults.profile);
+    settings.airtime = payload
+      .u16()
+      .and_then(airtime::Config::new)
+      .unwrap_or(defaults.airtime);
+    settings.csma = payload.csma().unwrap_or(defaults.csma);
+    settings.flash_log = payload.bool().unwrap_or(defaults.flash_log);
+    settings.log_levels = payload
+      .u16()
+      .and_then(log_control::Config::decode)
+      .unwrap_or(defaults.log_levels);
+    settings.usb_identity = payload.usb_identity().unwrap_or(defaults.usb_identity);
+    Some(settings)
+  }
+}
+
+/// Name of a profile: letters, digits, `_` and `-`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileName {
+  name: [u8; PROFILE_NAME_MAX],
+  len: u8,
+}
+
+impl ProfileName {
+  pub fn new(name: &[u8]) -> Option<Self> {
+    if name.is_empty()
+      || name.len() > PROFILE_NAME_MAX
+      || !name
+        .iter()
+        .all(|&byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-')
+    {
+      return None;
+    }
+    let mut profile = Self {
+      name: [0; PROFILE_NAME_MAX],
+      len: name.len() as u8,
+    };
+    profile.name[..name.len()].copy_from_slice(name);
+    Some(profile)
+  }
+
+  pub fn as_str(&self) -> &str {
+    // Only ASCII is accepted.
+    core::str::from_utf8(&self.name[..self.len as usize]).unwrap_or("")
+  }
+}
+
+impl defmt::Format for ProfileName {
+  fn format(&self, f: defmt::Formatter) {
+    defmt::write!(f, "{=str}", self.as_str());
+  }
+}
+
+/// Why a profile could not be saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ProfileError {
+  /// Every slot holds another profile.
+  Full,
+  /// The record does not fit a slot.
+  TooLong,
+  /// Writing the flash failed.
+  Flash,
+}
+
+/// Names of the stored profiles by slot.
+pub fn profiles() -> [Option<ProfileName>; PROFILES] {
+  let page = stored_page();
+  core::array::from_fn(|slot| {
+    let profile = &page[slot_range(slot)];
+    let len = (profile[0] as usize).min(PROFILE_NAME_MAX);
+    // A slot whose record is damaged counts as free.
+    Settings::decode(&profile[PROFILE_HEADER_LEN..])?;
+    ProfileName::new(&profile[1..1 + len])
+  })
+}
+
+/// Slot of the profile called `name`.
+pub fn find_profile(name: &ProfileName) -> Option<usize> {
+  profiles().iter().position(|stored| stored.as_ref() == Some(name))
+}
+
+/// Forget the profile in `slot`.
+pub fn delete_profile(slot: usize, flash: &mut flash::Parts) -> flash::Result<()> {
+  rewrite_page(flash, |page| page[slot_range(slot)].fill(0xFF))
+}
+
+/// Names of the settings that differ, for the summary on screen.
+pub type Changes = heapless::Vec<&'static str, 32>;
+
+/// Log every setting that differs between `old` and `new`, old -> new, and
+/// return their names.  Keys are logged as changed without their values;
+/// the counter reservations are bookkeeping and left out.
+pub fn diff(old: &Settings, new: &Settings) -> Changes {
+  let mut changes = Changes::new();
+  macro_rules! compare {
+    (secret $($field:ident).+) => {
+      if old.$($field).+ != new.$($field).+ {
+        defmt::info!("[settings] {=str}: changed", stringify!($($field).+));
+        let _ = changes.push(stringify!($($field).+));
+      }
+    };
+    ($($field:ident).+) => {
+      if old.$($field).+ != new.$($field).+ {
+        defmt::info!(
+          "[settings] {=str}: {} -> {}",
+          stringify!($($field).+),
+          old.$($field).+,
+          new.$($field).+
+        );
+        let _ = changes.push(stringify!($($field).+));
+      }
+    };
+  }
+  compare!(node_address);
+  compare!(peer_address);
+  compare!(addressing);
+  compare!(secret link_key);
+  compare!(security);
+  compare!(radio.frequency_hz);
+  compare!(radio.power_dbm);
+  compare!(radio.sf);
+  compare!(radio.bandwidth);
+  compare!(radio.cr);
+  compare!(adr);
+  compare!(screen);
+  compare!(cw_id);
+  compare!(beacon);
+  compare!(repeater);
+  compare!(hop_limit);
+  compare!(tdma);
+  compare!(secret lorawan);
+  compare!(secret lorawan_otaa);
+  compare!(data_port);
+  compare!(modbus);
+  compare!(sensor);
+  compare!(battery);
+  compare!(calibration);
+  compare!(switch_guard);
+  compare!(rx_meta);
+  compare!(afc);
+  compare!(trim);
+  compare!(crc_pass);
+  compare!(profile);
+  compare!(airtime);
+  compare!(csma);
+  compare!(flash_log);
+  compare!(log_levels);
+  compare!(usb_identity);
+  changes
+}
+
+/// Copy the settings page, profiles included, to the external flash.
+pub fn back_up(storage: &mut dyn Storage) -> Result<(), spi_flash::Error> {
+  spi_flash::SETTINGS.erase_sector(storage, 0)?;
+  spi_flash::SETTINGS.program(storage, 0, stored_page())
+}
+
+/// Restore the settings page from the external flash when the page holds
+/// no intact record and the copy does.  Returns whether it did.
+pub fn restore(storage: &mut dyn Storage, flash: &mut flash::Parts) -> bool {
+  if Settings::decode(&stored_page()[..RECORD_MAX]).is_some() {
+    return false;
+  }
+  let mut copy = [0xFFu8; PAGE_LEN];
+  if spi_flash::SETTINGS.read(storage, 0, &mut copy).is_err()
+    || Settings::decode(&copy[..RECORD_MAX]).is_none()
+  {
+    return false;
+  }
+  defmt::warn!("[settings] page lost, restoring the external copy");
+  rewrite_page(flash, |page| page.copy_from_slice(&copy)).is_ok()
+}
+
+fn slot_range(slot: usize) -> core::ops::Range<usize> {
+  let start = RECORD_MAX + slot * SLOT_LEN;
+  start..start + SLOT_LEN
+}
+
+fn stored_page() -> &'static [u8] {
+  unsafe { core::slice::from_raw_parts(PAGE_ADDRESS as *const u8, PAGE_LEN) }
+}
+
+/// Erase the page and program it again as changed by `edit`.
+fn rewrite_page(
+  flash: &mut flash::Parts,
+  edit: impl FnOnce(&mut [u8; PAGE_LEN]),
+) -> flash::Result<()> {
+  let mut page = [0xFFu8; PAGE_LEN];
+  page.copy_from_slice(stored_page());
+  edit(&mut page);
+  // Erased flash reads 0xFF; only the programmed half-words are written.
+  let end = page.iter().rposition(|&byte| byte != 0xFF).map_or(0, |last| (last + 2) & !1);
+
+  let mut writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+  writer.erase(PAGE_OFFSET, PAGE_LEN)?;
+  writer.write(PAGE_OFFSET, &page[..end])?;
+  Ok(())
+}
+
+/// Fletcher-16 over a version 1 record.
+fn fletcher16(data: &[u8]) -> u16 {
+  let (mut a, mut b) = (0u16, 0u16);
+  for &byte in data {
+    a = (a + byte as u16) % 255;
+    b = (b + a) % 255;
+  }
+  (b << 8) | a
+}
+
+/// Little-endian field writer.
+struct Writer<'a> {
+  buf: &'a mut [u8],
+  len: usize,
+}
+
+impl<'a> Writer<'a> {
+  fn new(buf: &'a mut [u8]) -> Self {
+    Self { buf, len: 0 }
+  }
+
+  fn bytes(&mut self, data: &[u8]) -> Option<()> {
+    let field = self.buf.get_mut(self.len..self.len + data.len())?;
+    field.copy_from_slice(data);
+    self.len += data.len();
+    Some(())
+  }
+
+  fn u8(&mut self, value: u8) -> Option<()> {
+    self.bytes(&[value])
+  }
+
+  fn u16(&mut self, value: u16) -> Option<()> {
+    self.bytes(&value.to_le_bytes())
+  }
+
+  fn u32(&mut self, value: u32) -> Option<()> {
+    self.bytes(&value.to_le_bytes())
+  }
+}
+
+/// Little-endian field reader.
+struct Reader<'a> {
+  buf: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn new(buf: &'a [u8]) -> Self {
+    Self { buf, pos: 0 }
+  }
+
+  fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+    let field = self.buf.get(self.pos..self.pos + N)?;
+    self.pos += N;
+    field.try_into().ok()
+  }
+
+  fn u8(&mut self) -> Option<u8> {
+    self.bytes::<1>().map(|b| b[0])
+  }
+
+  fn bool(&mut self) -> Option<bool> {
+    self.u8().map(|b| b != 0)
+  }
+
+  fn u16(&mut self) -> Option<u16> {
+    self.bytes().map(u16::from_le_bytes)
+  }
+
+  fn u32(&mut self) -> Option<u32> {
+    self.bytes().map(u32::from_le_bytes)
+  }
+
+  /// A field of `len` bytes.
+  fn slice(&mut self, len: usize) -> Option<&'a [u8]> {
+    let field = self.buf.get(self.pos..self.pos + len)?;
+    self.pos += len;
+    Some(field)
+  }
+
+  fn screen(&mut self) -> Option<ScreenPower> {
+    Some(ScreenPower {
+      contrast: self.u8()?,
+      dim_after_s: self.u16()?,
+      off_after_s: self.u16()?,
+    })
+  }
+
+  fn cw_id(&mut self) -> Option<Beacon> {
+    let interval_s = self.u16()?;
+    let len = self.u8()? as usize;
+    let callsign = self.bytes::<{ cw::CALLSIGN_MAX }>()?;
+    Beacon::new(callsign.get(..len)?, interval_s)
+  }
+
+  fn beacon(&mut self) -> Option<beacon::Config> {
+    Some(beacon::Config {
+      interval_s: self.u16()?,
+      fields: self.u8()?,
+    })
+  }
+
+  fn sensor(&mut self) -> Option<sensor::Config> {
+    sensor::Config::new(self.u16()?, self.u8()?)
+  }
+
+  fn battery(&mut self) -> Option<battery::Config> {
+    battery::Config::new(self.u16()?, self.u16()?, self.u16()?)
+  }
+
+  fn trim(&mut self) -> Option<trim::Config> {
+    trim::Config::new(self.u16()? as i16, self.u8()? as i8)
+  }
+
+  fn csma(&mut self) -> Option<csma::Config> {
+    csma::Config::new(self.u16()?, self.u16()?)
+  }
+
+  fn switch_guard(&mut self) -> Option<SwitchGuard> {
+    SwitchGuard::new(self.u16()?, self.u16()?)
+  }
+
+  fn usb_identity(&mut self) -> Option<usb::Identity> {
+    let vid = self.u16()?;
+    let pid = self.u16()?;
+    let len = self.u8()? as usize;
+    let manufacturer = self.slice(len)?;
+    let len = self.u8()? as usize;
+    let product = self.slice(len)?;
+    usb::Identity::new(vid, pid, manufacturer, product)
+  }
+
+  fn data_port(&mut self) -> Option<uart::Config> {
+    let source = match self.u8()? {
+      0 => uart::Source::Usb,
+      1 => uart::Source::Uart,
+      2 if cfg!(feature = "usb-hid") => uart::Source::Hid,
+      _ => return None,
+    };
+    let baud = self.u32()?;
+    uart::Config::valid_baud(baud).then_some(uart::Config { source, baud })
+  }
+}
@@ -0,0 +1,468 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/sim.rs - 仿真射频后端
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Simulated radio backend.
+//!
+//! [`SimRadio`] implements [`Radio`] over a [`Medium`], the air shared by
+//! up to `N` simulated nodes, so protocol code (acknowledgements,
+//! fragmentation, the mesh) can be exercised on a board without radio
+//! modules.  The medium models:
+//!
+//! * airtime: a frame is on the air for [`RadioParams::airtime_us`] and
+//!   arrives, raising the node's interrupt line, when it ends;
+//! * RSSI: the sender's power less the path loss between the two nodes,
+//!   SNR against a fixed noise floor.  Below the demodulation floor of the
+//!   spreading factor ([`radio::required_snr_db`]) a frame is not heard,
+//!   within [`MARGINAL_DB`] of it the frame arrives with a CRC error;
+//! * loss: each delivery is dropped with a set probability, from a
+//!   seeded generator so runs repeat;
+//! * collisions and half duplex: a frame is lost when another frame on the
+//!   channel overlapping it is heard less than [`CAPTURE_DB`] weaker, or
+//!   when the receiver transmitted meanwhile.
+//!
+//! Only nodes on the same frequency, spreading factor, bandwidth and sync
+//! word hear each other.  Time is the medium's own and only moves with
+//! [`Medium::advance`]; the firmware time base is not involved.
+//!
+//! The model is plain `core` code with no MCU access.  Besides the
+//! on-target tests, `host-tests/` builds it for the host with the parts of
+//! [`crate::radio`] it uses, and tests it there.
+
+use core::cell::RefCell;
+
+use heapless::Vec;
+
+use crate::radio::{
+  self, AirProfile, Capture, PacketStatus, Radio, RadioParams, RxError, RxProgress,
+};
+
+/// Longest LoRa frame.
+pub const FRAME_MAX: usize = 255;
+/// Frames on the air, or overlapping one that is, at a time.
+const AIR_MAX: usize = 8;
+/// A frame this much above the demodulation floor or less arrives with a
+/// CRC error.
+pub const MARGINAL_DB: i16 = 3;
+/// An overlapping frame this much weaker or more does not disturb a frame.
+pub const CAPTURE_DB: i16 = 6;
+
+/// What a node is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+enum Mode {
+  Standby,
+  Rx,
+  Tx,
+}
+
+/// A frame arrived at a node.
+struct Arrival {
+  frame: Vec<u8, FRAME_MAX>,
+  status: PacketStatus,
+  crc_ok: bool,
+}
+
+/// One node's radio.
+struct Node {
+  params: RadioParams,
+  profile: AirProfile,
+  mode: Mode,
+  /// The interrupt line.
+  irq: bool,
+  /// End of the node's last transmission, for half duplex.
+  tx_end_us: u64,
+  arrival: Option<Arrival>,
+}
+
+/// A frame on the air.
+struct Flight {
+  from: usize,
+  params: RadioParams,
+  profile: AirProfile,
+  power_dbm: i8,
+  start_us: u64,
+  end_us: u64,
+  frame: Vec<u8, FRAME_MAX>,
+  delivered: bool,
+}
+
+impl Flight {
+  fn overlaps(&self, other: &Flight) -> bool {
+    self.start_us < other.end_us && other.start_us < self.end_us
+  }
+}
+
+/// The air between `N` simulated nodes.
+pub struct Medium<const N: usize> {
+  now_us: u64,
+  /// Path loss between each pair of nodes in dB.
+  path_loss_db: [[i16; N]; N],
+  noise_dbm: i16,
+  /// Probability of dropping a delivery, in ‰.
+  loss_permille: u16,
+  seed: u32,
+  nodes: [Node; N],
+  air: Vec<Flight, AIR_MAX>,
+}
+
+impl<const N: usize> Medium<N> {
+  /// Nodes `path_loss_db` apart from each other over a `noise_dbm` floor,
+  /// all on the default parameters and in standby.  `seed` starts the loss
+  /// and random-word generator and must not be zero.
+  pub fn new(path_loss_db: i16, noise_dbm: i16, seed: u32) -> Self {
+    Self {
+      now_us: 0,
+      path_loss_db: [[path_loss_db; N]; N],
+      noise_dbm,
+      loss_permille: 0,
+      seed,
+      nodes: core::array::from_fn(|_| Node {
+        params: RadioParams::default(),
+        profile: AirProfile::Link,
+        mode: Mode::Standby,
+        irq: false,
+        tx_end_us: 0,
+        arrival: None,
+      }),
+      air: Vec::new(),
+    }
+  }
+
+  /// Set the path loss between nodes `a` and `b`, both ways.
+  pub fn set_path_loss(&mut self, a: usize, b: usize, loss_db: i16) {
+    self.path_loss_db[a][b] = loss_db;
+    self.path_loss_db[b][a] = loss_db;
+  }
+
+  /// Drop each delivery with probability `loss_permille` ‰.
+  pub fn set_loss(&mut self, loss_permille: u16) {
+    self.loss_permille = loss_permille.min(1_000);
+  }
+
+  /// Medium time in microseconds.
+  pub fn now_us(&self) -> u64 {
+    self.now_us
+  }
+
+  /// Let `us` microseconds pass, ending transmissions and delivering their
+  /// frames on the way.
+  pub fn advance(&mut self, us: u64) {
+    let until = self.now_us + us;
+    while let Some(index) = self.next_end(until) {
+      self.now_us = self.air[index].end_us;
+      self.land(index);
+      self.prune();
+    }
+    self.now_us = until;
+  }
+
+  /// Radio handle of node `node` on `medium`.
+  pub fn radio(medium: &RefCell<Self>, node: usize) -> SimRadio<'_, N> {
+    SimRadio { medium, node }
+  }
+
+  /// The frame in flight that ends first, no later than `until`.
+  fn next_end(&self, until: u64) -> Option<usize> {
+    self
+      .air
+      .iter()
+      .enumerate()
+      .filter(|(_, flight)| !flight.delivered && flight.end_us <= until)
+      .min_by_key(|(_, flight)| flight.end_us)
+      .map(|(index, _)| index)
+  }
+
+  /// End the transmission of flight `index` and deliver it.
+  fn land(&mut self, index: usize) {
+    self.air[index].delivered = true;
+    let flight = &self.air[index];
+    let sender = &mut self.nodes[flight.from];
+    sender.mode = Mode::Standby;
+    sender.irq = true;
+    sender.tx_end_us = flight.end_us;
+
+    for to in 0..N {
+      let flight = &self.air[index];
+      if to == flight.from || !self.hears(to, flight) {
+        continue;
+      }
+      let rssi_dbm = self.rssi_dbm(flight, to);
+      let snr_db = rssi_dbm - self.noise_dbm;
+      let floor_db = radio::required_snr_db(flight.params.sf) as i16;
+      let collided = self.air.iter().enumerate().any(|(other, overlapping)| {
+        other != index
+          && overlapping.from != to
+          && flight.overlaps(overlapping)
+          && self.hears(to, overlapping)
+          && self.rssi_dbm(overlapping, to) > rssi_dbm - CAPTURE_DB
+      });
+      if snr_db < floor_db || collided || self.node_tx_during(to, flight) || self.dropped() {
+        continue;
+      }
+      let flight = &self.air[index];
+      let arrival = Arrival {
+        frame: flight.frame.clone(),
+        status: PacketStatus {
+          rssi_dbm,
+          snr_db: snr_db.clamp(i8::MIN as i16, i8::MAX as i16) as i8,
+        },
+        crc_ok: snr_db >= floor_db + MARGINAL_DB,
+      };
+      let node = &mut self.nodes[to];
+      node.arrival = Some(arrival);
+      node.irq = true;
+    }
+  }
+
+  /// Whether node `to` listens on the channel of `flight`.
+  fn hears(&self, to: usize, flight: &Flight) -> bool {
+    let node = &self.nodes[to];
+    node.mode == Mode::Rx
+      && same_channel(&node.params, node.profile, &flight.params, flight.profile)
+  }
+
+  fn rssi_dbm(&self, flight: &Flight, to: usize) -> i16 {
+    flight.power_dbm as i16 - self.path_loss_db[flight.from][to]
+  }
+
+  /// Whether node `to` transmitted while `flight` was on the air.
+  fn node_tx_during(&self, to: usize, flight: &Flight) -> bool {
+    self.nodes[to].tx_end_us > flight.start_us
+      || self
+        .air
+        .iter()
+        .any(|other| other.from == to && other.overlaps(flight))
+  }
+
+  /// Draw whether a delivery is lost.
+  fn dropped(&mut self) -> bool {
+    self.loss_permille > 0 && self.next_random() % 1_000 < self.loss_permille as u32
+  }
+
+  /// Forget delivered frames nothing on the air overlaps any more.
+  fn prune(&mut self) {
+    let earliest = self
+      .air
+      .iter()
+      .filter(|flight| !flight.delivered)
+      .map(|flight| flight.start_us)
+      .min()
+      .unwrap_or(u64::MAX);
+    self
+      .air
+      .retain(|flight| !flight.delivered || flight.end_us > earliest);
+  }
+
+  /// Xorshift32.
+  fn next_random(&mut self) -> u32 {
+    let mut x = self.seed;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    self.seed = x;
+    x
+  }
+}
+
+/// Whether two configurations hear each other.
+fn same_channel(
+  a: &RadioParams,
+  a_profile: AirProfile,
+  b: &RadioParams,
+  b_profile: AirProfile,
+) -> bool {
+  let link = |profile| matches!(profile, AirProfile::Link);
+  a.frequency_hz == b.frequency_hz
+    && a.sf == b.sf
+    && a.bandwidth == b.bandwidth
+    && link(a_profile) == link(b_profile)
+}
+
+/// A node of a [`Medium`].
+pub struct SimRadio<'a, const N: usize> {
+  medium: &'a RefCell<Medium<N>>,
+  node: usize,
+}
+
+impl<const N: usize> SimRadio<'_, N> {
+  /// The interrupt line: TxDone or a frame arrived.
+  pub fn irq(&self) -> bool {
+    self.medium.borrow().nodes[self.node].irq
+  }
+
+  fn set_mode(&self, mode: Mode) {
+    let mut medium = self.medium.borrow_mut();
+    let node = &mut medium.nodes[self.node];
+    node.mode = mode;
+    node.irq = false;
+  }
+
+  /// Whether a frame on the node's channel is on the air.
+  fn channel_busy(&self) -> bool {
+    let medium = self.medium.borrow();
+    let node = &medium.nodes[self.node];
+    medium.air.iter().any(|flight| {
+      !flight.delivered
+        && flight.from != self.node
+        && same_channel(&node.params, node.profile, &flight.params, flight.profile)
+    })
+  }
+}
+
+impl<const N: usize> Radio for SimRadio<'_, N> {
+  fn apply_profile(&mut self, params: &RadioParams, profile: AirProfile) -> bool {
+    if !params.is_valid() {
+      return false;
+    }
+    {
+      let mut medium = self.medium.borrow_mut();
+      let node = &mut medium.nodes[self.node];
+      node.params = *params;
+      node.profile = profile;
+      node.arrival = None;
+    }
+    self.set_mode(Mode::Rx);
+    true
+  }
+
+  fn start_rx(&mut self) -> bool {
+    self.set_mode(Mode::Rx);
+    true
+  }
+
+  fn send(&mut self, frame: &[u8]) -> bool {
+    let mut medium = self.medium.borrow_mut();
+    let now_us = medium.now_us;
+    let node = &medium.nodes[self.node];
+    let Ok(frame) = Vec::from_slice(frame) else {
+      return false;
+    };
+    if node.mode == Mode::Tx {
+      return false;
+    }
+    let flight = Flight {
+      from: self.node,
+      params: node.params,
+      profile: node.profile,
+      power_dbm: node.params.tx_power_dbm(),
+      start_us: now_us,
+      end_us: now_us + node.params.airtime_us(frame.len()) as u64,
+      frame,
+      delivered: false,
+    };
+    if medium.air.push(flight).is_err() {
+      return false;
+    }
+    let node = &mut medium.nodes[self.node];
+    node.mode = Mode::Tx;
+    node.irq = false;
+    true
+  }
+
+  fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, RxError> {
+    match self.capture(buf)? {
+      Some(capture) if capture.crc_ok => Ok(Some(capture.len)),
+      Some(_) => Err(RxError),
+      None => Ok(None),
+    }
+  }
+
+  fn capture(&mut self, buf: &mut [u8]) -> Result<Option<Capture>, RxError> {
+    let arrival = {
+      let mut medium = self.medium.borrow_mut();
+      let node = &mut medium.nodes[self.node];
+      node.irq = false;
+      node.arrival.take()
+    };
+    let Some(arrival) = arrival else {
+      return Ok(None);
+    };
+    let len = arrival.frame.len().min(buf.len());
+    buf[..len].copy_from_slice(&arrival.frame[..len]);
+    radio::set_packet_status(arrival.status);
+    Ok(Some(Capture {
+      len,
+      crc_ok: arrival.crc_ok,
+    }))
+  }
+
+  fn listen_at(&mut self, frequency_hz: u32) -> bool {
+    self.medium.borrow_mut().nodes[self.node]
+      .params
+      .frequency_hz = frequency_hz;
+    self.set_mode(Mode::Rx);
+    true
+  }
+
+  fn rssi_inst(&mut self) -> Option<i16> {
+    let medium = self.medium.borrow();
+    let node = &medium.nodes[self.node];
+    let strongest = medium
+      .air
+      .iter()
+      .filter(|flight| {
+        !flight.delivered
+          && flight.from != self.node
+          && flight.params.frequency_hz == node.params.frequency_hz
+      })
+      .map(|flight| medium.rssi_dbm(flight, self.node))
+      .max();
+    Some(strongest.map_or(medium.noise_dbm, |rssi| rssi.max(medium.noise_dbm)))
+  }
+
+  fn freq_error_hz(&mut self) -> Option<i32> {
+    Some(0)
+  }
+
+  fn random_word(&mut self) -> Option<u32> {
+    Some(self.medium.borrow_mut().next_random())
+  }
+
+  fn rx_progress(&mut self) -> Option<RxProgress> {
+    self.channel_busy().then_some(RxProgress::Header)
+  }
+
+  fn tune(&mut self, frequency_hz: u32) -> bool {
+    self.medium.borrow_mut().nodes[self.node]
+      .params
+      .frequency_hz = frequency_hz;
+    self.set_mode(Mode::Standby);
+    true
+  }
+
+  fn carrier(&mut self, _on: bool) -> bool {
+    false
+  }
+
+  fn channel_active(&mut self) -> Option<bool> {
+    let busy = self.channel_busy();
+    self.set_mode(Mode::Standby);
+    Some(busy)
+  }
+
+  fn take_hang(&mut self) -> bool {
+    false
+  }
+
+  fn device_errors(&mut self) -> Option<u16> {
+    Some(0)
+  }
+
+  fn hard_reset(&mut self) {
+    self.set_mode(Mode::Standby);
+  }
+
+  fn sleep(&mut self) -> bool {
+    self.set_mode(Mode::Standby);
+    true
+  }
+}
@@ -0,0 +1,78 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/sniffer.rs - 抓包模式与 pcap 记录
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Packet capture.
+//!
+//! `AT+SNIFF=1` turns the bridge into a LoRa protocol analyser: every
+//! frame the radio hears, including frames that failed their CRC, goes to
+//! the data port whole, link header and MIC included, and is not handled
+//! any further.  The output is a pcap stream; the data port can be saved
+//! straight to a `.pcap` file:
+//!
+//! * `AT+SNIFF=1` first writes the 24-byte pcap file header (microsecond
+//!   timestamps, link type [`LINKTYPE`], `LINKTYPE_USER0`), so a capture
+//!   opened late is restarted with another `AT+SNIFF=1`;
+//! * then each frame is a pcap record: the 16-byte record header, the
+//!   16-byte metadata header of [`crate::rxmeta`] (flag
+//!   [`FLAG_CRC_ERROR`](crate::rxmeta::FLAG_CRC_ERROR) marks a bad CRC)
+//!   and the frame.
+//!
+//! Records carry network time ([`crate::timesync`]) when the bridge has
+//! it, otherwise the time since boot.  All fields are little endian.
+//! `AT+SNIFF=0`, the default, returns to bridging; the mode is not
+//! persisted.
+
+use crate::packetizer::MAX_PAYLOAD;
+use crate::rxmeta::{self, Meta};
+
+/// `LINKTYPE_USER0`; Wireshark can be told to dissect it as LoRa.
+pub const LINKTYPE: u32 = 147;
+/// Longest record body: the metadata header and a full frame.
+pub const SNAPLEN: usize = rxmeta::HEADER_LEN + MAX_PAYLOAD;
+pub const FILE_HEADER_LEN: usize = 24;
+pub const RECORD_HEADER_LEN: usize = 16;
+
+/// pcap magic for microsecond timestamps.
+const MAGIC: u32 = 0xA1B2_C3D4;
+const VERSION_MAJOR: u16 = 2;
+const VERSION_MINOR: u16 = 4;
+
+/// pcap file header.
+pub fn file_header() -> [u8; FILE_HEADER_LEN] {
+  let mut header = [0u8; FILE_HEADER_LEN];
+  header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+  header[4..6].copy_from_slice(&VERSION_MAJOR.to_le_bytes());
+  header[6..8].copy_from_slice(&VERSION_MINOR.to_le_bytes());
+  // Time zone offset and timestamp accuracy stay zero.
+  header[16..20].copy_from_slice(&(SNAPLEN as u32).to_le_bytes());
+  header[20..24].copy_from_slice(&LINKTYPE.to_le_bytes());
+  header
+}
+
+/// pcap record header and metadata header of a frame described by `meta`,
+/// captured at `network_ms` milliseconds since the Unix epoch if known.
+pub fn record_header(
+  meta: &Meta,
+  network_ms: Option<u64>,
+) -> [u8; RECORD_HEADER_LEN + rxmeta::HEADER_LEN] {
+  let time_ms = network_ms.unwrap_or(meta.received_ms as u64);
+  let body_len = (rxmeta::HEADER_LEN + meta.len) as u32;
+
+  let mut header = [0u8; RECORD_HEADER_LEN + rxmeta::HEADER_LEN];
+  header[0..4].copy_from_slice(&((time_ms / 1_000) as u32).to_le_bytes());
+  header[4..8].copy_from_slice(&((time_ms % 1_000) as u32 * 1_000).to_le_bytes());
+  header[8..12].copy_from_slice(&body_len.to_le_bytes());
+  header[12..16].copy_from_slice(&body_len.to_le_bytes());
+  header[RECORD_HEADER_LEN..].copy_from_slice(&meta.encode());
+  header
+}
@@ -0,0 +1,145 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/spi_bus.rs - 共享 SPI 总线
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! SPI1 and SPI2 shared by the devices on them.
+//!
+//! Like I2C2 in [`crate::i2c_bus`], SPI2 lives in a `RefCell`.  Every
+//! device on it is a [`Device`] (embedded-hal-bus `RefCellDevice`) with its
+//! own chip select, which borrows the bus for one transaction at a time;
+//! only the main loop touches SPI2, so a borrow never overlaps another
+//! one.
+//!
+//! SPI1 lives in a critical-section `Mutex` instead and its devices are
+//! [`SharedDevice`]s (`CriticalSectionDevice`): the radio on it moves
+//! between the main loop and interrupt handlers (see
+//! [`crate::radio_handle`]), so a transaction runs with interrupts
+//! disabled, about 2 ms for a full 255-byte buffer at 1 MHz.  The SX126x
+//! waits for BUSY before it starts a transaction (see [`crate::lora`]), so
+//! a busy radio never holds SPI1 meanwhile.
+//!
+//! SPI1 carries the radio, SPI2 the log storage ([`crate::spi_flash`] or
+//! [`crate::sd_log`]).  A bus runs at the clock of its slowest device; a
+//! further device needs a chip select in the board pin map and a
+//! [`device`] on the bus.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use critical_section::Mutex;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_bus::spi::{CriticalSectionDevice, DeviceError, RefCellDevice};
+
+use crate::board::{Miso, Mosi, Sck};
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+use crate::board::{Spi2Miso, Spi2Mosi, Spi2Sck};
+use crate::hal::pac::SPI1;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+use crate::hal::pac::SPI2;
+use crate::hal::prelude::*;
+use crate::hal::rcc::Rcc;
+use crate::hal::spi::{self, Mode, Phase, Polarity, Spi};
+use crate::timer;
+
+pub type Bus1 = Spi<SPI1, u8>;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+pub type Bus2 = Spi<SPI2, u8>;
+
+/// A device on `B` selected by `CS`.
+pub type Device<B, CS> = RefCellDevice<'static, B, CS, Wait>;
+/// A device on `B` selected by `CS` that may be used from an interrupt
+/// handler; each transaction runs in a critical section.
+pub type SharedDevice<B, CS> = CriticalSectionDevice<'static, B, CS, Wait>;
+
+/// Error of a device on either bus; the chip selects cannot fail.
+pub type Error = DeviceError<spi::Error, Infallible>;
+
+/// Mode 0, which the radios, the flash and SD cards all speak.
+const MODE: Mode = Mode {
+  polarity: Polarity::IdleLow,
+  phase: Phase::CaptureOnFirstTransition,
+};
+/// SPI1 clock, for the radio.
+const CLOCK1_HZ: u32 = 1_000_000;
+/// SPI2 clock: an SD card accepts no more from power-up.
+#[cfg(feature = "sd-card")]
+const CLOCK2_HZ: u32 = crate::sd_log::CLOCK_KHZ * 1_000;
+#[cfg(all(feature = "spi-flash", not(feature = "sd-card")))]
+const CLOCK2_HZ: u32 = crate::spi_flash::CLOCK_HZ;
+
+/// Busy-wait on the cycle counter, for delays within a transaction.
+pub struct Wait;
+
+impl DelayNs for Wait {
+  fn delay_ns(&mut self, ns: u32) {
+    timer::wait_until_cycles(timer::after_us(timer::now_cycles(), ns.div_ceil(1_000)));
+  }
+}
+
+/// Take over SPI1.
+///
+/// Must be called exactly once.
+pub fn init1(spi1: SPI1, pins: (Sck, Miso, Mosi), rcc: &mut Rcc) -> &'static Mutex<RefCell<Bus1>> {
+  let (sck, miso, mosi) = pins;
+  let spi = Spi::new(
+    spi1,
+    (Some(sck), Some(miso), Some(mosi)),
+    MODE,
+    CLOCK1_HZ.Hz(),
+    rcc,
+  );
+  cortex_m::singleton!(: Mutex<RefCell<Bus1>> = Mutex::new(RefCell::new(spi))).unwrap()
+}
+
+/// Take over SPI2.
+///
+/// Must be called exactly once.
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+pub fn init2(
+  spi2: SPI2,
+  pins: (Spi2Sck, Spi2Miso, Spi2Mosi),
+  rcc: &mut Rcc,
+) -> &'static RefCell<Bus2> {
+  let (sck, miso, mosi) = pins;
+  let spi = Spi::new(
+    spi2,
+    (Some(sck), Some(miso), Some(mosi)),
+    MODE,
+    CLOCK2_HZ.Hz(),
+    rcc,
+  );
+  cortex_m::singleton!(: RefCell<Bus2> = RefCell::new(spi)).unwrap()
+}
+
+/// The device on `bus` selected by `cs`, deselected until first used.
+pub fn device<B, CS>(bus: &'static RefCell<B>, cs: CS) -> Device<B, CS>
+where
+  CS: OutputPin<Error = Infallible>,
+{
+  match RefCellDevice::new(bus, cs, Wait) {
+    Ok(device) => device,
+    Err(never) => match never {},
+  }
+}
+
+/// The device on the `Mutex`-held `bus` selected by `cs`, deselected
+/// until first used.
+pub fn shared_device<B, CS>(bus: &'static Mutex<RefCell<B>>, cs: CS) -> SharedDevice<B, CS>
+where
+  CS: OutputPin<Error = Infallible>,
+{
+  match CriticalSectionDevice::new(bus, cs, Wait) {
+    Ok(device) => device,
+    Err(never) => match never {},
+  }
+}
@@ -0,0 +1,268 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/spi_flash.rs - 外接 SPI NOR 闪存（W25Qxx）
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! External SPI NOR flash (W25Qxx).
+//!
+//! Built with the `spi-flash` feature, a W25Q-series chip (or any NOR flash
+//! with the same command set: `0x9F` JEDEC ID, `0x03` read, `0x02` page
+//! program, `0x20` 4 KiB sector erase) sits on SPI2: SCK PB13, MISO PB14,
+//! MOSI PB15 and chip select PB12.  SPI1 stays with the radio, and PB12 and
+//! PB13 are TXEN and RXEN on Blue-High v1, so the flash needs a board that
+//! wires those elsewhere; `board-custom` moves them to PB6 and PB7 when
+//! `spi-flash` is enabled.
+//!
+//! The chip is raw block storage, split into fixed partitions:
+//!
+//! | partition    | offset  | size         | used by                        |
+//! |--------------|---------|--------------|--------------------------------|
+//! | [`OTA`]      | 0       | 64 KiB       | staged firmware, [`crate::ota`] |
+//! | [`SETTINGS`] | 64 KiB  | 4 KiB        | settings backup, [`crate::settings`] |
+//! | [`log`]      | 68 KiB  | rest of chip | packet log, [`crate::flash_log`] |
+//!
+//! The partitions also show up as files on a read-only USB drive; see
+//! [`crate::msc`].
+//!
+//! Chips of 128 KiB up to 16 MiB (24-bit addresses) are used; a chip that
+//! does not answer its JEDEC ID, or a build without the feature, leaves the
+//! bridge as it was.
+
+#![cfg_attr(not(feature = "spi-flash"), allow(dead_code))]
+
+/// SPI2 clock with the flash alone on it.
+pub const CLOCK_HZ: u32 = 9_000_000;
+/// Smallest erasable unit.
+pub const SECTOR_LEN: u32 = 4096;
+/// Largest chip addressed with 24 bits.
+const CAPACITY_MAX: u32 = 16 * 1024 * 1024;
+/// Smallest chip holding the fixed partitions and some log.
+const CAPACITY_MIN: u32 = 128 * 1024;
+
+/// Staged firmware image.
+pub const OTA: Partition = Partition {
+  start: 0,
+  len: 64 * 1024,
+};
+/// Copy of the settings page.
+pub const SETTINGS: Partition = Partition {
+  start: OTA.start + OTA.len,
+  len: SECTOR_LEN,
+};
+
+/// The packet log: the rest of a chip of `capacity` bytes.
+pub const fn log(capacity: u32) -> Partition {
+  let start = SETTINGS.start + SETTINGS.len;
+  Partition {
+    start,
+    len: capacity - start,
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+  /// The SPI transfer failed.
+  Bus,
+  /// The access does not fit the chip or the partition.
+  Range,
+}
+
+/// Byte-addressed NOR storage: erased bytes read `0xFF` and programming
+/// only clears bits.
+pub trait Storage {
+  /// Size in bytes.
+  fn capacity(&self) -> u32;
+  fn read(&mut self, address: u32, buf: &mut [u8]) -> Result<(), Error>;
+  /// Erase the [`SECTOR_LEN`] sector holding `address`.
+  fn erase_sector(&mut self, address: u32) -> Result<(), Error>;
+  /// Program `data` at `address`, which must be erased.
+  fn program(&mut self, address: u32, data: &[u8]) -> Result<(), Error>;
+}
+
+/// A fixed range of the chip; offsets are relative to its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Partition {
+  pub start: u32,
+  pub len: u32,
+}
+
+impl Partition {
+  /// Sectors in the partition.
+  pub fn sectors(&self) -> u32 {
+    self.len / SECTOR_LEN
+  }
+
+  fn address(&self, offset: u32, len: usize) -> Result<u32, Error> {
+    match offset.checked_add(len as u32) {
+      Some(end) if end <= self.len => Ok(self.start + offset),
+      _ => Err(Error::Range),
+    }
+  }
+
+  pub fn read(&self, storage: &mut dyn Storage, offset: u32, buf: &mut [u8]) -> Result<(), Error> {
+    storage.read(self.address(offset, buf.len())?, buf)
+  }
+
+  pub fn erase_sector(&self, storage: &mut dyn Storage, offset: u32) -> Result<(), Error> {
+    storage.erase_sector(self.address(offset, 1)?)
+  }
+
+  pub fn program(&self, storage: &mut dyn Storage, offset: u32, data: &[u8]) -> Result<(), Error> {
+    storage.program(self.address(offset, data.len())?, data)
+  }
+}
+
+/// Stands in for the chip in a build without it, so the callers need no
+/// feature gates of their own.
+pub enum Absent {}
+
+impl Storage for Absent {
+  fn capacity(&self) -> u32 {
+    match *self {}
+  }
+
+  fn read(&mut self, _address: u32, _buf: &mut [u8]) -> Result<(), Error> {
+    match *self {}
+  }
+
+  fn erase_sector(&mut self, _address: u32) -> Result<(), Error> {
+    match *self {}
+  }
+
+  fn program(&mut self, _address: u32, _data: &[u8]) -> Result<(), Error> {
+    match *self {}
+  }
+}
+
+#[cfg(feature = "spi-flash")]
+pub use chip::{W25q, init};
+
+/// The chip on SPI2.
+#[cfg(feature = "spi-flash")]
+mod chip {
+  use embedded_hal::spi::{Operation, SpiDevice};
+
+  use super::{CAPACITY_MAX, CAPACITY_MIN, Error, SECTOR_LEN, Storage};
+  use crate::board::Spi2Cs;
+  use crate::spi_bus::{self, Bus2, Device};
+
+  const CMD_WRITE_ENABLE: u8 = 0x06;
+  const CMD_READ_STATUS: u8 = 0x05;
+  const CMD_READ: u8 = 0x03;
+  const CMD_PAGE_PROGRAM: u8 = 0x02;
+  const CMD_SECTOR_ERASE: u8 = 0x20;
+  const CMD_JEDEC_ID: u8 = 0x9F;
+  const CMD_RELEASE_POWER_DOWN: u8 = 0xAB;
+  const STATUS_BUSY: u8 = 1 << 0;
+  /// Bytes one page program may write, aligned.
+  const PAGE_LEN: u32 = 256;
+
+  pub struct W25q {
+    spi: Device<Bus2, Spi2Cs>,
+    capacity: u32,
+  }
+
+  /// Identify the chip on `spi`; `None` when it does not answer.
+  pub fn init(spi: Device<Bus2, Spi2Cs>) -> Option<W25q> {
+    let mut flash = W25q { spi, capacity: 0 };
+    flash.transaction(&[CMD_RELEASE_POWER_DOWN], &mut []).ok()?;
+    let mut id = [0u8; 3];
+    flash.transaction(&[CMD_JEDEC_ID], &mut id).ok()?;
+    let capacity = 1u32.checked_shl(id[2] as u32).unwrap_or(0);
+    if matches!(id[0], 0x00 | 0xFF) || capacity < CAPACITY_MIN {
+      defmt::warn!("[spi_flash] no chip, JEDEC ID {=[u8]:02X}", id);
+      return None;
+    }
+    flash.capacity = capacity.min(CAPACITY_MAX);
+    defmt::info!(
+      "[spi_flash] JEDEC ID {=[u8]:02X}, {} KiB",
+      id,
+      flash.capacity / 1024
+    );
+    Some(flash)
+  }
+
+  impl W25q {
+    /// Send `header`, then clock `data` in.
+    fn transaction(&mut self, header: &[u8], data: &mut [u8]) -> Result<(), spi_bus::Error> {
+      self
+        .spi
+        .transaction(&mut [Operation::Write(header), Operation::Read(data)])
+    }
+
+    /// Send `header`, then `data`.
+    fn write(&mut self, header: &[u8], data: &[u8]) -> Result<(), spi_bus::Error> {
+      self
+        .spi
+        .transaction(&mut [Operation::Write(header), Operation::Write(data)])
+    }
+
+    fn wait_idle(&mut self) -> Result<(), spi_bus::Error> {
+      let mut status = [STATUS_BUSY];
+      while status[0] & STATUS_BUSY != 0 {
+        self.transaction(&[CMD_READ_STATUS], &mut status)?;
+      }
+      Ok(())
+    }
+
+    fn command(address: u32, command: u8) -> [u8; 4] {
+      let [_, high, middle, low] = address.to_be_bytes();
+      [command, high, middle, low]
+    }
+
+    fn check(&self, address: u32, len: usize) -> Result<(), Error> {
+      match address.checked_add(len as u32) {
+        Some(end) if end <= self.capacity => Ok(()),
+        _ => Err(Error::Range),
+      }
+    }
+  }
+
+  impl Storage for W25q {
+    fn capacity(&self) -> u32 {
+      self.capacity
+    }
+
+    fn read(&mut self, address: u32, buf: &mut [u8]) -> Result<(), Error> {
+      self.check(address, buf.len())?;
+      self
+        .transaction(&Self::command(address, CMD_READ), buf)
+        .map_err(|_| Error::Bus)
+    }
+
+    fn erase_sector(&mut self, address: u32) -> Result<(), Error> {
+      self.check(address, 1)?;
+      let sector = address & !(SECTOR_LEN - 1);
+      self
+        .write(&[CMD_WRITE_ENABLE], &[])
+        .and_then(|()| self.write(&Self::command(sector, CMD_SECTOR_ERASE), &[]))
+        .and_then(|()| self.wait_idle())
+        .map_err(|_| Error::Bus)
+    }
+
+    fn program(&mut self, mut address: u32, mut data: &[u8]) -> Result<(), Error> {
+      self.check(address, data.len())?;
+      while !data.is_empty() {
+        // A page program wraps at the page end, so split there.
+        let room = (PAGE_LEN - address % PAGE_LEN) as usize;
+        let (page, rest) = data.split_at(room.min(data.len()));
+        self
+          .write(&[CMD_WRITE_ENABLE], &[])
+          .and_then(|()| self.write(&Self::command(address, CMD_PAGE_PROGRAM), page))
+          .and_then(|()| self.wait_idle())
+          .map_err(|_| Error::Bus)?;
+        address += page.len() as u32;
+        data = rest;
+      }
+      Ok(())
+    }
+  }
+}
@@ -0,0 +1,115 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/startup.rs - 分阶段启动与降级状态
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Staged boot.
+//!
+//! A missing OLED or a radio that does not answer must not brick the
+//! whole bridge: the USB ports alone still take AT commands and show what
+//! went wrong.  Boot runs through the [`Stage`]s in order and records
+//! whether each one came up instead of panicking; whatever failed is left
+//! out and the rest carries on.
+//!
+//! A degraded boot is logged, blinks the error pattern, shows a notice on
+//! the OLED if that works and is reported on the control port as
+//! `+BOOT:DEGRADED,<stage>,...`; `AT+BOOT?` repeats the report, or
+//! `+BOOT:OK`.  A failed radio is retried by [`crate::supervisor`].
+
+use core::fmt::Write;
+
+use heapless::String;
+
+/// A step of the boot sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Stage {
+  /// The firmware image matches its CRC32 seal.
+  Image,
+  /// The OLED panel answered its init sequence.
+  Display,
+  /// The radio took the persisted settings.
+  Radio,
+  /// The startup packet went out.
+  RadioTx,
+  /// The radio entered continuous RX.
+  RadioRx,
+}
+
+impl Stage {
+  const ALL: [Stage; 5] = [
+    Stage::Image,
+    Stage::Display,
+    Stage::Radio,
+    Stage::RadioTx,
+    Stage::RadioRx,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Stage::Image => "IMAGE",
+      Stage::Display => "DISPLAY",
+      Stage::Radio => "RADIO",
+      Stage::RadioTx => "RADIO TX",
+      Stage::RadioRx => "RADIO RX",
+    }
+  }
+
+  fn bit(self) -> u8 {
+    1 << self as u8
+  }
+}
+
+/// Outcome of the boot stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Status {
+  failed: u8,
+}
+
+impl Status {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Record the outcome of `stage`.  Returns `ok`.
+  pub fn record(&mut self, stage: Stage, ok: bool) -> bool {
+    if ok {
+      self.failed &= !stage.bit();
+    } else {
+      self.failed |= stage.bit();
+      defmt::error!("[startup] {} failed, continuing without it", stage);
+    }
+    ok
+  }
+
+  pub fn ok(&self, stage: Stage) -> bool {
+    self.failed & stage.bit() == 0
+  }
+
+  /// Whether any stage failed.
+  pub fn degraded(&self) -> bool {
+    self.failed != 0
+  }
+
+  /// `+BOOT:OK`, or `+BOOT:DEGRADED` and the failed stages.
+  pub fn report(&self) -> String<64> {
+    let mut line = String::new();
+    if !self.degraded() {
+      let _ = write!(line, "+BOOT:OK\r\n");
+      return line;
+    }
+    let _ = write!(line, "+BOOT:DEGRADED");
+    for stage in Stage::ALL.into_iter().filter(|&stage| !self.ok(stage)) {
+      let _ = write!(line, ",{}", stage.name());
+    }
+    let _ = write!(line, "\r\n");
+    line
+  }
+}
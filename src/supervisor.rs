@@ -0,0 +1,133 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/supervisor.rs - 射频故障监控与自动恢复
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Radio fault supervisor.
+//!
+//! A brown-out or an ESD hit can leave the radio hung or silently
+//! misconfigured.  Between transmissions the supervisor looks for:
+//!
+//! * a command the chip did not accept in time (SX126x BUSY timeout, see
+//!   [`crate::lora`]);
+//! * [`FAULT_LIMIT`] failed commands between two checks: RX or TX not
+//!   started, or no TxDone;
+//! * every [`CHECK_MS`], fault flags the chip reports, or no answer.
+//!
+//! Any of these resets the radio through NRST and applies the persisted
+//! settings again, so a transient fault does not need a power cycle.  The
+//! main loop reports each recovery.  A radio that stays dead is only
+//! retried at the next check.
+
+use crate::radio::{self, Radio, RadioParams};
+
+/// Failed commands that trigger a recovery.
+pub const FAULT_LIMIT: u8 = 3;
+/// Interval between device error checks.
+pub const CHECK_MS: u32 = 5_000;
+
+/// What triggered a recovery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Cause {
+  Hang,
+  Commands,
+  /// The chip reported these fault flags.
+  Device(u16),
+  NoAnswer,
+}
+
+impl Cause {
+  pub fn name(self) -> &'static str {
+    match self {
+      Cause::Hang => "BUSY",
+      Cause::Commands => "COMMANDS",
+      Cause::Device(_) => "DEVICE",
+      Cause::NoAnswer => "NO ANSWER",
+    }
+  }
+}
+
+pub struct Supervisor {
+  faults: u8,
+  last_check_ms: u32,
+  recoveries: u32,
+  /// The last recovery failed; wait for the next check.
+  failed: bool,
+}
+
+impl Supervisor {
+  pub fn new(now: u32) -> Self {
+    Self {
+      faults: 0,
+      last_check_ms: now,
+      recoveries: 0,
+      failed: false,
+    }
+  }
+
+  /// Look for a fault; returns its cause when the radio needs a recovery.
+  pub fn poll(&mut self, radio: &mut impl Radio, now: u32) -> Option<Cause> {
+    self.faults = self.faults.saturating_add(radio::take_faults());
+    let checking = now.wrapping_sub(self.last_check_ms) >= CHECK_MS;
+    if self.failed && !checking {
+      return None;
+    }
+    if radio.take_hang() {
+      return Some(Cause::Hang);
+    }
+    if self.faults >= FAULT_LIMIT {
+      return Some(Cause::Commands);
+    }
+    if !checking {
+      return None;
+    }
+    self.last_check_ms = now;
+    match radio.device_errors() {
+      None => Some(Cause::NoAnswer),
+      Some(0) => {
+        self.faults = 0;
+        None
+      }
+      Some(errors) => Some(Cause::Device(errors)),
+    }
+  }
+
+  /// Reset the radio and apply `params`.  Returns whether it came back.
+  pub fn recover(
+    &mut self,
+    radio: &mut impl Radio,
+    params: &RadioParams,
+    cause: Cause,
+    now: u32,
+  ) -> bool {
+    self.recoveries = self.recoveries.wrapping_add(1);
+    defmt::warn!("[supervisor] radio fault {}, resetting", cause);
+    radio.hard_reset();
+    let recovered = radio.apply(params);
+    // Whatever failed on the way down does not count against the radio.
+    radio::take_faults();
+    radio.take_hang();
+    self.faults = 0;
+    self.last_check_ms = now;
+    self.failed = !recovered;
+    if recovered {
+      defmt::info!("[supervisor] radio recovered");
+    } else {
+      defmt::error!("[supervisor] radio recovery failed");
+    }
+    recovered
+  }
+
+  /// Recoveries since boot.
+  pub fn recoveries(&self) -> u32 {
+    self.recoveries
+  }
+}
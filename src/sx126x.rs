@@ -0,0 +1,407 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/sx126x.rs - SX1262/SX1268 射频后端
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! SX1262/SX1268 backend (E22 modules), built with the `driver-sx1268-rs`
+//! feature.
+//!
+//! Wraps the `sx1268-rs` driver and issues the few commands it does not
+//! expose (RSSI sweeps, image calibration, the SX1262 PA table) directly
+//! through the shared [`RadioControl`].
+
+use sx1268_rs::{Sx1268, control::Control};
+
+use crate::{band, timer};
+use crate::lora::{self, SharedControl};
+use crate::radio::{
+  self, AirProfile, Bandwidth, Capture, Radio, RadioParams, RxError, RxProgress, SwitchGuard,
+};
+
+/// SX126x control wired as on the selected board.
+pub type BlueHighControl = crate::board::Control;
+
+/// Handle for commands the driver does not expose.
+pub type RadioControl = SharedControl<BlueHighControl>;
+
+/// Continuous RX: the chip never times out and re-arms after each packet.
+const RX_CONTINUOUS: u32 = 0xFF_FFFF;
+
+const SET_STANDBY: u8 = 0x80;
+const SET_RX: u8 = 0x82;
+const SET_SLEEP: u8 = 0x84;
+const SET_RF_FREQUENCY: u8 = 0x86;
+const SET_CAD_PARAMS: u8 = 0x88;
+const SET_TX_PARAMS: u8 = 0x8E;
+const SET_PA_CONFIG: u8 = 0x95;
+const CALIBRATE_IMAGE: u8 = 0x98;
+const SET_CAD: u8 = 0xC5;
+const SET_TX_CONTINUOUS_WAVE: u8 = 0xD1;
+const SET_DIO_IRQ_PARAMS: u8 = 0x08;
+const CLEAR_IRQ_STATUS: u8 = 0x02;
+const GET_IRQ_STATUS: u8 = 0x12;
+const GET_RX_BUFFER_STATUS: u8 = 0x13;
+const GET_PACKET_STATUS: u8 = 0x14;
+const GET_RSSI_INST: u8 = 0x15;
+const GET_DEVICE_ERRORS: u8 = 0x17;
+const CLEAR_DEVICE_ERRORS: u8 = 0x07;
+/// OpError bits that mean the chip cannot transmit: PLL lock and PA ramp.
+/// The others flag calibration steps, and XoscStartErr is always set at
+/// power-up with a TCXO.
+const FATAL_DEVICE_ERRORS: u16 = 0x0140;
+/// IRQ bits: TxDone, RxDone, CrcErr and Timeout drive DIO1 as usual; the
+/// CAD results and the early RX stages are only polled.
+const IRQ_DIO1: u16 = 0x0243;
+const IRQ_RX_DONE: u16 = 0x0002;
+const IRQ_PREAMBLE_DETECTED: u16 = 0x0004;
+const IRQ_HEADER_VALID: u16 = 0x0010;
+const IRQ_CRC_ERROR: u16 = 0x0040;
+const IRQ_CAD_DONE: u16 = 0x0080;
+const IRQ_CAD_DETECTED: u16 = 0x0100;
+const IRQ_ALL: u16 = 0x03FF;
+/// Longest a CAD may take before it is given up.
+const CAD_TIMEOUT_MS: u32 = 100;
+/// Frequency error estimate of the last packet, 20 bits signed.  Not in
+/// the datasheet; Semtech's reference drivers read it here.
+const REG_FREQ_ERROR: u16 = 0x076B;
+/// RandomNumberGen, 32 bits of wideband noise while in RX.
+const REG_RANDOM: u16 = 0x0819;
+/// SetTxParams ramp time code for 40 µs, as in the driver configuration.
+const RAMP_40_US: u8 = 0x02;
+/// Crystal frequency; the frequency word is `f * 2^25 / F_XTAL`.
+const F_XTAL_HZ: u64 = 32_000_000;
+/// SetSleep configuration: warm start, the RTC off.
+const SLEEP_WARM_START: u8 = 0x04;
+
+pub struct Sx126x {
+  driver: Sx1268<RadioControl>,
+  control: RadioControl,
+  /// Spreading factor of the applied parameters, for the CAD thresholds.
+  sf: u8,
+  /// Bandwidth of the applied parameters, for the frequency error.
+  bandwidth: Bandwidth,
+  /// In sleep; BUSY stays high until NSS wakes the chip.
+  asleep: bool,
+}
+
+impl Sx126x {
+  pub fn new(control: RadioControl) -> Self {
+    Self {
+      driver: Sx1268::new(control),
+      control,
+      sf: RadioParams::default().sf,
+      bandwidth: RadioParams::default().bandwidth,
+      asleep: false,
+    }
+  }
+
+  /// Delays around RF switch transitions from the next one on.
+  pub fn set_switch_guard(&self, guard: SwitchGuard) {
+    self.control.with(|control| control.switch_guard = guard);
+  }
+
+  /// Band-specific setup the driver does not do: image calibration for the
+  /// channel and, on the SX1262, the PA table.  Runs in standby after every
+  /// driver initialisation.
+  fn configure_band(&self, params: &RadioParams) -> bool {
+    let image = band::image_calibration(params.frequency_hz);
+    self.control.with(|control| {
+      if control.write_command(SET_STANDBY, &[0x00]).is_err()
+        || control.write_command(CALIBRATE_IMAGE, &image).is_err()
+      {
+        return false;
+      }
+      let Some(pa) = band::pa_setting(params.tx_power_dbm()) else {
+        return true;
+      };
+      control
+        .write_command(SET_PA_CONFIG, &[pa.duty_cycle, pa.hp_max, pa.device_sel, 0x01])
+        .is_ok()
+        && control
+          .write_command(SET_TX_PARAMS, &[pa.tx_power_dbm as u8, RAMP_40_US])
+          .is_ok()
+    })
+  }
+
+  /// Sample the link quality of the frame just read from the buffer; it
+  /// holds until the next RxDone.
+  fn sample_packet_status(&self) {
+    let mut status = [0u8; 3];
+    if self
+      .control
+      .with(|control| control.read_command(GET_PACKET_STATUS, &[0x00], &mut status))
+      .is_ok()
+    {
+      radio::record_packet_status(status[0], status[1]);
+    }
+  }
+}
+
+impl Radio for Sx126x {
+  fn apply_profile(&mut self, params: &RadioParams, profile: AirProfile) -> bool {
+    let Some(config) = params.to_profile_config(profile) else {
+      defmt::error!("[radio] invalid parameters {}", params);
+      return false;
+    };
+    if self.asleep {
+      let _ = self.control.with(|control| control.wakeup());
+      self.asleep = false;
+    }
+    if self.driver.init(config).is_err() {
+      defmt::error!("[radio] re-init failed");
+      return false;
+    }
+    if !self.configure_band(params) {
+      defmt::error!("[radio] {} band setup failed", band::CHIP);
+      return false;
+    }
+    self.sf = params.sf;
+    self.bandwidth = params.bandwidth;
+    defmt::info!("[radio] applied {} ({})", params, profile);
+    self.start_rx()
+  }
+
+  fn start_rx(&mut self) -> bool {
+    // Every IRQ latches, so the early RX stages can be polled.
+    let [mask_hi, mask_lo] = IRQ_ALL.to_be_bytes();
+    let [dio1_hi, dio1_lo] = IRQ_DIO1.to_be_bytes();
+    let started = self.driver.start_lora_rx(RX_CONTINUOUS).is_ok()
+      && self.control.with(|control| {
+        control
+          .write_command(SET_DIO_IRQ_PARAMS, &[mask_hi, mask_lo, dio1_hi, dio1_lo, 0, 0, 0, 0])
+          .is_ok()
+      });
+    if !started {
+      radio::record_fault();
+    }
+    started
+  }
+
+  fn send(&mut self, frame: &[u8]) -> bool {
+    let sent = self.driver.send_lora(frame, 0).is_ok();
+    if !sent {
+      radio::record_fault();
+    }
+    sent
+  }
+
+  fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, RxError> {
+    let received = self.driver.recv_lora(buf).map_err(|_| RxError)?;
+    if received.is_some() {
+      self.sample_packet_status();
+    }
+    Ok(received)
+  }
+
+  /// The driver drops frames with a bad CRC, so the buffer is read here.
+  fn capture(&mut self, buf: &mut [u8]) -> Result<Option<Capture>, RxError> {
+    let captured = self.control.with(|control| {
+      let mut status = [0u8; 2];
+      control
+        .read_command(GET_IRQ_STATUS, &[0x00], &mut status)
+        .map_err(|_| RxError)?;
+      let irq = u16::from_be_bytes(status);
+      control
+        .write_command(CLEAR_IRQ_STATUS, &[0xFF, 0xFF])
+        .map_err(|_| RxError)?;
+      if irq & IRQ_RX_DONE == 0 {
+        return Ok(None);
+      }
+      // Payload length and where it starts in the buffer.
+      let mut buffer = [0u8; 2];
+      control
+        .read_command(GET_RX_BUFFER_STATUS, &[0x00], &mut buffer)
+        .map_err(|_| RxError)?;
+      let len = (buffer[0] as usize).min(buf.len());
+      control
+        .read_buffer(buffer[1], &mut buf[..len])
+        .map_err(|_| RxError)?;
+      Ok(Some(Capture {
+        len,
+        crc_ok: irq & IRQ_CRC_ERROR == 0,
+      }))
+    });
+    if let Ok(Some(_)) = captured {
+      self.sample_packet_status();
+    }
+    captured
+  }
+
+  fn listen_at(&mut self, frequency_hz: u32) -> bool {
+    self.tune(frequency_hz)
+      && self
+        .control
+        .with(|control| control.write_command(SET_RX, &[0xFF, 0xFF, 0xFF]).is_ok())
+  }
+
+  fn rssi_inst(&mut self) -> Option<i16> {
+    let mut rssi = [0u8; 1];
+    self
+      .control
+      .with(|control| control.read_command(GET_RSSI_INST, &[0x00], &mut rssi))
+      .ok()?;
+    Some(-(rssi[0] as i16) / 2)
+  }
+
+  fn freq_error_hz(&mut self) -> Option<i32> {
+    let mut raw = [0u8; 3];
+    self
+      .control
+      .with(|control| control.read_register(REG_FREQ_ERROR, &mut raw))
+      .ok()?;
+    let steps = (i32::from_be_bytes([0, raw[0], raw[1], raw[2]]) << 12) >> 12;
+    // 1.55 Hz per step at 1.6 MHz, scaled to the bandwidth.
+    Some((steps as i64 * 155 * self.bandwidth.khz() as i64 / 160_000) as i32)
+  }
+
+  fn random_word(&mut self) -> Option<u32> {
+    let mut word = [0u8; 4];
+    self
+      .control
+      .with(|control| control.read_register(REG_RANDOM, &mut word))
+      .ok()?;
+    Some(u32::from_be_bytes(word))
+  }
+
+  fn rx_progress(&mut self) -> Option<RxProgress> {
+    let mut status = [0u8; 2];
+    self
+      .control
+      .with(|control| control.read_command(GET_IRQ_STATUS, &[0x00], &mut status))
+      .ok()?;
+    let irq = u16::from_be_bytes(status) & (IRQ_PREAMBLE_DETECTED | IRQ_HEADER_VALID);
+    if irq == 0 {
+      return None;
+    }
+    let [hi, lo] = irq.to_be_bytes();
+    self
+      .control
+      .with(|control| control.write_command(CLEAR_IRQ_STATUS, &[hi, lo]))
+      .ok()?;
+    if irq & IRQ_HEADER_VALID != 0 {
+      Some(RxProgress::Header)
+    } else {
+      Some(RxProgress::Preamble)
+    }
+  }
+
+  fn tune(&mut self, frequency_hz: u32) -> bool {
+    let word = ((frequency_hz as u64) << 25) / F_XTAL_HZ;
+    self.control.with(|control| {
+      control.write_command(SET_STANDBY, &[0x00]).is_ok()
+        && control
+          .write_command(SET_RF_FREQUENCY, &(word as u32).to_be_bytes())
+          .is_ok()
+    })
+  }
+
+  fn carrier(&mut self, on: bool) -> bool {
+    // The E22 PA only reaches the antenna with TXEN raised.
+    self.control.with(|control| {
+      if on {
+        control.switch_tx(0).is_ok()
+          && control.write_command(SET_TX_CONTINUOUS_WAVE, &[]).is_ok()
+      } else {
+        control.write_command(SET_STANDBY, &[0x00]).is_ok() && control.switch_rx(0).is_ok()
+      }
+    })
+  }
+
+  fn channel_active(&mut self) -> Option<bool> {
+    // Detection peak per AN1200.48 for 125 kHz: 22 at SF7 rising to 28.
+    let peak = self.sf + 15;
+    let [mask_hi, mask_lo] = IRQ_ALL.to_be_bytes();
+    let [dio1_hi, dio1_lo] = IRQ_DIO1.to_be_bytes();
+    let started = self.control.with(|control| {
+      control.write_command(SET_STANDBY, &[0x00]).is_ok()
+        && control
+          .write_command(SET_DIO_IRQ_PARAMS, &[mask_hi, mask_lo, dio1_hi, dio1_lo, 0, 0, 0, 0])
+          .is_ok()
+        && control.write_command(CLEAR_IRQ_STATUS, &[0xFF, 0xFF]).is_ok()
+        // Two symbols, minimum 10, back to standby when done.
+        && control
+          .write_command(SET_CAD_PARAMS, &[0x01, peak, 10, 0x00, 0, 0, 0])
+          .is_ok()
+        && control.write_command(SET_CAD, &[]).is_ok()
+    });
+    if !started {
+      return None;
+    }
+
+    // The cycle counter, unlike SysTick, runs under a critical section.
+    let deadline = timer::after_us(timer::now_cycles(), CAD_TIMEOUT_MS * 1_000);
+    loop {
+      let mut status = [0u8; 2];
+      self
+        .control
+        .with(|control| control.read_command(GET_IRQ_STATUS, &[0x00], &mut status))
+        .ok()?;
+      let irq = u16::from_be_bytes(status);
+      if irq & IRQ_CAD_DONE != 0 {
+        let [hi, lo] = (IRQ_CAD_DONE | IRQ_CAD_DETECTED).to_be_bytes();
+        self
+          .control
+          .with(|control| control.write_command(CLEAR_IRQ_STATUS, &[hi, lo]))
+          .ok()?;
+        return Some(irq & IRQ_CAD_DETECTED != 0);
+      }
+      if timer::cycles_reached(deadline) {
+        defmt::warn!("[radio] CAD timed out");
+        self
+          .control
+          .with(|control| control.write_command(SET_STANDBY, &[0x00]))
+          .ok()?;
+        return None;
+      }
+    }
+  }
+
+  fn take_hang(&mut self) -> bool {
+    lora::take_busy_timeout()
+  }
+
+  fn device_errors(&mut self) -> Option<u16> {
+    let mut errors = [0u8; 2];
+    self
+      .control
+      .with(|control| control.read_command(GET_DEVICE_ERRORS, &[0x00], &mut errors))
+      .ok()?;
+    let errors = u16::from_be_bytes(errors) & FATAL_DEVICE_ERRORS;
+    if errors != 0 {
+      self
+        .control
+        .with(|control| control.write_command(CLEAR_DEVICE_ERRORS, &[0x00, 0x00]))
+        .ok()?;
+    }
+    Some(errors)
+  }
+
+  fn hard_reset(&mut self) {
+    let _ = self.control.with(|control| control.reset());
+    self.asleep = false;
+  }
+
+  /// Both RF switch paths go off too, so the E22 LNA draws nothing.
+  fn sleep(&mut self) -> bool {
+    let asleep = self.control.with(|control| {
+      control.write_command(SET_STANDBY, &[0x00]).is_ok()
+        && control.tx_pin.set_low().is_ok()
+        && control.rx_pin.set_low().is_ok()
+        && control.write_command(SET_SLEEP, &[SLEEP_WARM_START]).is_ok()
+    });
+    if asleep {
+      self.asleep = true;
+    } else {
+      radio::record_fault();
+    }
+    asleep
+  }
+}
@@ -0,0 +1,430 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/sx1276.rs - SX1276/SX1278 射频后端
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! SX1276/SX1278 backend (RFM95, RA-02), built with the `sx1276` feature.
+//!
+//! Unlike the SX126x command set, the SX127x is a plain register file: an
+//! access is `[address | 0x80 for write][data ...]`, and frames go through a
+//! 256-byte FIFO shared by TX and RX.  Only LoRa mode is used.
+//!
+//! Wiring uses the board pin map: NSS and NRST as for an E22, and the
+//! module's DIO0 (RxDone / TxDone) on the pin the E22's DIO1 would use.
+//! BUSY, TXEN and RXEN are not needed; these modules switch the antenna
+//! internally and always use the PA_BOOST output.
+//!
+//! The link settings match the SX126x backend (explicit header, CRC on,
+//! 8 preamble symbols, LDRO on, private sync word), so both kinds of bridge
+//! can talk to each other.
+
+use embedded_hal::spi::{Operation, SpiDevice};
+
+use crate::board::{Nrst, Nss};
+use crate::lora_config;
+use crate::radio::{
+  self, AirProfile, Bandwidth, Capture, PacketStatus, Radio, RadioParams, RxError, RxProgress,
+};
+use crate::spi_bus::{Bus1, Error, SharedDevice};
+use crate::timer;
+
+const REG_FIFO: u8 = 0x00;
+const REG_OP_MODE: u8 = 0x01;
+const REG_FRF_MSB: u8 = 0x06;
+const REG_PA_CONFIG: u8 = 0x09;
+const REG_OCP: u8 = 0x0B;
+const REG_FIFO_ADDR_PTR: u8 = 0x0D;
+const REG_FIFO_TX_BASE_ADDR: u8 = 0x0E;
+const REG_FIFO_RX_BASE_ADDR: u8 = 0x0F;
+const REG_FIFO_RX_CURRENT_ADDR: u8 = 0x10;
+const REG_IRQ_FLAGS: u8 = 0x12;
+const REG_RX_NB_BYTES: u8 = 0x13;
+const REG_PKT_SNR_VALUE: u8 = 0x19;
+const REG_PKT_RSSI_VALUE: u8 = 0x1A;
+const REG_MODEM_STAT: u8 = 0x18;
+const REG_FEI_MSB: u8 = 0x28;
+const REG_RSSI_WIDEBAND: u8 = 0x2C;
+const REG_RSSI_VALUE: u8 = 0x1B;
+const REG_MODEM_CONFIG_1: u8 = 0x1D;
+const REG_MODEM_CONFIG_2: u8 = 0x1E;
+const REG_PREAMBLE_MSB: u8 = 0x20;
+const REG_PAYLOAD_LENGTH: u8 = 0x22;
+const REG_MODEM_CONFIG_3: u8 = 0x26;
+const REG_INVERT_IQ: u8 = 0x33;
+const REG_SYNC_WORD: u8 = 0x39;
+const REG_INVERT_IQ_2: u8 = 0x3B;
+const REG_DIO_MAPPING_1: u8 = 0x40;
+const REG_VERSION: u8 = 0x42;
+const REG_PA_DAC: u8 = 0x4D;
+
+const WRITE: u8 = 0x80;
+/// RegVersion of every SX1276/77/78/79.
+const VERSION: u8 = 0x12;
+
+const MODE_LONG_RANGE: u8 = 0x80;
+/// Selects the low-frequency register set, for channels below 525 MHz.
+const MODE_LOW_FREQUENCY: u8 = 0x08;
+const MODE_SLEEP: u8 = 0x00;
+const MODE_STANDBY: u8 = 0x01;
+const MODE_TX: u8 = 0x03;
+const MODE_RX_CONTINUOUS: u8 = 0x05;
+const MODE_CAD: u8 = 0x07;
+
+const IRQ_RX_DONE: u8 = 0x40;
+const IRQ_PAYLOAD_CRC_ERROR: u8 = 0x20;
+const IRQ_VALID_HEADER: u8 = 0x10;
+const IRQ_CAD_DONE: u8 = 0x04;
+const IRQ_CAD_DETECTED: u8 = 0x01;
+/// RegModemStat: the modem locked onto a preamble.
+const MODEM_SIGNAL_SYNCHRONIZED: u8 = 0x02;
+/// Longest a CAD may take before it is given up.
+const CAD_TIMEOUT_MS: u32 = 100;
+/// DIO0 mapping in RegDioMapping1 bits 7..6.
+const DIO0_RX_DONE: u8 = 0x00;
+const DIO0_TX_DONE: u8 = 0x40;
+
+/// Same LoRa sync word as `0x1424` on the SX126x.
+const SYNC_WORD: u8 = 0x12;
+/// LoRaWAN sync word, `0x3444` on the SX126x.
+const SYNC_WORD_PUBLIC: u8 = 0x34;
+/// RegInvertIQ / RegInvertIQ2 for normal and inverted RX IQ (AN1200.24).
+const INVERT_IQ_OFF: (u8, u8) = (0x27, 0x1D);
+const INVERT_IQ_RX: (u8, u8) = (0x67, 0x19);
+/// Boundary between the low- and high-frequency ports.
+const LOW_FREQUENCY_MAX_HZ: u32 = 525_000_000;
+/// Crystal frequency; Frf is `f * 2^19 / F_XTAL`.
+const F_XTAL_HZ: u64 = 32_000_000;
+/// PA_BOOST output from which +20 dBm needs the high-power DAC.
+const POWER_BOOST_FROM_DBM: i8 = 18;
+
+pub struct Sx1276 {
+  spi: SharedDevice<Bus1, Nss>,
+  nrst: Nrst,
+  /// Operating mode bits besides the mode itself.
+  mode_base: u8,
+  /// Bandwidth of the applied parameters, for the frequency error.
+  bandwidth: Bandwidth,
+}
+
+impl Sx1276 {
+  pub fn new(spi: SharedDevice<Bus1, Nss>, nrst: Nrst) -> Self {
+    Self {
+      spi,
+      nrst,
+      mode_base: MODE_LONG_RANGE,
+      bandwidth: RadioParams::default().bandwidth,
+    }
+  }
+
+  fn write(&mut self, address: u8, data: &[u8]) -> Result<(), Error> {
+    self
+      .spi
+      .transaction(&mut [Operation::Write(&[address | WRITE]), Operation::Write(data)])
+  }
+
+  fn read(&mut self, address: u8, data: &mut [u8]) -> Result<(), Error> {
+    // As in `lora.rs`: `write()` discards the MISO byte clocked out with
+    // the address, so the data follow directly.
+    self
+      .spi
+      .transaction(&mut [Operation::Write(&[address & !WRITE]), Operation::Read(data)])
+  }
+
+  fn write_register(&mut self, address: u8, value: u8) -> Result<(), Error> {
+    self.write(address, &[value])
+  }
+
+  fn read_register(&mut self, address: u8) -> Result<u8, Error> {
+    let mut value = [0u8; 1];
+    self.read(address, &mut value)?;
+    Ok(value[0])
+  }
+
+  fn set_mode(&mut self, mode: u8) -> Result<(), Error> {
+    self.write_register(REG_OP_MODE, self.mode_base | mode)
+  }
+
+  fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), Error> {
+    let frf = ((frequency_hz as u64) << 19) / F_XTAL_HZ;
+    self.write(REG_FRF_MSB, &(frf as u32).to_be_bytes()[1..])
+  }
+
+  /// RSSI offset of the port in use (datasheet §5.5.5).
+  fn rssi_offset(&self) -> i16 {
+    if self.mode_base & MODE_LOW_FREQUENCY != 0 {
+      -164
+    } else {
+      -157
+    }
+  }
+
+  fn reset(&mut self) {
+    self.nrst.set_low();
+    wait_ms(1);
+    self.nrst.set_high();
+    wait_ms(6);
+  }
+
+  fn configure(&mut self, params: &RadioParams, profile: AirProfile) -> Result<bool, Error> {
+    self.reset();
+    let version = self.read_register(REG_VERSION)?;
+    if version != VERSION {
+      defmt::error!("[sx1276] unexpected version 0x{:02X}", version);
+      return Ok(false);
+    }
+
+    // LoRa mode can only be selected in sleep.
+    self.write_register(REG_OP_MODE, MODE_SLEEP)?;
+    self.mode_base = MODE_LONG_RANGE;
+    if params.frequency_hz < LOW_FREQUENCY_MAX_HZ {
+      self.mode_base |= MODE_LOW_FREQUENCY;
+    }
+    self.set_mode(MODE_SLEEP)?;
+    self.set_mode(MODE_STANDBY)?;
+    self.set_frequency(params.tuned_frequency_hz())?;
+
+    let power = params
+      .tx_power_dbm()
+      .clamp(lora_config::MIN_POWER_DBM, lora_config::MAX_POWER_DBM);
+    if power >= POWER_BOOST_FROM_DBM {
+      self.write_register(REG_PA_DAC, 0x87)?;
+      self.write_register(REG_PA_CONFIG, 0x80 | (power - 5) as u8)?;
+      // Over-current limit 240 mA.
+      self.write_register(REG_OCP, 0x3B)?;
+    } else {
+      self.write_register(REG_PA_DAC, 0x84)?;
+      self.write_register(REG_PA_CONFIG, 0x80 | (power - 2) as u8)?;
+      // Over-current limit 100 mA, the reset value.
+      self.write_register(REG_OCP, 0x2B)?;
+    }
+
+    let bandwidth = match params.bandwidth {
+      Bandwidth::Khz125 => 0x70,
+      Bandwidth::Khz250 => 0x80,
+      Bandwidth::Khz500 => 0x90,
+    };
+    // Explicit header.
+    self.write_register(REG_MODEM_CONFIG_1, bandwidth | ((params.cr - 4) << 1))?;
+    self.bandwidth = params.bandwidth;
+    // Payload CRC on.
+    self.write_register(REG_MODEM_CONFIG_2, (params.sf << 4) | 0x04)?;
+    // LDRO as on the SX126x; AGC on.
+    let ldro = if profile.ldro(params) { 0x08 } else { 0x00 };
+    self.write_register(REG_MODEM_CONFIG_3, ldro | 0x04)?;
+    self.write(REG_PREAMBLE_MSB, &lora_config::LINK.preamble_symbols.to_be_bytes())?;
+    let sync_word = if profile == AirProfile::Link { SYNC_WORD } else { SYNC_WORD_PUBLIC };
+    let (invert_iq, invert_iq_2) = if profile.invert_iq() { INVERT_IQ_RX } else { INVERT_IQ_OFF };
+    self.write_register(REG_SYNC_WORD, sync_word)?;
+    self.write_register(REG_INVERT_IQ, invert_iq)?;
+    self.write_register(REG_INVERT_IQ_2, invert_iq_2)?;
+    self.write_register(REG_FIFO_TX_BASE_ADDR, 0x00)?;
+    self.write_register(REG_FIFO_RX_BASE_ADDR, 0x00)?;
+    Ok(true)
+  }
+
+  fn try_start_rx(&mut self) -> Result<(), Error> {
+    self.set_mode(MODE_STANDBY)?;
+    self.write_register(REG_DIO_MAPPING_1, DIO0_RX_DONE)?;
+    self.write_register(REG_IRQ_FLAGS, 0xFF)?;
+    self.set_mode(MODE_RX_CONTINUOUS)
+  }
+
+  fn try_cad(&mut self) -> Result<Option<bool>, Error> {
+    self.set_mode(MODE_STANDBY)?;
+    self.write_register(REG_IRQ_FLAGS, 0xFF)?;
+    self.set_mode(MODE_CAD)?;
+    let deadline = timer::after_us(timer::now_cycles(), CAD_TIMEOUT_MS * 1_000);
+    loop {
+      let flags = self.read_register(REG_IRQ_FLAGS)?;
+      if flags & IRQ_CAD_DONE != 0 {
+        // The chip returns to standby by itself.
+        self.write_register(REG_IRQ_FLAGS, 0xFF)?;
+        return Ok(Some(flags & IRQ_CAD_DETECTED != 0));
+      }
+      if timer::cycles_reached(deadline) {
+        defmt::warn!("[sx1276] CAD timed out");
+        self.set_mode(MODE_STANDBY)?;
+        return Ok(None);
+      }
+    }
+  }
+
+  fn try_send(&mut self, frame: &[u8]) -> Result<(), Error> {
+    self.set_mode(MODE_STANDBY)?;
+    self.write_register(REG_DIO_MAPPING_1, DIO0_TX_DONE)?;
+    self.write_register(REG_IRQ_FLAGS, 0xFF)?;
+    self.write_register(REG_FIFO_ADDR_PTR, 0x00)?;
+    self.write(REG_FIFO, frame)?;
+    self.write_register(REG_PAYLOAD_LENGTH, frame.len() as u8)?;
+    self.set_mode(MODE_TX)
+  }
+}
+
+impl Radio for Sx1276 {
+  fn apply_profile(&mut self, params: &RadioParams, profile: AirProfile) -> bool {
+    if !params.is_valid() {
+      defmt::error!("[radio] invalid parameters {}", params);
+      return false;
+    }
+    match self.configure(params, profile) {
+      Ok(true) => {}
+      Ok(false) => return false,
+      Err(_) => {
+        defmt::error!("[radio] re-init failed");
+        return false;
+      }
+    }
+    defmt::info!("[radio] applied {} ({})", params, profile);
+    self.start_rx()
+  }
+
+  fn start_rx(&mut self) -> bool {
+    let started = self.try_start_rx().is_ok();
+    if !started {
+      radio::record_fault();
+    }
+    started
+  }
+
+  fn send(&mut self, frame: &[u8]) -> bool {
+    let sent = self.try_send(frame).is_ok();
+    if !sent {
+      radio::record_fault();
+    }
+    sent
+  }
+
+  fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, RxError> {
+    match self.capture(buf)? {
+      Some(capture) if capture.crc_ok => Ok(Some(capture.len)),
+      Some(_) => Err(RxError),
+      None => Ok(None),
+    }
+  }
+
+  fn capture(&mut self, buf: &mut [u8]) -> Result<Option<Capture>, RxError> {
+    let flags = self.read_register(REG_IRQ_FLAGS).map_err(|_| RxError)?;
+    self
+      .write_register(REG_IRQ_FLAGS, 0xFF)
+      .map_err(|_| RxError)?;
+    if flags & IRQ_RX_DONE == 0 {
+      return Ok(None);
+    }
+
+    let address = self
+      .read_register(REG_FIFO_RX_CURRENT_ADDR)
+      .map_err(|_| RxError)?;
+    let length = self.read_register(REG_RX_NB_BYTES).map_err(|_| RxError)?;
+    let len = (length as usize).min(buf.len());
+    self
+      .write_register(REG_FIFO_ADDR_PTR, address)
+      .map_err(|_| RxError)?;
+    self.read(REG_FIFO, &mut buf[..len]).map_err(|_| RxError)?;
+
+    let snr_raw = self.read_register(REG_PKT_SNR_VALUE).map_err(|_| RxError)?;
+    let rssi_raw = self.read_register(REG_PKT_RSSI_VALUE).map_err(|_| RxError)?;
+    let snr_db = (snr_raw as i8) / 4;
+    let mut rssi_dbm = self.rssi_offset() + rssi_raw as i16;
+    if snr_db < 0 {
+      rssi_dbm += snr_db as i16;
+    }
+    radio::set_packet_status(PacketStatus { rssi_dbm, snr_db });
+    Ok(Some(Capture {
+      len,
+      crc_ok: flags & IRQ_PAYLOAD_CRC_ERROR == 0,
+    }))
+  }
+
+  fn listen_at(&mut self, frequency_hz: u32) -> bool {
+    self.set_mode(MODE_STANDBY).is_ok()
+      && self.set_frequency(frequency_hz).is_ok()
+      && self.set_mode(MODE_RX_CONTINUOUS).is_ok()
+  }
+
+  fn rssi_inst(&mut self) -> Option<i16> {
+    let value = self.read_register(REG_RSSI_VALUE).ok()?;
+    Some(self.rssi_offset() + value as i16)
+  }
+
+  fn freq_error_hz(&mut self) -> Option<i32> {
+    let mut raw = [0u8; 3];
+    self.read(REG_FEI_MSB, &mut raw).ok()?;
+    let steps = (i32::from_be_bytes([0, raw[0], raw[1], raw[2]]) << 12) >> 12;
+    // Datasheet §4.1.5: 2^24 / F_XTAL Hz per step, scaled to the bandwidth.
+    let hz = steps as i64 * (1 << 24) * self.bandwidth.khz() as i64 / (F_XTAL_HZ as i64 * 500);
+    Some(hz as i32)
+  }
+
+  /// One bit per read: the LSB of the wideband RSSI (AN1200.24).
+  fn random_word(&mut self) -> Option<u32> {
+    let mut word = 0;
+    for _ in 0..32 {
+      let sample = self.read_register(REG_RSSI_WIDEBAND).ok()?;
+      word = (word << 1) | (sample & 1) as u32;
+    }
+    Some(word)
+  }
+
+  /// LoRa mode has no preamble IRQ; the modem status shows the lock while
+  /// the frame lasts, only the header flag latches.
+  fn rx_progress(&mut self) -> Option<RxProgress> {
+    let flags = self.read_register(REG_IRQ_FLAGS).ok()?;
+    if flags & IRQ_VALID_HEADER != 0 {
+      self.write_register(REG_IRQ_FLAGS, IRQ_VALID_HEADER).ok()?;
+      return Some(RxProgress::Header);
+    }
+    let status = self.read_register(REG_MODEM_STAT).ok()?;
+    (status & MODEM_SIGNAL_SYNCHRONIZED != 0).then_some(RxProgress::Preamble)
+  }
+
+  fn tune(&mut self, frequency_hz: u32) -> bool {
+    self.set_mode(MODE_STANDBY).is_ok() && self.set_frequency(frequency_hz).is_ok()
+  }
+
+  /// LoRa mode has no plain carrier; it would need the FSK/OOK modem.
+  fn carrier(&mut self, on: bool) -> bool {
+    !on && self.set_mode(MODE_STANDBY).is_ok()
+  }
+
+  fn channel_active(&mut self) -> Option<bool> {
+    self.try_cad().ok().flatten()
+  }
+
+  /// Without a BUSY line a hung chip shows as failed commands instead.
+  fn take_hang(&mut self) -> bool {
+    false
+  }
+
+  /// No error flags; a chip that lost its registers no longer reads back
+  /// its version.
+  fn device_errors(&mut self) -> Option<u16> {
+    (self.read_register(REG_VERSION).ok()? == VERSION).then_some(0)
+  }
+
+  fn hard_reset(&mut self) {
+    self.reset();
+  }
+
+  /// [`Radio::apply`] resets the chip, which wakes it.
+  fn sleep(&mut self) -> bool {
+    let asleep = self.set_mode(MODE_SLEEP).is_ok();
+    if !asleep {
+      radio::record_fault();
+    }
+    asleep
+  }
+}
+
+/// Busy-wait on the cycle counter, which keeps running with interrupts
+/// disabled, see [`crate::radio_handle`].
+fn wait_ms(ms: u32) {
+  timer::wait_until_cycles(timer::after_us(timer::now_cycles(), ms * 1_000));
+}
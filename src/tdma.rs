@@ -0,0 +1,331 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/tdma.rs - 时分多址时隙调度
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Time-slotted channel access.
+//!
+//! Optional TDMA for channels shared by many bridges.  Time is divided into
+//! cycles of `slots` equal slots.  The master (`AT+TDMA=MASTER,<slots>,
+//! <slot>`) broadcasts a sync control frame at the start of every cycle, in
+//! slot 0:
+//!
+//! ```text
+//! [0x50][cycle u16 LE][slots u8][slot ms u16 LE][flags u8]
+//! ```
+//!
+//! A slot is long enough for a frame of the largest size at the current
+//! radio settings, from the airtime calculator, plus [`GUARD_MS`].  Nodes
+//! (`AT+TDMA=NODE,<slot>`) take the cycle start from the sync, corrected by
+//! the airtime of the sync frame itself, and then send host data, beacons
+//! and relayed frames only inside their own slot, and only when the frame
+//! fits in the rest of it.  A node that has missed [`SYNC_LOST_CYCLES`]
+//! syncs stops transmitting until it hears the master again.  Link tests,
+//! remote configuration and CW identification are not slotted.
+//!
+//! Slots are assigned by hand; two nodes must not share one.
+//!
+//! When the master has network time ([`crate::timesync`]) its cycles start
+//! on multiples of the cycle length in network time, and flag bit 0 says
+//! so.  A node that has network time as well then takes its slot position
+//! from the clock instead of the last sync, so it stays in its slot
+//! through lost syncs for as long as the clock holds.  The master skips
+//! the sync of a cycle it noticed later than [`GUARD_MS`] into it.
+
+use crate::packetizer::MAX_PAYLOAD;
+use crate::radio::RadioParams;
+use crate::timesync;
+
+/// Most slots per cycle, the sync slot included.
+pub const MAX_SLOTS: u8 = 16;
+/// Room in every slot beyond a full-size frame, for clock drift and
+/// turnaround.
+pub const GUARD_MS: u32 = 20;
+/// Cycles without sync after which a node stops transmitting.
+pub const SYNC_LOST_CYCLES: u32 = 3;
+
+const TYPE_SYNC: u8 = 0x50;
+/// Bytes of a sync message.
+pub const SYNC_LEN: usize = 7;
+/// Sync flag: cycles start on network time.
+const FLAG_ALIGNED: u8 = 0x01;
+
+/// What this bridge does in the TDMA scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Role {
+  /// Transmit whenever there is something to send.
+  Off,
+  /// Send sync frames and own slot `slot`.
+  Master,
+  /// Follow the master and own slot `slot`.
+  Node,
+}
+
+/// Persisted TDMA configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Config {
+  pub role: Role,
+  /// Slots per cycle; used by the master only, nodes learn it from sync.
+  pub slots: u8,
+  /// Own slot, `1..slots`.
+  pub slot: u8,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      role: Role::Off,
+      slots: 0,
+      slot: 0,
+    }
+  }
+}
+
+impl Config {
+  pub fn master(slots: u8, slot: u8) -> Option<Self> {
+    ((2..=MAX_SLOTS).contains(&slots) && (1..slots).contains(&slot)).then_some(Self {
+      role: Role::Master,
+      slots,
+      slot,
+    })
+  }
+
+  pub fn node(slot: u8) -> Option<Self> {
+    (1..MAX_SLOTS).contains(&slot).then_some(Self {
+      role: Role::Node,
+      slots: 0,
+      slot,
+    })
+  }
+
+  pub fn encode(&self) -> [u8; 3] {
+    let role = match self.role {
+      Role::Off => 0,
+      Role::Master => 1,
+      Role::Node => 2,
+    };
+    [role, self.slots, self.slot]
+  }
+
+  pub fn decode(bytes: [u8; 3]) -> Option<Self> {
+    match bytes[0] {
+      0 => Some(Self::default()),
+      1 => Self::master(bytes[1], bytes[2]),
+      2 => Self::node(bytes[2]),
+      _ => None,
+    }
+  }
+}
+
+/// Slot length for `params`: one full-size frame plus the guard time.
+pub fn slot_ms(params: &RadioParams) -> u32 {
+  params.airtime_us(MAX_PAYLOAD).div_ceil(1_000) + GUARD_MS
+}
+
+/// Sync message of the master.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Sync {
+  pub cycle: u16,
+  pub slots: u8,
+  pub slot_ms: u16,
+  /// Cycles start on multiples of the cycle length in network time.
+  pub aligned: bool,
+}
+
+impl Sync {
+  pub fn encode(&self) -> [u8; SYNC_LEN] {
+    let [cycle_lo, cycle_hi] = self.cycle.to_le_bytes();
+    let [slot_lo, slot_hi] = self.slot_ms.to_le_bytes();
+    let flags = if self.aligned { FLAG_ALIGNED } else { 0 };
+    [TYPE_SYNC, cycle_lo, cycle_hi, self.slots, slot_lo, slot_hi, flags]
+  }
+
+  /// Also takes the six-byte sync of masters without network time support.
+  pub fn decode(payload: &[u8]) -> Option<Self> {
+    match *payload {
+      [TYPE_SYNC, cycle_lo, cycle_hi, slots, slot_lo, slot_hi, ref flags @ ..]
+        if flags.len() <= 1 =>
+      {
+        Some(Self {
+          cycle: u16::from_le_bytes([cycle_lo, cycle_hi]),
+          slots,
+          slot_ms: u16::from_le_bytes([slot_lo, slot_hi]),
+          aligned: flags.first().is_some_and(|flags| flags & FLAG_ALIGNED != 0),
+        })
+      }
+      _ => None,
+    }
+  }
+}
+
+pub struct Scheduler {
+  config: Config,
+  slots: u8,
+  slot_ms: u32,
+  /// Start of the current cycle, once known.
+  cycle_start: Option<u32>,
+  /// When the last sync was sent or heard.
+  last_sync: u32,
+  cycle: u16,
+  /// The cycles follow network time.
+  aligned: bool,
+  /// Master on network time: the last cycle seen, sync sent or not.
+  network_cycle: Option<u64>,
+  clock: timesync::Clock,
+}
+
+impl Scheduler {
+  pub fn new(config: Config) -> Self {
+    Self {
+      config,
+      slots: config.slots,
+      slot_ms: 0,
+      cycle_start: None,
+      last_sync: 0,
+      cycle: 0,
+      aligned: false,
+      network_cycle: None,
+      clock: timesync::Clock::new(),
+    }
+  }
+
+  pub fn config(&self) -> Config {
+    self.config
+  }
+
+  /// Switch roles; a node waits for the next sync.
+  pub fn set_config(&mut self, config: Config) {
+    *self = Self::new(config);
+  }
+
+  /// Follow the current network time; called every main loop pass.
+  pub fn set_clock(&mut self, clock: timesync::Clock) {
+    self.clock = clock;
+  }
+
+  /// Network time to place the cycle on, if the cycles follow it.
+  fn network_ms(&self, now: u32) -> Option<u64> {
+    self.aligned.then(|| self.clock.now(now)).flatten()
+  }
+
+  fn cycle_ms(&self) -> u32 {
+    self.slots as u32 * self.slot_ms
+  }
+
+  /// Master: the sync to send now, at the start of a new cycle.
+  pub fn sync_due(&mut self, params: &RadioParams, now: u32) -> Option<Sync> {
+    if self.config.role != Role::Master {
+      return None;
+    }
+    if let Some(network_ms) = self.clock.now(now) {
+      return self.aligned_sync_due(params, network_ms, now);
+    }
+    self.aligned = false;
+    self.network_cycle = None;
+    if let Some(start) = self.cycle_start
+      && now.wrapping_sub(start) < self.cycle_ms()
+    {
+      return None;
+    }
+    self.slot_ms = slot_ms(params);
+    self.cycle = self.cycle.wrapping_add(1);
+    self.cycle_start = Some(now);
+    self.last_sync = now;
+    Some(Sync {
+      cycle: self.cycle,
+      slots: self.slots,
+      slot_ms: self.slot_ms.min(u16::MAX as u32) as u16,
+      aligned: false,
+    })
+  }
+
+  fn aligned_sync_due(
+    &mut self,
+    params: &RadioParams,
+    network_ms: u64,
+    now: u32,
+  ) -> Option<Sync> {
+    let slot_ms = slot_ms(params);
+    let cycle_ms = self.slots as u64 * slot_ms as u64;
+    let cycle = network_ms / cycle_ms;
+    if self.network_cycle == Some(cycle) {
+      return None;
+    }
+    self.network_cycle = Some(cycle);
+    self.slot_ms = slot_ms;
+    self.aligned = true;
+    let phase_ms = (network_ms % cycle_ms) as u32;
+    if phase_ms > GUARD_MS {
+      defmt::debug!("[tdma] cycle start missed by {} ms, no sync", phase_ms);
+      return None;
+    }
+    self.cycle = cycle as u16;
+    self.cycle_start = Some(now.wrapping_sub(phase_ms));
+    self.last_sync = now;
+    Some(Sync {
+      cycle: self.cycle,
+      slots: self.slots,
+      slot_ms: self.slot_ms.min(u16::MAX as u32) as u16,
+      aligned: true,
+    })
+  }
+
+  /// Node: align to a sync received at `now` that took `airtime_ms` on air.
+  pub fn on_sync(&mut self, sync: &Sync, airtime_ms: u32, now: u32) {
+    if self.config.role != Role::Node || sync.slots < 2 || sync.slot_ms == 0 {
+      return;
+    }
+    if self.cycle_start.is_none() {
+      defmt::info!("[tdma] synced, {} slots of {} ms", sync.slots, sync.slot_ms);
+    }
+    self.slots = sync.slots;
+    self.slot_ms = sync.slot_ms as u32;
+    self.cycle = sync.cycle;
+    self.cycle_start = Some(now.wrapping_sub(airtime_ms));
+    self.last_sync = now;
+    self.aligned = sync.aligned;
+  }
+
+  /// Whether the node follows the master's cycle; always for the master.
+  pub fn synced(&self, now: u32) -> bool {
+    match self.config.role {
+      Role::Off => false,
+      Role::Master => true,
+      Role::Node => {
+        self.cycle_start.is_some()
+          && (now.wrapping_sub(self.last_sync) <= SYNC_LOST_CYCLES * self.cycle_ms()
+            || self.network_ms(now).is_some())
+      }
+    }
+  }
+
+  /// Whether a frame of `frame_len` bytes sent with `params` may start
+  /// now.
+  pub fn may_transmit(&self, params: &RadioParams, frame_len: usize, now: u32) -> bool {
+    if self.config.role == Role::Off {
+      return true;
+    }
+    let airtime_ms = params.airtime_us(frame_len).div_ceil(1_000);
+    let Some(start) = self.cycle_start else {
+      return false;
+    };
+    if !self.synced(now) || self.config.slot >= self.slots {
+      return false;
+    }
+    let offset = match self.network_ms(now) {
+      Some(network_ms) => (network_ms % self.cycle_ms() as u64) as u32,
+      None => now.wrapping_sub(start) % self.cycle_ms(),
+    };
+    let slot = offset / self.slot_ms;
+    let left_ms = self.slot_ms - offset % self.slot_ms;
+    slot == self.config.slot as u32 && left_ms >= airtime_ms
+  }
+}
@@ -0,0 +1,129 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/telemetry.rs - 周期状态推送
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Telemetry stream.
+//!
+//! `AT+STATS=<interval s>` pushes a status line to the control port every
+//! `interval` seconds, so a monitoring system can scrape the bridge without
+//! polling a handful of commands:
+//!
+//! ```text
+//! +STATS:<uptime s>,<tx>,<rx>,<errors>,<crc errors>,<rssi dBm|->,<snr dB|->,
+//!        <airtime ms>,<duty budget ms>,<cpu load %>,<loop max us>,<isr max us>
+//! ```
+//!
+//! (one line).  The counts are LoRa frames since boot, RSSI and SNR those
+//! of the last packet received, the airtime the total spent transmitting
+//! and the budget the high-power airtime left before the duty-cycle
+//! derating (see [`crate::derate`]) caps the power.  The CPU load, to a
+//! tenth of a percent, and the longest main-loop pass and interrupt
+//! handler cover the time since the previous report (see [`crate::load`]).
+//!
+//! `AT+STATS=<interval s>,BIN`, or [`Op::Telemetry`](crate::host::Op) from
+//! a host application, sends the same values as a binary protocol frame
+//! instead: a [`Reply::Telemetry`](crate::host::Reply) with id `0`.
+//! `AT+STATS=0` stops the stream.  It is not persisted.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::host::Telemetry;
+
+/// Longest streaming interval accepted.
+pub const MAX_INTERVAL_S: u32 = 3_600;
+
+/// How the stream is framed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Framing {
+  /// `+STATS:` lines.
+  Text,
+  /// Binary host protocol frames.
+  Binary,
+}
+
+/// Streaming state.
+pub struct Stream {
+  /// Milliseconds between reports, `0` for off.
+  interval_ms: u32,
+  framing: Framing,
+  last_ms: u32,
+}
+
+impl Stream {
+  pub fn new() -> Self {
+    Self {
+      interval_ms: 0,
+      framing: Framing::Text,
+      last_ms: 0,
+    }
+  }
+
+  /// Accept `0` (off) up to [`MAX_INTERVAL_S`].
+  pub fn valid_interval(interval_s: u32) -> bool {
+    interval_s <= MAX_INTERVAL_S
+  }
+
+  /// Report every `interval_s` seconds, starting now; `0` stops.
+  pub fn set(&mut self, interval_s: u32, framing: Framing, now: u32) {
+    defmt::info!("[telemetry] {} every {} s", framing, interval_s);
+    self.interval_ms = interval_s * 1_000;
+    self.framing = framing;
+    // The first report is due straight away.
+    self.last_ms = now.wrapping_sub(self.interval_ms);
+  }
+
+  /// The framing of a report that is due.
+  pub fn due(&mut self, now: u32) -> Option<Framing> {
+    if self.interval_ms == 0 || now.wrapping_sub(self.last_ms) < self.interval_ms {
+      return None;
+    }
+    self.last_ms = now;
+    Some(self.framing)
+  }
+}
+
+impl Default for Stream {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The `+STATS:` line of `telemetry`.
+pub fn report(telemetry: &Telemetry) -> String<128> {
+  let mut line = String::new();
+  let _ = write!(
+    line,
+    "+STATS:{},{},{},{},{},",
+    telemetry.uptime_ms / 1_000,
+    telemetry.lora_tx,
+    telemetry.lora_rx,
+    telemetry.errors,
+    telemetry.crc_errors
+  );
+  let _ = match (telemetry.rssi_dbm, telemetry.snr_db) {
+    (Some(rssi), Some(snr)) => write!(line, "{},{},", rssi, snr),
+    _ => write!(line, "-,-,"),
+  };
+  let _ = write!(
+    line,
+    "{},{},{}.{},{},{}\r\n",
+    telemetry.airtime_ms,
+    telemetry.duty_budget_ms,
+    telemetry.cpu_load_permille / 10,
+    telemetry.cpu_load_permille % 10,
+    telemetry.loop_max_us,
+    telemetry.isr_max_us
+  );
+  line
+}
@@ -0,0 +1,120 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/terminal.rs - 收发数据终端视图
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Line buffer of bridged traffic for the OLED terminal page.
+//!
+//! Every frame starts a new line marked with its direction, `>` for USB →
+//! LoRa and `<` for LoRa → USB.  Text frames are shown as text, wrapped at
+//! the screen width and split at line feeds.  A frame with any byte that is
+//! not printable ASCII, CR, LF or tab is shown as one line of hex:
+//!
+//! ```text
+//! > hello
+//! < 01 A0 FF 00 3C +12
+//! ```
+//!
+//! Only the newest `ROWS` lines are kept.
+
+use core::fmt::Write;
+
+use heapless::{Deque, String};
+
+/// Bytes of a binary frame shown in hex.
+const HEX_BYTES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Direction {
+  /// USB → LoRa.
+  Tx,
+  /// LoRa → USB.
+  Rx,
+}
+
+impl Direction {
+  fn marker(self) -> &'static str {
+    match self {
+      Direction::Tx => "> ",
+      Direction::Rx => "< ",
+    }
+  }
+}
+
+pub struct Terminal<const ROWS: usize, const COLUMNS: usize> {
+  lines: Deque<String<COLUMNS>, ROWS>,
+}
+
+impl<const ROWS: usize, const COLUMNS: usize> Terminal<ROWS, COLUMNS> {
+  pub fn new() -> Self {
+    Self {
+      lines: Deque::new(),
+    }
+  }
+
+  /// Add a bridged frame.
+  pub fn push(&mut self, direction: Direction, data: &[u8]) {
+    let mut line = String::new();
+    let _ = line.push_str(direction.marker());
+    let text = data
+      .iter()
+      .all(|&byte| byte.is_ascii_graphic() || matches!(byte, b' ' | b'\r' | b'\n' | b'\t'));
+    if !text {
+      for byte in data.iter().take(HEX_BYTES) {
+        let _ = write!(line, "{:02X} ", byte);
+      }
+      if data.len() > HEX_BYTES {
+        let _ = write!(line, "+{}", data.len() - HEX_BYTES);
+      }
+      self.commit(line);
+      return;
+    }
+
+    for &byte in data {
+      match byte {
+        b'\r' => continue,
+        b'\n' => {
+          self.commit(core::mem::take(&mut line));
+          let _ = line.push_str("  ");
+          continue;
+        }
+        _ => {}
+      }
+      if line.len() == COLUMNS {
+        self.commit(core::mem::take(&mut line));
+        let _ = line.push_str("  ");
+      }
+      let _ = line.push(if byte == b'\t' { ' ' } else { byte as char });
+    }
+    // A trailing line feed leaves only the continuation indent.
+    if !line.trim_end().is_empty() {
+      self.commit(line);
+    }
+  }
+
+  /// Lines on screen, oldest first.
+  pub fn lines(&self) -> impl Iterator<Item = &String<COLUMNS>> {
+    self.lines.iter()
+  }
+
+  fn commit(&mut self, line: String<COLUMNS>) {
+    if self.lines.is_full() {
+      self.lines.pop_front();
+    }
+    let _ = self.lines.push_back(line);
+  }
+}
+
+impl<const ROWS: usize, const COLUMNS: usize> Default for Terminal<ROWS, COLUMNS> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
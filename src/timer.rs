@@ -0,0 +1,88 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/timer.rs - 系统节拍计时
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Monotonic millisecond time base driven by SysTick, plus the DWT cycle
+//! counter for microsecond timing.
+//!
+//! The counter wraps after ~49 days; always compare instants with
+//! [`elapsed_ms`] (wrapping subtraction) rather than `<`.  The cycle
+//! counter wraps within a minute and is only meant for short waits.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::{DCB, DWT, SYST};
+use cortex_m_rt::exception;
+
+static MILLIS: AtomicU32 = AtomicU32::new(0);
+static CYCLES_PER_US: AtomicU32 = AtomicU32::new(1);
+
+/// Start the 1 kHz SysTick interrupt and the cycle counter.
+pub fn init(mut syst: SYST, mut dcb: DCB, mut dwt: DWT, sysclk_hz: u32) {
+  syst.set_clock_source(SystClkSource::Core);
+  syst.set_reload(sysclk_hz / 1_000 - 1);
+  syst.clear_current();
+  syst.enable_counter();
+  syst.enable_interrupt();
+
+  CYCLES_PER_US.store(sysclk_hz / 1_000_000, Ordering::Relaxed);
+  dcb.enable_trace();
+  dwt.enable_cycle_counter();
+}
+
+/// Milliseconds since [`init`].
+pub fn now_ms() -> u32 {
+  MILLIS.load(Ordering::Relaxed)
+}
+
+/// Milliseconds elapsed since the instant `since`.
+pub fn elapsed_ms(since: u32) -> u32 {
+  now_ms().wrapping_sub(since)
+}
+
+/// Current value of the core cycle counter.
+pub fn now_cycles() -> u32 {
+  DWT::cycle_count()
+}
+
+/// Core cycles per microsecond.
+pub fn cycles_per_us() -> u32 {
+  CYCLES_PER_US.load(Ordering::Relaxed)
+}
+
+/// Microseconds in `cycles` core cycles.
+pub fn cycles_to_us(cycles: u32) -> u32 {
+  cycles / CYCLES_PER_US.load(Ordering::Relaxed)
+}
+
+/// Advance the cycle instant `from` by `us` microseconds.
+pub fn after_us(from: u32, us: u32) -> u32 {
+  from.wrapping_add(us * CYCLES_PER_US.load(Ordering::Relaxed))
+}
+
+/// Whether the cycle instant `deadline` has passed.
+pub fn cycles_reached(deadline: u32) -> bool {
+  now_cycles().wrapping_sub(deadline) as i32 >= 0
+}
+
+/// Busy-wait until the cycle instant `deadline`.
+pub fn wait_until_cycles(deadline: u32) {
+  while !cycles_reached(deadline) {}
+}
+
+#[exception]
+fn SysTick() {
+  let _isr = crate::load::Isr::start();
+  let now = MILLIS.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+  crate::led::tick(now);
+}
@@ -0,0 +1,326 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/timesync.rs - GPS 授时与节点间时间同步
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! GPS-disciplined network time.
+//!
+//! Bridges share a network time, milliseconds since 2000-01-01 UTC, kept
+//! as an offset to the local monotonic clock.  A bridge with a GPS fix
+//! takes it from every RMC sentence, a bridge with an RTC from the RTC
+//! (see [`crate::rtc`]); either broadcasts it every
+//! [`BROADCAST_INTERVAL_MS`] in a control frame:
+//!
+//! ```text
+//! [0x60][network ms u64 LE][source u8]
+//! ```
+//!
+//! stamped just before the transmission starts, `source` `0` for GPS and
+//! `1` for an RTC.  Older bridges leave out `source`, meaning GPS.  Other
+//! bridges set their clock from it, corrected by the airtime of the frame,
+//! the same way TDMA nodes correct the master's sync.  What remains is the
+//! time between RxDone and the main loop noticing it, a few milliseconds.
+//! A clock not refreshed for [`HOLDOVER_MS`] is dropped; a 20 ppm crystal
+//! drifts about a millisecond in that time.  A bridge with its own fix
+//! ignores time frames, and a bridge following GPS time over the air
+//! ignores RTC time.  An RTC bridge that hears another one follows it and
+//! stops broadcasting, so the network settles on one time; its RTC is then
+//! set from it.
+//!
+//! A bridge without time sends `[0x61]` every [`REQUEST_INTERVAL_MS`]; a
+//! bridge with GPS or RTC time answers with its next broadcast at once,
+//! so a bridge has time within seconds of starting.
+//!
+//! There is no PPS input, so network time trails UTC by the GPS module's
+//! NMEA latency, typically some tens of milliseconds, but is the same on
+//! every node.  With network time the TDMA master starts its cycles on
+//! multiples of the cycle length and nodes keep their slots without
+//! hearing every sync (see [`crate::tdma`]); packet-forwarder records
+//! carry it as their `time` field.  `AT+TIME?` reports it.  Time frames
+//! need the address header.
+//!
+//! A battery-backed RTC, when fitted, keeps the clock between GPS fixes
+//! and time frames, and across power cuts; either of them overrides it.
+//! See [`crate::rtc`].
+//!
+//! Without the `gps` feature a bridge only follows time frames.
+
+#![cfg_attr(not(feature = "gps"), allow(dead_code))]
+
+use core::fmt::Write;
+
+use heapless::Vec;
+
+use crate::gps;
+
+const TYPE_TIME: u8 = 0x60;
+const TYPE_REQUEST: u8 = 0x61;
+/// Longest time transfer message.
+pub const MESSAGE_LEN: usize = 10;
+/// Interval between time broadcasts of a GPS or RTC bridge.
+pub const BROADCAST_INTERVAL_MS: u32 = 10_000;
+/// Interval between time requests of a bridge without time.
+pub const REQUEST_INTERVAL_MS: u32 = 15_000;
+/// How long a clock stays valid without a refresh.
+pub const HOLDOVER_MS: u32 = 60_000;
+
+/// Days from 1970-01-01 to 2000-01-01.
+const EPOCH_DAYS: u32 = 10_957;
+const DAY_MS: u64 = 86_400_000;
+
+/// Where the network time came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Source {
+  Gps,
+  Radio,
+  Rtc,
+}
+
+/// Time message of a GPS or RTC bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Message {
+  pub network_ms: u64,
+  /// [`Source::Gps`] or [`Source::Rtc`].
+  pub source: Source,
+}
+
+impl Message {
+  pub fn encode(&self) -> [u8; MESSAGE_LEN] {
+    let mut bytes = [0u8; MESSAGE_LEN];
+    bytes[0] = TYPE_TIME;
+    bytes[1..9].copy_from_slice(&self.network_ms.to_le_bytes());
+    bytes[9] = match self.source {
+      Source::Rtc => 1,
+      _ => 0,
+    };
+    bytes
+  }
+
+  pub fn decode(payload: &[u8]) -> Option<Self> {
+    let (time, source) = match payload {
+      [TYPE_TIME, time @ ..] if time.len() == 8 => (time, Source::Gps),
+      [TYPE_TIME, time @ .., 0] => (time, Source::Gps),
+      [TYPE_TIME, time @ .., 1] => (time, Source::Rtc),
+      _ => return None,
+    };
+    Some(Self {
+      network_ms: u64::from_le_bytes(time.try_into().ok()?),
+      source,
+    })
+  }
+}
+
+/// Time request of a bridge without time.
+pub const REQUEST: [u8; 1] = [TYPE_REQUEST];
+
+/// Whether a control payload is a time request.
+pub fn is_request(payload: &[u8]) -> bool {
+  payload == REQUEST
+}
+
+/// What a bridge sends to share time.
+pub enum Transfer {
+  Time(Message),
+  Request,
+}
+
+impl Transfer {
+  pub fn encode(&self) -> Vec<u8, MESSAGE_LEN> {
+    let mut bytes = Vec::new();
+    let _ = match self {
+      Transfer::Time(message) => bytes.extend_from_slice(&message.encode()),
+      Transfer::Request => bytes.extend_from_slice(&REQUEST),
+    };
+    bytes
+  }
+}
+
+/// Network time as an anchor on the local clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+  /// Local time, the network time then and where it came from.
+  anchor: Option<(u32, u64, Source)>,
+  /// Where the sender of the last time message had its time from.
+  upstream: Source,
+  last_broadcast_ms: Option<u32>,
+  last_request_ms: Option<u32>,
+}
+
+impl Clock {
+  pub fn new() -> Self {
+    Self {
+      anchor: None,
+      upstream: Source::Gps,
+      last_broadcast_ms: None,
+      last_request_ms: None,
+    }
+  }
+
+  /// Network time at local time `now`, while the clock is valid.
+  pub fn now(&self, now: u32) -> Option<u64> {
+    let (local_ms, network_ms, _) = self.anchor?;
+    let elapsed_ms = now.wrapping_sub(local_ms);
+    (elapsed_ms < HOLDOVER_MS).then_some(network_ms + elapsed_ms as u64)
+  }
+
+  /// Where the time came from, while the clock is valid.
+  pub fn source(&self, now: u32) -> Option<Source> {
+    self.now(now).and(self.anchor.map(|(_, _, source)| source))
+  }
+
+  /// Set the clock from a GPS fix reported at `now`.
+  pub fn on_fix(&mut self, fix: &gps::Fix, now: u32) {
+    if self.source(now) != Some(Source::Gps) {
+      defmt::info!("[time] synced to GPS");
+    }
+    self.anchor = Some((now, epoch_ms(&fix.date, &fix.time), Source::Gps));
+  }
+
+  /// Set the clock from a time message received at `now` that took
+  /// `airtime_ms` on air.
+  pub fn on_message(&mut self, message: &Message, airtime_ms: u32, now: u32) {
+    match self.source(now) {
+      Some(Source::Gps) => return,
+      Some(Source::Radio) if self.upstream == Source::Gps && message.source != Source::Gps => {
+        return;
+      }
+      Some(Source::Radio) => {}
+      Some(Source::Rtc) | None => defmt::info!("[time] synced over the air"),
+    }
+    let network_ms = message.network_ms + airtime_ms as u64;
+    self.anchor = Some((now, network_ms, Source::Radio));
+    self.upstream = message.source;
+  }
+
+  /// Answer a time request with the next broadcast, if there is time to
+  /// give.
+  pub fn on_request(&mut self) {
+    self.last_broadcast_ms = None;
+  }
+
+  /// Set the clock from the RTC, which read `network_ms` at `now`, unless
+  /// GPS or radio time is valid.
+  pub fn on_rtc(&mut self, network_ms: u64, now: u32) {
+    match self.source(now) {
+      Some(Source::Gps | Source::Radio) => return,
+      Some(Source::Rtc) => {}
+      None => defmt::info!("[time] set from the RTC"),
+    }
+    self.anchor = Some((now, network_ms, Source::Rtc));
+  }
+
+  /// GPS or RTC bridge: the time message to send now, if one is due.
+  fn broadcast_due(&mut self, now: u32) -> Option<Message> {
+    let source = self.source(now).filter(|source| *source != Source::Radio)?;
+    if self
+      .last_broadcast_ms
+      .is_some_and(|last| now.wrapping_sub(last) < BROADCAST_INTERVAL_MS)
+    {
+      return None;
+    }
+    self.last_broadcast_ms = Some(now);
+    Some(Message {
+      network_ms: self.now(now)?,
+      source,
+    })
+  }
+
+  /// Bridge without time: whether to ask for it now.
+  fn request_due(&mut self, now: u32) -> bool {
+    if self.now(now).is_some()
+      || self
+        .last_request_ms
+        .is_some_and(|last| now.wrapping_sub(last) < REQUEST_INTERVAL_MS)
+    {
+      return false;
+    }
+    self.last_request_ms = Some(now);
+    true
+  }
+
+  /// What to send now to share time, if anything.
+  pub fn transfer_due(&mut self, now: u32) -> Option<Transfer> {
+    match self.broadcast_due(now) {
+      Some(message) => Some(Transfer::Time(message)),
+      None => self.request_due(now).then_some(Transfer::Request),
+    }
+  }
+}
+
+impl Default for Clock {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Network time of a UTC date and time.
+pub fn epoch_ms(date: &gps::Date, time: &gps::Time) -> u64 {
+  // Days from 1970-01-01 (H. Hinnant's days_from_civil), years >= 2000.
+  let (month, day) = (date.month as u32, date.day as u32);
+  let year = date.year as u32 - (month <= 2) as u32;
+  let era = year / 400;
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  let days = era * 146_097 + day_of_era - 719_468 - EPOCH_DAYS;
+  let seconds = time.hours as u64 * 3_600 + time.minutes as u64 * 60 + time.seconds as u64;
+  days as u64 * DAY_MS + seconds * 1_000
+}
+
+/// UTC date and time of network time, to the second.
+pub fn civil(network_ms: u64) -> (gps::Date, gps::Time) {
+  // H. Hinnant's civil_from_days.
+  let days = (network_ms / DAY_MS) as u32 + EPOCH_DAYS + 719_468;
+  let era = days / 146_097;
+  let day_of_era = days - era * 146_097;
+  let year_of_era =
+    (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+  let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+  let year = year_of_era + era * 400 + (month <= 2) as u32;
+
+  let seconds_of_day = (network_ms % DAY_MS / 1_000) as u32;
+  let date = gps::Date {
+    year: year as u16,
+    month: month as u8,
+    day: day as u8,
+  };
+  let time = gps::Time {
+    hours: (seconds_of_day / 3_600) as u8,
+    minutes: (seconds_of_day / 60 % 60) as u8,
+    seconds: (seconds_of_day % 60) as u8,
+  };
+  (date, time)
+}
+
+/// Day of the week of network time, 1 for Monday to 7 for Sunday.
+pub fn weekday(network_ms: u64) -> u8 {
+  // 2000-01-01 was a Saturday.
+  ((network_ms / DAY_MS + 5) % 7) as u8 + 1
+}
+
+/// Write network time in ISO 8601, `2026-10-15T12:34:56.789Z`.
+pub fn write_iso(out: &mut impl Write, network_ms: u64) -> core::fmt::Result {
+  let (date, time) = civil(network_ms);
+  write!(
+    out,
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+    date.year,
+    date.month,
+    date.day,
+    time.hours,
+    time.minutes,
+    time.seconds,
+    network_ms % 1_000
+  )
+}
@@ -0,0 +1,142 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/trim.rs - 温度补偿频率微调
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Temperature-compensated frequency trim.
+//!
+//! A module crystal drifts with temperature; at 433 MHz one ppm is 433 Hz,
+//! which matters once the bandwidth is narrow and the link runs close to
+//! the sensitivity limit.  `AT+TRIM=<ppb/°C>,<reference °C>` sets how far
+//! the module's carrier moves per degree away from the temperature where
+//! it is on frequency, e.g. `AT+TRIM=-300,25` when it drops 0.3 ppm per
+//! degree of warming; `0` turns the trim off.  Every [`SAMPLE_MS`] between
+//! transmissions the temperature is read, from the environment sensor
+//! when there is a reading (see [`crate::sensor`]), otherwise from the
+//! MCU's internal sensor, and the carrier is moved against the expected
+//! drift once that changes by [`STEP_HZ`].
+//!
+//! The setting is persisted; `AT+TRIM?` reports it with the temperature
+//! and the trim applied.  The trim adds to the AFC correction
+//! ([`crate::afc`]).
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::radio;
+
+/// Interval between temperature readings.
+pub const SAMPLE_MS: u32 = 10_000;
+/// Change of the expected drift that moves the trim.
+pub const STEP_HZ: i32 = 50;
+/// Largest coefficient accepted, 5 ppm/°C.
+pub const MAX_PPB_PER_C: i16 = 5_000;
+
+/// Persisted trim configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Config {
+  /// Carrier drift in ppb per °C, `0` for off.
+  pub ppb_per_c: i16,
+  /// Temperature at which the carrier is on frequency.
+  pub reference_c: i8,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      ppb_per_c: 0,
+      reference_c: 25,
+    }
+  }
+}
+
+impl Config {
+  /// Accept coefficients up to [`MAX_PPB_PER_C`] either way.
+  pub fn new(ppb_per_c: i16, reference_c: i8) -> Option<Self> {
+    (ppb_per_c.unsigned_abs() <= MAX_PPB_PER_C as u16).then_some(Self {
+      ppb_per_c,
+      reference_c,
+    })
+  }
+
+  /// Trim against the drift of a carrier on `frequency_hz` at
+  /// `temperature_cdeg` hundredths of a degree.
+  pub fn trim_hz(&self, frequency_hz: u32, temperature_cdeg: i32) -> i32 {
+    let delta_cdeg = (temperature_cdeg - self.reference_c as i32 * 100) as i64;
+    let drift = frequency_hz as i64 * self.ppb_per_c as i64 * delta_cdeg / 100_000_000_000;
+    -drift as i32
+  }
+}
+
+/// Sampling schedule and the trim applied.
+pub struct Trim {
+  last_ms: u32,
+  temperature_cdeg: Option<i32>,
+  trim_hz: i32,
+}
+
+impl Trim {
+  pub fn new(now: u32) -> Self {
+    Self {
+      // The first reading is due straight away.
+      last_ms: now.wrapping_sub(SAMPLE_MS),
+      temperature_cdeg: None,
+      trim_hz: 0,
+    }
+  }
+
+  /// Take the next reading straight away, e.g. after the configuration
+  /// changed.
+  pub fn restart(&mut self, now: u32) {
+    self.last_ms = now.wrapping_sub(SAMPLE_MS);
+  }
+
+  /// Whether a temperature reading is due.
+  pub fn due(&mut self, now: u32) -> bool {
+    if now.wrapping_sub(self.last_ms) < SAMPLE_MS {
+      return false;
+    }
+    self.last_ms = now;
+    true
+  }
+
+  /// Account a temperature reading.  Returns whether the radio has to be
+  /// applied again for a new trim.
+  pub fn update(&mut self, config: &Config, frequency_hz: u32, temperature_cdeg: i32) -> bool {
+    self.temperature_cdeg = Some(temperature_cdeg);
+    let trim_hz = config.trim_hz(frequency_hz, temperature_cdeg);
+    // Turning the trim off always takes effect.
+    if trim_hz == self.trim_hz || (trim_hz != 0 && (trim_hz - self.trim_hz).abs() < STEP_HZ) {
+      return false;
+    }
+    defmt::info!("[trim] {} Hz at {} c°C", trim_hz, temperature_cdeg);
+    self.trim_hz = trim_hz;
+    radio::set_frequency_trim(trim_hz);
+    true
+  }
+
+  /// `+TRIM:<ppb/°C>,<reference °C>,<temperature °C|->,<trim Hz>`.
+  pub fn report(&self, config: &Config) -> String<48> {
+    let mut line = String::new();
+    let _ = write!(line, "+TRIM:{},{},", config.ppb_per_c, config.reference_c);
+    let _ = match self.temperature_cdeg {
+      Some(cdeg) => {
+        let sign = if cdeg < 0 { "-" } else { "" };
+        let cdeg = cdeg.unsigned_abs();
+        write!(line, "{}{}.{:02}", sign, cdeg / 100, cdeg % 100)
+      }
+      None => write!(line, "-"),
+    };
+    let _ = write!(line, ",{}\r\n", self.trim_hz);
+    line
+  }
+}
@@ -0,0 +1,172 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/txqueue.rs - 带优先级的发送队列
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Priority queue for outgoing frames.
+//!
+//! Frames are encoded and sealed where they arise, into packets from the
+//! pool ([`crate::packet`]), and queued here; the main loop sends one per
+//! pass, the oldest frame of the highest [`Priority`] first, so link
+//! maintenance is never stuck behind a backlog of host data:
+//!
+//! | Priority  | Frames                                     | Depth | When full          |
+//! |-----------|--------------------------------------------|-------|--------------------|
+//! | `Control` | radio negotiation, ping probes and replies | 3     | oldest dropped     |
+//! | `Data`    | host data, benchmark and PER test frames   | 2     | not queued         |
+//! | `Beacon`  | beacons and sensor reports                 | 1     | oldest replaced    |
+//!
+//! Host data is never dropped here: while its share is full the packetizer
+//! keeps the frame and host data waits in the port queue.  A newer beacon
+//! replaces one still waiting, since only the latest reading matters.
+//!
+//! Frames with timing of their own go straight out instead: pairing, TDMA
+//! sync, network time, relayed frames, LoRaWAN and the binary protocol's
+//! `Transmit`.  `AT+TXQ?` answers `+TXQ:<queued>,<dropped>` per priority,
+//! highest first, followed by `<in use>,<peak>` of the packet pool.
+
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+
+use crate::packet::{self, Packet};
+
+/// Frames queued at most, all priorities together.
+pub const DEPTH: usize = 6;
+
+/// Precedence of a queued frame, highest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum Priority {
+  Control,
+  Data,
+  Beacon,
+}
+
+impl Priority {
+  const ALL: [Priority; 3] = [Priority::Control, Priority::Data, Priority::Beacon];
+
+  /// Frames of this priority queued at most.
+  pub fn depth(self) -> usize {
+    match self {
+      Priority::Control => 3,
+      Priority::Data => 2,
+      Priority::Beacon => 1,
+    }
+  }
+}
+
+/// What a frame carries, for the bookkeeping once it was sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Origin {
+  /// A control message between the bridges.
+  Control,
+  /// Host data; the payload follows the `offset`-byte header.
+  Host { offset: usize, len: usize },
+  /// A beacon line of `len` bytes.
+  Beacon(usize),
+  /// A sensor report of `len` bytes.
+  Sensor(usize),
+}
+
+/// A sealed frame ready for the radio.
+pub struct Entry {
+  pub priority: Priority,
+  pub origin: Origin,
+  pub frame: Packet,
+}
+
+pub struct Queue {
+  /// In arrival order.
+  entries: Vec<Entry, DEPTH>,
+  /// Frames dropped per priority since boot.
+  dropped: [u32; 3],
+}
+
+impl Queue {
+  pub fn new() -> Self {
+    Self {
+      entries: Vec::new(),
+      dropped: [0; 3],
+    }
+  }
+
+  /// Whether a frame of `priority` is queued without a drop.
+  pub fn has_room(&self, priority: Priority) -> bool {
+    self.queued(priority) < priority.depth()
+  }
+
+  /// Queue `frame`.  A full share drops the oldest frame of `priority`,
+  /// except for [`Priority::Data`], which is refused; returns whether the
+  /// frame was queued.
+  pub fn push(&mut self, priority: Priority, origin: Origin, frame: Packet) -> bool {
+    if !self.has_room(priority) {
+      if priority == Priority::Data {
+        return false;
+      }
+      if let Some(oldest) = self.entries.iter().position(|e| e.priority == priority) {
+        self.entries.remove(oldest);
+        self.dropped[priority as usize] = self.dropped[priority as usize].wrapping_add(1);
+        defmt::warn!("[txqueue] {} frame dropped", priority);
+      }
+    }
+    self
+      .entries
+      .push(Entry {
+        priority,
+        origin,
+        frame,
+      })
+      .is_ok()
+  }
+
+  /// The frame to send next.
+  pub fn peek(&self) -> Option<&Entry> {
+    Priority::ALL
+      .iter()
+      .find_map(|&priority| self.entries.iter().find(|e| e.priority == priority))
+  }
+
+  /// Remove the frame [`peek`](Self::peek) returned.
+  pub fn pop(&mut self) -> Option<Entry> {
+    let priority = self.peek()?.priority;
+    let index = self.entries.iter().position(|e| e.priority == priority)?;
+    Some(self.entries.remove(index))
+  }
+
+  fn queued(&self, priority: Priority) -> usize {
+    self.entries.iter().filter(|e| e.priority == priority).count()
+  }
+
+  /// `+TXQ:<queued>,<dropped>,...` per priority, highest first, then the
+  /// packets in use and the peak.
+  pub fn report(&self) -> String<64> {
+    let mut line = String::new();
+    let _ = line.push_str("+TXQ:");
+    for (i, &priority) in Priority::ALL.iter().enumerate() {
+      let sep = if i == 0 { "" } else { "," };
+      let _ = write!(
+        line,
+        "{}{},{}",
+        sep,
+        self.queued(priority),
+        self.dropped[priority as usize]
+      );
+    }
+    let _ = write!(line, ",{},{}\r\n", packet::in_use(), packet::peak());
+    line
+  }
+}
+
+impl Default for Queue {
+  fn default() -> Self {
+    Self::new()
+  }
+}
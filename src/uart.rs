@@ -0,0 +1,294 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/uart.rs - USART1 串口数据口
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! USART1 (PA9 TX / PA10 RX) as the bridge data port.
+//!
+//! For MCUs and PLCs without a USB host the bridge data can run over
+//! USART1 at 3.3 V levels, 8N1, instead of the USB data port.  The UART is
+//! used when `AT+PORT=UART` is stored or the strap pin is tied to ground at
+//! boot; `AT+UARTBAUD=<baud>` sets the rate.  Both take effect at the next
+//! reset.  The USB control port keeps working, so the bridge can still be
+//! configured from a PC.
+//!
+//! Like the USB data port, the UART is serviced from its interrupt and
+//! exchanges bytes with the application through two lock-free SPSC queues.
+//! There is no flow control: bytes that arrive while the RX queue is full,
+//! or radio data that does not fit the TX queue, are dropped and counted.
+//! The UART has no DTR either, so bridging is always on.
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::interrupt::Mutex;
+use cortex_m::peripheral::NVIC;
+use heapless::spsc::{Consumer, Producer, Queue};
+
+use crate::board::{UartRx, UartTx};
+use crate::hal::pac::{Interrupt, USART1, interrupt};
+use crate::hal::prelude::*;
+use crate::hal::rcc::Rcc;
+use crate::hal::serial::{self, Serial};
+use crate::load;
+use crate::usb;
+
+/// Baud rate unless changed with `AT+UARTBAUD`.
+pub const DEFAULT_BAUD: u32 = 9600;
+/// Supported rates; USART1 runs from the 72 MHz PCLK2.
+pub const MIN_BAUD: u32 = 1200;
+pub const MAX_BAUD: u32 = 921_600;
+
+/// Bytes buffered from the UART towards the radio.
+const RX_CAPACITY: usize = 512;
+/// Bytes buffered from the radio towards the UART.
+const TX_CAPACITY: usize = 512;
+
+static RX_DROPS: AtomicU32 = AtomicU32::new(0);
+static TX_DROPS: AtomicU32 = AtomicU32::new(0);
+
+static UART: Mutex<RefCell<Option<Isr>>> = Mutex::new(RefCell::new(None));
+
+/// Where the bridge data goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Source {
+  Usb,
+  Uart,
+  /// The USB HID interface, built with `usb-hid`; see [`crate::hid`].
+  Hid,
+}
+
+/// Persisted data port settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Config {
+  pub source: Source,
+  pub baud: u32,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      source: match cfg!(feature = "usb-hid") {
+        true => Source::Hid,
+        false => Source::Usb,
+      },
+      baud: DEFAULT_BAUD,
+    }
+  }
+}
+
+impl Config {
+  pub fn valid_baud(baud: u32) -> bool {
+    (MIN_BAUD..=MAX_BAUD).contains(&baud)
+  }
+}
+
+/// The serial halves together with the ISR ends of the queues.
+struct Isr {
+  rx: serial::Rx<USART1>,
+  tx: serial::Tx<USART1>,
+  rx_queue: Producer<'static, u8, RX_CAPACITY>,
+  tx_queue: Consumer<'static, u8, TX_CAPACITY>,
+}
+
+/// Application end of the UART queues.
+pub struct Port {
+  rx: Consumer<'static, u8, RX_CAPACITY>,
+  tx: Producer<'static, u8, TX_CAPACITY>,
+  baud: u32,
+}
+
+/// Bytes dropped on the UART.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct Stats {
+  /// Received while the RX queue was full.
+  pub rx_drops: u32,
+  /// Radio bytes that did not fit the TX queue.
+  pub tx_drops: u32,
+}
+
+/// Set up USART1 at `baud` and hand it to its interrupt.
+///
+/// Must be called exactly once.
+pub fn init(usart: USART1, tx: UartTx, rx: UartRx, baud: u32, rcc: &mut Rcc) -> Port {
+  let config = serial::Config::default().baudrate(baud.bps());
+  let serial = Serial::new(usart, (tx, rx), config, rcc);
+  let (tx, mut rx) = serial.split();
+  rx.listen();
+
+  let rx_queue: &'static mut Queue<u8, RX_CAPACITY> =
+    cortex_m::singleton!(: Queue<u8, RX_CAPACITY> = Queue::new()).unwrap();
+  let tx_queue: &'static mut Queue<u8, TX_CAPACITY> =
+    cortex_m::singleton!(: Queue<u8, TX_CAPACITY> = Queue::new()).unwrap();
+  let (rx_producer, rx_consumer) = rx_queue.split();
+  let (tx_producer, tx_consumer) = tx_queue.split();
+
+  cortex_m::interrupt::free(|cs| {
+    UART.borrow(cs).replace(Some(Isr {
+      rx,
+      tx,
+      rx_queue: rx_producer,
+      tx_queue: tx_consumer,
+    }));
+  });
+
+  unsafe { NVIC::unmask(Interrupt::USART1) };
+
+  Port {
+    rx: rx_consumer,
+    tx: tx_producer,
+    baud,
+  }
+}
+
+impl Port {
+  /// Take one byte received on the UART.
+  pub fn read_byte(&mut self) -> Option<u8> {
+    self.rx.dequeue()
+  }
+
+  /// Queue bridge data for the UART.  Bytes that do not fit are dropped
+  /// and counted; returns the number of bytes queued.
+  pub fn write(&mut self, data: &[u8]) -> usize {
+    let mut n = 0;
+    for &byte in data {
+      if self.tx.enqueue(byte).is_err() {
+        break;
+      }
+      n += 1;
+    }
+    let dropped = data.len() - n;
+    if dropped > 0 {
+      TX_DROPS.fetch_add(dropped as u32, Ordering::Relaxed);
+    }
+    // The ISR starts the transmitter and keeps it fed.
+    NVIC::pend(Interrupt::USART1);
+    n
+  }
+}
+
+/// The port the bridge data goes through.
+pub enum DataPort {
+  Usb(usb::Bridge),
+  Uart(Port),
+  /// The USB HID interface, which carries the framed protocol only.
+  Hid(usb::Bridge),
+}
+
+impl DataPort {
+  /// Whether there is someone to bridge for: a program holding the USB
+  /// data port open (DTR) on a bus that is not suspended, a configured and
+  /// not suspended device for HID, or always on the UART.
+  pub fn open(&self) -> bool {
+    match self {
+      Self::Usb(_) => usb::host_dtr() && !usb::is_suspended(),
+      Self::Uart(_) => true,
+      Self::Hid(_) => usb::is_configured() && !usb::is_suspended(),
+    }
+  }
+
+  /// Whether the host suspended the USB bus; never on the UART.
+  pub fn suspended(&self) -> bool {
+    matches!(self, Self::Usb(_) | Self::Hid(_)) && usb::is_suspended()
+  }
+
+  /// Drop host data not bridged yet; see [`usb::Bridge::discard`].
+  pub fn discard(&mut self) {
+    if let Self::Usb(bridge) | Self::Hid(bridge) = self {
+      bridge.discard();
+    }
+  }
+
+  pub fn read_byte(&mut self) -> Option<u8> {
+    match self {
+      Self::Usb(bridge) | Self::Hid(bridge) => bridge.read_byte(),
+      Self::Uart(port) => port.read_byte(),
+    }
+  }
+
+  /// Queue bridge data for the host; see [`usb::Bridge::write`] and
+  /// [`Port::write`].
+  pub fn write(&mut self, data: &[u8]) -> usize {
+    match self {
+      Self::Usb(bridge) | Self::Hid(bridge) => bridge.write(data),
+      Self::Uart(port) => port.write(data),
+    }
+  }
+
+  /// Line rate of the port: the UART baud rate, or what the host set on
+  /// the USB data port.  HID has no line coding and counts as the default.
+  pub fn baud(&self) -> u32 {
+    match self {
+      Self::Usb(_) => match usb::host_baud() {
+        0 => DEFAULT_BAUD,
+        baud => baud,
+      },
+      Self::Uart(port) => port.baud,
+      Self::Hid(_) => DEFAULT_BAUD,
+    }
+  }
+
+  /// Bytes towards the host dropped so far.
+  pub fn drops(&self) -> u32 {
+    match self {
+      Self::Usb(_) | Self::Hid(_) => usb::stats().host_drops,
+      Self::Uart(_) => stats().tx_drops,
+    }
+  }
+
+  /// Whether the port only carries the framed protocol, whatever the line
+  /// coding says.
+  pub fn framed_only(&self) -> bool {
+    matches!(self, Self::Hid(_))
+  }
+}
+
+/// Snapshot of the UART drop counters.
+pub fn stats() -> Stats {
+  Stats {
+    rx_drops: RX_DROPS.load(Ordering::Relaxed),
+    tx_drops: TX_DROPS.load(Ordering::Relaxed),
+  }
+}
+
+impl Isr {
+  fn service(&mut self) {
+    // Errors (overrun, framing, noise) clear themselves on the read.
+    while let Ok(byte) = self.rx.read() {
+      if self.rx_queue.enqueue(byte).is_err() {
+        RX_DROPS.fetch_add(1, Ordering::Relaxed);
+      }
+    }
+    while let Some(&byte) = self.tx_queue.peek() {
+      if self.tx.write_u8(byte).is_err() {
+        break;
+      }
+      self.tx_queue.dequeue();
+    }
+    // TXE keeps interrupting while the register is empty, so it is only
+    // enabled while there is something left to send.
+    if self.tx_queue.ready() {
+      self.tx.listen();
+    } else {
+      self.tx.unlisten();
+    }
+  }
+}
+
+#[interrupt]
+fn USART1() {
+  let _isr = load::Isr::start();
+  cortex_m::interrupt::free(|cs| {
+    if let Some(uart) = UART.borrow(cs).borrow_mut().as_mut() {
+      uart.service();
+    }
+  });
+}
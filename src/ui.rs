@@ -0,0 +1,796 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/ui.rs - OLED 状态页面
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! OLED status pages.
+//!
+//! The screen shows one of a few pages (radio configuration, traffic
+//! counters, recent bridged data, last packet quality, RSSI trend, errors,
+//! uptime and, with the `gps` feature, the GPS fix), moving to the next
+//! every [`PAGE_INTERVAL_MS`] or when the button is tapped.  Short notices
+//! such as "Pairing..." and summaries such as the settings just changed
+//! temporarily replace the page.
+//!
+//! The top line of every page and notice is composed of the page title and
+//! a status bar: USB connection, radio activity (`T` transmitting, `R`
+//! receiving, `-` idle), a link symbol once paired and four signal bars
+//! derived from the RSSI of the last received packet.  With battery
+//! monitoring a battery icon goes in front of it and titles are cut two
+//! characters shorter.
+//!
+//! Rendering never blocks the main loop for long: page text is regenerated
+//! every [`REFRESH_MS`] into RAM, and each [`Ui::poll`] redraws at most one
+//! changed text line.  The SSD1306 driver only flushes the area touched since
+//! the last flush, so a pass costs one 12-pixel band on the I2C bus instead
+//! of the whole 1 KiB frame.  The RSSI trend graph is redrawn the same way,
+//! one band at a time, clipped to the band.
+//!
+//! To save power and burn-in the screen dims and then blanks after a
+//! configurable time without activity (see [`ScreenPower`]); any event wakes
+//! it again.  While the host has the USB bus suspended it stays dimmed, and
+//! the status bar shows the sleeping radio as `z`.
+//!
+//! For walk tests the big-digit mode replaces the pages with the RSSI and
+//! SNR of the last packet in seven-segment digits half the panel tall, which
+//! stay readable at arm's length where the 6x10 font does not.
+
+use core::fmt::Write;
+
+use embedded_graphics::{
+  mono_font::{MonoTextStyle, ascii::FONT_6X10},
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+  text::{Baseline, Text},
+};
+use heapless::{Deque, String, Vec};
+
+use crate::display::{HEIGHT, WIDTH};
+use crate::gps;
+use crate::radio::{PacketStatus, RadioParams};
+use crate::terminal::{Direction, Terminal};
+
+/// Time each page stays on screen.
+pub const PAGE_INTERVAL_MS: u32 = 4_000;
+/// Interval between regenerating the page text.
+pub const REFRESH_MS: u32 = 500;
+/// Default time a notice stays on screen.
+pub const NOTICE_MS: u32 = 3_000;
+/// Time a summary stays on screen.
+pub const SUMMARY_MS: u32 = 5_000;
+/// RSSI samples kept for the trend page, one bar each.
+pub const HISTORY: usize = 64;
+const HISTORY_BAR_WIDTH: u32 = 2;
+/// RSSI at the bottom and at the top of a bar graph.
+const GRAPH_FLOOR_DBM: i16 = -130;
+const GRAPH_CEILING_DBM: i16 = -30;
+
+/// Text lines a page is composed of.
+const PAGE_LINES: usize = 5;
+/// Text lines that fit the panel; on short panels the last page lines are
+/// not shown.
+const LINES: usize = (HEIGHT / LINE_HEIGHT) as usize;
+/// Characters per line with the 6x10 font.
+const COLUMNS: usize = 21;
+/// Characters of the title left of the status bar.
+const TITLE_COLUMNS: usize = 12;
+/// Left edge of the status bar icons.
+const STATUS_LEFT: i32 = 78;
+/// Rows per text line; short panels pack the 10-row font tighter.
+#[cfg(feature = "ssd1306-128x32")]
+const LINE_HEIGHT: u32 = 10;
+#[cfg(not(feature = "ssd1306-128x32"))]
+const LINE_HEIGHT: u32 = 12;
+
+type Line = String<COLUMNS>;
+
+/// Persisted screen brightness and inactivity timeouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct ScreenPower {
+  /// SSD1306 contrast while active.
+  pub contrast: u8,
+  /// Seconds without activity before dimming; `0` never dims.
+  pub dim_after_s: u16,
+  /// Seconds without activity before blanking; `0` never blanks.
+  pub off_after_s: u16,
+}
+
+impl Default for ScreenPower {
+  fn default() -> Self {
+    // The contrast the SSD1306 driver starts with.
+    Self {
+      contrast: 0x5F,
+      dim_after_s: 0,
+      off_after_s: 0,
+    }
+  }
+}
+
+/// State the panel should be put in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Screen {
+  On { contrast: u8 },
+  Off,
+}
+
+/// What the radio is doing, for the status bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum RadioState {
+  Idle,
+  Receiving,
+  Transmitting,
+  /// Asleep while the USB bus is suspended.
+  Asleep,
+}
+
+/// Icons of the status bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StatusBar {
+  usb: bool,
+  radio: RadioState,
+  paired: bool,
+  /// Signal bars, 0 to 4.
+  bars: u8,
+  /// Battery icon segments, without battery monitoring none.
+  battery: Option<u8>,
+}
+
+impl StatusBar {
+  fn new(snapshot: &Snapshot) -> Self {
+    let bars = match snapshot.last_packet.map(|status| status.rssi_dbm) {
+      Some(rssi) if rssi >= -70 => 4,
+      Some(rssi) if rssi >= -85 => 3,
+      Some(rssi) if rssi >= -100 => 2,
+      Some(rssi) if rssi >= -115 => 1,
+      _ => 0,
+    };
+    Self {
+      usb: snapshot.usb,
+      radio: snapshot.radio_state,
+      paired: snapshot.paired,
+      bars,
+      battery: snapshot.battery,
+    }
+  }
+}
+
+/// What one line band shows.
+#[derive(Clone, PartialEq, Eq)]
+enum Content {
+  Text(Line),
+  /// Page title followed by the status bar.
+  Header(Line, StatusBar),
+  /// Part of the RSSI graph, at the given history revision.
+  Graph(u32),
+  /// Part of the big-digit reading.
+  Big(Option<PacketStatus>),
+}
+
+impl Default for Content {
+  fn default() -> Self {
+    Content::Text(Line::new())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Page {
+  Radio,
+  Traffic,
+  Terminal,
+  Signal,
+  Trend,
+  Errors,
+  Uptime,
+  Gps,
+}
+
+impl Page {
+  fn next(self) -> Self {
+    match self {
+      Page::Radio => Page::Traffic,
+      Page::Traffic => Page::Terminal,
+      Page::Terminal => Page::Signal,
+      Page::Signal => Page::Trend,
+      Page::Trend => Page::Errors,
+      Page::Errors => Page::Uptime,
+      Page::Uptime if cfg!(feature = "gps") => Page::Gps,
+      Page::Uptime | Page::Gps => Page::Radio,
+    }
+  }
+
+  fn title(self) -> &'static str {
+    match self {
+      Page::Radio => "Radio",
+      Page::Traffic => "Traffic",
+      Page::Terminal => "Terminal",
+      Page::Signal => "Signal",
+      Page::Trend => "RSSI trend",
+      Page::Errors => "Errors",
+      Page::Uptime => "Uptime",
+      Page::Gps => "GPS",
+    }
+  }
+}
+
+/// What the pages show, sampled by the main loop when a refresh is due.
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot {
+  pub radio: RadioParams,
+  pub local: u16,
+  pub peer: u16,
+  pub adr: bool,
+  pub tx_frames: u32,
+  pub rx_frames: u32,
+  pub last_packet: Option<PacketStatus>,
+  pub errors: u32,
+  pub log_dropped: u32,
+  pub uptime_ms: u32,
+  pub usb: bool,
+  pub radio_state: RadioState,
+  pub paired: bool,
+  pub gps: Option<gps::Fix>,
+  /// Battery icon segments, see [`crate::battery::Monitor::level`].
+  pub battery: Option<u8>,
+}
+
+pub struct Ui {
+  page: Page,
+  page_since: u32,
+  next_refresh: u32,
+  /// Lines of a notice or summary and when it ends.
+  notice: Option<([Line; PAGE_LINES], u32)>,
+  /// The screen belongs to someone else until this time.
+  held_until: Option<u32>,
+  /// Content to show on each line.
+  wanted: [Content; LINES],
+  /// Content currently on each line; `None` when unknown.
+  shown: [Option<Content>; LINES],
+  /// Recent RSSI values, oldest first.
+  history: Deque<i16, HISTORY>,
+  /// Bumped on every new RSSI value so the graph is redrawn.
+  revision: u32,
+  /// Big-digit mode instead of the pages.
+  big: bool,
+  /// Recent bridged data for the terminal page.
+  terminal: Terminal<{ LINES - 1 }, COLUMNS>,
+  power: ScreenPower,
+  last_activity: u32,
+  /// Panel state last handed out by [`Ui::screen_change`].
+  screen: Screen,
+  /// Dimmed whatever the activity, see [`Ui::set_suspended`].
+  suspended: bool,
+}
+
+impl Ui {
+  pub fn new(now: u32) -> Self {
+    Self {
+      page: Page::Radio,
+      page_since: now,
+      next_refresh: now,
+      notice: None,
+      held_until: None,
+      wanted: Default::default(),
+      shown: Default::default(),
+      history: Deque::new(),
+      revision: 0,
+      big: false,
+      terminal: Terminal::new(),
+      power: ScreenPower::default(),
+      last_activity: now,
+      screen: Screen::On {
+        contrast: ScreenPower::default().contrast,
+      },
+      suspended: false,
+    }
+  }
+
+  pub fn set_power(&mut self, power: ScreenPower, now: u32) {
+    self.power = power;
+    self.last_activity = now;
+  }
+
+  /// Restart the inactivity timer.  Returns whether the screen was blank,
+  /// so the caller can swallow the button press that woke it.
+  pub fn wake(&mut self, now: u32) -> bool {
+    self.last_activity = now;
+    self.screen == Screen::Off
+  }
+
+  /// Keep the screen dimmed while the USB bus is suspended.
+  pub fn set_suspended(&mut self, suspended: bool) {
+    self.suspended = suspended;
+  }
+
+  /// The panel state to apply, when it differs from the last one.
+  pub fn screen_change(&mut self, now: u32) -> Option<Screen> {
+    let idle_s = now.wrapping_sub(self.last_activity) / 1000;
+    let expired = |after_s: u16| after_s != 0 && idle_s >= after_s as u32;
+    let wanted = if expired(self.power.off_after_s) {
+      Screen::Off
+    } else if self.suspended || expired(self.power.dim_after_s) {
+      Screen::On {
+        contrast: self.power.contrast / 8,
+      }
+    } else {
+      Screen::On {
+        contrast: self.power.contrast,
+      }
+    };
+    if wanted == self.screen {
+      return None;
+    }
+    self.screen = wanted;
+    Some(wanted)
+  }
+
+  /// Add a bridged frame to the terminal page.
+  pub fn log_traffic(&mut self, direction: Direction, data: &[u8]) {
+    self.terminal.push(direction, data);
+  }
+
+  /// Add the RSSI of a received frame to the trend graph.
+  pub fn record_rssi(&mut self, rssi_dbm: i16) {
+    if self.history.is_full() {
+      self.history.pop_front();
+    }
+    let _ = self.history.push_back(rssi_dbm);
+    self.revision = self.revision.wrapping_add(1);
+  }
+
+  /// Show the next page now, e.g. on a button tap.
+  pub fn next_page(&mut self, now: u32) {
+    self.notice = None;
+    self.page = self.page.next();
+    self.page_since = now;
+    self.next_refresh = now;
+  }
+
+  /// Switch between the pages and big-digit mode.
+  pub fn toggle_big(&mut self, now: u32) {
+    self.big = !self.big;
+    self.notice = None;
+    self.page_since = now;
+    self.next_refresh = now;
+  }
+
+  /// Replace the page with `text` for `duration_ms`.
+  pub fn notice(&mut self, text: &str, now: u32, duration_ms: u32) {
+    let mut lines: [Line; PAGE_LINES] = Default::default();
+    let _ = lines[LINES / 2].push_str(&text[..text.len().min(COLUMNS)]);
+    self.show(lines, now, duration_ms);
+  }
+
+  /// Replace the page with `title` over `items` for `duration_ms`, as many
+  /// to a line as fit; `..` ends the last line when some are left over.
+  pub fn summary(&mut self, title: &str, items: &[&str], now: u32, duration_ms: u32) {
+    let mut lines: [Line; PAGE_LINES] = Default::default();
+    let _ = lines[0].push_str(&title[..title.len().min(COLUMNS)]);
+    let mut row = 1;
+    for item in items {
+      // The last line keeps room for the `..`.
+      let width = if row == PAGE_LINES - 1 { COLUMNS - 3 } else { COLUMNS };
+      let item = &item[..item.len().min(COLUMNS - 3)];
+      let sep = if lines[row].is_empty() { 0 } else { 1 };
+      if lines[row].len() + sep + item.len() > width {
+        row += 1;
+        if row == PAGE_LINES {
+          let _ = lines[PAGE_LINES - 1].push_str(" ..");
+          break;
+        }
+      } else if sep == 1 {
+        let _ = lines[row].push(' ');
+      }
+      let _ = lines[row].push_str(item);
+    }
+    self.show(lines, now, duration_ms);
+  }
+
+  fn show(&mut self, lines: [Line; PAGE_LINES], now: u32, duration_ms: u32) {
+    self.notice = Some((lines, now.wrapping_add(duration_ms)));
+    self.last_activity = now;
+    self.next_refresh = now;
+  }
+
+  /// Leave the screen alone for `duration_ms` after the caller drew on it.
+  pub fn hold(&mut self, now: u32, duration_ms: u32) {
+    self.held_until = Some(now.wrapping_add(duration_ms));
+  }
+
+  /// Draw at most one changed line; returns whether the display needs a
+  /// flush.
+  pub fn poll<D, F>(&mut self, now: u32, snapshot: F, display: &mut D) -> bool
+  where
+    D: DrawTarget<Color = BinaryColor>,
+    F: FnOnce() -> Snapshot,
+  {
+    // Nothing to show on a blank panel; catch up once it is woken.
+    if self.screen == Screen::Off {
+      return false;
+    }
+    if let Some(until) = self.held_until {
+      if (now.wrapping_sub(until) as i32) < 0 {
+        return false;
+      }
+      // Whatever is on screen now is not ours.
+      self.held_until = None;
+      self.shown = Default::default();
+      self.next_refresh = now;
+    }
+
+    if let Some((_, until)) = &self.notice
+      && now.wrapping_sub(*until) as i32 >= 0
+    {
+      self.notice = None;
+      self.page_since = now;
+      self.next_refresh = now;
+    }
+    let cycling = self.notice.is_none() && !self.big;
+    if cycling && now.wrapping_sub(self.page_since) >= PAGE_INTERVAL_MS {
+      self.page = self.page.next();
+      self.page_since = now;
+      self.next_refresh = now;
+    }
+    if now.wrapping_sub(self.next_refresh) as i32 >= 0 {
+      self.next_refresh = now.wrapping_add(REFRESH_MS);
+      self.render(&snapshot());
+    }
+
+    let stale = |i: &usize| self.shown[*i].as_ref() != Some(&self.wanted[*i]);
+    let Some(index) = (0..LINES).find(stale) else {
+      return false;
+    };
+    match &self.wanted[index] {
+      Content::Text(text) => draw_line(display, index, text),
+      Content::Header(title, status) => draw_header(display, title, status),
+      Content::Graph(_) => {
+        let values: Vec<i16, HISTORY> = self.history.iter().copied().collect();
+        draw_graph_band(display, index, &values);
+      }
+      Content::Big(reading) => draw_big_band(display, index, *reading),
+    }
+    self.shown[index] = Some(self.wanted[index].clone());
+    true
+  }
+
+  /// Regenerate the content of every line.
+  fn render(&mut self, snapshot: &Snapshot) {
+    if self.big && self.notice.is_none() {
+      self.wanted = core::array::from_fn(|_| Content::Big(snapshot.last_packet));
+      return;
+    }
+    let mut lines: [Line; PAGE_LINES] = Default::default();
+    match &self.notice {
+      Some((text, _)) => lines = text.clone(),
+      None => self.page_text(snapshot, &mut lines),
+    }
+    let title = core::mem::take(&mut lines[0]);
+    self.wanted = core::array::from_fn(|i| Content::Text(core::mem::take(&mut lines[i])));
+    self.wanted[0] = Content::Header(title, StatusBar::new(snapshot));
+    if self.notice.is_none() && self.page == Page::Trend && !self.history.is_empty() {
+      for content in &mut self.wanted[1..] {
+        *content = Content::Graph(self.revision);
+      }
+    }
+  }
+
+  /// Text of the current page.
+  fn page_text(&self, snapshot: &Snapshot, lines: &mut [Line; PAGE_LINES]) {
+    let [title, l1, l2, l3, l4] = lines;
+    let _ = title.push_str(self.page.title());
+    match self.page {
+      Page::Radio => {
+        let radio = &snapshot.radio;
+        let _ = write!(
+          l1,
+          "{}.{:03}MHz {}dBm",
+          radio.frequency_hz / 1_000_000,
+          radio.frequency_hz / 1_000 % 1_000,
+          radio.tx_power_dbm()
+        );
+        let _ = write!(
+          l2,
+          "SF{} BW{} CR4/{}",
+          radio.sf,
+          radio.bandwidth.khz(),
+          radio.cr
+        );
+        let _ = write!(l3, "Node {:04X}>{:04X}", snapshot.local, snapshot.peer);
+        let _ = write!(l4, "ADR {}", if snapshot.adr { "on" } else { "off" });
+      }
+      Page::Traffic => {
+        let _ = write!(l1, "TX {}", snapshot.tx_frames);
+        let _ = write!(l2, "RX {}", snapshot.rx_frames);
+      }
+      Page::Terminal => {
+        let mut rows = self.terminal.lines();
+        for line in [l1, l2, l3, l4] {
+          match rows.next() {
+            Some(row) => *line = row.clone(),
+            None => break,
+          }
+        }
+      }
+      Page::Signal => match snapshot.last_packet {
+        Some(status) => {
+          let _ = write!(l1, "RSSI {} dBm", status.rssi_dbm);
+          let _ = write!(l2, "SNR {} dB", status.snr_db);
+        }
+        None => {
+          let _ = l1.push_str("No packet yet");
+        }
+      },
+      Page::Trend => {
+        if let Some(rssi) = self.history.back() {
+          // The graph fills the lines below the title.
+          title.clear();
+          let _ = write!(title, "RSSI {}", rssi);
+        } else {
+          let _ = l1.push_str("No packet yet");
+        }
+      }
+      Page::Errors => {
+        let _ = write!(l1, "Errors {}", snapshot.errors);
+        let _ = write!(l2, "Log dropped {}", snapshot.log_dropped);
+      }
+      Page::Uptime => {
+        let seconds = snapshot.uptime_ms / 1000;
+        let _ = write!(
+          l1,
+          "{}d {:02}:{:02}:{:02}",
+          seconds / 86_400,
+          seconds / 3_600 % 24,
+          seconds / 60 % 60,
+          seconds % 60
+        );
+      }
+      Page::Gps => match snapshot.gps {
+        Some(fix) => {
+          let _ = l1.push_str("Lat ");
+          let _ = gps::write_degrees(l1, fix.latitude_ude);
+          let _ = l2.push_str("Lon ");
+          let _ = gps::write_degrees(l2, fix.longitude_ude);
+          match fix.altitude_m {
+            Some(altitude_m) => {
+              let _ = write!(l3, "Alt {}m Sats {}", altitude_m, fix.satellites);
+            }
+            None => {
+              let _ = write!(l3, "Sats {}", fix.satellites);
+            }
+          }
+          let time = fix.time;
+          let _ = write!(
+            l4,
+            "UTC {:02}:{:02}:{:02}",
+            time.hours, time.minutes, time.seconds
+          );
+        }
+        None => {
+          let _ = l1.push_str("No fix");
+        }
+      },
+    }
+  }
+}
+
+/// Screen area of line `index`.
+fn band(index: usize) -> Rectangle {
+  let top = index as u32 * LINE_HEIGHT;
+  // The last band also covers the rows below it.
+  let height = if index == LINES - 1 {
+    HEIGHT - top
+  } else {
+    LINE_HEIGHT
+  };
+  Rectangle::new(Point::new(0, top as i32), Size::new(WIDTH, height))
+}
+
+/// Clear the band of line `index` and draw `text` into it.
+fn draw_line<D>(display: &mut D, index: usize, text: &str)
+where
+  D: DrawTarget<Color = BinaryColor>,
+{
+  let area = band(index);
+  let _ = area
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+    .draw(display);
+  let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+  let _ = Text::with_baseline(text, area.top_left, style, Baseline::Top).draw(display);
+}
+
+/// Draw the title line: the title on the left, the status bar on the right.
+fn draw_header<D>(display: &mut D, title: &str, status: &StatusBar)
+where
+  D: DrawTarget<Color = BinaryColor>,
+{
+  let columns = if status.battery.is_some() {
+    TITLE_COLUMNS - 2
+  } else {
+    TITLE_COLUMNS
+  };
+  draw_line(display, 0, &title[..title.len().min(columns)]);
+  let on = PrimitiveStyle::with_fill(BinaryColor::On);
+  let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+
+  // Battery outline with its tip, one 2-pixel segment per level.
+  if let Some(level) = status.battery {
+    let left = STATUS_LEFT - 13;
+    let _ = Rectangle::new(Point::new(left, 2), Size::new(10, 7))
+      .into_styled(outline)
+      .draw(display);
+    let _ = Rectangle::new(Point::new(left + 10, 4), Size::new(1, 3))
+      .into_styled(on)
+      .draw(display);
+    for i in 0..level {
+      let _ = Rectangle::new(Point::new(left + 2 + 2 * i as i32, 4), Size::new(2, 3))
+        .into_styled(on)
+        .draw(display);
+    }
+  }
+
+  // USB plug, filled while the host has configured the device.
+  let plug = Rectangle::new(Point::new(STATUS_LEFT, 3), Size::new(7, 5));
+  let _ = plug.into_styled(if status.usb { on } else { outline }).draw(display);
+  let _ = Rectangle::new(Point::new(STATUS_LEFT + 7, 4), Size::new(2, 3))
+    .into_styled(on)
+    .draw(display);
+
+  let activity = match status.radio {
+    RadioState::Idle => "-",
+    RadioState::Receiving => "R",
+    RadioState::Transmitting => "T",
+    RadioState::Asleep => "z",
+  };
+  let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+  let _ = Text::with_baseline(activity, Point::new(STATUS_LEFT + 11, 0), style, Baseline::Top)
+    .draw(display);
+
+  // Two interlocked links once paired.
+  if status.paired {
+    for offset in [0, 3] {
+      let _ = Rectangle::new(Point::new(STATUS_LEFT + 19 + offset, 3 + offset), Size::new(5, 5))
+        .into_styled(outline)
+        .draw(display);
+    }
+  }
+
+  // Signal bars, 3 pixels wide and rising to the right; empty ones leave a
+  // baseline.
+  for i in 0..4u8 {
+    let left = STATUS_LEFT + 31 + i as i32 * 5;
+    let height = if i < status.bars { 3 + 2 * i as u32 } else { 1 };
+    let bottom = LINE_HEIGHT as i32 - 1;
+    let _ = Rectangle::new(Point::new(left, bottom - height as i32), Size::new(3, height))
+      .into_styled(on)
+      .draw(display);
+  }
+}
+
+/// Segments lit for each character, bit 0 = top (a) to bit 6 = middle (g).
+fn segments(c: char) -> u8 {
+  match c {
+    '0' => 0x3F,
+    '1' => 0x06,
+    '2' => 0x5B,
+    '3' => 0x4F,
+    '4' => 0x66,
+    '5' => 0x6D,
+    '6' => 0x7D,
+    '7' => 0x07,
+    '8' => 0x7F,
+    '9' => 0x6F,
+    '-' => 0x40,
+    _ => 0,
+  }
+}
+
+/// Redraw the part of the big-digit reading inside the band of line
+/// `index`: RSSI on the upper half of the screen, SNR on the lower.
+fn draw_big_band<D>(display: &mut D, index: usize, reading: Option<PacketStatus>)
+where
+  D: DrawTarget<Color = BinaryColor>,
+{
+  // 28 rows tall on a 64-row panel.
+  const DIGIT_HEIGHT: u32 = HEIGHT / 2 - 4;
+  const DIGIT_WIDTH: u32 = DIGIT_HEIGHT * 4 / 7;
+  const STROKE: u32 = if DIGIT_HEIGHT >= 14 { DIGIT_HEIGHT / 7 } else { 1 };
+  const PITCH: i32 = DIGIT_WIDTH as i32 + 4;
+
+  let area = band(index);
+  let _ = area
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+    .draw(display);
+  let mut display = display.clipped(&area);
+  let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+  let on = PrimitiveStyle::with_fill(BinaryColor::On);
+
+  let mut rssi = String::<8>::new();
+  let mut snr = String::<8>::new();
+  match reading {
+    Some(status) => {
+      let _ = write!(rssi, "{}", status.rssi_dbm);
+      let _ = write!(snr, "{}", status.snr_db);
+    }
+    None => {
+      let _ = rssi.push_str("--");
+      let _ = snr.push_str("--");
+    }
+  }
+
+  let (w, h, t) = (DIGIT_WIDTH, DIGIT_HEIGHT, STROKE);
+  let half = (h / 2) as i32;
+  for (top, label, value) in [(0, "RSSI", rssi), (HEIGHT as i32 / 2, "SNR", snr)] {
+    let _ = Text::with_baseline(label, Point::new(0, top), style, Baseline::Top).draw(&mut display);
+    // Digits right-aligned.
+    let mut x = WIDTH as i32 - PITCH * value.len() as i32;
+    let y = top + 2;
+    for c in value.chars() {
+      let lit = segments(c);
+      let bars = [
+        (x, y, w, t),
+        (x + (w - t) as i32, y, t, h / 2),
+        (x + (w - t) as i32, y + half, t, h / 2),
+        (x, y + (h - t) as i32, w, t),
+        (x, y + half, t, h / 2),
+        (x, y, t, h / 2),
+        (x, y + half - (t / 2) as i32, w, t),
+      ];
+      for (segment, (sx, sy, sw, sh)) in bars.into_iter().enumerate() {
+        if lit & (1 << segment) != 0 {
+          let _ = Rectangle::new(Point::new(sx, sy), Size::new(sw, sh))
+            .into_styled(on)
+            .draw(&mut display);
+        }
+      }
+      x += PITCH;
+    }
+  }
+}
+
+/// Redraw the part of the RSSI graph inside the band of line `index`.  The
+/// graph spans every line below the title, newest value on the right.
+fn draw_graph_band<D>(display: &mut D, index: usize, values: &[i16])
+where
+  D: DrawTarget<Color = BinaryColor>,
+{
+  let area = band(index);
+  let _ = area
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+    .draw(display);
+  let width = values.len() as u32 * HISTORY_BAR_WIDTH;
+  let graph = Rectangle::new(
+    Point::new((WIDTH - width) as i32, LINE_HEIGHT as i32),
+    Size::new(width, HEIGHT - LINE_HEIGHT),
+  );
+  draw_bars(&mut display.clipped(&area), values, HISTORY_BAR_WIDTH, graph);
+}
+
+/// Draw RSSI `values` as bars `bar_width` pixels wide, rising from the
+/// bottom of `area`: empty at -130 dBm, full height at -30 dBm.
+pub fn draw_bars<D>(display: &mut D, values: &[i16], bar_width: u32, area: Rectangle)
+where
+  D: DrawTarget<Color = BinaryColor>,
+{
+  let span = (GRAPH_CEILING_DBM - GRAPH_FLOOR_DBM) as u32;
+  let bottom = area.top_left.y + area.size.height as i32;
+  let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+  for (i, &rssi) in values.iter().enumerate() {
+    let level = (rssi.clamp(GRAPH_FLOOR_DBM, GRAPH_CEILING_DBM) - GRAPH_FLOOR_DBM) as u32;
+    let height = level * area.size.height / span;
+    let left = area.top_left.x + (i as u32 * bar_width) as i32;
+    let _ = Rectangle::new(
+      Point::new(left, bottom - height as i32),
+      Size::new(bar_width, height),
+    )
+    .into_styled(fill)
+    .draw(display);
+  }
+}
@@ -0,0 +1,748 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/usb.rs - USB 中断服务与缓冲
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Interrupt-driven USB composite device.
+//!
+//! The USB stack lives in a static and is serviced exclusively from the
+//! `USB_HP_CAN_TX` / `USB_LP_CAN_RX0` interrupts.  The application never
+//! touches the stack directly; it exchanges bytes with the ISR through four
+//! ring buffers:
+//!
+//! | buffer     | producer | consumer | contents                    |
+//! |------------|----------|----------|-----------------------------|
+//! | data RX    | ISR      | main     | host → LoRa bridge data     |
+//! | data TX    | main     | ISR      | LoRa → host bridge data     |
+//! | `CTRL_RX`  | ISR      | main     | AT command bytes            |
+//! | `CTRL_TX`  | main     | ISR      | command replies, log lines  |
+//!
+//! The data buffers are lock-free SPSC queues: the ISR owns one end and the
+//! application owns the other through [`Bridge`].  The low-rate control
+//! buffers are accessed inside `cortex_m::interrupt::free`.  After queueing
+//! output the application pends the USB interrupt so the ISR pushes it to
+//! the host.
+//!
+//! Backpressure differs per direction:
+//!
+//! * host → LoRa: when the RX queue cannot take a full packet the ISR leaves
+//!   it in the endpoint, so the OUT endpoint NAKs and the host waits.
+//! * LoRa → host: the radio cannot be paused, so bytes that do not fit the
+//!   TX queue (or arrive while no host is attached) are dropped and counted.
+//!
+//! The data port also follows the CDC line state.  Bridging only happens
+//! while the host asserts DTR: without it, OUT data is read and discarded
+//! (so a terminal opening the port cannot trigger garbage transmissions) and
+//! radio data is dropped.  While the radio is busy transmitting, OUT packets
+//! are held in the endpoint, so the host sees the NAKs as flow control.
+//! usbd-serial exposes no way to send CDC `SERIAL_STATE` notifications, so
+//! the busy state is not signalled on the interrupt endpoint.
+//!
+//! When the host suspends the bus (it sleeps, or the cable is pulled and
+//! the bus goes idle) the device leaves the configured state, so radio data
+//! is dropped as with no host attached.  Radio data still queued towards
+//! the host is discarded on entering suspend, and the application discards
+//! host data it has not bridged yet (see [`Bridge::discard`]), so nothing
+//! stale crosses the link after a resume or replug.  The Blue Pill has no
+//! VBUS sense pin; an unplugged cable shows as a suspend.
+//!
+//! Once [`ms_os::FUNCTIONS`] lists a function, the device reports USB 2.1
+//! so Windows reads its Microsoft OS 2.0 descriptors, see [`crate::ms_os`].
+//!
+//! VID, PID, manufacturer and product come from the settings
+//! (`AT+USBID`, see [`Identity`]), so a re-badged bridge keeps the stock
+//! firmware.  Only test and shared VIDs are accepted there.
+//!
+//! Built with a log store (`spi-flash` or `sd-card`), a read-only mass
+//! storage function follows the two ports; see [`crate::msc`].
+//!
+//! Built with `usb-hid`, the settings may put a HID interface in place of
+//! the CDC data port (see [`crate::hid`]).  It feeds the same queues, in
+//! reports instead of packets, and has no line state: it counts as open
+//! while the device is configured.
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use cortex_m::interrupt::{CriticalSection, Mutex};
+use cortex_m::peripheral::NVIC;
+use heapless::spsc::{Consumer, Producer, Queue};
+use heapless::{Deque, String, Vec};
+use usb_device::bus::UsbBusAllocator;
+use usb_device::class::UsbClass;
+use usb_device::prelude::*;
+use usbd_serial::SerialPort;
+
+use crate::device_id;
+#[cfg(feature = "usb-hid")]
+use crate::hid;
+use crate::load;
+use crate::ms_os;
+#[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+use crate::msc;
+use crate::timer;
+use crate::hal::pac::{Interrupt, interrupt};
+use crate::hal::usb::{Peripheral, UsbBus, UsbBusType};
+
+/// Bytes buffered from the host towards the radio.
+const DATA_RX_CAPACITY: usize = 512;
+/// Bytes buffered from the radio towards the host.
+const DATA_TX_CAPACITY: usize = 512;
+/// Bytes buffered per direction of the control port; a whole host protocol
+/// response fits beside pending log text.
+const CTRL_CAPACITY: usize = 256;
+/// Size of one full-speed bulk packet.
+const PACKET_SIZE: usize = 64;
+
+type Buffer<const N: usize> = Mutex<RefCell<Deque<u8, N>>>;
+
+static CTRL_RX: Buffer<CTRL_CAPACITY> = Mutex::new(RefCell::new(Deque::new()));
+static CTRL_TX: Buffer<CTRL_CAPACITY> = Mutex::new(RefCell::new(Deque::new()));
+
+static CONFIGURED: AtomicBool = AtomicBool::new(false);
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+static SUSPENDS: AtomicU32 = AtomicU32::new(0);
+static HOST_DTR: AtomicBool = AtomicBool::new(false);
+static HOST_RTS: AtomicBool = AtomicBool::new(false);
+static HOST_BAUD: AtomicU32 = AtomicU32::new(0);
+static RADIO_BUSY: AtomicBool = AtomicBool::new(false);
+static OUT_NAKS: AtomicU32 = AtomicU32::new(0);
+static HOST_DROPS: AtomicU32 = AtomicU32::new(0);
+
+static USB_STACK: Mutex<RefCell<Option<UsbStack>>> = Mutex::new(RefCell::new(None));
+
+/// The USB device together with its two CDC functions and the ISR ends of
+/// the data queues.
+struct UsbStack {
+  device: UsbDevice<'static, UsbBusType>,
+  data_port: DataFunction,
+  ctrl_port: SerialPort<'static, UsbBusType>,
+  ms_os: ms_os::MsOs20,
+  data_rx: Producer<'static, u8, DATA_RX_CAPACITY>,
+  data_tx: Consumer<'static, u8, DATA_TX_CAPACITY>,
+  /// Bytes taken from `data_tx` that the port has not accepted yet.
+  data_tx_pending: Vec<u8, PACKET_SIZE>,
+  #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+  msc: msc::Function,
+  #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+  msc_state: msc::State,
+}
+
+/// The function carrying the bridge data.
+enum DataFunction {
+  Cdc(SerialPort<'static, UsbBusType>),
+  #[cfg(feature = "usb-hid")]
+  Hid(hid::Function),
+}
+
+impl DataFunction {
+  fn class(&mut self) -> &mut dyn UsbClass<UsbBusType> {
+    match self {
+      Self::Cdc(port) => port,
+      #[cfg(feature = "usb-hid")]
+      Self::Hid(function) => function,
+    }
+  }
+
+  /// The CDC data port, unless the data goes through HID.
+  fn cdc(&mut self) -> Option<&mut SerialPort<'static, UsbBusType>> {
+    match self {
+      Self::Cdc(port) => Some(port),
+      #[cfg(feature = "usb-hid")]
+      Self::Hid(_) => None,
+    }
+  }
+
+  /// Bridge data one packet or report carries.
+  fn chunk_len(&self) -> usize {
+    match self {
+      Self::Cdc(_) => PACKET_SIZE,
+      #[cfg(feature = "usb-hid")]
+      Self::Hid(_) => hid::CHUNK_MAX,
+    }
+  }
+}
+
+/// Application end of the bridge data queues.
+pub struct Bridge {
+  rx: Consumer<'static, u8, DATA_RX_CAPACITY>,
+  tx: Producer<'static, u8, DATA_TX_CAPACITY>,
+  /// The data goes through the HID function, which is always open.
+  hid: bool,
+}
+
+/// Backpressure counters of the data port.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct BridgeStats {
+  /// Times the OUT endpoint was left NAKing because the RX queue was full.
+  pub out_naks: u32,
+  /// Radio bytes dropped because the TX queue was full or no host attached.
+  pub host_drops: u32,
+  /// Times the host suspended the bus.
+  pub suspends: u32,
+}
+
+/// Vendor ID the bridge ships with.
+pub const DEFAULT_VID: u16 = 0x26c0;
+/// Product ID the bridge ships with.
+pub const DEFAULT_PID: u16 = 0x27dd;
+/// Manufacturer string the bridge ships with.
+pub const DEFAULT_MANUFACTURER: &str = "Wareless Group";
+/// Product string the bridge ships with.
+pub const DEFAULT_PRODUCT: &str = "Blue-High LoRa Cake";
+/// Vendor IDs an integrator may enumerate under: the bridge's own, the
+/// pid.codes open hardware VID and the shared V-USB VID.  Anything else is
+/// a VID the integrator would have to own, which the firmware cannot check.
+pub const ALLOWED_VIDS: [u16; 3] = [DEFAULT_VID, 0x1209, 0x16c0];
+/// Longest manufacturer or product string, short enough that the
+/// settings record keeps room for fields added after the identity.
+pub const IDENTITY_STRING_MAX: usize = 12;
+
+/// The identity the device enumerates with, persisted in the settings so
+/// a re-badged bridge needs no firmware change.  Empty strings stand for
+/// the default ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Identity {
+  pub vid: u16,
+  pub pid: u16,
+  manufacturer: [u8; IDENTITY_STRING_MAX],
+  manufacturer_len: u8,
+  product: [u8; IDENTITY_STRING_MAX],
+  product_len: u8,
+}
+
+impl Default for Identity {
+  fn default() -> Self {
+    Self {
+      vid: DEFAULT_VID,
+      pid: DEFAULT_PID,
+      manufacturer: [0; IDENTITY_STRING_MAX],
+      manufacturer_len: 0,
+      product: [0; IDENTITY_STRING_MAX],
+      product_len: 0,
+    }
+  }
+}
+
+impl Identity {
+  /// Accept a VID of [`ALLOWED_VIDS`], a non-zero PID and strings of up to
+  /// [`IDENTITY_STRING_MAX`] printable ASCII characters without `,`.
+  pub fn new(vid: u16, pid: u16, manufacturer: &[u8], product: &[u8]) -> Option<Self> {
+    if !ALLOWED_VIDS.contains(&vid) || pid == 0 {
+      return None;
+    }
+    let mut identity = Self {
+      vid,
+      pid,
+      ..Self::default()
+    };
+    identity.manufacturer_len = copy_string(&mut identity.manufacturer, manufacturer)?;
+    identity.product_len = copy_string(&mut identity.product, product)?;
+    Some(identity)
+  }
+
+  /// The manufacturer string as set, empty for the default.
+  pub fn manufacturer_raw(&self) -> &[u8] {
+    &self.manufacturer[..self.manufacturer_len as usize]
+  }
+
+  /// The product string as set, empty for the default.
+  pub fn product_raw(&self) -> &[u8] {
+    &self.product[..self.product_len as usize]
+  }
+
+  /// The manufacturer string the device reports.
+  pub fn manufacturer(&self) -> &str {
+    string_or(self.manufacturer_raw(), DEFAULT_MANUFACTURER)
+  }
+
+  /// The product string the device reports.
+  pub fn product(&self) -> &str {
+    string_or(self.product_raw(), DEFAULT_PRODUCT)
+  }
+}
+
+impl defmt::Format for Identity {
+  fn format(&self, f: defmt::Formatter) {
+    defmt::write!(
+      f,
+      "{=u16:04X}:{=u16:04X} {=str}/{=str}",
+      self.vid,
+      self.pid,
+      self.manufacturer(),
+      self.product()
+    );
+  }
+}
+
+/// Copy an identity string into `slot`, returning its length.
+fn copy_string(slot: &mut [u8; IDENTITY_STRING_MAX], string: &[u8]) -> Option<u8> {
+  if string.len() > IDENTITY_STRING_MAX
+    || !string.iter().all(|&byte| byte.is_ascii_graphic() || byte == b' ')
+    || string.contains(&b',')
+  {
+    return None;
+  }
+  slot[..string.len()].copy_from_slice(string);
+  Some(string.len() as u8)
+}
+
+/// `string` as text, or `default` when it is empty.
+fn string_or<'a>(string: &'a [u8], default: &'a str) -> &'a str {
+  match core::str::from_utf8(string) {
+    Ok(string) if !string.is_empty() => string,
+    _ => default,
+  }
+}
+
+/// Build the composite device with `identity` and hand it to the USB
+/// interrupts.  With `hid_data` and built with `usb-hid`, the bridge data
+/// goes through a HID function instead of the CDC data port.
+///
+/// Must be called exactly once.
+pub fn init(usb: Peripheral, identity: &Identity, hid_data: bool) -> Bridge {
+  let bus: &'static UsbBusAllocator<UsbBusType> =
+    cortex_m::singleton!(: UsbBusAllocator<UsbBusType> = UsbBus::new(usb))
+      .expect("USB bus already initialised");
+
+  // Composite device with two CDC-ACM functions (grouped by IADs):
+  //   interface 0/1 — transparent LoRa bridge data
+  //   interface 2/3 — log lines and AT host commands
+  // Endpoints are allocated in this order; both functions together use
+  // 400 of the 512 bytes of USB packet memory, the mass storage function
+  // behind them another 64.  A HID data function (interface 0 alone) takes
+  // 128 bytes instead of the 136 of the CDC one.
+  let hid_data = cfg!(feature = "usb-hid") && hid_data;
+  #[cfg(feature = "usb-hid")]
+  let data_port = match hid_data {
+    true => DataFunction::Hid(hid::new(bus)),
+    false => DataFunction::Cdc(SerialPort::new(bus)),
+  };
+  #[cfg(not(feature = "usb-hid"))]
+  let data_port = DataFunction::Cdc(SerialPort::new(bus));
+  let ctrl_port = SerialPort::new(bus);
+  #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+  let msc = {
+    let buffer: &'static mut [u8; msc::BLOCK_LEN] =
+      cortex_m::singleton!(: [u8; msc::BLOCK_LEN] = [0; msc::BLOCK_LEN]).unwrap();
+    usbd_storage::subclass::scsi::Scsi::new(bus, msc::PACKET_SIZE, 0, &mut buffer[..]).unwrap()
+  };
+
+  // The serial number is the chip UID, so several bridges on one host
+  // enumerate as distinct devices.
+  let serial: &'static String<{ device_id::SERIAL_LEN }> =
+    cortex_m::singleton!(: String<{ device_id::SERIAL_LEN }> = device_id::serial_string()).unwrap();
+
+  let ms_os_set: &'static ms_os::DescriptorSet =
+    cortex_m::singleton!(: ms_os::DescriptorSet = ms_os::descriptor_set(ms_os::FUNCTIONS).unwrap())
+      .unwrap();
+
+  // The descriptors borrow the strings for as long as the device lives.
+  let identity: &'static Identity = cortex_m::singleton!(: Identity = *identity).unwrap();
+  defmt::info!("[usb] identity {}", identity);
+
+  // USB 2.1 for the BOS descriptor that points Windows at the set, only
+  // when the set binds a function.
+  let usb_rev = match ms_os::FUNCTIONS.is_empty() {
+    true => UsbRev::Usb200,
+    false => UsbRev::Usb210,
+  };
+  let device = UsbDeviceBuilder::new(bus, UsbVidPid(identity.vid, identity.pid))
+    .usb_rev(usb_rev)
+    .strings(&[StringDescriptors::default()
+      .manufacturer(identity.manufacturer())
+      .product(identity.product())
+      .serial_number(serial.as_str())])
+    .unwrap()
+    .composite_with_iads()
+    .build();
+
+  let rx_queue: &'static mut Queue<u8, DATA_RX_CAPACITY> =
+    cortex_m::singleton!(: Queue<u8, DATA_RX_CAPACITY> = Queue::new()).unwrap();
+  let tx_queue: &'static mut Queue<u8, DATA_TX_CAPACITY> =
+    cortex_m::singleton!(: Queue<u8, DATA_TX_CAPACITY> = Queue::new()).unwrap();
+  let (rx_producer, rx_consumer) = rx_queue.split();
+  let (tx_producer, tx_consumer) = tx_queue.split();
+
+  cortex_m::interrupt::free(|cs| {
+    USB_STACK.borrow(cs).replace(Some(UsbStack {
+      device,
+      data_port,
+      ctrl_port,
+      ms_os: ms_os::MsOs20::new(ms_os_set),
+      data_rx: rx_producer,
+      data_tx: tx_consumer,
+      data_tx_pending: Vec::new(),
+      #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+      msc,
+      #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+      msc_state: msc::State::default(),
+    }));
+  });
+
+  unsafe {
+    NVIC::unmask(Interrupt::USB_HP_CAN_TX);
+    NVIC::unmask(Interrupt::USB_LP_CAN_RX0);
+  }
+
+  Bridge {
+    rx: rx_consumer,
+    tx: tx_producer,
+    hid: hid_data,
+  }
+}
+
+impl Bridge {
+  /// Take bridge data received from the host.
+  pub fn read(&mut self, buf: &mut [u8]) -> usize {
+    let mut n = 0;
+    while n < buf.len() {
+      match self.rx.dequeue() {
+        Some(byte) => {
+          buf[n] = byte;
+          n += 1;
+        }
+        None => break,
+      }
+    }
+    if n > 0 {
+      // Space was freed; let the ISR accept a packet it may have NAKed.
+      NVIC::pend(Interrupt::USB_LP_CAN_RX0);
+    }
+    n
+  }
+
+  /// Take one byte received from the host.
+  pub fn read_byte(&mut self) -> Option<u8> {
+    let byte = self.rx.dequeue()?;
+    if self.rx.capacity() - self.rx.len() == PACKET_SIZE {
+      // Room for a packet again; let the ISR accept one it may have NAKed.
+      NVIC::pend(Interrupt::USB_LP_CAN_RX0);
+    }
+    Some(byte)
+  }
+
+  /// Bytes from the host waiting to be read.
+  pub fn pending(&self) -> usize {
+    self.rx.len()
+  }
+
+  /// Drop host data that has not been read, e.g. when the bus suspends.
+  pub fn discard(&mut self) {
+    while self.rx.dequeue().is_some() {}
+    NVIC::pend(Interrupt::USB_LP_CAN_RX0);
+  }
+
+  /// Queue bridge data for the host.
+  ///
+  /// Bytes that do not fit, or that arrive while no host has the data port
+  /// open (DTR deasserted, or the device not configured for HID), are
+  /// dropped and counted.  Returns the number of bytes queued.
+  pub fn write(&mut self, data: &[u8]) -> usize {
+    let mut n = 0;
+    if is_configured() && (self.hid || host_dtr()) {
+      for &byte in data {
+        if self.tx.enqueue(byte).is_err() {
+          break;
+        }
+        n += 1;
+      }
+    }
+    let dropped = data.len() - n;
+    if dropped > 0 {
+      HOST_DROPS.fetch_add(dropped as u32, Ordering::Relaxed);
+    }
+    NVIC::pend(Interrupt::USB_LP_CAN_RX0);
+    n
+  }
+}
+
+/// Snapshot of the data-port backpressure counters.
+pub fn stats() -> BridgeStats {
+  BridgeStats {
+    out_naks: OUT_NAKS.load(Ordering::Relaxed),
+    host_drops: HOST_DROPS.load(Ordering::Relaxed),
+    suspends: SUSPENDS.load(Ordering::Relaxed),
+  }
+}
+
+/// Whether the host has configured the device.
+pub fn is_configured() -> bool {
+  CONFIGURED.load(Ordering::Relaxed)
+}
+
+/// Whether the host has suspended the bus.
+pub fn is_suspended() -> bool {
+  SUSPENDED.load(Ordering::Relaxed)
+}
+
+/// Whether the host has the data port open (DTR asserted).
+pub fn host_dtr() -> bool {
+  HOST_DTR.load(Ordering::Relaxed)
+}
+
+/// Whether the host asserts RTS on the data port.
+pub fn host_rts() -> bool {
+  HOST_RTS.load(Ordering::Relaxed)
+}
+
+/// Baud rate the host last set on the data port.
+pub fn host_baud() -> u32 {
+  HOST_BAUD.load(Ordering::Relaxed)
+}
+
+/// Hold off host data while the radio is busy transmitting.
+pub fn set_radio_busy(busy: bool) {
+  RADIO_BUSY.store(busy, Ordering::Relaxed);
+  if !busy {
+    // Accept any packet that was NAKed while busy.
+    NVIC::pend(Interrupt::USB_LP_CAN_RX0);
+  }
+}
+
+/// Queue command replies or log text for the control port.
+pub fn write_control(data: &[u8]) -> usize {
+  let n = cortex_m::interrupt::free(|cs| push_from(&mut CTRL_TX.borrow(cs).borrow_mut(), data));
+  NVIC::pend(Interrupt::USB_LP_CAN_RX0);
+  n
+}
+
+/// Queue control-port text longer than the buffer, waiting up to
+/// `timeout_ms` for the host to take each piece.  Returns `false` when the
+/// host stopped reading and the rest was dropped.
+pub fn write_control_all(mut data: &[u8], timeout_ms: u32) -> bool {
+  let start = timer::now_ms();
+  while !data.is_empty() {
+    data = &data[write_control(data)..];
+    if timer::elapsed_ms(start) >= timeout_ms {
+      return data.is_empty();
+    }
+  }
+  true
+}
+
+/// Free space in the control-port output buffer.
+pub fn control_space() -> usize {
+  cortex_m::interrupt::free(|cs| CTRL_CAPACITY - CTRL_TX.borrow(cs).borrow().len())
+}
+
+/// Accumulate control-port bytes into `line` until a CR or LF arrives.
+///
+/// Returns `true` when `line` holds a complete, non-empty command line
+/// (without terminator).  A zero byte starts a binary frame instead (see
+/// [`crate::host`]): `line` then holds that zero and the frame up to the
+/// closing zero.  The caller clears `line` after handling it.  Bytes beyond
+/// the capacity of `line` are discarded.
+pub fn read_control_line<const N: usize>(line: &mut Vec<u8, N>) -> bool {
+  cortex_m::interrupt::free(|cs| {
+    let mut rx = CTRL_RX.borrow(cs).borrow_mut();
+    while let Some(byte) = rx.pop_front() {
+      match byte {
+        0 if line.len() > 1 && line[0] == 0 => return true,
+        // Back-to-back delimiters are one; a partial text line is dropped.
+        0 => {
+          line.clear();
+          let _ = line.push(0);
+        }
+        _ if line.first() == Some(&0) => {
+          let _ = line.push(byte);
+        }
+        b'\r' | b'\n' if line.is_empty() => {}
+        b'\r' | b'\n' => return true,
+        _ => {
+          let _ = line.push(byte);
+        }
+      }
+    }
+    false
+  })
+}
+
+fn push_from<const N: usize>(queue: &mut Deque<u8, N>, data: &[u8]) -> usize {
+  let mut n = 0;
+  for &byte in data {
+    if queue.push_back(byte).is_err() {
+      break;
+    }
+    n += 1;
+  }
+  n
+}
+
+impl UsbStack {
+  /// Service the device and move bytes between endpoints and buffers.
+  fn service(&mut self, cs: &CriticalSection) {
+    #[cfg(not(any(feature = "spi-flash", feature = "sd-card")))]
+    self.device.poll(&mut [self.data_port.class(), &mut self.ctrl_port, &mut self.ms_os]);
+    #[cfg(any(feature = "spi-flash", feature = "sd-card"))]
+    {
+      self.device.poll(&mut [
+        self.data_port.class(),
+        &mut self.ctrl_port,
+        &mut self.msc,
+        &mut self.ms_os,
+      ]);
+      let state = &mut self.msc_state;
+      let _ = self.msc.poll(|command| state.process(command));
+    }
+    let state = self.device.state();
+    CONFIGURED.store(state == UsbDeviceState::Configured, Ordering::Relaxed);
+    let suspended = state == UsbDeviceState::Suspend;
+    if suspended && !SUSPENDED.swap(true, Ordering::Relaxed) {
+      SUSPENDS.fetch_add(1, Ordering::Relaxed);
+      self.discard_data_tx();
+    } else if !suspended {
+      SUSPENDED.store(false, Ordering::Relaxed);
+    }
+    if let Some(port) = self.data_port.cdc() {
+      HOST_DTR.store(port.dtr(), Ordering::Relaxed);
+      HOST_RTS.store(port.rts(), Ordering::Relaxed);
+      HOST_BAUD.store(port.line_coding().data_rate(), Ordering::Relaxed);
+    }
+
+    self.receive_data();
+    receive(&mut self.ctrl_port, &mut CTRL_RX.borrow(cs).borrow_mut());
+    self.transmit_data();
+    transmit(&mut self.ctrl_port, &mut CTRL_TX.borrow(cs).borrow_mut());
+  }
+
+  /// Move one OUT packet or report of bridge data into the RX queue, if it
+  /// fits.
+  fn receive_data(&mut self) {
+    if let Some(port) = self.data_port.cdc()
+      && !port.dtr()
+    {
+      // Port not opened by a program: swallow whatever the host sends.
+      let mut packet = [0u8; PACKET_SIZE];
+      while let Ok(count) = port.read(&mut packet) {
+        if count == 0 {
+          break;
+        }
+      }
+      return;
+    }
+    if RADIO_BUSY.load(Ordering::Relaxed) {
+      return;
+    }
+
+    let free = self.data_rx.capacity() - self.data_rx.len();
+    if free < self.data_port.chunk_len() {
+      OUT_NAKS.fetch_add(1, Ordering::Relaxed);
+      return;
+    }
+    let mut packet = [0u8; PACKET_SIZE];
+    let data = match &mut self.data_port {
+      DataFunction::Cdc(port) => port.read(&mut packet).map(|count| &packet[..count]),
+      #[cfg(feature = "usb-hid")]
+      DataFunction::Hid(function) => function
+        .pull_raw_output(&mut packet)
+        .map(|count| hid::unpack(&packet[..count])),
+    };
+    if let Ok(data) = data {
+      for &byte in data {
+        let _ = self.data_rx.enqueue(byte);
+      }
+    }
+  }
+
+  /// Drop radio data the host has not taken; it is stale after a suspend.
+  fn discard_data_tx(&mut self) {
+    self.data_tx_pending.clear();
+    while self.data_tx.dequeue().is_some() {}
+  }
+
+  /// Hand queued bridge data to the port in packet-sized pieces.
+  fn transmit_data(&mut self) {
+    let chunk_len = self.data_port.chunk_len();
+    loop {
+      while self.data_tx_pending.len() < chunk_len {
+        match self.data_tx.dequeue() {
+          Some(byte) => {
+            let _ = self.data_tx_pending.push(byte);
+          }
+          None => break,
+        }
+      }
+      if self.data_tx_pending.is_empty() {
+        break;
+      }
+      let pending = &self.data_tx_pending;
+      let written = match &mut self.data_port {
+        DataFunction::Cdc(port) => port.write(pending),
+        #[cfg(feature = "usb-hid")]
+        DataFunction::Hid(function) => function
+          .push_raw_input(&hid::pack(pending))
+          .map(|_| pending.len()),
+      };
+      match written {
+        Ok(n) if n > 0 => {
+          let rest = self.data_tx_pending.len() - n;
+          self.data_tx_pending.copy_within(n.., 0);
+          self.data_tx_pending.truncate(rest);
+        }
+        _ => break,
+      }
+    }
+    if let Some(port) = self.data_port.cdc() {
+      let _ = port.flush();
+    }
+  }
+}
+
+/// Move one OUT packet into `queue`, if it fits.
+///
+/// A packet that does not fit is left in the endpoint, which NAKs the host
+/// until the application has drained the queue.
+fn receive<const N: usize>(port: &mut SerialPort<'static, UsbBusType>, queue: &mut Deque<u8, N>) {
+  if N - queue.len() < PACKET_SIZE {
+    return;
+  }
+  let mut packet = [0u8; PACKET_SIZE];
+  if let Ok(count) = port.read(&mut packet) {
+    push_from(queue, &packet[..count]);
+  }
+}
+
+/// Hand as much of `queue` to the port as it accepts.
+fn transmit<const N: usize>(port: &mut SerialPort<'static, UsbBusType>, queue: &mut Deque<u8, N>) {
+  while !queue.is_empty() {
+    let (front, _) = queue.as_slices();
+    match port.write(front) {
+      Ok(n) if n > 0 => {
+        for _ in 0..n {
+          queue.pop_front();
+        }
+      }
+      _ => break,
+    }
+  }
+  let _ = port.flush();
+}
+
+fn on_usb_interrupt() {
+  cortex_m::interrupt::free(|cs| {
+    if let Some(stack) = USB_STACK.borrow(cs).borrow_mut().as_mut() {
+      stack.service(cs);
+    }
+  });
+}
+
+#[interrupt]
+fn USB_HP_CAN_TX() {
+  let _isr = load::Isr::start();
+  on_usb_interrupt();
+}
+
+#[interrupt]
+fn USB_LP_CAN_RX0() {
+  let _isr = load::Isr::start();
+  on_usb_interrupt();
+}
@@ -0,0 +1,139 @@
+// 该文件是 BlueHigh 项目的一部分。
+// src/version.rs - 固件版本与构建信息
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! Firmware version and build information.
+//!
+//! `build.rs` embeds the git commit, the build time and the Cargo features
+//! the image was built with.  `AT+VER?` answers two lines, so host tools
+//! can tell which commands and which radio to expect:
+//!
+//! ```text
+//! +VER:<version>,<git hash>,<build time, Unix seconds>,<chip|->,<module>
+//! +FEAT:<feature>,<feature>,...
+//! +IMAGE:<bytes>,<crc32, hex>,<OK|BAD|UNSEALED>
+//! ```
+//!
+//! The chip is the one the image drives (see [`crate::band`]), or `-` when
+//! it did not answer at boot.  The hash is `unknown` for builds outside a
+//! git checkout.
+//!
+//! The image is the application, from the end of the boot selector page
+//! (see [`crate::boot_select`]) to the end of the `.data` initialisers; its
+//! CRC32 (see [`crate::crc`]) is taken at boot and checked against the seal
+//! written after it.  The selector is left out, since an update keeps the
+//! one already in flash.  An image that does not
+//! match, flashed only in part by a field update, still boots, so it can
+//! be flashed again, but counts as a failed boot stage and keeps the PA
+//! derated (see [`crate::derate`]).
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::band;
+use crate::boot_select::APP_START;
+use crate::crc;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Short hash of the commit built.
+pub const GIT_HASH: &str = env!("BLUE_HIGH_GIT_HASH");
+/// Build time in seconds since the Unix epoch, as text.
+pub const BUILD_TIME: &str = env!("BLUE_HIGH_BUILD_TIME");
+/// Enabled Cargo features, comma separated.
+pub const FEATURES: &str = env!("BLUE_HIGH_FEATURES");
+
+/// [`SEAL`] of an image nobody sealed: erased flash.
+const UNSEALED: u32 = u32::MAX;
+
+/// CRC32 of the image ahead of it, written into the `.bin` file after the
+/// build by `tools/seal_image.py`.  `memory.x` places it right after the
+/// `.data` initialisers, so it is the last word of the image.
+#[unsafe(link_section = ".image_crc")]
+#[used]
+static SEAL: u32 = UNSEALED;
+
+/// Outcome of the image check at boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ImageCheck {
+  Ok,
+  /// The image does not match its seal: partly flashed or corrupted.
+  Mismatch,
+  /// A build flashed straight from the ELF, without a seal to check.
+  Unsealed,
+}
+
+impl ImageCheck {
+  pub fn name(self) -> &'static str {
+    match self {
+      ImageCheck::Ok => "OK",
+      ImageCheck::Mismatch => "BAD",
+      ImageCheck::Unsealed => "UNSEALED",
+    }
+  }
+}
+
+/// The program image in flash, up to the seal.
+pub fn image() -> &'static [u8] {
+  let end = (&raw const SEAL) as usize;
+  // SAFETY: the flash up to the seal is the image the linker laid out,
+  // mapped and never written while running.
+  unsafe { core::slice::from_raw_parts(APP_START as *const u8, end - APP_START) }
+}
+
+/// CRC32 of [`image`]; reads the whole image, so take it once.
+pub fn image_crc() -> u32 {
+  crc::crc32(image())
+}
+
+/// Check `crc`, the CRC32 of [`image`], against the seal.
+pub fn check_image(crc: u32) -> ImageCheck {
+  // The seal is patched after the build; the compiler must not assume
+  // the value it was built with.
+  let seal = unsafe { (&raw const SEAL).read_volatile() };
+  let check = match seal {
+    UNSEALED => ImageCheck::Unsealed,
+    seal if seal == crc => ImageCheck::Ok,
+    _ => ImageCheck::Mismatch,
+  };
+  match check {
+    ImageCheck::Ok => defmt::info!("[version] Image CRC32 {=u32:X} verified", crc),
+    ImageCheck::Mismatch => {
+      defmt::error!("[version] Image CRC32 {=u32:X}, sealed {=u32:X}", crc, seal)
+    }
+    ImageCheck::Unsealed => defmt::warn!("[version] Image not sealed, CRC32 {=u32:X}", crc),
+  }
+  check
+}
+
+/// `+IMAGE:` line.
+pub fn image_report(crc: u32, check: ImageCheck) -> String<40> {
+  let mut line = String::new();
+  let _ = write!(line, "+IMAGE:{},{:08X},{}\r\n", image().len(), crc, check.name());
+  line
+}
+
+/// `+VER:` line; `radio_ok` when the chip answered at boot.
+pub fn report(radio_ok: bool) -> String<80> {
+  let chip = if radio_ok { band::CHIP } else { "-" };
+  let mut line = String::new();
+  let _ = write!(
+    line,
+    "+VER:{},{},{},{},{}\r\n",
+    VERSION,
+    GIT_HASH,
+    BUILD_TIME,
+    chip,
+    band::MODULE
+  );
+  line
+}
@@ -0,0 +1,762 @@
+// 该文件是 BlueHigh 项目的一部分。
+// tests/on_target.rs - 板上测试
+//
+// 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+// 除非遵守该许可证条款，否则您不得使用本文件。
+// 您可通过以下网址获取许可证副本：
+// http://www.apache.org/licenses/LICENSE-2.0
+// 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+// 不附带任何形式的明示或暗示的保证或条件。
+// 有关许可权限与限制的具体条款，请参阅本许可协议。
+//
+// Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+//! On-target tests.
+//!
+//! Runs on a Blue-High board through probe-rs (the runner in
+//! `.cargo/config.toml`):
+//!
+//! ```text
+//! cargo test --test on_target
+//! ```
+//!
+//! The features select the build under test as for the firmware.  The tests
+//! cover what only the hardware can: the SPI link to the radio, the
+//! settings page of the internal flash, the CRCs as compiled for the MCU,
+//! the time base, the locking of the shared radio handle, the hand-over
+//! from the boot selector, the layout of the Microsoft OS 2.0 descriptors
+//! and, built with `usb-hid`, the HID report framing.  The SX126x
+//! command, register and buffer reads are checked byte by byte against
+//! recorded bus traffic, where the status and NOP bytes make off-by-one
+//! placement easy.  The settings test writes the page and puts the record
+//! it found back, defaults if there was none.  The link layer runs over
+//! simulated radios (see [`blue_high::sim`]) as well, and its MIC is
+//! checked to cover the hop limit.
+//!
+//! With a second board running the firmware in range and on the same
+//! radio settings, `BLUE_HIGH_PEER=1 cargo test --test on_target` also
+//! pings it over the air (see [`blue_high::ping`]); without, that test
+//! only logs that it was skipped.  The link settings are the ones stored
+//! on the board under test: addressing on, paired with the peer, and link
+//! security off.
+
+#![no_std]
+#![no_main]
+
+use panic_probe as _;
+
+#[defmt_test::tests]
+mod tests {
+  use core::cell::RefCell;
+  #[cfg(feature = "driver-sx1268-rs")]
+  use core::convert::Infallible;
+  use core::hint::black_box;
+
+  use blue_high::hal::{flash, pac, prelude::*};
+  #[cfg(feature = "usb-hid")]
+  use blue_high::hid;
+  #[cfg(feature = "driver-sx1268-rs")]
+  use blue_high::lora::{self, ControlCell, LoraControl, OptionalPin, SharedControl};
+  use blue_high::radio::{self, Radio};
+  use blue_high::settings::{self, Settings};
+  use blue_high::sim::Medium;
+  #[cfg(feature = "driver-sx1268-rs")]
+  use blue_high::sx126x;
+  #[cfg(feature = "sx1276")]
+  use blue_high::sx1276;
+  use blue_high::{
+    board, boot_select, crc, hal, link, ms_os, ota, packetizer, ping, radio_handle, relay,
+    security, spi_bus, timer, usb,
+  };
+  use defmt::{assert, assert_eq, info};
+  #[cfg(feature = "driver-sx1268-rs")]
+  use embedded_hal::{
+    digital::{InputPin, OutputPin},
+    spi::SpiBus,
+  };
+  #[cfg(feature = "driver-sx1268-rs")]
+  use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
+  #[cfg(feature = "driver-sx1268-rs")]
+  use sx1268_rs::control::Control;
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  type Lora = sx126x::Sx126x;
+  #[cfg(feature = "sx1276")]
+  type Lora = sx1276::Sx1276;
+
+  /// LoRa sync word, two bytes the SPI test may overwrite: every
+  /// [`Radio::apply`] writes them again.
+  #[cfg(feature = "driver-sx1268-rs")]
+  const REG_SYNC_WORD: u16 = 0x0740;
+  /// Patterns the SPI test writes and reads back.
+  const ECHO_PATTERNS: [[u8; 2]; 5] = [
+    [0x00, 0xFF],
+    [0xFF, 0x00],
+    [0x55, 0xAA],
+    [0xA5, 0x5A],
+    [0x12, 0x34],
+  ];
+  /// Interval the timer test measures.
+  const TIMER_TEST_US: u32 = 250_000;
+  /// Pings the loopback test sends; one answer is enough.
+  const LOOPBACK_PROBES: u8 = 3;
+  /// Path loss and noise floor of the simulated air.
+  const SIM_PATH_LOSS_DB: i16 = 100;
+  const SIM_NOISE_DBM: i16 = -120;
+
+  /// Status byte the recorded chip clocks out during the NOP.
+  #[cfg(feature = "driver-sx1268-rs")]
+  const RECORDED_STATUS: u8 = 0xA2;
+  /// Status of a second command in the same recording, told apart from
+  /// the first.
+  #[cfg(feature = "driver-sx1268-rs")]
+  const PACKET_STATUS_STATUS: u8 = 0xD4;
+
+  /// SPI bus that answers from a recorded MISO sequence and keeps what was
+  /// clocked out on MOSI.
+  #[cfg(feature = "driver-sx1268-rs")]
+  struct Recorded {
+    miso: &'static [u8],
+    mosi: heapless::Vec<u8, 32>,
+  }
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  impl Recorded {
+    /// Clock one byte out and the recorded answer in.
+    fn clock(&mut self, out: u8) -> u8 {
+      let answer = self.miso.get(self.mosi.len()).copied().unwrap_or(0xFF);
+      let _ = self.mosi.push(out);
+      answer
+    }
+  }
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  impl embedded_hal::spi::ErrorType for Recorded {
+    type Error = Infallible;
+  }
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  impl SpiBus for Recorded {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+      words.iter_mut().for_each(|word| *word = self.clock(0x00));
+      Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+      words.iter().for_each(|&word| {
+        self.clock(word);
+      });
+      Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Infallible> {
+      for i in 0..read.len().max(write.len()) {
+        let answer = self.clock(write.get(i).copied().unwrap_or(0x00));
+        if let Some(word) = read.get_mut(i) {
+          *word = answer;
+        }
+      }
+      Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+      words.iter_mut().for_each(|word| *word = self.clock(*word));
+      Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+      Ok(())
+    }
+  }
+
+  /// A control line that is never busy.
+  #[cfg(feature = "driver-sx1268-rs")]
+  struct Line;
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  impl embedded_hal::digital::ErrorType for Line {
+    type Error = Infallible;
+  }
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  impl OutputPin for Line {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+      Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+      Ok(())
+    }
+  }
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  impl InputPin for Line {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+      Ok(false)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+      Ok(true)
+    }
+  }
+
+  /// Control lines with the only device on a recorded bus answering `miso`.
+  #[cfg(feature = "driver-sx1268-rs")]
+  type RecordedControl =
+    LoraControl<ExclusiveDevice<Recorded, Line, NoDelay>, Line, Line, Line, Line>;
+
+  #[cfg(feature = "driver-sx1268-rs")]
+  fn recorded(miso: &'static [u8]) -> RecordedControl {
+    let bus = Recorded {
+      miso,
+      mosi: heapless::Vec::new(),
+    };
+    LoraControl {
+      spi: ExclusiveDevice::new_no_delay(bus, Line).unwrap(),
+      nrst_pin: Line,
+      busy_pin: Line,
+      tx_pin: OptionalPin::none(),
+      rx_pin: OptionalPin::none(),
+      switch_guard: radio::SwitchGuard::default(),
+    }
+  }
+
+  struct State {
+    flash: flash::Parts,
+    lora: Lora,
+    dio1: radio::Dio1,
+    #[cfg(feature = "driver-sx1268-rs")]
+    control: sx126x::RadioControl,
+    settings: Settings,
+  }
+
+  #[init]
+  fn init() -> State {
+    rtt_target::rtt_init_defmt!();
+
+    let dp = pac::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let mut flash = dp.FLASH.constrain();
+    let rcc = dp.RCC.constrain();
+    let mut rcc = rcc.freeze(
+      hal::rcc::Config::hse(hal::HSE_HZ.Hz())
+        .sysclk(hal::SYSCLK_HZ.Hz())
+        .pclk1(hal::PCLK1_HZ.Hz()),
+      &mut flash.acr,
+    );
+    timer::init(cp.SYST, cp.DCB, cp.DWT, hal::SYSCLK_HZ);
+
+    let pins = board::Pins::new(board::Ports {
+      gpioa: dp.GPIOA.split(&mut rcc),
+      gpiob: dp.GPIOB.split(&mut rcc),
+      gpioc: dp.GPIOC.split(&mut rcc),
+    });
+    let spi1 = spi_bus::init1(dp.SPI1, (pins.sck, pins.miso, pins.mosi), &mut rcc);
+
+    #[cfg(feature = "driver-sx1268-rs")]
+    let (mut lora, control) = {
+      let control = cortex_m::singleton!(
+        : ControlCell<sx126x::BlueHighControl> = ControlCell::new(LoraControl {
+          spi: spi_bus::shared_device(spi1, pins.nss),
+          nrst_pin: pins.nrst,
+          busy_pin: pins.busy,
+          tx_pin: if cfg!(feature = "dio2-rf-switch") {
+            OptionalPin::none()
+          } else {
+            OptionalPin::new(pins.txen)
+          },
+          rx_pin: if cfg!(feature = "dio2-rf-switch") {
+            OptionalPin::none()
+          } else {
+            OptionalPin::new(pins.rxen)
+          },
+          switch_guard: radio::SwitchGuard::default(),
+        })
+      )
+      .unwrap();
+      let control = SharedControl::new(control);
+      (sx126x::Sx126x::new(control), control)
+    };
+    #[cfg(feature = "sx1276")]
+    let mut lora = {
+      let _ = (pins.busy, pins.txen, pins.rxen);
+      sx1276::Sx1276::new(spi_bus::shared_device(spi1, pins.nss), pins.nrst)
+    };
+
+    let settings = Settings::load();
+    assert!(lora.apply(&settings.radio), "radio did not come up");
+    State {
+      flash,
+      lora,
+      dio1: pins.dio1,
+      #[cfg(feature = "driver-sx1268-rs")]
+      control,
+      settings,
+    }
+  }
+
+  /// Registers written over SPI read back unchanged.
+  #[test]
+  fn spi_echo(state: &mut State) {
+    #[cfg(feature = "driver-sx1268-rs")]
+    for pattern in ECHO_PATTERNS {
+      let mut echo = [0u8; 2];
+      assert!(
+        state
+          .control
+          .write_register(REG_SYNC_WORD, &pattern)
+          .is_ok()
+      );
+      assert!(
+        state
+          .control
+          .read_register(REG_SYNC_WORD, &mut echo)
+          .is_ok()
+      );
+      assert_eq!(echo, pattern);
+    }
+    #[cfg(feature = "sx1276")]
+    {
+      let _ = ECHO_PATTERNS;
+      info!("[test] SPI echo needs an SX126x, skipped");
+    }
+    assert!(state.lora.apply(&state.settings.radio));
+  }
+
+  /// GetIrqStatus: the status comes with the NOP after the opcode, the
+  /// response right after it.
+  #[cfg(feature = "driver-sx1268-rs")]
+  #[test]
+  fn read_command_frame() {
+    let mut control = recorded(&[0x00, RECORDED_STATUS, 0x02, 0x01]);
+    let mut irq = [0u8; 2];
+    assert!(control.read_command(0x12, &[0x00], &mut irq).is_ok());
+    assert_eq!(control.spi.bus().mosi.as_slice(), &[0x12, 0x00, 0x00, 0x00]);
+    assert_eq!(irq, [0x02, 0x01]);
+    assert_eq!(lora::last_status_byte(), RECORDED_STATUS);
+  }
+
+  /// ReadRegister: opcode, address and a NOP for the status, then the
+  /// data.
+  #[cfg(feature = "driver-sx1268-rs")]
+  #[test]
+  fn read_register_frame() {
+    let mut control = recorded(&[0x00, 0x00, 0x00, RECORDED_STATUS, 0x34, 0x44]);
+    let mut data = [0u8; 2];
+    assert!(control.read_register(REG_SYNC_WORD, &mut data).is_ok());
+    assert_eq!(
+      control.spi.bus().mosi.as_slice(),
+      &[0x1D, 0x07, 0x40, 0x00, 0x00, 0x00]
+    );
+    assert_eq!(data, [0x34, 0x44]);
+    assert_eq!(lora::last_status_byte(), RECORDED_STATUS);
+  }
+
+  /// ReadBuffer: opcode, offset and a NOP for the status, then the
+  /// payload, and no other command.  A GetPacketStatus after it, as the
+  /// receive path sends, keeps its own status.
+  #[cfg(feature = "driver-sx1268-rs")]
+  #[test]
+  fn read_buffer_frame() {
+    let mut control = recorded(&[
+      0x00,
+      0x00,
+      RECORDED_STATUS,
+      b'B',
+      b'H',
+      b'!',
+      0x00,
+      PACKET_STATUS_STATUS,
+      0x80,
+      0x14,
+      0x80,
+    ]);
+    let mut payload = [0u8; 3];
+    assert!(control.read_buffer(0x10, &mut payload).is_ok());
+    assert_eq!(
+      control.spi.bus().mosi.as_slice(),
+      &[0x1E, 0x10, 0x00, 0x00, 0x00, 0x00]
+    );
+    assert_eq!(&payload, b"BH!");
+    assert_eq!(lora::last_status_byte(), RECORDED_STATUS);
+
+    let mut packet = [0u8; 3];
+    assert!(control.read_command(0x14, &[0x00], &mut packet).is_ok());
+    assert_eq!(packet, [0x80, 0x14, 0x80]);
+    assert_eq!(lora::last_status_byte(), PACKET_STATUS_STATUS);
+  }
+
+  /// Settings survive the flash page and an export, a USB identity outside
+  /// the VID policy is refused, and so is a damaged record or a hop limit
+  /// out of range.
+  #[test]
+  fn settings_round_trip(state: &mut State) {
+    let mut changed = state.settings.clone();
+    changed.node_address ^= 0x5A5A;
+    changed.peer_address ^= 0xA5A5;
+    changed.addressing = !changed.addressing;
+    changed.profile = Some(1);
+    // Longest strings: the record still leaves room for new fields.
+    changed.usb_identity =
+      usb::Identity::new(0x1209, 0x0001, b"Integrator X", b"Field Bridge").unwrap();
+    assert!(usb::Identity::new(0x1209, 0x0001, b"Integrator X1", b"").is_none());
+    assert!(usb::Identity::new(0x0483, 0x5740, b"", b"").is_none());
+    assert!(usb::Identity::new(0x1209, 0x0001, b"A,B", b"").is_none());
+    assert!(changed.save(&mut state.flash).is_ok());
+    let loaded = Settings::load();
+    assert_eq!(loaded, changed);
+    assert!(settings::diff(&changed, &loaded).is_empty());
+
+    let mut record = [0u8; settings::RECORD_MAX];
+    let len = changed.export(&mut record).unwrap();
+    assert!(len <= settings::RECORD_MAX - 6);
+    assert_eq!(changed.import(&record[..len]), Some(changed.clone()));
+    record[len - 1] ^= 0xFF;
+    assert_eq!(changed.import(&record[..len]), None);
+
+    // A hop limit the hop byte cannot hold falls back to the default.
+    let mut unbounded = changed.clone();
+    unbounded.hop_limit = relay::MAX_HOP_LIMIT + 1;
+    let len = unbounded.export(&mut record).unwrap();
+    let imported = changed.import(&record[..len]).unwrap();
+    assert_eq!(imported.hop_limit, relay::DEFAULT_HOP_LIMIT);
+
+    assert!(state.settings.save(&mut state.flash).is_ok());
+    assert_eq!(Settings::load(), state.settings);
+  }
+
+  /// The CRCs compute their check values on the MCU too, not only in the
+  /// compile-time checks of `crc.rs`.
+  #[test]
+  fn crc_check_values() {
+    let check = black_box(b"123456789".as_slice());
+    assert_eq!(crc::crc16_ccitt(check), 0x29B1);
+    assert_eq!(crc::crc16_modbus(check), 0x4B37);
+    assert_eq!(crc::crc32(check), 0xCBF4_3926);
+    for split in 0..=check.len() {
+      let (head, tail) = check.split_at(split);
+      assert_eq!(crc::crc32_update(crc::crc32(head), tail), 0xCBF4_3926);
+    }
+  }
+
+  /// The Microsoft OS 2.0 descriptor set is only its header without
+  /// functions, and a WinUSB function nests its lengths correctly.
+  #[test]
+  fn ms_os_descriptor_set() {
+    let empty = ms_os::descriptor_set(&[]).unwrap();
+    assert_eq!(
+      &empty[..],
+      &[0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x06, 0x0A, 0x00]
+    );
+
+    let winusb = ms_os::Function {
+      first_interface: 4,
+      compatible_id: "WINUSB",
+      interface_guid: Some("{8C1D7A52-3E0B-4F6A-9B2D-5A0C1E4F7D63}"),
+    };
+    let set = ms_os::descriptor_set(&[winusb]).unwrap();
+    assert_eq!(set.len(), 178);
+    // Set, configuration subset and function subset lengths.
+    assert_eq!(&set[8..10], &178u16.to_le_bytes());
+    assert_eq!(&set[16..18], &168u16.to_le_bytes());
+    assert_eq!(&set[18..24], &[0x08, 0x00, 0x02, 0x00, 4, 0x00]);
+    assert_eq!(&set[24..26], &160u16.to_le_bytes());
+    assert_eq!(&set[30..38], b"WINUSB\0\0");
+    // The GUID list ends with two NUL characters.
+    assert_eq!(&set[174..], &[0, 0, 0, 0]);
+  }
+
+  /// HID reports carry their count and zero padding, a long chunk is split
+  /// across reports and a count past the end is cut short.
+  #[cfg(feature = "usb-hid")]
+  #[test]
+  fn hid_report_framing() {
+    let report = hid::pack(&[3, b'B', b'H', b'!']);
+    assert_eq!(&report[..5], &[4, 3, b'B', b'H', b'!']);
+    assert!(report[5..].iter().all(|&byte| byte == 0));
+    assert_eq!(hid::unpack(&report), &[3, b'B', b'H', b'!']);
+
+    let long = [0xA5u8; hid::REPORT_LEN];
+    let report = hid::pack(&long);
+    assert_eq!(usize::from(report[0]), hid::CHUNK_MAX);
+    assert_eq!(hid::unpack(&report), &long[..hid::CHUNK_MAX]);
+
+    assert_eq!(hid::unpack(&[0xFF, 1, 2]), &[1, 2]);
+    assert!(hid::unpack(&[]).is_empty());
+  }
+
+  /// The shared radio handle refuses nested and interleaved access instead
+  /// of panicking, and holds the radio again after a lend.
+  #[test]
+  fn radio_handle_locking() {
+    let handle: radio_handle::Shared<u8> = radio_handle::Shared::new();
+    assert_eq!(handle.lock(|radio| *radio), None);
+    handle.init(7);
+    assert_eq!(
+      handle.lock(|radio| handle.lock(|_| ()).is_none().then_some(*radio)),
+      Some(Some(7))
+    );
+    let lent = handle.lend(|radio| {
+      *radio += 1;
+      handle.lock(|_| ()).is_none() && handle.lend(|_| ()).is_none()
+    });
+    assert_eq!(lent, Some(true));
+    assert_eq!(handle.lock(|radio| *radio), Some(8));
+
+    // A handler finding the radio lent out leaves its work for later.
+    let mut deferred = false;
+    handle.lend(|_| {
+      if handle.lock(|radio| *radio += 1).is_none() {
+        deferred = true;
+      }
+    });
+    assert!(deferred);
+    if deferred {
+      handle.lock(|radio| *radio += 1);
+    }
+    assert_eq!(handle.lock(|radio| *radio), Some(9));
+  }
+
+  /// The core reset into the boot selector in the first page, which handed
+  /// over to the vector table of this image after it.
+  #[test]
+  fn boot_selector_hand_over() {
+    // The reset vector, second word of the flash.
+    let reset = unsafe { (0x0800_0004 as *const u32).read_volatile() };
+    assert!((reset as usize) < boot_select::APP_START);
+    let vtor = unsafe { (*cortex_m::peripheral::SCB::PTR).vtor.read() };
+    assert_eq!(vtor as usize, boot_select::APP_START);
+  }
+
+  /// The millisecond tick and the cycle counter agree, and cycle waits end
+  /// on time.
+  #[test]
+  fn timer_accuracy() {
+    let start_ms = timer::now_ms();
+    let start = timer::now_cycles();
+    timer::wait_until_cycles(timer::after_us(start, TIMER_TEST_US));
+    let waited_us = timer::cycles_to_us(timer::now_cycles().wrapping_sub(start));
+    let elapsed_ms = timer::now_ms().wrapping_sub(start_ms);
+    info!("[test] waited {} us, SysTick {} ms", waited_us, elapsed_ms);
+    assert!((TIMER_TEST_US..TIMER_TEST_US + 50).contains(&waited_us));
+    assert!(elapsed_ms.abs_diff(TIMER_TEST_US / 1_000) <= 1);
+  }
+
+  /// A `PING` crosses the simulated air through the link layer after its
+  /// airtime, and goes unheard out of range.
+  #[test]
+  fn sim_link() {
+    let medium = RefCell::new(Medium::<2>::new(SIM_PATH_LOSS_DB, SIM_NOISE_DBM, 1));
+    let mut a = Medium::radio(&medium, 0);
+    let mut b = Medium::radio(&medium, 1);
+    let params = radio::RadioParams::default();
+    assert!(a.apply(&params) && b.apply(&params));
+    let mut link_a = link::Link {
+      addressing: true,
+      local: 1,
+      peer: 2,
+      seq: 0,
+      hop_limit: 0,
+    };
+    let link_b = link::Link {
+      local: 2,
+      peer: 1,
+      ..link_a
+    };
+
+    let probe = ping::Probe::Ping { seq: 1, sent_ms: 0 };
+    let mut body = [0u8; ping::PROBE_MAX];
+    let len = probe.encode(&mut body);
+    let mut frame = heapless::Vec::<u8, { packetizer::MAX_PAYLOAD }>::new();
+    link_a.encode(link::Kind::Control, &body[..len], &mut frame);
+    let airtime_us = params.airtime_us(frame.len()) as u64;
+    assert!(a.send(&frame));
+    medium.borrow_mut().advance(airtime_us - 1);
+    assert!(!a.irq() && !b.irq());
+    medium.borrow_mut().advance(1);
+    assert!(a.irq() && b.irq());
+
+    let mut buf = [0u8; packetizer::MAX_PAYLOAD];
+    assert_eq!(b.receive(&mut buf), Ok(Some(frame.len())));
+    let Ok(received) = link_b.decode(&buf[..frame.len()]) else {
+      defmt::panic!("the frame did not pass the address filter");
+    };
+    assert_eq!(received.src, Some(1));
+    assert_eq!(received.kind, link::Kind::Control);
+    assert_eq!(ping::Probe::decode(received.payload), Some(probe));
+    assert_eq!(
+      radio::take_packet_status().map(|status| status.rssi_dbm),
+      Some(params.tx_power_dbm() as i16 - SIM_PATH_LOSS_DB)
+    );
+
+    medium.borrow_mut().set_path_loss(0, 1, 200);
+    assert!(a.start_rx() && a.send(&frame));
+    medium.borrow_mut().advance(airtime_us);
+    assert!(a.irq() && !b.irq());
+  }
+
+  /// A relay may count a hop but not raise the hop limit: the MIC covers
+  /// the limit, and a frame that fails it is not authentic.
+  #[test]
+  fn link_mic_covers_hop_limit() {
+    let key = [0x42; 16];
+    let mut sender = security::Security::new(true, key, 0);
+    let mut receiver = security::Security::new(true, key, 0);
+    let mut link = link::Link {
+      addressing: true,
+      local: 1,
+      peer: 2,
+      seq: 0,
+      hop_limit: 3,
+    };
+    let mut frame = heapless::Vec::<u8, { packetizer::MAX_PAYLOAD }>::new();
+    link.encode(link::Kind::Data, b"BH!", &mut frame);
+    let header_len = link.header_len();
+    sender.seal(&mut frame, header_len);
+    let hops = header_len - 1;
+
+    let mut relayed = frame.clone();
+    relayed[hops] = link::Header::hop_byte(1, 3);
+    assert!(receiver.authentic(&relayed, header_len));
+    let mut raised = frame.clone();
+    raised[hops] = link::Header::hop_byte(0, 15);
+    assert!(!receiver.authentic(&raised, header_len));
+    assert_eq!(
+      receiver.open(&raised, header_len, 1),
+      Err(security::Reject::BadMic)
+    );
+    assert_eq!(receiver.open(&relayed, header_len, 1), Ok(&b"BH!"[..]));
+  }
+
+  /// An update needs link security, and `FINISH` carries the CMAC of the
+  /// size and the chunks sent in order.
+  #[test]
+  fn ota_finish_carries_image_mac() {
+    use cmac::Mac;
+
+    let mut sender = ota::Sender::new();
+    let start = ota::Request::Start { size: 8, crc: 0 };
+    let off = security::Security::new(false, [0x42; 16], 0);
+    assert!(!sender.start(start, 0, &off));
+
+    let on = security::Security::new(true, [0x42; 16], 0);
+    let image = [1, 2, 3, 4, 5, 6, 7, 8];
+    let requests = [
+      start,
+      ota::Request::Data {
+        offset: 0,
+        chunk: ota::Chunk::new(&image[..4]).unwrap(),
+      },
+      // A repeat does not enter the CMAC twice.
+      ota::Request::Data {
+        offset: 0,
+        chunk: ota::Chunk::new(&image[..4]).unwrap(),
+      },
+      ota::Request::Data {
+        offset: 4,
+        chunk: ota::Chunk::new(&image[4..]).unwrap(),
+      },
+    ];
+    for (seq, request) in requests.into_iter().enumerate() {
+      assert!(sender.start(request, 0, &on));
+      assert!(sender.handle(seq as u8, ota::Status::Ok, 0).is_some());
+    }
+    let finish = ota::Request::Finish {
+      tag: [0; ota::TAG_LEN],
+    };
+    assert!(sender.start(finish, 0, &on));
+    let Some(ota::Event::Send(message)) = sender.poll(0) else {
+      defmt::panic!("FINISH was not sent");
+    };
+
+    let mut mac = on.image_mac().unwrap();
+    mac.update(&8u32.to_le_bytes());
+    mac.update(&image);
+    let mut tag = [0u8; ota::TAG_LEN];
+    tag.copy_from_slice(&mac.finalize().into_bytes());
+    assert_eq!(
+      message,
+      ota::Message::Request {
+        seq: 4,
+        request: ota::Request::Finish { tag },
+      }
+    );
+    let mut body = [0u8; ota::MESSAGE_MAX];
+    let len = message.encode(&mut body);
+    assert_eq!(ota::Message::decode(&body[..len]), Some(message));
+  }
+
+  /// A `PING` to the peer board comes back as a `PONG`.
+  #[test]
+  fn radio_loopback(state: &mut State) {
+    if option_env!("BLUE_HIGH_PEER").is_none() {
+      info!("[test] no peer board, skipped; set BLUE_HIGH_PEER=1 to run");
+      return;
+    }
+    assert!(state.settings.addressing, "the loopback test needs addressing");
+    assert!(
+      !state.settings.security,
+      "the loopback test needs link security off"
+    );
+    let mut link = link::Link {
+      addressing: state.settings.addressing,
+      local: state.settings.node_address,
+      peer: state.settings.peer_address,
+      seq: 0,
+      hop_limit: state.settings.hop_limit,
+    };
+    let mut answered = false;
+    for seq in 0..LOOPBACK_PROBES {
+      let sent_ms = timer::now_ms();
+      let mut body = [0u8; ping::PROBE_MAX];
+      let len = ping::Probe::Ping { seq, sent_ms }.encode(&mut body);
+      let mut frame = heapless::Vec::<u8, { packetizer::MAX_PAYLOAD }>::new();
+      link.encode(link::Kind::Control, &body[..len], &mut frame);
+      assert!(radio::transmit_blocking(
+        &mut state.lora,
+        &state.dio1,
+        &frame
+      ));
+      assert!(state.lora.start_rx());
+
+      let mut buf = [0u8; packetizer::MAX_PAYLOAD];
+      while timer::now_ms().wrapping_sub(sent_ms) < ping::PROBE_TIMEOUT_MS {
+        if !state.dio1.is_high() {
+          continue;
+        }
+        let Ok(Some(len)) = state.lora.receive(&mut buf) else {
+          continue;
+        };
+        let Ok(received) = link.decode(&buf[..len]) else {
+          continue;
+        };
+        if received.kind != link::Kind::Control {
+          continue;
+        }
+        if let Some(ping::Probe::Pong {
+          seq: pong_seq,
+          heard,
+          ..
+        }) = ping::Probe::decode(received.payload)
+          && pong_seq == seq
+        {
+          let here = radio::take_packet_status();
+          info!(
+            "[test] PONG {} after {} ms, peer heard {}, we heard {}",
+            seq,
+            timer::now_ms().wrapping_sub(sent_ms),
+            heard,
+            here
+          );
+          answered = true;
+          break;
+        }
+      }
+      if answered {
+        break;
+      }
+      info!("[test] PING {} lost", seq);
+    }
+    assert!(answered, "the peer did not answer");
+  }
+}
@@ -0,0 +1,54 @@
+#!/usr/bin/env python3
+# 该文件是 BlueHigh 项目的一部分。
+# tools/seal_image.py - 写入固件映像的 CRC32 校验值
+#
+# 本文件根据 Apache 许可证第 2.0 版（以下简称“许可证”）授权使用；
+# 除非遵守该许可证条款，否则您不得使用本文件。
+# 您可通过以下网址获取许可证副本：
+# http://www.apache.org/licenses/LICENSE-2.0
+# 除非适用法律要求或书面同意，根据本许可协议分发的软件均按“原样”提供，
+# 不附带任何形式的明示或暗示的保证或条件。
+# 有关许可权限与限制的具体条款，请参阅本许可协议。
+#
+# Copyright (C) 2026 Johann Li <me@qinka.pro>, Wareless Group
+
+"""Seal a firmware `.bin` with the CRC32 of its image.
+
+The last word of the image is the seal (see `src/version.rs`), erased
+flash in a fresh build.  It covers the application after the 1 KiB boot
+selector page, which an update does not replace.  The bridge checks it at
+boot:
+
+    cargo objcopy --release -- -O binary blue-high.bin
+    python3 tools/seal_image.py blue-high.bin
+"""
+
+import struct
+import sys
+import zlib
+
+UNSEALED = 0xFFFFFFFF
+BOOT_LEN = 1024
+
+
+def main():
+    if len(sys.argv) != 2:
+        sys.exit("usage: seal_image.py <image.bin>")
+    path = sys.argv[1]
+    with open(path, "rb") as f:
+        image = bytearray(f.read())
+    if len(image) <= BOOT_LEN + 4 or len(image) % 4:
+        sys.exit(f"{path}: not a firmware image")
+    body = image[BOOT_LEN:-4]
+    crc = zlib.crc32(body)
+    (seal,) = struct.unpack_from("<I", image, len(image) - 4)
+    if seal not in (UNSEALED, crc):
+        sys.exit(f"{path}: last word 0x{seal:08X} is not a seal")
+    struct.pack_into("<I", image, len(image) - 4, crc)
+    with open(path, "wb") as f:
+        f.write(image)
+    print(f"{path}: {len(body)} bytes, CRC32 {crc:08X}")
+
+
+if __name__ == "__main__":
+    main()